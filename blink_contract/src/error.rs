@@ -0,0 +1,59 @@
+use warp::error::Error;
+
+// Errors surfaced by the Blink API, so callers can tell failures apart without matching on
+// messages. Anything without a variant of its own ends up in `Other`.
+#[derive(Debug, thiserror::Error)]
+pub enum BlinkError {
+    // DID we haven't identified, or paired with, yet
+    #[error("Peer {0} hasn't been identified")]
+    NotIdentified(String),
+    #[error("Couldn't find a topic for {0}")]
+    NoTopicForDid(String),
+    // Call, stream, transfer, fragment... that doesn't exist (anymore)
+    #[error("{0} doesn't exist")]
+    NotFound(String),
+    // The request makes no sense in the current state, or ever
+    #[error("{0}")]
+    Invalid(String),
+    // Serialized size and the limit, in bytes
+    #[error("Message is {0} bytes, at most {1} can be sent")]
+    TooLarge(usize, usize),
+    #[error("Couldn't serialize data: {0}")]
+    Serialization(String),
+    #[error("Key error: {0}")]
+    Key(String),
+    #[error("Fragment error: {0}")]
+    Fragment(String),
+    #[error("Timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    // The service was stopped, nothing can be sent to it anymore
+    #[error("Blink service stopped")]
+    ChannelClosed,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl From<BlinkError> for Error {
+    fn from(error: BlinkError) -> Self {
+        match error {
+            BlinkError::NotIdentified(_) | BlinkError::NoTopicForDid(_) => {
+                Error::IdentityDoesntExist
+            }
+            BlinkError::NotFound(_) => Error::DataObjectNotFound,
+            error => Error::OtherWithContext(error.to_string()),
+        }
+    }
+}
+
+// Why a message didn't go out to one of its recipients.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RecipientError {
+    // There's no pairwise topic with the DID yet, pairing with it fixes that
+    #[error("Not paired with the recipient")]
+    NotPaired,
+    // Journaling it or handing it to the event loop failed
+    #[error("{0}")]
+    Failed(String),
+}
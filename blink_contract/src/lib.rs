@@ -1,12 +1,60 @@
-use anyhow::Result;
+mod error;
+
+pub use error::{BlinkError, RecipientError};
+
 use async_trait::async_trait;
-use libp2p::Multiaddr;
+use libp2p::{futures::future::BoxFuture, Multiaddr};
 use sata::Sata;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use warp::crypto::DID;
 
-pub enum StreamKind {}
+type Result<T> = std::result::Result<T, BlinkError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamKind {
+    Audio,
+    Video,
+    ScreenShare,
+}
+
+// Upper bounds for a video stream, agreed on by both ends when the stream opens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VideoCaps {
+    pub max_width: u32,
+    pub max_height: u32,
+    // Bits per second
+    pub max_bitrate: u32,
+}
+
+impl VideoCaps {
+    // Caps both sides can live with
+    pub fn intersect(&self, other: &VideoCaps) -> VideoCaps {
+        VideoCaps {
+            max_width: self.max_width.min(other.max_width),
+            max_height: self.max_height.min(other.max_height),
+            max_bitrate: self.max_bitrate.min(other.max_bitrate),
+        }
+    }
+}
+
+// What a user tells its contacts about itself, set by hand unlike whether its node is reachable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    #[default]
+    Online,
+    Away,
+    DoNotDisturb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallEndReason {
+    Rejected,
+    Busy,
+    HungUp,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     DialSuccessful(String),
     DialError(String),
@@ -26,7 +74,130 @@ pub enum Event {
     PeerConnectionClosed(String),
     ConnectionEstablished(String),
     TaskCancelled,
-    CouldntFindTopicForDid,
+    ErrorProvidingContent(String),
+    ContentAtRisk(String),
+    MailboxReplayed(usize),
+    MailboxError(String),
+    WriteAheadLogError(String),
+    DeviceSynced(usize),
+    DeviceSyncError(String),
+    IncomingStream(String, u64, StreamKind),
+    StreamOpened(u64, String),
+    StreamRejected(u64),
+    StreamMuted(u64, bool),
+    StreamClosed(u64),
+    StreamError(String),
+    VideoCapsNegotiated(u64, VideoCaps),
+    KeyframeRequested(u64),
+    TransactionCompleted(u64),
+    TransactionFailed(u64, String),
+    UnknownExtension(String),
+    StreamBitrateChanged(u64, u32),
+    PeerExtensionsChanged(String, Vec<String>),
+    GroupParticipantsChanged(u64, Vec<String>),
+    GroupStreamAdded(u64, String, u64),
+    IncomingCall(String, u64, StreamKind),
+    CallAccepted(u64),
+    CallEnded(u64, CallEndReason),
+    MessageQuarantined(String),
+    RecordingSaved(u64, String),
+    RecordingError(String),
+    // Sender's DID, transfer id, file name and size
+    IncomingFile(String, u64, String, u64),
+    // Transfer id, bytes transferred and file size
+    TransferProgress(u64, u64, u64),
+    TransferCompleted(u64),
+    TransferFailed(u64, String),
+    // CID announced on the DHT
+    ContentProvided(String),
+    // CID of a replica that took a remote update
+    FragmentMerged(String),
+    // CID dropped by the fragment store's garbage collection
+    FragmentEvicted(String),
+    // Topic a message was held back on until a peer subscribes to it
+    PublishDeferred(String),
+    // DID (or PeerId if unidentified) of a remote peer that subscribed to the topic
+    PeerJoinedTopic(String, String),
+    // DID (or PeerId if unidentified) of a remote peer that unsubscribed or disconnected, and the topic
+    PeerLeftTopic(String, String),
+    // PeerId being dialed
+    Dialing(String),
+    // Address a remote peer is connecting from
+    IncomingConnection(Multiaddr),
+    // Address the remote peer connected from and why the connection failed
+    IncomingConnectionError(Multiaddr, String),
+    // PeerId we dialed, if known, and why the dial failed
+    OutgoingConnectionError(Option<String>, String),
+    // PeerId of a banned peer whose connection was refused
+    BannedPeer(String),
+    // DID of a peer whose Blink protocol version can't talk to ours, their version and ours
+    IncompatiblePeer(String, String, String),
+    // PeerId of the author and the topic of received messages dropped over a rate limit, once per flood
+    RateLimited(String, String),
+    // PeerId of the rendezvous node we registered at
+    RendezvousRegistered(String),
+    // DID looked up through rendezvous and the PeerId registered for it
+    RendezvousDiscovered(String, String),
+    RendezvousError(String),
+    // Our DID record reached the DHT
+    DidRecordPublished,
+    // DID looked up on the DHT and the PeerId its signed record points to
+    DidResolved(String, String),
+    DidRecordError(String),
+    // Local network discovery couldn't be started
+    MdnsError(String),
+    // DID (or PeerId if unidentified) of a connected peer that stopped answering pings
+    PeerUnresponsive(String),
+    // Path of a synced file, bytes of it fetched so far and its size
+    FileSyncProgress(String, u64, u64),
+    // Path of a synced file fetched whole and its CID
+    FileSynced(String, String),
+    // DID sharing a directory with us and the CID of its index
+    DirectoryOffered(String, String),
+    // DID asking to become friends, answered with accept_friend_request or decline_friend_request
+    FriendRequestReceived(String),
+    // DID that accepted our friend request, it's paired with from now on
+    FriendRequestAccepted(String),
+    FriendRequestError(String),
+    // DID whose newer profile was cached, read it with PeerToPeerService::profile
+    ProfileUpdated(String),
+    // Our own profile couldn't be published
+    ProfileError(String),
+    // DID of a contact that announced another presence status
+    ContactStatusChanged(String, Status),
+    // Topic and author of a message a validator rejected, or that a pairwise topic's peer didn't
+    // sign, it wasn't passed on
+    MessageRejected(String, String),
+    // DID (or PeerId if unidentified) of a peer whose score fell under the graylist threshold,
+    // gossipsub ignores it until it recovers
+    PeerGraylisted(String, f64),
+    // DID (or PeerId) of a peer whose score fell under the blacklist threshold, it was
+    // disconnected and is ignored for the rest of the session
+    PeerBlacklisted(String, f64),
+    // DID (or PeerId) of a graylisted peer whose score is back over the threshold
+    PeerScoreRecovered(String, f64),
+    // Address enough peers observed us at, advertised from now on
+    ExternalAddressConfirmed(Multiaddr),
+    // DID (or PeerId) of a connected peer that told us about other addresses or protocols
+    PeerIdentifyUpdated(String),
+    // The service stopped driving the network, until it's resumed
+    NetworkingPaused,
+    NetworkingResumed,
+    // Recipient a message couldn't go out to, NotPaired ones can be paired with and sent to again
+    SendFailed {
+        recipient: DID,
+        reason: RecipientError,
+    },
+    // Pairwise topic a message came in on that no DID is paired through anymore
+    UnpairedTopic(String),
+    // Topic of a message to a muted conversation, cached and recorded but kept off the message stream
+    MessageMuted(String),
+    // Id of a disappearing message deleted from the cache and history once it expired
+    MessageExpired(String),
+    // Topic and id of a message its sender edited, the history holds the new payload
+    MessageEdited(String, String),
+    // Topic and id of a message its sender deleted, the history keeps a tombstone
+    MessageDeleted(String, String),
 }
 
 #[async_trait]
@@ -39,6 +210,45 @@ pub trait EventBus: Send + Sync {
     fn event_occurred(&mut self, event: Event);
 }
 
+pub trait ExtensionHandler: Send + Sync {
+    // Message sent to the extension by the given DID over its pairwise channel
+    fn message_received(&mut self, sender: String, data: Sata);
+}
+
+// What becomes of a message a validator looked at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    // Delivered, and passed on to the other peers on the topic
+    Accept,
+    // Dropped, gossipsub scores down the peer that passed it on
+    Reject,
+    // Dropped, nobody is blamed for it
+    Ignore,
+}
+
+pub trait MessageValidator: Send + Sync {
+    // Decides on a message before it's delivered or passed on, the author is a DID once identified
+    fn validate(&mut self, topic: &str, author: &str, data: &Sata) -> Validation;
+}
+
+pub trait Keystore: Send + Sync {
+    // Public half of the identity, safe to share with other peers
+    fn public_key(&self) -> Result<DID>;
+    // Signs the payload with the identity key, in the encoding libp2p verifies it in: the 64 raw
+    // bytes for Ed25519, a DER encoded ECDSA signature over the SHA-256 of the data for secp256k1
+    // and P-256
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>>;
+    // Derives the shared secret between the identity key and the given public key
+    fn key_exchange(&self, public_key: &DID) -> Result<Vec<u8>>;
+}
+
+pub trait Clock: Send + Sync {
+    // Milliseconds since the unix epoch
+    fn now_millis(&self) -> u64;
+    // Completes once the clock moved forward by the given duration
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
 #[async_trait]
 pub trait SendBlinkBehaviour {
     async fn send(data: Sata) -> Result<()>;
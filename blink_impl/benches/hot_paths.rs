@@ -0,0 +1,95 @@
+// Paths every message or blob goes through. Results land in target/criterion, with the
+// numbers of each benchmark in <group>/<benchmark>/new/estimates.json
+use anyhow::anyhow;
+use blink_contract::Keystore;
+use blink_impl::{
+    open_envelope, reassemble, seal_envelope, BincodeWireCodec, DagCborWireCodec, FragmentTree,
+    InMemoryKeystore, PeerToPeerService, WireCodec,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use did_key::Ed25519KeyPair;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::crypto::DID;
+
+const MESSAGE_SIZES: [usize; 3] = [64, 4 * 1024, 64 * 1024];
+const BLOB_SIZES: [usize; 2] = [1024 * 1024, 16 * 1024 * 1024];
+
+fn message(size: usize) -> Sata {
+    Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &"x".repeat(size))
+        .unwrap()
+}
+
+fn keystore() -> InMemoryKeystore {
+    let did = DID::from(did_key::generate::<Ed25519KeyPair>(None));
+    InMemoryKeystore::new(Arc::new(did)).unwrap()
+}
+
+fn envelopes(c: &mut Criterion) {
+    let codecs: [(&str, Box<dyn WireCodec>); 2] = [
+        ("bincode", Box::new(BincodeWireCodec)),
+        ("dag-cbor", Box::new(DagCborWireCodec)),
+    ];
+    let mut group = c.benchmark_group("envelope");
+    for size in MESSAGE_SIZES {
+        let sata = message(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, codec) in &codecs {
+            let sealed = seal_envelope(&**codec, &sata).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("seal/{}", name), size),
+                &sata,
+                |b, x| b.iter(|| seal_envelope(&**codec, x).unwrap()),
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("open/{}", name), size),
+                &sealed,
+                |b, x| b.iter(|| open_envelope(x).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn topics(c: &mut Criterion) {
+    let keystore = keystore();
+    let peer = keystore().public_key().unwrap();
+    c.bench_function("pairwise_topic", |b| {
+        b.iter(|| PeerToPeerService::pairwise_topic(&keystore, &peer).unwrap())
+    });
+}
+
+fn fragments(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fragment_tree");
+    group.sample_size(10);
+    for size in BLOB_SIZES {
+        let blob: Vec<u8> = (0..size).map(|x| x as u8).collect();
+        let tree = FragmentTree::split(&blob, 0).unwrap();
+        let store: HashMap<String, _> = tree
+            .fragments()
+            .iter()
+            .map(|x| (x.cid().to_string(), x.clone()))
+            .collect();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("split", size), &blob, |b, x| {
+            b.iter(|| FragmentTree::split(x, 0).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("reassemble", size), tree.root(), |b, x| {
+            b.iter(|| {
+                reassemble(x, |cid| {
+                    store
+                        .get(cid)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("Missing {}", cid))
+                })
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, envelopes, topics, fragments);
+criterion_main!(benches);
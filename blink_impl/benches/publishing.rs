@@ -0,0 +1,69 @@
+// A message from publish to the other end, over two services paired in-process.
+// Needs the testkit feature: cargo bench --features testkit --bench publishing
+use blink_impl::{Cluster, Topology};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+const MESSAGE_SIZES: [usize; 2] = [64, 16 * 1024];
+
+fn message_to(cluster: &Cluster, node: usize, size: usize) -> Sata {
+    let mut sata = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &"x".repeat(size))
+        .unwrap();
+    sata.add_recipient(cluster.node(node).did().as_ref())
+        .unwrap();
+    sata
+}
+
+fn publishing(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut cluster = runtime
+        .block_on(Cluster::start(2, Topology::FullMesh))
+        .unwrap();
+    let mut group = c.benchmark_group("publish");
+    for size in MESSAGE_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        // Seal, publish, receive and open, until it comes out of the receiver's channel
+        group.bench_function(BenchmarkId::new("to_delivery", size), |b| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let sata = message_to(&cluster, 1, size);
+                        let start = Instant::now();
+                        cluster.node_mut(0).service.send(sata).await.unwrap();
+                        cluster.node_mut(1).messages.recv().await.unwrap();
+                        elapsed += start.elapsed();
+                    }
+                    elapsed
+                })
+            })
+        });
+        // Same, until the receiver wrote it to its cache
+        group.bench_function(BenchmarkId::new("to_cache", size), |b| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let sata = message_to(&cluster, 1, size);
+                        let cached = cluster.node(1).cache.read().data.len();
+                        let start = Instant::now();
+                        cluster.node_mut(0).service.send(sata).await.unwrap();
+                        while cluster.node(1).cache.read().data.len() == cached {
+                            tokio::task::yield_now().await;
+                        }
+                        elapsed += start.elapsed();
+                        cluster.node_mut(1).messages.recv().await.unwrap();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, publishing);
+criterion_main!(benches);
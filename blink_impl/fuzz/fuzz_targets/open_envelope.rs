@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// Whatever a peer gossips, opening it gives an error rather than a panic
+fuzz_target!(|bytes: &[u8]| {
+    let _ = blink_impl::parse_envelope(bytes);
+    let _ = blink_impl::open_envelope(bytes);
+    let _ = blink_impl::open_envelopes(bytes);
+});
@@ -0,0 +1,25 @@
+#![no_main]
+use anyhow::anyhow;
+use blink_impl::{reassemble, DataFragment, TREE_CODEC};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+
+// The first fragment is the root, the others are what a peer answers when asked for a CID
+fuzz_target!(|fragments: Vec<(bool, Vec<u8>)>| {
+    let mut fragments = fragments.into_iter().map(|(node, data)| match node {
+        true => DataFragment::with_codec(data, TREE_CODEC, 0),
+        false => DataFragment::new(data, 0),
+    });
+    let root = match fragments.next() {
+        Some(root) => root,
+        None => return,
+    };
+    let store: HashMap<String, DataFragment> =
+        fragments.map(|x| (x.cid().to_string(), x)).collect();
+    let _ = reassemble(&root, |cid| {
+        store
+            .get(cid)
+            .cloned()
+            .ok_or_else(|| anyhow!("Missing {}", cid))
+    });
+});
@@ -0,0 +1,58 @@
+use crate::conversations::message_id;
+use sata::Sata;
+use std::collections::HashMap;
+use warp::crypto::DID;
+
+// Messages held per DID while pairing, the oldest are dropped past it
+const MAX_HELD: usize = 64;
+
+// Messages sent to DIDs we aren't paired with, held until pairing with them completes.
+#[derive(Default)]
+pub(crate) struct AutoPairing {
+    enabled: bool,
+    held: HashMap<String, (DID, Vec<Sata>)>,
+}
+
+impl AutoPairing {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // Holds the message, true if it's the first one for the DID, pairing starts then.
+    pub(crate) fn hold(&mut self, did: DID, sata: Sata) -> bool {
+        let mut first = false;
+        let (_, held) = self.held.entry(did.to_string()).or_insert_with(|| {
+            first = true;
+            (did, Vec::new())
+        });
+        if held.len() >= MAX_HELD {
+            held.remove(0);
+        }
+        held.push(sata);
+        first
+    }
+
+    // Messages held for the DID, oldest first, to send now that we're paired.
+    pub(crate) fn paired(&mut self, did: &str) -> Vec<Sata> {
+        self.held
+            .remove(did)
+            .map(|(_, held)| held)
+            .unwrap_or_default()
+    }
+
+    // Stops holding the message for whoever it was to.
+    pub(crate) fn forget(&mut self, id: &str) {
+        for (_, held) in self.held.values_mut() {
+            held.retain(|x| message_id(x) != id);
+        }
+    }
+
+    // Gives up on the DID, returning it if messages were still held for it.
+    pub(crate) fn expired(&mut self, did: &str) -> Option<DID> {
+        self.held.remove(did).map(|(did, _)| did)
+    }
+}
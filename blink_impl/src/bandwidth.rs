@@ -0,0 +1,170 @@
+use crate::behavior::BehaviourEvent;
+use crate::streams::StreamId;
+use libp2p::gossipsub::GossipsubEvent;
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Length of the window caps are measured over
+const WINDOW_MILLIS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Traffic {
+    pub sent: u64,
+    pub received: u64,
+}
+
+// Bytes exchanged since the service started, as serialized by Blink before transport framing.
+// Gossip only counts towards the total on the way out, the mesh decides who it goes to.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BandwidthStats {
+    pub total: Traffic,
+    // Keyed by PeerId
+    pub peers: HashMap<String, Traffic>,
+    // Audio and video frames of each stream
+    pub streams: HashMap<StreamId, Traffic>,
+}
+
+// Bytes per second past which bulk transfers wait, calls and live messages are never held back.
+// Both directions count towards the caps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthCaps {
+    pub global_bytes_per_sec: Option<u64>,
+    pub per_peer_bytes_per_sec: Option<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct BandwidthMeter {
+    caps: BandwidthCaps,
+    stats: BandwidthStats,
+    window_start: u64,
+    window_total: u64,
+    window_peers: HashMap<PeerId, u64>,
+}
+
+impl BandwidthMeter {
+    pub(crate) fn caps(&self) -> BandwidthCaps {
+        self.caps
+    }
+
+    pub(crate) fn set_caps(&mut self, caps: BandwidthCaps) {
+        self.caps = caps;
+    }
+
+    pub(crate) fn stats(&self) -> BandwidthStats {
+        self.stats.clone()
+    }
+
+    pub(crate) fn sent(
+        &mut self,
+        peer: Option<&PeerId>,
+        stream: Option<StreamId>,
+        bytes: u64,
+        now: u64,
+    ) {
+        self.stats.total.sent += bytes;
+        if let Some(stream) = stream {
+            self.stats.streams.entry(stream).or_default().sent += bytes;
+        }
+        if let Some(peer) = peer {
+            self.stats.peers.entry(peer.to_string()).or_default().sent += bytes;
+        }
+        self.count(peer, bytes, now);
+    }
+
+    pub(crate) fn received(
+        &mut self,
+        peer: &PeerId,
+        stream: Option<StreamId>,
+        bytes: u64,
+        now: u64,
+    ) {
+        self.stats.total.received += bytes;
+        if let Some(stream) = stream {
+            self.stats.streams.entry(stream).or_default().received += bytes;
+        }
+        self.stats
+            .peers
+            .entry(peer.to_string())
+            .or_default()
+            .received += bytes;
+        self.count(Some(peer), bytes, now);
+    }
+
+    // Whether bulk traffic with the peer can go now, or should wait for a later round.
+    pub(crate) fn allows_bulk(&mut self, peer: &PeerId, now: u64) -> bool {
+        self.roll(now);
+        let global = self
+            .caps
+            .global_bytes_per_sec
+            .map_or(true, |cap| self.window_total < cap);
+        let per_peer = self.caps.per_peer_bytes_per_sec.map_or(true, |cap| {
+            self.window_peers.get(peer).copied().unwrap_or_default() < cap
+        });
+        global && per_peer
+    }
+
+    fn count(&mut self, peer: Option<&PeerId>, bytes: u64, now: u64) {
+        self.roll(now);
+        self.window_total += bytes;
+        if let Some(peer) = peer {
+            *self.window_peers.entry(*peer).or_default() += bytes;
+        }
+    }
+
+    fn roll(&mut self, now: u64) {
+        if now.saturating_sub(self.window_start) >= WINDOW_MILLIS {
+            self.window_start = now;
+            self.window_total = 0;
+            self.window_peers.clear();
+        }
+    }
+}
+
+pub(crate) fn size_of(message: &impl Serialize) -> u64 {
+    bincode::serialized_size(message).unwrap_or_default()
+}
+
+// Who sent a received message, the stream it belongs to and its size.
+pub(crate) fn inbound(event: &BehaviourEvent) -> Option<(PeerId, Option<StreamId>, u64)> {
+    match event {
+        BehaviourEvent::Gossipsub(GossipsubEvent::Message {
+            propagation_source,
+            message,
+            ..
+        }) => Some((*propagation_source, None, message.data.len() as u64)),
+        BehaviourEvent::StreamEvent(RequestResponseEvent::Message {
+            peer,
+            message: RequestResponseMessage::Request { request, .. },
+        }) => Some((
+            *peer,
+            request.frame_of().map(|(id, _)| id),
+            size_of(request),
+        )),
+        BehaviourEvent::StreamEvent(event) => request_response(event),
+        BehaviourEvent::MailboxEvent(event) => request_response(event),
+        BehaviourEvent::DeviceSyncEvent(event) => request_response(event),
+        BehaviourEvent::ProfileEvent(event) => request_response(event),
+        BehaviourEvent::FileTransferEvent(event) => request_response(event),
+        BehaviourEvent::ConfluxEvent(event) => request_response(event),
+        BehaviourEvent::DirectEvent(event) => request_response(event),
+        _ => None,
+    }
+}
+
+fn request_response<TRequest: Serialize, TResponse: Serialize>(
+    event: &RequestResponseEvent<TRequest, TResponse>,
+) -> Option<(PeerId, Option<StreamId>, u64)> {
+    match event {
+        RequestResponseEvent::Message { peer, message } => {
+            let bytes = match message {
+                RequestResponseMessage::Request { request, .. } => size_of(request),
+                RequestResponseMessage::Response { response, .. } => size_of(response),
+            };
+            Some((*peer, None, bytes))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,94 @@
+use crate::peer_to_peer_service::TopicName;
+use sata::Sata;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Coalescing of small messages to the same topic into one publish, for chatty traffic like
+// typing notifications. Peers need a version that reads batches, so it's off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchSettings {
+    pub enabled: bool,
+    // Larger messages go out on their own
+    pub max_message_bytes: u64,
+    // How long the first message of a batch waits for others
+    pub window_millis: u64,
+    // A batch goes out as soon as it holds this much
+    pub max_batch_bytes: u64,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_message_bytes: 512,
+            window_millis: 5,
+            max_batch_bytes: 16 * 1024,
+        }
+    }
+}
+
+impl BatchSettings {
+    pub(crate) fn window(&self) -> Duration {
+        Duration::from_millis(self.window_millis)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Batched {
+    // Opened a batch for the topic, it has to be flushed once the window passed
+    First,
+    Added,
+    // Over the size limit, to be flushed now
+    Full,
+}
+
+#[derive(Default)]
+struct Batch {
+    messages: Vec<Sata>,
+    bytes: u64,
+}
+
+// Messages held back per topic until their batch goes out.
+#[derive(Default)]
+pub(crate) struct Batcher {
+    settings: BatchSettings,
+    batches: HashMap<TopicName, Batch>,
+}
+
+impl Batcher {
+    pub(crate) fn settings(&self) -> BatchSettings {
+        self.settings
+    }
+
+    pub(crate) fn set_settings(&mut self, settings: BatchSettings) {
+        self.settings = settings;
+    }
+
+    // Whether a message this big waits for a batch, rather than going out right away.
+    pub(crate) fn batches(&self, bytes: u64) -> bool {
+        self.settings.enabled && bytes <= self.settings.max_message_bytes
+    }
+
+    pub(crate) fn add(&mut self, topic: TopicName, sata: Sata, bytes: u64) -> Batched {
+        let batch = self.batches.entry(topic).or_default();
+        batch.messages.push(sata);
+        batch.bytes += bytes;
+        if batch.bytes >= self.settings.max_batch_bytes {
+            Batched::Full
+        } else if batch.messages.len() == 1 {
+            Batched::First
+        } else {
+            Batched::Added
+        }
+    }
+
+    // What the topic's batch holds, in the order it was sent.
+    pub(crate) fn take(&mut self, topic: &str) -> Vec<Sata> {
+        self.batches
+            .remove(topic)
+            .map(|x| x.messages)
+            .unwrap_or_default()
+    }
+}
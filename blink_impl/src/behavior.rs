@@ -1,19 +1,53 @@
+use crate::config::BlinkConfig;
+use crate::conflux::{self, ConfluxBehaviour, FragmentRequest, FragmentResponse};
+use crate::delivery::{self, DirectAck, DirectBehaviour, DirectMessage};
+use crate::device_sync::{self, DeviceSnapshot, DeviceSyncBehaviour, DeviceSyncRequest};
+use crate::file_transfer::{self, FileTransferBehaviour, TransferRequest, TransferResponse};
+use crate::mailbox::{self, MailboxBehaviour, MailboxRequest, MailboxResponse};
+use crate::peer_info::UNRESPONSIVE_AFTER;
+use crate::profile::{self, PeerProfile, ProfileBehaviour};
+use crate::publishing::MAX_TRANSMIT_SIZE;
+use crate::streams::{self, StreamBehaviour, StreamMessage, StreamResponse};
+use crate::version::PROTOCOL_VERSION;
 use anyhow::{anyhow, Result};
 use libp2p::gossipsub::{Gossipsub, MessageAuthenticity, ValidationMode};
+#[cfg(not(target_arch = "wasm32"))]
+use libp2p::mdns::{Mdns, MdnsEvent};
 use libp2p::ping::{Ping, PingConfig, PingEvent};
+use libp2p::swarm::toggle::Toggle;
 use libp2p::{
     gossipsub,
     gossipsub::GossipsubEvent,
     identify::{Identify, IdentifyConfig, IdentifyEvent},
     identity::Keypair,
-    kad::{store::MemoryStore, Kademlia, KademliaConfig, KademliaEvent},
-    mdns::{Mdns, MdnsEvent},
+    kad::{store::MemoryStore, Kademlia, KademliaConfig, KademliaEvent, KademliaStoreInserts},
     relay::v2::relay::{Event, Relay},
+    rendezvous,
+    request_response::RequestResponseEvent,
     NetworkBehaviour, PeerId,
 };
+use std::borrow::Cow;
+use std::num::NonZeroU32;
 use std::time::Duration;
 
-const IDENTIFY_PROTOCOL_VERSION: &str = "/ipfs/0.1.0";
+// Browsers can't multicast, local discovery is a no-op there
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type LocalDiscovery = Mdns;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type LocalDiscovery = libp2p::swarm::DummyBehaviour;
+
+// Local discovery, or nothing when it's turned off. Turning it off stops the announcements
+// along with the queries.
+pub(crate) async fn local_discovery(enabled: bool) -> Result<Toggle<LocalDiscovery>> {
+    if !enabled {
+        return Ok(Toggle::from(None));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let mdns = Mdns::new(Default::default()).await?;
+    #[cfg(target_arch = "wasm32")]
+    let mdns = LocalDiscovery::default();
+    Ok(Toggle::from(Some(mdns)))
+}
 
 #[derive(NetworkBehaviour)]
 #[behaviour(event_process = false, out_event = "BehaviourEvent")]
@@ -21,50 +55,123 @@ pub(crate) struct BlinkBehavior {
     pub(crate) gossip_sub: Gossipsub,
     pub(crate) kademlia: Kademlia<MemoryStore>,
     pub(crate) identity: Identify,
-    pub(crate) relay: Relay,
-    pub(crate) mdns: Mdns,
+    pub(crate) relay: Toggle<Relay>,
+    pub(crate) rendezvous: rendezvous::client::Behaviour,
+    pub(crate) mdns: Toggle<LocalDiscovery>,
     pub(crate) ping: Ping,
+    pub(crate) mailbox: MailboxBehaviour,
+    pub(crate) device_sync: DeviceSyncBehaviour,
+    pub(crate) streams: StreamBehaviour,
+    pub(crate) profile: ProfileBehaviour,
+    pub(crate) file_transfer: FileTransferBehaviour,
+    pub(crate) conflux: ConfluxBehaviour,
+    pub(crate) direct: DirectBehaviour,
 }
 
 impl BlinkBehavior {
-    pub(crate) async fn new(key_pair: &Keypair) -> Result<Self> {
+    pub(crate) async fn new(
+        key_pair: &Keypair,
+        agent_version: String,
+        config: &BlinkConfig,
+    ) -> Result<Self> {
         let peer_id = PeerId::from(&key_pair.public());
-        let mdns = Mdns::new(Default::default()).await?;
+        let mdns = local_discovery(config.mdns).await?;
 
-        let relay = Relay::new(peer_id, Default::default());
+        let relay_server = &config.relay_server;
+        let relay = Toggle::from(
+            relay_server
+                .enabled
+                .then(|| Relay::new(peer_id, relay_server.config())),
+        );
+        let rendezvous = rendezvous::client::Behaviour::new(key_pair.clone());
         // Create a Kademlia behaviour.
         let mut kademlia_cfg = KademliaConfig::default();
         kademlia_cfg.set_query_timeout(Duration::from_secs(5 * 60));
+        if let Some(protocol) = &config.kademlia.protocol {
+            if !protocol.starts_with('/') {
+                return Err(anyhow!(
+                    "Kademlia protocol {} doesn't start with /",
+                    protocol
+                ));
+            }
+            kademlia_cfg.set_protocol_names(vec![Cow::Owned(protocol.clone().into_bytes())]);
+        }
+        // Inbound records and provider announcements come as events then, which we ignore
+        if config.kademlia.client_mode {
+            kademlia_cfg.set_record_filtering(KademliaStoreInserts::FilterBoth);
+        }
         let store = MemoryStore::new(peer_id.clone());
         let kademlia = Kademlia::with_config(peer_id.clone(), store, kademlia_cfg);
         // let config = gossipsub::GossipsubConfigBuilder::default()
         //     .build()
         //     .map_err(|e| anyhow::anyhow!(e))?;
 
+        let tuning = &config.gossipsub;
+        let scoring = config.scoring;
         let config = gossipsub::GossipsubConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
+            .heartbeat_interval(tuning.heartbeat_interval())
+            .mesh_n(tuning.mesh_n)
+            .mesh_n_low(tuning.mesh_n_low)
+            .mesh_n_high(tuning.mesh_n_high)
+            // Small topics are flooded by Blink itself, see delivery
+            .flood_publish(false)
             .validation_mode(ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
+            .max_transmit_size(MAX_TRANSMIT_SIZE)
+            // Messages are only passed on once we reported them valid, see validators
+            .validate_messages()
             // same content will be propagated.
             .build()
-            .expect("Valid config");
+            .map_err(|x| anyhow!("Invalid gossipsub config: {}", x))?;
         // build a gossipsub network behaviour
 
-        let gossip_sub = Gossipsub::new(MessageAuthenticity::Signed(key_pair.clone()), config)
+        let mut gossip_sub = Gossipsub::new(MessageAuthenticity::Signed(key_pair.clone()), config)
             .map_err(|x| anyhow!(x))?;
-        let identity = Identify::new(IdentifyConfig::new(
-            IDENTIFY_PROTOCOL_VERSION.into(),
-            key_pair.public(),
-        ));
+        if scoring.enabled {
+            let thresholds = scoring
+                .thresholds()
+                .map_err(|x| anyhow!("Invalid peer scoring: {}", x))?;
+            gossip_sub
+                .with_peer_score(scoring.params(), thresholds)
+                .map_err(|x| anyhow!("Invalid peer scoring: {}", x))?;
+        }
+        // New listen addresses, relayed ones included, are pushed to connected peers right away
+        let identity = Identify::new(
+            IdentifyConfig::new(PROTOCOL_VERSION.into(), key_pair.public())
+                .with_agent_version(agent_version)
+                .with_push_listen_addr_updates(true),
+        );
 
-        let ping = Ping::new(PingConfig::new().with_keep_alive(true));
+        // A couple more failures than it takes to report the peer unresponsive, so the UI sees it
+        // before the connection is dropped
+        let max_failures = NonZeroU32::new(UNRESPONSIVE_AFTER + 2).expect("Not zero");
+        let ping = Ping::new(
+            PingConfig::new()
+                .with_keep_alive(true)
+                .with_max_failures(max_failures),
+        );
+        let mailbox = mailbox::new_behaviour();
+        let device_sync = device_sync::new_behaviour();
+        let streams = streams::new_behaviour();
+        let profile = profile::new_behaviour();
+        let file_transfer = file_transfer::new_behaviour();
+        let conflux = conflux::new_behaviour();
+        let direct = delivery::new_behaviour();
 
         Ok(Self {
             gossip_sub,
             kademlia,
             relay,
+            rendezvous,
             identity,
             mdns,
             ping,
+            mailbox,
+            device_sync,
+            streams,
+            profile,
+            file_transfer,
+            conflux,
+            direct,
         })
     }
 }
@@ -73,10 +180,61 @@ impl BlinkBehavior {
 pub(crate) enum BehaviourEvent {
     Gossipsub(GossipsubEvent),
     RelayEvent(Event),
+    RendezvousEvent(rendezvous::client::Event),
     KademliaEvent(KademliaEvent),
     IdentifyEvent(IdentifyEvent),
+    #[cfg(not(target_arch = "wasm32"))]
     MdnsEvent(MdnsEvent),
     PingEvent(PingEvent),
+    MailboxEvent(RequestResponseEvent<MailboxRequest, MailboxResponse>),
+    DeviceSyncEvent(RequestResponseEvent<DeviceSyncRequest, DeviceSnapshot>),
+    StreamEvent(RequestResponseEvent<StreamMessage, StreamResponse>),
+    ProfileEvent(RequestResponseEvent<PeerProfile, PeerProfile>),
+    FileTransferEvent(RequestResponseEvent<TransferRequest, TransferResponse>),
+    ConfluxEvent(RequestResponseEvent<FragmentRequest, FragmentResponse>),
+    DirectEvent(RequestResponseEvent<DirectMessage, DirectAck>),
+}
+
+impl From<RequestResponseEvent<DirectMessage, DirectAck>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<DirectMessage, DirectAck>) -> Self {
+        BehaviourEvent::DirectEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<FragmentRequest, FragmentResponse>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<FragmentRequest, FragmentResponse>) -> Self {
+        BehaviourEvent::ConfluxEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<TransferRequest, TransferResponse>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<TransferRequest, TransferResponse>) -> Self {
+        BehaviourEvent::FileTransferEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<PeerProfile, PeerProfile>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<PeerProfile, PeerProfile>) -> Self {
+        BehaviourEvent::ProfileEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<StreamMessage, StreamResponse>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<StreamMessage, StreamResponse>) -> Self {
+        BehaviourEvent::StreamEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<DeviceSyncRequest, DeviceSnapshot>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<DeviceSyncRequest, DeviceSnapshot>) -> Self {
+        BehaviourEvent::DeviceSyncEvent(event)
+    }
+}
+
+impl From<RequestResponseEvent<MailboxRequest, MailboxResponse>> for BehaviourEvent {
+    fn from(event: RequestResponseEvent<MailboxRequest, MailboxResponse>) -> Self {
+        BehaviourEvent::MailboxEvent(event)
+    }
 }
 
 impl From<PingEvent> for BehaviourEvent {
@@ -85,12 +243,20 @@ impl From<PingEvent> for BehaviourEvent {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl From<MdnsEvent> for BehaviourEvent {
     fn from(event: MdnsEvent) -> Self {
         BehaviourEvent::MdnsEvent(event)
     }
 }
 
+#[cfg(target_arch = "wasm32")]
+impl From<void::Void> for BehaviourEvent {
+    fn from(event: void::Void) -> Self {
+        void::unreachable(event)
+    }
+}
+
 impl From<IdentifyEvent> for BehaviourEvent {
     fn from(event: IdentifyEvent) -> Self {
         BehaviourEvent::IdentifyEvent(event)
@@ -109,6 +275,12 @@ impl From<GossipsubEvent> for BehaviourEvent {
     }
 }
 
+impl From<rendezvous::client::Event> for BehaviourEvent {
+    fn from(event: rendezvous::client::Event) -> Self {
+        BehaviourEvent::RendezvousEvent(event)
+    }
+}
+
 impl From<Event> for BehaviourEvent {
     fn from(event: Event) -> Self {
         BehaviourEvent::RelayEvent(event)
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+// Which received messages are written to the PocketDimension cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheScope {
+    #[default]
+    All,
+    // Only messages on pairwise topics, what we hold as someone's mailbox stays out
+    Direct,
+    Nothing,
+}
+
+// Decides what the gossipsub receive handler hands to `PocketDimension::add_data`.
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    pub scope: CacheScope,
+    // Whether to cache a topic regardless of the scope
+    pub overrides: HashMap<String, bool>,
+    // Bytes Blink may have written to the cache at once, messages past it are not cached
+    pub max_size: Option<u64>,
+    // How long a write counts against `max_size`, the cache is expected to expire entries on the same schedule
+    pub ttl: Option<Duration>,
+}
+
+impl CachePolicy {
+    pub fn with_override(mut self, topic: impl Into<String>, cache: bool) -> Self {
+        self.overrides.insert(topic.into(), cache);
+        self
+    }
+
+    fn covers(&self, topic: &str, direct: bool) -> bool {
+        if let Some(cache) = self.overrides.get(topic) {
+            return *cache;
+        }
+        match self.scope {
+            CacheScope::All => true,
+            CacheScope::Direct => direct,
+            CacheScope::Nothing => false,
+        }
+    }
+}
+
+// The policy in effect and the writes counting against its size limit.
+#[derive(Default)]
+pub(crate) struct CacheLedger {
+    policy: CachePolicy,
+    // (written at, bytes), oldest first
+    writes: VecDeque<(u64, u64)>,
+    size: u64,
+}
+
+impl CacheLedger {
+    pub(crate) fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: CachePolicy) {
+        self.policy = policy;
+    }
+
+    // Whether a message of `bytes` on `topic` should be cached, counting it if so.
+    pub(crate) fn admit(&mut self, topic: &str, direct: bool, bytes: u64, now: u64) -> bool {
+        if !self.policy.covers(topic, direct) {
+            return false;
+        }
+        self.expire(now);
+        if let Some(max_size) = self.policy.max_size {
+            if self.size + bytes > max_size {
+                return false;
+            }
+        }
+        self.writes.push_back((now, bytes));
+        self.size += bytes;
+        true
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn expire(&mut self, now: u64) {
+        let ttl = match self.policy.ttl {
+            Some(ttl) => ttl.as_millis() as u64,
+            None => return,
+        };
+        while let Some((written_at, bytes)) = self.writes.front().copied() {
+            if now.saturating_sub(written_at) < ttl {
+                break;
+            }
+            self.writes.pop_front();
+            self.size -= bytes;
+        }
+    }
+}
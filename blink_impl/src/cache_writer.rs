@@ -0,0 +1,65 @@
+use crate::event_sink::EventSink;
+use crate::shared_state::SharedState;
+use blink_contract::Event;
+use sata::Sata;
+use std::sync::Arc;
+use tokio::sync::mpsc::{error::TrySendError, Receiver, Sender};
+use warp::{data::DataType, pocket_dimension::PocketDimension, sync::RwLock};
+
+// Messages waiting for the cache, past it new ones are dropped rather than stall the event loop
+pub(crate) const CACHE_QUEUE_SIZE: usize = 1024;
+
+// Hands received messages to a task writing them to the cache, so a slow cache only
+// holds up itself.
+#[derive(Clone)]
+pub(crate) struct CacheWriter {
+    queue: Sender<(DataType, Sata)>,
+}
+
+impl CacheWriter {
+    pub(crate) fn new(queue: Sender<(DataType, Sata)>) -> Self {
+        Self { queue }
+    }
+
+    pub(crate) fn write(&self, logger: &EventSink, sata: Sata) {
+        self.write_as(logger, DataType::Messaging, sata);
+    }
+
+    pub(crate) fn write_as(&self, logger: &EventSink, dimension: DataType, sata: Sata) {
+        match self.queue.try_send((dimension, sata)) {
+            Ok(_) => {}
+            Err(TrySendError::Full(_)) => {
+                logger.event_occurred(Event::ErrorAddingToCache("Cache queue full".into()))
+            }
+            // The writer stops with the service, nothing is left to cache for
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    // Caches the edit or deletion of a message after the writes queued before it. The cache
+    // only ever grows, `apply_changes` gives the messages as they read now.
+    pub(crate) async fn write_change(&self, change: Sata) {
+        // Waits for room rather than leave a stale message behind
+        let _ = self.queue.send((DataType::Messaging, change)).await;
+    }
+}
+
+// Writes queued messages to the cache, in the order they were received.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) async fn write_to_cache(
+    cache: Arc<RwLock<dyn PocketDimension>>,
+    logger: EventSink,
+    mut queued: Receiver<(DataType, Sata)>,
+    state: SharedState,
+) {
+    while let Some((dimension, sata)) = queued.recv().await {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = cache.write().add_data(dimension, &sata);
+        #[cfg(feature = "metrics")]
+        state.metrics.write().cache_written(started.elapsed());
+        if let Err(e) = result {
+            logger.event_occurred(Event::ErrorAddingToCache(e.enum_to_string()));
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::{BTreeSet, HashMap};
+
+// Where a message came in: the conversation, by the DID it's with, and the channel of it.
+// Messages sent with `send` have no channel, they're on the bare pairwise topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelTopic {
+    pub did: String,
+    pub channel: Option<String>,
+}
+
+// Channels apps opened on the conversations, delivered to the message stream under a topic of
+// their own. Gossipsub has no wildcards, so a channel opened on every conversation is
+// subscribed on each pairwise topic, the ones paired later included.
+#[derive(Default)]
+pub(crate) struct ChannelRegistry {
+    everywhere: BTreeSet<String>,
+    // DID of the conversation to the channels opened on it alone
+    conversations: HashMap<String, BTreeSet<String>>,
+}
+
+impl ChannelRegistry {
+    // Opens the channel on the DID's conversation, or on every one without a DID. Returns false
+    // if it already was.
+    pub(crate) fn open(&mut self, did: Option<&str>, channel: &str) -> bool {
+        match did {
+            Some(did) => self
+                .conversations
+                .entry(did.to_string())
+                .or_default()
+                .insert(channel.to_string()),
+            None => self.everywhere.insert(channel.to_string()),
+        }
+    }
+
+    // Returns false if the channel wasn't opened that way. A channel opened on every
+    // conversation stays open on the ones it was also opened on by DID.
+    pub(crate) fn close(&mut self, did: Option<&str>, channel: &str) -> bool {
+        match did {
+            Some(did) => {
+                let closed = self
+                    .conversations
+                    .get_mut(did)
+                    .map_or(false, |x| x.remove(channel));
+                if self.conversations.get(did).map_or(false, |x| x.is_empty()) {
+                    self.conversations.remove(did);
+                }
+                closed
+            }
+            None => self.everywhere.remove(channel),
+        }
+    }
+
+    pub(crate) fn is_open(&self, did: &str, channel: &str) -> bool {
+        self.everywhere.contains(channel)
+            || self
+                .conversations
+                .get(did)
+                .map_or(false, |x| x.contains(channel))
+    }
+
+    // Whether the channel is open on any conversation.
+    pub(crate) fn is_known(&self, channel: &str) -> bool {
+        self.everywhere.contains(channel)
+            || self.conversations.values().any(|x| x.contains(channel))
+    }
+
+    // Channels open on the DID's conversation, sorted.
+    pub(crate) fn channels_of(&self, did: &str) -> Vec<String> {
+        let mut channels = self.everywhere.clone();
+        if let Some(opened) = self.conversations.get(did) {
+            channels.extend(opened.iter().cloned());
+        }
+        channels.into_iter().collect()
+    }
+}
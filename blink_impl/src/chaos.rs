@@ -0,0 +1,141 @@
+use crate::peer_to_peer_service::BlinkCommand;
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use std::collections::HashSet;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+
+#[derive(Default)]
+struct Faults {
+    dropped_publishes: AtomicUsize,
+    failed_cache_writes: AtomicUsize,
+    identify_delay_millis: AtomicU64,
+    publish_delay_millis: AtomicU64,
+    cut_links: Mutex<HashSet<PeerId>>,
+}
+
+// Control handle to inject faults into a running service's swarm loop.
+// Lets tests reproduce lossy or slow conditions deterministically, without external network tooling.
+#[derive(Clone, Default)]
+pub struct ChaosHandle {
+    faults: Arc<Faults>,
+    // Link faults need the swarm, they go through the event loop
+    commands: Option<Sender<BlinkCommand>>,
+}
+
+impl ChaosHandle {
+    pub(crate) fn new(commands: Sender<BlinkCommand>) -> Self {
+        Self {
+            faults: Arc::default(),
+            commands: Some(commands),
+        }
+    }
+
+    // The next `count` publishes are reported as sent but never reach the network
+    pub fn drop_next_publishes(&self, count: usize) {
+        self.faults
+            .dropped_publishes
+            .store(count, Ordering::Release);
+    }
+
+    pub fn fail_next_cache_writes(&self, count: usize) {
+        self.faults
+            .failed_cache_writes
+            .store(count, Ordering::Release);
+    }
+
+    // Identified peers are only handled once the delay passed, zero turns it off
+    pub fn delay_identify(&self, delay: Duration) {
+        self.faults
+            .identify_delay_millis
+            .store(delay.as_millis() as u64, Ordering::Release);
+    }
+
+    // Every publish goes out only once the delay passed, zero turns it off
+    pub fn delay_publishes(&self, delay: Duration) {
+        self.faults
+            .publish_delay_millis
+            .store(delay.as_millis() as u64, Ordering::Release);
+    }
+
+    // Disconnects the peer and refuses its connections until the link is healed
+    pub fn cut_link(&self, peer: PeerId) -> Result<()> {
+        self.send(BlinkCommand::CutLink(peer, true))?;
+        self.faults
+            .cut_links
+            .lock()
+            .expect("Not poisoned")
+            .insert(peer);
+        Ok(())
+    }
+
+    pub fn heal_link(&self, peer: PeerId) -> Result<()> {
+        self.send(BlinkCommand::CutLink(peer, false))?;
+        self.faults
+            .cut_links
+            .lock()
+            .expect("Not poisoned")
+            .remove(&peer);
+        Ok(())
+    }
+
+    pub fn reset(&self) {
+        self.drop_next_publishes(0);
+        self.fail_next_cache_writes(0);
+        self.delay_identify(Duration::ZERO);
+        self.delay_publishes(Duration::ZERO);
+        let cut: Vec<PeerId> = self
+            .faults
+            .cut_links
+            .lock()
+            .expect("Not poisoned")
+            .iter()
+            .copied()
+            .collect();
+        for peer in cut {
+            let _ = self.heal_link(peer);
+        }
+    }
+
+    pub(crate) fn take_dropped_publish(&self) -> bool {
+        take_one(&self.faults.dropped_publishes)
+    }
+
+    pub(crate) fn take_failed_cache_write(&self) -> bool {
+        take_one(&self.faults.failed_cache_writes)
+    }
+
+    pub(crate) fn identify_delay(&self) -> Option<Duration> {
+        match self.faults.identify_delay_millis.load(Ordering::Acquire) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    pub(crate) fn publish_delay(&self) -> Option<Duration> {
+        match self.faults.publish_delay_millis.load(Ordering::Acquire) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    fn send(&self, command: BlinkCommand) -> Result<()> {
+        let commands = self
+            .commands
+            .as_ref()
+            .ok_or_else(|| anyhow!("Not attached to a service"))?;
+        commands
+            .try_send(command)
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+}
+
+fn take_one(counter: &AtomicUsize) -> bool {
+    counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |x| x.checked_sub(1))
+        .is_ok()
+}
@@ -0,0 +1,60 @@
+use crate::runtime;
+use blink_contract::Clock;
+use libp2p::futures::future::BoxFuture;
+use std::time::Duration;
+use tokio::sync::watch;
+
+// Clock backed by the system time and tokio timers, the browser's in wasm32.
+#[derive(Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        runtime::unix_millis()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let sleep = tokio::time::sleep(duration);
+        #[cfg(target_arch = "wasm32")]
+        let sleep = futures_timer::Delay::new(duration);
+        Box::pin(sleep)
+    }
+}
+
+// Clock that only moves when `advance` is called.
+// Lets tests go through retries, retention and other timed behaviour without waiting for real.
+pub struct VirtualClock {
+    now: watch::Sender<u64>,
+}
+
+impl VirtualClock {
+    pub fn new(start_millis: u64) -> Self {
+        let (now, _) = watch::channel(start_millis);
+        Self { now }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let now = *self.now.borrow() + duration.as_millis() as u64;
+        self.now.send_replace(now);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now_millis(&self) -> u64 {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut now = self.now.subscribe();
+        let deadline = *now.borrow() + duration.as_millis() as u64;
+        Box::pin(async move {
+            while *now.borrow() < deadline {
+                // The clock is gone, so time won't move anymore
+                if now.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
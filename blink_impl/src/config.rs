@@ -0,0 +1,316 @@
+use crate::bandwidth::BandwidthCaps;
+use crate::batching::BatchSettings;
+use crate::cache_policy::{CachePolicy, CacheScope};
+use crate::delivery::DeliverySettings;
+use crate::event_history::Verbosity;
+use crate::idle::IdlePolicy;
+use crate::rate_limit::RateLimits;
+use crate::relay_server::RelayServerSettings;
+use crate::scoring::ScoreSettings;
+use anyhow::{anyhow, Context, Result};
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// Every environment override starts with it, e.g. BLINK_LISTEN_ADDRS
+const ENV_PREFIX: &str = "BLINK_";
+
+// Networking parameters of a service, loaded with `from_file` so deployments can change them
+// without recompiling. Missing keys keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlinkConfig {
+    pub listen_addrs: Vec<Multiaddr>,
+    // Addresses we're reachable at whatever peers observe, e.g. a forwarded port
+    pub external_addrs: Vec<Multiaddr>,
+    // Known nodes, /p2p/ addresses are added to Kademlia and gossiped with directly
+    pub bootstrap: Vec<Multiaddr>,
+    // Relay nodes dialed on startup, for peers that can't be reached directly
+    pub relays: Vec<Multiaddr>,
+    // Rendezvous nodes to register our DID at, with their /p2p/ part
+    pub rendezvous: Vec<Multiaddr>,
+    // Announce ourselves and look for peers on the local network
+    pub mdns: bool,
+    // Messages to DIDs we aren't paired with are held while we find, dial and pair with them,
+    // instead of failing
+    pub auto_pair: bool,
+    pub gossipsub: GossipsubTuning,
+    pub kademlia: KademliaSettings,
+    pub cache: CacheSettings,
+    pub rate_limits: RateLimits,
+    pub bandwidth: BandwidthCaps,
+    pub idle: IdlePolicy,
+    pub relay_server: RelayServerSettings,
+    pub batching: BatchSettings,
+    pub delivery: DeliverySettings,
+    pub scoring: ScoreSettings,
+    pub verbosity: Verbosity,
+    // File holding this device's transport key, created on first start. Without it every start
+    // gets a new key, and so a new PeerId
+    pub device_key: Option<PathBuf>,
+}
+
+impl Default for BlinkConfig {
+    fn default() -> Self {
+        Self {
+            listen_addrs: vec!["/ip4/0.0.0.0/tcp/0".parse().expect("Valid address")],
+            external_addrs: Vec::new(),
+            bootstrap: Vec::new(),
+            relays: Vec::new(),
+            rendezvous: Vec::new(),
+            mdns: true,
+            auto_pair: false,
+            gossipsub: GossipsubTuning::default(),
+            kademlia: KademliaSettings::default(),
+            cache: CacheSettings::default(),
+            rate_limits: RateLimits::default(),
+            bandwidth: BandwidthCaps::default(),
+            idle: IdlePolicy::default(),
+            relay_server: RelayServerSettings::default(),
+            batching: BatchSettings::default(),
+            delivery: DeliverySettings::default(),
+            scoring: ScoreSettings::default(),
+            verbosity: Verbosity::default(),
+            device_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipsubTuning {
+    pub heartbeat_interval_ms: u64,
+    // Peers kept in the mesh of each topic, and the bounds it's rebalanced within
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+}
+
+impl Default for GossipsubTuning {
+    fn default() -> Self {
+        Self {
+            // Slow on purpose, it keeps the logs readable
+            heartbeat_interval_ms: 10_000,
+            mesh_n: 6,
+            mesh_n_low: 5,
+            mesh_n_high: 12,
+        }
+    }
+}
+
+impl GossipsubTuning {
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KademliaSettings {
+    // Records and provider announcements of other peers aren't stored, so nobody relies on us
+    // for them. Ours are still stored and served
+    pub client_mode: bool,
+    // Protocol of an isolated DHT, e.g. /satellite/kad/1.0.0, only peers speaking it take part.
+    // Left out, the public IPFS DHT is joined
+    pub protocol: Option<String>,
+}
+
+// What `PeerToPeerService::reconfigure` changes while the service runs, values left out
+// stay as they are. Everything else needs a restart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDelta {
+    pub rate_limits: Option<RateLimits>,
+    pub cache: Option<CacheSettings>,
+    // Replaces the relays in use, new ones are dialed and the ones left out may close when idle
+    pub relays: Option<Vec<Multiaddr>>,
+    pub verbosity: Option<Verbosity>,
+}
+
+// `CachePolicy` in a form that reads well in a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub scope: CacheScope,
+    pub overrides: HashMap<String, bool>,
+    pub max_size: Option<u64>,
+    pub ttl_secs: Option<u64>,
+}
+
+impl CacheSettings {
+    pub fn policy(&self) -> CachePolicy {
+        CachePolicy {
+            scope: self.scope,
+            overrides: self.overrides.clone(),
+            max_size: self.max_size,
+            ttl: self.ttl_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+impl BlinkConfig {
+    // Reads a TOML file, then applies the `BLINK_*` environment variables on top.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let mut config = Self::from_toml(&text)?;
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    // Overrides from the environment. Lists are comma separated:
+    // BLINK_LISTEN_ADDRS, BLINK_EXTERNAL_ADDRS, BLINK_BOOTSTRAP, BLINK_RELAYS, BLINK_RENDEZVOUS,
+    // BLINK_MDNS (true or false), BLINK_AUTO_PAIR (true or false),
+    // BLINK_GOSSIPSUB_HEARTBEAT_INTERVAL_MS, BLINK_GOSSIPSUB_MESH_N, BLINK_GOSSIPSUB_MESH_N_LOW,
+    // BLINK_GOSSIPSUB_MESH_N_HIGH, BLINK_CACHE_SCOPE (all, direct or nothing),
+    // BLINK_CACHE_MAX_SIZE, BLINK_CACHE_TTL_SECS, BLINK_IDLE_TIMEOUT_SECS (empty to never close),
+    // BLINK_IDLE_KEEP_PAIRED, BLINK_RELAY_SERVER (true or false), BLINK_RELAY_MAX_RESERVATIONS,
+    // BLINK_RELAY_MAX_CIRCUITS, BLINK_RELAY_MAX_CIRCUIT_BYTES,
+    // BLINK_RELAY_MAX_CIRCUIT_DURATION_SECS, BLINK_BATCHING (true or false),
+    // BLINK_BATCH_MAX_MESSAGE_BYTES, BLINK_BATCH_WINDOW_MILLIS, BLINK_BATCH_MAX_BYTES,
+    // BLINK_DELIVERY_FLOOD_UP_TO, BLINK_PEER_SCORING (true or false),
+    // BLINK_SCORE_GRAYLIST_THRESHOLD, BLINK_SCORE_BLACKLIST_THRESHOLD, BLINK_KADEMLIA_CLIENT_MODE
+    // (true or false), BLINK_KADEMLIA_PROTOCOL (empty for the public DHT), BLINK_VERBOSITY (all,
+    // quiet or silent) and BLINK_DEVICE_KEY (empty for a new key every start).
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(std::env::vars())
+    }
+
+    pub(crate) fn apply_vars(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<()> {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) => key,
+                None => continue,
+            };
+            let context = || format!("Invalid value for {}", name);
+            match key {
+                "LISTEN_ADDRS" => self.listen_addrs = list(&value).with_context(context)?,
+                "EXTERNAL_ADDRS" => self.external_addrs = list(&value).with_context(context)?,
+                "BOOTSTRAP" => self.bootstrap = list(&value).with_context(context)?,
+                "RELAYS" => self.relays = list(&value).with_context(context)?,
+                "RENDEZVOUS" => self.rendezvous = list(&value).with_context(context)?,
+                "MDNS" => self.mdns = value.parse().with_context(context)?,
+                "AUTO_PAIR" => self.auto_pair = value.parse().with_context(context)?,
+                "GOSSIPSUB_HEARTBEAT_INTERVAL_MS" => {
+                    self.gossipsub.heartbeat_interval_ms = value.parse().with_context(context)?
+                }
+                "GOSSIPSUB_MESH_N" => {
+                    self.gossipsub.mesh_n = value.parse().with_context(context)?
+                }
+                "GOSSIPSUB_MESH_N_LOW" => {
+                    self.gossipsub.mesh_n_low = value.parse().with_context(context)?
+                }
+                "GOSSIPSUB_MESH_N_HIGH" => {
+                    self.gossipsub.mesh_n_high = value.parse().with_context(context)?
+                }
+                "CACHE_SCOPE" => self.cache.scope = scope(&value).with_context(context)?,
+                "CACHE_MAX_SIZE" => {
+                    self.cache.max_size = Some(value.parse().with_context(context)?)
+                }
+                "CACHE_TTL_SECS" => {
+                    self.cache.ttl_secs = Some(value.parse().with_context(context)?)
+                }
+                "IDLE_TIMEOUT_SECS" => {
+                    self.idle.timeout_secs = match value.trim() {
+                        "" => None,
+                        value => Some(value.parse().with_context(context)?),
+                    }
+                }
+                "IDLE_KEEP_PAIRED" => {
+                    self.idle.keep_paired = value.parse().with_context(context)?
+                }
+                "RELAY_SERVER" => {
+                    self.relay_server.enabled = value.parse().with_context(context)?
+                }
+                "RELAY_MAX_RESERVATIONS" => {
+                    self.relay_server.max_reservations = value.parse().with_context(context)?
+                }
+                "RELAY_MAX_CIRCUITS" => {
+                    self.relay_server.max_circuits = value.parse().with_context(context)?
+                }
+                "RELAY_MAX_CIRCUIT_BYTES" => {
+                    self.relay_server.max_circuit_bytes = value.parse().with_context(context)?
+                }
+                "RELAY_MAX_CIRCUIT_DURATION_SECS" => {
+                    self.relay_server.max_circuit_duration_secs =
+                        value.parse().with_context(context)?
+                }
+                "BATCHING" => self.batching.enabled = value.parse().with_context(context)?,
+                "BATCH_MAX_MESSAGE_BYTES" => {
+                    self.batching.max_message_bytes = value.parse().with_context(context)?
+                }
+                "BATCH_WINDOW_MILLIS" => {
+                    self.batching.window_millis = value.parse().with_context(context)?
+                }
+                "BATCH_MAX_BYTES" => {
+                    self.batching.max_batch_bytes = value.parse().with_context(context)?
+                }
+                "DELIVERY_FLOOD_UP_TO" => {
+                    self.delivery.flood_up_to = value.parse().with_context(context)?
+                }
+                "KADEMLIA_CLIENT_MODE" => {
+                    self.kademlia.client_mode = value.parse().with_context(context)?
+                }
+                "KADEMLIA_PROTOCOL" => {
+                    self.kademlia.protocol =
+                        Some(value.trim().to_string()).filter(|x| !x.is_empty())
+                }
+                "PEER_SCORING" => self.scoring.enabled = value.parse().with_context(context)?,
+                "SCORE_GRAYLIST_THRESHOLD" => {
+                    self.scoring.graylist_threshold = value.parse().with_context(context)?
+                }
+                "SCORE_BLACKLIST_THRESHOLD" => {
+                    self.scoring.blacklist_threshold = value.parse().with_context(context)?
+                }
+                "VERBOSITY" => self.verbosity = verbosity(&value).with_context(context)?,
+                "DEVICE_KEY" => {
+                    self.device_key =
+                        Some(PathBuf::from(value.trim())).filter(|x| !x.as_os_str().is_empty())
+                }
+                // Other tools may share the prefix
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn list<T: FromStr>(value: &str) -> Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| Ok(x.parse()?))
+        .collect()
+}
+
+fn scope(value: &str) -> Result<CacheScope> {
+    match value.to_lowercase().as_str() {
+        "all" => Ok(CacheScope::All),
+        "direct" => Ok(CacheScope::Direct),
+        "nothing" => Ok(CacheScope::Nothing),
+        _ => Err(anyhow!("Unknown cache scope {}", value)),
+    }
+}
+
+fn verbosity(value: &str) -> Result<Verbosity> {
+    match value.to_lowercase().as_str() {
+        "all" => Ok(Verbosity::All),
+        "quiet" => Ok(Verbosity::Quiet),
+        "silent" => Ok(Verbosity::Silent),
+        _ => Err(anyhow!("Unknown verbosity {}", value)),
+    }
+}
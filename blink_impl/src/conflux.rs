@@ -0,0 +1,856 @@
+use crate::{
+    fragment_store::{FragmentStore, MemoryFragmentStore},
+    fragment_tree::{self, FragmentTree},
+    fragments::{CidPolicy, DataFragment},
+    live_fragment::LiveFragment,
+    peer_to_peer_service::BlinkCommand,
+    protocol::{BincodeCodec, BlinkProtocol},
+};
+use anyhow::anyhow;
+use blink_contract::{BlinkError, Clock};
+use libp2p::{
+    futures::Stream,
+    request_response::{ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig},
+    PeerId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, iter,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError, Receiver, Sender},
+    oneshot,
+};
+use warp::sync::RwLock;
+
+const CONFLUX_PROTOCOL: &[u8] = b"/blink/conflux/1.0.0";
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+const WATCH_QUEUE_SIZE: usize = 64;
+
+// Bounds the fragment store is kept within, pinned fragments are never evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcLimits {
+    pub max_fragments: Option<usize>,
+    pub max_bytes: Option<u64>,
+    // Fragments whose timestamp is older than this are evicted
+    pub max_age: Option<Duration>,
+}
+
+impl Default for GcLimits {
+    fn default() -> Self {
+        Self {
+            max_fragments: None,
+            max_bytes: Some(256 * 1024 * 1024),
+            max_age: None,
+        }
+    }
+}
+
+pub(crate) type ConfluxBehaviour = RequestResponse<BincodeCodec<FragmentRequest, FragmentResponse>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum FragmentRequest {
+    Want(String),
+    // New version of a fragment, merged by peers holding a replica
+    Update(DataFragment),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum FragmentResponse {
+    Have(DataFragment),
+    DontHave,
+}
+
+pub(crate) fn new_behaviour() -> ConfluxBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(CONFLUX_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+// A fragment we asked our peers for, with whoever waits for it
+#[derive(Default)]
+struct Want {
+    asked: HashSet<RequestId>,
+    peers: HashSet<PeerId>,
+    // Looking for providers on the DHT
+    searching: bool,
+    waiters: Vec<oneshot::Sender<Option<DataFragment>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentUpdate {
+    // Added or updated on this node
+    Local(DataFragment),
+    // Fetched from a peer or changed by a merged remote update
+    Remote(DataFragment),
+    Removed,
+    // A live fragment woke up or was killed
+    Alive(bool),
+}
+
+// Changes to one fragment, in the order they happened.
+// A watcher that falls more than 64 updates behind misses the ones in between.
+pub struct FragmentWatch {
+    updates: Receiver<FragmentUpdate>,
+}
+
+impl Stream for FragmentWatch {
+    type Item = FragmentUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FragmentUpdate>> {
+        self.updates.poll_recv(cx)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfluxError {
+    NotFound(String),
+    // The data doesn't hash to the CID the fragment claims
+    CidMismatch(String),
+    // No peer had the fragment before the timeout
+    Unavailable(String),
+    // The fragment store failed
+    Storage(String),
+    // A blob whose tree doesn't decode or doesn't add up
+    InvalidTree(String),
+    // Killed, or content addressed and so unable to keep its CID across versions
+    NotLive(String),
+    // Another fragment is already stored under this CID
+    Collision(String),
+    // The payload couldn't be turned into a fragment
+    Encoding(String),
+    // The service was stopped
+    Closed,
+}
+
+impl fmt::Display for ConfluxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfluxError::NotFound(cid) => write!(f, "Fragment {} isn't stored", cid),
+            ConfluxError::CidMismatch(cid) => write!(f, "Fragment data doesn't match {}", cid),
+            ConfluxError::Unavailable(cid) => write!(f, "No peer has fragment {}", cid),
+            ConfluxError::Storage(e) => write!(f, "Fragment store error: {}", e),
+            ConfluxError::InvalidTree(e) => write!(f, "Invalid fragment tree: {}", e),
+            ConfluxError::NotLive(cid) => write!(f, "Fragment {} isn't live", cid),
+            ConfluxError::Collision(cid) => write!(f, "Fragment {} already exists", cid),
+            ConfluxError::Encoding(e) => write!(f, "Couldn't encode fragment: {}", e),
+            ConfluxError::Closed => write!(f, "Blink service stopped"),
+        }
+    }
+}
+
+impl std::error::Error for ConfluxError {}
+
+impl From<ConfluxError> for BlinkError {
+    fn from(error: ConfluxError) -> Self {
+        match error {
+            ConfluxError::NotFound(cid) => BlinkError::NotFound(format!("Fragment {}", cid)),
+            ConfluxError::Closed => BlinkError::ChannelClosed,
+            error => BlinkError::Fragment(error.to_string()),
+        }
+    }
+}
+
+// What happens when a fragment is added under a CID that is already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    #[default]
+    Reject,
+    // Keeps whichever has the later timestamp, then the higher version
+    ReplaceIfNewer,
+    // Merges both as last writer wins registers
+    Merge,
+}
+
+// Handle to the fragment store, cheap to clone and share between tasks.
+// Fragments added are announced on the DHT, the ones missing locally are fetched from peers.
+#[derive(Clone)]
+pub struct Conflux {
+    state: Arc<RwLock<ConfluxState>>,
+    commands: Sender<BlinkCommand>,
+    clock: Arc<dyn Clock>,
+    collisions: CollisionPolicy,
+}
+
+impl Conflux {
+    pub(crate) fn new(
+        state: Arc<RwLock<ConfluxState>>,
+        commands: Sender<BlinkCommand>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            state,
+            commands,
+            clock,
+            collisions: CollisionPolicy::default(),
+        }
+    }
+
+    // Policy the Oracle applies when it creates a fragment whose CID is taken.
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collisions = policy;
+        self
+    }
+
+    pub fn collision_policy(&self) -> CollisionPolicy {
+        self.collisions
+    }
+
+    pub async fn add(&self, fragment: DataFragment) -> Result<(), ConfluxError> {
+        if !fragment.verify() {
+            return Err(ConfluxError::CidMismatch(fragment.cid().to_string()));
+        }
+        let cid = fragment.cid().to_string();
+        self.state.write().add_fragment(fragment).map_err(storage)?;
+        self.send(BlinkCommand::Provide(cid)).await
+    }
+
+    // Stores a fragment unless another one holds its CID, in which case `policy` decides.
+    // Returns the fragment stored under the CID afterwards.
+    pub async fn add_with(
+        &self,
+        fragment: DataFragment,
+        policy: CollisionPolicy,
+    ) -> Result<DataFragment, ConfluxError> {
+        if !fragment.verify() {
+            return Err(ConfluxError::CidMismatch(fragment.cid().to_string()));
+        }
+        let cid = fragment.cid().to_string();
+        // Checked and stored under one lock, two fragments racing for a CID see each other
+        let stored = {
+            let mut state = self.state.write();
+            match state.stored(&cid).map_err(storage)? {
+                None => {
+                    state.add_fragment(fragment.clone()).map_err(storage)?;
+                    fragment
+                }
+                Some(existing) => match policy {
+                    CollisionPolicy::Reject => return Err(ConfluxError::Collision(cid)),
+                    CollisionPolicy::ReplaceIfNewer => {
+                        if (fragment.timestamp(), fragment.version())
+                            > (existing.timestamp(), existing.version())
+                        {
+                            state.add_fragment(fragment.clone()).map_err(storage)?;
+                            fragment
+                        } else {
+                            existing
+                        }
+                    }
+                    CollisionPolicy::Merge => {
+                        state.update(fragment).map_err(storage)?;
+                        state.stored(&cid).map_err(storage)?.unwrap_or(existing)
+                    }
+                },
+            }
+        };
+        self.send(BlinkCommand::Provide(cid)).await?;
+        Ok(stored)
+    }
+
+    // Moves to another store, announcing the fragments it already holds.
+    // Returns how many there were.
+    pub async fn use_store(
+        &self,
+        store: impl FragmentStore + 'static,
+    ) -> Result<usize, ConfluxError> {
+        let cids = store.cids().map_err(storage)?;
+        self.state.write().set_store(Box::new(store));
+        for cid in &cids {
+            self.send(BlinkCommand::Provide(cid.clone())).await?;
+        }
+        Ok(cids.len())
+    }
+
+    // Adds fragments in bulk, for migrations. Every fragment is checked against its CID first.
+    pub async fn import(&self, fragments: Vec<DataFragment>) -> Result<usize, ConfluxError> {
+        if let Some(forged) = fragments.iter().find(|x| !x.verify()) {
+            return Err(ConfluxError::CidMismatch(forged.cid().to_string()));
+        }
+        let count = fragments.len();
+        for fragment in fragments {
+            self.add(fragment).await?;
+        }
+        Ok(count)
+    }
+
+    // Every fragment stored locally.
+    pub async fn export(&self) -> Result<Vec<DataFragment>, ConfluxError> {
+        let state = self.state.read();
+        let mut fragments = Vec::new();
+        for cid in state.cids().map_err(storage)? {
+            if let Some(fragment) = state.stored(&cid).map_err(storage)? {
+                fragments.push(fragment);
+            }
+        }
+        Ok(fragments)
+    }
+
+    // Stores a new version of a fragment and pushes it to the connected peers,
+    // the ones holding a replica merge it and answer with theirs.
+    pub async fn update(&self, fragment: DataFragment) -> Result<(), ConfluxError> {
+        if !fragment.verify() {
+            return Err(ConfluxError::CidMismatch(fragment.cid().to_string()));
+        }
+        self.state
+            .write()
+            .update(fragment.clone())
+            .map_err(storage)?;
+        self.send(BlinkCommand::PushFragment(fragment)).await
+    }
+
+    // Stores new data as the next version of a fragment and pushes it like `update` does.
+    pub async fn set(&self, cid: &str, data: Vec<u8>) -> Result<DataFragment, ConfluxError> {
+        let mut fragment = self
+            .state
+            .read()
+            .stored(cid)
+            .map_err(storage)?
+            .ok_or_else(|| ConfluxError::NotFound(cid.to_string()))?;
+        fragment.set(data, self.clock.now_millis());
+        self.update(fragment.clone()).await?;
+        Ok(fragment)
+    }
+
+    // Follows a fragment with an identity CID, fetched from peers first if need be.
+    pub async fn live(&self, cid: &str) -> Result<LiveFragment, ConfluxError> {
+        let fragment = self.get_by_cid(cid).await?;
+        if fragment.policy() != CidPolicy::Identity {
+            return Err(ConfluxError::NotLive(cid.to_string()));
+        }
+        Ok(LiveFragment::new(cid.to_string(), self.clone()))
+    }
+
+    pub(crate) fn liveness_changed(&self, cid: &str, alive: bool) {
+        self.state.write().notify(cid, FragmentUpdate::Alive(alive));
+    }
+
+    // Splits a blob into a fragment tree and adds every fragment of it, returns the root CID.
+    pub async fn add_blob(&self, data: &[u8]) -> Result<String, ConfluxError> {
+        let tree = FragmentTree::split(data, self.clock.now_millis()).map_err(invalid_tree)?;
+        let root = tree.root().cid().to_string();
+        for fragment in tree.into_fragments() {
+            self.add(fragment).await?;
+        }
+        Ok(root)
+    }
+
+    // Fetches every fragment of a blob's tree, locally or from peers, and puts the blob back together.
+    pub async fn get_blob(&self, root: &str) -> Result<Vec<u8>, ConfluxError> {
+        self.get_blob_with_progress(root, |_, _| {}).await
+    }
+
+    // Same as get_blob, `progress` is given the bytes fetched so far and the blob's size
+    // after every chunk.
+    pub(crate) async fn get_blob_with_progress(
+        &self,
+        root: &str,
+        mut progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<Vec<u8>, ConfluxError> {
+        let mut fetched = HashMap::new();
+        let mut pending = vec![root.to_string()];
+        let (mut size, mut bytes) = (0, 0);
+        while let Some(cid) = pending.pop() {
+            // Repeated chunks are linked more than once, they only need fetching once
+            if fetched.contains_key(&cid) {
+                continue;
+            }
+            let fragment = self.get_by_cid(&cid).await?;
+            match fragment_tree::links(&fragment).map_err(invalid_tree)? {
+                Some((below, links)) => {
+                    if cid == root {
+                        size = below;
+                    }
+                    pending.extend(links);
+                }
+                None => {
+                    bytes += fragment.data().len() as u64;
+                    progress(bytes.min(size), size);
+                }
+            }
+            fetched.insert(cid, fragment);
+        }
+        fragment_tree::reassemble(&fetched[root], |cid| {
+            fetched
+                .get(cid)
+                .cloned()
+                .ok_or_else(|| anyhow!("Fragment {} wasn't fetched", cid))
+        })
+        .map_err(invalid_tree)
+    }
+
+    // Local copy if there is one, otherwise the fragment of the first peer having it.
+    pub async fn get_by_cid(&self, cid: &str) -> Result<DataFragment, ConfluxError> {
+        let (fragment, first) = self.state.write().want(cid.to_string());
+        if first {
+            self.send(BlinkCommand::WantFragment(cid.to_string()))
+                .await?;
+        }
+        tokio::select! {
+            fragment = fragment => fragment
+                .ok()
+                .flatten()
+                .ok_or_else(|| ConfluxError::Unavailable(cid.to_string())),
+            _ = self.clock.sleep(FETCH_TIMEOUT) => {
+                self.state.write().give_up(cid);
+                Err(ConfluxError::Unavailable(cid.to_string()))
+            }
+        }
+    }
+
+    // Follows the changes to a fragment, whether it is stored yet or not.
+    pub fn watch(&self, cid: &str) -> FragmentWatch {
+        FragmentWatch {
+            updates: self.state.write().watch(cid.to_string()),
+        }
+    }
+
+    // Keeps a stored fragment out of garbage collection.
+    pub async fn pin(&self, cid: &str) -> Result<(), ConfluxError> {
+        let mut state = self.state.write();
+        if state.stored(cid).map_err(storage)?.is_none() {
+            return Err(ConfluxError::NotFound(cid.to_string()));
+        }
+        state.pin(cid.to_string());
+        Ok(())
+    }
+
+    // Returns false if the fragment wasn't pinned.
+    pub async fn unpin(&self, cid: &str) -> bool {
+        self.state.write().unpin(cid)
+    }
+
+    // Pins every fragment of a blob's tree, they all have to be stored.
+    pub async fn pin_blob(&self, root: &str) -> Result<(), ConfluxError> {
+        let cids = self.stored_tree(root)?;
+        let mut state = self.state.write();
+        for cid in cids {
+            state.pin(cid);
+        }
+        Ok(())
+    }
+
+    // Returns false if the blob's root wasn't pinned. Chunks other pinned blobs share with it
+    // are unpinned too.
+    pub async fn unpin_blob(&self, root: &str) -> Result<bool, ConfluxError> {
+        let cids = self.stored_tree(root)?;
+        let mut state = self.state.write();
+        let pinned = state.unpin(root);
+        for cid in &cids {
+            state.unpin(cid);
+        }
+        Ok(pinned)
+    }
+
+    fn stored_tree(&self, root: &str) -> Result<HashSet<String>, ConfluxError> {
+        let mut cids = HashSet::new();
+        let mut pending = vec![root.to_string()];
+        while let Some(cid) = pending.pop() {
+            if cids.contains(&cid) {
+                continue;
+            }
+            let fragment = self
+                .state
+                .read()
+                .stored(&cid)
+                .map_err(storage)?
+                .ok_or_else(|| ConfluxError::NotFound(cid.clone()))?;
+            if let Some((_, links)) = fragment_tree::links(&fragment).map_err(invalid_tree)? {
+                pending.extend(links);
+            }
+            cids.insert(cid);
+        }
+        Ok(cids)
+    }
+
+    pub async fn set_gc_limits(&self, limits: GcLimits) {
+        self.state.write().set_limits(limits);
+    }
+
+    // Runs a collection now instead of waiting for the next periodic one.
+    pub async fn collect_garbage(&self) -> Result<(), ConfluxError> {
+        self.send(BlinkCommand::CollectGarbage).await
+    }
+
+    // Drops the local copy and stops announcing it.
+    pub async fn remove(&self, cid: &str) -> Result<DataFragment, ConfluxError> {
+        let fragment = self
+            .state
+            .write()
+            .remove_fragment(cid)
+            .map_err(storage)?
+            .ok_or_else(|| ConfluxError::NotFound(cid.to_string()))?;
+        self.send(BlinkCommand::StopProviding(cid.to_string()))
+            .await?;
+        Ok(fragment)
+    }
+
+    // CIDs of the fragments stored locally.
+    pub async fn list(&self) -> Result<Vec<String>, ConfluxError> {
+        self.state.read().cids().map_err(storage)
+    }
+
+    async fn send(&self, command: BlinkCommand) -> Result<(), ConfluxError> {
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| ConfluxError::Closed)
+    }
+}
+
+fn storage(e: anyhow::Error) -> ConfluxError {
+    ConfluxError::Storage(e.to_string())
+}
+
+fn invalid_tree(e: anyhow::Error) -> ConfluxError {
+    ConfluxError::InvalidTree(e.to_string())
+}
+
+// Fragments held by this node, and the ones it is fetching from its peers.
+pub(crate) struct ConfluxState {
+    store: Box<dyn FragmentStore>,
+    wants: HashMap<String, Want>,
+    requests: HashMap<RequestId, String>,
+    // Updates pushed to peers, their answer is merged back
+    pushes: HashSet<RequestId>,
+    // Peers that hold or asked for each fragment we hold, updates are pushed to them only
+    interested: HashMap<String, HashSet<PeerId>>,
+    watchers: HashMap<String, Vec<Sender<FragmentUpdate>>>,
+    pinned: HashSet<String>,
+    limits: GcLimits,
+    // Last access of each fragment as a tick of `clock`, for least recently used eviction
+    accessed: HashMap<String, u64>,
+    clock: u64,
+}
+
+impl Default for ConfluxState {
+    fn default() -> Self {
+        Self {
+            store: Box::new(MemoryFragmentStore::default()),
+            wants: HashMap::new(),
+            requests: HashMap::new(),
+            pushes: HashSet::new(),
+            interested: HashMap::new(),
+            watchers: HashMap::new(),
+            pinned: HashSet::new(),
+            limits: GcLimits::default(),
+            accessed: HashMap::new(),
+            clock: 0,
+        }
+    }
+}
+
+impl ConfluxState {
+    pub(crate) fn set_store(&mut self, store: Box<dyn FragmentStore>) {
+        self.store = store;
+    }
+
+    pub(crate) fn add_fragment(&mut self, fragment: DataFragment) -> anyhow::Result<()> {
+        self.store.put(fragment.clone())?;
+        self.touch(fragment.cid());
+        self.notify(fragment.cid(), FragmentUpdate::Local(fragment.clone()));
+        Ok(())
+    }
+
+    // Local change, merged into our replica if we have one.
+    pub(crate) fn update(&mut self, fragment: DataFragment) -> anyhow::Result<()> {
+        if self.store.get(fragment.cid())?.is_none() {
+            return self.add_fragment(fragment);
+        }
+        self.touch(fragment.cid());
+        if let Some(merged) = self.merge_stored(&fragment)? {
+            self.notify(fragment.cid(), FragmentUpdate::Local(merged));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove_fragment(&mut self, cid: &str) -> anyhow::Result<Option<DataFragment>> {
+        let removed = self.store.remove(cid)?;
+        if removed.is_some() {
+            self.pinned.remove(cid);
+            self.accessed.remove(cid);
+            self.interested.remove(cid);
+            self.notify(cid, FragmentUpdate::Removed);
+        }
+        Ok(removed)
+    }
+
+    pub(crate) fn pin(&mut self, cid: String) {
+        self.pinned.insert(cid);
+    }
+
+    pub(crate) fn unpin(&mut self, cid: &str) -> bool {
+        self.pinned.remove(cid)
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: GcLimits) {
+        self.limits = limits;
+    }
+
+    fn touch(&mut self, cid: &str) {
+        self.clock += 1;
+        self.accessed.insert(cid.to_string(), self.clock);
+    }
+
+    // Evicts unpinned fragments past their age, then the least recently used ones
+    // until the store fits its limits. Returns the evicted CIDs.
+    pub(crate) fn collect_garbage(&mut self, now: u64) -> Vec<String> {
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut candidates = Vec::new();
+        // Fragments the store fails to read or remove are left alone until the next collection
+        for cid in self.store.cids().unwrap_or_default() {
+            let fragment = match self.fragment(&cid) {
+                Some(fragment) => fragment,
+                None => continue,
+            };
+            let size = fragment.data().len() as u64;
+            count += 1;
+            bytes += size;
+            if !self.pinned.contains(&cid) {
+                // Fragments nobody touched since the node started count as the least recently used
+                let accessed = self.accessed.get(&cid).copied().unwrap_or(0);
+                candidates.push((accessed, fragment.timestamp(), cid, size));
+            }
+        }
+        candidates.sort();
+
+        let mut evicted = Vec::new();
+        for (_, timestamp, cid, size) in candidates {
+            let expired = self.limits.max_age.map_or(false, |x| {
+                now.saturating_sub(timestamp) > x.as_millis() as u64
+            });
+            let over = self.limits.max_fragments.map_or(false, |x| count > x)
+                || self.limits.max_bytes.map_or(false, |x| bytes > x);
+            if !expired && !over {
+                continue;
+            }
+            if let Ok(Some(_)) = self.remove_fragment(&cid) {
+                count -= 1;
+                bytes -= size;
+                evicted.push(cid);
+            }
+        }
+        evicted
+    }
+
+    pub(crate) fn watch(&mut self, cid: String) -> Receiver<FragmentUpdate> {
+        let (sender, receiver) = mpsc::channel(WATCH_QUEUE_SIZE);
+        self.watchers.entry(cid).or_default().push(sender);
+        receiver
+    }
+
+    // Watchers that fell behind miss the update rather than stall the network loop
+    pub(crate) fn notify(&mut self, cid: &str, update: FragmentUpdate) {
+        if let Some(watchers) = self.watchers.get_mut(cid) {
+            watchers
+                .retain(|x| !matches!(x.try_send(update.clone()), Err(TrySendError::Closed(_))));
+            if watchers.is_empty() {
+                self.watchers.remove(cid);
+            }
+        }
+    }
+
+    pub(crate) fn cids(&self) -> anyhow::Result<Vec<String>> {
+        let mut cids = self.store.cids()?;
+        cids.sort();
+        Ok(cids)
+    }
+
+    pub(crate) fn stored(&self, cid: &str) -> anyhow::Result<Option<DataFragment>> {
+        self.store.get(cid)
+    }
+
+    // A store failing to read counts as not having the fragment, peers may still have it
+    pub(crate) fn fragment(&self, cid: &str) -> Option<DataFragment> {
+        self.store.get(cid).ok().flatten()
+    }
+
+    // Merges a remote version into our replica, fragments we don't hold are ignored.
+    // Returns true when our replica changed.
+    pub(crate) fn merge(&mut self, remote: DataFragment) -> anyhow::Result<bool> {
+        match self.merge_stored(&remote)? {
+            Some(merged) => {
+                self.notify(remote.cid(), FragmentUpdate::Remote(merged));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // The stored replica once the fragment was merged in, None if it didn't change
+    fn merge_stored(&mut self, fragment: &DataFragment) -> anyhow::Result<Option<DataFragment>> {
+        let mut local = match self.store.get(fragment.cid())? {
+            Some(local) => local,
+            None => return Ok(None),
+        };
+        if !local.merge(fragment)? {
+            return Ok(None);
+        }
+        self.store.put(local.clone())?;
+        Ok(Some(local))
+    }
+
+    pub(crate) fn pushed(&mut self, request_id: RequestId) {
+        self.pushes.insert(request_id);
+    }
+
+    // Only kept for fragments we hold, anyone could ask for CIDs we'd otherwise track forever
+    pub(crate) fn interest(&mut self, cid: &str, peer: PeerId) {
+        if self.fragment(cid).is_some() {
+            self.interested
+                .entry(cid.to_string())
+                .or_default()
+                .insert(peer);
+        }
+    }
+
+    pub(crate) fn interested(&self, cid: &str) -> Vec<PeerId> {
+        self.interested
+            .get(cid)
+            .map(|x| x.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // Registers interest in a fragment, the receiver resolves to None if no peer had it.
+    // Returns true when nobody was asked yet, so the caller has to send the want.
+    pub(crate) fn want(&mut self, cid: String) -> (oneshot::Receiver<Option<DataFragment>>, bool) {
+        let (sender, receiver) = oneshot::channel();
+        if let Some(fragment) = self.fragment(&cid) {
+            self.touch(&cid);
+            let _ = sender.send(Some(fragment));
+            return (receiver, false);
+        }
+        let first = !self.wants.contains_key(&cid);
+        self.wants.entry(cid).or_default().waiters.push(sender);
+        (receiver, first)
+    }
+
+    pub(crate) fn is_wanted(&self, cid: &str) -> bool {
+        self.wants.contains_key(cid)
+    }
+
+    pub(crate) fn asked(&mut self, request_id: RequestId, cid: String, peer: PeerId) {
+        if let Some(want) = self.wants.get_mut(&cid) {
+            want.asked.insert(request_id);
+            want.peers.insert(peer);
+            self.requests.insert(request_id, cid);
+        }
+    }
+
+    pub(crate) fn searching(&mut self, cid: &str) {
+        if let Some(want) = self.wants.get_mut(cid) {
+            want.searching = true;
+        }
+    }
+
+    // The DHT search ended, returns the providers we didn't ask yet.
+    // Gives up when there is nobody left to ask.
+    pub(crate) fn providers_found(
+        &mut self,
+        cid: &str,
+        providers: impl IntoIterator<Item = PeerId>,
+        local_peer_id: &PeerId,
+    ) -> Vec<PeerId> {
+        let want = match self.wants.get_mut(cid) {
+            Some(want) => want,
+            None => return Vec::new(),
+        };
+        want.searching = false;
+        let new: Vec<PeerId> = providers
+            .into_iter()
+            .filter(|x| x != local_peer_id && !want.peers.contains(x))
+            .collect();
+        if new.is_empty() && want.asked.is_empty() {
+            self.give_up(cid);
+        }
+        new
+    }
+
+    // A peer answered with a fragment, kept only if its data matches the CID we asked for.
+    // Answers to pushed updates are merged, returns true when that changed our replica.
+    pub(crate) fn received(&mut self, request_id: &RequestId, fragment: DataFragment) -> bool {
+        if self.pushes.remove(request_id) {
+            // Forged answers are dropped like any other failed merge
+            return self.merge(fragment).unwrap_or(false);
+        }
+        let cid = match self.requests.remove(request_id) {
+            Some(cid) => cid,
+            None => return false,
+        };
+        if !fragment.verify() || fragment.cid() != cid {
+            self.not_found(request_id, cid);
+            return false;
+        }
+        if let Some(want) = self.wants.remove(&cid) {
+            for request in want.asked {
+                self.requests.remove(&request);
+            }
+            for waiter in want.waiters {
+                let _ = waiter.send(Some(fragment.clone()));
+            }
+        }
+        // Waiters got the fragment already, failing to keep it only means fetching it again later
+        if self.store.put(fragment.clone()).is_ok() {
+            self.touch(&cid);
+            self.notify(&cid, FragmentUpdate::Remote(fragment));
+        }
+        false
+    }
+
+    // A peer didn't have the fragment or couldn't be reached.
+    pub(crate) fn missed(&mut self, request_id: &RequestId) {
+        self.pushes.remove(request_id);
+        if let Some(cid) = self.requests.remove(request_id) {
+            self.not_found(request_id, cid);
+        }
+    }
+
+    // Gives up on the fragment once every peer we asked said no and the DHT has no one else
+    fn not_found(&mut self, request_id: &RequestId, cid: String) {
+        let exhausted = match self.wants.get_mut(&cid) {
+            Some(want) => {
+                want.asked.remove(request_id);
+                want.asked.is_empty() && !want.searching
+            }
+            None => false,
+        };
+        if exhausted {
+            self.give_up(&cid);
+        }
+    }
+
+    pub(crate) fn give_up(&mut self, cid: &str) {
+        if let Some(want) = self.wants.remove(cid) {
+            for request in want.asked {
+                self.requests.remove(&request);
+            }
+            for waiter in want.waiters {
+                let _ = waiter.send(None);
+            }
+        }
+    }
+
+    // Answers a peer's want from what we hold, and an update with our replica once merged.
+    pub(crate) fn respond(&mut self, request: FragmentRequest) -> FragmentResponse {
+        let cid = match request {
+            FragmentRequest::Want(cid) => cid,
+            FragmentRequest::Update(fragment) => fragment.cid().to_string(),
+        };
+        match self.fragment(&cid) {
+            Some(fragment) => {
+                self.touch(&cid);
+                FragmentResponse::Have(fragment)
+            }
+            None => FragmentResponse::DontHave,
+        }
+    }
+}
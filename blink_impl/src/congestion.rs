@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+pub(crate) const AUDIO_MAX_BITRATE: u32 = 64_000;
+
+const MIN_BITRATE: u32 = 16_000;
+
+const DECREASE_FACTOR: f64 = 0.85;
+
+const INCREASE_FACTOR: f64 = 1.05;
+
+const HIGH_LOSS: f32 = 0.1;
+
+const LOW_LOSS: f32 = 0.02;
+
+// Smoothing factors from RFC 6298 and RFC 3550
+const RTT_GAIN: f64 = 0.125;
+const JITTER_GAIN: f64 = 1.0 / 16.0;
+
+// Queueing on the link shows up as RTT growing well past the best seen
+const QUEUEING_RTT_FACTOR: f64 = 2.0;
+const QUEUEING_RTT_MARGIN_MS: f64 = 50.0;
+
+// Receiver side view of a stream since the previous report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedbackReport {
+    pub received: u32,
+    pub lost: u32,
+    pub jitter_ms: u32,
+}
+
+// What the sender knows about the link a stream goes through.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkQuality {
+    pub rtt_ms: u32,
+    // Fraction of the frames lost during the last report
+    pub loss: f32,
+    pub jitter_ms: u32,
+    // Bits per second the encoder should aim for
+    pub target_bitrate: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct ReceiveStats {
+    received: u32,
+    lost: u32,
+    next_sequence: Option<u64>,
+    last_transit: Option<i64>,
+    jitter: f64,
+}
+
+impl ReceiveStats {
+    // `sent_at` is the sender's timestamp, only the difference between frames matters so clocks don't have to agree
+    pub(crate) fn on_frame(&mut self, sequence: u64, sent_at: Option<u64>, arrived_at: u64) {
+        match self.next_sequence {
+            // Already counted as lost
+            Some(next) if sequence < next => return,
+            // Sequence numbers and send times come from the remote, nothing here may overflow on them
+            Some(next) => {
+                let lost = (sequence - next).min(u32::MAX as u64) as u32;
+                self.lost = self.lost.saturating_add(lost);
+            }
+            None => {}
+        }
+        self.received = self.received.saturating_add(1);
+        self.next_sequence = Some(sequence.wrapping_add(1));
+
+        if let Some(sent_at) = sent_at {
+            let transit = (arrived_at as i64).saturating_sub(sent_at as i64);
+            if let Some(last_transit) = self.last_transit {
+                let deviation = transit.saturating_sub(last_transit).unsigned_abs() as f64;
+                self.jitter += (deviation - self.jitter) * JITTER_GAIN;
+            }
+            self.last_transit = Some(transit);
+        }
+    }
+
+    pub(crate) fn take_report(&mut self) -> Option<FeedbackReport> {
+        if self.received == 0 && self.lost == 0 {
+            return None;
+        }
+        let report = FeedbackReport {
+            received: self.received,
+            lost: self.lost,
+            jitter_ms: self.jitter.round() as u32,
+        };
+        self.received = 0;
+        self.lost = 0;
+        Some(report)
+    }
+}
+
+// Sender side bitrate control: backs off on loss or queueing, probes up slowly while the link is clean.
+pub(crate) struct RateController {
+    max_bitrate: u32,
+    target_bitrate: u32,
+    smoothed_rtt: Option<f64>,
+    min_rtt: Option<f64>,
+    loss: f32,
+    jitter_ms: u32,
+}
+
+impl RateController {
+    pub(crate) fn new(max_bitrate: u32) -> Self {
+        Self {
+            max_bitrate,
+            target_bitrate: max_bitrate,
+            smoothed_rtt: None,
+            min_rtt: None,
+            loss: 0.0,
+            jitter_ms: 0,
+        }
+    }
+
+    pub(crate) fn set_max_bitrate(&mut self, max_bitrate: u32) {
+        self.max_bitrate = max_bitrate;
+        self.target_bitrate = self.target_bitrate.min(max_bitrate);
+    }
+
+    pub(crate) fn on_rtt_sample(&mut self, rtt_ms: u64) {
+        let rtt = rtt_ms as f64;
+        self.smoothed_rtt = Some(match self.smoothed_rtt {
+            Some(smoothed) => smoothed + (rtt - smoothed) * RTT_GAIN,
+            None => rtt,
+        });
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |x| x.min(rtt)));
+    }
+
+    // Adapts the target to the receiver's report, returns the new target if it changed.
+    pub(crate) fn on_feedback(&mut self, report: FeedbackReport) -> Option<u32> {
+        let total = report.received + report.lost;
+        self.loss = if total == 0 {
+            0.0
+        } else {
+            report.lost as f32 / total as f32
+        };
+        self.jitter_ms = report.jitter_ms;
+
+        let queueing = match (self.smoothed_rtt, self.min_rtt) {
+            (Some(smoothed), Some(min)) => {
+                smoothed > min * QUEUEING_RTT_FACTOR + QUEUEING_RTT_MARGIN_MS
+            }
+            _ => false,
+        };
+        let target = if self.loss > HIGH_LOSS || queueing {
+            (self.target_bitrate as f64 * DECREASE_FACTOR) as u32
+        } else if self.loss < LOW_LOSS {
+            (self.target_bitrate as f64 * INCREASE_FACTOR).ceil() as u32
+        } else {
+            self.target_bitrate
+        };
+        let target = target.clamp(MIN_BITRATE.min(self.max_bitrate), self.max_bitrate);
+
+        if target == self.target_bitrate {
+            return None;
+        }
+        self.target_bitrate = target;
+        Some(target)
+    }
+
+    pub(crate) fn quality(&self) -> LinkQuality {
+        LinkQuality {
+            rtt_ms: self.smoothed_rtt.map_or(0, |x| x.round() as u32),
+            loss: self.loss,
+            jitter_ms: self.jitter_ms,
+            target_bitrate: self.target_bitrate,
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use crate::{
+    conflux::Conflux, event_sink::EventSink, peer_to_peer_service::PeerToPeerService,
+    wire::bounded_bincode,
+};
+use async_trait::async_trait;
+use blink_contract::{BlinkError, Event, ExtensionHandler};
+use chrono::{DateTime, Utc};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+use warp::{
+    constellation::{directory::Directory, file::File, Constellation},
+    crypto::DID,
+    error::Error,
+    module::Module,
+    sync::RwLock,
+    Extension, SingleHandle,
+};
+
+// Extension the directory offers go through
+const SHARE_NAMESPACE: &str = "constellation";
+
+// A file of the synced tree, its contents are a blob in Conflux.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncedFile {
+    pub(crate) cid: String,
+    pub(crate) size: u64,
+}
+
+// Every file by normalized path, and the directories nothing is in yet. Shared as is, as the
+// index of a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SyncIndex {
+    files: BTreeMap<String, SyncedFile>,
+    directories: BTreeSet<String>,
+}
+
+// Path relative to the root, without empty or `.` segments. `..` is refused so a shared index
+// can't reach outside of where it's mounted.
+pub(crate) fn normalize(path: &str) -> Result<String, Error> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                return Err(Error::OtherWithContext(format!(
+                    "Path {} leaves its directory",
+                    path
+                )))
+            }
+            _ => segments.push(segment),
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+fn join(parent: &str, child: &str) -> String {
+    match (parent.is_empty(), child.is_empty()) {
+        (true, _) => child.to_string(),
+        (_, true) => parent.to_string(),
+        _ => format!("{}/{}", parent, child),
+    }
+}
+
+// What's left of the path below the directory, None if it isn't below it
+fn below<'a>(path: &'a str, directory: &str) -> Option<&'a str> {
+    if directory.is_empty() {
+        return Some(path);
+    }
+    path.strip_prefix(directory)?.strip_prefix('/')
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+impl SyncIndex {
+    pub(crate) fn file(&self, path: &str) -> Option<&SyncedFile> {
+        self.files.get(path)
+    }
+
+    pub(crate) fn is_directory(&self, path: &str) -> bool {
+        path.is_empty()
+            || self.directories.contains(path)
+            || self.files.keys().any(|x| below(x, path).is_some())
+            || self.directories.iter().any(|x| below(x, path).is_some())
+    }
+
+    // Whether a file still points to the blob, it stays pinned as long as one does
+    pub(crate) fn references(&self, cid: &str) -> bool {
+        self.files.values().any(|x| x.cid == cid)
+    }
+
+    // Adds or replaces the file at `path`, its parents are created as needed. Returns the file
+    // it replaced.
+    pub(crate) fn insert(
+        &mut self,
+        path: String,
+        file: SyncedFile,
+    ) -> Result<Option<SyncedFile>, Error> {
+        if path.is_empty() || self.is_directory(&path) {
+            return Err(Error::OtherWithContext(format!("{} is a directory", path)));
+        }
+        let mut ancestor = parent(&path);
+        while !ancestor.is_empty() {
+            if self.files.contains_key(ancestor) {
+                return Err(Error::OtherWithContext(format!("{} is a file", ancestor)));
+            }
+            ancestor = parent(ancestor);
+        }
+        Ok(self.files.insert(path, file))
+    }
+
+    pub(crate) fn create_directory(&mut self, path: String, recursive: bool) -> Result<(), Error> {
+        if self.files.contains_key(&path) {
+            return Err(Error::OtherWithContext(format!("{} is a file", path)));
+        }
+        if !recursive && !self.is_directory(parent(&path)) {
+            return Err(Error::OtherWithContext(format!(
+                "{} doesn't exist",
+                parent(&path)
+            )));
+        }
+        if !path.is_empty() {
+            self.directories.insert(path);
+        }
+        Ok(())
+    }
+
+    // Removes a file, or a directory with everything in it when `recursive`. Returns the files
+    // removed.
+    pub(crate) fn remove(&mut self, path: &str, recursive: bool) -> Result<Vec<SyncedFile>, Error> {
+        if let Some(file) = self.files.remove(path) {
+            return Ok(vec![file]);
+        }
+        if path.is_empty() || !self.is_directory(path) {
+            return Err(Error::DataObjectNotFound);
+        }
+        let files: Vec<String> = self
+            .files
+            .keys()
+            .filter(|x| below(x, path).is_some())
+            .cloned()
+            .collect();
+        let empty = files.is_empty() && !self.directories.iter().any(|x| below(x, path).is_some());
+        if !empty && !recursive {
+            return Err(Error::OtherWithContext(format!("{} isn't empty", path)));
+        }
+        self.directories
+            .retain(|x| x != path && below(x, path).is_none());
+        Ok(files.iter().filter_map(|x| self.files.remove(x)).collect())
+    }
+
+    // What's in the directory, with paths relative to it.
+    pub(crate) fn subtree(&self, path: &str) -> SyncIndex {
+        SyncIndex {
+            files: self
+                .files
+                .iter()
+                .filter_map(|(x, file)| Some((below(x, path)?.to_string(), file.clone())))
+                .collect(),
+            directories: self
+                .directories
+                .iter()
+                .filter_map(|x| Some(below(x, path)?.to_string()))
+                .collect(),
+        }
+    }
+
+    // Puts a shared index in the directory at `at`, returns the files it replaced.
+    pub(crate) fn mount(&mut self, at: &str, index: SyncIndex) -> Result<Vec<SyncedFile>, Error> {
+        let mut replaced = Vec::new();
+        for (path, file) in index.files {
+            // Paths of a remote index are only as good as the peer sending them
+            let path = join(at, &normalize(&path)?);
+            replaced.extend(self.insert(path, file)?);
+        }
+        for path in index.directories {
+            let path = join(at, &normalize(&path)?);
+            self.create_directory(path, true)?;
+        }
+        Ok(replaced)
+    }
+
+    // The tree warp applications browse, named `name` at the top.
+    pub(crate) fn directory(&self, name: &str) -> Result<Directory, Error> {
+        self.build(name, "")
+    }
+
+    fn build(&self, name: &str, path: &str) -> Result<Directory, Error> {
+        // Names directly in the directory, with the file when it's one
+        let mut children: BTreeMap<&str, Option<&SyncedFile>> = BTreeMap::new();
+        for (x, file) in &self.files {
+            if let Some(rest) = below(x, path) {
+                match rest.split_once('/') {
+                    Some((directory, _)) => {
+                        children.entry(directory).or_insert(None);
+                    }
+                    None => {
+                        children.insert(rest, Some(file));
+                    }
+                }
+            }
+        }
+        for x in &self.directories {
+            if let Some(rest) = below(x, path) {
+                let directory = rest.split_once('/').map(|(x, _)| x).unwrap_or(rest);
+                children.entry(directory).or_insert(None);
+            }
+        }
+        let mut directory = Directory::new(name);
+        for (child, file) in children {
+            match file {
+                Some(file) => {
+                    let mut item = File::new(child);
+                    item.set_size(file.size as usize);
+                    item.set_reference(&file.cid);
+                    directory.add_item(item)?;
+                }
+                None => directory.add_item(self.build(child, &join(path, child))?)?,
+            }
+        }
+        Ok(directory)
+    }
+}
+
+// Sent to the DID a directory is shared with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DirectoryOffer {
+    pub(crate) name: String,
+    // CID of the bincode SyncIndex blob
+    pub(crate) index: String,
+}
+
+struct OfferInbox {
+    // DID that shared it and the offer, oldest first
+    offers: Vec<(String, DirectoryOffer)>,
+    events: EventSink,
+}
+
+impl ExtensionHandler for OfferInbox {
+    fn message_received(&mut self, sender: String, data: Sata) {
+        // Anything else on the namespace isn't ours to make sense of
+        if let Ok(offer) = data.decode::<DirectoryOffer>() {
+            self.events
+                .event_occurred(Event::DirectoryOffered(sender.clone(), offer.index.clone()));
+            self.offers.push((sender, offer));
+        }
+    }
+}
+
+// Warp's file system trait over Conflux, so directories can be synced between peers. Files
+// are blobs addressed by CID, fetched from whichever peer has them the first time they're read.
+// Sharing a directory sends its index to a paired DID, which mounts it where it likes.
+pub struct BlinkConstellation {
+    service: Arc<Mutex<PeerToPeerService>>,
+    conflux: Conflux,
+    events: EventSink,
+    inbox: Arc<RwLock<OfferInbox>>,
+    index: SyncIndex,
+    // Rebuilt from the index on every change
+    root: Directory,
+    modified: DateTime<Utc>,
+    path: PathBuf,
+}
+
+impl BlinkConstellation {
+    pub async fn new(service: Arc<Mutex<PeerToPeerService>>) -> Result<Self, BlinkError> {
+        let (conflux, events) = {
+            let service = service.lock().await;
+            (service.conflux(), service.events())
+        };
+        let inbox = Arc::new(RwLock::new(OfferInbox {
+            offers: Vec::new(),
+            events: events.clone(),
+        }));
+        service
+            .lock()
+            .await
+            .register_extension(SHARE_NAMESPACE, inbox.clone())
+            .await?;
+        let index = SyncIndex::default();
+        Ok(Self {
+            service,
+            conflux,
+            events,
+            inbox,
+            root: index.directory("root").map_err(anyhow::Error::from)?,
+            index,
+            modified: Utc::now(),
+            path: PathBuf::new(),
+        })
+    }
+
+    // Directories shared with us, by the DID that shared them, oldest first
+    pub fn offers(&self) -> Vec<(String, String, String)> {
+        self.inbox
+            .read()
+            .offers
+            .iter()
+            .map(|(did, offer)| (did.clone(), offer.name.clone(), offer.index.clone()))
+            .collect()
+    }
+
+    // Sends the directory's index to the DID, returns its CID
+    pub async fn share(&mut self, name: &str, did: &DID) -> Result<String, Error> {
+        let path = self.resolve(name)?;
+        if !self.index.is_directory(&path) {
+            return Err(Error::DataObjectNotFound);
+        }
+        let index = bincode::serialize(&self.index.subtree(&path))
+            .map_err(|e| Error::OtherWithContext(e.to_string()))?;
+        let index = self
+            .conflux
+            .add_blob(&index)
+            .await
+            .map_err(BlinkError::from)?;
+        self.conflux
+            .pin_blob(&index)
+            .await
+            .map_err(BlinkError::from)?;
+        let offer = DirectoryOffer {
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            index: index.clone(),
+        };
+        let mut sata = Sata::default();
+        sata.add_recipient(did.as_ref())
+            .map_err(|e| Error::OtherWithContext(format!("{:?}", e)))?;
+        let sata = sata
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, &offer)
+            .map_err(|e| Error::OtherWithContext(format!("{:?}", e)))?;
+        self.service
+            .lock()
+            .await
+            .send_to_extension(SHARE_NAMESPACE, sata)
+            .await?;
+        Ok(index)
+    }
+
+    // Mounts a shared directory at `at`, its files are fetched when they're read or synced
+    pub async fn accept_offer(&mut self, index: &str, at: &str) -> Result<(), Error> {
+        let at = self.resolve(at)?;
+        let data = self
+            .conflux
+            .get_blob(index)
+            .await
+            .map_err(BlinkError::from)?;
+        let shared: SyncIndex =
+            bounded_bincode(&data).map_err(|e| Error::OtherWithContext(e.to_string()))?;
+        let replaced = self.index.mount(&at, shared)?;
+        self.release(replaced).await;
+        self.inbox.write().offers.retain(|(_, x)| x.index != index);
+        self.changed()
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, Error> {
+        normalize(&self.path.join(name).to_string_lossy())
+    }
+
+    fn changed(&mut self) -> Result<(), Error> {
+        self.root = self.index.directory("root")?;
+        self.modified = Utc::now();
+        Ok(())
+    }
+
+    // Unpins the blobs no file points to any more
+    async fn release(&self, files: Vec<SyncedFile>) {
+        for file in files {
+            if !self.index.references(&file.cid) {
+                let _ = self.conflux.unpin_blob(&file.cid).await;
+            }
+        }
+    }
+
+    async fn fetch(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let file = self.index.file(path).ok_or(Error::DataObjectNotFound)?;
+        let (events, name) = (self.events.clone(), path.to_string());
+        let data = self
+            .conflux
+            .get_blob_with_progress(&file.cid, move |bytes, size| {
+                events.event_occurred(Event::FileSyncProgress(name.clone(), bytes, size))
+            })
+            .await
+            .map_err(BlinkError::from)?;
+        // Whatever was read once stays around for the next time
+        self.conflux
+            .pin_blob(&file.cid)
+            .await
+            .map_err(BlinkError::from)?;
+        self.events
+            .event_occurred(Event::FileSynced(path.to_string(), file.cid.clone()));
+        Ok(data)
+    }
+}
+
+impl Extension for BlinkConstellation {
+    fn id(&self) -> String {
+        "blink-constellation".into()
+    }
+
+    fn name(&self) -> String {
+        "Blink file sync".into()
+    }
+
+    fn module(&self) -> Module {
+        Module::FileSystem
+    }
+}
+
+impl SingleHandle for BlinkConstellation {}
+
+#[async_trait]
+impl Constellation for BlinkConstellation {
+    fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+
+    fn root_directory(&self) -> &Directory {
+        &self.root
+    }
+
+    // Changes made through it are lost on the next change through the adapter
+    fn root_directory_mut(&mut self) -> &mut Directory {
+        &mut self.root
+    }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    fn get_path_mut(&mut self) -> &mut PathBuf {
+        &mut self.path
+    }
+
+    async fn put(&mut self, name: &str, path: &str) -> Result<(), Error> {
+        let data = tokio::fs::read(path).await.map_err(BlinkError::from)?;
+        self.put_buffer(name, &data).await
+    }
+
+    async fn get(&self, name: &str, path: &str) -> Result<(), Error> {
+        let data = self.fetch(&self.resolve(name)?).await?;
+        tokio::fs::write(path, data)
+            .await
+            .map_err(BlinkError::from)?;
+        Ok(())
+    }
+
+    async fn put_buffer(&mut self, name: &str, buffer: &Vec<u8>) -> Result<(), Error> {
+        let path = self.resolve(name)?;
+        let cid = self
+            .conflux
+            .add_blob(buffer)
+            .await
+            .map_err(BlinkError::from)?;
+        self.conflux
+            .pin_blob(&cid)
+            .await
+            .map_err(BlinkError::from)?;
+        let file = SyncedFile {
+            cid,
+            size: buffer.len() as u64,
+        };
+        let replaced = self.index.insert(path, file)?;
+        self.release(replaced.into_iter().collect()).await;
+        self.changed()
+    }
+
+    async fn get_buffer(&self, name: &str) -> Result<Vec<u8>, Error> {
+        self.fetch(&self.resolve(name)?).await
+    }
+
+    async fn remove(&mut self, name: &str, recursive: bool) -> Result<(), Error> {
+        let path = self.resolve(name)?;
+        let removed = self.index.remove(&path, recursive)?;
+        self.release(removed).await;
+        self.changed()
+    }
+
+    async fn create_directory(&mut self, name: &str, recursive: bool) -> Result<(), Error> {
+        let path = self.resolve(name)?;
+        self.index.create_directory(path, recursive)?;
+        self.changed()
+    }
+
+    // Fetches the file so it's there to read offline
+    async fn sync_ref(&mut self, name: &str) -> Result<(), Error> {
+        self.fetch(&self.resolve(name)?).await.map(|_| ())
+    }
+}
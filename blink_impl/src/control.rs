@@ -0,0 +1,250 @@
+use crate::peer_to_peer_service::PeerToPeerService;
+use anyhow::Result;
+use blink_contract::Event;
+use libp2p::Multiaddr;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, UnixListener},
+    sync::Mutex,
+    time::interval,
+};
+use warp::crypto::DID;
+
+// How often subscribed connections are sent the events that happened since the last check
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+// Where the control server listens.
+#[derive(Debug, Clone)]
+pub enum ControlEndpoint {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct PairParams {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct SendParams {
+    recipients: Vec<String>,
+    text: String,
+}
+
+#[derive(Deserialize, Default)]
+struct SubscribeParams {
+    // Last event number already seen, the latest one if left out
+    since: Option<u64>,
+}
+
+struct RpcError(i64, String);
+
+// Serves JSON-RPC 2.0 over newline delimited JSON, so non-Rust frontends can drive the service.
+// Methods: `pair {address}`, `send {recipients, text}`, `subscribe {since?}`, `peers` and `topics`.
+// Once subscribed, a connection gets an `event` notification `{seq, name, detail}` for each event.
+// Anyone who can reach the endpoint controls the node, keep it on a Unix socket or loopback.
+pub async fn serve_control(
+    service: Arc<Mutex<PeerToPeerService>>,
+    endpoint: ControlEndpoint,
+) -> Result<()> {
+    match endpoint {
+        ControlEndpoint::Tcp(address) => {
+            let listener = TcpListener::bind(address).await?;
+            loop {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(handle_connection(service.clone(), stream));
+            }
+        }
+        ControlEndpoint::Unix(path) => {
+            let listener = UnixListener::bind(path)?;
+            loop {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(handle_connection(service.clone(), stream));
+            }
+        }
+    }
+}
+
+pub(crate) async fn handle_connection(
+    service: Arc<Mutex<PeerToPeerService>>,
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscribed_after: Option<u64> = None;
+    let mut poll = interval(EVENT_POLL_INTERVAL);
+    loop {
+        let outgoing = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => vec![respond(&service, &mut subscribed_after, &line).await],
+                _ => return,
+            },
+            _ = poll.tick() => match subscribed_after {
+                Some(seq) => {
+                    let events = service.lock().await.events_since(seq);
+                    if let Some((last, _)) = events.last() {
+                        subscribed_after = Some(*last);
+                    }
+                    events.into_iter().map(|(seq, event)| notification(seq, &event)).collect()
+                }
+                None => continue,
+            },
+        };
+        for message in outgoing {
+            let mut line = message.to_string();
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn respond(
+    service: &Mutex<PeerToPeerService>,
+    subscribed_after: &mut Option<u64>,
+    line: &str,
+) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, RpcError(PARSE_ERROR, e.to_string())),
+    };
+    let id = request.id.clone().unwrap_or(Value::Null);
+    match call(service, subscribed_after, request).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => error_response(id, error),
+    }
+}
+
+async fn call(
+    service: &Mutex<PeerToPeerService>,
+    subscribed_after: &mut Option<u64>,
+    request: Request,
+) -> Result<Value, RpcError> {
+    match request.method.as_str() {
+        "pair" => {
+            let params: PairParams = params(request.params)?;
+            let address: Multiaddr = params
+                .address
+                .parse()
+                .map_err(|_| RpcError(INVALID_PARAMS, "Invalid address".into()))?;
+            service
+                .lock()
+                .await
+                .pair_to_another_peer(address.into())
+                .await
+                .map_err(server_error)?;
+            Ok(Value::Null)
+        }
+        "send" => {
+            let params: SendParams = params(request.params)?;
+            let mut sata = Sata::default();
+            for recipient in params.recipients {
+                let did = DID::try_from(recipient)
+                    .map_err(|e| RpcError(INVALID_PARAMS, e.enum_to_string()))?;
+                sata.add_recipient(did.as_ref())
+                    .map_err(|e| server_error(anyhow::anyhow!(e)))?;
+            }
+            let sata = sata
+                .encode(IpldCodec::DagJson, Kind::Dynamic, &params.text)
+                .map_err(|e| server_error(anyhow::anyhow!(e)))?;
+            let report = service
+                .lock()
+                .await
+                .send(sata)
+                .await
+                .map_err(server_error)?;
+            // Null for the recipients it went out to, why it didn't for the others
+            let report: HashMap<_, _> = report
+                .into_iter()
+                .map(|(did, result)| (did, result.err().map(|e| e.to_string())))
+                .collect();
+            Ok(json!(report))
+        }
+        "subscribe" => {
+            let params: SubscribeParams = if request.params.is_null() {
+                SubscribeParams::default()
+            } else {
+                params(request.params)?
+            };
+            let latest = service
+                .lock()
+                .await
+                .recent_events()
+                .last()
+                .map_or(0, |(seq, _)| *seq);
+            let since = params.since.unwrap_or(latest);
+            *subscribed_after = Some(since);
+            Ok(json!({ "since": since }))
+        }
+        "peers" => {
+            let paired = service.lock().await.paired();
+            Ok(json!(paired.keys().collect::<Vec<_>>()))
+        }
+        "topics" => {
+            let service = service.lock().await;
+            let topics: Vec<Value> = service
+                .paired()
+                .into_iter()
+                .map(|(did, topic)| {
+                    let subscribers = service.topic_peers(&topic).len();
+                    json!({ "topic": topic, "did": did, "subscribers": subscribers })
+                })
+                .collect();
+            Ok(Value::Array(topics))
+        }
+        method => Err(RpcError(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {}", method),
+        )),
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError(INVALID_PARAMS, e.to_string()))
+}
+
+fn server_error(error: impl std::fmt::Display) -> RpcError {
+    RpcError(SERVER_ERROR, error.to_string())
+}
+
+fn error_response(id: Value, RpcError(code, message): RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Events aren't serializable, they go out as their variant name and debug output
+fn notification(seq: u64, event: &Event) -> Value {
+    let detail = format!("{:?}", event);
+    let name = detail
+        .split(|x: char| !x.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    json!({
+        "jsonrpc": "2.0",
+        "method": "event",
+        "params": { "seq": seq, "name": name, "detail": detail },
+    })
+}
@@ -0,0 +1,153 @@
+use crate::fragments;
+use sata::Sata;
+use std::collections::{HashSet, VecDeque};
+use std::ops::RangeBounds;
+
+// Oldest messages are forgotten past this, the PocketDimension cache keeps everything
+const MAX_MESSAGES: usize = 10_000;
+
+// Message sent or received on a pairwise topic, with what history queries need to know about it.
+#[derive(Debug, Clone)]
+pub struct StoredMessage {
+    // CID of the encoded Sata, the same message delivered twice keeps its id
+    pub id: String,
+    pub sender: String,
+    pub topic: String,
+    // Milliseconds since the unix epoch, when it was sent or received
+    pub timestamp: u64,
+    pub sata: Sata,
+    // When its sender last edited it, milliseconds since the unix epoch
+    pub edited_at: Option<u64>,
+    // Its sender deleted it, the payload is left empty
+    pub deleted: bool,
+}
+
+impl StoredMessage {
+    pub(crate) fn new(sender: String, topic: String, timestamp: u64, sata: Sata) -> Self {
+        Self {
+            id: message_id(&sata),
+            sender,
+            topic,
+            timestamp,
+            sata,
+            edited_at: None,
+            deleted: false,
+        }
+    }
+}
+
+// Same on both ends, what ties a sent message to its delivery
+pub(crate) fn message_id(sata: &Sata) -> String {
+    fragments::cid_of(&bincode::serialize(sata).unwrap_or_default())
+}
+
+// Recent conversations, indexed by topic for paging through a chat history.
+#[derive(Default)]
+pub(crate) struct ConversationStore {
+    // Ordered by timestamp
+    messages: VecDeque<StoredMessage>,
+    seen: HashSet<(String, String)>,
+}
+
+impl ConversationStore {
+    // Returns false for a message already recorded on the same topic, mailbox replays for instance.
+    pub(crate) fn record(&mut self, message: StoredMessage) -> bool {
+        if !self
+            .seen
+            .insert((message.topic.clone(), message.id.clone()))
+        {
+            return false;
+        }
+        let position = self
+            .messages
+            .partition_point(|x| x.timestamp <= message.timestamp);
+        self.messages.insert(position, message);
+        if self.messages.len() > MAX_MESSAGES {
+            if let Some(oldest) = self.messages.pop_front() {
+                self.seen.remove(&(oldest.topic, oldest.id));
+            }
+        }
+        true
+    }
+
+    // Forgets the message on every topic it was recorded on, true if there was any.
+    pub(crate) fn remove(&mut self, id: &str) -> bool {
+        let before = self.messages.len();
+        self.messages.retain(|x| x.id != id);
+        self.seen.retain(|(_, x)| x != id);
+        self.messages.len() != before
+    }
+
+    // Topics the message from `sender` was recorded on.
+    pub(crate) fn topics_of(&self, id: &str, sender: &str) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter(|x| x.id == id && x.sender == sender)
+            .map(|x| x.topic.clone())
+            .collect()
+    }
+
+    // Replaces the payload of the message `sender` sent on the topic, returning the one it had.
+    // Deleted messages stay deleted.
+    pub(crate) fn edit(
+        &mut self,
+        topic: &str,
+        id: &str,
+        sender: &str,
+        sata: Sata,
+        now: u64,
+    ) -> Option<Sata> {
+        let message = self.sent_by(topic, id, sender)?;
+        message.edited_at = Some(now);
+        Some(std::mem::replace(&mut message.sata, sata))
+    }
+
+    // Leaves a tombstone of the message `sender` sent on the topic, returning its payload.
+    pub(crate) fn delete(&mut self, topic: &str, id: &str, sender: &str) -> Option<Sata> {
+        let message = self.sent_by(topic, id, sender)?;
+        message.deleted = true;
+        Some(std::mem::take(&mut message.sata))
+    }
+
+    fn sent_by(&mut self, topic: &str, id: &str, sender: &str) -> Option<&mut StoredMessage> {
+        self.messages
+            .iter_mut()
+            .find(|x| x.id == id && x.topic == topic && x.sender == sender && !x.deleted)
+    }
+
+    // The latest `limit` messages of the conversation within the time range, oldest first.
+    // Passing `..oldest.timestamp` as the range gets the page before.
+    pub(crate) fn history(
+        &self,
+        topic: &str,
+        range: impl RangeBounds<u64>,
+        limit: usize,
+    ) -> Vec<StoredMessage> {
+        let mut page: Vec<StoredMessage> = self
+            .messages
+            .iter()
+            .rev()
+            .filter(|x| x.topic == topic && range.contains(&x.timestamp))
+            .take(limit)
+            .cloned()
+            .collect();
+        page.reverse();
+        page
+    }
+
+    // Messages whose text contains `text`, ignoring case, newest first.
+    // Payloads that don't decode to a string are skipped.
+    pub(crate) fn search(&self, text: &str) -> Vec<StoredMessage> {
+        let text = text.to_lowercase();
+        self.messages
+            .iter()
+            .rev()
+            .filter(|x| {
+                x.sata
+                    .decode::<String>()
+                    .map_or(false, |x| x.to_lowercase().contains(&text))
+            })
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,68 @@
+use crate::protocol::{BincodeCodec, BlinkProtocol};
+use libp2p::request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::iter;
+
+const DIRECT_PROTOCOL: &[u8] = b"/blink/direct/1.0.0";
+
+pub(crate) type DirectBehaviour = RequestResponse<BincodeCodec<DirectMessage, DirectAck>>;
+
+// A sealed envelope sent straight to a subscriber of its topic, as gossipsub would carry it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DirectMessage {
+    pub(crate) topic: String,
+    #[serde(with = "serde_bytes")]
+    pub(crate) data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DirectAck;
+
+pub(crate) fn new_behaviour() -> DirectBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(DIRECT_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStrategy {
+    // Sent straight to every subscriber we're connected to, nobody passes it on
+    Flood,
+    // Through the gossipsub mesh, subscribers pass it on to each other
+    Mesh,
+}
+
+// How published messages reach a topic's subscribers. The mesh takes a heartbeat to graft a
+// peer that just subscribed and only pays off once there are peers to pass messages on, so
+// topics with a single remote subscriber, like the pairwise ones, are flooded by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliverySettings {
+    // Topics with at most this many remote subscribers are flooded, 0 sends everything through the mesh
+    pub flood_up_to: usize,
+    // Strategy of the topics named here whatever their size, e.g. a group call or a channel topic
+    pub topics: HashMap<String, DeliveryStrategy>,
+}
+
+impl Default for DeliverySettings {
+    fn default() -> Self {
+        Self {
+            flood_up_to: 1,
+            topics: HashMap::new(),
+        }
+    }
+}
+
+impl DeliverySettings {
+    pub fn strategy(&self, topic: &str, subscribers: usize) -> DeliveryStrategy {
+        match self.topics.get(topic) {
+            Some(strategy) => *strategy,
+            None if subscribers <= self.flood_up_to => DeliveryStrategy::Flood,
+            None => DeliveryStrategy::Mesh,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::did_to_libp2p_pub;
+use crate::wire::bounded_bincode;
+use anyhow::{anyhow, bail, Result};
+use blink_contract::Keystore;
+use libp2p::identity::{ed25519, Keypair, PublicKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use warp::crypto::DID;
+
+// Carried in identify's agent version, other agents don't start with it
+const AGENT_PREFIX: &str = "blink-device/";
+// Keeps the signature from passing for any other payload the DID signs
+const DOMAIN: &[u8] = b"/blink/device-key/";
+
+// This device's transport key, read from `path` when given. The file is created with a new
+// key the first time, without a path every start gets a new one.
+pub(crate) fn load_or_generate(path: Option<&Path>) -> Result<Keypair> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(Keypair::generate_ed25519()),
+    };
+    match fs::read(path) {
+        Ok(mut bytes) => {
+            let key_pair = ed25519::Keypair::decode(&mut bytes)
+                .map_err(|e| anyhow!("Invalid device key {}: {}", path.display(), e))?;
+            Ok(Keypair::Ed25519(key_pair))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let key_pair = ed25519::Keypair::generate();
+            // Written next to the target then renamed, a crash never leaves half a key behind
+            let mut temporary = path.to_path_buf().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, key_pair.encode())?;
+            fs::rename(&temporary, path)?;
+            Ok(Keypair::Ed25519(key_pair))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// A device's transport key signed with the DID key, so peers can tell which identity a
+// connection belongs to without the DID key ever authenticating the transport.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DeviceCertificate {
+    pub(crate) did: String,
+    peer_id: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl DeviceCertificate {
+    pub(crate) fn new(keystore: &dyn Keystore, transport_key: &PublicKey) -> Result<Self> {
+        let mut certificate = Self {
+            did: keystore.public_key()?.to_string(),
+            peer_id: transport_key.to_peer_id().to_bytes(),
+            signature: Vec::new(),
+        };
+        certificate.signature = keystore.sign(&certificate.signed_bytes())?;
+        Ok(certificate)
+    }
+
+    // As sent in identify's agent version.
+    pub(crate) fn to_agent_version(&self) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            AGENT_PREFIX,
+            base64::encode(bincode::serialize(self)?)
+        ))
+    }
+
+    pub(crate) fn from_agent_version(agent_version: &str) -> Result<Self> {
+        let encoded = agent_version
+            .strip_prefix(AGENT_PREFIX)
+            .ok_or_else(|| anyhow!("Agent {} has no device certificate", agent_version))?;
+        Ok(bounded_bincode(&base64::decode(encoded)?)?)
+    }
+
+    // The DID the peer's transport key belongs to, an error unless the certificate is for
+    // that key and signed by the DID.
+    pub(crate) fn verify(&self, peer_id: &PeerId) -> Result<DID> {
+        if PeerId::from_bytes(&self.peer_id)? != *peer_id {
+            bail!("Certificate of {} is for another key", self.did);
+        }
+        let did = DID::try_from(self.did.clone())?;
+        if !did_to_libp2p_pub(&did)?.verify(&self.signed_bytes(), &self.signature) {
+            bail!("Certificate of {} isn't signed by it", self.did);
+        }
+        Ok(did)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = DOMAIN.to_vec();
+        bytes.extend_from_slice(self.did.as_bytes());
+        bytes.extend_from_slice(&self.peer_id);
+        bytes
+    }
+}
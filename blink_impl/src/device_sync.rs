@@ -0,0 +1,339 @@
+use crate::{
+    behavior::BlinkBehavior,
+    conversations,
+    device_key::DeviceCertificate,
+    event_sink::EventSink,
+    moderation::ModerationRecords,
+    peer_to_peer_service::PeerToPeerService,
+    protocol::{self, page_of, BincodeCodec, BlinkProtocol},
+    shared_state::SharedState,
+};
+use anyhow::{bail, Result};
+use blink_contract::{Event, Keystore};
+use hmac_sha512::Hash;
+use libp2p::{
+    gossipsub::IdentTopic,
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    PeerId, Swarm,
+};
+use sata::Sata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::Arc;
+use warp::{data::DataType, pocket_dimension::PocketDimension, sync::RwLock};
+
+const DEVICE_SYNC_PROTOCOL: &[u8] = b"/blink/device-sync/2.1.0";
+
+pub(crate) type DeviceSyncBehaviour =
+    RequestResponse<BincodeCodec<DeviceSyncRequest, DeviceSnapshot>>;
+
+// Asks another device of the same DID for a page of what it knows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DeviceSyncRequest {
+    certificate: DeviceCertificate,
+    // Messages received so far, the page starts after them
+    pub(crate) offset: usize,
+    // Set on the first request of a sync, the other device then pulls from us as well
+    pub(crate) pull_back: bool,
+}
+
+// A page of everything a device knows about its conversations.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DeviceSnapshot {
+    certificate: DeviceCertificate,
+    // Only the first page carries topics and moderation records
+    topics: HashMap<String, String>,
+    // Each message with when it expires if it's a disappearing one
+    pub(crate) messages: Vec<(Sata, Option<u64>)>,
+    moderation: ModerationRecords,
+    // Offset of the next page, None on the last one
+    pub(crate) next: Option<usize>,
+}
+
+pub(crate) fn new_behaviour() -> DeviceSyncBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(DEVICE_SYNC_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+impl DeviceSyncRequest {
+    pub(crate) fn new(certificate: DeviceCertificate, offset: usize, pull_back: bool) -> Self {
+        Self {
+            certificate,
+            offset,
+            pull_back,
+        }
+    }
+
+    pub(crate) fn verify_sender(&self, keystore: &dyn Keystore, sender: &PeerId) -> Result<()> {
+        verify_sender(&self.certificate, keystore, sender)
+    }
+}
+
+// The page of `messages` starting at `offset`, as many as fit in `max_bytes` but at least one.
+// Messages cached between two pages come after the last one, the merge drops any sent twice.
+pub(crate) fn page(
+    certificate: DeviceCertificate,
+    messages: Vec<(Sata, Option<u64>)>,
+    offset: usize,
+    max_bytes: u64,
+    topics: HashMap<String, String>,
+    moderation: ModerationRecords,
+) -> DeviceSnapshot {
+    let total = messages.len();
+    let page = page_of(messages.into_iter().skip(offset), max_bytes);
+    let end = offset + page.len();
+    let first = offset == 0;
+    DeviceSnapshot {
+        certificate,
+        topics: if first { topics } else { HashMap::new() },
+        messages: page,
+        moderation: if first {
+            moderation
+        } else {
+            ModerationRecords::default()
+        },
+        next: if end < total { Some(end) } else { None },
+    }
+}
+
+impl DeviceSnapshot {
+    pub(crate) fn verify_sender(&self, keystore: &dyn Keystore, sender: &PeerId) -> Result<()> {
+        verify_sender(&self.certificate, keystore, sender)
+    }
+
+    pub(crate) fn take_moderation(&mut self) -> ModerationRecords {
+        std::mem::take(&mut self.moderation)
+    }
+
+    // Adds the messages we don't hold yet to the cache, deduplicated by content hash.
+    // Returns the DID to topic entries we didn't know about and how many messages were added.
+    pub(crate) fn merge_into(
+        self,
+        cache: &mut dyn PocketDimension,
+        topics: &mut HashMap<String, String>,
+    ) -> Result<(Vec<String>, usize)> {
+        let known: HashSet<Vec<u8>> = cache
+            .get_data(DataType::Messaging, None)?
+            .iter()
+            .filter_map(content_hash)
+            .collect();
+
+        let mut added = 0;
+        for (message, _) in self.messages {
+            if let Some(hash) = content_hash(&message) {
+                if !known.contains(&hash) {
+                    cache.add_data(DataType::Messaging, &message)?;
+                    added += 1;
+                }
+            }
+        }
+
+        let mut new_topics = Vec::new();
+        for (did, topic) in self.topics {
+            if !topics.contains_key(&did) {
+                new_topics.push(topic.clone());
+                topics.insert(did, topic);
+            }
+        }
+
+        Ok((new_topics, added))
+    }
+}
+
+// The sender's transport key must be certified by our own DID, each device has a key of its own
+fn verify_sender(
+    certificate: &DeviceCertificate,
+    keystore: &dyn Keystore,
+    sender: &PeerId,
+) -> Result<()> {
+    let did = certificate.verify(sender)?;
+    if did != keystore.public_key()? {
+        bail!("Device {} belongs to {}", sender, did);
+    }
+    Ok(())
+}
+
+fn content_hash(sata: &Sata) -> Option<Vec<u8>> {
+    bincode::serialize(sata)
+        .ok()
+        .map(|bytes| Hash::hash(bytes).to_vec())
+}
+
+// Serves our pages to the other devices of our DID and merges the pages they send back.
+pub(crate) fn handle_event(
+    swarm: &mut Swarm<BlinkBehavior>,
+    cache: Arc<RwLock<dyn PocketDimension>>,
+    logger: EventSink,
+    state: &SharedState,
+    event: RequestResponseEvent<DeviceSyncRequest, DeviceSnapshot>,
+) {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => {
+                if let Err(e) = request.verify_sender(&*state.keystore, &peer) {
+                    logger.event_occurred(Event::DeviceSyncError(e.to_string()));
+                    return;
+                }
+                // The device that started the sync gets our data the same way, a page at a time
+                if request.pull_back {
+                    request_page(swarm, state, peer, 0, false);
+                }
+                match local_page(&cache, state, request.offset) {
+                    Ok(response) => {
+                        state.count_sent(&peer, None, &response);
+                        if swarm
+                            .behaviour_mut()
+                            .device_sync
+                            .send_response(channel, response)
+                            .is_err()
+                        {
+                            logger.event_occurred(Event::DeviceSyncError(
+                                "Connection closed before responding".into(),
+                            ));
+                        }
+                    }
+                    Err(e) => {
+                        logger.event_occurred(Event::DeviceSyncError(e.to_string()));
+                    }
+                }
+            }
+            RequestResponseMessage::Response { response, .. } => {
+                if let Err(e) = response.verify_sender(&*state.keystore, &peer) {
+                    state.messaging.device_syncs.write().remove(&peer);
+                    logger.event_occurred(Event::DeviceSyncError(e.to_string()));
+                    return;
+                }
+                let next = response.next;
+                let added = merge_snapshot(swarm, cache, logger.clone(), state, response);
+                let total = {
+                    let mut syncs = state.messaging.device_syncs.write();
+                    let total = syncs.entry(peer).or_default();
+                    *total += added;
+                    *total
+                };
+                match next {
+                    Some(offset) => request_page(swarm, state, peer, offset, false),
+                    None => {
+                        state.messaging.device_syncs.write().remove(&peer);
+                        logger.event_occurred(Event::DeviceSynced(total));
+                    }
+                }
+            }
+        },
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            state.messaging.device_syncs.write().remove(&peer);
+            logger.event_occurred(Event::DeviceSyncError(error.to_string()));
+        }
+        RequestResponseEvent::InboundFailure { error, .. } => {
+            logger.event_occurred(Event::DeviceSyncError(error.to_string()));
+        }
+        RequestResponseEvent::ResponseSent { .. } => {}
+    }
+}
+
+pub(crate) fn request_page(
+    swarm: &mut Swarm<BlinkBehavior>,
+    state: &SharedState,
+    peer: PeerId,
+    offset: usize,
+    pull_back: bool,
+) {
+    let request = DeviceSyncRequest::new(state.certificate.clone(), offset, pull_back);
+    state.count_sent(&peer, None, &request);
+    swarm
+        .behaviour_mut()
+        .device_sync
+        .send_request(&peer, request);
+}
+
+fn local_page(
+    cache: &Arc<RwLock<dyn PocketDimension>>,
+    state: &SharedState,
+    offset: usize,
+) -> Result<DeviceSnapshot> {
+    // Messages past their expiry but not swept yet stay out of the backfill
+    let now = state.clock.now_millis();
+    let expiry = state.messaging.expiry.read();
+    let messages = cache
+        .read()
+        .get_data(DataType::Messaging, None)?
+        .into_iter()
+        .filter_map(|x| match expiry.expires_at(&x) {
+            Some(expires_at) if expires_at <= now => None,
+            expires_at => Some((x, expires_at)),
+        })
+        .collect();
+    Ok(page(
+        state.certificate.clone(),
+        messages,
+        offset,
+        protocol::PAGE_BYTES,
+        state.peers.map_peer_topic.read().clone(),
+        state.messaging.moderation.read().records(),
+    ))
+}
+
+// Merges one page of a device sync, returns how many messages were added
+fn merge_snapshot(
+    swarm: &mut Swarm<BlinkBehavior>,
+    cache: Arc<RwLock<dyn PocketDimension>>,
+    logger: EventSink,
+    state: &SharedState,
+    mut snapshot: DeviceSnapshot,
+) -> usize {
+    let moderation = snapshot.take_moderation();
+    if let Err(e) = state.messaging.moderation.write().merge(moderation) {
+        logger.event_occurred(Event::DeviceSyncError(e.to_string()));
+    }
+    // Disappearing messages keep their expiry on this device, the ones already gone aren't kept
+    let now = state.clock.now_millis();
+    snapshot
+        .messages
+        .retain(|(_, expires_at)| expires_at.map_or(true, |x| x > now));
+    for (sata, expires_at) in &snapshot.messages {
+        if let Some(expires_at) = expires_at {
+            PeerToPeerService::schedule_expiry(state, conversations::message_id(sata), *expires_at);
+        }
+    }
+    let result = snapshot.merge_into(
+        &mut *cache.write(),
+        &mut *state.peers.map_peer_topic.write(),
+    );
+    match result {
+        Ok((new_topics, added)) => {
+            for topic in &new_topics {
+                if let Err(err) = swarm
+                    .behaviour_mut()
+                    .gossip_sub
+                    .subscribe(&IdentTopic::new(topic))
+                {
+                    logger.event_occurred(Event::SubscriptionError(err.to_string()));
+                }
+            }
+            for topic in new_topics {
+                let did = state.did_of_topic(&topic).unwrap_or_default();
+                let namespaces = state.conversation_namespaces(&did);
+                PeerToPeerService::subscribe_extension_topics(
+                    swarm,
+                    logger.clone(),
+                    &[topic],
+                    &namespaces,
+                );
+            }
+            added
+        }
+        Err(e) => {
+            logger.event_occurred(Event::DeviceSyncError(e.to_string()));
+            0
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use blink_contract::BlinkError;
+use libp2p::kad::{record::Key, GetRecordError, QueryId, QueryResult};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::oneshot;
+
+pub(crate) type DhtWaiter = oneshot::Sender<Result<DhtAnswer, BlinkError>>;
+
+// A Kademlia query started for the application, answered once it completes.
+#[derive(Debug)]
+pub(crate) enum DhtQuery {
+    FindPeer(PeerId),
+    GetRecord(Vec<u8>),
+    PutRecord(Vec<u8>, Vec<u8>),
+    GetProviders(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub(crate) enum DhtAnswer {
+    Addresses(Vec<Multiaddr>),
+    Records(Vec<Vec<u8>>),
+    Stored,
+    Providers(HashSet<PeerId>),
+}
+
+// Queries the application is waiting on. Their results don't go through the handling of
+// the queries Blink runs itself.
+#[derive(Default)]
+pub(crate) struct DhtQueries {
+    running: HashMap<QueryId, DhtWaiter>,
+}
+
+impl DhtQueries {
+    pub(crate) fn started(&mut self, id: QueryId, waiter: DhtWaiter) {
+        self.running.insert(id, waiter);
+    }
+
+    pub(crate) fn waits_for(&self, id: &QueryId) -> bool {
+        self.running.contains_key(id)
+    }
+
+    // The waiter of a completed query, the query is forgotten.
+    pub(crate) fn completed(&mut self, id: &QueryId) -> Option<DhtWaiter> {
+        self.running.remove(id)
+    }
+}
+
+// The answer to a completed query. `addresses` are the ones Kademlia knows for a peer, what
+// FindPeer answers with once the closest peers were asked.
+pub(crate) fn answer(
+    result: QueryResult,
+    addresses: impl FnOnce(&PeerId) -> Vec<Multiaddr>,
+) -> Result<DhtAnswer, BlinkError> {
+    match result {
+        QueryResult::GetClosestPeers(Ok(ok)) => {
+            let peer = PeerId::from_bytes(&ok.key)
+                .map_err(|e| BlinkError::Invalid(format!("Not a PeerId: {}", e)))?;
+            let addresses = addresses(&peer);
+            if addresses.is_empty() {
+                return Err(BlinkError::NotFound(format!("Peer {}", peer)));
+            }
+            Ok(DhtAnswer::Addresses(addresses))
+        }
+        QueryResult::GetRecord(Ok(ok)) => Ok(DhtAnswer::Records(
+            ok.records.into_iter().map(|x| x.record.value).collect(),
+        )),
+        QueryResult::GetRecord(Err(GetRecordError::NotFound { key, .. })) => {
+            Err(BlinkError::NotFound(format!("Record {}", printable(&key))))
+        }
+        QueryResult::PutRecord(Ok(_)) => Ok(DhtAnswer::Stored),
+        QueryResult::GetProviders(Ok(ok)) => Ok(DhtAnswer::Providers(ok.providers)),
+        QueryResult::GetClosestPeers(Err(e)) => Err(BlinkError::Other(e.into())),
+        QueryResult::GetRecord(Err(e)) => Err(BlinkError::Other(e.into())),
+        QueryResult::PutRecord(Err(e)) => Err(BlinkError::Other(e.into())),
+        QueryResult::GetProviders(Err(e)) => Err(BlinkError::Other(e.into())),
+        other => Err(BlinkError::Invalid(format!(
+            "Unexpected result {:?}",
+            other
+        ))),
+    }
+}
+
+// Keys are usually text, like the CIDs and DID records Blink stores
+fn printable(key: &Key) -> String {
+    String::from_utf8(key.to_vec()).unwrap_or_else(|_| format!("{:?}", key))
+}
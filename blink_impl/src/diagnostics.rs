@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// Reserved extension namespace the probes ride on
+pub(crate) const BENCH_NAMESPACE: &str = "blink.bench";
+
+pub type BenchmarkId = u64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum BenchMessage {
+    // Sequence number, send time in milliseconds and filler bytes
+    Probe(BenchmarkId, u32, u64, Vec<u8>),
+    // Echo of a probe with the same filler, so the measure covers both directions
+    Echo(BenchmarkId, u32, u64, Vec<u8>),
+}
+
+// How many probes to send and how long to wait for their echoes.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkOptions {
+    pub probes: u32,
+    pub payload_size: usize,
+    pub timeout: Duration,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            probes: 100,
+            payload_size: 1024,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub sent: u32,
+    pub received: u32,
+    // Share of probes that never came back, between 0 and 1
+    pub loss: f64,
+    // Payload bytes echoed per second, both directions counted
+    pub throughput: f64,
+    // Half the median round trip, the clocks of both peers don't need to agree
+    pub one_way_delay_ms: f64,
+    pub min_rtt_ms: u64,
+    pub max_rtt_ms: u64,
+}
+
+struct Run {
+    peer: String,
+    probes: u32,
+    payload_size: usize,
+    started_at: u64,
+    last_echo_at: u64,
+    rtts: HashMap<u32, u64>,
+    done: Option<oneshot::Sender<()>>,
+}
+
+// Benchmarks we run and whether we echo the probes of our peers.
+#[derive(Default)]
+pub(crate) struct BenchmarkRegistry {
+    answering: bool,
+    runs: HashMap<BenchmarkId, Run>,
+}
+
+impl BenchmarkRegistry {
+    pub(crate) fn set_answering(&mut self, answering: bool) {
+        self.answering = answering;
+    }
+
+    pub(crate) fn is_answering(&self) -> bool {
+        self.answering
+    }
+
+    // Starts a run, the receiver completes once every probe came back.
+    pub(crate) fn start(
+        &mut self,
+        id: BenchmarkId,
+        peer: String,
+        options: &BenchmarkOptions,
+        now: u64,
+    ) -> oneshot::Receiver<()> {
+        let (done, receiver) = oneshot::channel();
+        self.runs.insert(
+            id,
+            Run {
+                peer,
+                probes: options.probes,
+                payload_size: options.payload_size,
+                started_at: now,
+                last_echo_at: now,
+                rtts: HashMap::new(),
+                done: Some(done),
+            },
+        );
+        receiver
+    }
+
+    // Records an echo coming from `from`, duplicates and strangers are ignored.
+    pub(crate) fn echoed(
+        &mut self,
+        id: BenchmarkId,
+        from: &str,
+        sequence: u32,
+        sent_at: u64,
+        now: u64,
+    ) {
+        let run = match self.runs.get_mut(&id) {
+            Some(run) if run.peer == from && sequence < run.probes => run,
+            _ => return,
+        };
+        if run.rtts.contains_key(&sequence) {
+            return;
+        }
+        run.rtts.insert(sequence, now.saturating_sub(sent_at));
+        run.last_echo_at = run.last_echo_at.max(now);
+        if run.rtts.len() == run.probes as usize {
+            if let Some(done) = run.done.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self, id: BenchmarkId) -> Option<BenchmarkReport> {
+        let run = self.runs.remove(&id)?;
+        let mut rtts: Vec<u64> = run.rtts.values().copied().collect();
+        rtts.sort_unstable();
+        let received = rtts.len() as u32;
+        let elapsed = run.last_echo_at.saturating_sub(run.started_at).max(1);
+        let bytes = 2 * received as u64 * run.payload_size as u64;
+        Some(BenchmarkReport {
+            sent: run.probes,
+            received,
+            loss: match run.probes {
+                0 => 0.0,
+                probes => 1.0 - received as f64 / probes as f64,
+            },
+            throughput: bytes as f64 * 1000.0 / elapsed as f64,
+            one_way_delay_ms: rtts.get(rtts.len() / 2).map_or(0.0, |x| *x as f64 / 2.0),
+            min_rtt_ms: rtts.first().copied().unwrap_or_default(),
+            max_rtt_ms: rtts.last().copied().unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use crate::did_to_libp2p_pub;
+use crate::wire::bounded_bincode;
+use anyhow::{bail, Result};
+use blink_contract::Keystore;
+use hmac_sha512::Hash;
+use libp2p::kad::record::{Key, Record};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use warp::crypto::DID;
+
+// Keeps DID records apart from the fragment CIDs we provide
+const KEY_PREFIX: &[u8] = b"/blink/did/";
+
+// DHT key a DID's record is stored under, hashed so every key has the same length.
+pub(crate) fn key_of(did: &str) -> Key {
+    let mut key = KEY_PREFIX.to_vec();
+    key.extend_from_slice(&Hash::hash(did.as_bytes()));
+    Key::new(&key)
+}
+
+// Where a DID can be reached, signed with the DID key so the nodes storing it can't
+// point lookups somewhere else.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DidRecord {
+    pub(crate) did: String,
+    peer_id: Vec<u8>,
+    pub(crate) addrs: Vec<Multiaddr>,
+    // Milliseconds since the unix epoch
+    pub(crate) expires_at: u64,
+    signature: Vec<u8>,
+}
+
+impl DidRecord {
+    pub(crate) fn new(
+        keystore: &dyn Keystore,
+        did: String,
+        peer_id: &PeerId,
+        addrs: Vec<Multiaddr>,
+        expires_at: u64,
+    ) -> Result<Self> {
+        let mut record = Self {
+            did,
+            peer_id: peer_id.to_bytes(),
+            addrs,
+            expires_at,
+            signature: Vec::new(),
+        };
+        record.signature = keystore.sign(&record.signed_bytes()?)?;
+        Ok(record)
+    }
+
+    pub(crate) fn peer_id(&self) -> Result<PeerId> {
+        Ok(PeerId::from_bytes(&self.peer_id)?)
+    }
+
+    pub(crate) fn to_record(&self) -> Result<Record> {
+        Ok(Record::new(key_of(&self.did), bincode::serialize(self)?))
+    }
+
+    // Decodes a record found on the DHT, rejecting it unless it's stored under the key of
+    // its own DID, signed by that DID and not expired.
+    pub(crate) fn from_record(record: &Record, now: u64) -> Result<Self> {
+        let this: Self = bounded_bincode(&record.value)?;
+        if record.key != key_of(&this.did) {
+            bail!("Record for {} stored under another key", this.did);
+        }
+        if this.expires_at <= now {
+            bail!("Record for {} expired", this.did);
+        }
+        let public_key = did_to_libp2p_pub(&DID::try_from(this.did.clone())?)?;
+        if !public_key.verify(&this.signed_bytes()?, &this.signature) {
+            bail!("Record for {} isn't signed by it", this.did);
+        }
+        Ok(this)
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&(
+            &self.did,
+            &self.peer_id,
+            &self.addrs,
+            self.expires_at,
+        ))?)
+    }
+}
@@ -0,0 +1,118 @@
+use blink_contract::Event;
+use serde::Serialize;
+
+// What an event is about, for choosing which ones to forward or keep quiet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventCategory {
+    Connection,
+    Messaging,
+    Content,
+    Sync,
+    Streams,
+    Transactions,
+    Extensions,
+}
+
+impl EventCategory {
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::DialSuccessful(_)
+            | Event::DialError(_)
+            | Event::ConvertKeyError
+            | Event::NewListenAddr(_)
+            | Event::ExternalAddressConfirmed(_)
+            | Event::PeerIdentifyUpdated(_)
+            | Event::NetworkingPaused
+            | Event::NetworkingResumed
+            | Event::FailureToIdentifyPeer
+            | Event::PeerIdentified
+            | Event::FailureToDisconnectPeer
+            | Event::PeerConnectionClosed(_)
+            | Event::ConnectionEstablished(_)
+            | Event::TaskCancelled
+            | Event::Dialing(_)
+            | Event::IncomingConnection(_)
+            | Event::IncomingConnectionError(_, _)
+            | Event::OutgoingConnectionError(_, _)
+            | Event::BannedPeer(_)
+            | Event::IncompatiblePeer(_, _, _)
+            | Event::RendezvousRegistered(_)
+            | Event::RendezvousDiscovered(_, _)
+            | Event::RendezvousError(_)
+            | Event::DidRecordPublished
+            | Event::DidResolved(_, _)
+            | Event::DidRecordError(_)
+            | Event::MdnsError(_)
+            | Event::PeerUnresponsive(_)
+            | Event::PeerGraylisted(_, _)
+            | Event::PeerBlacklisted(_, _)
+            | Event::PeerScoreRecovered(_, _)
+            | Event::FriendRequestReceived(_)
+            | Event::FriendRequestAccepted(_)
+            | Event::FriendRequestError(_) => EventCategory::Connection,
+            Event::SubscriptionError(_)
+            | Event::ErrorAddingToCache(_)
+            | Event::ErrorDeserializingData
+            | Event::ErrorSerializingData
+            | Event::ErrorPublishingData(_)
+            | Event::GeneratedTopic(_, _)
+            | Event::SubscribedToTopic(_)
+            | Event::FailedToSendMessage
+            | Event::WriteAheadLogError(_)
+            | Event::MessageQuarantined(_)
+            | Event::PublishDeferred(_)
+            | Event::PeerJoinedTopic(_, _)
+            | Event::PeerLeftTopic(_, _)
+            | Event::RateLimited(_, _)
+            | Event::MessageRejected(_, _)
+            | Event::SendFailed { .. }
+            | Event::UnpairedTopic(_)
+            | Event::MessageMuted(_)
+            | Event::MessageExpired(_)
+            | Event::MessageEdited(_, _)
+            | Event::MessageDeleted(_, _) => EventCategory::Messaging,
+            Event::ErrorProvidingContent(_)
+            | Event::ContentAtRisk(_)
+            | Event::IncomingFile(_, _, _, _)
+            | Event::TransferProgress(_, _, _)
+            | Event::TransferCompleted(_)
+            | Event::TransferFailed(_, _)
+            | Event::ContentProvided(_)
+            | Event::FragmentMerged(_)
+            | Event::FragmentEvicted(_)
+            | Event::FileSyncProgress(_, _, _)
+            | Event::FileSynced(_, _)
+            | Event::DirectoryOffered(_, _) => EventCategory::Content,
+            Event::MailboxReplayed(_)
+            | Event::MailboxError(_)
+            | Event::DeviceSynced(_)
+            | Event::DeviceSyncError(_)
+            | Event::ProfileUpdated(_)
+            | Event::ProfileError(_)
+            | Event::ContactStatusChanged(_, _) => EventCategory::Sync,
+            Event::IncomingStream(_, _, _)
+            | Event::StreamOpened(_, _)
+            | Event::StreamRejected(_)
+            | Event::StreamMuted(_, _)
+            | Event::StreamClosed(_)
+            | Event::StreamError(_)
+            | Event::VideoCapsNegotiated(_, _)
+            | Event::KeyframeRequested(_)
+            | Event::StreamBitrateChanged(_, _)
+            | Event::GroupParticipantsChanged(_, _)
+            | Event::GroupStreamAdded(_, _, _)
+            | Event::IncomingCall(_, _, _)
+            | Event::CallAccepted(_)
+            | Event::CallEnded(_, _)
+            | Event::RecordingSaved(_, _)
+            | Event::RecordingError(_) => EventCategory::Streams,
+            Event::TransactionCompleted(_) | Event::TransactionFailed(_, _) => {
+                EventCategory::Transactions
+            }
+            Event::UnknownExtension(_) | Event::PeerExtensionsChanged(_, _) => {
+                EventCategory::Extensions
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::event_category::EventCategory;
+use crate::runtime;
+use blink_contract::{Event, EventBus};
+use hmac_sha512::HMAC;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+    sync::mpsc::{self, Receiver, Sender},
+    time::{sleep, timeout},
+};
+
+const QUEUE_SIZE: usize = 1024;
+
+const MAX_BATCH_SIZE: usize = 64;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+const SIGNATURE_HEADER: &str = "X-Blink-Signature";
+
+// Where the forwarded events are delivered.
+#[derive(Debug, Clone)]
+pub enum ForwardTarget {
+    // Each batch is POSTed to `path`, the signature goes in the X-Blink-Signature header
+    Http { address: SocketAddr, path: String },
+    // Each batch is written as one line: the signature, a space and the JSON body
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Serialize)]
+struct ForwardedEvent {
+    category: EventCategory,
+    name: String,
+    detail: String,
+    timestamp: u64,
+}
+
+#[derive(Serialize)]
+struct Batch<'a> {
+    events: &'a [ForwardedEvent],
+}
+
+// EventBus that forwards the selected categories of events as signed JSON batches.
+// Meant for headless nodes feeding their telemetry to existing infrastructure; needs a tokio runtime.
+// Batches are signed with HMAC-SHA512 over the body using `key`, base64 encoded.
+pub struct EventForwarder {
+    categories: HashSet<EventCategory>,
+    queue: Sender<ForwardedEvent>,
+}
+
+impl EventForwarder {
+    pub fn new(target: ForwardTarget, key: Vec<u8>, categories: Vec<EventCategory>) -> Self {
+        let (queue, events) = mpsc::channel(QUEUE_SIZE);
+        runtime::spawn(forward(target, key, events));
+        Self {
+            categories: categories.into_iter().collect(),
+            queue,
+        }
+    }
+}
+
+impl EventBus for EventForwarder {
+    fn event_occurred(&mut self, event: Event) {
+        let category = EventCategory::of(&event);
+        if !self.categories.contains(&category) {
+            return;
+        }
+        let detail = format!("{:?}", event);
+        let name = detail
+            .split(|x: char| !x.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_millis() as u64);
+        // Telemetry is best effort, the network loop must never wait on the endpoint
+        let _ = self.queue.try_send(ForwardedEvent {
+            category,
+            name,
+            detail,
+            timestamp,
+        });
+    }
+}
+
+pub fn sign(key: &[u8], body: &[u8]) -> String {
+    base64::encode(HMAC::mac(body, key))
+}
+
+async fn forward(target: ForwardTarget, key: Vec<u8>, mut events: Receiver<ForwardedEvent>) {
+    let mut batch = Vec::new();
+    loop {
+        let open = match timeout(FLUSH_INTERVAL, events.recv()).await {
+            Ok(Some(event)) => {
+                batch.push(event);
+                if batch.len() < MAX_BATCH_SIZE {
+                    continue;
+                }
+                true
+            }
+            Ok(None) => false,
+            Err(_) => true,
+        };
+        if !batch.is_empty() {
+            deliver(&target, &key, &batch).await;
+            batch.clear();
+        }
+        if !open {
+            return;
+        }
+    }
+}
+
+// Retries with an exponential backoff, the batch is dropped once every attempt failed
+async fn deliver(target: &ForwardTarget, key: &[u8], events: &[ForwardedEvent]) {
+    let body = match serde_json::to_vec(&Batch { events }) {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+    let signature = sign(key, &body);
+    let mut delay = FIRST_RETRY_DELAY;
+    for _ in 0..MAX_DELIVERY_ATTEMPTS {
+        let delivered = match target {
+            ForwardTarget::Http { address, path } => post(address, path, &signature, &body).await,
+            ForwardTarget::Unix(path) => write_line(path, &signature, &body).await,
+        };
+        if delivered.unwrap_or(false) {
+            return;
+        }
+        sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+async fn post(
+    address: &SocketAddr,
+    path: &str,
+    signature: &str,
+    body: &[u8],
+) -> std::io::Result<bool> {
+    let mut stream = TcpStream::connect(address).await?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}: {}\r\nConnection: close\r\n\r\n",
+        path,
+        address,
+        body.len(),
+        SIGNATURE_HEADER,
+        signature
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    // Status line looks like "HTTP/1.1 204 No Content"
+    let status = String::from_utf8_lossy(&response)
+        .split_whitespace()
+        .nth(1)
+        .map(|x| x.to_string());
+    Ok(status.map_or(false, |x| x.starts_with('2')))
+}
+
+async fn write_line(path: &PathBuf, signature: &str, body: &[u8]) -> std::io::Result<bool> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(signature.as_bytes()).await?;
+    stream.write_all(b" ").await?;
+    stream.write_all(body).await?;
+    stream.write_all(b"\n").await?;
+    stream.shutdown().await?;
+    Ok(true)
+}
@@ -0,0 +1,97 @@
+use crate::event_category::EventCategory;
+use blink_contract::{Event, EventBus};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use warp::sync::RwLock;
+
+// Events kept for late subscribers, the oldest are dropped past it
+pub(crate) const EVENT_HISTORY_SIZE: usize = 1024;
+
+// How much of what happens reaches the application's EventBus. The history, and so
+// `subscribe_events`, always has everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    #[default]
+    All,
+    // Connection events stay out, they're most of what a busy node logs
+    Quiet,
+    Silent,
+}
+
+impl Verbosity {
+    fn forwards(&self, event: &Event) -> bool {
+        match self {
+            Verbosity::All => true,
+            Verbosity::Quiet => EventCategory::of(event) != EventCategory::Connection,
+            Verbosity::Silent => false,
+        }
+    }
+}
+
+// Sits in front of the application's EventBus and keeps the latest events, numbered from 1.
+pub(crate) struct EventHistory {
+    inner: Arc<RwLock<dyn EventBus>>,
+    events: VecDeque<(u64, Event)>,
+    capacity: usize,
+    last: u64,
+    // Same numbering as the history, receivers lagging a whole history behind skip ahead
+    sender: broadcast::Sender<(u64, Event)>,
+    verbosity: Verbosity,
+}
+
+impl EventHistory {
+    pub(crate) fn new(inner: Arc<RwLock<dyn EventBus>>, capacity: usize) -> Self {
+        Self {
+            inner,
+            events: VecDeque::new(),
+            capacity,
+            last: 0,
+            sender: broadcast::channel(capacity.max(1)).0,
+            verbosity: Verbosity::default(),
+        }
+    }
+
+    pub(crate) fn last(&self) -> u64 {
+        self.last
+    }
+
+    pub(crate) fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    pub(crate) fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<(u64, Event)> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn recent(&self) -> Vec<(u64, Event)> {
+        self.events.iter().cloned().collect()
+    }
+
+    // Events numbered after `seq`, pass the last number seen to catch up.
+    pub(crate) fn since(&self, seq: u64) -> Vec<(u64, Event)> {
+        let start = self.events.partition_point(|(x, _)| *x <= seq);
+        self.events.range(start..).cloned().collect()
+    }
+}
+
+impl EventBus for EventHistory {
+    fn event_occurred(&mut self, event: Event) {
+        self.last += 1;
+        self.events.push_back((self.last, event.clone()));
+        // Nobody listening is fine, the history still has it
+        let _ = self.sender.send((self.last, event.clone()));
+        if self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
+        if self.verbosity.forwards(&event) {
+            self.inner.write().event_occurred(event);
+        }
+    }
+}
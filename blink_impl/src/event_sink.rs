@@ -0,0 +1,49 @@
+use blink_contract::{Event, EventBus};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::oneshot;
+use warp::sync::RwLock;
+
+enum Queued {
+    Event(Event),
+    Flush(oneshot::Sender<()>),
+}
+
+// Queues events for the application's EventBus, which a task of its own delivers them to in
+// order. The event loop never waits on the bus lock the application reads under.
+#[derive(Clone)]
+pub(crate) struct EventSink {
+    queue: UnboundedSender<Queued>,
+}
+
+impl EventSink {
+    // The sink and the task delivering what goes through it, to be spawned.
+    pub(crate) fn new(bus: Arc<RwLock<dyn EventBus>>) -> (Self, impl Future<Output = ()>) {
+        let (queue, mut queued) = unbounded_channel();
+        let deliver = async move {
+            while let Some(queued) = queued.recv().await {
+                match queued {
+                    Queued::Event(event) => bus.write().event_occurred(event),
+                    Queued::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        };
+        (Self { queue }, deliver)
+    }
+
+    pub(crate) fn event_occurred(&self, event: Event) {
+        // Only fails once the delivering task is gone, with the runtime
+        let _ = self.queue.send(Queued::Event(event));
+    }
+
+    // Completes once everything queued before it reached the bus.
+    pub(crate) async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.queue.send(Queued::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::conversations::message_id;
+use anyhow::Result;
+use sata::Sata;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+// Disappearing messages, sent or received, by message id with when they expire.
+// Once a path is set the schedule is written to it, so messages still disappear after a restart.
+#[derive(Default)]
+pub(crate) struct Expiry {
+    // Milliseconds since the unix epoch
+    expiring: HashMap<String, u64>,
+    // The same messages by when they expire, the first ones are the next to go
+    queue: BTreeSet<(u64, String)>,
+    path: Option<PathBuf>,
+}
+
+impl Expiry {
+    // Loads the schedule saved at `path` and keeps it up to date from now on.
+    // Of a message scheduled both before and after the restart, the earlier expiry wins.
+    pub(crate) fn open(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let saved: HashMap<String, u64> = bincode::deserialize(&bytes)?;
+                for (id, expires_at) in saved {
+                    self.insert(id, expires_at);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.path = Some(path);
+        self.save()
+    }
+
+    // True if the message expires sooner than it did.
+    // The earlier expiry wins when it's given twice.
+    pub(crate) fn schedule(&mut self, id: String, expires_at: u64) -> Result<bool> {
+        let sooner = self.insert(id, expires_at);
+        if sooner {
+            self.save()?;
+        }
+        Ok(sooner)
+    }
+
+    // When the message expires, None if it doesn't.
+    pub(crate) fn expires_at(&self, sata: &Sata) -> Option<u64> {
+        // Most traffic doesn't expire, it's spared hashing every message
+        if self.expiring.is_empty() {
+            return None;
+        }
+        self.expiring.get(&message_id(sata)).copied()
+    }
+
+    // Forgets the messages that expired by `now` and returns their ids, to be deleted.
+    pub(crate) fn due(&mut self, now: u64) -> Result<Vec<String>> {
+        let later = self
+            .queue
+            .split_off(&(now.saturating_add(1), String::new()));
+        let due: Vec<String> = std::mem::replace(&mut self.queue, later)
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        for id in &due {
+            self.expiring.remove(id);
+        }
+        if !due.is_empty() {
+            self.save()?;
+        }
+        Ok(due)
+    }
+
+    fn insert(&mut self, id: String, expires_at: u64) -> bool {
+        match self.expiring.get(&id) {
+            Some(x) if *x <= expires_at => return false,
+            Some(x) => {
+                self.queue.remove(&(*x, id.clone()));
+            }
+            None => {}
+        }
+        self.queue.insert((expires_at, id.clone()));
+        self.expiring.insert(id, expires_at);
+        true
+    }
+
+    // Written next to the target then renamed, a crash never leaves half a file behind
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let mut temporary = path.clone().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, bincode::serialize(&self.expiring)?)?;
+            fs::rename(&temporary, path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use blink_contract::{BlinkError, ExtensionHandler};
+use libp2p::PeerId;
+use std::{collections::HashMap, sync::Arc};
+use warp::sync::RwLock;
+
+// Neither base64 pairwise topics nor namespaces can contain it
+const NAMESPACE_SEPARATOR: char = ':';
+
+// Topic carrying an extension's messages over the pairwise channel `topic`.
+pub(crate) fn extension_topic(topic: &str, namespace: &str) -> String {
+    format!("{}{}{}", topic, NAMESPACE_SEPARATOR, namespace)
+}
+
+// Splits an extension topic back into the pairwise topic and the namespace.
+pub(crate) fn split_extension_topic(topic: &str) -> Option<(&str, &str)> {
+    topic.split_once(NAMESPACE_SEPARATOR)
+}
+
+pub(crate) fn validate_namespace(namespace: &str) -> Result<(), BlinkError> {
+    let valid_char = |x: char| x.is_ascii_alphanumeric() || x == '-' || x == '_' || x == '.';
+    if namespace.is_empty() || !namespace.chars().all(valid_char) {
+        return Err(BlinkError::Invalid(format!(
+            "Invalid extension namespace {:?}",
+            namespace
+        )));
+    }
+    Ok(())
+}
+
+// Extensions riding over the pairwise channels, by namespace, and the ones each peer advertised.
+#[derive(Default)]
+pub(crate) struct ExtensionRegistry {
+    handlers: HashMap<String, Arc<RwLock<dyn ExtensionHandler>>>,
+    remote: HashMap<PeerId, Vec<String>>,
+}
+
+impl ExtensionRegistry {
+    pub(crate) fn register(
+        &mut self,
+        namespace: String,
+        handler: Arc<RwLock<dyn ExtensionHandler>>,
+    ) -> bool {
+        if self.handlers.contains_key(&namespace) {
+            return false;
+        }
+        self.handlers.insert(namespace, handler);
+        true
+    }
+
+    pub(crate) fn unregister(&mut self, namespace: &str) -> bool {
+        self.handlers.remove(namespace).is_some()
+    }
+
+    pub(crate) fn handler(&self, namespace: &str) -> Option<Arc<RwLock<dyn ExtensionHandler>>> {
+        self.handlers.get(namespace).cloned()
+    }
+
+    pub(crate) fn namespaces(&self) -> Vec<String> {
+        let mut namespaces: Vec<String> = self.handlers.keys().cloned().collect();
+        namespaces.sort();
+        namespaces
+    }
+
+    // Records what a peer advertised, returns true if it differs from what we knew.
+    pub(crate) fn set_remote(&mut self, peer: PeerId, mut namespaces: Vec<String>) -> bool {
+        namespaces.sort();
+        namespaces.dedup();
+        if self.remote.get(&peer) == Some(&namespaces) {
+            return false;
+        }
+        self.remote.insert(peer, namespaces);
+        true
+    }
+
+    pub(crate) fn remote(&self, peer: &PeerId) -> Option<Vec<String>> {
+        self.remote.get(peer).cloned()
+    }
+}
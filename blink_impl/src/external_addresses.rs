@@ -0,0 +1,81 @@
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+
+// Distinct peers that have to observe an address before we advertise it
+pub(crate) const CONFIRMATIONS: usize = 2;
+
+// Outbound connections are observed on ephemeral ports, those are never confirmed
+const MAX_OBSERVED: usize = 32;
+
+// Addresses peers see us at, through identify, and the ones we advertise. The advertised
+// ones go into identify and our DID record, it's where other peers dial us.
+#[derive(Default)]
+pub(crate) struct ExternalAddresses {
+    observed: HashMap<Multiaddr, HashSet<PeerId>>,
+    confirmed: Vec<Multiaddr>,
+    // Added by the application or the config, kept whoever observes what
+    static_addrs: Vec<Multiaddr>,
+}
+
+impl ExternalAddresses {
+    // Records that the peer sees us at the address, true the first time enough peers did.
+    pub(crate) fn observed(&mut self, peer: PeerId, addr: Multiaddr) -> bool {
+        if self.confirmed.contains(&addr) {
+            return false;
+        }
+        if !self.observed.contains_key(&addr) && self.observed.len() >= MAX_OBSERVED {
+            let least = self
+                .observed
+                .iter()
+                .min_by_key(|(_, peers)| peers.len())
+                .map(|(addr, _)| addr.clone());
+            if let Some(least) = least {
+                self.observed.remove(&least);
+            }
+        }
+        let peers = self.observed.entry(addr.clone()).or_default();
+        peers.insert(peer);
+        if peers.len() < CONFIRMATIONS {
+            return false;
+        }
+        self.observed.remove(&addr);
+        self.confirmed.push(addr);
+        true
+    }
+
+    // False if it was already there.
+    pub(crate) fn add(&mut self, addr: Multiaddr) -> bool {
+        if self.static_addrs.contains(&addr) {
+            return false;
+        }
+        self.static_addrs.push(addr);
+        true
+    }
+
+    // Forgets the address, static or confirmed, false if it wasn't advertised.
+    pub(crate) fn remove(&mut self, addr: &Multiaddr) -> bool {
+        let before = self.static_addrs.len() + self.confirmed.len();
+        self.static_addrs.retain(|x| x != addr);
+        self.confirmed.retain(|x| x != addr);
+        before != self.static_addrs.len() + self.confirmed.len()
+    }
+
+    // Static addresses first, then the confirmed ones.
+    pub(crate) fn addresses(&self) -> Vec<Multiaddr> {
+        let mut addrs = self.static_addrs.clone();
+        for addr in &self.confirmed {
+            if !addrs.contains(addr) {
+                addrs.push(addr.clone());
+            }
+        }
+        addrs
+    }
+
+    // Addresses observed but not confirmed yet, with how many peers observed them.
+    pub(crate) fn unconfirmed(&self) -> Vec<(Multiaddr, usize)> {
+        self.observed
+            .iter()
+            .map(|(addr, peers)| (addr.clone(), peers.len()))
+            .collect()
+    }
+}
@@ -0,0 +1,626 @@
+use crate::{
+    behavior::BlinkBehavior,
+    event_sink::EventSink,
+    fragments,
+    protocol::{BincodeCodec, BlinkProtocol},
+    shared_state::SharedState,
+};
+use anyhow::{anyhow, bail, Result};
+use blink_contract::Event;
+use hmac_sha512::Hash;
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    PeerId, Swarm,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    iter,
+    path::{Path, PathBuf},
+};
+
+const FILE_TRANSFER_PROTOCOL: &[u8] = b"/blink/file-transfer/1.0.0";
+
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+// Chunks requested at once for a single transfer
+const MAX_CHUNKS_IN_FLIGHT: usize = 8;
+
+// Offers from one peer the application hasn't accepted yet, further ones are refused
+pub(crate) const MAX_PENDING_OFFERS: usize = 16;
+
+pub type TransferId = u64;
+
+pub(crate) type FileTransferBehaviour =
+    RequestResponse<BincodeCodec<TransferRequest, TransferResponse>>;
+
+// What the receiver needs to pull a file chunk by chunk and check it arrived whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FileOffer {
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    // SHA-512 of the whole file
+    hash: Vec<u8>,
+    // CID of each chunk, in file order
+    chunks: Vec<String>,
+}
+
+impl FileOffer {
+    pub(crate) fn from_file(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("{} isn't a file", path.display()))?;
+        let mut file = File::open(path)?;
+        let mut hash = Hash::new();
+        let mut chunks = Vec::new();
+        let mut size = 0;
+        loop {
+            let chunk = read_up_to(&mut file, CHUNK_SIZE)?;
+            if chunk.is_empty() {
+                break;
+            }
+            size += chunk.len() as u64;
+            hash.update(&chunk);
+            chunks.push(fragments::cid_of(&chunk));
+        }
+        Ok(Self {
+            name,
+            size,
+            hash: hash.finalize().to_vec(),
+            chunks,
+        })
+    }
+
+    fn chunk_len(&self, index: usize) -> u64 {
+        CHUNK_SIZE.min(self.size - index as u64 * CHUNK_SIZE)
+    }
+
+    // Chunk lengths are worked out from the size, so an offer listing more or fewer chunks than
+    // the size takes is refused rather than read
+    fn check(&self) -> Result<()> {
+        let expected = self.size / CHUNK_SIZE + u64::from(self.size % CHUNK_SIZE != 0);
+        if self.chunks.len() as u64 != expected {
+            bail!(
+                "Offer of {} bytes lists {} chunks instead of {}",
+                self.size,
+                self.chunks.len(),
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum TransferRequest {
+    Offer(TransferId, FileOffer),
+    // Sent by the receiver for every chunk it misses
+    Fetch(TransferId, usize),
+    // The receiver verified the whole file
+    Finished(TransferId),
+    Cancel(TransferId),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum TransferResponse {
+    Received,
+    Chunk(Vec<u8>),
+    Unknown,
+}
+
+pub(crate) fn new_behaviour() -> FileTransferBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(FILE_TRANSFER_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+fn read_up_to(file: &mut File, len: u64) -> Result<Vec<u8>> {
+    let mut chunk = Vec::new();
+    file.take(len).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn read_chunk(file: &mut File, index: usize) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(index as u64 * CHUNK_SIZE))?;
+    read_up_to(file, CHUNK_SIZE)
+}
+
+pub(crate) enum ChunkOutcome {
+    // Bytes received so far and the size of the file
+    Progress(u64, u64),
+    // The file was verified, with its size
+    Completed(u64),
+}
+
+struct Outgoing {
+    peer: PeerId,
+    path: PathBuf,
+    offer: FileOffer,
+    served: HashSet<usize>,
+}
+
+struct Incoming {
+    peer: PeerId,
+    offer: FileOffer,
+    // Set once the application accepted the file
+    file: Option<File>,
+    missing: BTreeSet<usize>,
+    in_flight: HashSet<usize>,
+}
+
+impl Incoming {
+    fn received_bytes(&self) -> u64 {
+        self.offer.size
+            - self
+                .missing
+                .iter()
+                .map(|x| self.offer.chunk_len(*x))
+                .sum::<u64>()
+    }
+}
+
+// Files we offered and files offered to us.
+// Receivers pull the chunks they miss, so an interrupted transfer picks up where it stopped.
+#[derive(Default)]
+pub(crate) struct TransferRegistry {
+    outgoing: HashMap<TransferId, Outgoing>,
+    incoming: HashMap<TransferId, Incoming>,
+    fetching: HashMap<RequestId, (TransferId, usize)>,
+    offering: HashMap<RequestId, TransferId>,
+}
+
+impl TransferRegistry {
+    pub(crate) fn offer(&mut self, id: TransferId, peer: PeerId, path: PathBuf, offer: FileOffer) {
+        self.outgoing.insert(
+            id,
+            Outgoing {
+                peer,
+                path,
+                offer,
+                served: HashSet::new(),
+            },
+        );
+    }
+
+    // Reads a chunk the receiver asked for, with the bytes it was sent so far and the file size.
+    pub(crate) fn serve(
+        &mut self,
+        id: TransferId,
+        from: &PeerId,
+        index: usize,
+    ) -> Option<(Vec<u8>, u64, u64)> {
+        let outgoing = match self.outgoing.get_mut(&id) {
+            Some(outgoing) if outgoing.peer == *from => outgoing,
+            _ => return None,
+        };
+        let expected = outgoing.offer.chunks.get(index)?;
+        let chunk = File::open(&outgoing.path)
+            .ok()
+            .and_then(|mut file| read_chunk(&mut file, index).ok())?;
+        // The file changed since it was offered
+        if fragments::cid_of(&chunk) != *expected {
+            return None;
+        }
+        outgoing.served.insert(index);
+        let sent: u64 = outgoing
+            .served
+            .iter()
+            .map(|x| outgoing.offer.chunk_len(*x))
+            .sum();
+        Some((chunk, sent, outgoing.offer.size))
+    }
+
+    pub(crate) fn track_offer(&mut self, request_id: RequestId, id: TransferId) {
+        self.offering.insert(request_id, id);
+    }
+
+    // The offer never reached the peer, returns the transfer to give up on.
+    pub(crate) fn offer_failed(&mut self, request_id: &RequestId) -> Option<TransferId> {
+        let id = self.offering.remove(request_id)?;
+        self.remove(id, None).map(|_| id)
+    }
+
+    pub(crate) fn offer_delivered(&mut self, request_id: &RequestId) {
+        self.offering.remove(request_id);
+    }
+
+    pub(crate) fn incoming_offer(
+        &mut self,
+        id: TransferId,
+        peer: PeerId,
+        offer: FileOffer,
+    ) -> Result<()> {
+        offer.check()?;
+        // The sender picks the id, so one in use isn't taken over whoever offers it
+        if self.incoming.contains_key(&id) || self.outgoing.contains_key(&id) {
+            bail!("Transfer {} is already in use", id);
+        }
+        let pending = self
+            .incoming
+            .values()
+            .filter(|x| x.peer == peer && x.file.is_none())
+            .count();
+        if pending >= MAX_PENDING_OFFERS {
+            bail!("{} has {} offers waiting already", peer, pending);
+        }
+        let missing = (0..offer.chunks.len()).collect();
+        self.incoming.insert(
+            id,
+            Incoming {
+                peer,
+                offer,
+                file: None,
+                missing,
+                in_flight: HashSet::new(),
+            },
+        );
+        Ok(())
+    }
+
+    // Starts receiving into `path`. Chunks already in the file with the right CID are kept,
+    // so accepting a file offered again after an interruption only fetches what is missing.
+    // Returns true when the file was already complete.
+    pub(crate) fn accept(&mut self, id: TransferId, path: &Path) -> Result<bool> {
+        let incoming = self
+            .incoming
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("No file offered as transfer {}", id))?;
+        if incoming.file.is_some() {
+            bail!("Transfer {} was already accepted", id);
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let present: Vec<usize> = incoming
+            .missing
+            .iter()
+            .copied()
+            .filter(|index| {
+                read_chunk(&mut file, *index).map_or(false, |x| {
+                    fragments::cid_of(&x) == incoming.offer.chunks[*index]
+                })
+            })
+            .collect();
+        for index in present {
+            incoming.missing.remove(&index);
+        }
+        file.set_len(incoming.offer.size)?;
+        incoming.file = Some(file);
+        if incoming.missing.is_empty() {
+            Self::verify(incoming)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Picks the next chunks to request, up to the in-flight limit, with the peer to ask.
+    pub(crate) fn next_fetches(&mut self, id: TransferId) -> Option<(PeerId, Vec<usize>)> {
+        let incoming = self.incoming.get_mut(&id).filter(|x| x.file.is_some())?;
+        let room = MAX_CHUNKS_IN_FLIGHT.saturating_sub(incoming.in_flight.len());
+        let next: Vec<usize> = incoming
+            .missing
+            .iter()
+            .filter(|x| !incoming.in_flight.contains(*x))
+            .take(room)
+            .copied()
+            .collect();
+        incoming.in_flight.extend(next.iter().copied());
+        Some((incoming.peer, next))
+    }
+
+    pub(crate) fn track_fetch(&mut self, request_id: RequestId, id: TransferId, index: usize) {
+        self.fetching.insert(request_id, (id, index));
+    }
+
+    // A fetch got no chunk back, it will be asked for again on the next round.
+    pub(crate) fn fetch_failed(&mut self, request_id: &RequestId) -> Option<TransferId> {
+        let (id, index) = self.fetching.remove(request_id)?;
+        if let Some(incoming) = self.incoming.get_mut(&id) {
+            incoming.in_flight.remove(&index);
+        }
+        Some(id)
+    }
+
+    // Checks the chunk against its CID and writes it in place.
+    // Once nothing is missing the whole file is checked against the offered hash.
+    pub(crate) fn chunk_received(
+        &mut self,
+        request_id: &RequestId,
+        chunk: Vec<u8>,
+    ) -> Option<(TransferId, Result<ChunkOutcome>)> {
+        let (id, index) = self.fetching.remove(request_id)?;
+        let incoming = self.incoming.get_mut(&id)?;
+        incoming.in_flight.remove(&index);
+        Some((id, Self::write_chunk(incoming, index, chunk)))
+    }
+
+    fn write_chunk(incoming: &mut Incoming, index: usize, chunk: Vec<u8>) -> Result<ChunkOutcome> {
+        if fragments::cid_of(&chunk) != incoming.offer.chunks[index] {
+            bail!("Chunk {} doesn't match its CID", index);
+        }
+        let file = incoming
+            .file
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transfer wasn't accepted"))?;
+        file.seek(SeekFrom::Start(index as u64 * CHUNK_SIZE))?;
+        file.write_all(&chunk)?;
+        incoming.missing.remove(&index);
+        if !incoming.missing.is_empty() {
+            return Ok(ChunkOutcome::Progress(
+                incoming.received_bytes(),
+                incoming.offer.size,
+            ));
+        }
+        Self::verify(incoming)?;
+        Ok(ChunkOutcome::Completed(incoming.offer.size))
+    }
+
+    fn verify(incoming: &mut Incoming) -> Result<()> {
+        let file = incoming
+            .file
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transfer wasn't accepted"))?;
+        file.flush()?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut hash = Hash::new();
+        loop {
+            let chunk = read_up_to(file, CHUNK_SIZE)?;
+            if chunk.is_empty() {
+                break;
+            }
+            hash.update(&chunk);
+        }
+        if hash.finalize().to_vec() != incoming.offer.hash {
+            bail!("File doesn't match the offered hash");
+        }
+        Ok(())
+    }
+
+    // Accepted transfers still waiting for chunks.
+    pub(crate) fn receiving(&self) -> Vec<TransferId> {
+        self.incoming
+            .iter()
+            .filter(|(_, x)| x.file.is_some() && !x.missing.is_empty())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub(crate) fn sender_of(&self, id: TransferId) -> Option<PeerId> {
+        self.incoming.get(&id).map(|x| x.peer)
+    }
+
+    // Forgets a transfer, returns the peer on the other end if `from` is None or matches it.
+    pub(crate) fn remove(&mut self, id: TransferId, from: Option<&PeerId>) -> Option<PeerId> {
+        let peer = self
+            .outgoing
+            .get(&id)
+            .map(|x| x.peer)
+            .or_else(|| self.incoming.get(&id).map(|x| x.peer))?;
+        if from.map_or(false, |x| *x != peer) {
+            return None;
+        }
+        self.outgoing.remove(&id);
+        self.incoming.remove(&id);
+        self.fetching.retain(|_, (transfer, _)| *transfer != id);
+        self.offering.retain(|_, transfer| *transfer != id);
+        Some(peer)
+    }
+}
+
+// Answers offers and chunk requests from the peers and follows up on the answers to ours.
+pub(crate) fn handle_event(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    event: RequestResponseEvent<TransferRequest, TransferResponse>,
+) {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => {
+                let response = request_received(logger.clone(), state, peer, request);
+                state.count_sent(&peer, None, &response);
+                // A peer that went away asks for the chunk again once it is back
+                let _ = swarm
+                    .behaviour_mut()
+                    .file_transfer
+                    .send_response(channel, response);
+            }
+            RequestResponseMessage::Response {
+                request_id,
+                response,
+            } => match response {
+                TransferResponse::Received => {
+                    state.media.transfers.write().offer_delivered(&request_id);
+                }
+                TransferResponse::Chunk(chunk) => {
+                    chunk_received(swarm, logger, state, request_id, chunk);
+                }
+                TransferResponse::Unknown => {
+                    // The sender forgot the transfer, or the receiver refused the offer
+                    let fetched = state.media.transfers.write().fetch_failed(&request_id);
+                    if let Some(id) = fetched {
+                        state.media.transfers.write().remove(id, None);
+                    }
+                    let failed =
+                        fetched.or_else(|| state.media.transfers.write().offer_failed(&request_id));
+                    if let Some(id) = failed {
+                        logger.event_occurred(Event::TransferFailed(
+                            id,
+                            "The peer doesn't know the transfer".into(),
+                        ));
+                    }
+                }
+            },
+        },
+        RequestResponseEvent::OutboundFailure {
+            request_id, error, ..
+        } => {
+            // Missed chunks are fetched again on the next retry, an offer that never arrived ends the transfer
+            let retried = state
+                .media
+                .transfers
+                .write()
+                .fetch_failed(&request_id)
+                .is_some();
+            let failed = if retried {
+                None
+            } else {
+                state.media.transfers.write().offer_failed(&request_id)
+            };
+            if let Some(id) = failed {
+                logger.event_occurred(Event::TransferFailed(id, error.to_string()));
+            }
+        }
+        RequestResponseEvent::InboundFailure { .. } => {}
+        RequestResponseEvent::ResponseSent { .. } => {}
+    }
+}
+
+fn request_received(
+    logger: EventSink,
+    state: &SharedState,
+    peer: PeerId,
+    request: TransferRequest,
+) -> TransferResponse {
+    match request {
+        TransferRequest::Offer(id, offer) => {
+            // Files come from paired peers only, strangers ask to be friends first
+            let did = match state.paired_did(&peer) {
+                Some(did) if !state.messaging.moderation.read().is_blocked(&did) => did,
+                _ => return TransferResponse::Unknown,
+            };
+            let (name, size) = (offer.name.clone(), offer.size);
+            if let Err(e) = state
+                .media
+                .transfers
+                .write()
+                .incoming_offer(id, peer, offer)
+            {
+                tracing::debug!(%peer, id, %e, "offer refused");
+                return TransferResponse::Unknown;
+            }
+            logger.event_occurred(Event::IncomingFile(did, id, name, size));
+            TransferResponse::Received
+        }
+        TransferRequest::Fetch(id, index) => {
+            let served = state.media.transfers.write().serve(id, &peer, index);
+            match served {
+                Some((chunk, sent, total)) => {
+                    logger.event_occurred(Event::TransferProgress(id, sent, total));
+                    TransferResponse::Chunk(chunk)
+                }
+                None => TransferResponse::Unknown,
+            }
+        }
+        TransferRequest::Finished(id) => {
+            if state
+                .media
+                .transfers
+                .write()
+                .remove(id, Some(&peer))
+                .is_some()
+            {
+                logger.event_occurred(Event::TransferCompleted(id));
+            }
+            TransferResponse::Received
+        }
+        TransferRequest::Cancel(id) => {
+            if state
+                .media
+                .transfers
+                .write()
+                .remove(id, Some(&peer))
+                .is_some()
+            {
+                logger.event_occurred(Event::TransferFailed(id, "Cancelled by the peer".into()));
+            }
+            TransferResponse::Received
+        }
+    }
+}
+
+fn chunk_received(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    request_id: RequestId,
+    chunk: Vec<u8>,
+) {
+    let received = state
+        .media
+        .transfers
+        .write()
+        .chunk_received(&request_id, chunk);
+    let (id, outcome) = match received {
+        Some(received) => received,
+        None => return,
+    };
+    match outcome {
+        Ok(ChunkOutcome::Progress(bytes, total)) => {
+            logger.event_occurred(Event::TransferProgress(id, bytes, total));
+            fetch_chunks(swarm, state, id);
+        }
+        Ok(ChunkOutcome::Completed(total)) => {
+            logger.event_occurred(Event::TransferProgress(id, total, total));
+            logger.event_occurred(Event::TransferCompleted(id));
+            if let Some(peer_id) = state.media.transfers.write().remove(id, None) {
+                let request = TransferRequest::Finished(id);
+                state.count_sent(&peer_id, None, &request);
+                swarm
+                    .behaviour_mut()
+                    .file_transfer
+                    .send_request(&peer_id, request);
+            }
+        }
+        Err(e) => {
+            logger.event_occurred(Event::TransferFailed(id, e.to_string()));
+            if let Some(peer_id) = state.media.transfers.write().remove(id, None) {
+                let request = TransferRequest::Cancel(id);
+                state.count_sent(&peer_id, None, &request);
+                swarm
+                    .behaviour_mut()
+                    .file_transfer
+                    .send_request(&peer_id, request);
+            }
+        }
+    }
+}
+
+// Waits for the next retry round while the bandwidth caps are reached
+pub(crate) fn fetch_chunks(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState, id: TransferId) {
+    let sender = state.media.transfers.read().sender_of(id);
+    let now = state.clock.now_millis();
+    if let Some(sender) = sender {
+        if !state.network.bandwidth.write().allows_bulk(&sender, now) {
+            return;
+        }
+    }
+    let next = state.media.transfers.write().next_fetches(id);
+    if let Some((peer_id, indices)) = next {
+        for index in indices {
+            let request = TransferRequest::Fetch(id, index);
+            state.count_sent(&peer_id, None, &request);
+            let request_id = swarm
+                .behaviour_mut()
+                .file_transfer
+                .send_request(&peer_id, request);
+            state
+                .media
+                .transfers
+                .write()
+                .track_fetch(request_id, id, index);
+        }
+    }
+}
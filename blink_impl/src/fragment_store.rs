@@ -0,0 +1,176 @@
+use crate::fragments::DataFragment;
+use anyhow::{anyhow, bail, Result};
+use blink_contract::Keystore;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hmac_sha512::HMAC;
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+const TEMPORARY_EXTENSION: &str = "tmp";
+
+const NONCE_SIZE: usize = 12;
+
+// Domain separation, keeps store keys apart from anything else derived from the same secret
+const STORE_KEY_CONTEXT: &[u8] = b"blink fragment store key";
+
+// Where Conflux keeps its fragments.
+pub trait FragmentStore: Send + Sync {
+    fn put(&mut self, fragment: DataFragment) -> Result<()>;
+    fn get(&self, cid: &str) -> Result<Option<DataFragment>>;
+    fn remove(&mut self, cid: &str) -> Result<Option<DataFragment>>;
+    fn cids(&self) -> Result<Vec<String>>;
+}
+
+// Keeps fragments until the process exits, the default store.
+#[derive(Default)]
+pub struct MemoryFragmentStore {
+    fragments: HashMap<String, DataFragment>,
+}
+
+impl FragmentStore for MemoryFragmentStore {
+    fn put(&mut self, fragment: DataFragment) -> Result<()> {
+        self.fragments.insert(fragment.cid().to_string(), fragment);
+        Ok(())
+    }
+
+    fn get(&self, cid: &str) -> Result<Option<DataFragment>> {
+        Ok(self.fragments.get(cid).cloned())
+    }
+
+    fn remove(&mut self, cid: &str) -> Result<Option<DataFragment>> {
+        Ok(self.fragments.remove(cid))
+    }
+
+    fn cids(&self) -> Result<Vec<String>> {
+        Ok(self.fragments.keys().cloned().collect())
+    }
+}
+
+// Key a disk store encrypts its fragments with.
+pub struct StoreKey([u8; 32]);
+
+impl StoreKey {
+    // Derived from the identity key, only the same identity can read the store back.
+    pub fn from_keystore(keystore: &dyn Keystore) -> Result<Self> {
+        // Identity signatures are deterministic, the same key comes out on every start
+        let secret = keystore.sign(STORE_KEY_CONTEXT)?;
+        Ok(Self::derive(&secret))
+    }
+
+    // For stores outliving an identity. Nothing slows down guessing, use a long random passphrase.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Self::derive(passphrase.as_bytes())
+    }
+
+    fn derive(secret: &[u8]) -> Self {
+        let mut key = [0; 32];
+        key.copy_from_slice(&HMAC::mac(STORE_KEY_CONTEXT, secret)[..32]);
+        Self(key)
+    }
+}
+
+// One file per fragment in a directory, named after its CID.
+// Encrypted stores write a random nonce followed by the ChaCha20-Poly1305 ciphertext.
+pub struct DiskFragmentStore {
+    directory: PathBuf,
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl DiskFragmentStore {
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory: directory.as_ref().to_path_buf(),
+            cipher: None,
+        })
+    }
+
+    // Fragments are encrypted before they are written, a store opened with another key can't read them.
+    pub fn open_encrypted(directory: impl AsRef<Path>, key: StoreKey) -> Result<Self> {
+        let mut store = Self::open(directory)?;
+        store.cipher = Some(ChaCha20Poly1305::new(Key::from_slice(&key.0)));
+        Ok(store)
+    }
+
+    fn seal(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(bytes),
+        };
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, bytes.as_slice())
+            .map_err(|_| anyhow!("Couldn't encrypt fragment"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn open_sealed(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(bytes),
+        };
+        if bytes.len() < NONCE_SIZE {
+            bail!("Encrypted fragment is truncated");
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_SIZE);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Couldn't decrypt fragment, wrong key or corrupted file"))
+    }
+
+    fn path_of(&self, cid: &str) -> Result<PathBuf> {
+        // CIDs come from peers, they must not be able to point outside the directory
+        if cid.is_empty() || !cid.chars().all(|x| x.is_ascii_alphanumeric()) {
+            bail!("Invalid CID {}", cid);
+        }
+        Ok(self.directory.join(cid))
+    }
+}
+
+impl FragmentStore for DiskFragmentStore {
+    fn put(&mut self, fragment: DataFragment) -> Result<()> {
+        let path = self.path_of(fragment.cid())?;
+        let temporary = path.with_extension(TEMPORARY_EXTENSION);
+        fs::write(&temporary, self.seal(bincode::serialize(&fragment)?)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    fn get(&self, cid: &str) -> Result<Option<DataFragment>> {
+        match fs::read(self.path_of(cid)?) {
+            Ok(bytes) => Ok(Some(bincode::deserialize(&self.open_sealed(bytes)?)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn remove(&mut self, cid: &str) -> Result<Option<DataFragment>> {
+        let fragment = self.get(cid)?;
+        if fragment.is_some() {
+            fs::remove_file(self.path_of(cid)?)?;
+        }
+        Ok(fragment)
+    }
+
+    fn cids(&self) -> Result<Vec<String>> {
+        let mut cids = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            // Leftovers of a write interrupted before its rename
+            if path.extension().is_some() {
+                continue;
+            }
+            if let Some(name) = path.file_name().and_then(|x| x.to_str()) {
+                cids.push(name.to_string());
+            }
+        }
+        Ok(cids)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::fragments::DataFragment;
+use crate::wire::bounded_bincode;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+// Tree node listing the CIDs of its children, from the multicodec private use range
+pub const TREE_CODEC: u64 = 0x30_0002;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+// Keeps a node of CIDs well under the chunk size
+const MAX_LINKS: usize = 174;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeNode {
+    // Bytes of blob below this node
+    size: u64,
+    links: Vec<String>,
+}
+
+// Blob split into raw chunks under a tree of link nodes, the root addresses the whole blob.
+pub struct FragmentTree {
+    root: DataFragment,
+    // Chunks and nodes alike, root included
+    fragments: Vec<DataFragment>,
+}
+
+impl FragmentTree {
+    pub fn split(data: &[u8], timestamp: u64) -> Result<Self> {
+        Self::split_with(data, DEFAULT_CHUNK_SIZE, timestamp)
+    }
+
+    pub fn split_with(data: &[u8], chunk_size: usize, timestamp: u64) -> Result<Self> {
+        if chunk_size == 0 {
+            bail!("Chunk size can't be zero");
+        }
+        let mut fragments = Vec::new();
+        let mut level: Vec<(String, u64)> = data
+            .chunks(chunk_size)
+            .map(|x| {
+                let chunk = DataFragment::new(x.to_vec(), timestamp);
+                let link = (chunk.cid().to_string(), x.len() as u64);
+                fragments.push(chunk);
+                link
+            })
+            .collect();
+        // Builds levels of nodes until a single one is left, an empty blob gets a root without links
+        loop {
+            let mut nodes = Vec::new();
+            for group in level.chunks(MAX_LINKS) {
+                nodes.push(node(group, timestamp)?);
+            }
+            if nodes.is_empty() {
+                nodes.push(node(&[], timestamp)?);
+            }
+            level = nodes
+                .iter()
+                .map(|(fragment, size)| (fragment.cid().to_string(), *size))
+                .collect();
+            let done = nodes.len() == 1;
+            fragments.extend(nodes.into_iter().map(|(fragment, _)| fragment));
+            if done {
+                break;
+            }
+        }
+        let root = fragments.last().cloned().expect("a root node was built");
+        Ok(Self { root, fragments })
+    }
+
+    pub fn root(&self) -> &DataFragment {
+        &self.root
+    }
+
+    pub fn fragments(&self) -> &[DataFragment] {
+        &self.fragments
+    }
+
+    pub fn into_fragments(self) -> Vec<DataFragment> {
+        self.fragments
+    }
+}
+
+fn node(links: &[(String, u64)], timestamp: u64) -> Result<(DataFragment, u64)> {
+    let size = links.iter().map(|(_, size)| size).sum();
+    let node = TreeNode {
+        size,
+        links: links.iter().map(|(cid, _)| cid.clone()).collect(),
+    };
+    Ok((
+        DataFragment::with_codec(bincode::serialize(&node)?, TREE_CODEC, timestamp),
+        size,
+    ))
+}
+
+// Children of a tree node in blob order, None for a chunk.
+pub(crate) fn links(fragment: &DataFragment) -> Result<Option<(u64, Vec<String>)>> {
+    if fragment.codec() != TREE_CODEC {
+        return Ok(None);
+    }
+    let node: TreeNode = bounded_bincode(fragment.data())?;
+    Ok(Some((node.size, node.links)))
+}
+
+// Puts the blob back together from its root, fetching every fragment below it through `get`.
+// Each fragment has to verify and match the CID it was linked by.
+pub fn reassemble(
+    root: &DataFragment,
+    mut get: impl FnMut(&str) -> Result<DataFragment>,
+) -> Result<Vec<u8>> {
+    let expected = match links(root)? {
+        Some((size, _)) => size,
+        None => bail!("Fragment {} isn't the root of a tree", root.cid()),
+    };
+    // Every chunk holds at least a byte and every node at least a link, so a tree linking
+    // the same subtree over and over is refused before it's walked for ever
+    let mut budget = expected.saturating_mul(2).saturating_add(1);
+    let mut data = Vec::new();
+    let mut pending = vec![root.clone()];
+    while let Some(fragment) = pending.pop() {
+        if data.len() as u64 > expected {
+            bail!("Blob is over the {} bytes its root says", expected);
+        }
+        if !fragment.verify() {
+            bail!("Fragment {} doesn't match its data", fragment.cid());
+        }
+        match links(&fragment)? {
+            Some((_, links)) => {
+                // Children are popped in order, so they go on the stack last to first
+                for cid in links.iter().rev() {
+                    if budget == 0 {
+                        bail!(
+                            "Tree under {} has more fragments than its size allows",
+                            root.cid()
+                        );
+                    }
+                    budget -= 1;
+                    let child = get(cid)?;
+                    if child.cid() != cid {
+                        bail!("Asked for {} but got {}", cid, child.cid());
+                    }
+                    pending.push(child);
+                }
+            }
+            None => data.extend_from_slice(fragment.data()),
+        }
+    }
+    if data.len() as u64 != expected {
+        bail!("Blob is {} bytes, its root says {}", data.len(), expected)
+    }
+    Ok(data)
+}
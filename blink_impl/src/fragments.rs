@@ -0,0 +1,267 @@
+use crate::runtime;
+use crate::wire::bounded_bincode;
+use anyhow::{anyhow, bail, Result};
+use sata::{
+    libipld::{
+        cid::Cid,
+        codec::Codec,
+        multihash::{Code, MultihashDigest},
+        serde::{from_ipld, to_ipld},
+        Ipld, IpldCodec,
+    },
+    Sata,
+};
+use serde::{Deserialize, Serialize};
+
+// Multicodec of opaque bytes, fragments don't assume anything about what they hold
+pub const RAW_CODEC: u64 = 0x55;
+
+// Bincode encoded Sata envelope, from the multicodec private use range
+pub const SATA_CODEC: u64 = 0x30_0001;
+
+// How a fragment's CID follows changes to its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CidPolicy {
+    // Every change gives the fragment a new CID, so it can always be fetched by its content
+    ContentAddressed,
+    // The CID of the first version stays and `content_hash` tracks the data
+    Identity,
+}
+
+// Piece of content addressed by the CID of its data.
+// Usually created through the Oracle, which also keeps it in Conflux.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataFragment {
+    cid: String,
+    // CID derived from the current data, equal to `cid` when content addressed
+    content_hash: String,
+    // Kept as an IPLD bytes value rather than a list of numbers
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    // Multicodec telling how to read `data`, part of the CID
+    codec: u64,
+    policy: CidPolicy,
+    // Milliseconds since the unix epoch
+    timestamp: u64,
+    // history[n] turns version n + 1 back into version n, the current version is its length
+    history: Vec<Patch>,
+}
+
+impl DataFragment {
+    pub fn new(data: Vec<u8>, timestamp: u64) -> Self {
+        Self::with_codec(data, RAW_CODEC, timestamp)
+    }
+
+    pub fn with_codec(data: Vec<u8>, codec: u64, timestamp: u64) -> Self {
+        let cid = cid_with_codec(&data, codec);
+        Self {
+            content_hash: cid.clone(),
+            cid,
+            data,
+            codec,
+            policy: CidPolicy::ContentAddressed,
+            timestamp,
+            history: Vec::new(),
+        }
+    }
+
+    // Switches policy, the fragment keeps its current CID either way.
+    pub fn with_policy(mut self, policy: CidPolicy) -> Self {
+        self.policy = policy;
+        if policy == CidPolicy::ContentAddressed {
+            self.cid = self.content_hash.clone();
+        }
+        self
+    }
+
+    // Replaces the data, the CID is re-derived unless the fragment keeps an identity CID.
+    pub fn set(&mut self, data: Vec<u8>, timestamp: u64) {
+        self.history.push(Patch::between(&data, &self.data));
+        self.content_hash = cid_with_codec(&data, self.codec);
+        if self.policy == CidPolicy::ContentAddressed {
+            self.cid = self.content_hash.clone();
+        }
+        self.data = data;
+        self.timestamp = timestamp;
+    }
+
+    pub fn from_sata(sata: &Sata, timestamp: u64) -> Result<Self> {
+        Ok(Self::with_codec(
+            bincode::serialize(sata)?,
+            SATA_CODEC,
+            timestamp,
+        ))
+    }
+
+    pub fn cid(&self) -> &str {
+        &self.cid
+    }
+
+    pub fn content_hash(&self) -> &str {
+        &self.content_hash
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn codec(&self) -> u64 {
+        self.codec
+    }
+
+    pub fn policy(&self) -> CidPolicy {
+        self.policy
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn version(&self) -> u64 {
+        self.history.len() as u64
+    }
+
+    // Data as it was at the given version, None if there is no such version.
+    pub fn get_version(&self, version: u64) -> Option<Vec<u8>> {
+        let version = usize::try_from(version).ok()?;
+        if version > self.history.len() {
+            return None;
+        }
+        self.history[version..]
+            .iter()
+            .rev()
+            .try_fold(self.data.clone(), |data, patch| patch.apply(&data).ok())
+    }
+
+    // Changes turning version `from` into version `to`.
+    pub fn diff(&self, from: u64, to: u64) -> Option<Patch> {
+        Some(Patch::between(
+            &self.get_version(from)?,
+            &self.get_version(to)?,
+        ))
+    }
+
+    // Brings back the data of an earlier version as a new version, the history is kept.
+    pub fn revert(&mut self, version: u64, timestamp: u64) -> Result<()> {
+        match self.get_version(version) {
+            Some(data) => {
+                self.set(data, timestamp);
+                Ok(())
+            }
+            None => bail!("Fragment {} has no version {}", self.cid, version),
+        }
+    }
+
+    // Last writer wins: the later timestamp is kept, ties go to the higher content hash
+    // so every replica settles on the same data. Returns true when the local data changed.
+    pub fn merge(&mut self, remote: &DataFragment) -> Result<bool> {
+        if remote.cid != self.cid {
+            bail!("Can't merge {} into {}", remote.cid, self.cid);
+        }
+        if !remote.verify() {
+            bail!("Data of {} doesn't match its hash", remote.cid);
+        }
+        let newer = (remote.timestamp, &remote.content_hash) > (self.timestamp, &self.content_hash);
+        if newer {
+            self.set(remote.data.clone(), remote.timestamp);
+        }
+        Ok(newer)
+    }
+
+    // Encodes to DAG-CBOR or DAG-JSON, the IPLD codecs Sata uses too.
+    pub fn encode(&self, codec: IpldCodec) -> Result<Vec<u8>> {
+        ensure_document_codec(codec)?;
+        let ipld = to_ipld(self).map_err(|e| anyhow!("{:?}", e))?;
+        codec.encode(&ipld).map_err(|e| anyhow!("{:?}", e))
+    }
+
+    pub fn decode(codec: IpldCodec, bytes: &[u8]) -> Result<Self> {
+        ensure_document_codec(codec)?;
+        let ipld: Ipld = codec.decode(bytes).map_err(|e| anyhow!("{:?}", e))?;
+        from_ipld(ipld).map_err(|e| anyhow!("{:?}", e))
+    }
+
+    // Whether the data hashes to what the fragment claims, nothing was swapped on the way.
+    pub fn verify(&self) -> bool {
+        let derived = cid_with_codec(&self.data, self.codec);
+        derived == self.content_hash && (self.policy == CidPolicy::Identity || derived == self.cid)
+    }
+}
+
+// Raw and DAG-PB can't hold an arbitrary document
+fn ensure_document_codec(codec: IpldCodec) -> Result<()> {
+    match codec {
+        IpldCodec::DagCbor | IpldCodec::DagJson => Ok(()),
+        other => bail!("Fragments can't be encoded as {:?}", other),
+    }
+}
+
+// Byte level change between two versions: whatever lies between
+// the common prefix and suffix gets replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Patch {
+    start: usize,
+    removed: usize,
+    inserted: Vec<u8>,
+}
+
+impl Patch {
+    pub fn between(from: &[u8], to: &[u8]) -> Self {
+        let prefix = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+        let suffix = from[prefix..]
+            .iter()
+            .rev()
+            .zip(to[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Self {
+            start: prefix,
+            removed: from.len() - prefix - suffix,
+            inserted: to[prefix..to.len() - suffix].to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed == 0 && self.inserted.is_empty()
+    }
+
+    pub fn apply(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let end = self.start + self.removed;
+        if end > data.len() {
+            bail!("Patch ends at {} past {} bytes", end, data.len());
+        }
+        let mut patched = Vec::with_capacity(data.len() - self.removed + self.inserted.len());
+        patched.extend_from_slice(&data[..self.start]);
+        patched.extend_from_slice(&self.inserted);
+        patched.extend_from_slice(&data[end..]);
+        Ok(patched)
+    }
+}
+
+impl TryFrom<Sata> for DataFragment {
+    type Error = anyhow::Error;
+
+    fn try_from(sata: Sata) -> Result<Self> {
+        Self::from_sata(&sata, runtime::unix_millis())
+    }
+}
+
+impl TryFrom<&DataFragment> for Sata {
+    type Error = anyhow::Error;
+
+    fn try_from(fragment: &DataFragment) -> Result<Self> {
+        if fragment.codec != SATA_CODEC {
+            bail!("Fragment {} doesn't hold a Sata", fragment.cid);
+        }
+        bounded_bincode(&fragment.data).map_err(|e| anyhow!(e))
+    }
+}
+
+// CIDv1 of raw bytes hashed with SHA2-256, in its default string form.
+pub fn cid_of(data: &[u8]) -> String {
+    cid_with_codec(data, RAW_CODEC)
+}
+
+pub fn cid_with_codec(data: &[u8], codec: u64) -> String {
+    Cid::new_v1(codec, Code::Sha2_256.digest(data)).to_string()
+}
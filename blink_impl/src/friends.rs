@@ -0,0 +1,275 @@
+use crate::{
+    behavior::BlinkBehavior,
+    did_records, did_to_libp2p_pub,
+    event_sink::EventSink,
+    mailbox::HOLD_FOR,
+    peer_to_peer_service::{BlinkCommand, PeerToPeerService},
+    runtime,
+    shared_state::SharedState,
+};
+use anyhow::{anyhow, bail, Result};
+use blink_contract::{Event, Keystore};
+use hmac_sha512::Hash;
+use libp2p::{kad::Quorum, Swarm};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use warp::crypto::DID;
+
+// Keeps friend request inboxes apart from the pairwise topics
+const INBOX_PREFIX: &[u8] = b"/blink/friend-requests/";
+
+// Requests waiting on an answer at once, further ones are refused until some are answered
+pub(crate) const MAX_INCOMING: usize = 100;
+
+// How far ahead of our clock a request can be sent, in milliseconds
+const MAX_CLOCK_SKEW: u64 = 5 * 60 * 1000;
+
+// Topic a DID receives friend requests on. Anyone can derive it, unlike the pairwise topics,
+// and a mailbox can watch it for the DID while it's offline.
+pub(crate) fn inbox_topic(did: &str) -> String {
+    let mut seed = INBOX_PREFIX.to_vec();
+    seed.extend_from_slice(did.as_bytes());
+    base64::encode(Hash::hash(seed))
+}
+
+// Asking to become friends, or saying yes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum FriendIntent {
+    Request,
+    Accept,
+}
+
+// Sent to the inbox of `to`, signed by `from` so nobody can send one in its name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FriendMessage {
+    pub(crate) intent: FriendIntent,
+    pub(crate) from: String,
+    pub(crate) to: String,
+    // Milliseconds since the unix epoch
+    pub(crate) sent_at: u64,
+    signature: Vec<u8>,
+}
+
+impl FriendMessage {
+    pub(crate) fn new(
+        keystore: &dyn Keystore,
+        intent: FriendIntent,
+        from: String,
+        to: String,
+        sent_at: u64,
+    ) -> Result<Self> {
+        let mut message = Self {
+            intent,
+            from,
+            to,
+            sent_at,
+            signature: Vec::new(),
+        };
+        message.signature = keystore.sign(&message.signed_bytes()?)?;
+        Ok(message)
+    }
+
+    // Rejects it unless it's meant for `us` and signed by the DID it's from.
+    pub(crate) fn verify(&self, us: &str) -> Result<()> {
+        if self.to != us {
+            bail!("Friend request from {} is meant for {}", self.from, self.to);
+        }
+        let public_key = did_to_libp2p_pub(&DID::try_from(self.from.clone())?)?;
+        if !public_key.verify(&self.signed_bytes()?, &self.signature) {
+            bail!("Friend request from {} isn't signed by it", self.from);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_sata(&self) -> Result<Sata> {
+        Sata::default()
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, self)
+            .map_err(|e| anyhow!("{:?}", e))
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&(
+            self.intent,
+            &self.from,
+            &self.to,
+            self.sent_at,
+        ))?)
+    }
+}
+
+// Friend requests waiting on an answer either way, and the DIDs that became friends through
+// one. Friends are paired with whether MultiPass knows their identity or not.
+#[derive(Default)]
+pub(crate) struct FriendRequests {
+    // DID asked to the time we asked
+    outgoing: HashMap<String, u64>,
+    incoming: HashMap<String, FriendMessage>,
+    // DID to when its last answered request was sent, anything sent until then is a replay
+    answered: HashMap<String, u64>,
+    friends: HashSet<String>,
+}
+
+impl FriendRequests {
+    pub(crate) fn sent(&mut self, did: String, sent_at: u64) {
+        self.outgoing.insert(did, sent_at);
+    }
+
+    // Returns false for a request already waiting, one from a friend, one older than a mailbox
+    // holds it or sent before the last one we answered, and once too many are waiting.
+    pub(crate) fn received(&mut self, request: FriendMessage, now: u64) -> bool {
+        if self.friends.contains(&request.from) || self.incoming.contains_key(&request.from) {
+            return false;
+        }
+        let stale = request.sent_at.saturating_add(HOLD_FOR) < now
+            || request.sent_at > now.saturating_add(MAX_CLOCK_SKEW);
+        let replayed = self
+            .answered
+            .get(&request.from)
+            .map_or(false, |x| request.sent_at <= *x);
+        if stale || replayed || self.incoming.len() >= MAX_INCOMING {
+            return false;
+        }
+        self.incoming.insert(request.from.clone(), request);
+        true
+    }
+
+    // Returns false unless we asked the DID, an acceptance nobody asked for changes nothing.
+    pub(crate) fn accepted(&mut self, did: &str) -> bool {
+        if self.outgoing.remove(did).is_none() {
+            return false;
+        }
+        self.friends.insert(did.to_string());
+        true
+    }
+
+    // Returns false if the DID didn't ask.
+    pub(crate) fn accept(&mut self, did: &str) -> bool {
+        if !self.answer(did) {
+            return false;
+        }
+        self.friends.insert(did.to_string());
+        true
+    }
+
+    pub(crate) fn decline(&mut self, did: &str) -> bool {
+        self.answer(did)
+    }
+
+    fn answer(&mut self, did: &str) -> bool {
+        match self.incoming.remove(did) {
+            Some(request) => {
+                self.answered.insert(did.to_string(), request.sent_at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_friend(&self, did: &str) -> bool {
+        self.friends.contains(did)
+    }
+
+    // DIDs that asked us, oldest first
+    pub(crate) fn incoming(&self) -> Vec<String> {
+        let mut incoming: Vec<&FriendMessage> = self.incoming.values().collect();
+        incoming.sort_by_key(|x| x.sent_at);
+        incoming.into_iter().map(|x| x.from.clone()).collect()
+    }
+
+    pub(crate) fn outgoing(&self) -> Vec<String> {
+        let mut outgoing: Vec<(&String, &u64)> = self.outgoing.iter().collect();
+        outgoing.sort_by_key(|(_, sent_at)| **sent_at);
+        outgoing.into_iter().map(|(did, _)| did.clone()).collect()
+    }
+}
+
+// Signed and published on the DID's inbox, deferred until a peer watching it is around
+pub(crate) fn send_message(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    keystore: &dyn Keystore,
+    state: &SharedState,
+    intent: FriendIntent,
+    did: String,
+) {
+    let sent_at = state.clock.now_millis();
+    let message = FriendMessage::new(
+        keystore,
+        intent,
+        state.local_did.clone(),
+        did.clone(),
+        sent_at,
+    )
+    .and_then(|x| x.to_sata());
+    match message {
+        Ok(sata) => {
+            let inbox = inbox_topic(&did);
+            PeerToPeerService::publish_command(swarm, logger, state, inbox, sata, None);
+        }
+        Err(e) => logger.event_occurred(Event::FriendRequestError(e.to_string())),
+    }
+}
+
+// Pairs right away when the friend is connected, otherwise once it's found on the DHT
+pub(crate) fn befriend(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    keystore: &dyn Keystore,
+    state: &SharedState,
+    did: String,
+) {
+    let their_public = match DID::try_from(did.clone()) {
+        Ok(their_public) => their_public,
+        Err(_) => {
+            logger.event_occurred(Event::ConvertKeyError);
+            return;
+        }
+    };
+    // Devices have transport keys of their own, the PeerId is known once one identified itself
+    let connected = state.peers.identified.read().peer_of(&did);
+    if let Some(peer_id) = connected {
+        PeerToPeerService::pair(swarm, logger, keystore, state, peer_id, their_public);
+    } else {
+        swarm
+            .behaviour_mut()
+            .kademlia
+            .get_record(did_records::key_of(&did), Quorum::One);
+    }
+}
+
+// A request or acceptance that reached our inbox, live or replayed from a mailbox
+pub(crate) fn message_received(logger: EventSink, state: &SharedState, info: Sata) {
+    let message = match info.decode::<FriendMessage>() {
+        Ok(message) => message,
+        Err(_) => {
+            logger.event_occurred(Event::ErrorDeserializingData);
+            return;
+        }
+    };
+    if let Err(e) = message.verify(&state.local_did) {
+        logger.event_occurred(Event::FriendRequestError(e.to_string()));
+        return;
+    }
+    if state.messaging.moderation.read().is_blocked(&message.from) {
+        return;
+    }
+    let from = message.from.clone();
+    match message.intent {
+        FriendIntent::Request => {
+            let now = state.clock.now_millis();
+            if state.peers.friends.write().received(message, now) {
+                logger.event_occurred(Event::FriendRequestReceived(from));
+            }
+        }
+        FriendIntent::Accept => {
+            if state.peers.friends.write().accepted(&from) {
+                logger.event_occurred(Event::FriendRequestAccepted(from.clone()));
+                let commands = state.commands.clone();
+                runtime::spawn(async move {
+                    let _ = commands.send(BlinkCommand::Befriend(from)).await;
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{streams::StreamId, unique_id};
+use blink_contract::{StreamKind, VideoCaps};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub type GroupCallId = u64;
+
+// Marks a stream as part of a group call.
+// Streams a member sends to the forwarder carry no origin, the ones the forwarder fans out name the participant they come from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GroupTag {
+    pub(crate) group: GroupCallId,
+    pub(crate) origin: Option<String>,
+}
+
+// A stream the forwarder has to open towards a member to relay another participant's media.
+pub(crate) struct Downlink {
+    pub(crate) id: StreamId,
+    pub(crate) peer: PeerId,
+    pub(crate) uplink: StreamId,
+    // Member whose media the downlink relays
+    pub(crate) origin: PeerId,
+    pub(crate) kind: StreamKind,
+    pub(crate) codec: String,
+    pub(crate) caps: Option<VideoCaps>,
+}
+
+// A stream a member sends to the forwarder.
+pub(crate) struct Uplink {
+    peer: PeerId,
+    kind: StreamKind,
+    codec: String,
+    caps: Option<VideoCaps>,
+    downlinks: Vec<(PeerId, StreamId)>,
+}
+
+impl Uplink {
+    pub(crate) fn new(
+        peer: PeerId,
+        kind: StreamKind,
+        codec: String,
+        caps: Option<VideoCaps>,
+    ) -> Self {
+        Self {
+            peer,
+            kind,
+            codec,
+            caps,
+            downlinks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HostedGroup {
+    uplinks: HashMap<StreamId, Uplink>,
+}
+
+impl HostedGroup {
+    fn members(&self) -> Vec<PeerId> {
+        let mut members: Vec<PeerId> = self.uplinks.values().map(|x| x.peer).collect();
+        members.sort();
+        members.dedup();
+        members
+    }
+}
+
+// Group calls we forward media for, and the ones we joined as a member.
+#[derive(Default)]
+pub(crate) struct GroupRegistry {
+    hosted: HashMap<GroupCallId, HostedGroup>,
+    uplink_group: HashMap<StreamId, GroupCallId>,
+    downlink_uplink: HashMap<StreamId, (GroupCallId, StreamId)>,
+    joined: HashMap<GroupCallId, Vec<StreamId>>,
+}
+
+impl GroupRegistry {
+    pub(crate) fn host(&mut self, group: GroupCallId) {
+        self.hosted.entry(group).or_default();
+    }
+
+    pub(crate) fn is_hosting(&self, group: GroupCallId) -> bool {
+        self.hosted.contains_key(&group)
+    }
+
+    // Registers a member's stream and returns the downlinks needed so everyone hears everyone.
+    // The forwarder itself gets uplinks directly, so `local` never receives a downlink.
+    pub(crate) fn add_uplink(
+        &mut self,
+        group: GroupCallId,
+        id: StreamId,
+        local: PeerId,
+        uplink: Uplink,
+    ) -> Vec<Downlink> {
+        let peer = uplink.peer;
+        let hosted = match self.hosted.get_mut(&group) {
+            Some(hosted) => hosted,
+            None => return Vec::new(),
+        };
+
+        let mut downlinks = Vec::new();
+        for member in hosted.members() {
+            if member != peer && member != local {
+                downlinks.push(Downlink {
+                    id: unique_id(),
+                    peer: member,
+                    uplink: id,
+                    origin: peer,
+                    kind: uplink.kind,
+                    codec: uplink.codec.clone(),
+                    caps: uplink.caps,
+                });
+            }
+        }
+        if peer != local {
+            for (other_id, other) in hosted.uplinks.iter() {
+                let relayed = other.downlinks.iter().any(|(x, _)| *x == peer);
+                if other.peer != peer && !relayed {
+                    downlinks.push(Downlink {
+                        id: unique_id(),
+                        peer,
+                        uplink: *other_id,
+                        origin: other.peer,
+                        kind: other.kind,
+                        codec: other.codec.clone(),
+                        caps: other.caps,
+                    });
+                }
+            }
+        }
+
+        hosted.uplinks.insert(id, uplink);
+        self.uplink_group.insert(id, group);
+        for downlink in &downlinks {
+            if let Some(uplink) = hosted.uplinks.get_mut(&downlink.uplink) {
+                uplink.downlinks.push((downlink.peer, downlink.id));
+            }
+            self.downlink_uplink
+                .insert(downlink.id, (group, downlink.uplink));
+        }
+        downlinks
+    }
+
+    pub(crate) fn group_of_uplink(&self, id: StreamId) -> Option<GroupCallId> {
+        self.uplink_group.get(&id).copied()
+    }
+
+    pub(crate) fn downlinks_of(&self, uplink: StreamId) -> Vec<(PeerId, StreamId)> {
+        self.uplink_group
+            .get(&uplink)
+            .and_then(|group| self.hosted.get(group))
+            .and_then(|hosted| hosted.uplinks.get(&uplink))
+            .map_or(Vec::new(), |x| x.downlinks.clone())
+    }
+
+    // Uplink a downlink relays, with the peer that sends it.
+    pub(crate) fn source_of(&self, downlink: StreamId) -> Option<(StreamId, PeerId)> {
+        let (group, uplink) = self.downlink_uplink.get(&downlink)?;
+        let peer = self.hosted.get(group)?.uplinks.get(uplink)?.peer;
+        Some((*uplink, peer))
+    }
+
+    // Forgets a member's stream, returns its group and the downlinks to close.
+    // A member without streams left is out of the call, so what was relayed to it goes too.
+    pub(crate) fn remove_uplink(
+        &mut self,
+        id: StreamId,
+    ) -> Option<(GroupCallId, Vec<(PeerId, StreamId)>)> {
+        let group = self.uplink_group.remove(&id)?;
+        let hosted = self.hosted.get_mut(&group)?;
+        let uplink = hosted.uplinks.remove(&id)?;
+        let mut closed = uplink.downlinks;
+
+        if !hosted.members().contains(&uplink.peer) {
+            for other in hosted.uplinks.values_mut() {
+                let (to_member, kept): (Vec<_>, Vec<_>) = other
+                    .downlinks
+                    .drain(..)
+                    .partition(|(peer, _)| *peer == uplink.peer);
+                other.downlinks = kept;
+                closed.extend(to_member);
+            }
+        }
+        for (_, downlink) in &closed {
+            self.downlink_uplink.remove(downlink);
+        }
+        Some((group, closed))
+    }
+
+    pub(crate) fn remove_downlink(&mut self, id: StreamId) {
+        if let Some((group, uplink)) = self.downlink_uplink.remove(&id) {
+            if let Some(uplink) = self
+                .hosted
+                .get_mut(&group)
+                .and_then(|x| x.uplinks.get_mut(&uplink))
+            {
+                uplink.downlinks.retain(|(_, x)| *x != id);
+            }
+        }
+    }
+
+    pub(crate) fn uplinks_of(&self, group: GroupCallId, peer: &PeerId) -> Vec<StreamId> {
+        self.hosted.get(&group).map_or(Vec::new(), |hosted| {
+            hosted
+                .uplinks
+                .iter()
+                .filter(|(_, x)| x.peer == *peer)
+                .map(|(id, _)| *id)
+                .collect()
+        })
+    }
+
+    pub(crate) fn members(&self, group: GroupCallId) -> Vec<PeerId> {
+        self.hosted
+            .get(&group)
+            .map_or(Vec::new(), |hosted| hosted.members())
+    }
+
+    // Stops forwarding, returns every stream of the call with the peer on the other end.
+    pub(crate) fn end(&mut self, group: GroupCallId) -> Vec<(PeerId, StreamId)> {
+        let hosted = match self.hosted.remove(&group) {
+            Some(hosted) => hosted,
+            None => return Vec::new(),
+        };
+        let mut streams = Vec::new();
+        for (id, uplink) in hosted.uplinks {
+            self.uplink_group.remove(&id);
+            for (peer, downlink) in uplink.downlinks {
+                self.downlink_uplink.remove(&downlink);
+                streams.push((peer, downlink));
+            }
+            streams.push((uplink.peer, id));
+        }
+        streams
+    }
+
+    pub(crate) fn joined(&mut self, group: GroupCallId, uplink: StreamId) {
+        self.joined.entry(group).or_default().push(uplink);
+    }
+
+    pub(crate) fn has_joined(&self, group: GroupCallId) -> bool {
+        self.joined.contains_key(&group)
+    }
+
+    pub(crate) fn leave(&mut self, group: GroupCallId) -> Vec<StreamId> {
+        self.joined.remove(&group).unwrap_or_default()
+    }
+}
@@ -0,0 +1,57 @@
+use libp2p::identify::IdentifyInfo;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Advertisement {
+    // First identify info since the peer connected
+    New,
+    // Pushed, or periodically sent, with other addresses or protocols than before
+    Changed,
+    Same,
+}
+
+// What each connected peer last told us about itself through identify.
+#[derive(Default)]
+pub(crate) struct IdentifiedPeers {
+    peers: HashMap<PeerId, (Vec<Multiaddr>, Vec<String>)>,
+    // DIDs proven by the peers' device certificates
+    dids: HashMap<PeerId, String>,
+}
+
+impl IdentifiedPeers {
+    pub(crate) fn advertised(&mut self, peer: PeerId, info: &IdentifyInfo) -> Advertisement {
+        let mut listen_addrs = info.listen_addrs.clone();
+        listen_addrs.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        let mut protocols = info.protocols.clone();
+        protocols.sort();
+        let advertised = (listen_addrs, protocols);
+        match self.peers.insert(peer, advertised.clone()) {
+            None => Advertisement::New,
+            Some(previous) if previous != advertised => Advertisement::Changed,
+            Some(_) => Advertisement::Same,
+        }
+    }
+
+    pub(crate) fn certified(&mut self, peer: PeerId, did: String) {
+        self.dids.insert(peer, did);
+    }
+
+    // The DID the peer's device certificate proved, None until identify got through.
+    pub(crate) fn did_of(&self, peer: &PeerId) -> Option<String> {
+        self.dids.get(peer).cloned()
+    }
+
+    // A connected device of the DID, any of them when it's connected from several.
+    pub(crate) fn peer_of(&self, did: &str) -> Option<PeerId> {
+        self.dids
+            .iter()
+            .find(|(_, x)| x.as_str() == did)
+            .map(|(peer, _)| *peer)
+    }
+
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+        self.dids.remove(peer);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::did_to_libp2p_pub;
+use anyhow::{anyhow, bail, Result};
+use blink_contract::Keystore;
+use hmac_sha512::Hash;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use warp::crypto::DID;
+
+// Keeps profile topics apart from friend request inboxes and the pairwise topics
+const TOPIC_PREFIX: &[u8] = b"/blink/identity-profile/";
+
+// Topic a DID publishes its profile on, followed by every peer paired with it.
+pub(crate) fn profile_topic(did: &str) -> String {
+    let mut seed = TOPIC_PREFIX.to_vec();
+    seed.extend_from_slice(did.as_bytes());
+    base64::encode(Hash::hash(seed))
+}
+
+// What contacts show for a DID, as it last published it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityProfile {
+    pub username: String,
+    pub status: Option<String>,
+    // Root CID of the picture's blob, fetched through Conflux
+    pub avatar: Option<String>,
+    // Milliseconds since the unix epoch, a profile only replaces an older one
+    pub published_at: u64,
+}
+
+// A profile signed by its DID, so nodes relaying it can't change it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SignedProfile {
+    pub(crate) did: String,
+    pub(crate) profile: IdentityProfile,
+    signature: Vec<u8>,
+}
+
+impl SignedProfile {
+    pub(crate) fn new(
+        keystore: &dyn Keystore,
+        did: String,
+        profile: IdentityProfile,
+    ) -> Result<Self> {
+        let mut signed = Self {
+            did,
+            profile,
+            signature: Vec::new(),
+        };
+        signed.signature = keystore.sign(&signed.signed_bytes()?)?;
+        Ok(signed)
+    }
+
+    pub(crate) fn verify(&self) -> Result<()> {
+        let public_key = did_to_libp2p_pub(&DID::try_from(self.did.clone())?)?;
+        if !public_key.verify(&self.signed_bytes()?, &self.signature) {
+            bail!("Profile of {} isn't signed by it", self.did);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_sata(&self) -> Result<Sata> {
+        Sata::default()
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, self)
+            .map_err(|e| anyhow!("{:?}", e))
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&(&self.did, &self.profile))?)
+    }
+}
+
+// Profiles of the DIDs we're paired with, by DID.
+#[derive(Default)]
+pub(crate) struct ProfileCache {
+    // Profile topic of every DID followed, to the DID
+    followed: HashMap<String, String>,
+    profiles: HashMap<String, IdentityProfile>,
+}
+
+impl ProfileCache {
+    // Returns the topic to subscribe to, None if the DID is already followed.
+    pub(crate) fn follow(&mut self, did: &str) -> Option<String> {
+        let topic = profile_topic(did);
+        if self.followed.contains_key(&topic) {
+            return None;
+        }
+        self.followed.insert(topic.clone(), did.to_string());
+        Some(topic)
+    }
+
+    pub(crate) fn did_of_topic(&self, topic: &str) -> Option<&String> {
+        self.followed.get(topic)
+    }
+
+    // Keeps a profile received on `topic`, returns false unless it's the followed DID's own
+    // and newer than the one kept.
+    pub(crate) fn update(&mut self, topic: &str, signed: SignedProfile) -> bool {
+        if self.followed.get(topic) != Some(&signed.did) || signed.verify().is_err() {
+            return false;
+        }
+        let newer = self
+            .profiles
+            .get(&signed.did)
+            .map_or(true, |x| x.published_at < signed.profile.published_at);
+        if newer {
+            self.profiles.insert(signed.did, signed.profile);
+        }
+        newer
+    }
+
+    pub(crate) fn profile(&self, did: &str) -> Option<IdentityProfile> {
+        self.profiles.get(did).cloned()
+    }
+}
@@ -0,0 +1,95 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// When connections nothing goes through are closed. Ping keeps every connection open
+// otherwise, so this is the only thing that closes a quiet one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdlePolicy {
+    // Closed after this long without messages or an open stream, never when left out
+    pub timeout_secs: Option<u64>,
+    // Peers we paired with stay connected however quiet they are
+    pub keep_paired: bool,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            timeout_secs: Some(5 * 60),
+            keep_paired: true,
+        }
+    }
+}
+
+// Last time something was exchanged with each connected peer.
+#[derive(Default)]
+pub(crate) struct IdleTracker {
+    policy: IdlePolicy,
+    last_active: HashMap<PeerId, u64>,
+    // DID to whether it's kept alive, instead of what the policy says
+    overrides: HashMap<String, bool>,
+    // Bootstrap, relay and rendezvous nodes from the config
+    pinned: HashSet<PeerId>,
+}
+
+impl IdleTracker {
+    pub(crate) fn policy(&self) -> IdlePolicy {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: IdlePolicy) {
+        self.policy = policy;
+    }
+
+    pub(crate) fn set_override(&mut self, did: String, keep_alive: Option<bool>) {
+        match keep_alive {
+            Some(keep_alive) => self.overrides.insert(did, keep_alive),
+            None => self.overrides.remove(&did),
+        };
+    }
+
+    pub(crate) fn pin(&mut self, peer: PeerId) {
+        self.pinned.insert(peer);
+    }
+
+    pub(crate) fn unpin(&mut self, peer: &PeerId) {
+        self.pinned.remove(peer);
+    }
+
+    pub(crate) fn active(&mut self, peer: &PeerId, now: u64) {
+        self.last_active.insert(*peer, now);
+    }
+
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        self.last_active.remove(peer);
+    }
+
+    // Peers quiet for longer than the timeout that aren't kept alive and have nothing open.
+    // `paired` maps the DIDs we paired with to their PeerId, overrides only apply to those.
+    pub(crate) fn idle(
+        &self,
+        now: u64,
+        paired: &HashMap<String, PeerId>,
+        busy: impl Fn(&PeerId) -> bool,
+    ) -> Vec<PeerId> {
+        let timeout = match self.policy.timeout_secs {
+            Some(timeout) => timeout * 1000,
+            None => return Vec::new(),
+        };
+        self.last_active
+            .iter()
+            .filter(|(_, last_active)| now.saturating_sub(**last_active) >= timeout)
+            .map(|(peer, _)| *peer)
+            .filter(|peer| !self.pinned.contains(peer))
+            .filter(|peer| {
+                let did = paired.iter().find(|(_, x)| *x == peer).map(|(did, _)| did);
+                let keep_alive = match did.and_then(|did| self.overrides.get(did)) {
+                    Some(keep_alive) => *keep_alive,
+                    None => self.policy.keep_paired && did.is_some(),
+                };
+                !keep_alive && !busy(peer)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{did_keypair_to_libp2p_keypair, libp2p_pub_to_did};
+use anyhow::Result;
+use blink_contract::{BlinkError, Keystore};
+use did_key::{DIDKey, Ed25519KeyPair, Generate, KeyMaterial, ECDH};
+use libp2p::identity::Keypair;
+use std::sync::Arc;
+use warp::{crypto::DID, error::Error};
+
+// Keystore holding the DID private key in process memory.
+// Use a custom `Keystore` to keep the key in an OS keychain, HSM or remote signer.
+pub struct InMemoryKeystore {
+    did: Arc<DID>,
+    key_pair: Keypair,
+}
+
+impl InMemoryKeystore {
+    pub fn new(did: Arc<DID>) -> Result<Self> {
+        let key_pair = did_keypair_to_libp2p_keypair((*did).as_ref())?;
+        Ok(Self { did, key_pair })
+    }
+}
+
+impl Keystore for InMemoryKeystore {
+    fn public_key(&self) -> Result<DID, BlinkError> {
+        libp2p_pub_to_did(&self.key_pair.public()).map_err(|e| BlinkError::Key(e.to_string()))
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, BlinkError> {
+        self.key_pair
+            .sign(data)
+            .map_err(|e| BlinkError::Key(e.to_string()))
+    }
+
+    fn key_exchange(&self, public_key: &DID) -> Result<Vec<u8>, BlinkError> {
+        let exchange = match ((*self.did).as_ref(), public_key.as_ref()) {
+            (DIDKey::Ed25519(private), DIDKey::Ed25519(public)) => {
+                let private_key_pair =
+                    Ed25519KeyPair::from_secret_key(&private.private_key_bytes()).get_x25519();
+                let public_key_pair =
+                    Ed25519KeyPair::from_public_key(&public.public_key_bytes()).get_x25519();
+                private_key_pair.key_exchange(&public_key_pair)
+            }
+            (DIDKey::Secp256k1(private), DIDKey::Secp256k1(public)) => private.key_exchange(public),
+            (DIDKey::P256(private), DIDKey::P256(public)) => private.key_exchange(public),
+            _ => return Err(BlinkError::Key(Error::PublicKeyInvalid.to_string())),
+        };
+        Ok(exchange)
+    }
+}
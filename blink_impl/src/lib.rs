@@ -1,33 +1,397 @@
+mod auto_pairing;
+mod bandwidth;
+mod batching;
 mod behavior;
-pub mod peer_to_peer_service;
+mod cache_policy;
+mod cache_writer;
+mod channels;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod clock;
+mod config;
+mod conflux;
+mod congestion;
+// Uploads and downloads through tokio's file system, native only
+#[cfg(all(feature = "constellation", not(target_arch = "wasm32")))]
+mod constellation;
+// Listens on TCP and Unix sockets, native only
+#[cfg(all(feature = "control", not(target_arch = "wasm32")))]
+mod control;
+mod conversations;
+mod delivery;
+mod device_key;
+mod device_sync;
+mod dht;
+mod diagnostics;
+mod did_records;
+mod event_category;
+// Forwards over TCP and Unix sockets, which browsers don't have
+#[cfg(not(target_arch = "wasm32"))]
+mod event_forwarder;
+mod event_history;
+mod event_sink;
+mod expiry;
+mod extensions;
+mod external_addresses;
+mod file_transfer;
+mod fragment_store;
+mod fragment_tree;
+mod fragments;
+mod friends;
+mod group_calls;
+mod identified_peers;
+mod identity_profile;
+mod idle;
+mod keystore;
+mod live_fragment;
+mod mailbox;
+mod membership;
+mod message_changes;
+mod message_kinds;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod moderation;
+mod mutes;
+mod node;
+mod oracle;
+mod pause;
+mod peer_info;
+mod peer_to_peer_service;
+mod presence;
+mod profile;
+mod protocol;
+mod providers;
+mod publishing;
+mod rate_limit;
+#[cfg(feature = "raygun")]
+mod raygun;
+mod readiness;
+mod recording;
+mod relay_server;
+mod rendezvous;
+mod runtime;
+mod scoring;
+mod shared_state;
+mod signaling;
+mod snapshot;
+mod streams;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "testkit")]
+mod testkit;
+mod transactions;
+mod transport;
+mod validators;
+mod version;
+mod wal;
+mod wire;
+
+// The stable surface, modules stay private so they can be reorganised freely
+pub use bandwidth::{BandwidthCaps, BandwidthStats, Traffic};
+pub use batching::BatchSettings;
+pub use cache_policy::{CachePolicy, CacheScope};
+pub use channels::ChannelTopic;
+#[cfg(feature = "chaos")]
+pub use chaos::ChaosHandle;
+pub use clock::{SystemClock, VirtualClock};
+pub use config::{BlinkConfig, CacheSettings, ConfigDelta, GossipsubTuning, KademliaSettings};
+pub use conflux::{
+    CollisionPolicy, Conflux, ConfluxError, FragmentUpdate, FragmentWatch, GcLimits,
+};
+pub use congestion::LinkQuality;
+#[cfg(all(feature = "constellation", not(target_arch = "wasm32")))]
+pub use constellation::BlinkConstellation;
+#[cfg(all(feature = "control", not(target_arch = "wasm32")))]
+pub use control::{serve_control, ControlEndpoint};
+pub use conversations::StoredMessage;
+pub use delivery::{DeliverySettings, DeliveryStrategy};
+pub use diagnostics::{BenchmarkId, BenchmarkOptions, BenchmarkReport};
+pub use event_category::EventCategory;
+#[cfg(not(target_arch = "wasm32"))]
+pub use event_forwarder::{sign as sign_forwarded_batch, EventForwarder, ForwardTarget};
+pub use event_history::Verbosity;
+pub use file_transfer::TransferId;
+pub use fragment_store::{DiskFragmentStore, FragmentStore, MemoryFragmentStore, StoreKey};
+pub use fragment_tree::{reassemble, FragmentTree, DEFAULT_CHUNK_SIZE, TREE_CODEC};
+pub use fragments::{
+    cid_of, cid_with_codec, CidPolicy, DataFragment, Patch, RAW_CODEC, SATA_CODEC,
+};
+pub use group_calls::GroupCallId;
+pub use identity_profile::IdentityProfile;
+pub use idle::IdlePolicy;
+pub use keystore::InMemoryKeystore;
+pub use live_fragment::LiveFragment;
+pub use message_changes::apply_changes;
+pub use message_kinds::{
+    BlinkMessage, Typed, TypedReceiver, CHAT_KIND, DELETE_KIND, EDIT_KIND, FRAGMENT_ANNOUNCE_KIND,
+    SIGNAL_KIND,
+};
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsSnapshot;
+pub use mutes::Conversation;
+pub use node::BlinkNode;
+pub use oracle::Oracle;
+pub use pause::PauseMode;
+pub use peer_info::PeerInfo;
+pub use peer_to_peer_service::{MessageContent, PeerToPeerService, TopicName};
+pub use publishing::{SendError, SendReport};
+pub use rate_limit::{RateLimit, RateLimits};
+#[cfg(feature = "raygun")]
+pub use raygun::BlinkRayGun;
+pub use recording::{
+    recorded_frames, Direction, RecordedFrame, RecordedPayload, RecordingManifest, RecordingOptions,
+};
+pub use relay_server::{RelayServerSettings, RelayStats};
+pub use scoring::ScoreSettings;
+pub use signaling::CallId;
+pub use streams::{
+    CallHandle, Region, ScreenFrame, ScreenMetadata, StreamId, VideoFrame, VideoStream,
+};
+#[cfg(feature = "testkit")]
+pub use testkit::{Cluster, EventLog, MemoryCache, TestNode, Topology, TrustingMultiPass};
+pub use transactions::TransactionId;
+pub use version::PROTOCOL_VERSION;
+pub use wire::{
+    open_envelope, open_envelopes, parse_envelope, seal_envelope, BincodeWireCodec,
+    DagCborWireCodec, WireCodec, BATCH_CODEC, BINCODE_CODEC, DAG_CBOR_CODEC, SIGNED_CODEC,
+};
 
+#[cfg(test)]
+mod when_accounting_bandwidth;
+#[cfg(test)]
+mod when_adapting_bitrate;
+#[cfg(test)]
+mod when_advertising_external_addresses;
+#[cfg(test)]
+mod when_batching_messages;
+#[cfg(test)]
+mod when_befriending_peers;
+#[cfg(test)]
+mod when_benchmarking_peers;
+#[cfg(test)]
+mod when_caching_messages;
+#[cfg(test)]
+mod when_certifying_device_keys;
+#[cfg(test)]
+mod when_closing_idle_connections;
+#[cfg(all(test, feature = "metrics"))]
+mod when_collecting_metrics;
+#[cfg(test)]
+mod when_configuring_the_service;
+#[cfg(all(test, feature = "control"))]
+mod when_controlling_the_node;
+#[cfg(test)]
+mod when_converting_keys;
+#[cfg(test)]
+mod when_decoding_message_kinds;
+#[cfg(test)]
+mod when_editing_messages;
+#[cfg(test)]
+mod when_encoding_wire_messages;
+#[cfg(test)]
+mod when_exchanging_fragments;
+#[cfg(test)]
+mod when_expiring_messages;
+#[cfg(test)]
+mod when_feeding_malformed_payloads;
+#[cfg(test)]
+mod when_forwarding_events;
+#[cfg(test)]
+mod when_forwarding_group_calls;
+#[cfg(test)]
+mod when_holding_messages_at_a_mailbox;
+#[cfg(test)]
+mod when_hosting_identities;
+#[cfg(all(test, feature = "chaos"))]
+mod when_injecting_faults;
+#[cfg(test)]
+mod when_keeping_fragments_live;
+#[cfg(test)]
+mod when_moderating_peers;
+#[cfg(test)]
+mod when_muting_conversations;
+#[cfg(test)]
+mod when_negotiating_protocol_versions;
+#[cfg(test)]
+mod when_opening_channels;
+#[cfg(test)]
+mod when_pairing_on_send;
+#[cfg(test)]
+mod when_pausing_networking;
+#[cfg(test)]
+mod when_pinging_peers;
+#[cfg(test)]
+mod when_publishing_messages;
+#[cfg(test)]
+mod when_publishing_profiles;
+#[cfg(test)]
+mod when_querying_history;
+#[cfg(test)]
+mod when_querying_the_dht;
+#[cfg(test)]
+mod when_rate_limiting_peers;
+#[cfg(test)]
+mod when_receiving_identify_updates;
+#[cfg(test)]
+mod when_recording_streams;
+#[cfg(test)]
+mod when_relaying_for_peers;
+#[cfg(test)]
+mod when_replaying_events;
+#[cfg(test)]
+mod when_resolving_did_records;
+#[cfg(test)]
+mod when_resolving_rendezvous_namespaces;
+#[cfg(test)]
+mod when_running_a_network;
+#[cfg(test)]
+mod when_scoring_peers;
+#[cfg(test)]
+mod when_signaling_calls;
+#[cfg(test)]
+mod when_splitting_blobs;
+#[cfg(test)]
+mod when_storing_fragments;
+#[cfg(test)]
+mod when_streaming_calls;
+#[cfg(test)]
+mod when_streaming_video;
+#[cfg(test)]
+mod when_syncing_devices;
+#[cfg(all(test, feature = "constellation"))]
+mod when_syncing_files;
+#[cfg(test)]
+mod when_taking_network_snapshots;
+#[cfg(test)]
+mod when_tracking_presence;
+#[cfg(test)]
+mod when_tracking_providers;
+#[cfg(test)]
+mod when_tracking_topic_members;
+#[cfg(test)]
+mod when_transferring_files;
 #[cfg(test)]
 mod when_using_peer_to_peer_service;
+#[cfg(all(test, feature = "raygun"))]
+mod when_using_raygun;
+#[cfg(test)]
+mod when_using_the_oracle;
+#[cfg(all(test, feature = "testkit"))]
+mod when_using_the_testkit;
+#[cfg(test)]
+mod when_using_virtual_clock;
+#[cfg(test)]
+mod when_using_write_ahead_log;
+#[cfg(test)]
+mod when_validating_messages;
+#[cfg(test)]
+mod when_waiting_for_readiness;
 
 extern crate core;
 
 use anyhow::Result;
-use did_key::{DIDKey, Ed25519KeyPair, KeyMaterial};
-use libp2p::identity::Keypair::Ed25519;
-use std::{sync::atomic::AtomicBool, sync::Arc};
+use did_key::{DIDKey, Ed25519KeyPair, Generate, KeyMaterial, P256KeyPair, Secp256k1KeyPair};
+use hmac_sha512::Hash;
+use libp2p::identity::{ecdsa, ed25519, secp256k1, Keypair, PublicKey};
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::Arc,
+};
+use tokio::sync::Notify;
 
 use warp::{crypto::DID, error::Error};
 
-pub type CancellationToken = Arc<AtomicBool>;
+// Stops the service's event loop once cancelled, clones share the same state.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    // Completes once `cancel` was called, right away if it already was.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before checking the flag so a cancel in between isn't missed
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Ids picked locally that have to be unlikely to collide with other peers' or a previous run's
+pub(crate) fn unique_id() -> u64 {
+    let nanos = runtime::unix_nanos();
+    let counter = ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let hash = Hash::hash([nanos.to_le_bytes().as_slice(), &counter.to_le_bytes()].concat());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(bytes)
+}
 
-fn did_keypair_to_libp2p_keypair(key_pair: &DIDKey) -> Result<libp2p::identity::Keypair> {
+fn did_keypair_to_libp2p_keypair(key_pair: &DIDKey) -> Result<Keypair> {
     let private = key_pair.private_key_bytes();
-    let secret_key = libp2p::identity::ed25519::SecretKey::from_bytes(private)?;
-    Ok(Ed25519(secret_key.into()))
+    let key_pair = match key_pair {
+        DIDKey::Ed25519(_) => {
+            let secret_key = ed25519::SecretKey::from_bytes(private)?;
+            Keypair::Ed25519(secret_key.into())
+        }
+        DIDKey::Secp256k1(_) => {
+            let secret_key = secp256k1::SecretKey::from_bytes(private)?;
+            Keypair::Secp256k1(secret_key.into())
+        }
+        DIDKey::P256(_) => {
+            let secret_key = ecdsa::SecretKey::from_bytes(&private)?;
+            Keypair::Ecdsa(secret_key.into())
+        }
+        _ => anyhow::bail!(Error::PrivateKeyInvalid),
+    };
+    Ok(key_pair)
 }
 
-fn libp2p_pub_to_did(public_key: &libp2p::identity::PublicKey) -> Result<DID> {
-    let pk = match public_key {
+// The DID of a libp2p public key, for ed25519, secp256k1 and P-256 keys.
+pub fn libp2p_pub_to_did(public_key: &libp2p::identity::PublicKey) -> Result<DID> {
+    let did: DIDKey = match public_key {
         libp2p::identity::PublicKey::Ed25519(pk) => {
-            let did: DIDKey = Ed25519KeyPair::from_public_key(&pk.encode()).into();
-            did.try_into()?
+            Ed25519KeyPair::from_public_key(&pk.encode()).into()
+        }
+        libp2p::identity::PublicKey::Secp256k1(pk) => {
+            Secp256k1KeyPair::from_public_key(&pk.encode()).into()
         }
+        libp2p::identity::PublicKey::Ecdsa(pk) => {
+            P256KeyPair::from_public_key(&pk.to_bytes()).into()
+        }
+        _ => anyhow::bail!(Error::PublicKeyInvalid),
+    };
+    Ok(did.try_into()?)
+}
+
+// The libp2p public key of a DID, the other way around from `libp2p_pub_to_did`.
+pub fn did_to_libp2p_pub(did: &DID) -> Result<PublicKey> {
+    let bytes = did.as_ref().public_key_bytes();
+    let public_key = match did.as_ref() {
+        DIDKey::Ed25519(_) => PublicKey::Ed25519(ed25519::PublicKey::decode(&bytes)?),
+        DIDKey::Secp256k1(_) => PublicKey::Secp256k1(secp256k1::PublicKey::decode(&bytes)?),
+        DIDKey::P256(_) => PublicKey::Ecdsa(ecdsa::PublicKey::from_bytes(&bytes)?),
         _ => anyhow::bail!(Error::PublicKeyInvalid),
     };
-    Ok(pk)
+    Ok(public_key)
 }
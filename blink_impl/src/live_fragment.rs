@@ -0,0 +1,103 @@
+use crate::{
+    conflux::{Conflux, ConfluxError, FragmentUpdate, FragmentWatch},
+    fragments::DataFragment,
+    runtime,
+};
+use libp2p::futures::{channel::mpsc, future::AbortHandle, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+const WRITE_QUEUE_SIZE: usize = 16;
+
+// Fragment kept in step with its replicas while alive. Its versions, local or remote,
+// come out as a stream and whatever goes into its sink becomes the next version.
+pub struct LiveFragment {
+    cid: String,
+    conflux: Conflux,
+    updates: Option<FragmentWatch>,
+    writes: Option<(mpsc::Sender<Vec<u8>>, AbortHandle)>,
+}
+
+impl LiveFragment {
+    pub(crate) fn new(cid: String, conflux: Conflux) -> Self {
+        let mut live = Self {
+            cid,
+            conflux,
+            updates: None,
+            writes: None,
+        };
+        live.wake();
+        live
+    }
+
+    pub fn cid(&self) -> &str {
+        &self.cid
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.updates.is_some()
+    }
+
+    // Follows the fragment again. Sinks taken before it was killed stay closed.
+    pub fn wake(&mut self) {
+        if self.is_alive() {
+            return;
+        }
+        self.updates = Some(self.conflux.watch(&self.cid));
+        let (sender, mut receiver) = mpsc::channel(WRITE_QUEUE_SIZE);
+        let conflux = self.conflux.clone();
+        let cid = self.cid.clone();
+        let writer = runtime::spawn_abortable(async move {
+            while let Some(data) = receiver.next().await {
+                // Nobody to report to from a sink, `write` is there for callers that care
+                let _ = conflux.set(&cid, data).await;
+            }
+        });
+        self.writes = Some((sender, writer));
+        self.conflux.liveness_changed(&self.cid, true);
+    }
+
+    // Ends the stream and drops the writes still queued in the sink.
+    pub fn kill(&mut self) {
+        if !self.is_alive() {
+            return;
+        }
+        self.updates = None;
+        if let Some((_, writer)) = self.writes.take() {
+            writer.abort();
+        }
+        self.conflux.liveness_changed(&self.cid, false);
+    }
+
+    // Sink turning what is sent into it into new versions, None once killed.
+    pub fn sink(&self) -> Option<mpsc::Sender<Vec<u8>>> {
+        self.writes.as_ref().map(|(sender, _)| sender.clone())
+    }
+
+    // Writes the next version right away, reporting what a sink can't.
+    pub async fn write(&self, data: Vec<u8>) -> Result<DataFragment, ConfluxError> {
+        if !self.is_alive() {
+            return Err(ConfluxError::NotLive(self.cid.clone()));
+        }
+        self.conflux.set(&self.cid, data).await
+    }
+}
+
+impl Stream for LiveFragment {
+    type Item = FragmentUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FragmentUpdate>> {
+        match self.updates.as_mut() {
+            Some(updates) => Pin::new(updates).poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl Drop for LiveFragment {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{
+    behavior::BlinkBehavior,
+    event_sink::EventSink,
+    peer_to_peer_service::{MessageContent, PeerToPeerService},
+    protocol::{self, page_of, BincodeCodec, BlinkProtocol},
+    shared_state::SharedState,
+};
+use blink_contract::{Event, Validation};
+use libp2p::{
+    gossipsub::{IdentTopic, TopicHash},
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    PeerId, Swarm,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
+use tokio::sync::mpsc::Sender;
+
+const MAILBOX_PROTOCOL: &[u8] = b"/blink/mailbox/1.3.0";
+// Messages held per topic at most, the oldest make room for new ones.
+pub(crate) const TOPIC_CAP: usize = 1000;
+// How long a message is held, in milliseconds.
+pub(crate) const HOLD_FOR: u64 = 7 * 24 * 60 * 60 * 1000;
+
+pub(crate) type MailboxBehaviour = RequestResponse<BincodeCodec<MailboxRequest, MailboxResponse>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum MailboxRequest {
+    // Keep messages published on these topics until the owner syncs them
+    Watch(Vec<String>),
+    // A page of the stored messages for the topics that arrived after the timestamp (ms),
+    // starting after the position the previous page ended at, 0 for the first one
+    MissedSince(Vec<String>, u64, u64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum MailboxResponse {
+    Watching,
+    // Topic and envelope as its author sealed and signed it,
+    // then the position to ask the next page from, None on the last one
+    Messages(Vec<(String, Vec<u8>)>, Option<u64>),
+    Refused,
+}
+
+pub(crate) fn new_behaviour() -> MailboxBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(MAILBOX_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+// A replay from a mailbox we're paging through.
+pub(crate) struct MailboxSync {
+    pub(crate) topics: Vec<String>,
+    pub(crate) since: u64,
+    // Messages in the pages received so far
+    pub(crate) replayed: usize,
+}
+
+struct Held {
+    position: u64,
+    received_at: u64,
+    id: String,
+    envelope: Vec<u8>,
+    expires_at: Option<u64>,
+}
+
+// Messages held on behalf of offline peers while running in mailbox mode.
+// They're kept in memory only, a restart of the mailbox loses them. Each topic holds at most
+// TOPIC_CAP messages for HOLD_FOR, which bounds the memory it takes.
+#[derive(Default)]
+pub(crate) struct Mailbox {
+    enabled: bool,
+    // DIDs watching each topic, only they get its messages back
+    watchers: HashMap<String, HashSet<String>>,
+    // Watches asked for before the peer's subscription to the topic reached us, with its DID
+    requested: HashMap<PeerId, (String, HashSet<String>)>,
+    messages: HashMap<String, VecDeque<Held>>,
+    // Of the last message stored, pages resume after a position
+    position: u64,
+}
+
+impl Mailbox {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.watchers.clear();
+            self.requested.clear();
+            self.messages.clear();
+        }
+    }
+
+    // Watches the topic for the DID, once its device is seen subscribed to the topic.
+    // True when the topic wasn't watched yet and the mailbox has to subscribe to it.
+    pub(crate) fn watch(&mut self, peer: PeerId, did: String, topic: String, member: bool) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if member {
+            return self.start_watching(did, topic);
+        }
+        self.requested
+            .entry(peer)
+            .or_insert_with(|| (did, HashSet::new()))
+            .1
+            .insert(topic);
+        false
+    }
+
+    // The peer subscribed to the topic, true when that starts a watch as for `watch`.
+    pub(crate) fn subscribed(&mut self, peer: &PeerId, topic: &str) -> bool {
+        let did = match self.requested.get_mut(peer) {
+            Some((did, topics)) if topics.remove(topic) => did.clone(),
+            _ => return false,
+        };
+        if self
+            .requested
+            .get(peer)
+            .map_or(false, |(_, x)| x.is_empty())
+        {
+            self.requested.remove(peer);
+        }
+        self.start_watching(did, topic.to_string())
+    }
+
+    // Drops the watches the peer asked for but never got to, it asks again when it's back.
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        self.requested.remove(peer);
+    }
+
+    pub(crate) fn is_watching(&self, topic: &str) -> bool {
+        self.enabled && self.watchers.contains_key(topic)
+    }
+
+    // Holds the envelope a message came in, unopened, so the watcher can check who signed it.
+    pub(crate) fn store(
+        &mut self,
+        topic: String,
+        received_at: u64,
+        id: String,
+        envelope: Vec<u8>,
+        expires_at: Option<u64>,
+    ) {
+        self.position += 1;
+        let held = self.messages.entry(topic).or_default();
+        held.push_back(Held {
+            position: self.position,
+            received_at,
+            id,
+            envelope,
+            expires_at,
+        });
+        if held.len() > TOPIC_CAP {
+            held.pop_front();
+        }
+        while held.front().map_or(false, |x| {
+            x.received_at.saturating_add(HOLD_FOR) <= received_at
+        }) {
+            held.pop_front();
+        }
+    }
+
+    // Stops holding the message for anyone.
+    pub(crate) fn forget(&mut self, id: &str) {
+        for held in self.messages.values_mut() {
+            held.retain(|x| x.id != id);
+        }
+    }
+
+    // A page of what arrived after `since` on the topics the DID watches, starting after
+    // `after`, and the position the next page starts after.
+    pub(crate) fn missed_since(
+        &self,
+        did: &str,
+        topics: &[String],
+        since: u64,
+        after: u64,
+        now: u64,
+        max_bytes: u64,
+    ) -> (Vec<(String, Vec<u8>)>, Option<u64>) {
+        let mut missed: Vec<(&String, &Held)> = topics
+            .iter()
+            .filter(|topic| self.watchers.get(*topic).map_or(false, |x| x.contains(did)))
+            .filter_map(|topic| self.messages.get(topic).map(|held| (topic, held)))
+            .flat_map(|(topic, held)| held.iter().map(move |x| (topic, x)))
+            .filter(|(_, x)| {
+                x.position > after
+                    && x.received_at > since
+                    && x.received_at.saturating_add(HOLD_FOR) > now
+                    // Disappearing messages aren't handed out past their expiry
+                    && x.expires_at.map_or(true, |x| x > now)
+            })
+            .collect();
+        missed.sort_by_key(|(_, x)| x.position);
+
+        let page = page_of(
+            missed
+                .iter()
+                .map(|(topic, x)| ((*topic).clone(), x.envelope.clone())),
+            max_bytes,
+        );
+        let next = match page.len() < missed.len() {
+            true => Some(missed[page.len() - 1].1.position),
+            false => None,
+        };
+        (page, next)
+    }
+
+    fn start_watching(&mut self, did: String, topic: String) -> bool {
+        let watchers = self.watchers.entry(topic).or_default();
+        let first = watchers.is_empty();
+        watchers.insert(did);
+        first
+    }
+}
+
+// Holds messages for the peers that watch topics through us and answers their syncs, and replays
+// the pages of the syncs we asked for the way gossiped messages are received.
+pub(crate) async fn handle_event(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    message_sender: &Sender<MessageContent>,
+    event: RequestResponseEvent<MailboxRequest, MailboxResponse>,
+) {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => {
+                // Messages are held for a DID, the peer has to have proven which one it is
+                let did = state.peers.identified.read().did_of(&peer);
+                let enabled = state.messaging.mailbox.read().is_enabled();
+                let response = match (request, did) {
+                    (MailboxRequest::Watch(topics), Some(did)) if enabled => {
+                        for topic in topics {
+                            // Only members of a topic get its messages held
+                            let member = state
+                                .peers
+                                .topic_members
+                                .read()
+                                .peers(&topic)
+                                .contains(&peer);
+                            if state.messaging.mailbox.write().watch(
+                                peer,
+                                did.clone(),
+                                topic.clone(),
+                                member,
+                            ) {
+                                subscribe(swarm, &logger, topic);
+                            }
+                        }
+                        MailboxResponse::Watching
+                    }
+                    (MailboxRequest::MissedSince(topics, since, after), Some(did)) if enabled => {
+                        let (messages, next) = state.messaging.mailbox.read().missed_since(
+                            &did,
+                            &topics,
+                            since,
+                            after,
+                            state.clock.now_millis(),
+                            protocol::PAGE_BYTES,
+                        );
+                        MailboxResponse::Messages(messages, next)
+                    }
+                    _ => MailboxResponse::Refused,
+                };
+                state.count_sent(&peer, None, &response);
+                if swarm
+                    .behaviour_mut()
+                    .mailbox
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    logger.event_occurred(Event::MailboxError(
+                        "Connection closed before responding".into(),
+                    ));
+                }
+            }
+            RequestResponseMessage::Response { response, .. } => match response {
+                MailboxResponse::Watching => {}
+                MailboxResponse::Messages(messages, next) => {
+                    // Only a replay we asked for, of the topics we asked for
+                    let mut sync = match state.messaging.mailbox_syncs.write().remove(&peer) {
+                        Some(sync) => sync,
+                        None => return,
+                    };
+                    for (topic, envelope) in messages {
+                        if !sync.topics.contains(&topic) {
+                            continue;
+                        }
+                        // Held messages go through what gossiped ones do, the mailbox
+                        // relays them but their authors signed them
+                        let verdict = PeerToPeerService::payload_received(
+                            swarm,
+                            logger.clone(),
+                            state,
+                            message_sender,
+                            peer,
+                            TopicHash::from_raw(topic),
+                            &envelope,
+                        )
+                        .await;
+                        if verdict == Validation::Accept {
+                            sync.replayed += 1;
+                        }
+                    }
+                    match next {
+                        Some(after) => {
+                            request_page(swarm, state, peer, &sync, after);
+                            state.messaging.mailbox_syncs.write().insert(peer, sync);
+                        }
+                        None => {
+                            logger.event_occurred(Event::MailboxReplayed(sync.replayed));
+                        }
+                    }
+                }
+                MailboxResponse::Refused => {
+                    state.messaging.mailbox_syncs.write().remove(&peer);
+                    logger.event_occurred(Event::MailboxError(
+                        "Peer isn't running in mailbox mode or doesn't know our DID".into(),
+                    ));
+                }
+            },
+        },
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            state.messaging.mailbox_syncs.write().remove(&peer);
+            logger.event_occurred(Event::MailboxError(error.to_string()));
+        }
+        RequestResponseEvent::InboundFailure { error, .. } => {
+            logger.event_occurred(Event::MailboxError(error.to_string()));
+        }
+        RequestResponseEvent::ResponseSent { .. } => {}
+    }
+}
+
+pub(crate) fn request_page(
+    swarm: &mut Swarm<BlinkBehavior>,
+    state: &SharedState,
+    peer: PeerId,
+    sync: &MailboxSync,
+    after: u64,
+) {
+    let request = MailboxRequest::MissedSince(sync.topics.clone(), sync.since, after);
+    state.count_sent(&peer, None, &request);
+    swarm.behaviour_mut().mailbox.send_request(&peer, request);
+}
+
+pub(crate) fn subscribe(swarm: &mut Swarm<BlinkBehavior>, logger: &EventSink, topic: String) {
+    if let Err(err) = swarm
+        .behaviour_mut()
+        .gossip_sub
+        .subscribe(&IdentTopic::new(topic))
+    {
+        logger.event_occurred(Event::SubscriptionError(err.to_string()));
+    }
+}
@@ -0,0 +1,70 @@
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+// Remote peers gossipsub told us subscribed to each topic.
+#[derive(Default)]
+pub(crate) struct TopicMembers {
+    topics: HashMap<String, HashSet<PeerId>>,
+    // Woken on every change, and on pairing since that's when a DID gets its topic
+    changed: Arc<Notify>,
+}
+
+impl TopicMembers {
+    pub(crate) fn subscribed(&mut self, topic: String, peer: PeerId) -> bool {
+        let added = self.topics.entry(topic).or_default().insert(peer);
+        if added {
+            self.changed.notify_waiters();
+        }
+        added
+    }
+
+    pub(crate) fn unsubscribed(&mut self, topic: &str, peer: &PeerId) -> bool {
+        let removed = match self.topics.get_mut(topic) {
+            Some(peers) => peers.remove(peer),
+            None => false,
+        };
+        if self.topics.get(topic).map_or(false, |x| x.is_empty()) {
+            self.topics.remove(topic);
+        }
+        if removed {
+            self.changed.notify_waiters();
+        }
+        removed
+    }
+
+    // Forgets the peer everywhere, gossipsub doesn't report unsubscriptions of peers that went away.
+    // Returns the topics it was subscribed to.
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) -> Vec<String> {
+        let topics: Vec<String> = self
+            .topics
+            .iter()
+            .filter(|(_, peers)| peers.contains(peer))
+            .map(|(topic, _)| topic.clone())
+            .collect();
+        for topic in &topics {
+            self.unsubscribed(topic, peer);
+        }
+        topics
+    }
+
+    pub(crate) fn peers(&self, topic: &str) -> Vec<PeerId> {
+        self.topics
+            .get(topic)
+            .map(|x| x.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn has_members(&self, topic: &str) -> bool {
+        self.topics.get(topic).map_or(false, |x| !x.is_empty())
+    }
+
+    pub(crate) fn changed(&self) -> Arc<Notify> {
+        self.changed.clone()
+    }
+
+    pub(crate) fn paired(&self) {
+        self.changed.notify_waiters();
+    }
+}
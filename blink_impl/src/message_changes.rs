@@ -0,0 +1,67 @@
+use crate::conversations::message_id;
+use crate::message_kinds::{self, KindOnly, Typed, DELETE_KIND, EDIT_KIND};
+use anyhow::Result;
+use sata::Sata;
+use std::collections::HashMap;
+
+// What the sender of a message changed about it after sending, sent on the message's topic.
+#[derive(Debug, Clone)]
+pub(crate) enum MessageChange {
+    // Id of the message and what it now says
+    Edit(String, Sata),
+    Delete(String),
+}
+
+impl MessageChange {
+    pub(crate) fn id(&self) -> &str {
+        match self {
+            MessageChange::Edit(id, _) | MessageChange::Delete(id) => id,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Result<Sata> {
+        match self {
+            MessageChange::Edit(id, sata) => {
+                Typed::new(EDIT_KIND, (id, sata)).encode_into(Sata::default())
+            }
+            MessageChange::Delete(id) => Typed::new(DELETE_KIND, id).encode_into(Sata::default()),
+        }
+    }
+
+    // The change the message carries, None for any other message.
+    pub(crate) fn decode(sata: &Sata) -> Option<Self> {
+        let kind = sata.decode::<KindOnly>().ok()?.kind;
+        match kind.as_str() {
+            EDIT_KIND => message_kinds::body(sata).map(|(id, sata)| MessageChange::Edit(id, sata)),
+            DELETE_KIND => message_kinds::body(sata).map(MessageChange::Delete),
+            _ => None,
+        }
+    }
+}
+
+// The cached messages as they read now. Edits and deletions are cached after the message they
+// change, by the id it's cached with; this applies them and leaves them out.
+pub fn apply_changes(cached: Vec<Sata>) -> Vec<Sata> {
+    let mut messages: Vec<Option<Sata>> = Vec::with_capacity(cached.len());
+    let mut positions = HashMap::new();
+    for sata in cached {
+        match MessageChange::decode(&sata) {
+            Some(MessageChange::Edit(id, edited)) => {
+                if let Some(position) = positions.remove(&id) {
+                    positions.insert(message_id(&edited), position);
+                    messages[position] = Some(edited);
+                }
+            }
+            Some(MessageChange::Delete(id)) => {
+                if let Some(position) = positions.remove(&id) {
+                    messages[position] = None;
+                }
+            }
+            None => {
+                positions.insert(message_id(&sata), messages.len());
+                messages.push(Some(sata));
+            }
+        }
+    }
+    messages.into_iter().flatten().collect()
+}
@@ -0,0 +1,137 @@
+use crate::peer_to_peer_service::MessageContent;
+use anyhow::{anyhow, Result};
+use blink_contract::BlinkError;
+use libp2p::gossipsub::TopicHash;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
+use warp::sync::RwLock;
+
+// Kinds every service decodes, applications can't register decoders for them
+pub const CHAT_KIND: &str = "chat";
+pub const SIGNAL_KIND: &str = "signal";
+pub const FRAGMENT_ANNOUNCE_KIND: &str = "fragment_announce";
+// Edits and deletions of sent messages, applied by the service and never delivered
+pub const EDIT_KIND: &str = "edit";
+pub const DELETE_KIND: &str = "delete";
+
+// The payload of a typed message: the kind receivers pick a decoder by, and the body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Typed<T> {
+    pub kind: String,
+    pub body: T,
+}
+
+impl<T: Serialize> Typed<T> {
+    pub fn new(kind: impl Into<String>, body: T) -> Self {
+        Self {
+            kind: kind.into(),
+            body,
+        }
+    }
+
+    // Encodes into the message, recipients added to it beforehand stay.
+    pub fn encode_into(&self, sata: Sata) -> Result<Sata> {
+        sata.encode(IpldCodec::DagCbor, Kind::Dynamic, self)
+            .map_err(|e| anyhow!("{:?}", e))
+    }
+}
+
+// Read first, to know which body to decode
+#[derive(Deserialize)]
+pub(crate) struct KindOnly {
+    pub(crate) kind: String,
+}
+
+// A received message decoded by the kind it was sent as.
+#[derive(Debug)]
+pub enum BlinkMessage {
+    Chat(String),
+    // Name of an application signal, e.g. typing, and its data
+    Signal(String, Vec<u8>),
+    // CID of a fragment the sender holds
+    FragmentAnnounce(String),
+    // Registered kind and what its decoder made of the body, downcast to the registered type
+    Custom(String, Box<dyn Any + Send>),
+    // Untyped, of a kind nothing is registered for, or with a body that didn't decode
+    Raw(Sata),
+}
+
+type Decoder = Arc<dyn Fn(&Sata) -> Result<Box<dyn Any + Send>> + Send + Sync>;
+
+// Decoders of the kinds the application registered.
+#[derive(Default)]
+pub(crate) struct MessageKinds {
+    decoders: HashMap<String, Decoder>,
+}
+
+impl MessageKinds {
+    // Messages of the kind decode to `Custom` holding a `T`, replacing any decoder it had.
+    pub(crate) fn register<T>(&mut self, kind: &str) -> Result<(), BlinkError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let built_in = [
+            CHAT_KIND,
+            SIGNAL_KIND,
+            FRAGMENT_ANNOUNCE_KIND,
+            EDIT_KIND,
+            DELETE_KIND,
+        ];
+        if built_in.contains(&kind) {
+            return Err(BlinkError::Invalid(format!("{} is a built-in kind", kind)));
+        }
+        let decoder: Decoder = Arc::new(|sata: &Sata| {
+            let typed = sata.decode::<Typed<T>>().map_err(|e| anyhow!("{:?}", e))?;
+            Ok(Box::new(typed.body) as Box<dyn Any + Send>)
+        });
+        self.decoders.insert(kind.to_string(), decoder);
+        Ok(())
+    }
+
+    pub(crate) fn decode(&self, sata: Sata) -> BlinkMessage {
+        let kind = match sata.decode::<KindOnly>() {
+            Ok(typed) => typed.kind,
+            Err(_) => return BlinkMessage::Raw(sata),
+        };
+        let decoded = match kind.as_str() {
+            CHAT_KIND => body(&sata).map(BlinkMessage::Chat),
+            SIGNAL_KIND => body(&sata).map(|(name, data)| BlinkMessage::Signal(name, data)),
+            FRAGMENT_ANNOUNCE_KIND => body(&sata).map(BlinkMessage::FragmentAnnounce),
+            _ => match self.decoders.get(&kind) {
+                Some(decoder) => decoder(&sata).ok().map(|x| BlinkMessage::Custom(kind, x)),
+                None => None,
+            },
+        };
+        decoded.unwrap_or(BlinkMessage::Raw(sata))
+    }
+}
+
+pub(crate) fn body<T: DeserializeOwned>(sata: &Sata) -> Option<T> {
+    sata.decode::<Typed<T>>().ok().map(|x| x.body)
+}
+
+// The service's message channel, with every message decoded by its kind.
+pub struct TypedReceiver {
+    messages: Receiver<MessageContent>,
+    kinds: Arc<RwLock<MessageKinds>>,
+}
+
+impl TypedReceiver {
+    pub(crate) fn new(
+        messages: Receiver<MessageContent>,
+        kinds: Arc<RwLock<MessageKinds>>,
+    ) -> Self {
+        Self { messages, kinds }
+    }
+
+    // None once the service stopped.
+    pub async fn recv(&mut self) -> Option<(TopicHash, BlinkMessage)> {
+        let (topic, sata) = self.messages.recv().await?;
+        Some((topic, self.kinds.read().decode(sata)))
+    }
+}
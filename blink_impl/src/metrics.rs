@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Counters since the service started, pulled with `PeerToPeerService::metrics`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetricsSnapshot {
+    pub connections_established: u64,
+    pub connections_closed: u64,
+    pub dials: u64,
+    pub dial_failures: u64,
+    pub publish_successes: u64,
+    pub publish_failures: u64,
+    pub received_per_topic: HashMap<String, u64>,
+    pub cache_writes: u64,
+    // Summed over every write, divide by cache_writes for the mean
+    pub cache_write_time: Duration,
+    pub max_cache_write_time: Duration,
+    // Commands waiting for the event loop when the snapshot was taken
+    pub queued_commands: usize,
+    // Received messages waiting to be read, as of the latest delivery
+    pub queued_messages: usize,
+}
+
+impl MetricsSnapshot {
+    pub fn open_connections(&self) -> u64 {
+        self.connections_established
+            .saturating_sub(self.connections_closed)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    snapshot: MetricsSnapshot,
+}
+
+impl Metrics {
+    pub(crate) fn connection_established(&mut self) {
+        self.snapshot.connections_established += 1;
+    }
+
+    pub(crate) fn connection_closed(&mut self) {
+        self.snapshot.connections_closed += 1;
+    }
+
+    pub(crate) fn dialed(&mut self) {
+        self.snapshot.dials += 1;
+    }
+
+    pub(crate) fn dial_failed(&mut self) {
+        self.snapshot.dial_failures += 1;
+    }
+
+    pub(crate) fn published(&mut self, success: bool) {
+        if success {
+            self.snapshot.publish_successes += 1;
+        } else {
+            self.snapshot.publish_failures += 1;
+        }
+    }
+
+    pub(crate) fn received(&mut self, topic: &str) {
+        *self
+            .snapshot
+            .received_per_topic
+            .entry(topic.to_string())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn cache_written(&mut self, took: Duration) {
+        self.snapshot.cache_writes += 1;
+        self.snapshot.cache_write_time += took;
+        self.snapshot.max_cache_write_time = self.snapshot.max_cache_write_time.max(took);
+    }
+
+    pub(crate) fn messages_queued(&mut self, queued: usize) {
+        self.snapshot.queued_messages = queued;
+    }
+
+    pub(crate) fn snapshot(&self, queued_commands: usize) -> MetricsSnapshot {
+        MetricsSnapshot {
+            queued_commands,
+            ..self.snapshot.clone()
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::group_calls::GroupCallId;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+// Latest decision about a DID, the newest one wins when devices disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Decision {
+    active: bool,
+    changed_at: u64,
+}
+
+// Moderation decisions, exchanged with the identity's other devices during a sync.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ModerationRecords {
+    blocked: BTreeMap<String, Decision>,
+    quarantined: BTreeMap<String, Decision>,
+    banned: BTreeMap<(GroupCallId, String), Decision>,
+}
+
+impl ModerationRecords {
+    // Takes the newer decisions from `other`, returns how many changed.
+    fn merge(&mut self, other: ModerationRecords) -> usize {
+        merge_decisions(&mut self.blocked, other.blocked)
+            + merge_decisions(&mut self.quarantined, other.quarantined)
+            + merge_decisions(&mut self.banned, other.banned)
+    }
+}
+
+fn merge_decisions<K: Ord>(
+    ours: &mut BTreeMap<K, Decision>,
+    theirs: BTreeMap<K, Decision>,
+) -> usize {
+    let mut changed = 0;
+    for (key, decision) in theirs {
+        let newer = ours
+            .get(&key)
+            .map_or(true, |x| x.changed_at < decision.changed_at);
+        if newer {
+            ours.insert(key, decision);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+fn set_decision<K: Ord>(
+    decisions: &mut BTreeMap<K, Decision>,
+    key: K,
+    active: bool,
+    now: u64,
+) -> bool {
+    if decisions.get(&key).map_or(false, |x| x.active) == active {
+        return false;
+    }
+    decisions.insert(
+        key,
+        Decision {
+            active,
+            changed_at: now,
+        },
+    );
+    true
+}
+
+fn active<K: Ord + Clone>(decisions: &BTreeMap<K, Decision>) -> Vec<K> {
+    decisions
+        .iter()
+        .filter(|(_, x)| x.active)
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+// Blocked DIDs, quarantined senders and group call bans.
+// Once a path is set every change is written to it, so decisions survive restarts.
+#[derive(Default)]
+pub(crate) struct ModerationStore {
+    path: Option<PathBuf>,
+    records: ModerationRecords,
+}
+
+impl ModerationStore {
+    // Loads the decisions saved at `path` and keeps it up to date from now on.
+    // Decisions taken before the store was opened are kept if they are newer.
+    pub(crate) fn open(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let saved = bincode::deserialize(&bytes)?;
+                self.records.merge(saved);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.path = Some(path);
+        self.save()
+    }
+
+    pub(crate) fn records(&self) -> ModerationRecords {
+        self.records.clone()
+    }
+
+    // Merges the decisions of another device, returns how many changed.
+    pub(crate) fn merge(&mut self, records: ModerationRecords) -> Result<usize> {
+        let changed = self.records.merge(records);
+        if changed > 0 {
+            self.save()?;
+        }
+        Ok(changed)
+    }
+
+    pub(crate) fn set_blocked(&mut self, did: &str, blocked: bool, now: u64) -> Result<bool> {
+        let changed = set_decision(&mut self.records.blocked, did.to_string(), blocked, now);
+        self.saved(changed)
+    }
+
+    pub(crate) fn is_blocked(&self, did: &str) -> bool {
+        self.records.blocked.get(did).map_or(false, |x| x.active)
+    }
+
+    pub(crate) fn blocked(&self) -> Vec<String> {
+        active(&self.records.blocked)
+    }
+
+    pub(crate) fn set_quarantined(
+        &mut self,
+        did: &str,
+        quarantined: bool,
+        now: u64,
+    ) -> Result<bool> {
+        let changed = set_decision(
+            &mut self.records.quarantined,
+            did.to_string(),
+            quarantined,
+            now,
+        );
+        self.saved(changed)
+    }
+
+    pub(crate) fn is_quarantined(&self, did: &str) -> bool {
+        self.records
+            .quarantined
+            .get(did)
+            .map_or(false, |x| x.active)
+    }
+
+    pub(crate) fn quarantined(&self) -> Vec<String> {
+        active(&self.records.quarantined)
+    }
+
+    pub(crate) fn set_banned(
+        &mut self,
+        group: GroupCallId,
+        did: &str,
+        banned: bool,
+        now: u64,
+    ) -> Result<bool> {
+        let changed = set_decision(
+            &mut self.records.banned,
+            (group, did.to_string()),
+            banned,
+            now,
+        );
+        self.saved(changed)
+    }
+
+    pub(crate) fn is_banned(&self, group: GroupCallId, did: &str) -> bool {
+        self.records
+            .banned
+            .get(&(group, did.to_string()))
+            .map_or(false, |x| x.active)
+    }
+
+    pub(crate) fn banned(&self, group: GroupCallId) -> Vec<String> {
+        active(&self.records.banned)
+            .into_iter()
+            .filter(|(x, _)| *x == group)
+            .map(|(_, did)| did)
+            .collect()
+    }
+
+    fn saved(&self, changed: bool) -> Result<bool> {
+        if changed {
+            self.save()?;
+        }
+        Ok(changed)
+    }
+
+    // Written next to the target then renamed, a crash never leaves half a file behind
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let mut temporary = path.clone().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, bincode::serialize(&self.records)?)?;
+            fs::rename(&temporary, path)?;
+        }
+        Ok(())
+    }
+}
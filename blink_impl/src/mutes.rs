@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+use warp::crypto::DID;
+
+// A conversation to mute, the one with a DID or whatever is on a topic, a group's for instance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Conversation {
+    Did(String),
+    Topic(String),
+}
+
+impl From<&DID> for Conversation {
+    fn from(did: &DID) -> Self {
+        Conversation::Did(did.to_string())
+    }
+}
+
+// Muted conversations, their messages are cached but kept off the message stream.
+#[derive(Default)]
+pub(crate) struct Mutes {
+    muted: HashSet<Conversation>,
+}
+
+impl Mutes {
+    // True if the conversation wasn't muted already.
+    pub(crate) fn mute(&mut self, conversation: Conversation) -> bool {
+        self.muted.insert(conversation)
+    }
+
+    // True if the conversation was muted.
+    pub(crate) fn unmute(&mut self, conversation: &Conversation) -> bool {
+        self.muted.remove(conversation)
+    }
+
+    pub(crate) fn muted(&self) -> Vec<Conversation> {
+        self.muted.iter().cloned().collect()
+    }
+
+    // Whether a message on any of the topics, e.g. a channel and its pairwise topic, from the DID
+    // when known, is muted.
+    pub(crate) fn is_muted(&self, topics: &[&str], sender: Option<&str>) -> bool {
+        if self.muted.is_empty() {
+            return false;
+        }
+        topics
+            .iter()
+            .any(|x| self.muted.contains(&Conversation::Topic(x.to_string())))
+            || sender.map_or(false, |x| {
+                self.muted.contains(&Conversation::Did(x.to_string()))
+            })
+    }
+}
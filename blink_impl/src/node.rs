@@ -0,0 +1,146 @@
+use crate::config::BlinkConfig;
+use crate::peer_to_peer_service::{MessageContent, PeerToPeerService};
+use crate::{runtime, CancellationToken};
+use blink_contract::{BlinkError, Clock, Event, EventBus, Keystore};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use warp::{crypto::DID, multipass::MultiPass, pocket_dimension::PocketDimension, sync::RwLock};
+
+// Messages of every identity waiting to be read, the identities' own queues fill up past it
+const MESSAGE_QUEUE_SIZE: usize = 256;
+
+// Event bus of a hosted identity, tags what it reports with its DID.
+struct RoutedEvents {
+    did: String,
+    events: UnboundedSender<(String, Event)>,
+}
+
+impl EventBus for RoutedEvents {
+    fn event_occurred(&mut self, event: Event) {
+        // Nobody reads the node's events anymore, they're still in the service's history
+        let _ = self.events.send((self.did.clone(), event));
+    }
+}
+
+struct HostedIdentity {
+    did: DID,
+    service: Arc<Mutex<PeerToPeerService>>,
+    cancellation_token: CancellationToken,
+}
+
+// Several identities in one process, for account switching or bots hosting many DIDs.
+// Each identity gets a service of its own, with its own swarm since a transport is bound to a
+// single key, but they all run on the caller's runtime and share the node's clock and config.
+// Events and messages come out of the node's receivers tagged with the DID they're for, and
+// commands go through the DID's service.
+pub struct BlinkNode {
+    config: BlinkConfig,
+    clock: Arc<dyn Clock>,
+    identities: HashMap<String, HostedIdentity>,
+    events: UnboundedSender<(String, Event)>,
+    messages: Sender<(String, MessageContent)>,
+}
+
+impl BlinkNode {
+    // Every identity starts with `config`, its listen addresses should use port 0 since a fixed
+    // port can only be bound by the first identity.
+    pub fn new(
+        config: BlinkConfig,
+        clock: Arc<dyn Clock>,
+    ) -> (
+        Self,
+        UnboundedReceiver<(String, Event)>,
+        Receiver<(String, MessageContent)>,
+    ) {
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let (messages, messages_rx) = mpsc::channel(MESSAGE_QUEUE_SIZE);
+        let node = Self {
+            config,
+            clock,
+            identities: HashMap::new(),
+            events,
+            messages,
+        };
+        (node, events_rx, messages_rx)
+    }
+
+    // Starts a service for the keystore's identity and returns its DID.
+    pub async fn add_identity(
+        &mut self,
+        keystore: Arc<dyn Keystore>,
+        cache: Arc<RwLock<dyn PocketDimension>>,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
+    ) -> Result<DID, BlinkError> {
+        let did = keystore.public_key()?;
+        let key = did.to_string();
+        if self.identities.contains_key(&key) {
+            return Err(BlinkError::Invalid(format!("{} is already hosted", key)));
+        }
+        let logger = Arc::new(RwLock::new(RoutedEvents {
+            did: key.clone(),
+            events: self.events.clone(),
+        }));
+        let cancellation_token = CancellationToken::new();
+        let (service, mut received) = PeerToPeerService::with_config(
+            self.config.clone(),
+            keystore,
+            self.clock.clone(),
+            cache,
+            multi_pass,
+            logger,
+            cancellation_token.clone(),
+        )
+        .await?;
+        let messages = self.messages.clone();
+        let recipient = key.clone();
+        // Ends with the service, once its message channel closes
+        runtime::spawn(async move {
+            while let Some(message) = received.recv().await {
+                if messages.send((recipient.clone(), message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        self.identities.insert(
+            key,
+            HostedIdentity {
+                did: did.clone(),
+                service: Arc::new(Mutex::new(service)),
+                cancellation_token,
+            },
+        );
+        Ok(did)
+    }
+
+    // Stops the identity's service and waits until it shut down.
+    pub async fn remove_identity(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let hosted = self
+            .identities
+            .remove(&did.to_string())
+            .ok_or_else(|| BlinkError::NotFound(format!("Hosted identity {}", did)))?;
+        hosted.cancellation_token.cancel();
+        hosted.service.lock().await.wait_for_shutdown().await;
+        Ok(())
+    }
+
+    pub fn identities(&self) -> Vec<DID> {
+        self.identities.values().map(|x| x.did.clone()).collect()
+    }
+
+    // Service of a hosted identity, to send commands as it.
+    pub fn service(&self, did: &DID) -> Option<Arc<Mutex<PeerToPeerService>>> {
+        self.identities
+            .get(&did.to_string())
+            .map(|x| x.service.clone())
+    }
+
+    // Stops every identity.
+    pub async fn shutdown(&mut self) {
+        for (_, hosted) in self.identities.drain() {
+            hosted.cancellation_token.cancel();
+            hosted.service.lock().await.wait_for_shutdown().await;
+        }
+    }
+}
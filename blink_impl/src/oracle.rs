@@ -0,0 +1,82 @@
+use crate::{
+    conflux::{CollisionPolicy, Conflux, ConfluxError},
+    fragments::{CidPolicy, DataFragment, RAW_CODEC},
+    live_fragment::LiveFragment,
+};
+use blink_contract::Clock;
+use sata::Sata;
+use std::sync::Arc;
+
+// Entry point for the lifecycle of fragments. Creates them stamped with the node's clock
+// and keeps them in Conflux, a CID already taken is handled by the collision policy.
+#[derive(Clone)]
+pub struct Oracle {
+    conflux: Conflux,
+    clock: Arc<dyn Clock>,
+}
+
+impl Oracle {
+    pub(crate) fn new(conflux: Conflux, clock: Arc<dyn Clock>) -> Self {
+        Self { conflux, clock }
+    }
+
+    // Rejecting collisions unless told otherwise.
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.conflux = self.conflux.with_collision_policy(policy);
+        self
+    }
+
+    // Immutable fragment, addressed by its content.
+    pub async fn create(&self, data: Vec<u8>) -> Result<DataFragment, ConfluxError> {
+        self.create_with_codec(data, RAW_CODEC).await
+    }
+
+    pub async fn create_with_codec(
+        &self,
+        data: Vec<u8>,
+        codec: u64,
+    ) -> Result<DataFragment, ConfluxError> {
+        let fragment = DataFragment::with_codec(data, codec, self.clock.now_millis());
+        self.track(fragment).await
+    }
+
+    // Fragment keeping the CID of its first version, for content edited over time and by several peers.
+    pub async fn create_shared(&self, data: Vec<u8>) -> Result<DataFragment, ConfluxError> {
+        let fragment =
+            DataFragment::new(data, self.clock.now_millis()).with_policy(CidPolicy::Identity);
+        self.track(fragment).await
+    }
+
+    pub async fn create_from_sata(&self, sata: Sata) -> Result<DataFragment, ConfluxError> {
+        let fragment = DataFragment::from_sata(&sata, self.clock.now_millis())
+            .map_err(|e| ConfluxError::Encoding(e.to_string()))?;
+        self.track(fragment).await
+    }
+
+    pub async fn get(&self, cid: &str) -> Result<DataFragment, ConfluxError> {
+        self.conflux.get_by_cid(cid).await
+    }
+
+    pub async fn set(&self, cid: &str, data: Vec<u8>) -> Result<DataFragment, ConfluxError> {
+        self.conflux.set(cid, data).await
+    }
+
+    pub async fn live(&self, cid: &str) -> Result<LiveFragment, ConfluxError> {
+        self.conflux.live(cid).await
+    }
+
+    pub async fn remove(&self, cid: &str) -> Result<DataFragment, ConfluxError> {
+        self.conflux.remove(cid).await
+    }
+
+    // Watching, pinning, blobs and store management live there.
+    pub fn conflux(&self) -> &Conflux {
+        &self.conflux
+    }
+
+    async fn track(&self, fragment: DataFragment) -> Result<DataFragment, ConfluxError> {
+        self.conflux
+            .add_with(fragment, self.conflux.collision_policy())
+            .await
+    }
+}
@@ -0,0 +1,43 @@
+use libp2p::PeerId;
+
+// What happens to open connections while networking is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PauseMode {
+    // Left open, their handlers keep pinging, so resuming is instant
+    #[default]
+    KeepConnections,
+    // Closed, and the peers redialed on resume
+    Disconnect,
+}
+
+struct Paused {
+    mdns: bool,
+    // Connected when we paused, closed by Disconnect
+    peers: Vec<PeerId>,
+}
+
+// Whether the event loop stopped driving the swarm, and what resuming brings back.
+#[derive(Default)]
+pub(crate) struct PauseState {
+    paused: Option<Paused>,
+}
+
+impl PauseState {
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    // False if it already was. `peers` are the ones to redial on resume.
+    pub(crate) fn pause(&mut self, mdns: bool, peers: Vec<PeerId>) -> bool {
+        if self.paused.is_some() {
+            return false;
+        }
+        self.paused = Some(Paused { mdns, peers });
+        true
+    }
+
+    // Whether mDNS was on and the peers to redial, None if we weren't paused.
+    pub(crate) fn resume(&mut self) -> Option<(bool, Vec<PeerId>)> {
+        self.paused.take().map(|x| (x.mdns, x.peers))
+    }
+}
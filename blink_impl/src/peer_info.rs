@@ -0,0 +1,55 @@
+use libp2p::PeerId;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Smoothing factor from RFC 6298, as used for streams
+const RTT_GAIN: f64 = 0.125;
+
+// Consecutive failed pings before a peer is reported unresponsive
+pub(crate) const UNRESPONSIVE_AFTER: u32 = 3;
+
+// Connection quality of a connected peer, measured with pings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PeerInfo {
+    // Smoothed round trip time, None until a ping got an answer
+    pub rtt_ms: Option<f64>,
+    pub last_rtt_ms: Option<u32>,
+    // Failed pings since the last answered one
+    pub consecutive_failures: u32,
+    pub failures: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct PingTracker {
+    peers: HashMap<PeerId, PeerInfo>,
+}
+
+impl PingTracker {
+    pub(crate) fn succeeded(&mut self, peer: &PeerId, rtt: Duration) {
+        let info = self.peers.entry(*peer).or_default();
+        let sample = rtt.as_secs_f64() * 1000.0;
+        info.rtt_ms = Some(match info.rtt_ms {
+            Some(smoothed) => smoothed + RTT_GAIN * (sample - smoothed),
+            None => sample,
+        });
+        info.last_rtt_ms = Some(sample as u32);
+        info.consecutive_failures = 0;
+    }
+
+    // Returns whether the peer just became unresponsive, it's reported once per streak.
+    pub(crate) fn failed(&mut self, peer: &PeerId) -> bool {
+        let info = self.peers.entry(*peer).or_default();
+        info.consecutive_failures += 1;
+        info.failures += 1;
+        info.consecutive_failures == UNRESPONSIVE_AFTER
+    }
+
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+
+    pub(crate) fn info(&self, peer: &PeerId) -> Option<PeerInfo> {
+        self.peers.get(peer).copied()
+    }
+}
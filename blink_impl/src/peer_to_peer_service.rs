@@ -1,39 +1,104 @@
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosHandle;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsSnapshot;
 use crate::{
-    behavior::{BehaviourEvent, BlinkBehavior},
-    did_keypair_to_libp2p_keypair, {libp2p_pub_to_did, CancellationToken},
+    bandwidth::{self, BandwidthCaps, BandwidthStats},
+    batching::{BatchSettings, Batched},
+    behavior::{self, BehaviourEvent, BlinkBehavior},
+    cache_policy::CachePolicy,
+    cache_writer::{self, CacheWriter, CACHE_QUEUE_SIZE},
+    channels::ChannelTopic,
+    config::{BlinkConfig, ConfigDelta},
+    conflux::{Conflux, FragmentRequest, FragmentResponse},
+    conversations::{self, StoredMessage},
+    delivery::{DeliverySettings, DeliveryStrategy, DirectAck, DirectMessage},
+    device_key::{self, DeviceCertificate},
+    device_sync,
+    dht::{self, DhtAnswer, DhtQuery, DhtWaiter},
+    diagnostics::{self, BenchMessage, BenchmarkOptions, BenchmarkReport},
+    did_records::{self, DidRecord},
+    event_history::{EventHistory, Verbosity, EVENT_HISTORY_SIZE},
+    event_sink::EventSink,
+    extensions,
+    file_transfer::{self, FileOffer, TransferId, TransferRequest},
+    fragment_tree::FragmentTree,
+    fragments::DataFragment,
+    friends::{self, FriendIntent},
+    group_calls::{GroupCallId, GroupTag},
+    identified_peers::Advertisement,
+    identity_profile::{self, IdentityProfile, SignedProfile},
+    idle::IdlePolicy,
+    mailbox::{self, MailboxRequest, MailboxSync},
+    message_changes::MessageChange,
+    message_kinds::{BlinkMessage, TypedReceiver},
+    mutes::Conversation,
+    oracle::Oracle,
+    pause::PauseMode,
+    peer_info::PeerInfo,
+    profile::PeerProfile,
+    providers::ProviderTracker,
+    publishing::{self, PublishFailure, SendReport},
+    rate_limit::RateLimits,
+    recording::{self, RecordingOptions, RecordingRegistry},
+    relay_server::RelayStats,
+    rendezvous, runtime,
+    scoring::{ScoreLevel, ScoreSettings},
+    shared_state::SharedState,
+    signaling::{self, CallId, CallSignal},
+    snapshot::{NetworkSnapshot, MAX_SNAPSHOT_PEERS},
+    streams::{self, CallHandle, ScreenFrame, StreamId, StreamMessage},
+    transactions::{TransactionId, TransactionOutcome, TransactionPart},
+    transport, validators, version,
+    wal::{WalOperation, WriteAheadLog},
+    wire::{Opened, SignedEnvelope, WireCodec},
+    {unique_id, CancellationToken},
+};
+use anyhow::{anyhow, Result};
+use blink_contract::{
+    BlinkError, CallEndReason, Clock, Event, EventBus, ExtensionHandler, Keystore,
+    MessageValidator, RecipientError, Status, StreamKind, Validation, VideoCaps,
 };
-use anyhow::Result;
-use blink_contract::{Event, EventBus};
-use did_key::{Ed25519KeyPair, Generate, KeyMaterial, ECDH};
 use hmac_sha512::Hash;
+#[cfg(not(target_arch = "wasm32"))]
+use libp2p::mdns::MdnsEvent;
 use libp2p::{
-    core::transport::upgrade,
-    futures::StreamExt,
+    futures::{
+        future::{join_all, AbortHandle},
+        Stream, StreamExt,
+    },
+    gossipsub::error::PublishError,
     gossipsub::GossipsubEvent,
     gossipsub::IdentTopic,
+    gossipsub::MessageId,
     gossipsub::TopicHash,
-    identify::IdentifyEvent,
+    identify::{IdentifyEvent, IdentifyInfo},
     identity::Keypair,
-    kad::{KademliaEvent, QueryResult},
-    mdns::MdnsEvent,
-    mplex, noise,
+    kad::{record::Key, BootstrapOk, GetProvidersOk, KademliaEvent, QueryResult, Quorum, Record},
+    ping::{PingEvent, PingFailure, PingSuccess},
+    rendezvous::client::Event as RendezvousEvent,
+    request_response::{RequestResponseEvent, RequestResponseMessage},
     swarm::dial_opts::DialOpts,
-    swarm::{NetworkBehaviour, SwarmBuilder, SwarmEvent},
-    tcp::{GenTcpConfig, TokioTcpTransport},
-    Multiaddr, PeerId, Swarm, Transport,
+    swarm::{AddressScore, NetworkBehaviour, SwarmBuilder, SwarmEvent},
+    Multiaddr, PeerId, Swarm,
 };
-use sata::Sata;
-use std::collections::HashMap;
-use std::sync::{atomic::Ordering, Arc};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::{
+    sync::broadcast::{self, error::RecvError},
     sync::mpsc::{Receiver, Sender},
-    task::JoinHandle,
 };
+use tracing::Instrument;
 use warp::sync::RwLock;
 use warp::{
     crypto::DID,
     data::DataType,
-    multipass::{identity::Identifier, MultiPass},
+    multipass::{identity::Identifier, Friends, MultiPass},
     pocket_dimension::PocketDimension,
 };
 
@@ -43,17 +108,109 @@ pub type MessageContent = (TopicHash, Sata);
 
 const CHANNEL_SIZE: usize = 64;
 
+const PROVIDER_REANNOUNCE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Outlives a few reannouncements, so one that doesn't make it leaves us reachable
+const DID_RECORD_LIFETIME: Duration = Duration::from_secs(3 * 10 * 60);
+
+const TRANSACTION_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+const STREAM_FEEDBACK_INTERVAL: Duration = Duration::from_secs(1);
+
+const TRANSFER_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+const FRAGMENT_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+const PROFILE_BROADCAST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Scores decay every second, crossings are reported within a few of happening
+const SCORE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Disappearing messages are deleted within a second of their expiry
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// How long a peer MultiPass doesn't know stays connected, long enough for a friend request
+const STRANGER_GRACE: Duration = Duration::from_secs(10);
+
+// Time to find, dial and pair with a DID messages are held for, they fail past it
+const AUTO_PAIR_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug)]
 pub(crate) enum BlinkCommand {
     Dial(DialOpts),
-    PublishToTopic(TopicName, Sata),
+    PublishToTopic(TopicName, Sata, Option<u64>),
+    Provide(String),
+    StopProviding(String),
+    SetMailboxMode(bool),
+    WatchAtMailbox(PeerId, Vec<TopicName>),
+    SyncFromMailbox(PeerId, Vec<TopicName>, u64),
+    SyncWithDevice(PeerId),
+    OpenStream(
+        PeerId,
+        StreamId,
+        StreamKind,
+        Option<VideoCaps>,
+        Option<GroupTag>,
+    ),
+    SendStreamMessage(PeerId, StreamMessage),
+    CloseStream(StreamId),
+    EndGroupCall(GroupCallId),
+    RunTransaction(TransactionId),
+    SubscribeExtension(String),
+    UnsubscribeExtension(String),
+    // DID of the conversation, every conversation without one, and the channels
+    SubscribeChannels(Option<String>, Vec<String>),
+    UnsubscribeChannels(Option<String>, Vec<String>),
+    AnnounceProfile,
+    SendTransferRequest(PeerId, TransferRequest),
+    FetchChunks(TransferId),
+    WantFragment(String),
+    PushFragment(DataFragment),
+    CollectGarbage,
+    // DID to look up at the rendezvous nodes
+    RendezvousDiscover(String),
+    // DID to look up on the DHT
+    ResolveDid(String),
+    // DID held messages are given up on, unless we paired with it meanwhile
+    AutoPairExpired(String),
+    // Peers and topics a previous run saved
+    RestoreSnapshot(NetworkSnapshot),
+    Reconfigure(ConfigDelta),
+    Pause(PauseMode),
+    Resume,
+    // Advertised whatever peers observe, or not anymore
+    AddExternalAddress(Multiaddr),
+    RemoveExternalAddress(Multiaddr),
+    // Started for the application, answered through the waiter
+    QueryDht(DhtQuery, DhtWaiter),
+    SetMdns(bool),
+    // The batch held for the topic is due
+    FlushBatch(TopicName),
+    // DID to ask, or to say yes to, through its friend request inbox
+    SendFriendRequest(String),
+    AcceptFriendRequest(String),
+    // DID that accepted our request, to pair with
+    Befriend(String),
+    // Disconnected unless it became a friend since it was identified
+    DropStranger(PeerId),
+    BroadcastProfile,
+    #[cfg(feature = "chaos")]
+    DelayedIdentify(PeerId, IdentifyInfo),
+    #[cfg(feature = "chaos")]
+    DelayedPublish(TopicName, Sata, Option<u64>),
+    // Whether the link to the peer is cut or healed
+    #[cfg(feature = "chaos")]
+    CutLink(PeerId, bool),
 }
 
 pub struct PeerToPeerService {
     command_channel: Sender<BlinkCommand>,
-    task_handle: JoinHandle<()>,
-    map_peer_topic: Arc<RwLock<HashMap<String, String>>>,
-    event_bus: Arc<RwLock<dyn EventBus>>,
+    task_handle: AbortHandle,
+    stopped: tokio::sync::watch::Receiver<()>,
+    event_bus: EventSink,
+    state: SharedState,
 }
 
 impl Drop for PeerToPeerService {
@@ -64,62 +221,291 @@ impl Drop for PeerToPeerService {
 
 impl PeerToPeerService {
     pub async fn new(
-        did_key: Arc<DID>,
+        keystore: Arc<impl Keystore + 'static>,
+        clock: Arc<impl Clock + 'static>,
         address_to_listen: &str,
         initial_known_address: Option<Vec<Multiaddr>>,
         cache: Arc<RwLock<impl PocketDimension + 'static>>,
         multi_pass: Arc<RwLock<impl MultiPass + 'static>>,
         logger: Arc<RwLock<impl EventBus + 'static>>,
         cancellation_token: CancellationToken,
-    ) -> Result<(Self, Receiver<MessageContent>)> {
-        let key_pair = did_keypair_to_libp2p_keypair((*did_key).as_ref())?;
-        let pub_key = key_pair.public();
-        let peer_id = PeerId::from(&pub_key);
-        let mut swarm = Self::create_swarm(&key_pair, &peer_id).await?;
-        if let Some(initial_address) = initial_known_address {
-            for addr in &initial_address {
-                if let Some(peer_addr) = PeerId::try_from_multiaddr(addr) {
-                    let behaviour = swarm.behaviour_mut();
-                    behaviour.kademlia.add_address(&peer_addr, addr.clone());
-                    behaviour.gossip_sub.add_explicit_peer(&peer_addr);
-                }
+    ) -> Result<(Self, Receiver<MessageContent>), BlinkError> {
+        Self::new_dyn(
+            keystore,
+            clock,
+            address_to_listen,
+            initial_known_address,
+            cache,
+            multi_pass,
+            logger,
+            cancellation_token,
+        )
+        .await
+    }
+
+    // Same as new, for implementations picked at runtime or stored behind a trait
+    pub async fn new_dyn(
+        keystore: Arc<dyn Keystore>,
+        clock: Arc<dyn Clock>,
+        address_to_listen: &str,
+        initial_known_address: Option<Vec<Multiaddr>>,
+        cache: Arc<RwLock<dyn PocketDimension>>,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
+        logger: Arc<RwLock<dyn EventBus>>,
+        cancellation_token: CancellationToken,
+    ) -> Result<(Self, Receiver<MessageContent>), BlinkError> {
+        let config = BlinkConfig {
+            listen_addrs: vec![address_to_listen.parse().map_err(|e| {
+                BlinkError::Invalid(format!("Invalid address {}: {}", address_to_listen, e))
+            })?],
+            bootstrap: initial_known_address.unwrap_or_default(),
+            ..Default::default()
+        };
+        Self::with_config(
+            config,
+            keystore,
+            clock,
+            cache,
+            multi_pass,
+            logger,
+            cancellation_token,
+        )
+        .await
+    }
+
+    // Starts the service with everything BlinkConfig covers, usually loaded with BlinkConfig::from_file
+    pub async fn with_config(
+        config: BlinkConfig,
+        keystore: Arc<dyn Keystore>,
+        clock: Arc<dyn Clock>,
+        cache: Arc<RwLock<dyn PocketDimension>>,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
+        logger: Arc<RwLock<dyn EventBus>>,
+        cancellation_token: CancellationToken,
+    ) -> Result<(Self, Receiver<MessageContent>), BlinkError> {
+        // The DID key stays in the keystore, the transport has a key of its own it vouches for
+        let key_pair = device_key::load_or_generate(config.device_key.as_deref())?;
+        let certificate = DeviceCertificate::new(&*keystore, &key_pair.public())?;
+        let peer_id = PeerId::from(key_pair.public());
+        let mut swarm = Self::create_swarm(&key_pair, &certificate, &peer_id, &config).await?;
+        let mut pinned = Vec::new();
+        for addr in &config.bootstrap {
+            if let Some(peer_addr) = PeerId::try_from_multiaddr(addr) {
+                let behaviour = swarm.behaviour_mut();
+                behaviour.kademlia.add_address(&peer_addr, addr.clone());
+                behaviour.gossip_sub.add_explicit_peer(&peer_addr);
+                pinned.push(peer_addr);
+            }
+        }
+        for addr in &config.relays {
+            if let Some(peer_addr) = PeerId::try_from_multiaddr(addr) {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_addr, addr.clone());
+                pinned.push(peer_addr);
             }
+            swarm.dial(addr.clone()).map_err(anyhow::Error::from)?;
         }
 
-        swarm.listen_on(address_to_listen.parse()?)?;
+        let mut rendezvous_nodes = Vec::new();
+        for addr in &config.rendezvous {
+            let node = PeerId::try_from_multiaddr(addr).ok_or_else(|| {
+                BlinkError::Invalid(format!("Rendezvous node {} has no /p2p/ part", addr))
+            })?;
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&node, addr.clone());
+            swarm.dial(addr.clone()).map_err(anyhow::Error::from)?;
+            rendezvous_nodes.push(node);
+        }
+
+        for addr in &config.listen_addrs {
+            swarm.listen_on(addr.clone()).map_err(anyhow::Error::from)?;
+        }
+        // Fails when no node with a /p2p/ part was given, there's nothing to wait for then
+        let bootstrapping = swarm.behaviour_mut().kademlia.bootstrap().is_ok();
+        for addr in &config.external_addrs {
+            swarm.add_external_address(addr.clone(), AddressScore::Infinite);
+        }
 
-        let map = Arc::new(RwLock::new(HashMap::new()));
-        let map_clone = map.clone();
+        let history = Arc::new(RwLock::new(EventHistory::new(logger, EVENT_HISTORY_SIZE)));
+        let (logger, deliver_events) = EventSink::new(history.clone());
+        runtime::spawn(deliver_events);
         let logger_thread = logger.clone();
         let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+        let local_did = keystore.public_key()?.to_string();
+        swarm
+            .behaviour_mut()
+            .gossip_sub
+            .subscribe(&IdentTopic::new(friends::inbox_topic(&local_did)))
+            .map_err(|e| anyhow!("{:?}", e))?;
+        let (recording_tx, recording_rx) = tokio::sync::mpsc::unbounded_channel();
+        runtime::spawn(recording::write_recordings(
+            cache.clone(),
+            logger.clone(),
+            recording_rx,
+        ));
+        let recordings = RecordingRegistry::new(recording_tx);
+        let (cache_tx, cache_rx) = tokio::sync::mpsc::channel(CACHE_QUEUE_SIZE);
+        let state = SharedState::new(
+            command_tx.clone(),
+            clock,
+            peer_id,
+            certificate,
+            keystore.clone(),
+            recordings,
+            CacheWriter::new(cache_tx),
+            history,
+        );
+        runtime::spawn(cache_writer::write_to_cache(
+            cache.clone(),
+            logger.clone(),
+            cache_rx,
+            state.clone(),
+        ));
+        state
+            .messaging
+            .cache_ledger
+            .write()
+            .set_policy(config.cache.policy());
+        state
+            .messaging
+            .rate_limiter
+            .write()
+            .set_limits(config.rate_limits);
+        state.network.bandwidth.write().set_caps(config.bandwidth);
+        *state.network.mdns.write() = config.mdns;
+        state
+            .peers
+            .auto_pairing
+            .write()
+            .set_enabled(config.auto_pair);
+        state.peers.idle.write().set_policy(config.idle);
+        state
+            .messaging
+            .batcher
+            .write()
+            .set_settings(config.batching);
+        *state.messaging.delivery.write() = config.delivery.clone();
+        state.peers.scores.write().set_settings(config.scoring);
+        state.history.write().set_verbosity(config.verbosity);
+        *state.network.relay_nodes.write() = config.relays.clone();
+        if bootstrapping {
+            state.network.readiness.write().bootstrap_started();
+        }
+        for addr in &config.external_addrs {
+            state.network.external.write().add(addr.clone());
+        }
+        for node in rendezvous_nodes {
+            state.network.rendezvous.write().add_node(node);
+            state.peers.idle.write().pin(node);
+        }
+        for peer in pinned {
+            state.peers.idle.write().pin(peer);
+        }
+        let state_thread = state.clone();
         let (message_tx, message_rx) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+        let (running, stopped) = tokio::sync::watch::channel(());
 
-        let handler = tokio::spawn(async move {
+        let handler = runtime::spawn_abortable(async move {
+            let clock = state_thread.clock.clone();
+            let mut reannounce = clock.sleep(PROVIDER_REANNOUNCE_INTERVAL);
+            let mut retry_transactions = clock.sleep(TRANSACTION_RETRY_INTERVAL);
+            let mut stream_feedback = clock.sleep(STREAM_FEEDBACK_INTERVAL);
+            let mut retry_transfers = clock.sleep(TRANSFER_RETRY_INTERVAL);
+            let mut collect_garbage = clock.sleep(FRAGMENT_GC_INTERVAL);
+            let mut check_idle = clock.sleep(IDLE_CHECK_INTERVAL);
+            let mut broadcast_profile = clock.sleep(PROFILE_BROADCAST_INTERVAL);
+            let mut check_scores = clock.sleep(SCORE_CHECK_INTERVAL);
+            let mut expire_messages = clock.sleep(EXPIRY_CHECK_INTERVAL);
+            // Dropped with the task, which is what wait_for_shutdown waits for
+            let _running = running;
             loop {
-                if cancellation_token.load(Ordering::Acquire) {
-                    logger_thread.write().event_occurred(Event::TaskCancelled);
-                }
-
+                // Nothing drives the swarm while paused, heartbeats and discovery stop with it
+                let paused = state_thread.network.pause.read().is_paused();
                 tokio::select! {
+                    _ = cancellation_token.cancelled() => {
+                        logger_thread.event_occurred(Event::TaskCancelled);
+                        break;
+                    }
                      cmd = command_rx.recv() => {
                          if let Some(command) = cmd {
-                             Self::handle_command(&mut swarm, command, logger_thread.clone()).await;
+                             let span = tracing::debug_span!("command", ?command);
+                             Self::handle_command(&mut swarm, command, logger_thread.clone(), cache.clone(),
+                                multi_pass.clone(), keystore.clone(), state_thread.clone()).instrument(span).await;
                          }
                      },
-                    event = swarm.select_next_some() => {
+                    event = swarm.select_next_some(), if !paused => {
+                         let span = tracing::debug_span!("swarm_event", ?event);
                          Self::handle_event(&mut swarm, event, cache.clone(),
-                            logger_thread.clone(), multi_pass.clone(), &message_tx, did_key.clone(), map_clone.clone()).await;
+                            logger_thread.clone(), multi_pass.clone(), &message_tx, keystore.clone(), state_thread.clone()).instrument(span).await;
+                    }
+                    _ = &mut reannounce, if !paused => {
+                        reannounce = clock.sleep(PROVIDER_REANNOUNCE_INTERVAL);
+                        Self::reannounce_providers(&mut swarm, state_thread.network.providers.clone());
+                        Self::register_at_rendezvous(&mut swarm, &state_thread);
+                        Self::publish_did_record(&mut swarm, &logger_thread, &*keystore, &state_thread);
+                        Self::save_snapshot(&mut swarm, &state_thread);
+                    }
+                    _ = &mut retry_transactions, if !paused => {
+                        retry_transactions = clock.sleep(TRANSACTION_RETRY_INTERVAL);
+                        let ids = state_thread.messaging.outbox.read().ids();
+                        for id in ids {
+                            Self::run_transaction(&mut swarm, logger_thread.clone(), &state_thread, id);
+                        }
+                    }
+                    _ = &mut stream_feedback, if !paused => {
+                        stream_feedback = clock.sleep(STREAM_FEEDBACK_INTERVAL);
+                        streams::send_feedback(&mut swarm, &state_thread);
+                    }
+                    _ = &mut retry_transfers, if !paused => {
+                        retry_transfers = clock.sleep(TRANSFER_RETRY_INTERVAL);
+                        let ids = state_thread.media.transfers.read().receiving();
+                        for id in ids {
+                            file_transfer::fetch_chunks(&mut swarm, &state_thread, id);
+                        }
+                    }
+                    _ = &mut collect_garbage, if !paused => {
+                        collect_garbage = clock.sleep(FRAGMENT_GC_INTERVAL);
+                        Self::collect_garbage(&mut swarm, &logger_thread, &state_thread);
+                    }
+                    _ = &mut check_idle, if !paused => {
+                        check_idle = clock.sleep(IDLE_CHECK_INTERVAL);
+                        Self::close_idle_connections(&mut swarm, &state_thread);
+                    }
+                    _ = &mut broadcast_profile, if !paused => {
+                        broadcast_profile = clock.sleep(PROFILE_BROADCAST_INTERVAL);
+                        Self::publish_profile(&mut swarm, logger_thread.clone(), &multi_pass, &*keystore, &state_thread);
+                    }
+                    _ = &mut check_scores, if !paused => {
+                        check_scores = clock.sleep(SCORE_CHECK_INTERVAL);
+                        Self::check_scores(&mut swarm, &logger_thread, &state_thread);
+                    }
+                    // Disappearing messages go whether the network is paused or not
+                    _ = &mut expire_messages => {
+                        expire_messages = clock.sleep(EXPIRY_CHECK_INTERVAL);
+                        Self::expire_messages(&logger_thread, &state_thread);
                     }
                 }
             }
+            Self::save_snapshot(&mut swarm, &state_thread);
+            let peers: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+            for peer in peers {
+                let _ = swarm.disconnect_peer_id(peer);
+            }
+            // Stopped means the application saw every event, TaskCancelled included
+            logger_thread.flush().await;
         });
 
         Ok((
             Self {
                 command_channel: command_tx,
                 task_handle: handler,
-                map_peer_topic: map,
+                stopped,
                 event_bus: logger.clone(),
+                state,
             },
             message_rx,
         ))
@@ -128,58 +514,670 @@ impl PeerToPeerService {
     async fn handle_command(
         swarm: &mut Swarm<BlinkBehavior>,
         command: BlinkCommand,
-        logger: Arc<RwLock<impl EventBus>>,
+        logger: EventSink,
+        cache: Arc<RwLock<dyn PocketDimension>>,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
+        keystore: Arc<dyn Keystore>,
+        state: SharedState,
     ) {
         match command {
             BlinkCommand::Dial(dial_opts) => {
+                if state.network.pause.read().is_paused() {
+                    logger.event_occurred(Event::DialError("Networking is paused".into()));
+                    return;
+                }
                 let peer_id = (&dial_opts)
                     .get_peer_id()
                     .map_or(String::new(), |x| x.to_string());
                 match swarm.dial(dial_opts) {
                     Ok(_) => {
-                        logger
-                            .write()
-                            .event_occurred(Event::DialSuccessful(peer_id));
+                        logger.event_occurred(Event::DialSuccessful(peer_id));
                     }
                     Err(err) => {
-                        logger
-                            .write()
-                            .event_occurred(Event::DialError(err.to_string()));
+                        logger.event_occurred(Event::DialError(err.to_string()));
+                    }
+                }
+            }
+            BlinkCommand::PublishToTopic(name, sata, journal_id) => {
+                #[cfg(feature = "chaos")]
+                if let Some(delay) = state.chaos.publish_delay() {
+                    let commands = state.commands.clone();
+                    let sleep = state.clock.sleep(delay);
+                    runtime::spawn(async move {
+                        sleep.await;
+                        let _ = commands
+                            .send(BlinkCommand::DelayedPublish(name, sata, journal_id))
+                            .await;
+                    });
+                    return;
+                }
+                // Batches have no room for an expiry, disappearing messages go out on their own
+                if journal_id.is_some() || state.messaging.expiry.read().expires_at(&sata).is_some()
+                {
+                    Self::publish_command(swarm, logger, &state, name, sata, journal_id);
+                    return;
+                }
+                let bytes = bincode::serialized_size(&sata).unwrap_or(u64::MAX);
+                if !state.messaging.batcher.read().batches(bytes) {
+                    // Whatever is held for the topic goes first, so messages stay in order
+                    Self::flush_batch(swarm, logger.clone(), &state, name.clone());
+                    Self::publish_command(swarm, logger, &state, name, sata, None);
+                    return;
+                }
+                let batched = state
+                    .messaging
+                    .batcher
+                    .write()
+                    .add(name.clone(), sata, bytes);
+                match batched {
+                    Batched::First => {
+                        let commands = state.commands.clone();
+                        let sleep = state
+                            .clock
+                            .sleep(state.messaging.batcher.read().settings().window());
+                        runtime::spawn(async move {
+                            sleep.await;
+                            let _ = commands.send(BlinkCommand::FlushBatch(name)).await;
+                        });
+                    }
+                    Batched::Added => {}
+                    Batched::Full => Self::flush_batch(swarm, logger, &state, name),
+                }
+            }
+            BlinkCommand::FlushBatch(name) => {
+                Self::flush_batch(swarm, logger, &state, name);
+            }
+            BlinkCommand::SendFriendRequest(did) => {
+                friends::send_message(
+                    swarm,
+                    logger,
+                    &*keystore,
+                    &state,
+                    FriendIntent::Request,
+                    did.clone(),
+                );
+                // A direct connection gets it there when no mailbox watches the inbox
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_record(did_records::key_of(&did), Quorum::One);
+            }
+            BlinkCommand::AcceptFriendRequest(did) => {
+                if !state.peers.friends.write().accept(&did) {
+                    return;
+                }
+                // Implementations keeping friends of their own learn about it, the others refuse
+                // and Blink keeps the friendship to itself
+                if let Ok(public_key) = DID::try_from(did.clone()) {
+                    if let Err(e) = multi_pass.write().accept_request(&public_key) {
+                        tracing::debug!(%e, "MultiPass didn't take the friend request");
+                    }
+                }
+                friends::send_message(
+                    swarm,
+                    logger.clone(),
+                    &*keystore,
+                    &state,
+                    FriendIntent::Accept,
+                    did.clone(),
+                );
+                friends::befriend(swarm, logger, &*keystore, &state, did);
+            }
+            BlinkCommand::Befriend(did) => {
+                friends::befriend(swarm, logger, &*keystore, &state, did);
+            }
+            BlinkCommand::BroadcastProfile => {
+                Self::publish_profile(swarm, logger, &multi_pass, &*keystore, &state);
+            }
+            BlinkCommand::DropStranger(peer_id) => {
+                let paired = state
+                    .peers
+                    .map_did_peer
+                    .read()
+                    .values()
+                    .any(|x| *x == peer_id);
+                if !paired
+                    && swarm.is_connected(&peer_id)
+                    && swarm.disconnect_peer_id(peer_id).is_err()
+                {
+                    logger.event_occurred(Event::FailureToDisconnectPeer);
+                }
+            }
+            #[cfg(feature = "chaos")]
+            BlinkCommand::DelayedPublish(name, sata, journal_id) => {
+                Self::publish_command(swarm, logger, &state, name, sata, journal_id);
+            }
+            #[cfg(feature = "chaos")]
+            BlinkCommand::CutLink(peer_id, cut) => {
+                if cut {
+                    swarm.ban_peer_id(peer_id);
+                } else {
+                    swarm.unban_peer_id(peer_id);
+                }
+            }
+            BlinkCommand::Provide(cid) => {
+                let key = Key::new(&cid);
+                state.network.providers.write().track(cid);
+                if let Err(err) = swarm.behaviour_mut().kademlia.start_providing(key) {
+                    logger.event_occurred(Event::ErrorProvidingContent(err.to_string()));
+                }
+            }
+            BlinkCommand::StopProviding(cid) => {
+                if state.network.providers.write().untrack(&cid) {
+                    swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .stop_providing(&Key::new(&cid));
+                }
+            }
+            BlinkCommand::SetMailboxMode(enabled) => {
+                state.messaging.mailbox.write().set_enabled(enabled);
+            }
+            BlinkCommand::WatchAtMailbox(peer_id, topics) => {
+                let request = MailboxRequest::Watch(topics);
+                state.count_sent(&peer_id, None, &request);
+                swarm
+                    .behaviour_mut()
+                    .mailbox
+                    .send_request(&peer_id, request);
+            }
+            BlinkCommand::SyncFromMailbox(peer_id, topics, since) => {
+                let sync = MailboxSync {
+                    topics,
+                    since,
+                    replayed: 0,
+                };
+                mailbox::request_page(swarm, &state, peer_id, &sync, 0);
+                state.messaging.mailbox_syncs.write().insert(peer_id, sync);
+            }
+            BlinkCommand::SyncWithDevice(peer_id) => {
+                device_sync::request_page(swarm, &state, peer_id, 0, true);
+            }
+            BlinkCommand::OpenStream(peer_id, id, kind, caps, group) => {
+                let codecs = streams::supported_codecs(kind);
+                let message = StreamMessage::Open(id, kind, codecs, caps, group);
+                if let Some(request_id) =
+                    streams::send_message(swarm, logger.clone(), &state, peer_id, message)
+                {
+                    state
+                        .media
+                        .streams
+                        .write()
+                        .track_open_request(request_id, id);
+                }
+            }
+            BlinkCommand::SendStreamMessage(peer_id, message) => {
+                streams::send_message(swarm, logger.clone(), &state, peer_id, message);
+            }
+            BlinkCommand::CloseStream(id) => {
+                let peer = state.media.streams.read().peer_of(id);
+                if let Some(peer_id) = peer {
+                    state.media.streams.write().close(id);
+                    state
+                        .media
+                        .recordings
+                        .write()
+                        .finish(id, state.clock.now_millis());
+                    if peer_id != state.local_peer {
+                        let message = StreamMessage::Close(id);
+                        state.count_sent(&peer_id, None, &message);
+                        swarm
+                            .behaviour_mut()
+                            .streams
+                            .send_request(&peer_id, message);
+                    }
+                    logger.event_occurred(Event::StreamClosed(id));
+                    streams::group_stream_closed(swarm, logger.clone(), &state, id);
+                }
+            }
+            BlinkCommand::EndGroupCall(group) => {
+                for (peer_id, id) in state.media.groups.write().end(group) {
+                    state.media.streams.write().close(id);
+                    state
+                        .media
+                        .recordings
+                        .write()
+                        .finish(id, state.clock.now_millis());
+                    if peer_id != state.local_peer {
+                        let message = StreamMessage::Close(id);
+                        state.count_sent(&peer_id, None, &message);
+                        swarm
+                            .behaviour_mut()
+                            .streams
+                            .send_request(&peer_id, message);
+                    }
+                }
+            }
+            BlinkCommand::RunTransaction(id) => {
+                Self::run_transaction(swarm, logger, &state, id);
+            }
+            BlinkCommand::SubscribeExtension(namespace) => {
+                let topics: Vec<String> = state
+                    .peers
+                    .map_peer_topic
+                    .read()
+                    .values()
+                    .cloned()
+                    .collect();
+                Self::subscribe_extension_topics(swarm, logger, &topics, &[namespace]);
+            }
+            BlinkCommand::AnnounceProfile => {
+                let peers: Vec<PeerId> =
+                    state.peers.map_did_peer.read().values().copied().collect();
+                for peer_id in peers {
+                    Self::send_profile(swarm, &state, &peer_id);
+                }
+            }
+            BlinkCommand::SendTransferRequest(peer_id, request) => {
+                let offered = match &request {
+                    TransferRequest::Offer(id, _) => Some(*id),
+                    _ => None,
+                };
+                state.count_sent(&peer_id, None, &request);
+                let request_id = swarm
+                    .behaviour_mut()
+                    .file_transfer
+                    .send_request(&peer_id, request);
+                if let Some(id) = offered {
+                    state.media.transfers.write().track_offer(request_id, id);
+                }
+            }
+            BlinkCommand::FetchChunks(id) => {
+                file_transfer::fetch_chunks(swarm, &state, id);
+            }
+            BlinkCommand::WantFragment(cid) => {
+                // Connected peers are asked right away, providers found on the DHT once the query ends
+                state.network.conflux.write().searching(&cid);
+                swarm.behaviour_mut().kademlia.get_providers(Key::new(&cid));
+                let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+                Self::ask_for_fragment(swarm, &state, &cid, peers);
+            }
+            BlinkCommand::PushFragment(fragment) => {
+                let peers = state.network.conflux.read().interested(fragment.cid());
+                for peer_id in peers {
+                    if !swarm.is_connected(&peer_id) {
+                        continue;
+                    }
+                    let request = FragmentRequest::Update(fragment.clone());
+                    state.count_sent(&peer_id, None, &request);
+                    let request_id = swarm
+                        .behaviour_mut()
+                        .conflux
+                        .send_request(&peer_id, request);
+                    state.network.conflux.write().pushed(request_id);
+                }
+            }
+            BlinkCommand::CollectGarbage => {
+                Self::collect_garbage(swarm, &logger, &state);
+            }
+            BlinkCommand::RendezvousDiscover(did) => {
+                let namespace = state.network.rendezvous.write().want(did);
+                let nodes = state.network.rendezvous.read().nodes();
+                for node in nodes {
+                    if !swarm.is_connected(&node) {
+                        continue;
+                    }
+                    swarm.behaviour_mut().rendezvous.discover(
+                        Some(namespace.clone()),
+                        None,
+                        None,
+                        node,
+                    );
+                }
+            }
+            BlinkCommand::ResolveDid(did) => {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_record(did_records::key_of(&did), Quorum::One);
+            }
+            BlinkCommand::AutoPairExpired(did) => {
+                if let Some(recipient) = state.peers.auto_pairing.write().expired(&did) {
+                    logger.event_occurred(Event::SendFailed {
+                        recipient,
+                        reason: RecipientError::NotPaired,
+                    });
+                }
+            }
+            BlinkCommand::RestoreSnapshot(snapshot) => {
+                let kademlia = &mut swarm.behaviour_mut().kademlia;
+                for (peer, addrs) in snapshot.peers() {
+                    for addr in addrs {
+                        kademlia.add_address(&peer, addr);
+                    }
+                }
+                // Connects to the restored peers, gossipsub meshes form over those connections
+                let _ = kademlia.bootstrap();
+                for topic in snapshot.topics {
+                    if let Err(e) = swarm
+                        .behaviour_mut()
+                        .gossip_sub
+                        .subscribe(&IdentTopic::new(topic))
+                    {
+                        logger.event_occurred(Event::SubscriptionError(e.to_string()));
+                    }
+                }
+            }
+            BlinkCommand::Reconfigure(delta) => {
+                if let Some(limits) = delta.rate_limits {
+                    state.messaging.rate_limiter.write().set_limits(limits);
+                }
+                if let Some(cache) = delta.cache {
+                    state
+                        .messaging
+                        .cache_ledger
+                        .write()
+                        .set_policy(cache.policy());
+                }
+                if let Some(verbosity) = delta.verbosity {
+                    state.history.write().set_verbosity(verbosity);
+                }
+                if let Some(relays) = delta.relays {
+                    let previous =
+                        std::mem::replace(&mut *state.network.relay_nodes.write(), relays.clone());
+                    for addr in previous.iter().filter(|x| !relays.contains(x)) {
+                        if let Some(peer) = PeerId::try_from_multiaddr(addr) {
+                            swarm.behaviour_mut().kademlia.remove_address(&peer, addr);
+                            state.peers.idle.write().unpin(&peer);
+                        }
+                    }
+                    for addr in relays.into_iter().filter(|x| !previous.contains(x)) {
+                        if let Some(peer) = PeerId::try_from_multiaddr(&addr) {
+                            swarm
+                                .behaviour_mut()
+                                .kademlia
+                                .add_address(&peer, addr.clone());
+                            state.peers.idle.write().pin(peer);
+                        }
+                        if let Err(e) = swarm.dial(addr) {
+                            logger.event_occurred(Event::DialError(e.to_string()));
+                        }
+                    }
+                }
+            }
+            BlinkCommand::Pause(mode) => {
+                let connected: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                let redial = match mode {
+                    PauseMode::KeepConnections => Vec::new(),
+                    PauseMode::Disconnect => connected,
+                };
+                let mdns = swarm.behaviour().mdns.is_enabled();
+                if !state.network.pause.write().pause(mdns, redial.clone()) {
+                    return;
+                }
+                if mdns {
+                    Self::set_local_discovery(swarm, &logger, &state, false).await;
+                }
+                for peer in redial {
+                    let _ = swarm.disconnect_peer_id(peer);
+                }
+                logger.event_occurred(Event::NetworkingPaused);
+            }
+            BlinkCommand::Resume => {
+                let (mdns, redial) = match state.network.pause.write().resume() {
+                    Some(resumed) => resumed,
+                    None => return,
+                };
+                if mdns && !swarm.behaviour().mdns.is_enabled() {
+                    Self::set_local_discovery(swarm, &logger, &state, true).await;
+                }
+                // Time paused doesn't count as idle
+                let now = state.clock.now_millis();
+                let connected: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                for peer in connected {
+                    state.peers.idle.write().active(&peer, now);
+                }
+                // Kademlia and identify kept their addresses, gossipsub meshes again once connected
+                for peer in redial {
+                    if let Err(e) = swarm.dial(peer) {
+                        logger.event_occurred(Event::DialError(e.to_string()));
+                    }
+                }
+                logger.event_occurred(Event::NetworkingResumed);
+            }
+            BlinkCommand::AddExternalAddress(addr) => {
+                if state.network.external.write().add(addr.clone()) {
+                    swarm.add_external_address(addr, AddressScore::Infinite);
+                    Self::push_identify(swarm);
+                    Self::publish_did_record(swarm, &logger, &*keystore, &state);
+                }
+            }
+            BlinkCommand::RemoveExternalAddress(addr) => {
+                if state.network.external.write().remove(&addr) {
+                    swarm.remove_external_address(&addr);
+                    Self::push_identify(swarm);
+                    Self::publish_did_record(swarm, &logger, &*keystore, &state);
+                }
+            }
+            BlinkCommand::QueryDht(query, waiter) => {
+                let kademlia = &mut swarm.behaviour_mut().kademlia;
+                let id = match query {
+                    DhtQuery::FindPeer(peer) => kademlia.get_closest_peers(peer),
+                    DhtQuery::GetRecord(key) => kademlia.get_record(Key::new(&key), Quorum::One),
+                    DhtQuery::PutRecord(key, value) => {
+                        match kademlia.put_record(Record::new(key, value), Quorum::One) {
+                            Ok(id) => id,
+                            Err(e) => {
+                                let _ = waiter.send(Err(BlinkError::Other(e.into())));
+                                return;
+                            }
+                        }
                     }
+                    DhtQuery::GetProviders(key) => kademlia.get_providers(Key::new(&key)),
+                };
+                state.network.dht.write().started(id, waiter);
+            }
+            BlinkCommand::SetMdns(enabled) => {
+                if swarm.behaviour().mdns.is_enabled() != enabled {
+                    Self::set_local_discovery(swarm, &logger, &state, enabled).await;
                 }
             }
-            BlinkCommand::PublishToTopic(name, sata) => {
-                let serialized_result = bincode::serialize(&sata);
-                match serialized_result {
-                    Ok(serialized) => {
-                        let topic = IdentTopic::new(name);
-                        if let Err(err) =
-                            swarm.behaviour_mut().gossip_sub.publish(topic, serialized)
+            #[cfg(feature = "chaos")]
+            BlinkCommand::DelayedIdentify(peer_id, info) => {
+                Self::peer_identified(swarm, logger, multi_pass, keystore, &state, peer_id, info);
+            }
+            BlinkCommand::SubscribeChannels(did, channels) => {
+                let topics = state.conversation_topics(did.as_deref());
+                Self::subscribe_extension_topics(swarm, logger, &topics, &channels);
+            }
+            BlinkCommand::UnsubscribeChannels(did, channels) => {
+                for topic in state.conversation_topics(did.as_deref()) {
+                    let did = state.did_of_topic(&topic).unwrap_or_default();
+                    for channel in &channels {
+                        // Still open on the conversation some other way
+                        if state.messaging.channels.read().is_open(&did, channel) {
+                            continue;
+                        }
+                        let channel_topic = extensions::extension_topic(&topic, channel);
+                        if let Err(err) = swarm
+                            .behaviour_mut()
+                            .gossip_sub
+                            .unsubscribe(&IdentTopic::new(channel_topic))
                         {
-                            logger
-                                .write()
-                                .event_occurred(Event::ErrorPublishingData(err.to_string()));
+                            logger.event_occurred(Event::SubscriptionError(err.to_string()));
                         }
                     }
-                    Err(_) => {
-                        logger.write().event_occurred(Event::ErrorSerializingData);
+                }
+            }
+            BlinkCommand::UnsubscribeExtension(namespace) => {
+                let topics: Vec<String> = state
+                    .peers
+                    .map_peer_topic
+                    .read()
+                    .values()
+                    .cloned()
+                    .collect();
+                for topic in topics {
+                    let extension_topic = extensions::extension_topic(&topic, &namespace);
+                    if let Err(err) = swarm
+                        .behaviour_mut()
+                        .gossip_sub
+                        .unsubscribe(&IdentTopic::new(extension_topic))
+                    {
+                        logger.event_occurred(Event::SubscriptionError(err.to_string()));
                     }
                 }
             }
         }
     }
 
+    async fn set_local_discovery(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: &EventSink,
+        state: &SharedState,
+        enabled: bool,
+    ) {
+        let mdns = match behavior::local_discovery(enabled).await {
+            Ok(mdns) => mdns,
+            Err(e) => {
+                logger.event_occurred(Event::MdnsError(e.to_string()));
+                return;
+            }
+        };
+        // No expiry is reported for the peers it found once it's gone
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(previous) = swarm.behaviour().mdns.as_ref() {
+            let discovered: Vec<PeerId> = previous.discovered_nodes().copied().collect();
+            for peer in discovered {
+                swarm.behaviour_mut().gossip_sub.remove_explicit_peer(&peer);
+            }
+        }
+        swarm.behaviour_mut().mdns = mdns;
+        *state.network.mdns.write() = enabled;
+    }
+
+    fn close_idle_connections(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState) {
+        let now = state.clock.now_millis();
+        let paired = state.peers.map_did_peer.read().clone();
+        let idle = state.peers.idle.read().idle(now, &paired, |peer| {
+            // Gossipsub redials the peers mDNS found, closing them would only churn
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(mdns) = swarm.behaviour().mdns.as_ref() {
+                if mdns.has_node(peer) {
+                    return true;
+                }
+            }
+            state.media.streams.read().has_streams_with(peer)
+        });
+        for peer in idle {
+            let _ = swarm.disconnect_peer_id(peer);
+        }
+    }
+
+    // Sets score parameters for the topics subscribed to since, and reports peers whose score
+    // crossed a threshold
+    fn check_scores(swarm: &mut Swarm<BlinkBehavior>, logger: &EventSink, state: &SharedState) {
+        let settings = state.peers.scores.read().settings();
+        if !settings.enabled {
+            return;
+        }
+        let topics: Vec<TopicHash> = swarm.behaviour().gossip_sub.topics().cloned().collect();
+        for topic in topics {
+            let members = state.peers.topic_members.read().peers(topic.as_str()).len();
+            let strategy = state
+                .messaging
+                .delivery
+                .read()
+                .strategy(topic.as_str(), members);
+            if !state
+                .peers
+                .scores
+                .write()
+                .needs_params(topic.as_str(), strategy)
+            {
+                continue;
+            }
+            if let Err(e) = swarm.behaviour_mut().gossip_sub.set_topic_params(
+                IdentTopic::new(topic.as_str()),
+                settings.topic_params(strategy),
+            ) {
+                tracing::warn!(%topic, "Couldn't set score parameters: {}", e);
+            }
+        }
+        let gossip_sub = &swarm.behaviour().gossip_sub;
+        let scores: Vec<(PeerId, f64)> = gossip_sub
+            .all_peers()
+            .filter_map(|(peer, _)| Some((*peer, gossip_sub.peer_score(peer)?)))
+            .collect();
+        for (peer, score) in scores {
+            let level = match state.peers.scores.write().scored(peer, score) {
+                Some(level) => level,
+                None => continue,
+            };
+            let did = state.did_of(&peer);
+            match level {
+                ScoreLevel::Fine => logger.event_occurred(Event::PeerScoreRecovered(did, score)),
+                ScoreLevel::Graylisted => logger.event_occurred(Event::PeerGraylisted(did, score)),
+                ScoreLevel::Blacklisted => {
+                    swarm.behaviour_mut().gossip_sub.blacklist_peer(&peer);
+                    let _ = swarm.disconnect_peer_id(peer);
+                    logger.event_occurred(Event::PeerBlacklisted(did, score));
+                }
+            }
+        }
+    }
+
+    // Saved with every provider reannouncement and on shutdown, once a path was given
+    fn save_snapshot(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState) {
+        if !state.network.snapshots.read().is_enabled() {
+            return;
+        }
+        let mut peers = Vec::new();
+        for bucket in swarm.behaviour_mut().kademlia.kbuckets() {
+            for entry in bucket.iter() {
+                let addrs = entry.node.value.iter().cloned().collect();
+                peers.push((entry.node.key.preimage().to_string(), addrs));
+            }
+        }
+        peers.truncate(MAX_SNAPSHOT_PEERS);
+        let topics = swarm
+            .behaviour()
+            .gossip_sub
+            .topics()
+            .map(|x| x.to_string())
+            .collect();
+        let snapshot = NetworkSnapshot { peers, topics };
+        if let Err(e) = state.network.snapshots.read().save(&snapshot) {
+            tracing::warn!("Couldn't save the network snapshot: {}", e);
+        }
+    }
+
+    fn reannounce_providers(
+        swarm: &mut Swarm<BlinkBehavior>,
+        providers: Arc<RwLock<ProviderTracker>>,
+    ) {
+        let kademlia = &mut swarm.behaviour_mut().kademlia;
+        for cid in providers.read().provided() {
+            let key = Key::new(&cid);
+            // The local record only lives in our store, announcing again refreshes it on the DHT
+            let _ = kademlia.start_providing(key.clone());
+            kademlia.get_providers(key);
+        }
+    }
+
     async fn handle_event<TErr>(
         swarm: &mut Swarm<BlinkBehavior>,
         event: SwarmEvent<BehaviourEvent, TErr>,
-        cache: Arc<RwLock<impl PocketDimension>>,
-        logger: Arc<RwLock<impl EventBus>>,
-        multi_pass: Arc<RwLock<impl MultiPass>>,
+        cache: Arc<RwLock<dyn PocketDimension>>,
+        logger: EventSink,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
         message_sender: &Sender<MessageContent>,
-        did: Arc<DID>,
-        map: Arc<RwLock<HashMap<String, String>>>,
+        keystore: Arc<dyn Keystore>,
+        state: SharedState,
     ) {
+        if let SwarmEvent::Behaviour(event) = &event {
+            if let Some((peer, stream, bytes)) = bandwidth::inbound(event) {
+                let now = state.clock.now_millis();
+                state
+                    .network
+                    .bandwidth
+                    .write()
+                    .received(&peer, stream, bytes, now);
+                state.peers.idle.write().active(&peer, now);
+            }
+        }
         match event {
+            #[cfg(not(target_arch = "wasm32"))]
             SwarmEvent::Behaviour(BehaviourEvent::MdnsEvent(event)) => match event {
                 MdnsEvent::Discovered(list) => {
                     for (peer, _) in list {
@@ -188,7 +1186,8 @@ impl PeerToPeerService {
                 }
                 MdnsEvent::Expired(list) => {
                     for (peer, _) in list {
-                        if !swarm.behaviour().mdns.has_node(&peer) {
+                        let mdns = swarm.behaviour().mdns.as_ref();
+                        if !mdns.map_or(false, |mdns| mdns.has_node(&peer)) {
                             swarm.behaviour_mut().gossip_sub.remove_explicit_peer(&peer);
                         }
                     }
@@ -196,89 +1195,168 @@ impl PeerToPeerService {
             },
             SwarmEvent::Behaviour(BehaviourEvent::IdentifyEvent(identify)) => match identify {
                 IdentifyEvent::Received { peer_id, info } => {
-                    let did_result = libp2p_pub_to_did(&info.public_key);
-
-                    match did_result {
-                        Ok(their_public) => {
-                            match multi_pass
-                                .read()
-                                .get_identity(Identifier::from(their_public.clone()))
-                            {
-                                Ok(_) => {
-                                    let topic = Self::generate_topic_from_key_exchange(
-                                        &*did,
-                                        &their_public,
-                                    );
-                                    let pb = their_public.clone().to_string();
-                                    map.write().insert(pb, topic.clone());
-
-                                    let topic_subs = IdentTopic::new(&topic);
-                                    match swarm.behaviour_mut().gossip_sub.subscribe(&topic_subs) {
-                                        Ok(_) => {
-                                            logger.write().event_occurred(Event::GeneratedTopic(
-                                                their_public,
-                                                topic.clone(),
-                                            ));
-                                            logger
-                                                .write()
-                                                .event_occurred(Event::SubscribedToTopic(topic));
-                                            logger.write().event_occurred(Event::PeerIdentified);
-                                        }
-                                        Err(er) => {
-                                            logger.write().event_occurred(
-                                                Event::SubscriptionError(er.to_string()),
-                                            );
-                                        }
-                                    }
-                                }
-                                Err(_) => {
-                                    logger.write().event_occurred(Event::FailureToIdentifyPeer);
-                                    if swarm.disconnect_peer_id(peer_id).is_err() {
-                                        logger
-                                            .write()
-                                            .event_occurred(Event::FailureToDisconnectPeer);
-                                    }
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            logger.write().event_occurred(Event::ConvertKeyError);
-                        }
+                    let observed = info.observed_addr.clone();
+                    if state
+                        .network
+                        .external
+                        .write()
+                        .observed(peer_id, observed.clone())
+                    {
+                        swarm.add_external_address(observed.clone(), AddressScore::Infinite);
+                        logger.event_occurred(Event::ExternalAddressConfirmed(observed));
+                        Self::push_identify(swarm);
+                        Self::publish_did_record(swarm, &logger, &*keystore, &state);
                     }
+                    #[cfg(feature = "chaos")]
+                    if let Some(delay) = state.chaos.identify_delay() {
+                        let commands = state.commands.clone();
+                        let sleep = state.clock.sleep(delay);
+                        runtime::spawn(async move {
+                            sleep.await;
+                            let _ = commands
+                                .send(BlinkCommand::DelayedIdentify(peer_id, info))
+                                .await;
+                        });
+                        return;
+                    }
+                    Self::peer_identified(
+                        swarm, logger, multi_pass, keystore, &state, peer_id, info,
+                    );
                 }
                 IdentifyEvent::Sent { .. } => {}
-                IdentifyEvent::Pushed { .. } => {}
+                IdentifyEvent::Pushed { peer_id } => {
+                    tracing::debug!(%peer_id, "Pushed our identify info");
+                }
                 IdentifyEvent::Error { .. } => {}
             },
             SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gsp)) => match gsp {
-                GossipsubEvent::Message { message, .. } => {
-                    let message_data = message.data;
-                    let data = bincode::deserialize::<Sata>(&message_data);
-                    match data {
-                        Ok(info) => {
-                            if let Err(e) = cache.write().add_data(DataType::Messaging, &info) {
-                                logger
-                                    .write()
-                                    .event_occurred(Event::ErrorAddingToCache(e.enum_to_string()));
-                            }
-                            if let Err(_) = message_sender.send((message.topic, info.clone())).await
-                            {
-                                logger.write().event_occurred(Event::FailedToSendMessage);
+                GossipsubEvent::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                } => {
+                    #[cfg(feature = "metrics")]
+                    state.metrics.write().received(message.topic.as_str());
+                    let author = message.source.unwrap_or(propagation_source);
+                    let verdict = Self::payload_received(
+                        swarm,
+                        logger,
+                        &state,
+                        message_sender,
+                        author,
+                        message.topic,
+                        &message.data,
+                    )
+                    .await;
+                    Self::report_validation(swarm, &message_id, &propagation_source, verdict);
+                }
+                GossipsubEvent::Subscribed { peer_id, topic } => {
+                    if state
+                        .peers
+                        .topic_members
+                        .write()
+                        .subscribed(topic.to_string(), peer_id)
+                    {
+                        logger.event_occurred(Event::PeerJoinedTopic(
+                            state.did_of(&peer_id),
+                            topic.to_string(),
+                        ));
+                    }
+                    // A watch asked for before this subscription reached us starts now
+                    if state
+                        .messaging
+                        .mailbox
+                        .write()
+                        .subscribed(&peer_id, topic.as_str())
+                    {
+                        mailbox::subscribe(swarm, &logger, topic.to_string());
+                    }
+                    // A new follower gets our profile without waiting for the next broadcast
+                    if topic.as_str() == identity_profile::profile_topic(&state.local_did) {
+                        Self::publish_profile(
+                            swarm,
+                            logger.clone(),
+                            &multi_pass,
+                            &*keystore,
+                            &state,
+                        );
+                    }
+                    let deferred = state.messaging.unpublished.write().take(topic.as_str());
+                    for sata in deferred {
+                        let name = topic.to_string();
+                        match Self::try_publish(swarm, logger.clone(), &state, name.clone(), &sata)
+                        {
+                            Ok(_) => {}
+                            Err(PublishFailure::InsufficientPeers) => {
+                                state.messaging.unpublished.write().defer(name, sata);
                             }
+                            Err(failure) => logger
+                                .event_occurred(Event::ErrorPublishingData(failure.to_string())),
                         }
-                        Err(_) => {
-                            logger.write().event_occurred(Event::ErrorDeserializingData);
+                    }
+                    // Someone can hear us now, retry what was journaled but never went out
+                    let pending = state
+                        .messaging
+                        .wal
+                        .read()
+                        .as_ref()
+                        .map(|x| x.pending())
+                        .unwrap_or_default();
+                    for (id, operation) in pending {
+                        match operation {
+                            WalOperation::Publish(name, sata) => {
+                                if name == topic.as_str()
+                                    && Self::publish(swarm, logger.clone(), &state, name, &sata)
+                                {
+                                    Self::commit_journal(
+                                        state.messaging.wal.clone(),
+                                        logger.clone(),
+                                        id,
+                                    );
+                                }
+                            }
+                            // Parts of a transaction are retried through the outbox, bans when the log opens
+                            WalOperation::TransactionPart(..) | WalOperation::GroupBan(..) => {}
                         }
                     }
+                    let waiting = state.messaging.outbox.read().waiting_on(topic.as_str());
+                    for id in waiting {
+                        Self::run_transaction(swarm, logger.clone(), &state, id);
+                    }
+                }
+                GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                    if state
+                        .peers
+                        .topic_members
+                        .write()
+                        .unsubscribed(topic.as_str(), &peer_id)
+                    {
+                        logger.event_occurred(Event::PeerLeftTopic(
+                            state.did_of(&peer_id),
+                            topic.to_string(),
+                        ));
+                    }
                 }
-                GossipsubEvent::Subscribed { .. } => {}
-                GossipsubEvent::Unsubscribed { .. } => {}
                 GossipsubEvent::GossipsubNotSupported { .. } => {}
             },
             SwarmEvent::Behaviour(BehaviourEvent::KademliaEvent(kad)) => match kad {
                 KademliaEvent::InboundRequest { .. } => {}
+                KademliaEvent::OutboundQueryCompleted { id, result, .. }
+                    if state.network.dht.read().waits_for(&id) =>
+                {
+                    let kademlia = &mut swarm.behaviour_mut().kademlia;
+                    let answer = dht::answer(result, |peer| kademlia.addresses_of_peer(peer));
+                    if let Some(waiter) = state.network.dht.write().completed(&id) {
+                        let _ = waiter.send(answer);
+                    }
+                }
                 KademliaEvent::OutboundQueryCompleted { result, .. } => match result {
-                    QueryResult::Bootstrap(_) => {}
+                    QueryResult::Bootstrap(Ok(BootstrapOk { num_remaining, .. }))
+                        if num_remaining > 0 => {}
+                    QueryResult::Bootstrap(_) => {
+                        state.network.readiness.write().bootstrapped();
+                        state.network.readiness_changed.notify_waiters();
+                    }
                     QueryResult::GetClosestPeers(Ok(ok)) => {
                         let kademlia = &mut swarm.behaviour_mut().kademlia;
                         for peer in ok.peers {
@@ -288,11 +1366,59 @@ impl PeerToPeerService {
                             }
                         }
                     }
-                    QueryResult::GetProviders(_) => {}
-                    QueryResult::StartProviding(_) => {}
+                    QueryResult::GetProviders(Ok(GetProvidersOk {
+                        key,
+                        providers: found,
+                        ..
+                    })) => {
+                        if let Ok(cid) = String::from_utf8(key.to_vec()) {
+                            let local_peer_id = *swarm.local_peer_id();
+                            let new = state.network.conflux.write().providers_found(
+                                &cid,
+                                found.iter().copied(),
+                                &local_peer_id,
+                            );
+                            Self::ask_for_fragment(swarm, &state, &cid, new);
+                            state.network.providers.write().providers_found(
+                                &logger,
+                                cid,
+                                &found,
+                                &local_peer_id,
+                            );
+                        }
+                    }
+                    QueryResult::GetProviders(Err(err)) => {
+                        if let Ok(cid) = String::from_utf8(err.key().to_vec()) {
+                            let local_peer_id = *swarm.local_peer_id();
+                            state.network.conflux.write().providers_found(
+                                &cid,
+                                Vec::new(),
+                                &local_peer_id,
+                            );
+                        }
+                    }
+                    QueryResult::StartProviding(Ok(ok)) => {
+                        if let Ok(cid) = String::from_utf8(ok.key.to_vec()) {
+                            logger.event_occurred(Event::ContentProvided(cid));
+                        }
+                    }
+                    QueryResult::StartProviding(Err(err)) => {
+                        logger.event_occurred(Event::ErrorProvidingContent(err.to_string()));
+                    }
                     QueryResult::RepublishProvider(_) => {}
-                    QueryResult::GetRecord(_) => {}
-                    QueryResult::PutRecord(_) => {}
+                    QueryResult::GetRecord(Ok(ok)) => {
+                        let records = ok.records.into_iter().map(|x| x.record);
+                        Self::did_records_found(swarm, &logger, &state, records);
+                    }
+                    QueryResult::GetRecord(Err(err)) => {
+                        logger.event_occurred(Event::DidRecordError(err.to_string()));
+                    }
+                    QueryResult::PutRecord(Ok(_)) => {
+                        logger.event_occurred(Event::DidRecordPublished);
+                    }
+                    QueryResult::PutRecord(Err(err)) => {
+                        logger.event_occurred(Event::DidRecordError(err.to_string()));
+                    }
                     QueryResult::RepublishRecord(_) => {}
                     _ => {}
                 },
@@ -301,93 +1427,3188 @@ impl PeerToPeerService {
                 KademliaEvent::RoutablePeer { .. } => {}
                 KademliaEvent::PendingRoutablePeer { .. } => {}
             },
+            SwarmEvent::Behaviour(BehaviourEvent::MailboxEvent(event)) => {
+                mailbox::handle_event(swarm, logger, &state, message_sender, event).await
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::DeviceSyncEvent(event)) => {
+                device_sync::handle_event(swarm, cache, logger, &state, event)
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::StreamEvent(event)) => {
+                streams::handle_event(swarm, logger, &state, event)
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::ProfileEvent(event)) => match event {
+                RequestResponseEvent::Message { peer, message } => match message {
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    } => {
+                        Self::profile_received(logger.clone(), &state, peer, request);
+                        let profile = Self::local_profile(&state);
+                        state.count_sent(&peer, None, &profile);
+                        // The peer disconnected, it will get our profile when it identifies us again
+                        let _ = swarm
+                            .behaviour_mut()
+                            .profile
+                            .send_response(channel, profile);
+                    }
+                    RequestResponseMessage::Response { response, .. } => {
+                        Self::profile_received(logger, &state, peer, response);
+                    }
+                },
+                // Peers running an older Blink don't speak the protocol, they simply advertise nothing
+                RequestResponseEvent::OutboundFailure { .. } => {}
+                RequestResponseEvent::InboundFailure { .. } => {}
+                RequestResponseEvent::ResponseSent { .. } => {}
+            },
+            SwarmEvent::Behaviour(BehaviourEvent::DirectEvent(event)) => match event {
+                RequestResponseEvent::Message {
+                    peer,
+                    message:
+                        RequestResponseMessage::Request {
+                            request, channel, ..
+                        },
+                } => {
+                    // The peer disconnected, it knows nothing of what we make of the message anyway
+                    let _ = swarm
+                        .behaviour_mut()
+                        .direct
+                        .send_response(channel, DirectAck);
+                    let topic = TopicHash::from_raw(request.topic);
+                    // Only what gossipsub would have delivered to us
+                    if !swarm.behaviour().gossip_sub.topics().any(|x| *x == topic) {
+                        tracing::debug!(%peer, %topic, "flooded on a topic we're not subscribed to");
+                        return;
+                    }
+                    if !state.may_flood(&peer, topic.as_str()) {
+                        tracing::debug!(%peer, %topic, "flooded by a peer that can't publish there");
+                        return;
+                    }
+                    #[cfg(feature = "metrics")]
+                    state.metrics.write().received(topic.as_str());
+                    Self::payload_received(
+                        swarm,
+                        logger,
+                        &state,
+                        message_sender,
+                        peer,
+                        topic,
+                        &request.data,
+                    )
+                    .await;
+                }
+                RequestResponseEvent::Message { .. } => {}
+                // Lost like a message gossipsub couldn't deliver
+                RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                    tracing::debug!(%peer, %error, "flooding failed");
+                }
+                RequestResponseEvent::InboundFailure { .. } => {}
+                RequestResponseEvent::ResponseSent { .. } => {}
+            },
+            SwarmEvent::Behaviour(BehaviourEvent::ConfluxEvent(event)) => match event {
+                RequestResponseEvent::Message { peer, message } => match message {
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    } => {
+                        let cid = match &request {
+                            FragmentRequest::Want(cid) => cid.as_str(),
+                            FragmentRequest::Update(fragment) => fragment.cid(),
+                        };
+                        state.network.conflux.write().interest(cid, peer);
+                        if let FragmentRequest::Update(fragment) = &request {
+                            // Forged updates and fragments we don't hold are dropped
+                            if let Ok(true) = state.network.conflux.write().merge(fragment.clone())
+                            {
+                                logger.event_occurred(Event::FragmentMerged(
+                                    fragment.cid().to_string(),
+                                ));
+                            }
+                        }
+                        let response = state.network.conflux.write().respond(request);
+                        state.count_sent(&peer, None, &response);
+                        // The peer went away, it asks someone else
+                        let _ = swarm
+                            .behaviour_mut()
+                            .conflux
+                            .send_response(channel, response);
+                    }
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    } => match response {
+                        FragmentResponse::Have(fragment) => {
+                            let cid = fragment.cid().to_string();
+                            let genuine = fragment.verify();
+                            if state
+                                .network
+                                .conflux
+                                .write()
+                                .received(&request_id, fragment)
+                            {
+                                logger.event_occurred(Event::FragmentMerged(cid.clone()));
+                            }
+                            if genuine {
+                                state.network.conflux.write().interest(&cid, peer);
+                            }
+                        }
+                        FragmentResponse::DontHave => {
+                            state.network.conflux.write().missed(&request_id);
+                        }
+                    },
+                },
+                RequestResponseEvent::OutboundFailure { request_id, .. } => {
+                    state.network.conflux.write().missed(&request_id);
+                }
+                RequestResponseEvent::InboundFailure { .. } => {}
+                RequestResponseEvent::ResponseSent { .. } => {}
+            },
+            SwarmEvent::Behaviour(BehaviourEvent::FileTransferEvent(event)) => {
+                file_transfer::handle_event(swarm, logger, &state, event)
+            }
             SwarmEvent::ConnectionEstablished { peer_id, .. } => {
-                logger
-                    .write()
-                    .event_occurred(Event::ConnectionEstablished(peer_id.to_string()));
+                #[cfg(feature = "metrics")]
+                state.metrics.write().connection_established();
+                logger.event_occurred(Event::ConnectionEstablished(peer_id.to_string()));
+                let now = state.clock.now_millis();
+                state.peers.idle.write().active(&peer_id, now);
+                if state.network.rendezvous.read().is_node(&peer_id) {
+                    Self::rendezvous_node_connected(swarm, &state, peer_id);
+                }
             }
-            SwarmEvent::ConnectionClosed { peer_id, .. } => {
-                logger
-                    .write()
-                    .event_occurred(Event::PeerConnectionClosed(peer_id.to_string()));
+            SwarmEvent::Behaviour(BehaviourEvent::RendezvousEvent(event)) => {
+                Self::rendezvous_event(swarm, logger, &state, event);
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                #[cfg(feature = "metrics")]
+                state.metrics.write().connection_closed();
+                logger.event_occurred(Event::PeerConnectionClosed(peer_id.to_string()));
+                if num_established == 0 {
+                    state.peers.idle.write().disconnected(&peer_id);
+                    state.peers.pings.write().disconnected(&peer_id);
+                    state.peers.scores.write().disconnected(&peer_id);
+                    state.peers.identified.write().disconnected(&peer_id);
+                    state.messaging.mailbox.write().disconnected(&peer_id);
+                    let topics = state.peers.topic_members.write().disconnected(&peer_id);
+                    for topic in topics {
+                        logger.event_occurred(Event::PeerLeftTopic(state.did_of(&peer_id), topic));
+                    }
+                }
+            }
+            SwarmEvent::IncomingConnection { send_back_addr, .. } => {
+                logger.event_occurred(Event::IncomingConnection(send_back_addr));
+            }
+            SwarmEvent::IncomingConnectionError {
+                send_back_addr,
+                error,
+                ..
+            } => {
+                logger.event_occurred(Event::IncomingConnectionError(
+                    send_back_addr,
+                    error.to_string(),
+                ));
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error } => {
+                #[cfg(feature = "metrics")]
+                state.metrics.write().dial_failed();
+                logger.event_occurred(Event::OutgoingConnectionError(
+                    peer_id.map(|x| x.to_string()),
+                    error.to_string(),
+                ));
+            }
+            SwarmEvent::BannedPeer { peer_id, .. } => {
+                logger.event_occurred(Event::BannedPeer(peer_id.to_string()));
             }
-            SwarmEvent::IncomingConnection { .. } => {}
-            SwarmEvent::IncomingConnectionError { .. } => {}
-            SwarmEvent::OutgoingConnectionError { .. } => {}
-            SwarmEvent::BannedPeer { .. } => {}
             SwarmEvent::NewListenAddr { address, .. } => {
-                logger.write().event_occurred(Event::NewListenAddr(address));
+                state.network.readiness.write().listening(address.clone());
+                state.network.readiness_changed.notify_waiters();
+                logger.event_occurred(Event::NewListenAddr(address));
+                Self::publish_did_record(swarm, &logger, &*keystore, &state);
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::PingEvent(PingEvent { peer, result })) => {
+                match result {
+                    Ok(PingSuccess::Ping { rtt }) => {
+                        state.peers.pings.write().succeeded(&peer, rtt)
+                    }
+                    Ok(PingSuccess::Pong) => {}
+                    // Not a sign of a bad link
+                    Err(PingFailure::Unsupported) => {}
+                    Err(_) => {
+                        if state.peers.pings.write().failed(&peer) {
+                            logger.event_occurred(Event::PeerUnresponsive(state.did_of(&peer)));
+                        }
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::RelayEvent(event)) => {
+                tracing::debug!(?event, "relay server");
+                state.network.relay.write().event(&event);
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                state.network.readiness.write().expired(&address);
+            }
+            SwarmEvent::ListenerClosed { .. } => {}
+            SwarmEvent::ListenerError { .. } => {}
+            SwarmEvent::Dialing(peer_id) => {
+                #[cfg(feature = "metrics")]
+                state.metrics.write().dialed();
+                logger.event_occurred(Event::Dialing(peer_id.to_string()));
             }
-            SwarmEvent::ExpiredListenAddr { .. } => {}
-            SwarmEvent::ListenerClosed { .. } => {}
-            SwarmEvent::ListenerError { .. } => {}
-            SwarmEvent::Dialing(_) => {}
             _ => {}
         }
     }
 
-    fn generate_topic_from_key_exchange(private_key: &DID, public_key: &DID) -> String {
-        let private_key_pair =
-            Ed25519KeyPair::from_secret_key(&private_key.as_ref().private_key_bytes()).get_x25519();
-        let public_key_pair =
-            Ed25519KeyPair::from_public_key(&public_key.as_ref().public_key_bytes()).get_x25519();
-        let exchange = private_key_pair.key_exchange(&public_key_pair);
-        let hashed = Hash::hash(exchange);
-        let topic = base64::encode(hashed);
+    // Pairs with an identified peer whose identity MultiPass knows about
+    fn peer_identified(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        multi_pass: Arc<RwLock<dyn MultiPass>>,
+        keystore: Arc<dyn Keystore>,
+        state: &SharedState,
+        peer_id: PeerId,
+        info: IdentifyInfo,
+    ) {
+        // Peers we didn't pair with yet go through pairing again, they may have become known
+        let advertisement = state.peers.identified.write().advertised(peer_id, &info);
+        let paired = state
+            .peers
+            .map_did_peer
+            .read()
+            .values()
+            .any(|x| *x == peer_id);
+        match advertisement {
+            _ if !paired => {}
+            Advertisement::New => {}
+            Advertisement::Changed => {
+                let kademlia = &mut swarm.behaviour_mut().kademlia;
+                for addr in info.listen_addrs {
+                    kademlia.add_address(&peer_id, addr);
+                }
+                logger.event_occurred(Event::PeerIdentifyUpdated(state.did_of(&peer_id)));
+                return;
+            }
+            Advertisement::Same => return,
+        }
+        let did_result = DeviceCertificate::from_agent_version(&info.agent_version)
+            .and_then(|x| x.verify(&peer_id));
+
+        match did_result {
+            Ok(their_public) => {
+                state
+                    .peers
+                    .identified
+                    .write()
+                    .certified(peer_id, their_public.to_string());
+                // Friends made over Blink are paired whether MultiPass knows them or not
+                let known = state
+                    .peers
+                    .friends
+                    .read()
+                    .is_friend(&their_public.to_string())
+                    || multi_pass
+                        .read()
+                        .get_identity(Identifier::from(their_public.clone()))
+                        .is_ok();
+                match known {
+                    true if !version::is_compatible(&info.protocol_version) => {
+                        logger.event_occurred(Event::IncompatiblePeer(
+                            their_public.to_string(),
+                            info.protocol_version,
+                            version::PROTOCOL_VERSION.to_string(),
+                        ));
+                        if swarm.disconnect_peer_id(peer_id).is_err() {
+                            logger.event_occurred(Event::FailureToDisconnectPeer);
+                        }
+                    }
+                    true => Self::pair(swarm, logger, &*keystore, state, peer_id, their_public),
+                    false => {
+                        logger.event_occurred(Event::FailureToIdentifyPeer);
+                        // Left a moment to ask us to be friends, or to take a request of ours
+                        let commands = state.commands.clone();
+                        let sleep = state.clock.sleep(STRANGER_GRACE);
+                        runtime::spawn(async move {
+                            sleep.await;
+                            let _ = commands.send(BlinkCommand::DropStranger(peer_id)).await;
+                        });
+                    }
+                }
+            }
+            Err(_) => {
+                logger.event_occurred(Event::ConvertKeyError);
+            }
+        }
+    }
+
+    // Subscribes to the pairwise topic of an identified peer we trust
+    pub(crate) fn pair(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        keystore: &dyn Keystore,
+        state: &SharedState,
+        peer_id: PeerId,
+        their_public: DID,
+    ) {
+        let topic = match Self::pairwise_topic(keystore, &their_public) {
+            Ok(topic) => topic,
+            Err(_) => {
+                logger.event_occurred(Event::ConvertKeyError);
+                return;
+            }
+        };
+        let pb = their_public.clone().to_string();
+        let profile_topic = state.peers.profiles.write().follow(&pb);
+        state.peers.map_did_peer.write().insert(pb.clone(), peer_id);
+        state.peers.map_peer_topic.write().insert(pb, topic.clone());
+        state.peers.topic_members.read().paired();
+        if let Some(profile_topic) = profile_topic {
+            if let Err(e) = swarm
+                .behaviour_mut()
+                .gossip_sub
+                .subscribe(&IdentTopic::new(profile_topic))
+            {
+                logger.event_occurred(Event::SubscriptionError(e.to_string()));
+            }
+        }
+
+        let namespaces = state.conversation_namespaces(&their_public.to_string());
+        let topic_subs = IdentTopic::new(&topic);
+        match swarm.behaviour_mut().gossip_sub.subscribe(&topic_subs) {
+            Ok(_) => {
+                logger.event_occurred(Event::GeneratedTopic(their_public.clone(), topic.clone()));
+                logger.event_occurred(Event::SubscribedToTopic(topic.clone()));
+                logger.event_occurred(Event::PeerIdentified);
+                Self::subscribe_extension_topics(
+                    swarm,
+                    logger.clone(),
+                    &[topic.clone()],
+                    &namespaces,
+                );
+                Self::send_profile(swarm, state, &peer_id);
+                let held = state
+                    .peers
+                    .auto_pairing
+                    .write()
+                    .paired(&their_public.to_string());
+                for sata in held {
+                    state
+                        .messaging
+                        .conversations
+                        .write()
+                        .record(StoredMessage::new(
+                            state.local_did.clone(),
+                            topic.clone(),
+                            state.clock.now_millis(),
+                            sata.clone(),
+                        ));
+                    Self::publish_command(swarm, logger.clone(), state, topic.clone(), sata, None);
+                }
+            }
+            Err(er) => {
+                logger.event_occurred(Event::SubscriptionError(er.to_string()));
+            }
+        }
+    }
+
+    // Connected peers learn our new addresses now instead of at the next identify round
+    fn push_identify(swarm: &mut Swarm<BlinkBehavior>) {
+        let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+        swarm.behaviour_mut().identity.push(peers);
+    }
+
+    // Publishes our profile as MultiPass has it, with the picture in Conflux and only its CID in the profile
+    fn publish_profile(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        multi_pass: &Arc<RwLock<dyn MultiPass>>,
+        keystore: &dyn Keystore,
+        state: &SharedState,
+    ) {
+        let identity = match multi_pass.read().get_identity(Identifier::own()) {
+            Ok(identity) => identity,
+            Err(e) => {
+                logger.event_occurred(Event::ProfileError(e.to_string()));
+                return;
+            }
+        };
+        let picture = identity.graphics().profile_picture();
+        let avatar = match picture.is_empty() {
+            true => None,
+            false => match Self::store_blob(swarm, &logger, state, picture.as_bytes()) {
+                Ok(cid) => Some(cid),
+                Err(e) => {
+                    logger.event_occurred(Event::ProfileError(e.to_string()));
+                    return;
+                }
+            },
+        };
+        let profile = IdentityProfile {
+            username: identity.username(),
+            status: identity.status_message(),
+            avatar,
+            published_at: state.clock.now_millis(),
+        };
+        let sata = SignedProfile::new(keystore, state.local_did.clone(), profile)
+            .and_then(|x| x.to_sata());
+        let sata = match sata {
+            Ok(sata) => sata,
+            Err(e) => {
+                logger.event_occurred(Event::ProfileError(e.to_string()));
+                return;
+            }
+        };
+        let topic = identity_profile::profile_topic(&state.local_did);
+        match Self::try_publish(swarm, logger.clone(), state, topic, &sata) {
+            // Nobody follows us yet, followers get it once they subscribe
+            Ok(_) | Err(PublishFailure::InsufficientPeers) => {}
+            Err(failure) => {
+                logger.event_occurred(Event::ErrorPublishingData(failure.to_string()));
+            }
+        }
+    }
+
+    // Stores, pins and provides every fragment of the blob, returns its root CID
+    fn store_blob(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: &EventSink,
+        state: &SharedState,
+        data: &[u8],
+    ) -> Result<String> {
+        let tree = FragmentTree::split(data, state.clock.now_millis())?;
+        let root = tree.root().cid().to_string();
+        // Same picture as last time, it's all there already
+        if state.network.conflux.read().stored(&root)?.is_some() {
+            return Ok(root);
+        }
+        for fragment in tree.into_fragments() {
+            let cid = fragment.cid().to_string();
+            {
+                let mut conflux = state.network.conflux.write();
+                conflux.add_fragment(fragment)?;
+                conflux.pin(cid.clone());
+            }
+            state.network.providers.write().track(cid.clone());
+            if let Err(e) = swarm
+                .behaviour_mut()
+                .kademlia
+                .start_providing(Key::new(&cid))
+            {
+                logger.event_occurred(Event::ErrorProvidingContent(e.to_string()));
+            }
+        }
+        Ok(root)
+    }
+
+    // A profile published by a DID we follow, cached under accounts when it's newer than the one we had
+    fn identity_profile_received(logger: EventSink, state: &SharedState, topic: &str, info: Sata) {
+        let signed = match info.decode::<SignedProfile>() {
+            Ok(signed) => signed,
+            Err(_) => {
+                logger.event_occurred(Event::ErrorDeserializingData);
+                return;
+            }
+        };
+        let did = signed.did.clone();
+        if state.peers.profiles.write().update(topic, signed) {
+            state
+                .cache_writer
+                .write_as(&logger, DataType::Accounts, info);
+            logger.event_occurred(Event::ProfileUpdated(did));
+        }
+    }
+
+    fn publish(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        name: TopicName,
+        sata: &Sata,
+    ) -> bool {
+        match Self::try_publish(swarm, logger.clone(), state, name, sata) {
+            Ok(_) => true,
+            Err(failure) => {
+                logger.event_occurred(Event::ErrorPublishingData(failure.to_string()));
+                false
+            }
+        }
+    }
+
+    // Serialization failures are reported here, publish failures are left to the caller
+    fn try_publish(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        name: TopicName,
+        sata: &Sata,
+    ) -> std::result::Result<(), PublishFailure> {
+        if state.drops_publish() {
+            return Ok(());
+        }
+        let expires_at = state.messaging.expiry.read().expires_at(sata);
+        // Expired while waiting to go out, it's deleted rather than sent
+        if expires_at.map_or(false, |x| x <= state.clock.now_millis()) {
+            return Ok(());
+        }
+        let serialized = Self::seal(state, &name, sata, expires_at).map_err(|e| {
+            logger.event_occurred(Event::ErrorSerializingData);
+            PublishFailure::Permanent(e.to_string())
+        })?;
+        Self::publish_sealed(swarm, state, name, serialized)
+    }
+
+    // Envelopes on pairwise topics are signed, so a mailbox can pass them on but not forge them
+    fn seal(
+        state: &SharedState,
+        name: &str,
+        sata: &Sata,
+        expires_at: Option<u64>,
+    ) -> Result<Vec<u8>> {
+        let envelope = state
+            .messaging
+            .wire
+            .read()
+            .seal_expiring(sata, expires_at)?;
+        if state.paired_did_of_topic(name).is_none() {
+            return Ok(envelope);
+        }
+        SignedEnvelope::new(&*state.keystore, name, envelope)?.seal()
+    }
+
+    fn publish_sealed(
+        swarm: &mut Swarm<BlinkBehavior>,
+        state: &SharedState,
+        name: TopicName,
+        serialized: Vec<u8>,
+    ) -> std::result::Result<(), PublishFailure> {
+        let bytes = serialized.len() as u64;
+        let members = state.peers.topic_members.read().peers(&name);
+        let strategy = state
+            .messaging
+            .delivery
+            .read()
+            .strategy(&name, members.len());
+        if strategy == DeliveryStrategy::Flood {
+            return Self::flood(swarm, state, name, serialized, &members);
+        }
+        let topic = IdentTopic::new(name);
+        let result = match swarm.behaviour_mut().gossip_sub.publish(topic, serialized) {
+            // Already went out, the mesh has it
+            Ok(_) | Err(PublishError::Duplicate) => Ok(()),
+            Err(err) => Err(err.into()),
+        };
+        if let Err(failure) = &result {
+            tracing::debug!(%failure, "publish failed");
+        }
+        if result.is_ok() {
+            let now = state.clock.now_millis();
+            state.network.bandwidth.write().sent(None, None, bytes, now);
+            for peer in &members {
+                state.peers.idle.write().active(peer, now);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        state.metrics.write().published(result.is_ok());
+        result
+    }
+
+    // Sends the envelope to each subscriber instead of waiting for the mesh to have them
+    fn flood(
+        swarm: &mut Swarm<BlinkBehavior>,
+        state: &SharedState,
+        name: TopicName,
+        serialized: Vec<u8>,
+        members: &[PeerId],
+    ) -> std::result::Result<(), PublishFailure> {
+        let result = if members.is_empty() {
+            Err(PublishFailure::InsufficientPeers)
+        } else {
+            Ok(())
+        };
+        let message = DirectMessage {
+            topic: name,
+            data: serialized,
+        };
+        for peer in members {
+            state.count_sent(peer, None, &message);
+            swarm
+                .behaviour_mut()
+                .direct
+                .send_request(peer, message.clone());
+        }
+        #[cfg(feature = "metrics")]
+        state.metrics.write().published(result.is_ok());
+        result
+    }
+
+    // Publishes the parts of a transaction that didn't go out yet and reports once it's settled
+    fn run_transaction(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        id: TransactionId,
+    ) {
+        let pending = state.messaging.outbox.read().pending_parts(id);
+        for (index, topic, sata) in pending {
+            if Self::publish(swarm, logger.clone(), state, topic, &sata) {
+                let journal_id = state.messaging.outbox.write().part_sent(id, index);
+                if let Some(journal_id) = journal_id {
+                    Self::commit_journal(state.messaging.wal.clone(), logger.clone(), journal_id);
+                }
+            }
+        }
+
+        let outcome = state.messaging.outbox.write().finish_attempt(id);
+        match outcome {
+            TransactionOutcome::Pending => {}
+            TransactionOutcome::Completed => {
+                logger.event_occurred(Event::TransactionCompleted(id));
+            }
+            TransactionOutcome::Failed(undelivered, journal_ids) => {
+                for journal_id in journal_ids {
+                    Self::commit_journal(state.messaging.wal.clone(), logger.clone(), journal_id);
+                }
+                logger.event_occurred(Event::TransactionFailed(
+                    id,
+                    format!("{} message(s) couldn't be delivered", undelivered),
+                ));
+            }
+        }
+    }
+
+    pub(crate) fn publish_command(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        name: TopicName,
+        sata: Sata,
+        journal_id: Option<u64>,
+    ) {
+        match Self::try_publish(swarm, logger.clone(), state, name.clone(), &sata) {
+            Ok(_) => {
+                if let Some(id) = journal_id {
+                    Self::commit_journal(state.messaging.wal.clone(), logger, id);
+                }
+            }
+            Err(PublishFailure::InsufficientPeers) => {
+                // Journaled messages are retried from the write-ahead log
+                if journal_id.is_none() {
+                    state
+                        .messaging
+                        .unpublished
+                        .write()
+                        .defer(name.clone(), sata);
+                }
+                logger.event_occurred(Event::PublishDeferred(name));
+            }
+            Err(failure) => {
+                logger.event_occurred(Event::ErrorPublishingData(failure.to_string()));
+            }
+        }
+    }
+
+    // Publishes what was batched for the topic as one envelope, a lone message goes out as usual
+    fn flush_batch(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        name: TopicName,
+    ) {
+        let mut batch = state.messaging.batcher.write().take(&name);
+        if batch.len() <= 1 {
+            if let Some(sata) = batch.pop() {
+                Self::publish_command(swarm, logger, state, name, sata, None);
+            }
+            return;
+        }
+        if state.drops_publish() {
+            return;
+        }
+        let sealed = batch
+            .iter()
+            .map(|x| Self::seal(state, &name, x, None))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|x| state.messaging.wire.read().batch(&x));
+        let serialized = match sealed {
+            Ok(serialized) => serialized,
+            Err(_) => {
+                logger.event_occurred(Event::ErrorSerializingData);
+                return;
+            }
+        };
+        match Self::publish_sealed(swarm, state, name.clone(), serialized) {
+            Ok(_) => {}
+            Err(PublishFailure::InsufficientPeers) => {
+                // Deferred one by one, they go out unbatched once someone joins
+                for sata in batch {
+                    state
+                        .messaging
+                        .unpublished
+                        .write()
+                        .defer(name.clone(), sata);
+                }
+                logger.event_occurred(Event::PublishDeferred(name));
+            }
+            Err(failure) => {
+                logger.event_occurred(Event::ErrorPublishingData(failure.to_string()));
+            }
+        }
+    }
+
+    fn commit_journal(wal: Arc<RwLock<Option<WriteAheadLog>>>, logger: EventSink, id: u64) {
+        if let Some(wal) = wal.write().as_mut() {
+            if let Err(e) = wal.commit(id) {
+                logger.event_occurred(Event::WriteAheadLogError(e.to_string()));
+            }
+        }
+    }
+
+    fn local_profile(state: &SharedState) -> PeerProfile {
+        PeerProfile {
+            extensions: state.messaging.extensions.read().namespaces(),
+            status: state.peers.presence.read().own(),
+        }
+    }
+
+    fn send_profile(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState, peer_id: &PeerId) {
+        let profile = Self::local_profile(state);
+        state.count_sent(peer_id, None, &profile);
+        swarm.behaviour_mut().profile.send_request(peer_id, profile);
+    }
+
+    fn profile_received(
+        logger: EventSink,
+        state: &SharedState,
+        peer: PeerId,
+        profile: PeerProfile,
+    ) {
+        let changed = state
+            .messaging
+            .extensions
+            .write()
+            .set_remote(peer, profile.extensions);
+        if changed {
+            let extensions = state
+                .messaging
+                .extensions
+                .read()
+                .remote(&peer)
+                .unwrap_or_default();
+            logger.event_occurred(Event::PeerExtensionsChanged(
+                state.did_of(&peer),
+                extensions,
+            ));
+        }
+        let did = state.did_of(&peer);
+        if state
+            .peers
+            .presence
+            .write()
+            .announced(did.clone(), profile.status)
+        {
+            logger.event_occurred(Event::ContactStatusChanged(did, profile.status));
+        }
+    }
+
+    fn collect_garbage(swarm: &mut Swarm<BlinkBehavior>, logger: &EventSink, state: &SharedState) {
+        let now = state.clock.now_millis();
+        let evicted = state.network.conflux.write().collect_garbage(now);
+        for cid in evicted {
+            if state.network.providers.write().untrack(&cid) {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .stop_providing(&Key::new(&cid));
+            }
+            logger.event_occurred(Event::FragmentEvicted(cid));
+        }
+    }
+
+    // Registrations expire, this runs again with every provider reannouncement
+    fn register_at_rendezvous(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState) {
+        let nodes = state.network.rendezvous.read().nodes();
+        for node in nodes {
+            if !swarm.is_connected(&node) {
+                continue;
+            }
+            let namespace = rendezvous::namespace_of(&state.local_did);
+            swarm
+                .behaviour_mut()
+                .rendezvous
+                .register(namespace, node, None);
+        }
+    }
+
+    fn rendezvous_node_connected(
+        swarm: &mut Swarm<BlinkBehavior>,
+        state: &SharedState,
+        node: PeerId,
+    ) {
+        let namespace = rendezvous::namespace_of(&state.local_did);
+        let behaviour = &mut swarm.behaviour_mut().rendezvous;
+        behaviour.register(namespace, node, None);
+        for wanted in state.network.rendezvous.read().wanted() {
+            behaviour.discover(Some(wanted), None, None, node);
+        }
+    }
+
+    fn rendezvous_event(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        event: RendezvousEvent,
+    ) {
+        match event {
+            RendezvousEvent::Registered {
+                rendezvous_node, ..
+            } => {
+                logger.event_occurred(Event::RendezvousRegistered(rendezvous_node.to_string()));
+            }
+            RendezvousEvent::Discovered { registrations, .. } => {
+                for registration in registrations {
+                    let peer = registration.record.peer_id();
+                    if peer == state.local_peer {
+                        continue;
+                    }
+                    let addresses = registration.record.addresses().to_vec();
+                    for address in &addresses {
+                        swarm
+                            .behaviour_mut()
+                            .kademlia
+                            .add_address(&peer, address.clone());
+                    }
+                    if let Some(did) = state
+                        .network
+                        .rendezvous
+                        .read()
+                        .did_of(&registration.namespace)
+                    {
+                        logger.event_occurred(Event::RendezvousDiscovered(did, peer.to_string()));
+                    }
+                    // Pairing follows once identify tells us who it is
+                    if !swarm.is_connected(&peer) {
+                        let opts = DialOpts::peer_id(peer).addresses(addresses).build();
+                        if let Err(e) = swarm.dial(opts) {
+                            logger.event_occurred(Event::DialError(e.to_string()));
+                        }
+                    }
+                }
+            }
+            RendezvousEvent::RegisterFailed(error) => {
+                logger.event_occurred(Event::RendezvousError(error.to_string()));
+            }
+            RendezvousEvent::DiscoverFailed {
+                rendezvous_node,
+                error,
+                ..
+            } => {
+                logger.event_occurred(Event::RendezvousError(format!(
+                    "Discovery at {} failed: {:?}",
+                    rendezvous_node, error
+                )));
+            }
+            RendezvousEvent::Expired { .. } => {}
+        }
+    }
+
+    // Records expire, this runs again with every provider reannouncement and new listen address
+    fn publish_did_record(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: &EventSink,
+        keystore: &dyn Keystore,
+        state: &SharedState,
+    ) {
+        let mut addrs: Vec<Multiaddr> =
+            swarm.external_addresses().map(|x| x.addr.clone()).collect();
+        addrs.extend(swarm.listeners().cloned());
+        if addrs.is_empty() {
+            return;
+        }
+        let expires_at = state.clock.now_millis() + DID_RECORD_LIFETIME.as_millis() as u64;
+        let result = DidRecord::new(
+            keystore,
+            state.local_did.clone(),
+            &state.local_peer,
+            addrs,
+            expires_at,
+        )
+        .and_then(|record| record.to_record())
+        .and_then(|record| {
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .put_record(record, Quorum::One)?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            logger.event_occurred(Event::DidRecordError(e.to_string()));
+        }
+    }
+
+    fn did_records_found(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: &EventSink,
+        state: &SharedState,
+        records: impl Iterator<Item = libp2p::kad::Record>,
+    ) {
+        let now = state.clock.now_millis();
+        for record in records {
+            let found = DidRecord::from_record(&record, now)
+                .and_then(|record| Ok((record.peer_id()?, record)));
+            let (peer, record) = match found {
+                Ok(found) => found,
+                Err(e) => {
+                    logger.event_occurred(Event::DidRecordError(e.to_string()));
+                    continue;
+                }
+            };
+            if peer == state.local_peer {
+                continue;
+            }
+            for address in &record.addrs {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer, address.clone());
+            }
+            logger.event_occurred(Event::DidResolved(record.did, peer.to_string()));
+            // Pairing follows once identify tells us who it is
+            if !swarm.is_connected(&peer) {
+                let opts = DialOpts::peer_id(peer).addresses(record.addrs).build();
+                if let Err(e) = swarm.dial(opts) {
+                    logger.event_occurred(Event::DialError(e.to_string()));
+                }
+            }
+        }
+    }
+
+    fn ask_for_fragment(
+        swarm: &mut Swarm<BlinkBehavior>,
+        state: &SharedState,
+        cid: &str,
+        peers: Vec<PeerId>,
+    ) {
+        if !state.network.conflux.read().is_wanted(cid) {
+            return;
+        }
+        for peer_id in peers {
+            let request = FragmentRequest::Want(cid.to_string());
+            state.count_sent(&peer_id, None, &request);
+            let request_id = swarm
+                .behaviour_mut()
+                .conflux
+                .send_request(&peer_id, request);
+            state
+                .network
+                .conflux
+                .write()
+                .asked(request_id, cid.to_string(), peer_id);
+        }
+    }
+
+    // Gossipsub holds every message until it's told whether to pass it on
+    fn report_validation(
+        swarm: &mut Swarm<BlinkBehavior>,
+        message_id: &MessageId,
+        propagation_source: &PeerId,
+        verdict: Validation,
+    ) {
+        // Only fails for messages gossipsub already forgot about
+        let _ = swarm
+            .behaviour_mut()
+            .gossip_sub
+            .report_message_validation_result(
+                message_id,
+                propagation_source,
+                validators::acceptance(verdict),
+            );
+    }
+
+    pub(crate) fn subscribe_extension_topics(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        topics: &[String],
+        namespaces: &[String],
+    ) {
+        for topic in topics {
+            for namespace in namespaces {
+                let extension_topic = extensions::extension_topic(topic, namespace);
+                if let Err(err) = swarm
+                    .behaviour_mut()
+                    .gossip_sub
+                    .subscribe(&IdentTopic::new(extension_topic))
+                {
+                    logger.event_occurred(Event::SubscriptionError(err.to_string()));
+                }
+            }
+        }
+    }
+
+    // Extension messages skip the cache and the main message stream
+    fn route_to_extension(
+        logger: EventSink,
+        state: &SharedState,
+        pairwise_topic: &str,
+        namespace: &str,
+        info: Sata,
+    ) {
+        let handler = state.messaging.extensions.read().handler(namespace);
+        let handler = match handler {
+            Some(handler) => handler,
+            None => {
+                logger.event_occurred(Event::UnknownExtension(namespace.to_string()));
+                return;
+            }
+        };
+        match state.did_of_topic(pairwise_topic) {
+            Some(sender) => handler.write().message_received(sender, info),
+            None => logger.event_occurred(Event::UnpairedTopic(pairwise_topic.to_string())),
+        }
+    }
+
+    fn call_signal_received(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        pairwise_topic: &str,
+        info: Sata,
+    ) {
+        let sender = match state.did_of_topic(pairwise_topic) {
+            Some(sender) => sender,
+            None => {
+                logger.event_occurred(Event::UnpairedTopic(pairwise_topic.to_string()));
+                return;
+            }
+        };
+        let signal = match info.decode::<CallSignal>() {
+            Ok(signal) => signal,
+            Err(_) => {
+                logger.event_occurred(Event::ErrorDeserializingData);
+                return;
+            }
+        };
+        match signal {
+            CallSignal::Invite(id, kind) => {
+                if state.media.calls.write().incoming(id, sender.clone()) {
+                    logger.event_occurred(Event::IncomingCall(sender, id, kind));
+                } else {
+                    let topic =
+                        extensions::extension_topic(pairwise_topic, signaling::CALL_NAMESPACE);
+                    match Self::signal_to_sata(&CallSignal::Busy(id)) {
+                        Ok(busy) => {
+                            Self::publish(swarm, logger, state, topic, &busy);
+                        }
+                        Err(_) => logger.event_occurred(Event::ErrorSerializingData),
+                    }
+                }
+            }
+            CallSignal::Accept(id) => {
+                if state.media.calls.write().accepted(id, &sender) {
+                    logger.event_occurred(Event::CallAccepted(id));
+                }
+            }
+            CallSignal::Reject(id) => {
+                Self::call_ended(logger, state, &sender, id, CallEndReason::Rejected)
+            }
+            CallSignal::Busy(id) => {
+                Self::call_ended(logger, state, &sender, id, CallEndReason::Busy)
+            }
+            CallSignal::Hangup(id) => {
+                Self::call_ended(logger, state, &sender, id, CallEndReason::HungUp)
+            }
+        }
+    }
+
+    fn call_ended(
+        logger: EventSink,
+        state: &SharedState,
+        sender: &str,
+        id: CallId,
+        reason: CallEndReason,
+    ) {
+        if state.media.calls.write().end(id, sender).is_some() {
+            logger.event_occurred(Event::CallEnded(id, reason));
+        }
+    }
+
+    fn bench_message_received(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        pairwise_topic: &str,
+        info: Sata,
+    ) {
+        let sender = match state.did_of_topic(pairwise_topic) {
+            Some(sender) => sender,
+            None => {
+                logger.event_occurred(Event::UnpairedTopic(pairwise_topic.to_string()));
+                return;
+            }
+        };
+        let message = match info.decode::<BenchMessage>() {
+            Ok(message) => message,
+            Err(_) => {
+                logger.event_occurred(Event::ErrorDeserializingData);
+                return;
+            }
+        };
+        match message {
+            BenchMessage::Probe(id, sequence, sent_at, filler) => {
+                if !state.media.benchmarks.read().is_answering() {
+                    return;
+                }
+                let echo = BenchMessage::Echo(id, sequence, sent_at, filler);
+                match Sata::default().encode(IpldCodec::DagCbor, Kind::Dynamic, &echo) {
+                    Ok(echo) => {
+                        let topic = extensions::extension_topic(
+                            pairwise_topic,
+                            diagnostics::BENCH_NAMESPACE,
+                        );
+                        Self::publish(swarm, logger, state, topic, &echo);
+                    }
+                    Err(_) => logger.event_occurred(Event::ErrorSerializingData),
+                }
+            }
+            BenchMessage::Echo(id, sequence, sent_at, _) => {
+                state.media.benchmarks.write().echoed(
+                    id,
+                    &sender,
+                    sequence,
+                    sent_at,
+                    state.clock.now_millis(),
+                );
+            }
+        }
+    }
+
+    fn signal_to_sata(signal: &CallSignal) -> Result<Sata> {
+        Sata::default()
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, signal)
+            .map_err(|e| anyhow!("{:?}", e))
+    }
+
+    // Edits or deletes the message `sender` sent on the topic, in the history and the cache
+    fn apply_change(
+        logger: EventSink,
+        state: &SharedState,
+        topic: &str,
+        sender: &str,
+        change: MessageChange,
+    ) {
+        let id = change.id().to_string();
+        let now = state.clock.now_millis();
+        let (previous, replacement) = match change {
+            MessageChange::Edit(id, sata) => {
+                let previous = state.messaging.conversations.write().edit(
+                    topic,
+                    &id,
+                    sender,
+                    sata.clone(),
+                    now,
+                );
+                (previous, Some(sata))
+            }
+            MessageChange::Delete(id) => (
+                state
+                    .messaging
+                    .conversations
+                    .write()
+                    .delete(topic, &id, sender),
+                None,
+            ),
+        };
+        let previous = match previous {
+            Some(previous) => previous,
+            None => {
+                tracing::debug!(%topic, message = %id, "change to an unknown message ignored");
+                return;
+            }
+        };
+        // An edited disappearing message still disappears when the original would have
+        let expires_at = state.messaging.expiry.read().expires_at(&previous);
+        if let (Some(expires_at), Some(sata)) = (expires_at, &replacement) {
+            Self::schedule_expiry(state, conversations::message_id(sata), expires_at);
+        }
+        // The cache keeps the message, the change is cached after it by the id it's cached with
+        let cached = conversations::message_id(&previous);
+        let (event, change) = match replacement {
+            Some(sata) => (
+                Event::MessageEdited(topic.to_string(), id),
+                MessageChange::Edit(cached, sata),
+            ),
+            None => (
+                Event::MessageDeleted(topic.to_string(), id),
+                MessageChange::Delete(cached),
+            ),
+        };
+        match change.encode() {
+            Ok(change) => {
+                let writer = state.cache_writer.clone();
+                runtime::spawn(async move { writer.write_change(change).await });
+            }
+            Err(e) => logger.event_occurred(Event::ErrorAddingToCache(e.to_string())),
+        }
+        logger.event_occurred(event);
+    }
+
+    // Deletes the message wherever it's kept once the time, in milliseconds since the unix epoch, passed
+    pub(crate) fn schedule_expiry(state: &SharedState, id: String, expires_at: u64) {
+        if let Err(e) = state.messaging.expiry.write().schedule(id, expires_at) {
+            tracing::warn!("Couldn't save the expiry schedule: {}", e);
+        }
+    }
+
+    // Deletes the disappearing messages whose time is up from the history, the queues and the cache
+    fn expire_messages(logger: &EventSink, state: &SharedState) {
+        let due = match state.messaging.expiry.write().due(state.clock.now_millis()) {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::warn!("Couldn't save the expiry schedule: {}", e);
+                return;
+            }
+        };
+        for id in due {
+            state.messaging.conversations.write().remove(&id);
+            state.messaging.unpublished.write().forget(&id);
+            state.peers.auto_pairing.write().forget(&id);
+            state.messaging.mailbox.write().forget(&id);
+            match MessageChange::Delete(id.clone()).encode() {
+                Ok(change) => {
+                    let writer = state.cache_writer.clone();
+                    runtime::spawn(async move { writer.write_change(change).await });
+                }
+                Err(e) => logger.event_occurred(Event::ErrorAddingToCache(e.to_string())),
+            }
+            logger.event_occurred(Event::MessageExpired(id));
+        }
+    }
+
+    fn add_to_cache(logger: EventSink, state: &SharedState, topic: &str, info: &Sata) {
+        let direct = state.did_of_topic(topic).is_some();
+        let bytes = bincode::serialized_size(info).unwrap_or_default();
+        if !state.messaging.cache_ledger.write().admit(
+            topic,
+            direct,
+            bytes,
+            state.clock.now_millis(),
+        ) {
+            return;
+        }
+        if state.fails_cache_write() {
+            logger.event_occurred(Event::ErrorAddingToCache("Injected fault".into()));
+            return;
+        }
+        state.cache_writer.write(&logger, info.clone());
+    }
+
+    // A gossipsub message, or one flooded straight to us, returns what gossipsub should make of it
+    pub(crate) async fn payload_received(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        message_sender: &Sender<MessageContent>,
+        author: PeerId,
+        topic: TopicHash,
+        data: &[u8],
+    ) -> Validation {
+        let limited = state.messaging.rate_limiter.write().check(
+            &author,
+            topic.as_str(),
+            data.len(),
+            state.clock.now_millis(),
+        );
+        if let Err((limit, first)) = limited {
+            tracing::debug!(%author, %topic, ?limit, "rate limited");
+            if first {
+                logger.event_occurred(Event::RateLimited(author.to_string(), topic.to_string()));
+            }
+            return Validation::Ignore;
+        }
+        let opened = match state.messaging.wire.read().open_each(data) {
+            Ok(opened) => opened,
+            Err(_) => {
+                logger.event_occurred(Event::ErrorDeserializingData);
+                return Validation::Reject;
+            }
+        };
+        // Whoever relayed it, a message on a pairwise topic is from whoever signed it: the peer
+        // the topic is shared with, or another device of ours
+        let mut signer = None;
+        if let Some(paired) = state.paired_did_of_topic(topic.as_str()) {
+            let authors = [paired.as_str(), state.local_did.as_str()];
+            for message in &opened {
+                let verified = message
+                    .signed
+                    .as_ref()
+                    .map(|x| x.verify(topic.as_str(), &authors));
+                match verified {
+                    Some(Ok(did)) => signer = Some(did.to_string()),
+                    _ => {
+                        logger.event_occurred(Event::MessageRejected(
+                            topic.to_string(),
+                            state.did_of(&author),
+                        ));
+                        return Validation::Reject;
+                    }
+                }
+            }
+        }
+        let author = signer.unwrap_or_else(|| state.did_of(&author));
+        let now = state.clock.now_millis();
+        // Expired on the way, it's never delivered
+        let opened: Vec<Opened> = opened
+            .into_iter()
+            .filter(|x| x.expires_at.map_or(true, |x| x > now))
+            .collect();
+        let batch: Vec<Sata> = opened.iter().map(|x| x.sata.clone()).collect();
+        let validator = state.messaging.validators.read().validator(topic.as_str());
+        let verdict = validator.map_or(Validation::Accept, |x| {
+            validators::validate(&mut *x.write(), topic.as_str(), &author, &batch)
+        });
+        match verdict {
+            Validation::Accept => {}
+            Validation::Reject => {
+                logger.event_occurred(Event::MessageRejected(topic.to_string(), author));
+                return verdict;
+            }
+            Validation::Ignore => return verdict,
+        }
+        // Only accepted messages are scheduled, rejected ones never take a place in the queue
+        for message in &opened {
+            if let Some(expires_at) = message.expires_at {
+                Self::schedule_expiry(state, conversations::message_id(&message.sata), expires_at);
+            }
+        }
+        for message in opened {
+            Self::message_received(
+                swarm,
+                logger.clone(),
+                state,
+                message_sender,
+                topic.clone(),
+                message,
+            )
+            .await;
+        }
+        Validation::Accept
+    }
+
+    // A message that came in on a topic, from its own envelope or out of a batch
+    async fn message_received(
+        swarm: &mut Swarm<BlinkBehavior>,
+        logger: EventSink,
+        state: &SharedState,
+        message_sender: &Sender<MessageContent>,
+        hash: TopicHash,
+        message: Opened,
+    ) {
+        let info = message.sata;
+        let topic = hash.to_string();
+        let split = extensions::split_extension_topic(&topic);
+        let sender = state.did_of_topic(split.map_or(topic.as_str(), |x| x.0));
+        let (blocked, quarantined) = sender.as_ref().map_or((false, false), |x| {
+            let moderation = state.messaging.moderation.read();
+            (moderation.is_blocked(x), moderation.is_quarantined(x))
+        });
+        if blocked {
+            return;
+        }
+        // Quarantined messages land in the cache for review but skip the message stream
+        if let Some(sender) = sender.filter(|_| quarantined) {
+            if split.is_none() {
+                Self::add_to_cache(logger.clone(), state, &topic, &info);
+            }
+            logger.event_occurred(Event::MessageQuarantined(sender));
+            return;
+        }
+        if let Some((pairwise_topic, namespace)) = split {
+            if namespace == signaling::CALL_NAMESPACE {
+                Self::call_signal_received(swarm, logger, state, pairwise_topic, info);
+                return;
+            }
+            if namespace == diagnostics::BENCH_NAMESPACE {
+                Self::bench_message_received(swarm, logger, state, pairwise_topic, info);
+                return;
+            }
+            let opened = state.did_of_topic(pairwise_topic).map_or(false, |x| {
+                state.messaging.channels.read().is_open(&x, namespace)
+            });
+            if opened {
+                Self::deliver_message(logger, state, message_sender, hash, info).await;
+                return;
+            }
+            Self::route_to_extension(logger, state, pairwise_topic, namespace, info);
+            return;
+        }
+        let is_own_topic = state
+            .peers
+            .map_peer_topic
+            .read()
+            .values()
+            .any(|x| *x == topic);
+        if state.messaging.mailbox.read().is_watching(&topic) {
+            state.messaging.mailbox.write().store(
+                topic.clone(),
+                state.clock.now_millis(),
+                conversations::message_id(&info),
+                message.envelope,
+                message.expires_at,
+            );
+            if !is_own_topic {
+                Self::add_to_cache(logger.clone(), state, &topic, &info);
+                return;
+            }
+        }
+        Self::deliver_message(logger, state, message_sender, hash, info).await;
+    }
+
+    #[tracing::instrument(name = "deliver", skip_all, fields(%topic, sender, message))]
+    async fn deliver_message(
+        logger: EventSink,
+        state: &SharedState,
+        message_sender: &Sender<MessageContent>,
+        topic: TopicHash,
+        info: Sata,
+    ) {
+        if topic.as_str() == friends::inbox_topic(&state.local_did) {
+            friends::message_received(logger, state, info);
+            return;
+        }
+        let followed = state
+            .peers
+            .profiles
+            .read()
+            .did_of_topic(topic.as_str())
+            .is_some();
+        if followed {
+            Self::identity_profile_received(logger, state, topic.as_str(), info);
+            return;
+        }
+        // Channel messages are from whoever the conversation is with
+        let pairwise_topic =
+            extensions::split_extension_topic(topic.as_str()).map_or(topic.as_str(), |x| x.0);
+        let sender = state.did_of_topic(pairwise_topic).unwrap_or_default();
+        if let Some(change) = MessageChange::decode(&info) {
+            // Nobody in particular sent what's on other topics, so nobody may change it
+            if !sender.is_empty() {
+                Self::apply_change(logger, state, topic.as_str(), &sender, change);
+            }
+            return;
+        }
+        let message = StoredMessage::new(
+            sender,
+            topic.to_string(),
+            state.clock.now_millis(),
+            info.clone(),
+        );
+        let span = tracing::Span::current();
+        span.record("sender", message.sender.as_str());
+        span.record("message", message.id.as_str());
+        let muted = state.messaging.mutes.read().is_muted(
+            &[topic.as_str(), pairwise_topic],
+            Some(message.sender.as_str()).filter(|x| !x.is_empty()),
+        );
+        // Already here, live before a mailbox replayed it or the other way around
+        if !state.messaging.conversations.write().record(message) {
+            return;
+        }
+        Self::add_to_cache(logger.clone(), state, topic.as_str(), &info);
+        // Muted conversations keep their history, only the stream and its notifications skip them
+        if muted {
+            logger.event_occurred(Event::MessageMuted(topic.to_string()));
+            return;
+        }
+        if message_sender.send((topic, info)).await.is_err() {
+            tracing::warn!("message stream closed");
+            logger.event_occurred(Event::FailedToSendMessage);
+        }
+        #[cfg(feature = "metrics")]
+        state
+            .metrics
+            .write()
+            .messages_queued(CHANNEL_SIZE - message_sender.capacity());
+    }
+
+    // Topic shared with a paired peer, only the two of them can derive it from their key exchange
+    pub fn pairwise_topic(keystore: &dyn Keystore, public_key: &DID) -> Result<String, BlinkError> {
+        let exchange = keystore.key_exchange(public_key)?;
+        let hashed = Hash::hash(exchange);
+        let topic = base64::encode(hashed);
+
+        Ok(topic)
+    }
+
+    async fn create_swarm(
+        key_pair: &Keypair,
+        certificate: &DeviceCertificate,
+        peer_id: &PeerId,
+        config: &BlinkConfig,
+    ) -> Result<Swarm<BlinkBehavior>> {
+        let agent_version = certificate.to_agent_version()?;
+        let blink_behaviour = BlinkBehavior::new(&key_pair, agent_version, config).await?;
+        let transport = transport::build(key_pair)?;
+
+        let swarm = SwarmBuilder::new(transport, blink_behaviour, peer_id.clone())
+            .executor(Box::new(|fut| runtime::spawn(fut)))
+            .build();
+
+        Ok(swarm)
+    }
+
+    // Completes once the event loop stopped, after the cancellation token was cancelled
+    pub async fn wait_for_shutdown(&self) {
+        let mut stopped = self.stopped.clone();
+        while stopped.changed().await.is_ok() {}
+    }
+
+    // Latest events with their sequence numbers, for UIs that attach after the service started
+    pub fn recent_events(&self) -> Vec<(u64, Event)> {
+        self.state.history.read().recent()
+    }
+
+    // Events numbered after `seq`, pass the last number seen to catch up without missing any
+    pub fn events_since(&self, seq: u64) -> Vec<(u64, Event)> {
+        self.state.history.read().since(seq)
+    }
+
+    // Number of the latest event, 0 before the first one
+    pub fn last_event_seq(&self) -> u64 {
+        self.state.history.read().last()
+    }
+
+    // Every event from now on with its number, a receiver falling a whole history behind
+    // gets RecvError::Lagged and can catch up with events_since
+    pub fn subscribe_events(&self) -> broadcast::Receiver<(u64, Event)> {
+        self.state.history.read().subscribe()
+    }
+
+    // Completes with the next event the matcher accepts
+    pub async fn wait_for_event(
+        &self,
+        matcher: impl Fn(&Event) -> bool,
+        timeout: Duration,
+    ) -> Result<Event, BlinkError> {
+        self.wait_for_event_since(self.last_event_seq(), matcher, timeout)
+            .await
+    }
+
+    // Same as wait_for_event, counting the events numbered after `seq` that already occurred
+    pub async fn wait_for_event_since(
+        &self,
+        seq: u64,
+        matcher: impl Fn(&Event) -> bool,
+        timeout: Duration,
+    ) -> Result<Event, BlinkError> {
+        // Subscribed under the lock, so nothing falls between the history and the stream
+        let (mut events, past) = {
+            let history = self.state.history.read();
+            (history.subscribe(), history.since(seq))
+        };
+        let mut seen = seq;
+        for (number, event) in past {
+            if matcher(&event) {
+                return Ok(event);
+            }
+            seen = number;
+        }
+        let mut deadline = self.state.clock.sleep(timeout);
+        loop {
+            tokio::select! {
+                received = events.recv() => match received {
+                    Ok((number, event)) if number > seen => {
+                        seen = number;
+                        if matcher(&event) {
+                            return Ok(event);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(_)) => {
+                        for (number, event) in self.events_since(seen) {
+                            if matcher(&event) {
+                                return Ok(event);
+                            }
+                            seen = number;
+                        }
+                    }
+                    Err(RecvError::Closed) => return Err(BlinkError::ChannelClosed),
+                },
+                _ = &mut deadline => return Err(BlinkError::Timeout(timeout)),
+            }
+        }
+    }
+
+    // Completes once we listen on an address, and with `bootstrapped` once the Kademlia bootstrap
+    // from the configured nodes completed too, whether it found peers or not
+    pub async fn ready(&self, bootstrapped: bool, timeout: Duration) -> Result<(), BlinkError> {
+        let mut deadline = self.state.clock.sleep(timeout);
+        loop {
+            // Created before checking, so a change in between isn't missed
+            let changed = self.state.network.readiness_changed.notified();
+            if self.state.network.readiness.read().is_ready(bootstrapped) {
+                return Ok(());
+            }
+            tokio::select! {
+                _ = changed => {}
+                _ = self.wait_for_shutdown() => return Err(BlinkError::ChannelClosed),
+                _ = &mut deadline => return Err(BlinkError::Timeout(timeout)),
+            }
+        }
+    }
+
+    // Addresses we listen on, without the external ones peers reach us at
+    pub fn listen_addrs(&self) -> Vec<Multiaddr> {
+        self.state.network.readiness.read().listen_addrs()
+    }
+
+    // Paired DIDs and the topic shared with each
+    pub fn paired(&self) -> HashMap<String, String> {
+        self.state.peers.map_peer_topic.read().clone()
+    }
+
+    // Remote peers subscribed to the topic, a publish on it reaches nobody while this is empty
+    pub fn topic_peers(&self, topic: &str) -> Vec<PeerId> {
+        self.state.peers.topic_members.read().peers(topic)
+    }
+
+    // Completes once a remote peer subscribed to the DID's pairwise topic, so a send will reach it
+    pub async fn await_peer_ready(&self, did: &DID, timeout: Duration) -> Result<(), BlinkError> {
+        let did = did.to_string();
+        let changed = self.state.peers.topic_members.read().changed();
+        let mut deadline = self.state.clock.sleep(timeout);
+        loop {
+            // Registered before checking so a subscription in between isn't missed
+            let notified = changed.notified();
+            let topic = self.state.peers.map_peer_topic.read().get(&did).cloned();
+            if topic.map_or(false, |x| {
+                self.state.peers.topic_members.read().has_members(&x)
+            }) {
+                return Ok(());
+            }
+            tokio::select! {
+                _ = notified => {}
+                _ = &mut deadline => return Err(BlinkError::Timeout(timeout)),
+            }
+        }
+    }
+
+    pub async fn pair_to_another_peer(&mut self, dial_opts: DialOpts) -> Result<(), BlinkError> {
+        if self.is_paused() {
+            return Err(BlinkError::Invalid("Networking is paused".into()));
+        }
+        self.command(BlinkCommand::Dial(dial_opts)).await?;
+        Ok(())
+    }
+
+    pub async fn provide(&mut self, cid: String) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::Provide(cid)).await?;
+        Ok(())
+    }
+
+    pub async fn stop_providing(&mut self, cid: String) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::StopProviding(cid)).await?;
+        Ok(())
+    }
+
+    // Number of remote providers observed for a CID we provide, as of the last re-announcement
+    pub fn availability(&self, cid: &str) -> Option<usize> {
+        self.state.network.providers.read().availability(cid)
+    }
+
+    // Holds messages for peers that registered us as their mailbox until they sync
+    pub async fn set_mailbox_mode(&mut self, enabled: bool) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::SetMailboxMode(enabled)).await?;
+        Ok(())
+    }
+
+    // Asks a peer running in mailbox mode to keep messages for every topic we're paired on.
+    // It holds a topic once it sees us subscribed to it, for as long as mailbox::HOLD_FOR
+    pub async fn register_mailbox(&mut self, mailbox: PeerId) -> Result<(), BlinkError> {
+        let topics = self.mailbox_topics();
+        self.command(BlinkCommand::WatchAtMailbox(mailbox, topics))
+            .await?;
+        Ok(())
+    }
+
+    // Replays the messages the mailbox received after `since` (unix time in ms) a page at a time,
+    // Event::MailboxReplayed counts them once the last page is in
+    pub async fn sync_from_mailbox(
+        &mut self,
+        mailbox: PeerId,
+        since: u64,
+    ) -> Result<(), BlinkError> {
+        let topics = self.mailbox_topics();
+        self.command(BlinkCommand::SyncFromMailbox(mailbox, topics, since))
+            .await?;
+        Ok(())
+    }
+
+    // Friend requests sent while we're away wait at the mailbox too
+    fn mailbox_topics(&self) -> Vec<TopicName> {
+        let mut topics: Vec<TopicName> = self
+            .state
+            .peers
+            .map_peer_topic
+            .read()
+            .values()
+            .cloned()
+            .collect();
+        topics.push(friends::inbox_topic(&self.state.local_did));
+        topics
+    }
+
+    // Journals outgoing operations and group call bans to `path` and replays the ones a previous
+    // run left incomplete
+    pub async fn enable_write_ahead_log(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), BlinkError> {
+        let wal = WriteAheadLog::open(path)?;
+        let pending = wal.pending();
+        *self.state.messaging.wal.write() = Some(wal);
+
+        let mut transactions = Vec::new();
+        for (id, operation) in pending {
+            match operation {
+                WalOperation::Publish(topic, sata) => {
+                    self.command(BlinkCommand::PublishToTopic(topic, sata, Some(id)))
+                        .await?;
+                }
+                WalOperation::TransactionPart(transaction, topic, sata) => {
+                    self.state.messaging.outbox.write().insert(
+                        transaction,
+                        vec![TransactionPart::new(topic, sata, Some(id))],
+                    );
+                    if !transactions.contains(&transaction) {
+                        transactions.push(transaction);
+                    }
+                }
+                // The uplinks to close died with the previous run, only the decision is left to record
+                WalOperation::GroupBan(group, did, banned, at) => {
+                    self.state
+                        .messaging
+                        .moderation
+                        .write()
+                        .set_banned(group, &did, banned, at)?;
+                    Self::commit_journal(
+                        self.state.messaging.wal.clone(),
+                        self.event_bus.clone(),
+                        id,
+                    );
+                }
+            }
+        }
+        for transaction in transactions {
+            self.command(BlinkCommand::RunTransaction(transaction))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn journal(&self, operation: WalOperation) -> Result<Option<u64>> {
+        match self.state.messaging.wal.write().as_mut() {
+            Some(wal) => Ok(Some(wal.begin(operation)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Restores the peers Kademlia knew and the topics we were subscribed to from the snapshot a
+    // previous run saved at `path`, and saves there from now on, so a restart rejoins its
+    // meshes without rediscovering everything
+    pub async fn enable_network_snapshot(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), BlinkError> {
+        let snapshot = self.state.network.snapshots.write().open(path)?;
+        self.command(BlinkCommand::RestoreSnapshot(snapshot)).await
+    }
+
+    // The expiry of disappearing messages is kept in memory until a path is given, it's written to
+    // it from then on so they still disappear after a restart
+    pub fn enable_expiry_store(&mut self, path: impl AsRef<Path>) -> Result<(), BlinkError> {
+        Ok(self.state.messaging.expiry.write().open(path)?)
+    }
+
+    // Moderation decisions are kept in memory until a path is given, they are written to it from then on
+    pub fn enable_moderation_store(&mut self, path: impl AsRef<Path>) -> Result<(), BlinkError> {
+        Ok(self.state.messaging.moderation.write().open(path)?)
+    }
+
+    // Drops everything the DID sends us: messages, streams and call invites
+    pub fn block(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_blocked(&did.to_string(), true, now)?;
+        Ok(())
+    }
+
+    pub fn unblock(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_blocked(&did.to_string(), false, now)?;
+        Ok(())
+    }
+
+    pub fn blocked(&self) -> Vec<String> {
+        self.state.messaging.moderation.read().blocked()
+    }
+
+    // Messages from the DID are cached and announced by Event::MessageQuarantined instead of delivered
+    pub fn quarantine(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_quarantined(&did.to_string(), true, now)?;
+        Ok(())
+    }
+
+    pub fn release_from_quarantine(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_quarantined(&did.to_string(), false, now)?;
+        Ok(())
+    }
+
+    pub fn quarantined(&self) -> Vec<String> {
+        self.state.messaging.moderation.read().quarantined()
+    }
+
+    // Messages of the conversation are cached and announced by Event::MessageMuted instead of delivered
+    pub fn mute(&mut self, conversation: impl Into<Conversation>) {
+        self.state.messaging.mutes.write().mute(conversation.into());
+    }
+
+    pub fn unmute(&mut self, conversation: impl Into<Conversation>) {
+        self.state
+            .messaging
+            .mutes
+            .write()
+            .unmute(&conversation.into());
+    }
+
+    pub fn muted(&self) -> Vec<Conversation> {
+        self.state.messaging.mutes.read().muted()
+    }
+
+    // Removes the DID from a group call we forward and keeps it from joining again.
+    // Journaled, a ban cut short by a crash is recorded when the write-ahead log opens
+    pub async fn ban_from_group_call(
+        &mut self,
+        group: GroupCallId,
+        did: &DID,
+    ) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        let journal_id = self.journal(WalOperation::GroupBan(group, did.to_string(), true, now))?;
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_banned(group, &did.to_string(), true, now)?;
+        let closed = self.close_uplinks_of(group, did).await;
+        if let Some(id) = journal_id {
+            Self::commit_journal(self.state.messaging.wal.clone(), self.event_bus.clone(), id);
+        }
+        closed
+    }
+
+    async fn close_uplinks_of(&mut self, group: GroupCallId, did: &DID) -> Result<(), BlinkError> {
+        let peer_id = self
+            .state
+            .peers
+            .map_did_peer
+            .read()
+            .get(&did.to_string())
+            .copied();
+        if let Some(peer_id) = peer_id {
+            let uplinks = self.state.media.groups.read().uplinks_of(group, &peer_id);
+            for id in uplinks {
+                self.command(BlinkCommand::CloseStream(id)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unban_from_group_call(
+        &mut self,
+        group: GroupCallId,
+        did: &DID,
+    ) -> Result<(), BlinkError> {
+        let now = self.state.clock.now_millis();
+        let journal_id =
+            self.journal(WalOperation::GroupBan(group, did.to_string(), false, now))?;
+        self.state
+            .messaging
+            .moderation
+            .write()
+            .set_banned(group, &did.to_string(), false, now)?;
+        if let Some(id) = journal_id {
+            Self::commit_journal(self.state.messaging.wal.clone(), self.event_bus.clone(), id);
+        }
+        Ok(())
+    }
+
+    pub fn banned_from_group_call(&self, group: GroupCallId) -> Vec<String> {
+        self.state.messaging.moderation.read().banned(group)
+    }
+
+    // PeerId of this device's transport key, other devices of the same DID have their own
+    pub fn local_peer_id(&self) -> PeerId {
+        self.state.local_peer
+    }
+
+    // Exchanges cached messages, paired topics and moderation decisions with another device running the same DID.
+    // Each side pulls the other's a page at a time, DeviceSynced tells how many messages came in
+    pub async fn sync_with_device(&mut self, device: PeerId) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::SyncWithDevice(device)).await?;
+        Ok(())
+    }
+
+    // Starts an audio call with a peer we already identified
+    pub async fn call(&mut self, did: &DID) -> Result<CallHandle, BlinkError> {
+        self.open_stream(did, StreamKind::Audio).await
+    }
+
+    // Starts a video call, the remote may lower the caps to what it's willing to receive
+    pub async fn video_call(
+        &mut self,
+        did: &DID,
+        caps: VideoCaps,
+    ) -> Result<CallHandle, BlinkError> {
+        let peer_id = self.identified_peer(did)?;
+        self.start_stream(peer_id, StreamKind::Video, Some(caps), None)
+            .await
+    }
+
+    pub async fn open_stream(
+        &mut self,
+        did: &DID,
+        kind: StreamKind,
+    ) -> Result<CallHandle, BlinkError> {
+        let peer_id = self.identified_peer(did)?;
+        let caps = self.default_caps(kind);
+        self.start_stream(peer_id, kind, caps, None).await
+    }
+
+    // Starts a group call forwarded by this peer, members join it with the returned id
+    pub fn host_group_call(&mut self) -> GroupCallId {
+        let group = unique_id();
+        self.state.media.groups.write().host(group);
+        group
+    }
+
+    // Sends our media into a group call, `forwarder` is None for a call we host ourselves.
+    // The other participants' media arrives as incoming streams announced by Event::GroupStreamAdded
+    pub async fn join_group_call(
+        &mut self,
+        forwarder: Option<&DID>,
+        group: GroupCallId,
+        kind: StreamKind,
+    ) -> Result<CallHandle, BlinkError> {
+        let peer_id = match forwarder {
+            Some(did) => self.identified_peer(did)?,
+            None if self.state.media.groups.read().is_hosting(group) => self.state.local_peer,
+            None => {
+                return Err(BlinkError::Invalid(format!(
+                    "Group call {} isn't hosted by this peer",
+                    group
+                )))
+            }
+        };
+        let caps = self.default_caps(kind);
+        let tag = GroupTag {
+            group,
+            origin: None,
+        };
+        let handle = self.start_stream(peer_id, kind, caps, Some(tag)).await?;
+        self.state.media.groups.write().joined(group, handle.id());
+        Ok(handle)
+    }
+
+    // Stops sending to the group call, ending it for everyone if we are its forwarder
+    pub async fn leave_group_call(&mut self, group: GroupCallId) -> Result<(), BlinkError> {
+        let uplinks = self.state.media.groups.write().leave(group);
+        for id in uplinks {
+            self.command(BlinkCommand::CloseStream(id)).await?;
+        }
+        if self.state.media.groups.read().is_hosting(group) {
+            self.command(BlinkCommand::EndGroupCall(group)).await?;
+        }
+        Ok(())
+    }
+
+    // Shares the captured frames with every peer, the streams are closed once `frames` ends
+    pub async fn screen_share(
+        &mut self,
+        peers: Vec<DID>,
+        mut frames: impl Stream<Item = ScreenFrame> + Send + Unpin + 'static,
+    ) -> Result<Vec<StreamId>, BlinkError> {
+        let mut handles = Vec::new();
+        for peer in &peers {
+            handles.push(self.open_stream(peer, StreamKind::ScreenShare).await?);
+        }
+        let ids = handles.iter().map(|x| x.id()).collect();
+
+        let event_bus = self.event_bus.clone();
+        runtime::spawn(async move {
+            while let Some(frame) = frames.next().await {
+                for handle in handles.iter_mut() {
+                    if let Err(e) = handle.send_screen_frame(frame.clone()).await {
+                        event_bus.event_occurred(Event::StreamError(e.to_string()));
+                    }
+                }
+            }
+            for handle in handles {
+                let _ = handle.hangup().await;
+            }
+        });
+        Ok(ids)
+    }
+
+    // Limits the video streams peers can open with us
+    pub fn set_video_caps(&mut self, caps: VideoCaps) {
+        self.state.media.streams.write().set_video_caps(caps);
+    }
+
+    fn identified_peer(&self, did: &DID) -> Result<PeerId, BlinkError> {
+        self.state
+            .peers
+            .map_did_peer
+            .read()
+            .get(&did.to_string())
+            .copied()
+            .ok_or_else(|| BlinkError::NotIdentified(did.to_string()))
+    }
+
+    // Hands the command to the event loop, which only goes away once the service stopped
+    async fn command(&self, command: BlinkCommand) -> Result<(), BlinkError> {
+        self.command_channel
+            .send(command)
+            .await
+            .map_err(|_| BlinkError::ChannelClosed)
+    }
+
+    fn default_caps(&self, kind: StreamKind) -> Option<VideoCaps> {
+        match kind {
+            StreamKind::Video | StreamKind::ScreenShare => {
+                Some(self.state.media.streams.read().video_caps())
+            }
+            StreamKind::Audio => None,
+        }
+    }
+
+    async fn start_stream(
+        &mut self,
+        peer_id: PeerId,
+        kind: StreamKind,
+        caps: Option<VideoCaps>,
+        group: Option<GroupTag>,
+    ) -> Result<CallHandle, BlinkError> {
+        let id = unique_id();
+        let handle = self
+            .state
+            .media
+            .streams
+            .write()
+            .open(id, peer_id, kind, self.command_channel.clone())
+            .ok_or_else(|| BlinkError::Invalid(format!("Stream {} is already open", id)))?;
+        self.command(BlinkCommand::OpenStream(peer_id, id, kind, caps, group))
+            .await?;
+        Ok(handle)
+    }
+
+    // Records the stream into the cache as fragments until it closes, then Event::RecordingSaved
+    // gives the CID of the manifest listing them
+    pub fn record_stream(
+        &mut self,
+        id: StreamId,
+        options: RecordingOptions,
+    ) -> Result<(), BlinkError> {
+        let streams = self.state.media.streams.read();
+        let (peer, kind) = match (streams.peer_of(id), streams.kind_of(id)) {
+            (Some(peer), Some(kind)) => (peer, kind),
+            _ => return Err(BlinkError::NotFound(format!("Stream {}", id))),
+        };
+        let now = self.state.clock.now_millis();
+        let peer = self.state.did_of(&peer);
+        if !self
+            .state
+            .media
+            .recordings
+            .write()
+            .start(id, kind, peer, options, now)
+        {
+            return Err(BlinkError::Invalid(format!(
+                "Stream {} is already recorded",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    // Ends the recording early, the stream stays open
+    pub fn stop_recording(&mut self, id: StreamId) {
+        let now = self.state.clock.now_millis();
+        self.state.media.recordings.write().finish(id, now);
+    }
+
+    // Faults to inject into this service's swarm loop
+    #[cfg(feature = "chaos")]
+    pub fn chaos(&self) -> ChaosHandle {
+        self.state.chaos.clone()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        let queued = CHANNEL_SIZE - self.command_channel.capacity();
+        self.state.metrics.read().snapshot(queued)
+    }
+
+    // Takes the handle of a stream announced by Event::IncomingStream
+    pub fn accept_stream(&mut self, id: StreamId) -> Option<CallHandle> {
+        self.state.media.streams.write().take_incoming(id)
+    }
+
+    // Offers a file, the peer answers through Event::IncomingFile and accept_file.
+    // Both ends report Event::TransferProgress until Event::TransferCompleted or Event::TransferFailed.
+    // Offering the file again after an interrupted transfer lets the receiver keep the chunks it already has
+    pub async fn send_file(
+        &mut self,
+        did: &DID,
+        path: impl AsRef<Path>,
+    ) -> Result<TransferId, BlinkError> {
+        let peer_id = self.identified_peer(did)?;
+        let path = path.as_ref().to_path_buf();
+        let offer = FileOffer::from_file(&path)?;
+        let id = unique_id();
+        self.state
+            .media
+            .transfers
+            .write()
+            .offer(id, peer_id, path, offer.clone());
+        self.command(BlinkCommand::SendTransferRequest(
+            peer_id,
+            TransferRequest::Offer(id, offer),
+        ))
+        .await?;
+        Ok(id)
+    }
+
+    // Starts receiving a file announced by Event::IncomingFile into `path`
+    pub async fn accept_file(
+        &mut self,
+        id: TransferId,
+        path: impl AsRef<Path>,
+    ) -> Result<(), BlinkError> {
+        let complete = self
+            .state
+            .media
+            .transfers
+            .write()
+            .accept(id, path.as_ref())?;
+        if !complete {
+            self.command(BlinkCommand::FetchChunks(id)).await?;
+            return Ok(());
+        }
+        self.event_bus.event_occurred(Event::TransferCompleted(id));
+        if let Some(peer_id) = self.state.media.transfers.write().remove(id, None) {
+            self.command(BlinkCommand::SendTransferRequest(
+                peer_id,
+                TransferRequest::Finished(id),
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Declines an offered file or stops a transfer in either direction
+    pub async fn cancel_transfer(&mut self, id: TransferId) -> Result<(), BlinkError> {
+        let peer_id = self
+            .state
+            .media
+            .transfers
+            .write()
+            .remove(id, None)
+            .ok_or_else(|| BlinkError::NotFound(format!("Transfer {}", id)))?;
+        self.command(BlinkCommand::SendTransferRequest(
+            peer_id,
+            TransferRequest::Cancel(id),
+        ))
+        .await?;
+        Ok(())
+    }
 
-        topic
+    // Lower level access to the fragments, `oracle` is the way in for most uses
+    pub fn conflux(&self) -> Conflux {
+        Conflux::new(
+            self.state.network.conflux.clone(),
+            self.command_channel.clone(),
+            self.state.clock.clone(),
+        )
     }
 
-    async fn create_swarm(key_pair: &Keypair, peer_id: &PeerId) -> Result<Swarm<BlinkBehavior>> {
-        let blink_behaviour = BlinkBehavior::new(&key_pair).await?;
-        // Create a keypair for authenticated encryption of the transport.
-        let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&key_pair)?;
+    pub fn oracle(&self) -> Oracle {
+        Oracle::new(self.conflux(), self.state.clock.clone())
+    }
 
-        // Create a tokio-based TCP transport use noise for authenticated
-        // encryption and Mplex for multiplexing of substreams on a TCP stream.
-        let transport = TokioTcpTransport::new(GenTcpConfig::default().nodelay(true))
-            .upgrade(upgrade::Version::V1)
-            .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
-            .multiplex(mplex::MplexConfig::new())
-            .boxed();
+    // For adapters surfacing events of their own next to the service's
+    #[cfg(feature = "constellation")]
+    pub(crate) fn events(&self) -> EventSink {
+        self.event_bus.clone()
+    }
 
-        let swarm = SwarmBuilder::new(transport, blink_behaviour, peer_id.clone())
-            .executor(Box::new(|fut| {
-                tokio::spawn(fut);
-            }))
-            .build();
+    // Echoes the benchmark probes of paired peers, off unless the user agreed to it
+    pub fn set_benchmark_answering(&mut self, answering: bool) {
+        self.state.media.benchmarks.write().set_answering(answering);
+    }
 
-        Ok(swarm)
+    // Sends a burst of probes to a peer answering benchmarks and measures the echoes.
+    // Probes travel like messages, so the report reflects what the peer's messages go through
+    pub async fn benchmark(
+        &mut self,
+        did: &DID,
+        options: BenchmarkOptions,
+    ) -> Result<BenchmarkReport, BlinkError> {
+        let topic = self
+            .state
+            .peers
+            .map_peer_topic
+            .read()
+            .get(&did.to_string())
+            .cloned()
+            .ok_or_else(|| BlinkError::NotIdentified(did.to_string()))?;
+        let topic = extensions::extension_topic(&topic, diagnostics::BENCH_NAMESPACE);
+        let id = unique_id();
+        let done = self.state.media.benchmarks.write().start(
+            id,
+            did.to_string(),
+            &options,
+            self.state.clock.now_millis(),
+        );
+        for sequence in 0..options.probes {
+            let probe = BenchMessage::Probe(
+                id,
+                sequence,
+                self.state.clock.now_millis(),
+                vec![0; options.payload_size],
+            );
+            let sent = match Sata::default().encode(IpldCodec::DagCbor, Kind::Dynamic, &probe) {
+                Ok(sata) => {
+                    self.command(BlinkCommand::PublishToTopic(topic.clone(), sata, None))
+                        .await
+                }
+                Err(e) => Err(BlinkError::Serialization(format!("{:?}", e))),
+            };
+            if let Err(e) = sent {
+                self.state.media.benchmarks.write().finish(id);
+                return Err(e);
+            }
+        }
+        tokio::select! {
+            _ = done => {}
+            _ = self.state.clock.sleep(options.timeout) => {}
+        }
+        self.state
+            .media
+            .benchmarks
+            .write()
+            .finish(id)
+            .ok_or_else(|| BlinkError::NotFound(format!("Benchmark {}", id)))
     }
 
-    pub async fn pair_to_another_peer(&mut self, dial_opts: DialOpts) -> Result<()> {
-        self.command_channel
-            .send(BlinkCommand::Dial(dial_opts))
+    // Rings a peer, the answer comes as Event::CallAccepted or Event::CallEnded.
+    // No media flows until the application opens its streams
+    pub async fn invite_call(&mut self, did: &DID, kind: StreamKind) -> Result<CallId, BlinkError> {
+        let id = unique_id();
+        self.state.media.calls.write().invite(id, did.to_string());
+        if let Err(e) = self
+            .send_call_signal(&did.to_string(), CallSignal::Invite(id, kind))
+            .await
+        {
+            self.state.media.calls.write().end(id, &did.to_string());
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    // Answers a call announced by Event::IncomingCall
+    pub async fn accept_call(&mut self, id: CallId) -> Result<(), BlinkError> {
+        let peer = self
+            .state
+            .media
+            .calls
+            .write()
+            .accept(id)
+            .ok_or_else(|| BlinkError::NotFound(format!("Incoming call {}", id)))?;
+        self.send_call_signal(&peer, CallSignal::Accept(id)).await
+    }
+
+    pub async fn reject_call(&mut self, id: CallId) -> Result<(), BlinkError> {
+        self.end_call(id, CallSignal::Reject(id)).await
+    }
+
+    // Ends a call at any stage, cancelling it if the peer didn't answer yet
+    pub async fn hangup_call(&mut self, id: CallId) -> Result<(), BlinkError> {
+        self.end_call(id, CallSignal::Hangup(id)).await
+    }
+
+    async fn end_call(&mut self, id: CallId, signal: CallSignal) -> Result<(), BlinkError> {
+        let peer = self
+            .state
+            .media
+            .calls
+            .read()
+            .peer_of(id)
+            .ok_or_else(|| BlinkError::NotFound(format!("Call {}", id)))?;
+        self.state.media.calls.write().end(id, &peer);
+        self.send_call_signal(&peer, signal).await
+    }
+
+    async fn send_call_signal(&mut self, did: &str, signal: CallSignal) -> Result<(), BlinkError> {
+        let topic = self
+            .state
+            .peers
+            .map_peer_topic
+            .read()
+            .get(did)
+            .cloned()
+            .ok_or_else(|| BlinkError::NotIdentified(did.to_string()))?;
+        let topic = extensions::extension_topic(&topic, signaling::CALL_NAMESPACE);
+        let sata = Self::signal_to_sata(&signal)?;
+        self.command(BlinkCommand::PublishToTopic(topic, sata, None))
             .await?;
         Ok(())
     }
 
-    pub async fn send(&mut self, sata: Sata) -> Result<()> {
+    // Fails with a SendError for messages that can never go out, others are retried when the mesh forms.
+    // Every recipient is published to at once, the report says which of them it went out to
+    #[tracing::instrument(skip_all, fields(message = %conversations::message_id(&sata)))]
+    pub async fn send(&mut self, sata: Sata) -> Result<SendReport, BlinkError> {
+        publishing::check_sendable(&sata)?;
         let mut to_whom = Vec::new();
         if let Some(mut rec) = sata.recipients() {
             while !rec.is_empty() {
-                let did = DID::from(rec.pop().unwrap());
-                to_whom.push(did.to_string());
+                to_whom.push(DID::from(rec.pop().unwrap()));
             }
         }
 
-        for who in &to_whom {
-            if let Some(topic) = self.map_peer_topic.read().get(who) {
-                self.command_channel
-                    .send(BlinkCommand::PublishToTopic(topic.clone(), sata.clone()))
-                    .await?;
+        let expiring = self
+            .state
+            .messaging
+            .expiry
+            .read()
+            .expires_at(&sata)
+            .is_some();
+        let mut report = SendReport::new();
+        let mut publishes = Vec::new();
+        for did in to_whom {
+            let who = did.to_string();
+            let topic = self.state.peers.map_peer_topic.read().get(&who).cloned();
+            let topic = match topic {
+                Some(topic) => topic,
+                None if self.state.peers.auto_pairing.read().is_enabled() => {
+                    tracing::debug!(recipient = %who, "held until paired");
+                    let result = self.pair_on_send(did, sata.clone()).await;
+                    report.insert(who, result);
+                    continue;
+                }
+                None => {
+                    tracing::warn!(recipient = %who, "not paired");
+                    report.insert(who, self.send_failed(did, RecipientError::NotPaired));
+                    continue;
+                }
+            };
+            tracing::debug!(recipient = %who, %topic, "publishing");
+            // The write-ahead log is on disk, disappearing messages would outlive their expiry in it
+            let journaled = if expiring {
+                Ok(None)
             } else {
-                self.event_bus
-                    .write()
-                    .event_occurred(Event::CouldntFindTopicForDid);
+                self.journal(WalOperation::Publish(topic.clone(), sata.clone()))
+            };
+            let journal_id = match journaled {
+                Ok(journal_id) => journal_id,
+                Err(e) => {
+                    let reason = RecipientError::Failed(e.to_string());
+                    report.insert(who, self.send_failed(did, reason));
+                    continue;
+                }
+            };
+            self.state
+                .messaging
+                .conversations
+                .write()
+                .record(StoredMessage::new(
+                    self.state.local_did.clone(),
+                    topic.clone(),
+                    self.state.clock.now_millis(),
+                    sata.clone(),
+                ));
+            publishes.push((
+                did,
+                BlinkCommand::PublishToTopic(topic, sata.clone(), journal_id),
+            ));
+        }
+
+        let commands = &self.command_channel;
+        let sent = join_all(
+            publishes
+                .into_iter()
+                .map(|(did, command)| async move { (did, commands.send(command).await) }),
+        )
+        .await;
+        for (did, result) in sent {
+            let who = did.to_string();
+            let result = match result {
+                Ok(()) => Ok(()),
+                Err(e) => self.send_failed(did, RecipientError::Failed(e.to_string())),
+            };
+            report.insert(who, result);
+        }
+        Ok(report)
+    }
+
+    // Sends a disappearing message, deleted from our cache and history and the recipients' once the
+    // ttl is up, with Event::MessageExpired. It's never journaled, nor published after the ttl
+    pub async fn send_expiring(
+        &mut self,
+        sata: Sata,
+        ttl: Duration,
+    ) -> Result<SendReport, BlinkError> {
+        let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        let expires_at = self.state.clock.now_millis().saturating_add(ttl);
+        Self::schedule_expiry(&self.state, conversations::message_id(&sata), expires_at);
+        self.send(sata).await
+    }
+
+    // Replaces a message we sent, for us and whoever it went to, announced by Event::MessageEdited.
+    // The message keeps its id, later edits and deletions refer to it by that
+    pub async fn edit_message(&mut self, id: &str, sata: Sata) -> Result<(), BlinkError> {
+        publishing::check_sendable(&sata)?;
+        self.change_message(MessageChange::Edit(id.to_string(), sata))
+            .await
+    }
+
+    // Deletes a message we sent, for us and whoever it went to, announced by Event::MessageDeleted.
+    // The history keeps a tombstone of it
+    pub async fn delete_message(&mut self, id: &str) -> Result<(), BlinkError> {
+        self.change_message(MessageChange::Delete(id.to_string()))
+            .await
+    }
+
+    async fn change_message(&mut self, change: MessageChange) -> Result<(), BlinkError> {
+        let topics = self
+            .state
+            .messaging
+            .conversations
+            .read()
+            .topics_of(change.id(), &self.state.local_did);
+        if topics.is_empty() {
+            return Err(BlinkError::NotFound(format!("Message {}", change.id())));
+        }
+        let sata = change.encode()?;
+        for topic in topics {
+            Self::apply_change(
+                self.event_bus.clone(),
+                &self.state,
+                &topic,
+                &self.state.local_did,
+                change.clone(),
+            );
+            self.command(BlinkCommand::PublishToTopic(topic, sata.clone(), None))
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Holds the message until we're paired with the DID. The first one held looks it up on the
+    // DHT and at the rendezvous nodes, it's dialed once found and identify pairs us
+    async fn pair_on_send(&self, did: DID, sata: Sata) -> Result<(), RecipientError> {
+        let who = did.to_string();
+        if !self.state.peers.auto_pairing.write().hold(did, sata) {
+            return Ok(());
+        }
+        let commands = self.state.commands.clone();
+        let sleep = self.state.clock.sleep(AUTO_PAIR_TIMEOUT);
+        let expired = who.clone();
+        runtime::spawn(async move {
+            sleep.await;
+            let _ = commands.send(BlinkCommand::AutoPairExpired(expired)).await;
+        });
+        let mut lookups = vec![BlinkCommand::ResolveDid(who.clone())];
+        if !self.state.network.rendezvous.read().nodes().is_empty() {
+            lookups.push(BlinkCommand::RendezvousDiscover(who));
+        }
+        for command in lookups {
+            self.command(command)
+                .await
+                .map_err(|e| RecipientError::Failed(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    // Reports the recipient with Event::SendFailed, returning what goes in the send report
+    fn send_failed(&self, recipient: DID, reason: RecipientError) -> Result<(), RecipientError> {
+        self.event_bus.event_occurred(Event::SendFailed {
+            recipient,
+            reason: reason.clone(),
+        });
+        Err(reason)
+    }
+
+    // Messages of the kind, sent as `Typed<T>`, decode to BlinkMessage::Custom holding a `T`.
+    // Peers don't need to know about it, they can send it whether or not they registered it
+    pub fn register_message_kind<T>(&mut self, kind: &str) -> Result<(), BlinkError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.state
+            .messaging
+            .message_kinds
+            .write()
+            .register::<T>(kind)
+    }
+
+    pub fn decode_message(&self, sata: Sata) -> BlinkMessage {
+        self.state.messaging.message_kinds.read().decode(sata)
+    }
+
+    // Wraps the channel `new` returned, yielding its messages decoded by kind
+    pub fn typed_messages(&self, messages: Receiver<MessageContent>) -> TypedReceiver {
+        TypedReceiver::new(messages, self.state.messaging.message_kinds.clone())
+    }
+
+    // Latest messages exchanged with a paired DID within the time range (ms), oldest first
+    pub fn history(
+        &self,
+        did: &DID,
+        range: impl RangeBounds<u64>,
+        limit: usize,
+    ) -> Vec<StoredMessage> {
+        match self.state.peers.map_peer_topic.read().get(&did.to_string()) {
+            Some(topic) => self
+                .state
+                .messaging
+                .conversations
+                .read()
+                .history(topic, range, limit),
+            None => Vec::new(),
+        }
+    }
+
+    // Messages sent or received whose text contains `text`, newest first
+    pub fn search_messages(&self, text: &str) -> Vec<StoredMessage> {
+        self.state.messaging.conversations.read().search(text)
+    }
+
+    // Applies from the next received message, what is already cached stays
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.state.messaging.cache_ledger.write().set_policy(policy);
+    }
+
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.state.messaging.cache_ledger.read().policy().clone()
+    }
+
+    // Looks the DID up at the configured rendezvous nodes, reported by Event::RendezvousDiscovered.
+    // The peer is dialed, and paired if it's a known identity
+    pub async fn discover_by_rendezvous(&mut self, did: &DID) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::RendezvousDiscover(did.to_string()))
+            .await?;
+        Ok(())
+    }
+
+    // Asks the DID to become friends. The request waits in its inbox, or at its mailbox while it's
+    // offline, and the DID is looked up on the DHT meanwhile. Reported by Event::FriendRequestAccepted
+    pub async fn send_friend_request(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let did = did.to_string();
+        if self.state.peers.map_peer_topic.read().contains_key(&did) {
+            return Err(BlinkError::Invalid(format!("Already paired with {}", did)));
+        }
+        self.state
+            .peers
+            .friends
+            .write()
+            .sent(did.clone(), self.state.clock.now_millis());
+        self.command(BlinkCommand::SendFriendRequest(did)).await?;
+        Ok(())
+    }
+
+    // Becomes friends with a DID that asked, in MultiPass too if it keeps friends, and pairs with it
+    pub async fn accept_friend_request(&mut self, did: &DID) -> Result<(), BlinkError> {
+        let did = did.to_string();
+        if !self.state.peers.friends.read().incoming().contains(&did) {
+            return Err(BlinkError::NotFound(format!("Friend request from {}", did)));
+        }
+        self.command(BlinkCommand::AcceptFriendRequest(did)).await?;
+        Ok(())
+    }
+
+    // The DID isn't told, it only stops waiting on an answer
+    pub fn decline_friend_request(&mut self, did: &DID) -> Result<(), BlinkError> {
+        if !self.state.peers.friends.write().decline(&did.to_string()) {
+            return Err(BlinkError::NotFound(format!("Friend request from {}", did)));
+        }
+        Ok(())
+    }
+
+    // Profile the DID last published, kept for every DID we paired with since the service started
+    pub fn profile(&self, did: &DID) -> Option<IdentityProfile> {
+        self.state.peers.profiles.read().profile(&did.to_string())
+    }
+
+    // Publishes our profile right away, to call once the identity changed in MultiPass.
+    // It's published every few minutes and whenever a peer starts following it otherwise
+    pub async fn broadcast_profile(&mut self) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::BroadcastProfile).await?;
+        Ok(())
+    }
+
+    // DIDs waiting on an answer from us, oldest first
+    pub fn friend_requests(&self) -> Vec<String> {
+        self.state.peers.friends.read().incoming()
+    }
+
+    // DIDs we asked that didn't answer yet, oldest first
+    pub fn sent_friend_requests(&self) -> Vec<String> {
+        self.state.peers.friends.read().outgoing()
+    }
+
+    // Looks the DID up on the DHT, reported by Event::DidResolved once a record signed by it is found.
+    // The peer is dialed, and paired if it's a known identity
+    pub async fn resolve_did(&mut self, did: &DID) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::ResolveDid(did.to_string()))
+            .await?;
+        Ok(())
+    }
+
+    // Advertises the address whatever peers observe, for a server behind port forwarding
+    pub async fn add_external_address(&mut self, addr: Multiaddr) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::AddExternalAddress(addr)).await
+    }
+
+    // Stops advertising the address, whether it was added or confirmed by peers
+    pub async fn remove_external_address(&mut self, addr: Multiaddr) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::RemoveExternalAddress(addr))
+            .await
+    }
+
+    // Addresses we advertise, added ones first, then the ones peers confirmed
+    pub fn external_addresses(&self) -> Vec<Multiaddr> {
+        self.state.network.external.read().addresses()
+    }
+
+    // Addresses peers saw us at that aren't confirmed yet, with how many peers saw each
+    pub fn observed_addresses(&self) -> Vec<(Multiaddr, usize)> {
+        self.state.network.external.read().unconfirmed()
+    }
+
+    // Addresses of the peer, looked up through the peers closest to it on the DHT
+    pub async fn find_peer(&mut self, peer: PeerId) -> Result<Vec<Multiaddr>, BlinkError> {
+        match self.query_dht(DhtQuery::FindPeer(peer)).await? {
+            DhtAnswer::Addresses(addresses) => Ok(addresses),
+            other => Err(BlinkError::Invalid(format!(
+                "Unexpected answer {:?}",
+                other
+            ))),
+        }
+    }
+
+    // Values stored under the key on the DHT, by whoever stored them
+    pub async fn get_record(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, BlinkError> {
+        match self.query_dht(DhtQuery::GetRecord(key.to_vec())).await? {
+            DhtAnswer::Records(values) => Ok(values),
+            other => Err(BlinkError::Invalid(format!(
+                "Unexpected answer {:?}",
+                other
+            ))),
+        }
+    }
+
+    // Stores the value under the key, locally and at the peers closest to it.
+    // Nothing is signed, applications sign what others have to trust
+    pub async fn put_record(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), BlinkError> {
+        match self
+            .query_dht(DhtQuery::PutRecord(key.to_vec(), value))
+            .await?
+        {
+            DhtAnswer::Stored => Ok(()),
+            other => Err(BlinkError::Invalid(format!(
+                "Unexpected answer {:?}",
+                other
+            ))),
+        }
+    }
+
+    // Peers providing the key, e.g. a CID, ours included if we provide it
+    pub async fn get_providers(&mut self, key: &[u8]) -> Result<HashSet<PeerId>, BlinkError> {
+        match self.query_dht(DhtQuery::GetProviders(key.to_vec())).await? {
+            DhtAnswer::Providers(providers) => Ok(providers),
+            other => Err(BlinkError::Invalid(format!(
+                "Unexpected answer {:?}",
+                other
+            ))),
+        }
+    }
+
+    // Kademlia gives up on a query after 5 minutes, so the answer comes by then
+    async fn query_dht(&mut self, query: DhtQuery) -> Result<DhtAnswer, BlinkError> {
+        let (waiter, answer) = tokio::sync::oneshot::channel();
+        self.command(BlinkCommand::QueryDht(query, waiter)).await?;
+        answer.await.map_err(|_| BlinkError::ChannelClosed)?
+    }
+
+    // Bytes exchanged per peer and per stream since the service started
+    pub fn bandwidth_stats(&self) -> BandwidthStats {
+        self.state.network.bandwidth.read().stats()
+    }
+
+    // File transfers pause while over a cap, calls and messages keep going
+    pub fn set_bandwidth_caps(&mut self, caps: BandwidthCaps) {
+        self.state.network.bandwidth.write().set_caps(caps);
+    }
+
+    pub fn bandwidth_caps(&self) -> BandwidthCaps {
+        self.state.network.bandwidth.read().caps()
+    }
+
+    // Stops driving the network, for when the app is backgrounded: no dialing, gossip heartbeats,
+    // discovery or periodic work until `resume`. Commands are still taken, what they send goes
+    // out on resume. Reported by Event::NetworkingPaused
+    pub async fn pause(&mut self, mode: PauseMode) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::Pause(mode)).await
+    }
+
+    // Picks up where `pause` left off, redialing the peers it disconnected from. Reported by
+    // Event::NetworkingResumed
+    pub async fn resume(&mut self) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::Resume).await
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.network.pause.read().is_paused()
+    }
+
+    // Turns local network discovery on or off, while off we stop announcing ourselves on the LAN
+    pub async fn set_mdns(&mut self, enabled: bool) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::SetMdns(enabled)).await?;
+        Ok(())
+    }
+
+    pub fn mdns_enabled(&self) -> bool {
+        *self.state.network.mdns.read()
+    }
+
+    // Whether `send` pairs with recipients we aren't paired with yet instead of failing for them.
+    // Their messages are held meanwhile and count as sent in the report, Event::SendFailed
+    // follows if pairing doesn't complete in time
+    pub fn set_auto_pair(&mut self, enabled: bool) {
+        self.state.peers.auto_pairing.write().set_enabled(enabled);
+    }
+
+    pub fn auto_pair(&self) -> bool {
+        self.state.peers.auto_pairing.read().is_enabled()
+    }
+
+    // Reservations and circuits of the relay server, enabled through BlinkConfig::relay_server
+    pub fn relay_stats(&self) -> RelayStats {
+        self.state.network.relay.read().stats()
+    }
+
+    // Ping round trip times and failures of a connected DID, None if it isn't connected
+    pub fn peer_info(&self, did: &DID) -> Option<PeerInfo> {
+        let peer = *self.state.peers.map_did_peer.read().get(&did.to_string())?;
+        self.state.peers.pings.read().info(&peer)
+    }
+
+    // Checked every 30 seconds, connections already quiet for longer close on the next check
+    pub fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.state.peers.idle.write().set_policy(policy);
+    }
+
+    pub fn idle_policy(&self) -> IdlePolicy {
+        self.state.peers.idle.read().policy()
+    }
+
+    // Applies to messages sent from now on, what's already batched goes out with its window
+    pub fn set_batching(&mut self, settings: BatchSettings) {
+        self.state.messaging.batcher.write().set_settings(settings);
+    }
+
+    pub fn batching(&self) -> BatchSettings {
+        self.state.messaging.batcher.read().settings()
+    }
+
+    // Applies to messages published from now on
+    pub fn set_delivery(&mut self, settings: DeliverySettings) {
+        *self.state.messaging.delivery.write() = settings;
+    }
+
+    pub fn delivery(&self) -> DeliverySettings {
+        self.state.messaging.delivery.read().clone()
+    }
+
+    // Peer scoring is set up with the swarm, only BlinkConfig::scoring changes it
+    pub fn scoring(&self) -> ScoreSettings {
+        self.state.peers.scores.read().settings()
+    }
+
+    // Gossipsub score of the DID's peer as of the last check, None if we don't know it
+    pub fn peer_score(&self, did: &DID) -> Option<f64> {
+        let peer_id = *self.state.peers.map_did_peer.read().get(&did.to_string())?;
+        self.state.peers.scores.read().score(&peer_id)
+    }
+
+    // Keeps the connection to the DID open (true) or lets it close when idle (false) whatever
+    // the policy says, None goes back to the policy
+    pub fn set_keep_alive(&mut self, did: &DID, keep_alive: Option<bool>) {
+        self.state
+            .peers
+            .idle
+            .write()
+            .set_override(did.to_string(), keep_alive);
+    }
+
+    // Changes what the delta sets without restarting, applied by the event loop in between two
+    // events. Rate limit buckets start full again
+    pub async fn reconfigure(&mut self, delta: ConfigDelta) -> Result<(), BlinkError> {
+        self.command(BlinkCommand::Reconfigure(delta)).await
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        self.state.history.read().verbosity()
+    }
+
+    // Applies to messages received from now on, with every bucket starting full
+    pub fn set_rate_limits(&mut self, limits: RateLimits) {
+        self.state.messaging.rate_limiter.write().set_limits(limits);
+    }
+
+    pub fn rate_limits(&self) -> RateLimits {
+        self.state.messaging.rate_limiter.read().limits()
+    }
+
+    // Outgoing messages use the codec from now on, peers need it registered to read them
+    pub fn set_wire_codec(&mut self, codec: Arc<dyn WireCodec>) {
+        self.state.messaging.wire.write().use_codec(codec);
+    }
+
+    // Lets the service read messages sent with the codec without sending with it
+    pub fn register_wire_codec(&mut self, codec: Arc<dyn WireCodec>) {
+        self.state.messaging.wire.write().register(codec);
+    }
+
+    // Id of the codec outgoing messages are sent with
+    pub fn wire_codec(&self) -> u8 {
+        self.state.messaging.wire.read().outgoing()
+    }
+
+    // Sends several messages as one unit, reported by Event::TransactionCompleted or Event::TransactionFailed.
+    // Nothing is sent unless every recipient has a topic
+    pub async fn send_transaction(
+        &mut self,
+        messages: Vec<Sata>,
+    ) -> Result<TransactionId, BlinkError> {
+        let mut to_send = Vec::new();
+        for sata in messages {
+            let mut recipients = sata.recipients().unwrap_or_default();
+            while let Some(recipient) = recipients.pop() {
+                let did = DID::from(recipient);
+                let topic = self
+                    .state
+                    .peers
+                    .map_peer_topic
+                    .read()
+                    .get(&did.to_string())
+                    .cloned();
+                match topic {
+                    Some(topic) => to_send.push((topic, sata.clone())),
+                    None => {
+                        let who = did.to_string();
+                        let _ = self.send_failed(did, RecipientError::NotPaired);
+                        return Err(BlinkError::NoTopicForDid(who));
+                    }
+                }
+            }
+        }
+
+        let id = unique_id();
+        let mut parts = Vec::new();
+        for (topic, sata) in to_send {
+            let journal_id = self.journal(WalOperation::TransactionPart(
+                id,
+                topic.clone(),
+                sata.clone(),
+            ))?;
+            parts.push(TransactionPart::new(topic, sata, journal_id));
+        }
+        self.state.messaging.outbox.write().insert(id, parts);
+        self.command(BlinkCommand::RunTransaction(id)).await?;
+        Ok(id)
+    }
+
+    // Routes messages sent to `namespace` over the pairwise channels to the handler instead of the message stream
+    pub async fn register_extension(
+        &mut self,
+        namespace: &str,
+        handler: Arc<RwLock<impl ExtensionHandler + 'static>>,
+    ) -> Result<(), BlinkError> {
+        extensions::validate_namespace(namespace)?;
+        if namespace == signaling::CALL_NAMESPACE || namespace == diagnostics::BENCH_NAMESPACE {
+            return Err(BlinkError::Invalid(format!(
+                "Extension namespace {} is reserved",
+                namespace
+            )));
+        }
+        if self.state.messaging.channels.read().is_known(namespace) {
+            return Err(BlinkError::Invalid(format!(
+                "{} is an open channel",
+                namespace
+            )));
+        }
+        if !self
+            .state
+            .messaging
+            .extensions
+            .write()
+            .register(namespace.to_string(), handler)
+        {
+            return Err(BlinkError::Invalid(format!(
+                "Extension {} is already registered",
+                namespace
+            )));
+        }
+        self.command(BlinkCommand::SubscribeExtension(namespace.to_string()))
+            .await?;
+        self.command(BlinkCommand::AnnounceProfile).await?;
+        Ok(())
+    }
+
+    pub async fn unregister_extension(&mut self, namespace: &str) -> Result<(), BlinkError> {
+        if self
+            .state
+            .messaging
+            .extensions
+            .write()
+            .unregister(namespace)
+        {
+            self.command(BlinkCommand::UnsubscribeExtension(namespace.to_string()))
+                .await?;
+            self.command(BlinkCommand::AnnounceProfile).await?;
+        }
+        Ok(())
+    }
+
+    // Announced to the paired peers right away, and to every peer paired with later
+    pub async fn set_status(&mut self, status: Status) -> Result<(), BlinkError> {
+        if self.state.peers.presence.write().set_own(status) {
+            self.command(BlinkCommand::AnnounceProfile).await?;
+        }
+        Ok(())
+    }
+
+    pub fn status(&self) -> Status {
+        self.state.peers.presence.read().own()
+    }
+
+    // Status the contact last announced, None until it did. Kept while it's disconnected
+    pub fn contact_status(&self, did: &DID) -> Option<Status> {
+        self.state.peers.presence.read().contact(&did.to_string())
+    }
+
+    // Extension namespaces the peer advertised, None until it told us
+    pub fn peer_extensions(&self, did: &DID) -> Option<Vec<String>> {
+        let peer_id = self
+            .state
+            .peers
+            .map_did_peer
+            .read()
+            .get(&did.to_string())
+            .copied()?;
+        self.state.messaging.extensions.read().remote(&peer_id)
+    }
+
+    // Sends to the extension registered under `namespace` by each recipient
+    pub async fn send_to_extension(
+        &mut self,
+        namespace: &str,
+        sata: Sata,
+    ) -> Result<(), BlinkError> {
+        extensions::validate_namespace(namespace)?;
+        self.send_to_namespace(namespace, sata).await
+    }
+
+    // Decides which messages on the topic are delivered and passed on to other peers,
+    // one validator per topic
+    pub fn register_validator(
+        &mut self,
+        topic: &str,
+        validator: Arc<RwLock<impl MessageValidator + 'static>>,
+    ) -> Result<(), BlinkError> {
+        if !self
+            .state
+            .messaging
+            .validators
+            .write()
+            .register(topic.to_string(), validator)
+        {
+            return Err(BlinkError::Invalid(format!(
+                "Topic {} already has a validator",
+                topic
+            )));
+        }
+        Ok(())
+    }
+
+    // Every message on the topic is accepted again
+    pub fn unregister_validator(&mut self, topic: &str) {
+        self.state.messaging.validators.write().unregister(topic);
+    }
+
+    // Topic of a channel of the conversation with the DID, derived from the pairwise topic so
+    // only the two of them know it
+    pub fn channel_topic(&self, did: &DID, channel: &str) -> Result<TopicName, BlinkError> {
+        self.check_channel(channel)?;
+        let topic = self
+            .state
+            .peers
+            .map_peer_topic
+            .read()
+            .get(&did.to_string())
+            .cloned();
+        let topic = topic.ok_or_else(|| BlinkError::NoTopicForDid(did.to_string()))?;
+        Ok(extensions::extension_topic(&topic, channel))
+    }
+
+    // Conversation and channel a message of the stream came in on, None for other topics
+    pub fn channel_of(&self, topic: &TopicHash) -> Option<ChannelTopic> {
+        let (pairwise_topic, channel) = match extensions::split_extension_topic(topic.as_str()) {
+            Some((pairwise_topic, channel)) => (pairwise_topic, Some(channel.to_string())),
+            None => (topic.as_str(), None),
+        };
+        let did = self.state.did_of_topic(pairwise_topic)?;
+        Some(ChannelTopic { did, channel })
+    }
+
+    // Subscribes the channels of the DID's conversation, or of every conversation without a DID,
+    // the ones paired later included. Their messages come out of the message stream
+    pub async fn open_channels(
+        &mut self,
+        did: Option<&DID>,
+        channels: &[&str],
+    ) -> Result<(), BlinkError> {
+        for channel in channels {
+            self.check_channel(channel)?;
+        }
+        let did = did.map(|x| x.to_string());
+        let mut opened = Vec::new();
+        for channel in channels {
+            if self
+                .state
+                .messaging
+                .channels
+                .write()
+                .open(did.as_deref(), channel)
+            {
+                opened.push(channel.to_string());
+            }
+        }
+        if !opened.is_empty() {
+            self.command(BlinkCommand::SubscribeChannels(did, opened))
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Undoes open_channels, a channel stays subscribed where it's still open some other way
+    pub async fn close_channels(
+        &mut self,
+        did: Option<&DID>,
+        channels: &[&str],
+    ) -> Result<(), BlinkError> {
+        let did = did.map(|x| x.to_string());
+        let mut closed = Vec::new();
+        for channel in channels {
+            if self
+                .state
+                .messaging
+                .channels
+                .write()
+                .close(did.as_deref(), channel)
+            {
+                closed.push(channel.to_string());
             }
         }
+        if !closed.is_empty() {
+            self.command(BlinkCommand::UnsubscribeChannels(did, closed))
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Sends over the channel of each recipient's conversation, recipients that didn't open it
+    // never see it
+    pub async fn send_to_channel(&mut self, channel: &str, sata: Sata) -> Result<(), BlinkError> {
+        self.check_channel(channel)?;
+        self.send_to_namespace(channel, sata).await
+    }
+
+    // Channels share the extensions' topics, they can't take a namespace that's in use
+    fn check_channel(&self, channel: &str) -> Result<(), BlinkError> {
+        extensions::validate_namespace(channel)?;
+        let reserved = channel == signaling::CALL_NAMESPACE
+            || channel == diagnostics::BENCH_NAMESPACE
+            || self
+                .state
+                .messaging
+                .extensions
+                .read()
+                .handler(channel)
+                .is_some();
+        if reserved {
+            return Err(BlinkError::Invalid(format!(
+                "Channel {} is an extension namespace",
+                channel
+            )));
+        }
+        Ok(())
+    }
 
+    async fn send_to_namespace(&mut self, namespace: &str, sata: Sata) -> Result<(), BlinkError> {
+        let mut recipients = sata.recipients().unwrap_or_default();
+        while let Some(recipient) = recipients.pop() {
+            let did = DID::from(recipient);
+            let topic = self
+                .state
+                .peers
+                .map_peer_topic
+                .read()
+                .get(&did.to_string())
+                .cloned();
+            match topic {
+                Some(topic) => {
+                    let topic = extensions::extension_topic(&topic, namespace);
+                    let journal_id =
+                        self.journal(WalOperation::Publish(topic.clone(), sata.clone()))?;
+                    self.command(BlinkCommand::PublishToTopic(
+                        topic,
+                        sata.clone(),
+                        journal_id,
+                    ))
+                    .await?;
+                }
+                None => {
+                    let _ = self.send_failed(did, RecipientError::NotPaired);
+                }
+            }
+        }
         Ok(())
     }
 }
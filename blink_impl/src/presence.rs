@@ -0,0 +1,34 @@
+use blink_contract::Status;
+use std::collections::HashMap;
+
+// Our own presence status and the last one each contact announced, by DID. A contact keeps its
+// status while disconnected, whether it's reachable is up to the ping tracker.
+#[derive(Default)]
+pub(crate) struct Presence {
+    own: Status,
+    contacts: HashMap<String, Status>,
+}
+
+impl Presence {
+    pub(crate) fn own(&self) -> Status {
+        self.own
+    }
+
+    // Returns false if it's the status already set, there's nothing to announce then.
+    pub(crate) fn set_own(&mut self, status: Status) -> bool {
+        if self.own == status {
+            return false;
+        }
+        self.own = status;
+        true
+    }
+
+    // Returns true if the contact announced a status it didn't have, its first one included.
+    pub(crate) fn announced(&mut self, did: String, status: Status) -> bool {
+        self.contacts.insert(did, status) != Some(status)
+    }
+
+    pub(crate) fn contact(&self, did: &str) -> Option<Status> {
+        self.contacts.get(did).copied()
+    }
+}
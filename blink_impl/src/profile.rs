@@ -0,0 +1,25 @@
+use crate::protocol::{BincodeCodec, BlinkProtocol};
+use blink_contract::Status;
+use libp2p::request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig};
+use serde::{Deserialize, Serialize};
+use std::iter;
+
+// 1.1.0 added the presence status, older peers' profiles can't be read without it
+const PROFILE_PROTOCOL: &[u8] = b"/blink/profile/1.1.0";
+
+pub(crate) type ProfileBehaviour = RequestResponse<BincodeCodec<PeerProfile, PeerProfile>>;
+
+// What a node tells the peers it paired with about itself, answered with the remote's own profile.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PeerProfile {
+    pub(crate) extensions: Vec<String>,
+    pub(crate) status: Status,
+}
+
+pub(crate) fn new_behaviour() -> ProfileBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(PROFILE_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
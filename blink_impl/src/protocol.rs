@@ -0,0 +1,127 @@
+use crate::wire::bounded_bincode;
+use async_trait::async_trait;
+use libp2p::{
+    core::{
+        upgrade::{read_length_prefixed, write_length_prefixed},
+        ProtocolName,
+    },
+    futures::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    request_response::RequestResponseCodec,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io, marker::PhantomData};
+
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+// Encoded size a page of messages stays under. Well below MAX_MESSAGE_SIZE, whatever travels
+// next to the messages fits too.
+pub(crate) const PAGE_BYTES: u64 = 4 * 1024 * 1024;
+
+// The first items that fit in `max_bytes` once encoded, at least one so a large item still goes.
+pub(crate) fn page_of<T: Serialize>(items: impl IntoIterator<Item = T>, max_bytes: u64) -> Vec<T> {
+    let mut bytes = 0;
+    items
+        .into_iter()
+        .take_while(|x| {
+            let first = bytes == 0;
+            bytes += bincode::serialized_size(x).unwrap_or_default();
+            first || bytes <= max_bytes
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BlinkProtocol(pub(crate) &'static [u8]);
+
+impl ProtocolName for BlinkProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0
+    }
+}
+
+// Request/response codec writing bincode encoded, length prefixed frames.
+pub(crate) struct BincodeCodec<TRequest, TResponse> {
+    phantom: PhantomData<fn() -> (TRequest, TResponse)>,
+}
+
+impl<TRequest, TResponse> Default for BincodeCodec<TRequest, TResponse> {
+    fn default() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<TRequest, TResponse> Clone for BincodeCodec<TRequest, TResponse> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+async fn read_frame<T, TData>(io: &mut T) -> io::Result<TData>
+where
+    T: AsyncRead + Unpin + Send,
+    TData: DeserializeOwned,
+{
+    let bytes = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+    bounded_bincode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_frame<T, TData>(io: &mut T, data: TData) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    TData: Serialize,
+{
+    let bytes =
+        bincode::serialize(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_length_prefixed(io, bytes).await?;
+    io.close().await
+}
+
+#[async_trait]
+impl<TRequest, TResponse> RequestResponseCodec for BincodeCodec<TRequest, TResponse>
+where
+    TRequest: Serialize + DeserializeOwned + Send,
+    TResponse: Serialize + DeserializeOwned + Send,
+{
+    type Protocol = BlinkProtocol;
+    type Request = TRequest;
+    type Response = TResponse;
+
+    async fn read_request<T>(&mut self, _: &BlinkProtocol, io: &mut T) -> io::Result<TRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_frame(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &BlinkProtocol, io: &mut T) -> io::Result<TResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_frame(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &BlinkProtocol,
+        io: &mut T,
+        request: TRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_frame(io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &BlinkProtocol,
+        io: &mut T,
+        response: TResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_frame(io, response).await
+    }
+}
@@ -0,0 +1,52 @@
+use crate::event_sink::EventSink;
+use blink_contract::Event;
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+
+// Keeps the CIDs this node provides and how many other providers were last seen for each.
+#[derive(Default)]
+pub(crate) struct ProviderTracker {
+    availability: HashMap<String, usize>,
+}
+
+impl ProviderTracker {
+    pub(crate) fn track(&mut self, cid: String) {
+        self.availability.entry(cid).or_insert(0);
+    }
+
+    pub(crate) fn untrack(&mut self, cid: &str) -> bool {
+        self.availability.remove(cid).is_some()
+    }
+
+    pub(crate) fn provided(&self) -> Vec<String> {
+        self.availability.keys().cloned().collect()
+    }
+
+    pub(crate) fn availability(&self, cid: &str) -> Option<usize> {
+        self.availability.get(cid).copied()
+    }
+
+    // Stores the remote providers found for `cid`, reporting the content at risk when it's
+    // tracked and nobody but us provides it.
+    pub(crate) fn providers_found(
+        &mut self,
+        logger: &EventSink,
+        cid: String,
+        providers: &HashSet<PeerId>,
+        local_peer_id: &PeerId,
+    ) {
+        if self.update(&cid, providers, local_peer_id) {
+            logger.event_occurred(Event::ContentAtRisk(cid));
+        }
+    }
+
+    fn update(&mut self, cid: &str, providers: &HashSet<PeerId>, local_peer_id: &PeerId) -> bool {
+        match self.availability.get_mut(cid) {
+            Some(count) => {
+                *count = providers.iter().filter(|x| *x != local_peer_id).count();
+                *count == 0
+            }
+            None => false,
+        }
+    }
+}
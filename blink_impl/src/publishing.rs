@@ -0,0 +1,111 @@
+use crate::conversations::message_id;
+use blink_contract::{BlinkError, RecipientError};
+use libp2p::gossipsub::error::PublishError;
+use sata::Sata;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+// Largest message gossipsub accepts, also configured on the behaviour
+pub(crate) const MAX_TRANSMIT_SIZE: usize = 64 * 1024;
+
+// Messages kept per topic while waiting for the mesh, the oldest are dropped past it
+const MAX_UNPUBLISHED: usize = 256;
+
+// Why `send` refused a message, these never succeed on retry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    // Serialized size and the limit, in bytes
+    TooLarge(usize, usize),
+    Serialization(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::TooLarge(size, max) => {
+                write!(f, "Message is {} bytes, at most {} can be sent", size, max)
+            }
+            SendError::Serialization(e) => write!(f, "Couldn't serialize message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+impl From<SendError> for BlinkError {
+    fn from(error: SendError) -> Self {
+        match error {
+            SendError::TooLarge(size, max) => BlinkError::TooLarge(size, max),
+            SendError::Serialization(e) => BlinkError::Serialization(e),
+        }
+    }
+}
+
+// What became of a message for each of its recipients, by DID.
+pub type SendReport = HashMap<String, Result<(), RecipientError>>;
+
+// What a failed publish means for the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PublishFailure {
+    // Nobody in the mesh yet, worth retrying once someone subscribes
+    InsufficientPeers,
+    Permanent(String),
+}
+
+impl From<PublishError> for PublishFailure {
+    fn from(error: PublishError) -> Self {
+        match error {
+            PublishError::InsufficientPeers => PublishFailure::InsufficientPeers,
+            error => PublishFailure::Permanent(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for PublishFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishFailure::InsufficientPeers => write!(f, "No peers subscribed to the topic"),
+            PublishFailure::Permanent(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// Rejects what gossipsub would refuse whatever the state of the mesh.
+pub(crate) fn check_sendable(sata: &Sata) -> Result<(), SendError> {
+    let size = bincode::serialized_size(sata)
+        .map_err(|e| SendError::Serialization(e.to_string()))? as usize;
+    if size > MAX_TRANSMIT_SIZE {
+        return Err(SendError::TooLarge(size, MAX_TRANSMIT_SIZE));
+    }
+    Ok(())
+}
+
+// Messages that found no peers on their topic, published again on the next subscription.
+#[derive(Default)]
+pub(crate) struct Unpublished {
+    topics: HashMap<String, VecDeque<Sata>>,
+}
+
+impl Unpublished {
+    pub(crate) fn defer(&mut self, topic: String, sata: Sata) {
+        let queue = self.topics.entry(topic).or_default();
+        queue.push_back(sata);
+        if queue.len() > MAX_UNPUBLISHED {
+            queue.pop_front();
+        }
+    }
+
+    pub(crate) fn take(&mut self, topic: &str) -> Vec<Sata> {
+        self.topics
+            .remove(topic)
+            .map(|x| x.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    // Drops the message from every topic's queue, it isn't to be published anymore.
+    pub(crate) fn forget(&mut self, id: &str) {
+        for queue in self.topics.values_mut() {
+            queue.retain(|x| message_id(x) != id);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Buckets tracked before the ones that refilled completely are forgotten
+const MAX_TRACKED: usize = 4096;
+
+// Sustained rate, bursts of up to one second of it are let through.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub messages_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+// Limits applied to received gossip before it reaches the cache, unlimited when left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    // Counted against the author of the message
+    pub per_peer: Option<RateLimit>,
+    pub per_topic: Option<RateLimit>,
+}
+
+// What ran out when a message was dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Limited {
+    Peer,
+    Topic,
+}
+
+struct Bucket {
+    messages: f64,
+    bytes: f64,
+    updated_at: u64,
+    // Whether the last message was dropped, so a flood is reported once
+    limited: bool,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: u64) -> Self {
+        Self {
+            messages: limit.messages_per_sec,
+            bytes: limit.bytes_per_sec,
+            updated_at: now,
+            limited: false,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: u64) {
+        let elapsed = now.saturating_sub(self.updated_at) as f64 / 1000.0;
+        self.messages =
+            (self.messages + elapsed * limit.messages_per_sec).min(limit.messages_per_sec);
+        self.bytes = (self.bytes + elapsed * limit.bytes_per_sec).min(limit.bytes_per_sec);
+        self.updated_at = now;
+    }
+
+    fn has_room(&self, bytes: f64) -> bool {
+        self.messages >= 1.0 && self.bytes >= bytes
+    }
+
+    fn take(&mut self, bytes: f64) {
+        self.messages -= 1.0;
+        self.bytes -= bytes;
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.messages >= limit.messages_per_sec && self.bytes >= limit.bytes_per_sec
+    }
+}
+
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K: Hash + Eq + Clone> Default for Buckets<K> {
+    fn default() -> Self {
+        Self {
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Buckets<K> {
+    fn refilled(&mut self, key: &K, limit: &RateLimit, now: u64) -> &mut Bucket {
+        if self.buckets.len() >= MAX_TRACKED && !self.buckets.contains_key(key) {
+            self.buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                !bucket.is_full(limit)
+            });
+        }
+        let bucket = self
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| Bucket::full(limit, now));
+        bucket.refill(limit, now);
+        bucket
+    }
+}
+
+// Token buckets per author and per topic for the receive path.
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    limits: RateLimits,
+    peers: Buckets<PeerId>,
+    topics: Buckets<String>,
+}
+
+impl RateLimiter {
+    pub(crate) fn limits(&self) -> RateLimits {
+        self.limits
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: RateLimits) {
+        self.limits = limits;
+        self.peers = Buckets::default();
+        self.topics = Buckets::default();
+    }
+
+    // Counts the message if both buckets have room for it. Otherwise returns what ran out,
+    // along with whether it's the first drop since that bucket last let something through.
+    pub(crate) fn check(
+        &mut self,
+        peer: &PeerId,
+        topic: &str,
+        bytes: usize,
+        now: u64,
+    ) -> Result<(), (Limited, bool)> {
+        let bytes = bytes as f64;
+        if let Some(limit) = &self.limits.per_peer {
+            let bucket = self.peers.refilled(peer, limit, now);
+            if !bucket.has_room(bytes) {
+                let first = !bucket.limited;
+                bucket.limited = true;
+                return Err((Limited::Peer, first));
+            }
+        }
+        if let Some(limit) = &self.limits.per_topic {
+            let bucket = self.topics.refilled(&topic.to_string(), limit, now);
+            if !bucket.has_room(bytes) {
+                let first = !bucket.limited;
+                bucket.limited = true;
+                return Err((Limited::Topic, first));
+            }
+            bucket.take(bytes);
+            bucket.limited = false;
+        }
+        if let Some(limit) = &self.limits.per_peer {
+            let bucket = self.peers.refilled(peer, limit, now);
+            bucket.take(bytes);
+            bucket.limited = false;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+use crate::peer_to_peer_service::PeerToPeerService;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use hmac_sha512::Hash;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use warp::{
+    crypto::DID,
+    error::Error,
+    module::Module,
+    raygun::{
+        EmbedState, Message, MessageOptions, PinState, RayGun, Reaction, ReactionState, SenderId,
+    },
+    Extension, SingleHandle,
+};
+
+// Everything the conversation store keeps, it forgets older messages anyway
+const HISTORY_LIMIT: usize = 10_000;
+
+// What the adapter sends over a pairwise topic. Edits, reactions and pins are messages of
+// their own, folded into the message they point to when the conversation is read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ChatPayload {
+    Message {
+        id: Uuid,
+        lines: Vec<String>,
+        replied: Option<Uuid>,
+    },
+    // Only the author's edits and deletes apply
+    Edit {
+        id: Uuid,
+        lines: Vec<String>,
+    },
+    Delete {
+        id: Uuid,
+    },
+    React {
+        id: Uuid,
+        emoji: String,
+        added: bool,
+    },
+    Pin {
+        id: Uuid,
+        pinned: bool,
+    },
+}
+
+// A message with every change made to it so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChatMessage {
+    pub(crate) id: Uuid,
+    pub(crate) sender: String,
+    // Milliseconds since the unix epoch
+    pub(crate) timestamp: u64,
+    pub(crate) lines: Vec<String>,
+    pub(crate) replied: Option<Uuid>,
+    pub(crate) pinned: bool,
+    // Emoji and the DIDs that reacted with it, in the order they were first used
+    pub(crate) reactions: Vec<(String, Vec<String>)>,
+}
+
+// Replays a conversation, given as sender, timestamp and payload, oldest first.
+pub(crate) fn fold(
+    history: impl IntoIterator<Item = (String, u64, ChatPayload)>,
+) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = Vec::new();
+    for (sender, timestamp, payload) in history {
+        match payload {
+            ChatPayload::Message { id, lines, replied } => {
+                if messages.iter().all(|x| x.id != id) {
+                    messages.push(ChatMessage {
+                        id,
+                        sender,
+                        timestamp,
+                        lines,
+                        replied,
+                        pinned: false,
+                        reactions: Vec::new(),
+                    });
+                }
+            }
+            ChatPayload::Edit { id, lines } => {
+                if let Some(message) = messages.iter_mut().find(|x| x.id == id) {
+                    if message.sender == sender {
+                        message.lines = lines;
+                    }
+                }
+            }
+            ChatPayload::Delete { id } => messages.retain(|x| x.id != id || x.sender != sender),
+            ChatPayload::React { id, emoji, added } => {
+                if let Some(message) = messages.iter_mut().find(|x| x.id == id) {
+                    react(message, emoji, sender, added);
+                }
+            }
+            ChatPayload::Pin { id, pinned } => {
+                if let Some(message) = messages.iter_mut().find(|x| x.id == id) {
+                    message.pinned = pinned;
+                }
+            }
+        }
+    }
+    messages
+}
+
+fn react(message: &mut ChatMessage, emoji: String, sender: String, added: bool) {
+    let position = message.reactions.iter().position(|(x, _)| *x == emoji);
+    match (position, added) {
+        (Some(position), true) => {
+            let users = &mut message.reactions[position].1;
+            if !users.contains(&sender) {
+                users.push(sender);
+            }
+        }
+        (None, true) => message.reactions.push((emoji, vec![sender])),
+        (Some(position), false) => {
+            message.reactions[position].1.retain(|x| *x != sender);
+            if message.reactions[position].1.is_empty() {
+                message.reactions.remove(position);
+            }
+        }
+        (None, false) => {}
+    }
+}
+
+// Same on both ends, both derive it from the pairwise topic.
+pub(crate) fn conversation_id(topic: &str) -> Uuid {
+    let hashed = Hash::hash(topic.as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hashed[..16]);
+    Uuid::from_bytes(bytes)
+}
+
+fn to_raygun(conversation: Uuid, message: &ChatMessage) -> Result<Message, Error> {
+    let mut reactions = Vec::new();
+    for (emoji, users) in &message.reactions {
+        let mut reaction = Reaction::default();
+        reaction.set_emoji(emoji);
+        reaction.set_users(
+            users
+                .iter()
+                .map(|x| Ok(SenderId::from_did_key(DID::try_from(x.clone())?)))
+                .collect::<Result<_, Error>>()?,
+        );
+        reactions.push(reaction);
+    }
+    let mut raygun = Message::default();
+    raygun.set_id(message.id);
+    raygun.set_conversation_id(conversation);
+    raygun.set_sender(SenderId::from_did_key(DID::try_from(
+        message.sender.clone(),
+    )?));
+    raygun.set_date(Utc.timestamp_millis(message.timestamp as i64));
+    raygun.set_pinned(message.pinned);
+    raygun.set_reactions(reactions);
+    raygun.set_replied(message.replied);
+    raygun.set_value(message.lines.clone());
+    Ok(raygun)
+}
+
+// Warp's messaging trait over Blink, so Blink can stand in for the messaging module of a warp
+// application. Every paired DID is a conversation, carried by its pairwise topic and read back
+// from the conversation history.
+pub struct BlinkRayGun {
+    service: Arc<Mutex<PeerToPeerService>>,
+}
+
+impl BlinkRayGun {
+    pub fn new(service: Arc<Mutex<PeerToPeerService>>) -> Self {
+        Self { service }
+    }
+
+    async fn recipient(&self, conversation: Uuid) -> Result<DID, Error> {
+        let paired = self.service.lock().await.paired();
+        let did = paired
+            .into_iter()
+            .find(|(_, topic)| conversation_id(topic) == conversation)
+            .map(|(did, _)| did)
+            .ok_or_else(|| Error::OtherWithContext(format!("No conversation {}", conversation)))?;
+        DID::try_from(did)
+    }
+
+    async fn publish(&mut self, conversation: Uuid, payload: ChatPayload) -> Result<(), Error> {
+        let did = self.recipient(conversation).await?;
+        let mut sata = Sata::default();
+        sata.add_recipient(did.as_ref())
+            .map_err(|e| Error::OtherWithContext(format!("{:?}", e)))?;
+        let sata = sata
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, &payload)
+            .map_err(|e| Error::OtherWithContext(format!("{:?}", e)))?;
+        let report = self.service.lock().await.send(sata).await?;
+        match report.get(&did.to_string()) {
+            Some(Err(e)) => Err(Error::OtherWithContext(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Extension for BlinkRayGun {
+    fn id(&self) -> String {
+        "blink-raygun".into()
+    }
+
+    fn name(&self) -> String {
+        "Blink messaging".into()
+    }
+
+    fn module(&self) -> Module {
+        Module::Messaging
+    }
+}
+
+impl SingleHandle for BlinkRayGun {}
+
+#[async_trait]
+impl RayGun for BlinkRayGun {
+    // Conversations only exist with paired DIDs, others are looked up on the DHT to pair with
+    async fn create_conversation(&mut self, did: &DID) -> Result<Uuid, Error> {
+        let mut service = self.service.lock().await;
+        if let Some(topic) = service.paired().get(&did.to_string()) {
+            return Ok(conversation_id(topic));
+        }
+        service.resolve_did(did).await?;
+        Err(Error::OtherWithContext(format!(
+            "Not paired with {} yet, it's being looked up",
+            did
+        )))
+    }
+
+    async fn list_conversations(&self) -> Result<Vec<Uuid>, Error> {
+        let paired = self.service.lock().await.paired();
+        Ok(paired.values().map(|x| conversation_id(x)).collect())
+    }
+
+    // The whole history the conversation store kept, options aren't applied
+    async fn get_messages(
+        &self,
+        conversation_id: Uuid,
+        _: MessageOptions,
+    ) -> Result<Vec<Message>, Error> {
+        let did = self.recipient(conversation_id).await?;
+        let history = self.service.lock().await.history(&did, .., HISTORY_LIMIT);
+        // Anything sent other than through the adapter isn't part of the conversation
+        let payloads = history.into_iter().filter_map(|x| {
+            let payload = x.sata.decode::<ChatPayload>().ok()?;
+            Some((x.sender, x.timestamp, payload))
+        });
+        fold(payloads)
+            .iter()
+            .map(|x| to_raygun(conversation_id, x))
+            .collect()
+    }
+
+    // Edits the message when given its id
+    async fn send(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        message: Vec<String>,
+    ) -> Result<(), Error> {
+        let payload = match message_id {
+            Some(id) => ChatPayload::Edit { id, lines: message },
+            None => ChatPayload::Message {
+                id: Uuid::new_v4(),
+                lines: message,
+                replied: None,
+            },
+        };
+        self.publish(conversation_id, payload).await
+    }
+
+    async fn delete(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.publish(conversation_id, ChatPayload::Delete { id: message_id })
+            .await
+    }
+
+    async fn react(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: ReactionState,
+        emoji: Option<String>,
+    ) -> Result<(), Error> {
+        let emoji = emoji.ok_or_else(|| Error::OtherWithContext("No emoji given".into()))?;
+        let payload = ChatPayload::React {
+            id: message_id,
+            emoji,
+            added: matches!(state, ReactionState::Add),
+        };
+        self.publish(conversation_id, payload).await
+    }
+
+    async fn pin(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: PinState,
+    ) -> Result<(), Error> {
+        let payload = ChatPayload::Pin {
+            id: message_id,
+            pinned: matches!(state, PinState::Pin),
+        };
+        self.publish(conversation_id, payload).await
+    }
+
+    async fn reply(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        message: Vec<String>,
+    ) -> Result<(), Error> {
+        let payload = ChatPayload::Message {
+            id: Uuid::new_v4(),
+            lines: message,
+            replied: Some(message_id),
+        };
+        self.publish(conversation_id, payload).await
+    }
+
+    async fn embeds(&mut self, _: Uuid, _: Uuid, _: EmbedState) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+}
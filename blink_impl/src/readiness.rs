@@ -0,0 +1,38 @@
+use libp2p::Multiaddr;
+
+// How far startup got, what `PeerToPeerService::ready` waits on.
+#[derive(Default)]
+pub(crate) struct Readiness {
+    listen_addrs: Vec<Multiaddr>,
+    // None when there was nothing to bootstrap from, whether it completed otherwise
+    bootstrap: Option<bool>,
+}
+
+impl Readiness {
+    pub(crate) fn listening(&mut self, addr: Multiaddr) {
+        if !self.listen_addrs.contains(&addr) {
+            self.listen_addrs.push(addr);
+        }
+    }
+
+    pub(crate) fn expired(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.retain(|x| x != addr);
+    }
+
+    pub(crate) fn bootstrap_started(&mut self) {
+        self.bootstrap.get_or_insert(false);
+    }
+
+    // Completed or timed out, either way Kademlia has all the peers it will find.
+    pub(crate) fn bootstrapped(&mut self) {
+        self.bootstrap = Some(true);
+    }
+
+    pub(crate) fn listen_addrs(&self) -> Vec<Multiaddr> {
+        self.listen_addrs.clone()
+    }
+
+    pub(crate) fn is_ready(&self, bootstrapped: bool) -> bool {
+        !self.listen_addrs.is_empty() && (!bootstrapped || self.bootstrap != Some(false))
+    }
+}
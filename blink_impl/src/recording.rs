@@ -0,0 +1,240 @@
+use crate::{
+    event_sink::EventSink,
+    fragments::DataFragment,
+    streams::{StreamId, VideoFrame},
+};
+use anyhow::Result;
+use blink_contract::{Event, StreamKind};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use warp::{data::DataType, pocket_dimension::PocketDimension, sync::RwLock};
+
+// Frames are grouped until a chunk holds about this many bytes
+const CHUNK_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+// Which sides of a stream end up in its recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingOptions {
+    pub incoming: bool,
+    pub outgoing: bool,
+}
+
+impl RecordingOptions {
+    fn records(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Incoming => self.incoming,
+            Direction::Outgoing => self.outgoing,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedPayload {
+    // Sequence number and encoded frame of an audio stream
+    Audio(u64, Vec<u8>),
+    Video(VideoFrame),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub direction: Direction,
+    // When the frame was sent or received, in milliseconds since the unix epoch
+    pub at: u64,
+    pub payload: RecordedPayload,
+}
+
+impl RecordedFrame {
+    fn size(&self) -> usize {
+        match &self.payload {
+            RecordedPayload::Audio(_, data) => data.len(),
+            RecordedPayload::Video(frame) => frame.data.len(),
+        }
+    }
+}
+
+// Stored as the last fragment of a recording, lists its chunks in playback order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingManifest {
+    pub stream: StreamId,
+    pub kind: StreamKind,
+    // DID on the other end of the stream
+    pub peer: String,
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub chunks: Vec<String>,
+}
+
+impl RecordingManifest {
+    pub fn from_fragment(fragment: &DataFragment) -> Result<Self> {
+        Ok(bincode::deserialize(fragment.data())?)
+    }
+}
+
+// Frames held by one chunk fragment of a recording.
+pub fn recorded_frames(chunk: &DataFragment) -> Result<Vec<RecordedFrame>> {
+    Ok(bincode::deserialize(chunk.data())?)
+}
+
+pub(crate) enum RecordingOutput {
+    Chunk(DataFragment),
+    Finished(StreamId, DataFragment),
+    Failed(String),
+}
+
+struct Recorder {
+    options: RecordingOptions,
+    kind: StreamKind,
+    peer: String,
+    started_at: u64,
+    pending: Vec<RecordedFrame>,
+    pending_size: usize,
+    chunks: Vec<String>,
+}
+
+impl Recorder {
+    fn flush(&mut self, now: u64) -> Result<Option<DataFragment>> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        let data = bincode::serialize(&self.pending)?;
+        self.pending.clear();
+        self.pending_size = 0;
+        let chunk = DataFragment::new(data, now);
+        self.chunks.push(chunk.cid().to_string());
+        Ok(Some(chunk))
+    }
+}
+
+// Streams being recorded. Finished chunks are handed to a writer task so the swarm loop never waits on the cache.
+pub(crate) struct RecordingRegistry {
+    recorders: HashMap<StreamId, Recorder>,
+    output: UnboundedSender<RecordingOutput>,
+}
+
+impl RecordingRegistry {
+    pub(crate) fn new(output: UnboundedSender<RecordingOutput>) -> Self {
+        Self {
+            recorders: HashMap::new(),
+            output,
+        }
+    }
+
+    pub(crate) fn start(
+        &mut self,
+        id: StreamId,
+        kind: StreamKind,
+        peer: String,
+        options: RecordingOptions,
+        now: u64,
+    ) -> bool {
+        if self.recorders.contains_key(&id) {
+            return false;
+        }
+        self.recorders.insert(
+            id,
+            Recorder {
+                options,
+                kind,
+                peer,
+                started_at: now,
+                pending: Vec::new(),
+                pending_size: 0,
+                chunks: Vec::new(),
+            },
+        );
+        true
+    }
+
+    pub(crate) fn is_recording(&self, id: StreamId) -> bool {
+        self.recorders.contains_key(&id)
+    }
+
+    pub(crate) fn record(&mut self, id: StreamId, frame: RecordedFrame) {
+        let recorder = match self.recorders.get_mut(&id) {
+            Some(recorder) if recorder.options.records(frame.direction) => recorder,
+            _ => return,
+        };
+        let now = frame.at;
+        recorder.pending_size += frame.size();
+        recorder.pending.push(frame);
+        if recorder.pending_size >= CHUNK_SIZE {
+            let output = match recorder.flush(now) {
+                Ok(Some(chunk)) => RecordingOutput::Chunk(chunk),
+                Ok(None) => return,
+                Err(e) => RecordingOutput::Failed(e.to_string()),
+            };
+            let _ = self.output.send(output);
+        }
+    }
+
+    // Writes what is left and the manifest, does nothing for streams that aren't recorded.
+    pub(crate) fn finish(&mut self, id: StreamId, now: u64) {
+        let mut recorder = match self.recorders.remove(&id) {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let finished = recorder.flush(now).and_then(|last| {
+            if let Some(chunk) = last {
+                let _ = self.output.send(RecordingOutput::Chunk(chunk));
+            }
+            let manifest = RecordingManifest {
+                stream: id,
+                kind: recorder.kind,
+                peer: recorder.peer,
+                started_at: recorder.started_at,
+                ended_at: now,
+                chunks: recorder.chunks,
+            };
+            Ok(DataFragment::new(bincode::serialize(&manifest)?, now))
+        });
+        let output = match finished {
+            Ok(manifest) => RecordingOutput::Finished(id, manifest),
+            Err(e) => RecordingOutput::Failed(e.to_string()),
+        };
+        let _ = self.output.send(output);
+    }
+}
+
+// Persists recorded fragments to the cache, in the order they were produced.
+pub(crate) async fn write_recordings(
+    cache: Arc<RwLock<dyn PocketDimension>>,
+    logger: EventSink,
+    mut outputs: UnboundedReceiver<RecordingOutput>,
+) {
+    while let Some(output) = outputs.recv().await {
+        match output {
+            RecordingOutput::Chunk(chunk) => {
+                if let Err(e) = store_fragment(&cache, &chunk) {
+                    logger.event_occurred(Event::RecordingError(e.to_string()));
+                }
+            }
+            RecordingOutput::Finished(id, manifest) => match store_fragment(&cache, &manifest) {
+                Ok(_) => {
+                    logger.event_occurred(Event::RecordingSaved(id, manifest.cid().to_string()))
+                }
+                Err(e) => logger.event_occurred(Event::RecordingError(e.to_string())),
+            },
+            RecordingOutput::Failed(error) => {
+                logger.event_occurred(Event::RecordingError(error));
+            }
+        }
+    }
+}
+
+fn store_fragment(cache: &Arc<RwLock<dyn PocketDimension>>, fragment: &DataFragment) -> Result<()> {
+    let sata = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, fragment)
+        .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+    cache
+        .write()
+        .add_data(DataType::FileSystem, &sata)
+        .map_err(|e| anyhow::anyhow!(e.enum_to_string()))
+}
@@ -0,0 +1,118 @@
+use libp2p::relay::v2::relay::{Config, Event};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+// Relaying for peers that can't be reached directly, off unless the node is meant to be
+// relay infrastructure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayServerSettings {
+    pub enabled: bool,
+    pub max_reservations: usize,
+    pub max_circuits: usize,
+    // Each circuit is closed once it carried this many bytes or lasted this long
+    pub max_circuit_bytes: u64,
+    pub max_circuit_duration_secs: u64,
+}
+
+impl Default for RelayServerSettings {
+    fn default() -> Self {
+        // The limits libp2p picks, meant for hole punching rather than carrying whole calls
+        let config = Config::default();
+        Self {
+            enabled: false,
+            max_reservations: config.max_reservations,
+            max_circuits: config.max_circuits,
+            max_circuit_bytes: config.max_circuit_bytes,
+            max_circuit_duration_secs: config.max_circuit_duration.as_secs(),
+        }
+    }
+}
+
+impl RelayServerSettings {
+    pub(crate) fn config(&self) -> Config {
+        Config {
+            max_reservations: self.max_reservations,
+            max_circuits: self.max_circuits,
+            max_circuit_bytes: self.max_circuit_bytes,
+            max_circuit_duration: Duration::from_secs(self.max_circuit_duration_secs),
+            ..Config::default()
+        }
+    }
+}
+
+// What the relay server is carrying, all zero when it's off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RelayStats {
+    pub reservations: usize,
+    pub circuits: usize,
+    // Since the service started
+    pub reservations_accepted: u64,
+    pub reservations_denied: u64,
+    pub circuits_accepted: u64,
+    pub circuits_denied: u64,
+    pub circuits_failed: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct RelayTracker {
+    stats: RelayStats,
+    reservations: HashSet<PeerId>,
+    // Source and destination of the open circuits, peers may have more than one between them
+    circuits: HashMap<(PeerId, PeerId), usize>,
+}
+
+impl RelayTracker {
+    pub(crate) fn stats(&self) -> RelayStats {
+        RelayStats {
+            reservations: self.reservations.len(),
+            circuits: self.circuits.values().sum(),
+            ..self.stats
+        }
+    }
+
+    pub(crate) fn event(&mut self, event: &Event) {
+        match event {
+            Event::ReservationReqAccepted { src_peer_id, .. } => {
+                // Renewals count too, they're accepted like new ones
+                self.stats.reservations_accepted += 1;
+                self.reservations.insert(*src_peer_id);
+            }
+            Event::ReservationReqDenied { .. } => self.stats.reservations_denied += 1,
+            Event::ReservationTimedOut { src_peer_id } => {
+                self.reservations.remove(src_peer_id);
+            }
+            Event::CircuitReqAccepted {
+                src_peer_id,
+                dst_peer_id,
+            } => {
+                self.stats.circuits_accepted += 1;
+                *self
+                    .circuits
+                    .entry((*src_peer_id, *dst_peer_id))
+                    .or_default() += 1;
+            }
+            Event::CircuitReqDenied { .. } => self.stats.circuits_denied += 1,
+            Event::CircuitReqOutboundConnectFailed { .. }
+            | Event::CircuitReqAcceptFailed { .. }
+            | Event::CircuitReqReceiveFailed { .. } => self.stats.circuits_failed += 1,
+            Event::CircuitClosed {
+                src_peer_id,
+                dst_peer_id,
+                ..
+            } => {
+                let key = (*src_peer_id, *dst_peer_id);
+                if let Some(count) = self.circuits.get_mut(&key) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.circuits.remove(&key);
+                    }
+                }
+            }
+            // Failing to tell a peer it was accepted or refused changes nothing we track
+            _ => {}
+        }
+    }
+}
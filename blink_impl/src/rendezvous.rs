@@ -0,0 +1,50 @@
+use hmac_sha512::Hash;
+use libp2p::rendezvous::Namespace;
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+
+// Keeps Blink registrations apart from other applications using the same rendezvous nodes
+const NAMESPACE_PREFIX: &str = "blink/";
+
+// Namespace a DID registers under, anyone who knows the DID can look it up.
+// The DID is hashed so rendezvous nodes don't get to list who uses them.
+pub(crate) fn namespace_of(did: &str) -> Namespace {
+    let hashed = base64::encode(Hash::hash(did.as_bytes()));
+    Namespace::new(format!("{}{}", NAMESPACE_PREFIX, hashed)).expect("Fits the namespace limit")
+}
+
+// Rendezvous nodes from the config and the DIDs we look for through them.
+#[derive(Default)]
+pub(crate) struct RendezvousPoints {
+    nodes: HashSet<PeerId>,
+    // Namespace to the DID it was derived from
+    wanted: HashMap<String, String>,
+}
+
+impl RendezvousPoints {
+    pub(crate) fn add_node(&mut self, node: PeerId) {
+        self.nodes.insert(node);
+    }
+
+    pub(crate) fn is_node(&self, peer: &PeerId) -> bool {
+        self.nodes.contains(peer)
+    }
+
+    pub(crate) fn nodes(&self) -> Vec<PeerId> {
+        self.nodes.iter().copied().collect()
+    }
+
+    pub(crate) fn want(&mut self, did: String) -> Namespace {
+        let namespace = namespace_of(&did);
+        self.wanted.insert(namespace.to_string(), did);
+        namespace
+    }
+
+    pub(crate) fn wanted(&self) -> Vec<Namespace> {
+        self.wanted.values().map(|x| namespace_of(x)).collect()
+    }
+
+    pub(crate) fn did_of(&self, namespace: &Namespace) -> Option<String> {
+        self.wanted.get(&namespace.to_string()).cloned()
+    }
+}
@@ -0,0 +1,45 @@
+use libp2p::futures::future::{abortable, AbortHandle};
+use std::future::Future;
+
+// Runs a background task on whatever executor the target has.
+// Tokio natively, the browser's microtask queue through wasm-bindgen-futures on wasm32.
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::spawn(future);
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+// Like `spawn`, the task stops once the handle is aborted.
+pub(crate) fn spawn_abortable<F>(future: F) -> AbortHandle
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (task, handle) = abortable(future);
+    spawn(async move {
+        let _ = task.await;
+    });
+    handle
+}
+
+// Nanoseconds since the unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn unix_nanos() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |x| x.as_nanos())
+}
+
+// Nanoseconds since the unix epoch. The browser has no system clock std can read and only
+// tells milliseconds, the rest is random so times taken within one still differ.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn unix_nanos() -> u128 {
+    ((js_sys::Date::now() + js_sys::Math::random()) * 1_000_000.0) as u128
+}
+
+pub(crate) fn unix_millis() -> u64 {
+    (unix_nanos() / 1_000_000) as u64
+}
@@ -0,0 +1,161 @@
+use crate::delivery::DeliveryStrategy;
+use libp2p::gossipsub::{PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Gossipsub peer scoring. Peers lose score for messages a validator rejected, for advertising
+// messages with IHAVE they never send, and for delivering little on topics that go through
+// the mesh. Thresholds are scores, 0 is where every peer starts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreSettings {
+    pub enabled: bool,
+    // Below it, no gossip goes to the peer and its IHAVEs are ignored
+    pub gossip_threshold: f64,
+    // Below it, nothing we publish goes to the peer
+    pub publish_threshold: f64,
+    // Below it, gossipsub ignores everything the peer sends
+    pub graylist_threshold: f64,
+    // Below it, the peer is disconnected and ignored for the rest of the session
+    pub blacklist_threshold: f64,
+    // Above it, peers the peer suggests when pruning us are dialed
+    pub accept_px_threshold: f64,
+    // Median mesh score under which better peers are grafted
+    pub opportunistic_graft_threshold: f64,
+    // Weight of the squared count of rejected messages
+    pub invalid_message_weight: f64,
+    // Weight of the squared count of broken IHAVE promises and excessive IHAVEs
+    pub behaviour_penalty_weight: f64,
+    // Weight of the squared delivery deficit on mesh topics, flooded topics don't count
+    pub mesh_delivery_weight: f64,
+}
+
+impl Default for ScoreSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gossip_threshold: -10.0,
+            publish_threshold: -50.0,
+            graylist_threshold: -80.0,
+            blacklist_threshold: -200.0,
+            accept_px_threshold: 10.0,
+            opportunistic_graft_threshold: 20.0,
+            // A third rejected message graylists, a fifth blacklists
+            invalid_message_weight: -10.0,
+            behaviour_penalty_weight: -10.0,
+            mesh_delivery_weight: -1.0,
+        }
+    }
+}
+
+impl ScoreSettings {
+    pub(crate) fn params(&self) -> PeerScoreParams {
+        PeerScoreParams {
+            behaviour_penalty_weight: self.behaviour_penalty_weight,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn thresholds(&self) -> Result<PeerScoreThresholds, String> {
+        if self.blacklist_threshold > self.graylist_threshold {
+            return Err("The blacklist threshold must not be above the graylist one".into());
+        }
+        let thresholds = PeerScoreThresholds {
+            gossip_threshold: self.gossip_threshold,
+            publish_threshold: self.publish_threshold,
+            graylist_threshold: self.graylist_threshold,
+            accept_px_threshold: self.accept_px_threshold,
+            opportunistic_graft_threshold: self.opportunistic_graft_threshold,
+        };
+        thresholds.validate()?;
+        Ok(thresholds)
+    }
+
+    // Flooded topics don't go through the mesh, the peers in it would always fall short.
+    pub(crate) fn topic_params(&self, strategy: DeliveryStrategy) -> TopicScoreParams {
+        let params = TopicScoreParams {
+            invalid_message_deliveries_weight: self.invalid_message_weight,
+            ..Default::default()
+        };
+        match strategy {
+            DeliveryStrategy::Flood => TopicScoreParams {
+                mesh_message_deliveries_weight: 0.0,
+                mesh_failure_penalty_weight: 0.0,
+                ..params
+            },
+            // Quiet topics are common, a single delivery per window is enough
+            DeliveryStrategy::Mesh => TopicScoreParams {
+                mesh_message_deliveries_weight: self.mesh_delivery_weight,
+                mesh_message_deliveries_threshold: 1.0,
+                mesh_failure_penalty_weight: self.mesh_delivery_weight,
+                ..params
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScoreLevel {
+    Fine,
+    Graylisted,
+    Blacklisted,
+}
+
+// Scores of the peers gossipsub knows, as of the last check, and the topics we set score
+// parameters for.
+#[derive(Default)]
+pub(crate) struct PeerScores {
+    settings: ScoreSettings,
+    peers: HashMap<PeerId, (f64, ScoreLevel)>,
+    topics: HashMap<String, DeliveryStrategy>,
+}
+
+impl PeerScores {
+    pub(crate) fn settings(&self) -> ScoreSettings {
+        self.settings
+    }
+
+    pub(crate) fn set_settings(&mut self, settings: ScoreSettings) {
+        self.settings = settings;
+    }
+
+    pub(crate) fn score(&self, peer: &PeerId) -> Option<f64> {
+        self.peers.get(peer).map(|(score, _)| *score)
+    }
+
+    // Records the peer's score, returning its level if it crossed a threshold since the last
+    // one. Blacklisting is for the session, a blacklisted peer doesn't come back.
+    pub(crate) fn scored(&mut self, peer: PeerId, score: f64) -> Option<ScoreLevel> {
+        let level = if score < self.settings.blacklist_threshold {
+            ScoreLevel::Blacklisted
+        } else if score < self.settings.graylist_threshold {
+            ScoreLevel::Graylisted
+        } else {
+            ScoreLevel::Fine
+        };
+        let previous = self.peers.get(&peer).map(|(_, level)| *level);
+        if previous == Some(ScoreLevel::Blacklisted) {
+            self.peers.insert(peer, (score, ScoreLevel::Blacklisted));
+            return None;
+        }
+        self.peers.insert(peer, (score, level));
+        match previous {
+            Some(previous) if previous != level => Some(level),
+            None if level != ScoreLevel::Fine => Some(level),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn disconnected(&mut self, peer: &PeerId) {
+        if !matches!(self.peers.get(peer), Some((_, ScoreLevel::Blacklisted))) {
+            self.peers.remove(peer);
+        }
+    }
+
+    // Whether the topic's parameters have to be set, because it's new or changed strategy.
+    // Gossipsub keeps them once set, whether we stay subscribed or not.
+    pub(crate) fn needs_params(&mut self, topic: &str, strategy: DeliveryStrategy) -> bool {
+        self.topics.insert(topic.to_string(), strategy) != Some(strategy)
+    }
+}
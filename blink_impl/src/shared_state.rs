@@ -0,0 +1,380 @@
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosHandle;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::{
+    auto_pairing::AutoPairing,
+    bandwidth::{self, BandwidthMeter},
+    batching::Batcher,
+    cache_policy::CacheLedger,
+    cache_writer::CacheWriter,
+    channels::ChannelRegistry,
+    conflux::ConfluxState,
+    conversations::ConversationStore,
+    delivery::DeliverySettings,
+    device_key::DeviceCertificate,
+    dht::DhtQueries,
+    diagnostics::{self, BenchmarkRegistry},
+    event_history::EventHistory,
+    expiry::Expiry,
+    extensions::{self, ExtensionRegistry},
+    external_addresses::ExternalAddresses,
+    file_transfer::TransferRegistry,
+    friends::FriendRequests,
+    group_calls::GroupRegistry,
+    identified_peers::IdentifiedPeers,
+    identity_profile::ProfileCache,
+    idle::IdleTracker,
+    mailbox::{Mailbox, MailboxSync},
+    membership::TopicMembers,
+    message_kinds::MessageKinds,
+    moderation::ModerationStore,
+    mutes::Mutes,
+    pause::PauseState,
+    peer_info::PingTracker,
+    peer_to_peer_service::BlinkCommand,
+    presence::Presence,
+    providers::ProviderTracker,
+    publishing::Unpublished,
+    rate_limit::RateLimiter,
+    readiness::Readiness,
+    recording::RecordingRegistry,
+    relay_server::RelayTracker,
+    rendezvous::RendezvousPoints,
+    scoring::PeerScores,
+    signaling::{self, CallRegistry},
+    snapshot::SnapshotStore,
+    streams::{StreamId, StreamRegistry},
+    transactions::Outbox,
+    validators::ValidatorRegistry,
+    wal::WriteAheadLog,
+    wire::WireFormat,
+};
+use blink_contract::{Clock, Keystore};
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc::Sender, Notify};
+use warp::sync::RwLock;
+
+// State shared between the service handle and its event loop, grouped by subsystem.
+#[derive(Clone)]
+pub(crate) struct SharedState {
+    pub(crate) peers: PeerState,
+    pub(crate) messaging: MessagingState,
+    pub(crate) media: MediaState,
+    pub(crate) network: NetworkState,
+    // Events so far, in front of the application's EventBus
+    pub(crate) history: Arc<RwLock<EventHistory>>,
+    pub(crate) cache_writer: CacheWriter,
+    pub(crate) local_peer: PeerId,
+    pub(crate) local_did: String,
+    // Vouches for our transport key to the other devices of our DID
+    pub(crate) certificate: DeviceCertificate,
+    // Signs what we publish on pairwise topics
+    pub(crate) keystore: Arc<dyn Keystore>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) commands: Sender<BlinkCommand>,
+    #[cfg(feature = "chaos")]
+    pub(crate) chaos: ChaosHandle,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Arc<RwLock<Metrics>>,
+}
+
+// Who the peers are, what we paired with and how they behave.
+#[derive(Clone)]
+pub(crate) struct PeerState {
+    pub(crate) map_peer_topic: Arc<RwLock<HashMap<String, String>>>,
+    pub(crate) map_did_peer: Arc<RwLock<HashMap<String, PeerId>>>,
+    pub(crate) identified: Arc<RwLock<IdentifiedPeers>>,
+    pub(crate) topic_members: Arc<RwLock<TopicMembers>>,
+    pub(crate) scores: Arc<RwLock<PeerScores>>,
+    pub(crate) idle: Arc<RwLock<IdleTracker>>,
+    pub(crate) pings: Arc<RwLock<PingTracker>>,
+    pub(crate) profiles: Arc<RwLock<ProfileCache>>,
+    pub(crate) presence: Arc<RwLock<Presence>>,
+    pub(crate) auto_pairing: Arc<RwLock<AutoPairing>>,
+    pub(crate) friends: Arc<RwLock<FriendRequests>>,
+}
+
+// Messages on their way out and in, what's held for others and what's kept of them.
+#[derive(Clone)]
+pub(crate) struct MessagingState {
+    pub(crate) mailbox: Arc<RwLock<Mailbox>>,
+    // Replays from mailboxes we're paging through
+    pub(crate) mailbox_syncs: Arc<RwLock<HashMap<PeerId, MailboxSync>>>,
+    // Messages added so far by the device syncs we're pulling pages for
+    pub(crate) device_syncs: Arc<RwLock<HashMap<PeerId, usize>>>,
+    pub(crate) wal: Arc<RwLock<Option<WriteAheadLog>>>,
+    pub(crate) outbox: Arc<RwLock<Outbox>>,
+    pub(crate) unpublished: Arc<RwLock<Unpublished>>,
+    pub(crate) batcher: Arc<RwLock<Batcher>>,
+    pub(crate) delivery: Arc<RwLock<DeliverySettings>>,
+    pub(crate) conversations: Arc<RwLock<ConversationStore>>,
+    pub(crate) cache_ledger: Arc<RwLock<CacheLedger>>,
+    pub(crate) expiry: Arc<RwLock<Expiry>>,
+    pub(crate) message_kinds: Arc<RwLock<MessageKinds>>,
+    pub(crate) validators: Arc<RwLock<ValidatorRegistry>>,
+    pub(crate) moderation: Arc<RwLock<ModerationStore>>,
+    pub(crate) mutes: Arc<RwLock<Mutes>>,
+    pub(crate) wire: Arc<RwLock<WireFormat>>,
+    pub(crate) rate_limiter: Arc<RwLock<RateLimiter>>,
+    pub(crate) extensions: Arc<RwLock<ExtensionRegistry>>,
+    pub(crate) channels: Arc<RwLock<ChannelRegistry>>,
+}
+
+// Calls, streams and their recordings, file transfers and benchmarks.
+#[derive(Clone)]
+pub(crate) struct MediaState {
+    pub(crate) streams: Arc<RwLock<StreamRegistry>>,
+    pub(crate) groups: Arc<RwLock<GroupRegistry>>,
+    pub(crate) calls: Arc<RwLock<CallRegistry>>,
+    pub(crate) recordings: Arc<RwLock<RecordingRegistry>>,
+    pub(crate) transfers: Arc<RwLock<TransferRegistry>>,
+    pub(crate) benchmarks: Arc<RwLock<BenchmarkRegistry>>,
+}
+
+// Discovery, reachability and the fragments we hold, and how much goes through.
+#[derive(Clone)]
+pub(crate) struct NetworkState {
+    pub(crate) providers: Arc<RwLock<ProviderTracker>>,
+    pub(crate) conflux: Arc<RwLock<ConfluxState>>,
+    pub(crate) rendezvous: Arc<RwLock<RendezvousPoints>>,
+    pub(crate) mdns: Arc<RwLock<bool>>,
+    pub(crate) relay: Arc<RwLock<RelayTracker>>,
+    // Relays from the config or the last reconfigure
+    pub(crate) relay_nodes: Arc<RwLock<Vec<Multiaddr>>>,
+    pub(crate) dht: Arc<RwLock<DhtQueries>>,
+    pub(crate) external: Arc<RwLock<ExternalAddresses>>,
+    pub(crate) snapshots: Arc<RwLock<SnapshotStore>>,
+    pub(crate) readiness: Arc<RwLock<Readiness>>,
+    // Notified whenever readiness changes
+    pub(crate) readiness_changed: Arc<Notify>,
+    pub(crate) pause: Arc<RwLock<PauseState>>,
+    pub(crate) bandwidth: Arc<RwLock<BandwidthMeter>>,
+}
+
+impl Default for PeerState {
+    fn default() -> Self {
+        Self {
+            map_peer_topic: Arc::new(RwLock::new(HashMap::new())),
+            map_did_peer: Arc::new(RwLock::new(HashMap::new())),
+            identified: Arc::new(RwLock::new(IdentifiedPeers::default())),
+            topic_members: Arc::new(RwLock::new(TopicMembers::default())),
+            scores: Arc::new(RwLock::new(PeerScores::default())),
+            idle: Arc::new(RwLock::new(IdleTracker::default())),
+            pings: Arc::new(RwLock::new(PingTracker::default())),
+            profiles: Arc::new(RwLock::new(ProfileCache::default())),
+            presence: Arc::new(RwLock::new(Presence::default())),
+            auto_pairing: Arc::new(RwLock::new(AutoPairing::default())),
+            friends: Arc::new(RwLock::new(FriendRequests::default())),
+        }
+    }
+}
+
+impl Default for MessagingState {
+    fn default() -> Self {
+        Self {
+            mailbox: Arc::new(RwLock::new(Mailbox::default())),
+            mailbox_syncs: Arc::new(RwLock::new(HashMap::new())),
+            device_syncs: Arc::new(RwLock::new(HashMap::new())),
+            wal: Arc::new(RwLock::new(None)),
+            outbox: Arc::new(RwLock::new(Outbox::default())),
+            unpublished: Arc::new(RwLock::new(Unpublished::default())),
+            batcher: Arc::new(RwLock::new(Batcher::default())),
+            delivery: Arc::new(RwLock::new(DeliverySettings::default())),
+            conversations: Arc::new(RwLock::new(ConversationStore::default())),
+            cache_ledger: Arc::new(RwLock::new(CacheLedger::default())),
+            expiry: Arc::new(RwLock::new(Expiry::default())),
+            message_kinds: Arc::new(RwLock::new(MessageKinds::default())),
+            validators: Arc::new(RwLock::new(ValidatorRegistry::default())),
+            moderation: Arc::new(RwLock::new(ModerationStore::default())),
+            mutes: Arc::new(RwLock::new(Mutes::default())),
+            wire: Arc::new(RwLock::new(WireFormat::default())),
+            rate_limiter: Arc::new(RwLock::new(RateLimiter::default())),
+            extensions: Arc::new(RwLock::new(ExtensionRegistry::default())),
+            channels: Arc::new(RwLock::new(ChannelRegistry::default())),
+        }
+    }
+}
+
+impl MediaState {
+    fn new(recordings: RecordingRegistry) -> Self {
+        Self {
+            streams: Arc::new(RwLock::new(StreamRegistry::default())),
+            groups: Arc::new(RwLock::new(GroupRegistry::default())),
+            calls: Arc::new(RwLock::new(CallRegistry::default())),
+            recordings: Arc::new(RwLock::new(recordings)),
+            transfers: Arc::new(RwLock::new(TransferRegistry::default())),
+            benchmarks: Arc::new(RwLock::new(BenchmarkRegistry::default())),
+        }
+    }
+}
+
+impl Default for NetworkState {
+    fn default() -> Self {
+        Self {
+            providers: Arc::new(RwLock::new(ProviderTracker::default())),
+            conflux: Arc::new(RwLock::new(ConfluxState::default())),
+            rendezvous: Arc::new(RwLock::new(RendezvousPoints::default())),
+            mdns: Arc::new(RwLock::new(true)),
+            relay: Arc::new(RwLock::new(RelayTracker::default())),
+            relay_nodes: Arc::new(RwLock::new(Vec::new())),
+            dht: Arc::new(RwLock::new(DhtQueries::default())),
+            external: Arc::new(RwLock::new(ExternalAddresses::default())),
+            snapshots: Arc::new(RwLock::new(SnapshotStore::default())),
+            readiness: Arc::new(RwLock::new(Readiness::default())),
+            readiness_changed: Arc::new(Notify::new()),
+            pause: Arc::new(RwLock::new(PauseState::default())),
+            bandwidth: Arc::new(RwLock::new(BandwidthMeter::default())),
+        }
+    }
+}
+
+impl SharedState {
+    pub(crate) fn new(
+        commands: Sender<BlinkCommand>,
+        clock: Arc<dyn Clock>,
+        local_peer: PeerId,
+        certificate: DeviceCertificate,
+        keystore: Arc<dyn Keystore>,
+        recordings: RecordingRegistry,
+        cache_writer: CacheWriter,
+        history: Arc<RwLock<EventHistory>>,
+    ) -> Self {
+        #[cfg(feature = "chaos")]
+        let chaos = ChaosHandle::new(commands.clone());
+        Self {
+            peers: PeerState::default(),
+            messaging: MessagingState::default(),
+            media: MediaState::new(recordings),
+            network: NetworkState::default(),
+            history,
+            cache_writer,
+            local_peer,
+            local_did: certificate.did.clone(),
+            certificate,
+            keystore,
+            clock,
+            commands,
+            #[cfg(feature = "chaos")]
+            chaos,
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(RwLock::new(Metrics::default())),
+        }
+    }
+
+    // Whether an injected fault swallows the next publish
+    #[cfg(feature = "chaos")]
+    pub(crate) fn drops_publish(&self) -> bool {
+        self.chaos.take_dropped_publish()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    pub(crate) fn drops_publish(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "chaos")]
+    pub(crate) fn fails_cache_write(&self) -> bool {
+        self.chaos.take_failed_cache_write()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    pub(crate) fn fails_cache_write(&self) -> bool {
+        false
+    }
+
+    // Counts a request or response going out to the peer, frames count towards their stream too
+    pub(crate) fn count_sent(
+        &self,
+        peer: &PeerId,
+        stream: Option<StreamId>,
+        message: &impl Serialize,
+    ) {
+        let bytes = bandwidth::size_of(message);
+        let now = self.clock.now_millis();
+        self.network
+            .bandwidth
+            .write()
+            .sent(Some(peer), stream, bytes, now);
+        self.peers.idle.write().active(peer, now);
+    }
+
+    // DID of a peer we identified, falling back to its PeerId
+    pub(crate) fn did_of(&self, peer_id: &PeerId) -> String {
+        if *peer_id == self.local_peer {
+            return self.local_did.clone();
+        }
+        self.peers
+            .map_did_peer
+            .read()
+            .iter()
+            .find(|(_, peer)| *peer == peer_id)
+            .map_or(peer_id.to_string(), |(did, _)| did.clone())
+    }
+
+    // DID of a peer that proved it and that we're paired with, None for strangers
+    pub(crate) fn paired_did(&self, peer: &PeerId) -> Option<String> {
+        let did = self.peers.identified.read().did_of(peer)?;
+        self.peers
+            .map_peer_topic
+            .read()
+            .contains_key(&did)
+            .then(|| did)
+    }
+
+    // Whether the peer could have published on the topic: on a pairwise topic it has to be the
+    // peer it's shared with or another device of ours, on others a subscriber we know of
+    pub(crate) fn may_flood(&self, peer: &PeerId, topic: &str) -> bool {
+        match self.paired_did_of_topic(topic) {
+            Some(paired) => self
+                .identified
+                .read()
+                .did_of(peer)
+                .map_or(false, |x| x == paired || x == self.local_did),
+            None => self.peers.topic_members.read().peers(topic).contains(peer),
+        }
+    }
+
+    // DID of the peer we share the pairwise topic with
+    pub(crate) fn did_of_topic(&self, topic: &str) -> Option<String> {
+        self.peers
+            .map_peer_topic
+            .read()
+            .iter()
+            .find(|(_, x)| *x == topic)
+            .map(|(did, _)| did.clone())
+    }
+
+    // DID of the peer we share the pairwise topic with, or the one the extension topic is on
+    pub(crate) fn paired_did_of_topic(&self, topic: &str) -> Option<String> {
+        let pairwise_topic = extensions::split_extension_topic(topic).map_or(topic, |x| x.0);
+        self.did_of_topic(pairwise_topic)
+    }
+
+    // Namespaces subscribed on every pairwise topic: the registered extensions, call signaling and benchmark probes
+    pub(crate) fn channel_namespaces(&self) -> Vec<String> {
+        let mut namespaces = self.messaging.extensions.read().namespaces();
+        namespaces.push(signaling::CALL_NAMESPACE.to_string());
+        namespaces.push(diagnostics::BENCH_NAMESPACE.to_string());
+        namespaces
+    }
+
+    // Namespaces subscribed on the DID's pairwise topic, the channels opened on it included
+    pub(crate) fn conversation_namespaces(&self, did: &str) -> Vec<String> {
+        let mut namespaces = self.channel_namespaces();
+        namespaces.extend(self.messaging.channels.read().channels_of(did));
+        namespaces
+    }
+
+    // Pairwise topic of the DID, or of every paired DID without one
+    pub(crate) fn conversation_topics(&self, did: Option<&str>) -> Vec<String> {
+        let topics = self.peers.map_peer_topic.read();
+        match did {
+            Some(did) => topics.get(did).cloned().into_iter().collect(),
+            None => topics.values().cloned().collect(),
+        }
+    }
+}
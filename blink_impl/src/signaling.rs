@@ -0,0 +1,98 @@
+use blink_contract::StreamKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Reserved extension namespace the signaling rides on
+pub(crate) const CALL_NAMESPACE: &str = "blink.call";
+
+pub type CallId = u64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum CallSignal {
+    Invite(CallId, StreamKind),
+    Accept(CallId),
+    Reject(CallId),
+    // Answer to an invite while another call is going on
+    Busy(CallId),
+    Hangup(CallId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CallState {
+    // We invited the peer and wait for its answer
+    Ringing,
+    // The peer invited us
+    Incoming,
+    Active,
+}
+
+pub(crate) struct Call {
+    pub(crate) peer: String,
+    pub(crate) state: CallState,
+}
+
+// Calls being set up or going on, by id, with the DID on the other end.
+#[derive(Default)]
+pub(crate) struct CallRegistry {
+    calls: HashMap<CallId, Call>,
+}
+
+impl CallRegistry {
+    pub(crate) fn invite(&mut self, id: CallId, peer: String) {
+        self.calls.insert(
+            id,
+            Call {
+                peer,
+                state: CallState::Ringing,
+            },
+        );
+    }
+
+    // Records an invite, false if we are busy with another call.
+    pub(crate) fn incoming(&mut self, id: CallId, peer: String) -> bool {
+        if self.calls.values().any(|x| x.state == CallState::Active) {
+            return false;
+        }
+        self.calls.insert(
+            id,
+            Call {
+                peer,
+                state: CallState::Incoming,
+            },
+        );
+        true
+    }
+
+    // Accepts an invite we received, returns the peer to answer.
+    pub(crate) fn accept(&mut self, id: CallId) -> Option<String> {
+        let call = self.calls.get_mut(&id)?;
+        if call.state != CallState::Incoming {
+            return None;
+        }
+        call.state = CallState::Active;
+        Some(call.peer.clone())
+    }
+
+    // The peer we invited picked up.
+    pub(crate) fn accepted(&mut self, id: CallId, peer: &str) -> bool {
+        match self.calls.get_mut(&id) {
+            Some(call) if call.peer == peer && call.state == CallState::Ringing => {
+                call.state = CallState::Active;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Forgets the call if `peer` is part of it.
+    pub(crate) fn end(&mut self, id: CallId, peer: &str) -> Option<Call> {
+        if self.calls.get(&id)?.peer != peer {
+            return None;
+        }
+        self.calls.remove(&id)
+    }
+
+    pub(crate) fn peer_of(&self, id: CallId) -> Option<String> {
+        self.calls.get(&id).map(|x| x.peer.clone())
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+// The routing table of a well connected node, more only slows the restart down
+pub(crate) const MAX_SNAPSHOT_PEERS: usize = 256;
+
+// What a restarted node needs to rejoin the network quickly: the peers Kademlia knew and
+// the topics we were subscribed to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct NetworkSnapshot {
+    // PeerId, as text, and the addresses Kademlia had for it
+    pub(crate) peers: Vec<(String, Vec<Multiaddr>)>,
+    pub(crate) topics: Vec<String>,
+}
+
+impl NetworkSnapshot {
+    // Peers whose id still parses, a snapshot of another version may not.
+    pub(crate) fn peers(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.peers
+            .iter()
+            .filter_map(|(peer, addrs)| Some((peer.parse().ok()?, addrs.clone())))
+            .collect()
+    }
+}
+
+// Where the snapshot is saved, nothing is until a path is set.
+#[derive(Default)]
+pub(crate) struct SnapshotStore {
+    path: Option<PathBuf>,
+}
+
+impl SnapshotStore {
+    // Reads the snapshot a previous run saved at `path`, empty if there's none, and saves
+    // there from now on.
+    pub(crate) fn open(&mut self, path: impl AsRef<Path>) -> Result<NetworkSnapshot> {
+        let path = path.as_ref().to_path_buf();
+        let snapshot = match fs::read(&path) {
+            Ok(bytes) => bincode::deserialize(&bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => NetworkSnapshot::default(),
+            Err(e) => return Err(e.into()),
+        };
+        self.path = Some(path);
+        Ok(snapshot)
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // Written next to the target then renamed, a crash never leaves half a file behind
+    pub(crate) fn save(&self, snapshot: &NetworkSnapshot) -> Result<()> {
+        if let Some(path) = &self.path {
+            let mut temporary = path.clone().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, bincode::serialize(snapshot)?)?;
+            fs::rename(&temporary, path)?;
+        }
+        Ok(())
+    }
+}
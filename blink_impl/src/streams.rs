@@ -0,0 +1,1046 @@
+use crate::{
+    behavior::BlinkBehavior,
+    congestion::{FeedbackReport, LinkQuality, RateController, ReceiveStats, AUDIO_MAX_BITRATE},
+    event_sink::EventSink,
+    group_calls::{Downlink, GroupCallId, GroupTag, Uplink},
+    peer_to_peer_service::BlinkCommand,
+    protocol::{BincodeCodec, BlinkProtocol},
+    recording::{Direction, RecordedFrame, RecordedPayload},
+    shared_state::SharedState,
+};
+use anyhow::Result;
+use blink_contract::{Event, StreamKind, VideoCaps};
+use libp2p::{
+    futures::Stream,
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    PeerId, Swarm,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    iter,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::{
+    mpsc::{self, Receiver, Sender},
+    watch,
+};
+
+const STREAM_PROTOCOL: &[u8] = b"/blink/stream/1.0.0";
+
+const FRAME_BUFFER_SIZE: usize = 64;
+
+// Frames sent but not acknowledged yet, past this the link is backed up and deltas get dropped
+const MAX_FRAMES_IN_FLIGHT: usize = 16;
+
+// Incoming streams of one peer the application hasn't taken yet, further ones are rejected
+const MAX_INCOMING_PER_PEER: usize = 8;
+
+const AUDIO_CODECS: &[&str] = &["opus"];
+
+const VIDEO_CODECS: &[&str] = &["vp8", "h264"];
+
+// What we're willing to receive unless told otherwise
+pub(crate) const DEFAULT_VIDEO_CAPS: VideoCaps = VideoCaps {
+    max_width: 1280,
+    max_height: 720,
+    max_bitrate: 2_500_000,
+};
+
+pub type StreamId = u64;
+
+pub(crate) type StreamBehaviour = RequestResponse<BincodeCodec<StreamMessage, StreamResponse>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum StreamMessage {
+    // Stream id, kind, the codecs the caller can encode, in order of preference, the video caps it asks for
+    // and the group call the stream belongs to
+    Open(
+        StreamId,
+        StreamKind,
+        Vec<String>,
+        Option<VideoCaps>,
+        Option<GroupTag>,
+    ),
+    // Stream id, sequence number and an encoded frame
+    Frame(StreamId, u64, Vec<u8>),
+    VideoFrame(StreamId, VideoFrame),
+    // The receiver lost track of the deltas and can't decode until the next keyframe
+    KeyframeRequest(StreamId),
+    // Sent periodically by the receiving end so the sender can adapt its bitrate
+    Feedback(StreamId, FeedbackReport),
+    Mute(StreamId, bool),
+    Close(StreamId),
+    // Sent by the forwarder of a group call whenever someone joins or leaves
+    GroupParticipants(GroupCallId, Vec<String>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum StreamResponse {
+    // Negotiated codec and, for video, the caps both ends agreed on
+    Accepted(String, Option<VideoCaps>),
+    Rejected,
+    Received,
+}
+
+// An encoded video frame.
+// Delta frames only decode on top of the frames before them, keyframes decode on their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoFrame {
+    pub sequence: u64,
+    pub keyframe: bool,
+    // Capture time in milliseconds, used by the consumer to pace rendering
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+    // Only set on screen share streams
+    pub screen: Option<ScreenMetadata>,
+}
+
+// Area of the display, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// What a receiver needs to render a shared screen without decoding more than changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenMetadata {
+    pub display_width: u32,
+    pub display_height: u32,
+    // None while the cursor is outside the shared display
+    pub cursor: Option<(u32, u32)>,
+    // Parts of the display that changed since the previous frame, the whole display on keyframes
+    pub dirty_regions: Vec<Region>,
+}
+
+// A captured screen frame, ready to be sent on screen share streams.
+#[derive(Debug, Clone)]
+pub struct ScreenFrame {
+    pub keyframe: bool,
+    pub timestamp: u64,
+    pub metadata: ScreenMetadata,
+    pub data: Vec<u8>,
+}
+
+impl StreamMessage {
+    // Stream of a media frame and whether it can be dropped without breaking decoding for long
+    pub(crate) fn frame_of(&self) -> Option<(StreamId, bool)> {
+        match self {
+            StreamMessage::Frame(id, _, _) => Some((*id, true)),
+            StreamMessage::VideoFrame(id, frame) => Some((*id, !frame.keyframe)),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn new_behaviour() -> StreamBehaviour {
+    RequestResponse::new(
+        BincodeCodec::default(),
+        iter::once((BlinkProtocol(STREAM_PROTOCOL), ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    )
+}
+
+pub(crate) fn supported_codecs(kind: StreamKind) -> Vec<String> {
+    let codecs = match kind {
+        StreamKind::Audio => AUDIO_CODECS,
+        StreamKind::Video | StreamKind::ScreenShare => VIDEO_CODECS,
+    };
+    codecs.iter().map(|x| x.to_string()).collect()
+}
+
+// Picks the first codec offered by the caller that we support.
+pub(crate) fn negotiate_codec(kind: StreamKind, offered: &[String]) -> Option<String> {
+    let supported = supported_codecs(kind);
+    offered.iter().find(|x| supported.contains(x)).cloned()
+}
+
+struct VideoState {
+    frames: Sender<VideoFrame>,
+    next_sequence: u64,
+    // Deltas are useless until a keyframe arrives, either at the start or after a lost frame
+    waiting_for_keyframe: bool,
+    keyframe_requested: bool,
+}
+
+struct StreamState {
+    peer: PeerId,
+    kind: StreamKind,
+    frames: Sender<Vec<u8>>,
+    video: Option<VideoState>,
+    received: ReceiveStats,
+    rate: RateController,
+    quality: watch::Sender<LinkQuality>,
+    in_flight: usize,
+}
+
+// Live streams known to the event loop, and the incoming ones the application hasn't taken yet.
+pub(crate) struct StreamRegistry {
+    streams: HashMap<StreamId, StreamState>,
+    incoming: HashMap<StreamId, CallHandle>,
+    opening: HashMap<RequestId, StreamId>,
+    // Frames waiting for an acknowledgement, with the time they were sent
+    frames_in_flight: HashMap<RequestId, (StreamId, u64)>,
+    video_caps: VideoCaps,
+}
+
+impl Default for StreamRegistry {
+    fn default() -> Self {
+        Self {
+            streams: HashMap::new(),
+            incoming: HashMap::new(),
+            opening: HashMap::new(),
+            frames_in_flight: HashMap::new(),
+            video_caps: DEFAULT_VIDEO_CAPS,
+        }
+    }
+}
+
+impl StreamRegistry {
+    // None if the id is already taken, whoever the stream is with.
+    pub(crate) fn open(
+        &mut self,
+        id: StreamId,
+        peer: PeerId,
+        kind: StreamKind,
+        commands: Sender<BlinkCommand>,
+    ) -> Option<CallHandle> {
+        if self.streams.contains_key(&id) {
+            return None;
+        }
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_BUFFER_SIZE);
+        let (video, video_frames) = match kind {
+            StreamKind::Video | StreamKind::ScreenShare => {
+                let (video_tx, video_rx) = mpsc::channel(FRAME_BUFFER_SIZE);
+                let video = VideoState {
+                    frames: video_tx,
+                    next_sequence: 0,
+                    waiting_for_keyframe: true,
+                    keyframe_requested: false,
+                };
+                (Some(video), Some(VideoStream { frames: video_rx }))
+            }
+            StreamKind::Audio => (None, None),
+        };
+        let rate = RateController::new(match kind {
+            StreamKind::Audio => AUDIO_MAX_BITRATE,
+            StreamKind::Video | StreamKind::ScreenShare => self.video_caps.max_bitrate,
+        });
+        let (quality_tx, quality_rx) = watch::channel(rate.quality());
+        self.streams.insert(
+            id,
+            StreamState {
+                peer,
+                kind,
+                frames: frames_tx,
+                video,
+                received: ReceiveStats::default(),
+                rate,
+                quality: quality_tx,
+                in_flight: 0,
+            },
+        );
+        Some(CallHandle {
+            id,
+            peer,
+            kind,
+            sequence: 0,
+            muted: false,
+            commands,
+            frames: frames_rx,
+            video_frames,
+            quality: quality_rx,
+        })
+    }
+
+    // Opens a stream the peer asked for and parks its handle until the application takes it.
+    // The peer picked the id, it's refused if taken, and so is a peer with too many waiting.
+    pub(crate) fn open_incoming(
+        &mut self,
+        id: StreamId,
+        peer: PeerId,
+        kind: StreamKind,
+        commands: Sender<BlinkCommand>,
+    ) -> bool {
+        let waiting = self.incoming.values().filter(|x| x.peer == peer).count();
+        if waiting >= MAX_INCOMING_PER_PEER {
+            return false;
+        }
+        match self.open(id, peer, kind, commands) {
+            Some(handle) => {
+                self.incoming.insert(id, handle);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn video_caps(&self) -> VideoCaps {
+        self.video_caps
+    }
+
+    pub(crate) fn set_video_caps(&mut self, caps: VideoCaps) {
+        self.video_caps = caps;
+    }
+
+    pub(crate) fn take_incoming(&mut self, id: StreamId) -> Option<CallHandle> {
+        self.incoming.remove(&id)
+    }
+
+    pub(crate) fn track_open_request(&mut self, request_id: RequestId, id: StreamId) {
+        self.opening.insert(request_id, id);
+    }
+
+    pub(crate) fn open_request_completed(&mut self, request_id: &RequestId) -> Option<StreamId> {
+        self.opening.remove(request_id)
+    }
+
+    // Whether the application took the handle of an incoming stream
+    pub(crate) fn is_accepted(&self, id: StreamId) -> bool {
+        self.streams.contains_key(&id) && !self.incoming.contains_key(&id)
+    }
+
+    pub(crate) fn peer_of(&self, id: StreamId) -> Option<PeerId> {
+        self.streams.get(&id).map(|x| x.peer)
+    }
+
+    pub(crate) fn has_streams_with(&self, peer: &PeerId) -> bool {
+        self.streams.values().any(|x| x.peer == *peer)
+    }
+
+    pub(crate) fn kind_of(&self, id: StreamId) -> Option<StreamKind> {
+        self.streams.get(&id).map(|x| x.kind)
+    }
+
+    // Hands a received frame to the application, dropping it if the consumer is lagging behind.
+    pub(crate) fn push_frame(
+        &mut self,
+        id: StreamId,
+        from: &PeerId,
+        sequence: u64,
+        frame: Vec<u8>,
+        arrived_at: u64,
+    ) -> bool {
+        match self.streams.get_mut(&id) {
+            Some(stream) if stream.peer == *from => {
+                stream.received.on_frame(sequence, None, arrived_at);
+                stream.frames.try_send(frame).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    // Hands a received video frame to the application.
+    // Deltas that can't be decoded are dropped, returns true when the sender should be asked for a keyframe.
+    pub(crate) fn push_video_frame(
+        &mut self,
+        id: StreamId,
+        from: &PeerId,
+        frame: VideoFrame,
+        arrived_at: u64,
+    ) -> bool {
+        let stream = match self.streams.get_mut(&id) {
+            Some(stream) if stream.peer == *from => stream,
+            _ => return false,
+        };
+        let video = match stream.video.as_mut() {
+            Some(video) => video,
+            None => return false,
+        };
+        stream
+            .received
+            .on_frame(frame.sequence, Some(frame.timestamp), arrived_at);
+
+        if frame.sequence != video.next_sequence {
+            video.waiting_for_keyframe = true;
+        }
+        // The remote picks the sequence numbers
+        video.next_sequence = frame.sequence.wrapping_add(1);
+        if frame.keyframe {
+            video.waiting_for_keyframe = false;
+            video.keyframe_requested = false;
+        }
+
+        // A frame the consumer didn't take breaks the chain of deltas just like a lost one
+        if !video.waiting_for_keyframe && video.frames.try_send(frame).is_err() {
+            video.waiting_for_keyframe = true;
+        }
+
+        let request_keyframe = video.waiting_for_keyframe && !video.keyframe_requested;
+        if request_keyframe {
+            video.keyframe_requested = true;
+        }
+        request_keyframe
+    }
+
+    // Reports for the streams we received frames on since the last call.
+    pub(crate) fn take_feedback_reports(&mut self) -> Vec<(PeerId, StreamId, FeedbackReport)> {
+        self.streams
+            .iter_mut()
+            .filter_map(|(id, stream)| {
+                stream
+                    .received
+                    .take_report()
+                    .map(|report| (stream.peer, *id, report))
+            })
+            .collect()
+    }
+
+    // Applies the remote's report, returns the new target bitrate if it changed.
+    pub(crate) fn feedback_received(
+        &mut self,
+        id: StreamId,
+        from: &PeerId,
+        report: FeedbackReport,
+    ) -> Option<u32> {
+        let stream = self.streams.get_mut(&id).filter(|x| x.peer == *from)?;
+        let changed = stream.rate.on_feedback(report);
+        stream.quality.send_replace(stream.rate.quality());
+        changed
+    }
+
+    pub(crate) fn set_max_bitrate(&mut self, id: StreamId, max_bitrate: u32) {
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.rate.set_max_bitrate(max_bitrate);
+            stream.quality.send_replace(stream.rate.quality());
+        }
+    }
+
+    // Deltas are dropped rather than queued behind a backed up link
+    pub(crate) fn should_drop_frame(&self, id: StreamId, droppable: bool) -> bool {
+        droppable
+            && self
+                .streams
+                .get(&id)
+                .map_or(false, |x| x.in_flight >= MAX_FRAMES_IN_FLIGHT)
+    }
+
+    pub(crate) fn track_frame(&mut self, request_id: RequestId, id: StreamId, sent_at: u64) {
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.in_flight += 1;
+            self.frames_in_flight.insert(request_id, (id, sent_at));
+        }
+    }
+
+    // Acknowledged frames give a round trip sample, failed ones (`acknowledged_at` None) only free their slot
+    pub(crate) fn frame_completed(&mut self, request_id: &RequestId, acknowledged_at: Option<u64>) {
+        if let Some((id, sent_at)) = self.frames_in_flight.remove(request_id) {
+            if let Some(stream) = self.streams.get_mut(&id) {
+                stream.in_flight = stream.in_flight.saturating_sub(1);
+                if let Some(acknowledged_at) = acknowledged_at {
+                    stream
+                        .rate
+                        .on_rtt_sample(acknowledged_at.saturating_sub(sent_at));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn close(&mut self, id: StreamId) -> bool {
+        self.frames_in_flight.retain(|_, (stream, _)| *stream != id);
+        self.incoming.remove(&id);
+        self.streams.remove(&id).is_some()
+    }
+}
+
+// Handle to a live stream with a peer.
+// Frames are opaque to Blink, they are expected to be encoded with the negotiated codec.
+pub struct CallHandle {
+    id: StreamId,
+    peer: PeerId,
+    kind: StreamKind,
+    sequence: u64,
+    muted: bool,
+    commands: Sender<BlinkCommand>,
+    frames: Receiver<Vec<u8>>,
+    video_frames: Option<VideoStream>,
+    quality: watch::Receiver<LinkQuality>,
+}
+
+impl CallHandle {
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    pub fn kind(&self) -> StreamKind {
+        self.kind
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    // Latest estimate from the remote's feedback, encoders should follow `target_bitrate`
+    pub fn link_quality(&self) -> LinkQuality {
+        *self.quality.borrow()
+    }
+
+    pub async fn send_frame(&mut self, frame: Vec<u8>) -> Result<()> {
+        if self.muted {
+            return Ok(());
+        }
+        self.sequence += 1;
+        self.send(StreamMessage::Frame(self.id, self.sequence, frame))
+            .await
+    }
+
+    // Resolves to None once the remote hung up
+    pub async fn next_frame(&mut self) -> Option<Vec<u8>> {
+        self.frames.recv().await
+    }
+
+    pub async fn send_video_frame(
+        &mut self,
+        keyframe: bool,
+        timestamp: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        self.send_visual_frame(keyframe, timestamp, data, None)
+            .await
+    }
+
+    pub async fn send_screen_frame(&mut self, frame: ScreenFrame) -> Result<()> {
+        self.send_visual_frame(
+            frame.keyframe,
+            frame.timestamp,
+            frame.data,
+            Some(frame.metadata),
+        )
+        .await
+    }
+
+    // Frames received on a video stream, can only be taken once
+    pub fn video_frames(&mut self) -> Option<VideoStream> {
+        self.video_frames.take()
+    }
+
+    pub async fn mute(&mut self, muted: bool) -> Result<()> {
+        self.muted = muted;
+        self.send(StreamMessage::Mute(self.id, muted)).await
+    }
+
+    pub async fn hangup(self) -> Result<()> {
+        self.commands
+            .send(BlinkCommand::CloseStream(self.id))
+            .await?;
+        Ok(())
+    }
+
+    async fn send_visual_frame(
+        &mut self,
+        keyframe: bool,
+        timestamp: u64,
+        data: Vec<u8>,
+        screen: Option<ScreenMetadata>,
+    ) -> Result<()> {
+        if self.muted {
+            return Ok(());
+        }
+        self.sequence += 1;
+        let frame = VideoFrame {
+            sequence: self.sequence,
+            keyframe,
+            timestamp,
+            data,
+            screen,
+        };
+        self.send(StreamMessage::VideoFrame(self.id, frame)).await
+    }
+
+    async fn send(&self, message: StreamMessage) -> Result<()> {
+        self.commands
+            .send(BlinkCommand::SendStreamMessage(self.peer, message))
+            .await?;
+        Ok(())
+    }
+}
+
+// Decodable video frames of a stream, in order.
+// Ends once the remote hung up.
+pub struct VideoStream {
+    frames: Receiver<VideoFrame>,
+}
+
+impl Stream for VideoStream {
+    type Item = VideoFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<VideoFrame>> {
+        self.frames.poll_recv(cx)
+    }
+}
+
+// Answers what the peers send over their streams and tracks the answers to ours.
+pub(crate) fn handle_event(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    event: RequestResponseEvent<StreamMessage, StreamResponse>,
+) {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => {
+                let response = handle_message(swarm, peer, request, logger.clone(), state);
+                state.count_sent(&peer, None, &response);
+                if swarm
+                    .behaviour_mut()
+                    .streams
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    logger.event_occurred(Event::StreamError(
+                        "Connection closed before responding".into(),
+                    ));
+                }
+            }
+            RequestResponseMessage::Response {
+                request_id,
+                response,
+            } => {
+                state
+                    .media
+                    .streams
+                    .write()
+                    .frame_completed(&request_id, Some(state.clock.now_millis()));
+                let opened = state
+                    .media
+                    .streams
+                    .write()
+                    .open_request_completed(&request_id);
+                if let Some(id) = opened {
+                    match response {
+                        StreamResponse::Accepted(codec, caps) => {
+                            logger.event_occurred(Event::StreamOpened(id, codec));
+                            if let Some(caps) = caps {
+                                state
+                                    .media
+                                    .streams
+                                    .write()
+                                    .set_max_bitrate(id, caps.max_bitrate);
+                                logger.event_occurred(Event::VideoCapsNegotiated(id, caps));
+                            }
+                        }
+                        _ => {
+                            state.media.streams.write().close(id);
+                            logger.event_occurred(Event::StreamRejected(id));
+                        }
+                    }
+                }
+            }
+        },
+        RequestResponseEvent::OutboundFailure {
+            request_id, error, ..
+        } => {
+            state
+                .media
+                .streams
+                .write()
+                .frame_completed(&request_id, None);
+            let opened = state
+                .media
+                .streams
+                .write()
+                .open_request_completed(&request_id);
+            if let Some(id) = opened {
+                state.media.streams.write().close(id);
+                logger.event_occurred(Event::StreamRejected(id));
+            }
+            logger.event_occurred(Event::StreamError(error.to_string()));
+        }
+        RequestResponseEvent::InboundFailure { error, .. } => {
+            logger.event_occurred(Event::StreamError(error.to_string()));
+        }
+        RequestResponseEvent::ResponseSent { .. } => {}
+    }
+}
+
+fn handle_message(
+    swarm: &mut Swarm<BlinkBehavior>,
+    peer: PeerId,
+    message: StreamMessage,
+    logger: EventSink,
+    state: &SharedState,
+) -> StreamResponse {
+    match message {
+        StreamMessage::Open(id, kind, offered, requested_caps, group) => {
+            let codec = match negotiate_codec(kind, &offered) {
+                Some(codec) => codec,
+                None => return StreamResponse::Rejected,
+            };
+            // Calls are with paired peers, a stranger gets nothing through before it's a friend
+            let did = match peer == state.local_peer {
+                true => Some(state.local_did.clone()),
+                false => state.paired_did(&peer),
+            };
+            let did = match did {
+                Some(did) if !state.messaging.moderation.read().is_blocked(&did) => did,
+                _ => return StreamResponse::Rejected,
+            };
+            if let Some(GroupTag {
+                group,
+                origin: None,
+            }) = group
+            {
+                if !state.media.groups.read().is_hosting(group)
+                    || state.messaging.moderation.read().is_banned(group, &did)
+                {
+                    return StreamResponse::Rejected;
+                }
+            }
+
+            let caps = {
+                let mut streams = state.media.streams.write();
+                let local_caps = streams.video_caps();
+                let caps = match kind {
+                    StreamKind::Video | StreamKind::ScreenShare => {
+                        Some(requested_caps.map_or(local_caps, |x| x.intersect(&local_caps)))
+                    }
+                    StreamKind::Audio => None,
+                };
+                // Our own group stream was registered when the call was joined
+                if peer != state.local_peer {
+                    if !streams.open_incoming(id, peer, kind, state.commands.clone()) {
+                        return StreamResponse::Rejected;
+                    }
+                    if let Some(caps) = caps {
+                        streams.set_max_bitrate(id, caps.max_bitrate);
+                    }
+                }
+                caps
+            };
+            if peer != state.local_peer {
+                logger.event_occurred(Event::IncomingStream(did, id, kind));
+                if let Some(caps) = caps {
+                    logger.event_occurred(Event::VideoCapsNegotiated(id, caps));
+                }
+            }
+
+            match group {
+                Some(GroupTag {
+                    group,
+                    origin: None,
+                }) => {
+                    let uplink = Uplink::new(peer, kind, codec.clone(), caps);
+                    let downlinks =
+                        state
+                            .media
+                            .groups
+                            .write()
+                            .add_uplink(group, id, state.local_peer, uplink);
+                    open_downlinks(swarm, logger.clone(), state, group, downlinks);
+                    announce_participants(swarm, logger, state, group);
+                }
+                Some(GroupTag {
+                    group,
+                    origin: Some(origin),
+                }) => {
+                    logger.event_occurred(Event::GroupStreamAdded(group, origin, id));
+                }
+                None => {}
+            }
+            StreamResponse::Accepted(codec, caps)
+        }
+        StreamMessage::Frame(id, sequence, frame) => {
+            if state.media.groups.read().group_of_uplink(id).is_some() {
+                if state.media.streams.read().peer_of(id) != Some(peer) {
+                    return StreamResponse::Received;
+                }
+                let downlinks = state.media.groups.read().downlinks_of(id);
+                for (peer_id, downlink) in downlinks {
+                    let relayed = StreamMessage::Frame(downlink, sequence, frame.clone());
+                    send_message(swarm, logger.clone(), state, peer_id, relayed);
+                }
+                // The forwarder only plays what it accepted, and never its own media
+                if peer == state.local_peer || !state.media.streams.read().is_accepted(id) {
+                    return StreamResponse::Received;
+                }
+            }
+            let now = state.clock.now_millis();
+            if state.media.streams.read().peer_of(id) == Some(peer) {
+                record_frame(state, id, Direction::Incoming, || {
+                    RecordedPayload::Audio(sequence, frame.clone())
+                });
+            }
+            state
+                .media
+                .streams
+                .write()
+                .push_frame(id, &peer, sequence, frame, now);
+            StreamResponse::Received
+        }
+        StreamMessage::VideoFrame(id, frame) => {
+            if state.media.groups.read().group_of_uplink(id).is_some() {
+                if state.media.streams.read().peer_of(id) != Some(peer) {
+                    return StreamResponse::Received;
+                }
+                let downlinks = state.media.groups.read().downlinks_of(id);
+                for (peer_id, downlink) in downlinks {
+                    let relayed = StreamMessage::VideoFrame(downlink, frame.clone());
+                    send_message(swarm, logger.clone(), state, peer_id, relayed);
+                }
+                if peer == state.local_peer || !state.media.streams.read().is_accepted(id) {
+                    return StreamResponse::Received;
+                }
+            }
+            let now = state.clock.now_millis();
+            if state.media.streams.read().peer_of(id) == Some(peer) {
+                record_frame(state, id, Direction::Incoming, || {
+                    RecordedPayload::Video(frame.clone())
+                });
+            }
+            let request_keyframe = state
+                .media
+                .streams
+                .write()
+                .push_video_frame(id, &peer, frame, now);
+            if request_keyframe {
+                let request = StreamMessage::KeyframeRequest(id);
+                if let Err(e) = state
+                    .commands
+                    .try_send(BlinkCommand::SendStreamMessage(peer, request))
+                {
+                    logger.event_occurred(Event::StreamError(e.to_string()));
+                }
+            }
+            StreamResponse::Received
+        }
+        StreamMessage::KeyframeRequest(id) => {
+            if state.media.streams.read().peer_of(id) == Some(peer) {
+                // Only the participant behind a relayed stream can produce a keyframe
+                let source = state.media.groups.read().source_of(id);
+                match source {
+                    Some((uplink, origin)) => {
+                        let request = StreamMessage::KeyframeRequest(uplink);
+                        send_message(swarm, logger, state, origin, request);
+                    }
+                    None => logger.event_occurred(Event::KeyframeRequested(id)),
+                }
+            }
+            StreamResponse::Received
+        }
+        StreamMessage::Feedback(id, report) => {
+            let changed = state
+                .media
+                .streams
+                .write()
+                .feedback_received(id, &peer, report);
+            if let Some(bitrate) = changed {
+                logger.event_occurred(Event::StreamBitrateChanged(id, bitrate));
+            }
+            StreamResponse::Received
+        }
+        StreamMessage::Mute(id, muted) => {
+            if state.media.streams.read().peer_of(id) == Some(peer) {
+                let downlinks = state.media.groups.read().downlinks_of(id);
+                for (peer_id, downlink) in downlinks {
+                    let relayed = StreamMessage::Mute(downlink, muted);
+                    send_message(swarm, logger.clone(), state, peer_id, relayed);
+                }
+                if peer != state.local_peer {
+                    logger.event_occurred(Event::StreamMuted(id, muted));
+                }
+            }
+            StreamResponse::Received
+        }
+        StreamMessage::Close(id) => {
+            if state.media.streams.read().peer_of(id) == Some(peer) {
+                state.media.streams.write().close(id);
+                state
+                    .media
+                    .recordings
+                    .write()
+                    .finish(id, state.clock.now_millis());
+                logger.event_occurred(Event::StreamClosed(id));
+                group_stream_closed(swarm, logger, state, id);
+            }
+            StreamResponse::Received
+        }
+        StreamMessage::GroupParticipants(group, participants) => {
+            if state.media.groups.read().has_joined(group) {
+                logger.event_occurred(Event::GroupParticipantsChanged(group, participants));
+            }
+            StreamResponse::Received
+        }
+    }
+}
+
+// Messages for our own peer come from the media we send into a group call we forward
+pub(crate) fn send_message(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    peer_id: PeerId,
+    message: StreamMessage,
+) -> Option<RequestId> {
+    if peer_id == state.local_peer {
+        handle_message(swarm, peer_id, message, logger, state);
+        return None;
+    }
+    match message.frame_of() {
+        Some((id, droppable)) => {
+            if state.media.streams.read().should_drop_frame(id, droppable) {
+                return None;
+            }
+            record_frame(state, id, Direction::Outgoing, || match &message {
+                StreamMessage::VideoFrame(_, frame) => RecordedPayload::Video(frame.clone()),
+                StreamMessage::Frame(_, sequence, frame) => {
+                    RecordedPayload::Audio(*sequence, frame.clone())
+                }
+                _ => unreachable!("frame_of only matches frames"),
+            });
+            state.count_sent(&peer_id, Some(id), &message);
+            let request_id = swarm
+                .behaviour_mut()
+                .streams
+                .send_request(&peer_id, message);
+            state
+                .media
+                .streams
+                .write()
+                .track_frame(request_id, id, state.clock.now_millis());
+            Some(request_id)
+        }
+        None => {
+            state.count_sent(&peer_id, None, &message);
+            Some(
+                swarm
+                    .behaviour_mut()
+                    .streams
+                    .send_request(&peer_id, message),
+            )
+        }
+    }
+}
+
+pub(crate) fn send_feedback(swarm: &mut Swarm<BlinkBehavior>, state: &SharedState) {
+    let reports = state.media.streams.write().take_feedback_reports();
+    for (peer_id, id, report) in reports {
+        let message = StreamMessage::Feedback(id, report);
+        state.count_sent(&peer_id, None, &message);
+        swarm
+            .behaviour_mut()
+            .streams
+            .send_request(&peer_id, message);
+    }
+}
+
+// Payloads are only copied for streams being recorded
+fn record_frame(
+    state: &SharedState,
+    id: StreamId,
+    direction: Direction,
+    payload: impl FnOnce() -> RecordedPayload,
+) {
+    if state.media.recordings.read().is_recording(id) {
+        let frame = RecordedFrame {
+            direction,
+            at: state.clock.now_millis(),
+            payload: payload(),
+        };
+        state.media.recordings.write().record(id, frame);
+    }
+}
+
+// Relays the media of one participant to another
+fn open_downlinks(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    group: GroupCallId,
+    downlinks: Vec<Downlink>,
+) {
+    for downlink in downlinks {
+        {
+            let mut streams = state.media.streams.write();
+            let opened = streams.open(
+                downlink.id,
+                downlink.peer,
+                downlink.kind,
+                state.commands.clone(),
+            );
+            if opened.is_none() {
+                continue;
+            }
+            if let Some(caps) = downlink.caps {
+                streams.set_max_bitrate(downlink.id, caps.max_bitrate);
+            }
+        }
+        let tag = GroupTag {
+            group,
+            origin: Some(state.did_of(&downlink.origin)),
+        };
+        let message = StreamMessage::Open(
+            downlink.id,
+            downlink.kind,
+            vec![downlink.codec],
+            downlink.caps,
+            Some(tag),
+        );
+        if let Some(request_id) = send_message(swarm, logger.clone(), state, downlink.peer, message)
+        {
+            state
+                .media
+                .streams
+                .write()
+                .track_open_request(request_id, downlink.id);
+        }
+    }
+}
+
+fn announce_participants(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    group: GroupCallId,
+) {
+    let members = state.media.groups.read().members(group);
+    let participants: Vec<String> = members.iter().map(|x| state.did_of(x)).collect();
+    for member in members {
+        if member != state.local_peer {
+            let message = StreamMessage::GroupParticipants(group, participants.clone());
+            state.count_sent(&member, None, &message);
+            swarm.behaviour_mut().streams.send_request(&member, message);
+        }
+    }
+    logger.event_occurred(Event::GroupParticipantsChanged(group, participants));
+}
+
+// A participant stopped sending, what was relayed from it, or to it once it has nothing left, goes too
+pub(crate) fn group_stream_closed(
+    swarm: &mut Swarm<BlinkBehavior>,
+    logger: EventSink,
+    state: &SharedState,
+    id: StreamId,
+) {
+    state.media.groups.write().remove_downlink(id);
+    let removed = state.media.groups.write().remove_uplink(id);
+    if let Some((group, downlinks)) = removed {
+        for (peer_id, downlink) in downlinks {
+            state.media.streams.write().close(downlink);
+            state
+                .media
+                .recordings
+                .write()
+                .finish(downlink, state.clock.now_millis());
+            let message = StreamMessage::Close(downlink);
+            state.count_sent(&peer_id, None, &message);
+            swarm
+                .behaviour_mut()
+                .streams
+                .send_request(&peer_id, message);
+        }
+        announce_participants(swarm, logger, state, group);
+    }
+}
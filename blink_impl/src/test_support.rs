@@ -0,0 +1,44 @@
+// Setup shared by the `when_*` test modules.
+
+use crate::keystore::InMemoryKeystore;
+use blink_contract::Keystore;
+use did_key::Ed25519KeyPair;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::path::PathBuf;
+use std::sync::Arc;
+use warp::crypto::DID;
+
+// A message holding `body`.
+pub(crate) fn text(body: &str) -> Sata {
+    Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &body.to_string())
+        .unwrap()
+}
+
+// A new ed25519 DID.
+pub(crate) fn did() -> DID {
+    DID::from(did_key::generate::<Ed25519KeyPair>(None))
+}
+
+// A keystore for a new DID.
+pub(crate) fn keystore() -> InMemoryKeystore {
+    InMemoryKeystore::new(Arc::new(did())).unwrap()
+}
+
+pub(crate) fn did_of(keystore: &InMemoryKeystore) -> String {
+    keystore.public_key().unwrap().to_string()
+}
+
+// A path in the temp directory unique to this test run, cleared of what a previous run left.
+pub(crate) fn temp_path(name: &str, extension: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "blink_{}_{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
@@ -0,0 +1,441 @@
+use crate::clock::SystemClock;
+use crate::keystore::InMemoryKeystore;
+use crate::peer_to_peer_service::{MessageContent, PeerToPeerService};
+use crate::CancellationToken;
+use anyhow::Result;
+use blink_contract::{Event, EventBus};
+use did_key::Ed25519KeyPair;
+use libp2p::{Multiaddr, PeerId};
+use sata::Sata;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use warp::sync::RwLock;
+use warp::{
+    crypto::DID,
+    data::DataType,
+    error::Error,
+    module::Module,
+    multipass::identity::{Identifier, Identity, IdentityUpdate},
+    multipass::{Friends, MultiPass},
+    pocket_dimension::query::QueryBuilder,
+    pocket_dimension::PocketDimension,
+    Extension, SingleHandle,
+};
+
+// In-process, nodes connect instantly and nothing touches the network
+const LISTEN_ADDRESS: &str = "/memory/0";
+
+// Long enough for a loaded CI machine, short enough that a hang fails the test
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Events a node reported, in order.
+#[derive(Default)]
+pub struct EventLog {
+    pub events: Vec<Event>,
+}
+
+impl EventBus for EventLog {
+    fn event_occurred(&mut self, event: Event) {
+        self.events.push(event);
+    }
+}
+
+// Cache keeping everything in memory, queries are ignored.
+#[derive(Default)]
+pub struct MemoryCache {
+    pub data: Vec<(DataType, Sata)>,
+}
+
+impl MemoryCache {
+    fn of(&self, dimension: DataType) -> impl Iterator<Item = &Sata> {
+        self.data
+            .iter()
+            .filter(move |(x, _)| *x == dimension)
+            .map(|(_, data)| data)
+    }
+}
+
+impl Extension for MemoryCache {
+    fn id(&self) -> String {
+        "blink-testkit-cache".into()
+    }
+
+    fn name(&self) -> String {
+        "Testkit cache".into()
+    }
+
+    fn module(&self) -> Module {
+        Module::Cache
+    }
+}
+
+impl SingleHandle for MemoryCache {}
+
+impl PocketDimension for MemoryCache {
+    fn add_data(&mut self, dimension: DataType, data: &Sata) -> Result<(), Error> {
+        self.data.push((dimension, data.clone()));
+        Ok(())
+    }
+
+    fn has_data(&mut self, dimension: DataType, _: &QueryBuilder) -> Result<(), Error> {
+        match self.of(dimension).next() {
+            Some(_) => Ok(()),
+            None => Err(Error::DataObjectNotFound),
+        }
+    }
+
+    fn get_data(&self, dimension: DataType, _: Option<&QueryBuilder>) -> Result<Vec<Sata>, Error> {
+        Ok(self.of(dimension).cloned().collect())
+    }
+
+    fn size(&self, dimension: DataType, _: Option<&QueryBuilder>) -> Result<i64, Error> {
+        Ok(self
+            .of(dimension)
+            .map(|x| bincode::serialized_size(x).unwrap_or_default() as i64)
+            .sum())
+    }
+
+    fn count(&self, dimension: DataType, _: Option<&QueryBuilder>) -> Result<i64, Error> {
+        Ok(self.of(dimension).count() as i64)
+    }
+
+    fn empty(&mut self, dimension: DataType) -> Result<(), Error> {
+        self.data.retain(|(x, _)| *x != dimension);
+        Ok(())
+    }
+}
+
+// Identity store that knows every DID, so any two nodes can pair.
+#[derive(Default)]
+pub struct TrustingMultiPass;
+
+impl Extension for TrustingMultiPass {
+    fn id(&self) -> String {
+        "blink-testkit-multipass".into()
+    }
+
+    fn name(&self) -> String {
+        "Testkit identities".into()
+    }
+
+    fn module(&self) -> Module {
+        Module::Accounts
+    }
+}
+
+impl Friends for TrustingMultiPass {}
+
+impl SingleHandle for TrustingMultiPass {}
+
+impl MultiPass for TrustingMultiPass {
+    fn create_identity(&mut self, _: Option<&str>, _: Option<&str>) -> Result<DID, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    fn get_identity(&self, _: Identifier) -> Result<Identity, Error> {
+        Ok(Identity::default())
+    }
+
+    fn update_identity(&mut self, _: IdentityUpdate) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    fn decrypt_private_key(&self, _: Option<&str>) -> Result<DID, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    fn refresh_cache(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// A service of a cluster, with what it reported and received.
+pub struct TestNode {
+    pub service: PeerToPeerService,
+    pub events: Arc<RwLock<EventLog>>,
+    pub messages: Receiver<MessageContent>,
+    pub cache: Arc<RwLock<MemoryCache>>,
+    did: Arc<DID>,
+    peer_id: PeerId,
+    address: Multiaddr,
+    cancellation_token: CancellationToken,
+}
+
+impl TestNode {
+    // Starts a node with a new identity.
+    pub async fn start() -> Result<Self> {
+        Self::start_as(Arc::new(DID::from(did_key::generate::<Ed25519KeyPair>(
+            None,
+        ))))
+        .await
+    }
+
+    // Starts a node with an existing identity, as if it restarted.
+    pub async fn start_as(did: Arc<DID>) -> Result<Self> {
+        let keystore = Arc::new(InMemoryKeystore::new(did.clone())?);
+        let events = Arc::new(RwLock::new(EventLog::default()));
+        let cache = Arc::new(RwLock::new(MemoryCache::default()));
+        let cancellation_token = CancellationToken::new();
+        let (service, messages) = PeerToPeerService::new(
+            keystore,
+            Arc::new(SystemClock),
+            LISTEN_ADDRESS,
+            None,
+            cache.clone(),
+            Arc::new(RwLock::new(TrustingMultiPass)),
+            events.clone(),
+            cancellation_token.clone(),
+        )
+        .await?;
+        let peer_id = service.local_peer_id();
+        let mut node = Self {
+            service,
+            events,
+            messages,
+            cache,
+            did,
+            peer_id,
+            address: Multiaddr::empty(),
+            cancellation_token,
+        };
+        node.service.ready(false, PAIRING_TIMEOUT).await?;
+        if let Some(address) = node.service.listen_addrs().pop() {
+            node.address = address;
+        }
+        Ok(node)
+    }
+
+    pub fn did(&self) -> Arc<DID> {
+        self.did.clone()
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub fn address(&self) -> &Multiaddr {
+        &self.address
+    }
+
+    // Completes with the first event matching the predicate, including the ones already reported.
+    pub async fn wait_for(
+        &self,
+        predicate: impl Fn(&Event) -> bool,
+        timeout: Duration,
+    ) -> Result<Event> {
+        self.service
+            .wait_for_event_since(0, predicate, timeout)
+            .await
+    }
+
+    async fn stop(&self) {
+        self.cancellation_token.cancel();
+        self.service.wait_for_shutdown().await;
+    }
+}
+
+// Shape the nodes of a cluster are paired in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topology {
+    FullMesh,
+    // Each node paired with the next one
+    Line,
+    Ring,
+    // Every node paired with the given one only
+    Star(usize),
+    Edges(Vec<(usize, usize)>),
+}
+
+impl Topology {
+    pub fn edges(&self, size: usize) -> Vec<(usize, usize)> {
+        match self {
+            Topology::FullMesh => (0..size)
+                .flat_map(|a| (a + 1..size).map(move |b| (a, b)))
+                .collect(),
+            Topology::Line => (1..size).map(|b| (b - 1, b)).collect(),
+            Topology::Ring => {
+                let mut edges = Topology::Line.edges(size);
+                if size > 2 {
+                    edges.push((size - 1, 0));
+                }
+                edges
+            }
+            Topology::Star(center) => (0..size)
+                .filter(|x| x != center)
+                .map(|x| (*center, x))
+                .collect(),
+            Topology::Edges(edges) => edges.clone(),
+        }
+    }
+}
+
+// In-process services paired in a topology, with faults injected between them through their
+// chaos handles. Nodes are addressed by their index.
+pub struct Cluster {
+    nodes: Vec<TestNode>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl Cluster {
+    pub async fn start(size: usize, topology: Topology) -> Result<Self> {
+        let mut nodes = Vec::with_capacity(size);
+        for _ in 0..size {
+            nodes.push(TestNode::start().await?);
+        }
+        let mut cluster = Self {
+            nodes,
+            edges: Vec::new(),
+        };
+        for (a, b) in topology.edges(size) {
+            cluster.connect(a, b).await?;
+        }
+        Ok(cluster)
+    }
+
+    pub fn node(&self, index: usize) -> &TestNode {
+        &self.nodes[index]
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut TestNode {
+        &mut self.nodes[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Pairs the nodes and waits until each is subscribed to their pairwise topic.
+    pub async fn connect(&mut self, a: usize, b: usize) -> Result<()> {
+        self.pair(a, b).await?;
+        if !self.edges.contains(&(a, b)) && !self.edges.contains(&(b, a)) {
+            self.edges.push((a, b));
+        }
+        Ok(())
+    }
+
+    async fn pair(&mut self, a: usize, b: usize) -> Result<()> {
+        let (did_a, did_b) = (self.nodes[a].did.to_string(), self.nodes[b].did.to_string());
+        let (from_a, from_b) = (
+            self.nodes[a].service.last_event_seq(),
+            self.nodes[b].service.last_event_seq(),
+        );
+        let address = self.nodes[b].address.clone();
+        self.nodes[a]
+            .service
+            .pair_to_another_peer(address.into())
+            .await?;
+        self.nodes[a]
+            .service
+            .wait_for_event_since(from_a, |x| generated_topic_with(x, &did_b), PAIRING_TIMEOUT)
+            .await?;
+        self.nodes[b]
+            .service
+            .wait_for_event_since(from_b, |x| generated_topic_with(x, &did_a), PAIRING_TIMEOUT)
+            .await?;
+        // Subscribed on both sides, so the first message sent over the link isn't lost
+        let (did_a, did_b) = (self.nodes[a].did(), self.nodes[b].did());
+        self.nodes[a]
+            .service
+            .await_peer_ready(&did_b, PAIRING_TIMEOUT)
+            .await?;
+        self.nodes[b]
+            .service
+            .await_peer_ready(&did_a, PAIRING_TIMEOUT)
+            .await
+    }
+
+    // Disconnects the two nodes, neither accepts the other's connections until healed.
+    pub fn cut(&self, a: usize, b: usize) -> Result<()> {
+        self.nodes[a]
+            .service
+            .chaos()
+            .cut_link(self.nodes[b].peer_id)?;
+        self.nodes[b]
+            .service
+            .chaos()
+            .cut_link(self.nodes[a].peer_id)
+    }
+
+    // Lets the nodes connect again, and pairs them again if the topology has them paired.
+    pub async fn heal(&mut self, a: usize, b: usize) -> Result<()> {
+        self.nodes[a]
+            .service
+            .chaos()
+            .heal_link(self.nodes[b].peer_id)?;
+        self.nodes[b]
+            .service
+            .chaos()
+            .heal_link(self.nodes[a].peer_id)?;
+        if self.edges.contains(&(a, b)) || self.edges.contains(&(b, a)) {
+            self.pair(a, b).await?;
+        }
+        Ok(())
+    }
+
+    // Cuts every link between nodes of different groups.
+    pub fn partition(&self, groups: &[&[usize]]) -> Result<()> {
+        for (a, b) in cross_pairs(groups) {
+            self.cut(a, b)?;
+        }
+        Ok(())
+    }
+
+    pub async fn heal_partition(&mut self, groups: &[&[usize]]) -> Result<()> {
+        for (a, b) in cross_pairs(groups) {
+            self.heal(a, b).await?;
+        }
+        Ok(())
+    }
+
+    // Holds back every message the node publishes, zero removes the latency.
+    pub fn add_latency(&self, node: usize, delay: Duration) {
+        self.nodes[node].service.chaos().delay_publishes(delay);
+    }
+
+    // Stops the node's event loop, its peers see the connections close.
+    pub async fn kill(&mut self, node: usize) {
+        self.nodes[node].stop().await;
+    }
+
+    // Starts the node again with the same identity and pairs it with its neighbours.
+    pub async fn restart(&mut self, node: usize) -> Result<()> {
+        self.nodes[node].stop().await;
+        self.nodes[node] = TestNode::start_as(self.nodes[node].did.clone()).await?;
+        let neighbours: Vec<usize> = self
+            .edges
+            .iter()
+            .filter_map(|(a, b)| match (*a == node, *b == node) {
+                (true, _) => Some(*b),
+                (_, true) => Some(*a),
+                _ => None,
+            })
+            .collect();
+        for other in neighbours {
+            self.pair(node, other).await?;
+        }
+        Ok(())
+    }
+}
+
+fn generated_topic_with(event: &Event, did: &str) -> bool {
+    matches!(event, Event::GeneratedTopic(peer, _) if peer.to_string() == did)
+}
+
+fn cross_pairs(groups: &[&[usize]]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for (i, group) in groups.iter().enumerate() {
+        for other in &groups[i + 1..] {
+            for a in group.iter() {
+                for b in other.iter() {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+    }
+    pairs
+}
@@ -0,0 +1,117 @@
+use sata::Sata;
+use std::collections::HashMap;
+
+pub type TransactionId = u64;
+
+const MAX_TRANSACTION_ATTEMPTS: u32 = 10;
+
+pub(crate) struct TransactionPart {
+    pub(crate) topic: String,
+    pub(crate) sata: Sata,
+    pub(crate) journal_id: Option<u64>,
+    done: bool,
+}
+
+impl TransactionPart {
+    pub(crate) fn new(topic: String, sata: Sata, journal_id: Option<u64>) -> Self {
+        Self {
+            topic,
+            sata,
+            journal_id,
+            done: false,
+        }
+    }
+}
+
+struct Transaction {
+    parts: Vec<TransactionPart>,
+    attempts: u32,
+}
+
+pub(crate) enum TransactionOutcome {
+    Pending,
+    Completed,
+    // Number of undelivered messages and their journal entries
+    Failed(usize, Vec<u64>),
+}
+
+// Groups of messages that are reported as one unit, retried until every part went out.
+#[derive(Default)]
+pub(crate) struct Outbox {
+    transactions: HashMap<TransactionId, Transaction>,
+}
+
+impl Outbox {
+    pub(crate) fn insert(&mut self, id: TransactionId, parts: Vec<TransactionPart>) {
+        self.transactions
+            .entry(id)
+            .or_insert_with(|| Transaction {
+                parts: Vec::new(),
+                attempts: 0,
+            })
+            .parts
+            .extend(parts);
+    }
+
+    pub(crate) fn ids(&self) -> Vec<TransactionId> {
+        self.transactions.keys().copied().collect()
+    }
+
+    pub(crate) fn waiting_on(&self, topic: &str) -> Vec<TransactionId> {
+        self.transactions
+            .iter()
+            .filter(|(_, tx)| tx.parts.iter().any(|x| !x.done && x.topic == topic))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    // Index and content of the parts that didn't go out yet
+    pub(crate) fn pending_parts(&self, id: TransactionId) -> Vec<(usize, String, Sata)> {
+        self.transactions.get(&id).map_or(Vec::new(), |tx| {
+            tx.parts
+                .iter()
+                .enumerate()
+                .filter(|(_, part)| !part.done)
+                .map(|(index, part)| (index, part.topic.clone(), part.sata.clone()))
+                .collect()
+        })
+    }
+
+    // Marks a part as sent and returns its journal entry, if it has one.
+    pub(crate) fn part_sent(&mut self, id: TransactionId, index: usize) -> Option<u64> {
+        let part = self.transactions.get_mut(&id)?.parts.get_mut(index)?;
+        part.done = true;
+        part.journal_id
+    }
+
+    // Closes an attempt at sending the transaction, completed and failed ones are removed.
+    pub(crate) fn finish_attempt(&mut self, id: TransactionId) -> TransactionOutcome {
+        let transaction = match self.transactions.get_mut(&id) {
+            Some(transaction) => transaction,
+            None => return TransactionOutcome::Pending,
+        };
+
+        if transaction.parts.iter().all(|x| x.done) {
+            self.transactions.remove(&id);
+            return TransactionOutcome::Completed;
+        }
+
+        transaction.attempts += 1;
+        if transaction.attempts < MAX_TRANSACTION_ATTEMPTS {
+            return TransactionOutcome::Pending;
+        }
+
+        let undelivered: Vec<TransactionPart> = self
+            .transactions
+            .remove(&id)
+            .map(|tx| tx.parts.into_iter().filter(|x| !x.done).collect())
+            .unwrap_or_default();
+        TransactionOutcome::Failed(
+            undelivered.len(),
+            undelivered
+                .into_iter()
+                .filter_map(|x| x.journal_id)
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::upgrade, transport::Boxed},
+    identity::Keypair,
+    mplex, noise, PeerId, Transport,
+};
+
+// Authenticated and multiplexed transport for the target.
+// Natively that's TCP plus WebSocket over TCP so browsers can dial in,
+// in a browser it's the WebSocket implementation the page provides.
+// Tests and the memory-transport feature add /memory/ addresses for services in the same process.
+pub(crate) fn build(key_pair: &Keypair) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    // Create a keypair for authenticated encryption of the transport.
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(key_pair)?;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let base = {
+        use libp2p::tcp::{GenTcpConfig, TokioTcpTransport};
+        let tcp = || TokioTcpTransport::new(GenTcpConfig::default().nodelay(true));
+        // Tried first, it refuses anything that isn't a /ws address
+        libp2p::websocket::WsConfig::new(tcp()).or_transport(tcp())
+    };
+    #[cfg(all(not(target_arch = "wasm32"), any(test, feature = "memory-transport")))]
+    let base = libp2p::core::transport::MemoryTransport::default().or_transport(base);
+    #[cfg(target_arch = "wasm32")]
+    let base = libp2p::wasm_ext::ExtTransport::new(libp2p::wasm_ext::ffi::websocket_transport());
+
+    // Use noise for authenticated encryption and Mplex for multiplexing of substreams.
+    let transport = base
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    Ok(transport)
+}
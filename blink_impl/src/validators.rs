@@ -0,0 +1,60 @@
+use blink_contract::{MessageValidator, Validation};
+use libp2p::gossipsub::MessageAcceptance;
+use sata::Sata;
+use std::{collections::HashMap, sync::Arc};
+use warp::sync::RwLock;
+
+// Validators applications registered, by topic. Topics without one accept everything.
+#[derive(Default)]
+pub(crate) struct ValidatorRegistry {
+    validators: HashMap<String, Arc<RwLock<dyn MessageValidator>>>,
+}
+
+impl ValidatorRegistry {
+    pub(crate) fn register(
+        &mut self,
+        topic: String,
+        validator: Arc<RwLock<dyn MessageValidator>>,
+    ) -> bool {
+        if self.validators.contains_key(&topic) {
+            return false;
+        }
+        self.validators.insert(topic, validator);
+        true
+    }
+
+    pub(crate) fn unregister(&mut self, topic: &str) -> bool {
+        self.validators.remove(topic).is_some()
+    }
+
+    pub(crate) fn validator(&self, topic: &str) -> Option<Arc<RwLock<dyn MessageValidator>>> {
+        self.validators.get(topic).cloned()
+    }
+}
+
+// Runs the validator over every Sata a gossipsub message carried. Gossipsub passes the message
+// on whole or not at all, so it's only as good as its worst Sata.
+pub(crate) fn validate(
+    validator: &mut dyn MessageValidator,
+    topic: &str,
+    author: &str,
+    batch: &[Sata],
+) -> Validation {
+    let mut verdict = Validation::Accept;
+    for sata in batch {
+        match validator.validate(topic, author, sata) {
+            Validation::Reject => return Validation::Reject,
+            Validation::Ignore => verdict = Validation::Ignore,
+            Validation::Accept => {}
+        }
+    }
+    verdict
+}
+
+pub(crate) fn acceptance(verdict: Validation) -> MessageAcceptance {
+    match verdict {
+        Validation::Accept => MessageAcceptance::Accept,
+        Validation::Reject => MessageAcceptance::Reject,
+        Validation::Ignore => MessageAcceptance::Ignore,
+    }
+}
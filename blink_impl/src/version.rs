@@ -0,0 +1,21 @@
+// Advertised as the identify protocol version, "/blink/<major>.<minor>.<patch>".
+// Bump the major version with any change older peers can't read, such as a new wire envelope.
+pub const PROTOCOL_VERSION: &str = "/blink/1.0.0";
+
+fn major(version: &str) -> Option<u64> {
+    version
+        .strip_prefix("/blink/")?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+// Whether a peer advertising `theirs` can read what we send and the other way around.
+// Anything that isn't a Blink version, like Blink from before versioning, is incompatible.
+pub(crate) fn is_compatible(theirs: &str) -> bool {
+    match (major(theirs), major(PROTOCOL_VERSION)) {
+        (Some(theirs), Some(ours)) => theirs == ours,
+        _ => false,
+    }
+}
@@ -0,0 +1,127 @@
+use crate::group_calls::GroupCallId;
+use anyhow::Result;
+use sata::Sata;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum WalOperation {
+    Publish(String, Sata),
+    TransactionPart(u64, String, Sata),
+    // A DID banned from or let back into a group call, with when it was decided
+    GroupBan(GroupCallId, String, bool, u64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum WalRecord {
+    Begin(u64, WalOperation),
+    Commit(u64),
+}
+
+// Append-only journal of operations that must survive a crash.
+// An operation is written before it runs and committed once it completed,
+// anything left uncommitted is handed back by `pending` after a restart.
+pub(crate) struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    next_id: u64,
+    pending: BTreeMap<u64, WalOperation>,
+}
+
+impl WriteAheadLog {
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pending = match File::open(&path) {
+            Ok(file) => Self::read_pending(file)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let next_id = pending.keys().last().map_or(0, |x| x + 1);
+        let file = Self::compact(&path, &pending)?;
+
+        Ok(Self {
+            path,
+            file,
+            next_id,
+            pending,
+        })
+    }
+
+    pub(crate) fn begin(&mut self, operation: WalOperation) -> Result<u64> {
+        let id = self.next_id;
+        Self::append(&mut self.file, &WalRecord::Begin(id, operation.clone()))?;
+        self.next_id += 1;
+        self.pending.insert(id, operation);
+        Ok(id)
+    }
+
+    pub(crate) fn commit(&mut self, id: u64) -> Result<()> {
+        if self.pending.remove(&id).is_some() {
+            Self::append(&mut self.file, &WalRecord::Commit(id))?;
+        }
+        if self.pending.is_empty() {
+            self.file = Self::compact(&self.path, &self.pending)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn pending(&self) -> Vec<(u64, WalOperation)> {
+        self.pending
+            .iter()
+            .map(|(id, operation)| (*id, operation.clone()))
+            .collect()
+    }
+
+    fn read_pending(file: File) -> Result<BTreeMap<u64, WalOperation>> {
+        let mut reader = BufReader::new(file);
+        let mut pending = BTreeMap::new();
+        loop {
+            let mut len = [0u8; 4];
+            match reader.read_exact(&mut len) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let mut buffer = vec![0u8; u32::from_le_bytes(len) as usize];
+            // A record cut short by a crash was never acknowledged, so it's safe to drop
+            if reader.read_exact(&mut buffer).is_err() {
+                break;
+            }
+            match bincode::deserialize::<WalRecord>(&buffer) {
+                Ok(WalRecord::Begin(id, operation)) => {
+                    pending.insert(id, operation);
+                }
+                Ok(WalRecord::Commit(id)) => {
+                    pending.remove(&id);
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(pending)
+    }
+
+    fn compact(path: &Path, pending: &BTreeMap<u64, WalOperation>) -> Result<File> {
+        let temp_path = path.with_extension("compact");
+        let mut temp = File::create(&temp_path)?;
+        for (id, operation) in pending {
+            Self::append(&mut temp, &WalRecord::Begin(*id, operation.clone()))?;
+        }
+        temp.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+
+        Ok(OpenOptions::new().append(true).open(path)?)
+    }
+
+    fn append(file: &mut File, record: &WalRecord) -> Result<()> {
+        let bytes = bincode::serialize(record)?;
+        file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use crate::bandwidth::{BandwidthCaps, BandwidthMeter, Traffic};
+use libp2p::PeerId;
+
+#[test]
+fn traffic_is_counted_per_peer_and_per_stream() {
+    let mut meter = BandwidthMeter::default();
+    let peer = PeerId::random();
+
+    meter.sent(Some(&peer), Some(7), 100, 0);
+    meter.received(&peer, None, 40, 0);
+    meter.sent(None, None, 10, 0);
+
+    let stats = meter.stats();
+    assert_eq!(
+        stats.total,
+        Traffic {
+            sent: 110,
+            received: 40
+        }
+    );
+    assert_eq!(
+        stats.peers[&peer.to_string()],
+        Traffic {
+            sent: 100,
+            received: 40
+        }
+    );
+    assert_eq!(stats.streams[&7].sent, 100);
+    assert!(!stats.streams.contains_key(&0));
+}
+
+#[test]
+fn bulk_traffic_waits_once_a_peer_is_over_its_cap() {
+    let mut meter = BandwidthMeter::default();
+    meter.set_caps(BandwidthCaps {
+        per_peer_bytes_per_sec: Some(1000),
+        ..Default::default()
+    });
+    let busy = PeerId::random();
+
+    meter.received(&busy, Some(1), 1000, 0);
+
+    assert!(!meter.allows_bulk(&busy, 500));
+    assert!(meter.allows_bulk(&PeerId::random(), 500));
+    assert!(meter.allows_bulk(&busy, 1000));
+}
+
+#[test]
+fn the_global_cap_counts_every_peer() {
+    let mut meter = BandwidthMeter::default();
+    meter.set_caps(BandwidthCaps {
+        global_bytes_per_sec: Some(1000),
+        ..Default::default()
+    });
+
+    meter.sent(Some(&PeerId::random()), None, 600, 0);
+    meter.sent(None, None, 600, 0);
+
+    assert!(!meter.allows_bulk(&PeerId::random(), 0));
+}
+
+#[test]
+fn nothing_waits_without_caps() {
+    let mut meter = BandwidthMeter::default();
+    let peer = PeerId::random();
+
+    meter.received(&peer, None, u32::MAX as u64, 0);
+
+    assert!(meter.allows_bulk(&peer, 0));
+}
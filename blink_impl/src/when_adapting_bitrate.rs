@@ -0,0 +1,68 @@
+use crate::congestion::{FeedbackReport, RateController, ReceiveStats};
+
+const MAX_BITRATE: u32 = 1_000_000;
+
+fn report(received: u32, lost: u32) -> FeedbackReport {
+    FeedbackReport {
+        received,
+        lost,
+        jitter_ms: 0,
+    }
+}
+
+#[test]
+fn receiver_counts_gaps_as_lost_frames() {
+    let mut stats = ReceiveStats::default();
+    stats.on_frame(1, None, 0);
+    stats.on_frame(2, None, 10);
+    stats.on_frame(5, None, 20);
+
+    let report = stats.take_report().unwrap();
+
+    assert_eq!(report.received, 3);
+    assert_eq!(report.lost, 2);
+    assert!(stats.take_report().is_none());
+}
+
+#[test]
+fn receiver_measures_jitter_from_frame_timestamps() {
+    let mut stats = ReceiveStats::default();
+    stats.on_frame(1, Some(1_000), 5_000);
+    stats.on_frame(2, Some(1_033), 5_033);
+    stats.on_frame(3, Some(1_066), 5_266);
+
+    assert!(stats.take_report().unwrap().jitter_ms > 0);
+}
+
+#[test]
+fn heavy_loss_lowers_the_target() {
+    let mut rate = RateController::new(MAX_BITRATE);
+
+    let target = rate.on_feedback(report(70, 30)).unwrap();
+
+    assert!(target < MAX_BITRATE);
+    assert_eq!(rate.quality().target_bitrate, target);
+}
+
+#[test]
+fn clean_link_recovers_up_to_the_max() {
+    let mut rate = RateController::new(MAX_BITRATE);
+    rate.on_feedback(report(50, 50));
+
+    for _ in 0..100 {
+        rate.on_feedback(report(100, 0));
+    }
+
+    assert_eq!(rate.quality().target_bitrate, MAX_BITRATE);
+}
+
+#[test]
+fn growing_round_trips_lower_the_target() {
+    let mut rate = RateController::new(MAX_BITRATE);
+    rate.on_rtt_sample(20);
+    for _ in 0..50 {
+        rate.on_rtt_sample(400);
+    }
+
+    assert!(rate.on_feedback(report(100, 0)).unwrap() < MAX_BITRATE);
+}
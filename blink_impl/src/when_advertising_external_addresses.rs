@@ -0,0 +1,57 @@
+use crate::external_addresses::{ExternalAddresses, CONFIRMATIONS};
+use libp2p::{Multiaddr, PeerId};
+
+fn addr(text: &str) -> Multiaddr {
+    text.parse().unwrap()
+}
+
+#[test]
+fn addresses_are_confirmed_by_distinct_peers() {
+    let mut external = ExternalAddresses::default();
+    let observer = PeerId::random();
+    let public = addr("/ip4/203.0.113.7/tcp/4001");
+
+    assert!(!external.observed(observer, public.clone()));
+    assert!(!external.observed(observer, public.clone()));
+    assert_eq!(external.unconfirmed(), vec![(public.clone(), 1)]);
+    for _ in 1..CONFIRMATIONS {
+        external.observed(PeerId::random(), public.clone());
+    }
+
+    assert_eq!(external.addresses(), vec![public.clone()]);
+    assert!(external.unconfirmed().is_empty());
+    assert!(!external.observed(PeerId::random(), public));
+}
+
+#[test]
+fn static_addresses_come_first_and_can_be_removed() {
+    let mut external = ExternalAddresses::default();
+    let forwarded = addr("/ip4/203.0.113.7/tcp/4001");
+    let confirmed = addr("/ip4/203.0.113.7/tcp/5001");
+    for _ in 0..CONFIRMATIONS {
+        external.observed(PeerId::random(), confirmed.clone());
+    }
+
+    assert!(external.add(forwarded.clone()));
+    assert!(!external.add(forwarded.clone()));
+    assert_eq!(
+        external.addresses(),
+        vec![forwarded.clone(), confirmed.clone()]
+    );
+    assert!(external.remove(&confirmed));
+    assert!(!external.remove(&confirmed));
+    assert_eq!(external.addresses(), vec![forwarded]);
+}
+
+#[test]
+fn ephemeral_ports_dont_pile_up() {
+    let mut external = ExternalAddresses::default();
+
+    for port in 40000..41000 {
+        let observed = addr(&format!("/ip4/203.0.113.7/tcp/{}", port));
+        external.observed(PeerId::random(), observed);
+    }
+
+    assert!(external.unconfirmed().len() <= 32);
+    assert!(external.addresses().is_empty());
+}
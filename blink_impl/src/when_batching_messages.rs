@@ -0,0 +1,80 @@
+use crate::batching::{BatchSettings, Batched, Batcher};
+use crate::test_support::text;
+use crate::wire::{WireFormat, BATCH_CODEC, PLAIN_VERSION};
+
+fn enabled() -> Batcher {
+    let mut batcher = Batcher::default();
+    batcher.set_settings(BatchSettings {
+        enabled: true,
+        max_message_bytes: 100,
+        max_batch_bytes: 250,
+        ..BatchSettings::default()
+    });
+    batcher
+}
+
+#[test]
+fn nothing_is_batched_unless_turned_on() {
+    let batcher = Batcher::default();
+
+    assert!(!batcher.settings().enabled);
+    assert!(!batcher.batches(1));
+}
+
+#[test]
+fn only_small_messages_wait_for_a_batch() {
+    let batcher = enabled();
+
+    assert!(batcher.batches(100));
+    assert!(!batcher.batches(101));
+}
+
+#[test]
+fn a_batch_is_due_once_full_and_keeps_the_order_sent() {
+    let mut batcher = enabled();
+
+    assert_eq!(batcher.add("topic".into(), text("a"), 100), Batched::First);
+    assert_eq!(batcher.add("other".into(), text("x"), 100), Batched::First);
+    assert_eq!(batcher.add("topic".into(), text("b"), 100), Batched::Added);
+    assert_eq!(batcher.add("topic".into(), text("c"), 100), Batched::Full);
+
+    let batch = batcher.take("topic");
+    let bodies: Vec<String> = batch.iter().map(|x| x.decode().unwrap()).collect();
+    assert_eq!(bodies, ["a", "b", "c"]);
+    assert!(batcher.take("topic").is_empty());
+    assert_eq!(batcher.take("other").len(), 1);
+}
+
+#[test]
+fn a_batch_opens_into_the_messages_it_holds() {
+    let format = WireFormat::default();
+
+    let bytes = format.seal_batch(&[text("a"), text("b")]).unwrap();
+
+    assert_eq!(bytes[2..4], [PLAIN_VERSION, BATCH_CODEC]);
+    assert!(format.open(&bytes).is_err());
+    let opened = format.open_all(&bytes).unwrap();
+    let bodies: Vec<String> = opened.iter().map(|x| x.decode().unwrap()).collect();
+    assert_eq!(bodies, ["a", "b"]);
+}
+
+#[test]
+fn single_envelopes_open_as_a_batch_of_one() {
+    let format = WireFormat::default();
+
+    let bytes = format.seal(&text("hello")).unwrap();
+
+    let opened = format.open_all(&bytes).unwrap();
+    assert_eq!(opened.len(), 1);
+    assert_eq!(opened[0].decode::<String>().unwrap(), "hello");
+}
+
+#[test]
+fn batches_inside_batches_are_refused() {
+    let format = WireFormat::default();
+    let inner = format.seal_batch(&[text("a"), text("b")]).unwrap();
+    let mut bytes = inner[..4].to_vec();
+    bytes.extend(bincode::serialize(&vec![inner]).unwrap());
+
+    assert!(format.open_all(&bytes).is_err());
+}
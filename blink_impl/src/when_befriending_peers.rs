@@ -0,0 +1,114 @@
+use crate::friends::{inbox_topic, FriendIntent, FriendMessage, FriendRequests, MAX_INCOMING};
+use crate::keystore::InMemoryKeystore;
+use crate::mailbox::HOLD_FOR;
+use crate::test_support::{did_of, keystore};
+
+const NOW: u64 = 1_000_000;
+
+fn request(from: &InMemoryKeystore, to: &str, sent_at: u64) -> FriendMessage {
+    FriendMessage::new(
+        from,
+        FriendIntent::Request,
+        did_of(from),
+        to.to_string(),
+        sent_at,
+    )
+    .unwrap()
+}
+
+#[test]
+fn every_did_has_an_inbox_of_its_own() {
+    let (alice, bob) = (did_of(&keystore()), did_of(&keystore()));
+
+    assert_eq!(inbox_topic(&alice), inbox_topic(&alice));
+    assert_ne!(inbox_topic(&alice), inbox_topic(&bob));
+}
+
+#[test]
+fn a_signed_request_survives_the_trip_through_sata() {
+    let (alice, bob) = (keystore(), did_of(&keystore()));
+    let sent = request(&alice, &bob, NOW);
+
+    let received: FriendMessage = sent.to_sata().unwrap().decode().unwrap();
+
+    assert_eq!(received, sent);
+    assert!(received.verify(&bob).is_ok());
+}
+
+#[test]
+fn requests_for_someone_else_or_in_someone_elses_name_are_refused() {
+    let (alice, mallory, bob) = (keystore(), keystore(), did_of(&keystore()));
+    let mut forged = request(&mallory, &bob, NOW);
+    forged.from = did_of(&alice);
+
+    assert!(request(&alice, &bob, NOW).verify(&did_of(&alice)).is_err());
+    assert!(forged.verify(&bob).is_err());
+}
+
+#[test]
+fn a_request_waits_once_until_it_is_answered() {
+    let (alice, carol, bob) = (keystore(), keystore(), did_of(&keystore()));
+    let mut requests = FriendRequests::default();
+
+    assert!(requests.received(request(&alice, &bob, NOW + 1), NOW));
+    assert!(!requests.received(request(&alice, &bob, NOW + 2), NOW));
+    assert!(requests.received(request(&carol, &bob, NOW), NOW));
+    assert_eq!(requests.incoming(), [did_of(&carol), did_of(&alice)]);
+
+    assert!(requests.accept(&did_of(&alice)));
+    assert!(requests.decline(&did_of(&carol)));
+
+    assert!(requests.is_friend(&did_of(&alice)));
+    assert!(!requests.is_friend(&did_of(&carol)));
+    assert!(requests.incoming().is_empty());
+    assert!(!requests.received(request(&alice, &bob, NOW + 3), NOW));
+}
+
+#[test]
+fn only_dids_we_asked_can_accept() {
+    let (alice, bob) = (did_of(&keystore()), did_of(&keystore()));
+    let mut requests = FriendRequests::default();
+    requests.sent(alice.clone(), NOW);
+
+    assert!(!requests.accepted(&bob));
+    assert!(requests.accepted(&alice));
+
+    assert!(requests.is_friend(&alice));
+    assert!(!requests.is_friend(&bob));
+    assert!(requests.outgoing().is_empty());
+}
+
+#[test]
+fn a_declined_request_replayed_later_is_refused() {
+    let (alice, bob) = (keystore(), did_of(&keystore()));
+    let mut requests = FriendRequests::default();
+    let first = request(&alice, &bob, NOW);
+    requests.received(first.clone(), NOW);
+    requests.decline(&did_of(&alice));
+
+    assert!(!requests.received(first, NOW + 1));
+    assert!(requests.received(request(&alice, &bob, NOW + 1), NOW + 1));
+}
+
+#[test]
+fn stale_requests_and_requests_from_the_future_are_refused() {
+    let (alice, bob) = (keystore(), did_of(&keystore()));
+    let mut requests = FriendRequests::default();
+    let later = NOW + HOLD_FOR + 1;
+
+    assert!(!requests.received(request(&alice, &bob, NOW), later));
+    assert!(!requests.received(request(&alice, &bob, later), NOW));
+    assert!(requests.incoming().is_empty());
+}
+
+#[test]
+fn requests_past_the_cap_are_refused() {
+    let bob = did_of(&keystore());
+    let mut requests = FriendRequests::default();
+    for _ in 0..MAX_INCOMING {
+        assert!(requests.received(request(&keystore(), &bob, NOW), NOW));
+    }
+
+    assert!(!requests.received(request(&keystore(), &bob, NOW), NOW));
+    assert_eq!(requests.incoming().len(), MAX_INCOMING);
+}
@@ -0,0 +1,71 @@
+use crate::diagnostics::{BenchmarkOptions, BenchmarkRegistry};
+use std::time::Duration;
+
+const PEER: &str = "did:key:peer";
+
+fn options(probes: u32) -> BenchmarkOptions {
+    BenchmarkOptions {
+        probes,
+        payload_size: 1000,
+        timeout: Duration::from_secs(1),
+    }
+}
+
+#[test]
+fn report_measures_every_echo() {
+    let mut benchmarks = BenchmarkRegistry::default();
+    let mut done = benchmarks.start(1, PEER.into(), &options(3), 0);
+
+    benchmarks.echoed(1, PEER, 0, 0, 40);
+    benchmarks.echoed(1, PEER, 1, 10, 30);
+    assert!(done.try_recv().is_err());
+    benchmarks.echoed(1, PEER, 2, 20, 100);
+    assert!(done.try_recv().is_ok());
+
+    let report = benchmarks.finish(1).unwrap();
+    assert_eq!(report.received, 3);
+    assert_eq!(report.loss, 0.0);
+    assert_eq!(report.min_rtt_ms, 20);
+    assert_eq!(report.max_rtt_ms, 80);
+    assert_eq!(report.one_way_delay_ms, 20.0);
+    // 3 probes of 1000 bytes went out and back in 100 ms
+    assert_eq!(report.throughput, 60_000.0);
+}
+
+#[test]
+fn probes_without_echo_count_as_lost() {
+    let mut benchmarks = BenchmarkRegistry::default();
+    let _done = benchmarks.start(1, PEER.into(), &options(4), 0);
+
+    benchmarks.echoed(1, PEER, 0, 0, 10);
+
+    let report = benchmarks.finish(1).unwrap();
+    assert_eq!(report.sent, 4);
+    assert_eq!(report.received, 1);
+    assert_eq!(report.loss, 0.75);
+}
+
+#[test]
+fn echoes_from_others_and_duplicates_are_ignored() {
+    let mut benchmarks = BenchmarkRegistry::default();
+    let _done = benchmarks.start(1, PEER.into(), &options(2), 0);
+
+    benchmarks.echoed(1, "did:key:stranger", 0, 0, 10);
+    benchmarks.echoed(1, PEER, 1, 0, 10);
+    benchmarks.echoed(1, PEER, 1, 0, 50);
+    benchmarks.echoed(1, PEER, 7, 0, 10);
+
+    let report = benchmarks.finish(1).unwrap();
+    assert_eq!(report.received, 1);
+    assert_eq!(report.max_rtt_ms, 10);
+}
+
+#[test]
+fn probes_are_not_answered_without_consent() {
+    let mut benchmarks = BenchmarkRegistry::default();
+    assert!(!benchmarks.is_answering());
+
+    benchmarks.set_answering(true);
+
+    assert!(benchmarks.is_answering());
+}
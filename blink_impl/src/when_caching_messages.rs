@@ -0,0 +1,98 @@
+use crate::cache_policy::{CacheLedger, CachePolicy, CacheScope};
+use crate::cache_writer::CacheWriter;
+use crate::event_sink::EventSink;
+use crate::when_using_peer_to_peer_service::LogHandler;
+use blink_contract::Event;
+use sata::Sata;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::sync::RwLock;
+
+fn ledger(policy: CachePolicy) -> CacheLedger {
+    let mut ledger = CacheLedger::default();
+    ledger.set_policy(policy);
+    ledger
+}
+
+#[test]
+fn everything_is_cached_by_default() {
+    let mut ledger = CacheLedger::default();
+
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(ledger.admit("held-for-carol", false, 10, 0));
+    assert_eq!(ledger.size(), 20);
+}
+
+#[test]
+fn direct_scope_skips_topics_held_for_others() {
+    let mut ledger = ledger(CachePolicy {
+        scope: CacheScope::Direct,
+        ..Default::default()
+    });
+
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(!ledger.admit("held-for-carol", false, 10, 0));
+    assert_eq!(ledger.size(), 10);
+}
+
+#[test]
+fn overrides_win_over_the_scope() {
+    let mut ledger = ledger(
+        CachePolicy {
+            scope: CacheScope::Nothing,
+            ..Default::default()
+        }
+        .with_override("ab", true)
+        .with_override("ac", false),
+    );
+
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(!ledger.admit("ac", true, 10, 0));
+    assert!(!ledger.admit("ad", true, 10, 0));
+}
+
+#[test]
+fn messages_past_the_size_limit_are_not_cached() {
+    let mut ledger = ledger(CachePolicy {
+        max_size: Some(25),
+        ..Default::default()
+    });
+
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(!ledger.admit("ab", true, 10, 0));
+    assert!(ledger.admit("ab", true, 5, 0));
+}
+
+#[test]
+fn expired_writes_free_their_space() {
+    let mut ledger = ledger(CachePolicy {
+        max_size: Some(20),
+        ttl: Some(Duration::from_secs(60)),
+        ..Default::default()
+    });
+
+    assert!(ledger.admit("ab", true, 10, 0));
+    assert!(ledger.admit("ab", true, 10, 30_000));
+    assert!(!ledger.admit("ab", true, 10, 59_999));
+    assert!(ledger.admit("ab", true, 10, 60_000));
+    assert_eq!(ledger.size(), 20);
+}
+
+#[tokio::test]
+async fn messages_past_a_full_queue_are_dropped_and_reported() {
+    let log = Arc::new(RwLock::new(LogHandler { events: Vec::new() }));
+    let (logger, deliver) = EventSink::new(log.clone());
+    tokio::spawn(deliver);
+    let (queue, mut queued) = tokio::sync::mpsc::channel(1);
+    let writer = CacheWriter::new(queue);
+
+    writer.write(&logger, Sata::default());
+    writer.write(&logger, Sata::default());
+    logger.flush().await;
+
+    assert!(queued.try_recv().is_ok());
+    assert!(queued.try_recv().is_err());
+    let events = &log.read().events;
+    assert!(matches!(events[..], [Event::ErrorAddingToCache(_)]));
+}
@@ -0,0 +1,80 @@
+use crate::device_key::{load_or_generate, DeviceCertificate};
+use crate::test_support::{keystore, temp_path};
+use blink_contract::Keystore;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+
+#[test]
+fn a_certificate_names_the_did_that_signed_it() {
+    let keystore = keystore();
+    let transport = Keypair::generate_ed25519();
+
+    let certificate = DeviceCertificate::new(&keystore, &transport.public()).unwrap();
+    let agent_version = certificate.to_agent_version().unwrap();
+    let did = DeviceCertificate::from_agent_version(&agent_version)
+        .unwrap()
+        .verify(&transport.public().to_peer_id())
+        .unwrap();
+
+    assert_eq!(did, keystore.public_key().unwrap());
+}
+
+#[test]
+fn a_certificate_for_another_key_is_rejected() {
+    let keystore = keystore();
+    let certificate =
+        DeviceCertificate::new(&keystore, &Keypair::generate_ed25519().public()).unwrap();
+
+    assert!(certificate.verify(&PeerId::random()).is_err());
+}
+
+#[test]
+fn a_certificate_claiming_another_did_is_rejected() {
+    let transport = Keypair::generate_ed25519();
+    let mut certificate = DeviceCertificate::new(&keystore(), &transport.public()).unwrap();
+
+    certificate.did = keystore().public_key().unwrap().to_string();
+
+    assert!(certificate
+        .verify(&transport.public().to_peer_id())
+        .is_err());
+}
+
+#[test]
+fn agents_without_a_certificate_are_rejected() {
+    assert!(DeviceCertificate::from_agent_version("rust-libp2p/0.46.1").is_err());
+}
+
+#[test]
+fn the_transport_key_is_not_the_did_key() {
+    let keystore = keystore();
+    let transport = load_or_generate(None).unwrap();
+
+    let certificate = DeviceCertificate::new(&keystore, &transport.public()).unwrap();
+
+    assert!(certificate
+        .verify(
+            &crate::did_to_libp2p_pub(&keystore.public_key().unwrap())
+                .unwrap()
+                .to_peer_id()
+        )
+        .is_err());
+}
+
+#[test]
+fn a_saved_device_key_survives_a_restart() {
+    let path = temp_path("restart", "key");
+
+    let first = load_or_generate(Some(&path)).unwrap();
+    let second = load_or_generate(Some(&path)).unwrap();
+
+    assert_eq!(first.public(), second.public());
+}
+
+#[test]
+fn devices_without_a_saved_key_get_a_new_one() {
+    let first = load_or_generate(None).unwrap();
+    let second = load_or_generate(None).unwrap();
+
+    assert_ne!(first.public(), second.public());
+}
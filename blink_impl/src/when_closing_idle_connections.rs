@@ -0,0 +1,116 @@
+use crate::idle::{IdlePolicy, IdleTracker};
+use libp2p::PeerId;
+use std::collections::HashMap;
+
+const TIMEOUT_MILLIS: u64 = 60_000;
+
+fn tracker(keep_paired: bool) -> IdleTracker {
+    let mut tracker = IdleTracker::default();
+    tracker.set_policy(IdlePolicy {
+        timeout_secs: Some(TIMEOUT_MILLIS / 1000),
+        keep_paired,
+    });
+    tracker
+}
+
+fn nothing_open(_: &PeerId) -> bool {
+    false
+}
+
+#[test]
+fn quiet_connections_close_after_the_timeout() {
+    let mut tracker = tracker(true);
+    let quiet = PeerId::random();
+    let chatty = PeerId::random();
+
+    tracker.active(&quiet, 0);
+    tracker.active(&chatty, 0);
+    tracker.active(&chatty, TIMEOUT_MILLIS);
+
+    assert_eq!(
+        tracker.idle(TIMEOUT_MILLIS, &HashMap::new(), nothing_open),
+        vec![quiet]
+    );
+}
+
+#[test]
+fn peers_with_open_streams_stay_connected() {
+    let mut tracker = tracker(true);
+    let peer = PeerId::random();
+
+    tracker.active(&peer, 0);
+
+    assert!(tracker
+        .idle(TIMEOUT_MILLIS, &HashMap::new(), |_| true)
+        .is_empty());
+}
+
+#[test]
+fn paired_peers_stay_connected_unless_overridden() {
+    let mut tracker = tracker(true);
+    let peer = PeerId::random();
+    let paired = HashMap::from([("did:key:paired".to_string(), peer)]);
+    tracker.active(&peer, 0);
+
+    assert!(tracker
+        .idle(TIMEOUT_MILLIS, &paired, nothing_open)
+        .is_empty());
+
+    tracker.set_override("did:key:paired".into(), Some(false));
+    assert_eq!(
+        tracker.idle(TIMEOUT_MILLIS, &paired, nothing_open),
+        vec![peer]
+    );
+}
+
+#[test]
+fn a_favorite_stays_connected_when_paired_peers_dont() {
+    let mut tracker = tracker(false);
+    let favorite = PeerId::random();
+    let other = PeerId::random();
+    let paired = HashMap::from([
+        ("did:key:favorite".to_string(), favorite),
+        ("did:key:other".to_string(), other),
+    ]);
+    tracker.active(&favorite, 0);
+    tracker.active(&other, 0);
+
+    tracker.set_override("did:key:favorite".into(), Some(true));
+
+    assert_eq!(
+        tracker.idle(TIMEOUT_MILLIS, &paired, nothing_open),
+        vec![other]
+    );
+}
+
+#[test]
+fn configured_nodes_and_disconnected_peers_are_left_alone() {
+    let mut tracker = tracker(true);
+    let node = PeerId::random();
+    let gone = PeerId::random();
+    tracker.pin(node);
+    tracker.active(&node, 0);
+    tracker.active(&gone, 0);
+
+    tracker.disconnected(&gone);
+
+    assert!(tracker
+        .idle(TIMEOUT_MILLIS, &HashMap::new(), nothing_open)
+        .is_empty());
+}
+
+#[test]
+fn without_a_timeout_nothing_closes() {
+    let mut tracker = tracker(false);
+    tracker.set_policy(IdlePolicy {
+        timeout_secs: None,
+        keep_paired: false,
+    });
+    let peer = PeerId::random();
+
+    tracker.active(&peer, 0);
+
+    assert!(tracker
+        .idle(u64::MAX, &HashMap::new(), nothing_open)
+        .is_empty());
+}
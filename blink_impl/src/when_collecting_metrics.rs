@@ -0,0 +1,50 @@
+use crate::metrics::Metrics;
+use std::time::Duration;
+
+#[test]
+fn open_connections_are_established_minus_closed() {
+    let mut metrics = Metrics::default();
+    metrics.connection_established();
+    metrics.connection_established();
+    metrics.connection_closed();
+
+    assert_eq!(metrics.snapshot(0).open_connections(), 1);
+}
+
+#[test]
+fn received_messages_are_counted_per_topic() {
+    let mut metrics = Metrics::default();
+    metrics.received("ab");
+    metrics.received("ab");
+    metrics.received("ac");
+
+    let snapshot = metrics.snapshot(0);
+
+    assert_eq!(snapshot.received_per_topic["ab"], 2);
+    assert_eq!(snapshot.received_per_topic["ac"], 1);
+}
+
+#[test]
+fn cache_write_time_keeps_the_total_and_the_slowest() {
+    let mut metrics = Metrics::default();
+    metrics.cache_written(Duration::from_millis(3));
+    metrics.cache_written(Duration::from_millis(7));
+    metrics.cache_written(Duration::from_millis(2));
+
+    let snapshot = metrics.snapshot(0);
+
+    assert_eq!(snapshot.cache_writes, 3);
+    assert_eq!(snapshot.cache_write_time, Duration::from_millis(12));
+    assert_eq!(snapshot.max_cache_write_time, Duration::from_millis(7));
+}
+
+#[test]
+fn snapshot_reports_the_queue_depths() {
+    let mut metrics = Metrics::default();
+    metrics.messages_queued(4);
+
+    let snapshot = metrics.snapshot(9);
+
+    assert_eq!(snapshot.queued_messages, 4);
+    assert_eq!(snapshot.queued_commands, 9);
+}
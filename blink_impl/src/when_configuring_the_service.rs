@@ -0,0 +1,227 @@
+use crate::cache_policy::CacheScope;
+use crate::config::BlinkConfig;
+use crate::delivery::DeliveryStrategy;
+use crate::event_history::Verbosity;
+use std::time::Duration;
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn missing_keys_keep_their_defaults() {
+    let config = BlinkConfig::from_toml("").unwrap();
+
+    assert_eq!(config, BlinkConfig::default());
+}
+
+#[test]
+fn a_toml_file_covers_networking_and_cache() {
+    let config = BlinkConfig::from_toml(
+        r#"
+        listen_addrs = ["/ip4/127.0.0.1/tcp/4001"]
+        bootstrap = ["/ip4/10.0.0.1/tcp/4001"]
+
+        [gossipsub]
+        mesh_n = 4
+        mesh_n_low = 3
+
+        [cache]
+        scope = "direct"
+        ttl_secs = 60
+        overrides = { announcements = true }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.listen_addrs,
+        vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()]
+    );
+    assert_eq!(config.bootstrap.len(), 1);
+    assert_eq!(config.gossipsub.mesh_n, 4);
+    assert_eq!(config.gossipsub.mesh_n_low, 3);
+    assert_eq!(config.gossipsub.mesh_n_high, 12);
+    let policy = config.cache.policy();
+    assert_eq!(policy.scope, CacheScope::Direct);
+    assert_eq!(policy.ttl, Some(Duration::from_secs(60)));
+    assert_eq!(policy.overrides.get("announcements"), Some(&true));
+}
+
+#[test]
+fn environment_variables_override_the_file() {
+    let mut config = BlinkConfig::from_toml("[gossipsub]\nmesh_n = 4").unwrap();
+
+    config
+        .apply_vars(vars(&[
+            ("BLINK_GOSSIPSUB_MESH_N", "8"),
+            (
+                "BLINK_RELAYS",
+                "/ip4/10.0.0.2/tcp/4001, /ip4/10.0.0.3/tcp/4001",
+            ),
+            ("BLINK_CACHE_SCOPE", "Nothing"),
+            ("BLINK_EXTERNAL_ADDRS", "/ip4/203.0.113.7/tcp/4001"),
+            ("HOME", "/root"),
+        ]))
+        .unwrap();
+
+    assert_eq!(config.gossipsub.mesh_n, 8);
+    assert_eq!(config.relays.len(), 2);
+    assert_eq!(config.cache.scope, CacheScope::Nothing);
+    assert_eq!(config.external_addrs.len(), 1);
+}
+
+#[test]
+fn mdns_is_on_unless_turned_off() {
+    let config = BlinkConfig::from_toml("mdns = false").unwrap();
+    assert!(BlinkConfig::default().mdns);
+    assert!(!config.mdns);
+
+    let mut config = BlinkConfig::default();
+    config.apply_vars(vars(&[("BLINK_MDNS", "false")])).unwrap();
+    assert!(!config.mdns);
+}
+
+#[test]
+fn verbosity_is_read_from_the_file_or_the_environment() {
+    let config = BlinkConfig::from_toml(r#"verbosity = "quiet""#).unwrap();
+    assert_eq!(BlinkConfig::default().verbosity, Verbosity::All);
+    assert_eq!(config.verbosity, Verbosity::Quiet);
+
+    let mut config = BlinkConfig::default();
+    config
+        .apply_vars(vars(&[("BLINK_VERBOSITY", "Silent")]))
+        .unwrap();
+    assert_eq!(config.verbosity, Verbosity::Silent);
+    assert!(config
+        .apply_vars(vars(&[("BLINK_VERBOSITY", "loud")]))
+        .is_err());
+}
+
+#[test]
+fn auto_pairing_is_off_unless_turned_on() {
+    assert!(!BlinkConfig::default().auto_pair);
+
+    let mut config = BlinkConfig::default();
+    config
+        .apply_vars(vars(&[("BLINK_AUTO_PAIR", "true")]))
+        .unwrap();
+    assert!(config.auto_pair);
+}
+
+#[test]
+fn batching_is_off_unless_turned_on() {
+    assert!(!BlinkConfig::default().batching.enabled);
+
+    let mut config = BlinkConfig::default();
+    config
+        .apply_vars(vars(&[
+            ("BLINK_BATCHING", "true"),
+            ("BLINK_BATCH_WINDOW_MILLIS", "20"),
+        ]))
+        .unwrap();
+
+    assert!(config.batching.enabled);
+    assert_eq!(config.batching.window_millis, 20);
+}
+
+#[test]
+fn only_topics_with_a_single_remote_peer_are_flooded_by_default() {
+    let delivery = BlinkConfig::default().delivery;
+
+    assert_eq!(delivery.strategy("pairwise", 1), DeliveryStrategy::Flood);
+    assert_eq!(delivery.strategy("group", 4), DeliveryStrategy::Mesh);
+}
+
+#[test]
+fn named_topics_keep_their_delivery_strategy_whatever_their_size() {
+    let mut config = BlinkConfig::from_toml(
+        r#"
+        [delivery]
+        topics = { lobby = "mesh", call = "flood" }
+        "#,
+    )
+    .unwrap();
+    config
+        .apply_vars(vars(&[("BLINK_DELIVERY_FLOOD_UP_TO", "3")]))
+        .unwrap();
+
+    assert_eq!(config.delivery.strategy("lobby", 1), DeliveryStrategy::Mesh);
+    assert_eq!(config.delivery.strategy("call", 8), DeliveryStrategy::Flood);
+    assert_eq!(
+        config.delivery.strategy("other", 3),
+        DeliveryStrategy::Flood
+    );
+}
+
+#[test]
+fn peer_scoring_thresholds_come_from_the_file_or_environment() {
+    let mut config = BlinkConfig::from_toml(
+        r#"
+        [scoring]
+        blacklist_threshold = -500.0
+        "#,
+    )
+    .unwrap();
+    config
+        .apply_vars(vars(&[("BLINK_SCORE_GRAYLIST_THRESHOLD", "-100")]))
+        .unwrap();
+
+    assert!(config.scoring.enabled);
+    assert_eq!(config.scoring.graylist_threshold, -100.0);
+    assert_eq!(config.scoring.blacklist_threshold, -500.0);
+    assert_eq!(config.scoring.publish_threshold, -50.0);
+}
+
+#[test]
+fn an_isolated_dht_can_be_joined_as_a_client() {
+    let mut config = BlinkConfig::from_toml(
+        r#"
+        [kademlia]
+        protocol = "/satellite/kad/1.0.0"
+        "#,
+    )
+    .unwrap();
+    assert!(!config.kademlia.client_mode);
+    assert_eq!(
+        config.kademlia.protocol.as_deref(),
+        Some("/satellite/kad/1.0.0")
+    );
+
+    config
+        .apply_vars(vars(&[
+            ("BLINK_KADEMLIA_CLIENT_MODE", "true"),
+            ("BLINK_KADEMLIA_PROTOCOL", ""),
+        ]))
+        .unwrap();
+    assert!(config.kademlia.client_mode);
+    assert_eq!(config.kademlia.protocol, None);
+}
+
+#[test]
+fn the_device_key_path_can_be_set_and_cleared() {
+    let mut config = BlinkConfig::from_toml(r#"device_key = "/var/lib/blink/device.key""#).unwrap();
+    assert_eq!(
+        config.device_key.as_deref(),
+        Some(std::path::Path::new("/var/lib/blink/device.key"))
+    );
+
+    config
+        .apply_vars(vars(&[("BLINK_DEVICE_KEY", "")]))
+        .unwrap();
+    assert_eq!(config.device_key, None);
+}
+
+#[test]
+fn invalid_overrides_name_the_variable() {
+    let mut config = BlinkConfig::default();
+
+    let error = config
+        .apply_vars(vars(&[("BLINK_LISTEN_ADDRS", "not an address")]))
+        .unwrap_err();
+
+    assert!(error.to_string().contains("BLINK_LISTEN_ADDRS"));
+}
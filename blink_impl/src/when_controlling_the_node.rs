@@ -0,0 +1,104 @@
+use crate::control::handle_connection;
+use crate::when_using_peer_to_peer_service::create_service;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf,
+};
+use tokio::sync::Mutex;
+
+const TIMEOUT_SECS: u64 = 10;
+
+struct Client {
+    lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    writer: WriteHalf<DuplexStream>,
+}
+
+impl Client {
+    async fn connect() -> Self {
+        let (service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(handle_connection(Arc::new(Mutex::new(service)), server));
+        let (reader, writer) = tokio::io::split(client);
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    async fn request(&mut self, request: Value) {
+        let mut line = request.to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+    }
+
+    async fn next(&mut self) -> Value {
+        let line = self.lines.next_line().await.unwrap().unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+#[tokio::test]
+async fn peers_start_empty() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut client = Client::connect().await;
+
+        client
+            .request(json!({ "jsonrpc": "2.0", "id": 1, "method": "peers" }))
+            .await;
+
+        assert_eq!(
+            client.next().await,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": [] })
+        );
+    })
+    .await
+    .expect("timeout");
+}
+
+#[tokio::test]
+async fn unknown_methods_are_rejected() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut client = Client::connect().await;
+
+        client
+            .request(json!({ "jsonrpc": "2.0", "id": 7, "method": "reboot" }))
+            .await;
+
+        let response = client.next().await;
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], -32601);
+    })
+    .await
+    .expect("timeout");
+}
+
+#[tokio::test]
+async fn subscribers_are_notified_of_events() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut client = Client::connect().await;
+
+        client
+            .request(json!({ "jsonrpc": "2.0", "id": 1, "method": "subscribe" }))
+            .await;
+        assert!(client.next().await["result"]["since"].is_u64());
+        client
+            .request(json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "pair",
+                "params": { "address": "/ip4/127.0.0.1/tcp/1" },
+            }))
+            .await;
+
+        loop {
+            let message = client.next().await;
+            if message["method"] == "event" && message["params"]["name"] == "Dialing" {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("timeout");
+}
@@ -0,0 +1,57 @@
+use crate::test_support::keystore;
+use crate::{did_keypair_to_libp2p_keypair, libp2p_pub_to_did};
+use blink_contract::Keystore;
+use did_key::{Ed25519KeyPair, KeyMaterial, P256KeyPair, Secp256k1KeyPair, X25519KeyPair};
+use warp::crypto::DID;
+
+fn assert_round_trip(did: DID) {
+    let key_pair = did_keypair_to_libp2p_keypair(did.as_ref()).unwrap();
+    let converted = libp2p_pub_to_did(&key_pair.public()).unwrap();
+
+    assert_eq!(
+        did.as_ref().public_key_bytes(),
+        converted.as_ref().public_key_bytes()
+    );
+}
+
+#[test]
+fn ed25519_key_round_trips() {
+    assert_round_trip(DID::from(did_key::generate::<Ed25519KeyPair>(None)));
+}
+
+#[test]
+fn secp256k1_key_round_trips() {
+    assert_round_trip(DID::from(did_key::generate::<Secp256k1KeyPair>(None)));
+}
+
+#[test]
+fn p256_key_round_trips() {
+    assert_round_trip(DID::from(did_key::generate::<P256KeyPair>(None)));
+}
+
+#[test]
+fn secp256k1_key_keeps_peer_id_stable() {
+    let did = DID::from(did_key::generate::<Secp256k1KeyPair>(None));
+    let first = did_keypair_to_libp2p_keypair(did.as_ref()).unwrap();
+    let second = did_keypair_to_libp2p_keypair(did.as_ref()).unwrap();
+
+    assert_eq!(first.public().to_peer_id(), second.public().to_peer_id());
+}
+
+#[test]
+fn unsupported_key_type_is_rejected() {
+    let did = DID::from(did_key::generate::<X25519KeyPair>(None));
+
+    assert!(did_keypair_to_libp2p_keypair(did.as_ref()).is_err());
+}
+
+#[test]
+fn keystores_derive_the_same_shared_secret() {
+    let first = keystore();
+    let second = keystore();
+
+    let first_secret = first.key_exchange(&second.public_key().unwrap()).unwrap();
+    let second_secret = second.key_exchange(&first.public_key().unwrap()).unwrap();
+
+    assert_eq!(first_secret, second_secret);
+}
@@ -0,0 +1,69 @@
+use crate::message_kinds::{BlinkMessage, MessageKinds, Typed, CHAT_KIND, SIGNAL_KIND};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Reaction {
+    message: String,
+    emoji: String,
+}
+
+fn typed<T: Serialize>(kind: &str, body: T) -> Sata {
+    Typed::new(kind, body).encode_into(Sata::default()).unwrap()
+}
+
+#[test]
+fn built_in_kinds_decode_without_registering() {
+    let kinds = MessageKinds::default();
+
+    let chat = kinds.decode(typed(CHAT_KIND, "hello"));
+    let signal = kinds.decode(typed(SIGNAL_KIND, ("typing", vec![1u8])));
+
+    assert!(matches!(chat, BlinkMessage::Chat(text) if text == "hello"));
+    assert!(matches!(signal, BlinkMessage::Signal(name, data) if name == "typing" && data == [1]));
+}
+
+#[test]
+fn registered_kinds_decode_to_their_type() {
+    let mut kinds = MessageKinds::default();
+    kinds.register::<Reaction>("reaction").unwrap();
+    let reaction = Reaction {
+        message: "bafy".into(),
+        emoji: "+1".into(),
+    };
+
+    let decoded = kinds.decode(typed("reaction", reaction.clone()));
+
+    match decoded {
+        BlinkMessage::Custom(kind, body) => {
+            assert_eq!(kind, "reaction");
+            assert_eq!(body.downcast_ref::<Reaction>(), Some(&reaction));
+        }
+        other => panic!("Decoded as {:?}", other),
+    }
+}
+
+#[test]
+fn anything_else_stays_raw() {
+    let kinds = MessageKinds::default();
+    let untyped = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, "hello".to_string())
+        .unwrap();
+
+    assert!(matches!(kinds.decode(untyped), BlinkMessage::Raw(_)));
+    assert!(matches!(
+        kinds.decode(typed("reaction", "+1")),
+        BlinkMessage::Raw(_)
+    ));
+    assert!(matches!(
+        kinds.decode(typed(CHAT_KIND, 42)),
+        BlinkMessage::Raw(_)
+    ));
+}
+
+#[test]
+fn built_in_kinds_cant_be_registered() {
+    let mut kinds = MessageKinds::default();
+
+    assert!(kinds.register::<Reaction>(CHAT_KIND).is_err());
+}
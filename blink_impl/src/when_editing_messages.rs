@@ -0,0 +1,120 @@
+use crate::conversations::{message_id, ConversationStore, StoredMessage};
+use crate::message_changes::{apply_changes, MessageChange};
+use crate::message_kinds::{MessageKinds, DELETE_KIND, EDIT_KIND};
+use crate::test_support::text;
+use sata::Sata;
+
+// Alice's message on the topic she shares with bob, and its id
+fn store() -> (ConversationStore, String) {
+    let mut store = ConversationStore::default();
+    let sata = text("lunch at noon?");
+    let id = message_id(&sata);
+    store.record(StoredMessage::new("alice".into(), "ab".into(), 1, sata));
+    (store, id)
+}
+
+#[test]
+fn changes_survive_the_wire() {
+    let edit = MessageChange::Edit("id".into(), text("lunch at one?"));
+
+    match MessageChange::decode(&edit.encode().unwrap()) {
+        Some(MessageChange::Edit(id, sata)) => {
+            assert_eq!(id, "id");
+            assert_eq!(sata.decode::<String>().unwrap(), "lunch at one?");
+        }
+        other => panic!("{:?}", other),
+    }
+    let delete = MessageChange::Delete("id".into()).encode().unwrap();
+    assert!(matches!(MessageChange::decode(&delete), Some(MessageChange::Delete(x)) if x == "id"));
+    assert!(MessageChange::decode(&text("edit")).is_none());
+}
+
+#[test]
+fn edits_replace_the_payload_and_keep_the_id() {
+    let (mut store, id) = store();
+
+    let previous = store.edit("ab", &id, "alice", text("lunch at one?"), 5);
+
+    assert_eq!(
+        previous.unwrap().decode::<String>().unwrap(),
+        "lunch at noon?"
+    );
+    let history = store.history("ab", .., 10);
+    assert_eq!(history[0].id, id);
+    assert_eq!(history[0].edited_at, Some(5));
+    assert_eq!(history[0].sata.decode::<String>().unwrap(), "lunch at one?");
+    assert_eq!(store.topics_of(&id, "alice"), vec!["ab".to_string()]);
+}
+
+#[test]
+fn only_the_sender_changes_a_message() {
+    let (mut store, id) = store();
+
+    assert!(store.edit("ab", &id, "bob", text("no lunch"), 5).is_none());
+    assert!(store.delete("ab", &id, "bob").is_none());
+    assert!(store.delete("ac", &id, "alice").is_none());
+
+    assert!(store.topics_of(&id, "bob").is_empty());
+    assert!(!store.history("ab", .., 10)[0].deleted);
+}
+
+#[test]
+fn deleted_messages_leave_a_tombstone_that_stays() {
+    let (mut store, id) = store();
+
+    assert!(store.delete("ab", &id, "alice").is_some());
+
+    let history = store.history("ab", .., 10);
+    assert!(history[0].deleted);
+    assert!(store.edit("ab", &id, "alice", text("back"), 5).is_none());
+    assert!(store.delete("ab", &id, "alice").is_none());
+}
+
+#[test]
+fn edit_and_delete_kinds_are_reserved() {
+    let mut kinds = MessageKinds::default();
+
+    assert!(kinds.register::<String>(EDIT_KIND).is_err());
+    assert!(kinds.register::<String>(DELETE_KIND).is_err());
+}
+
+fn read(cached: Vec<Sata>) -> Vec<String> {
+    apply_changes(cached)
+        .iter()
+        .map(|x| x.decode::<String>().unwrap())
+        .collect()
+}
+
+#[test]
+fn cached_changes_apply_to_the_messages_before_them() {
+    let first = text("lunch at noon?");
+    let second = text("see you there");
+    let edited = text("lunch at one?");
+    let cached = vec![
+        first.clone(),
+        second.clone(),
+        MessageChange::Edit(message_id(&first), edited.clone())
+            .encode()
+            .unwrap(),
+        MessageChange::Edit(message_id(&edited), text("lunch at two?"))
+            .encode()
+            .unwrap(),
+        MessageChange::Delete(message_id(&second)).encode().unwrap(),
+    ];
+
+    assert_eq!(read(cached), vec!["lunch at two?".to_string()]);
+}
+
+#[test]
+fn cached_changes_to_unknown_messages_are_left_out() {
+    let message = text("lunch at noon?");
+    let cached = vec![
+        MessageChange::Delete(message_id(&message))
+            .encode()
+            .unwrap(),
+        message,
+        MessageChange::Delete("unknown".into()).encode().unwrap(),
+    ];
+
+    assert_eq!(read(cached), vec!["lunch at noon?".to_string()]);
+}
@@ -0,0 +1,115 @@
+use crate::test_support::{did_of, keystore, text};
+use crate::wire::{
+    BincodeWireCodec, DagCborWireCodec, SignedEnvelope, WireCodec, WireFormat, BINCODE_CODEC,
+    DAG_CBOR_CODEC, PLAIN_VERSION, SIGNED_CODEC, WIRE_VERSION,
+};
+use std::sync::Arc;
+
+#[test]
+fn messages_are_sealed_in_a_versioned_envelope() {
+    let format = WireFormat::default();
+
+    let bytes = format.seal(&text("hello")).unwrap();
+
+    assert_eq!(&bytes[..4], &[b'B', b'L', PLAIN_VERSION, BINCODE_CODEC]);
+    let opened = format.open(&bytes).unwrap();
+    assert_eq!(opened.decode::<String>().unwrap(), "hello");
+}
+
+#[test]
+fn every_registered_codec_is_read_whatever_is_sent() {
+    let mut sender = WireFormat::default();
+    sender.use_codec(Arc::new(DagCborWireCodec));
+    let receiver = WireFormat::default();
+
+    let bytes = sender.seal(&text("hello")).unwrap();
+
+    assert_eq!(bytes[3], DAG_CBOR_CODEC);
+    let opened = receiver.open(&bytes).unwrap();
+    assert_eq!(opened.decode::<String>().unwrap(), "hello");
+}
+
+#[test]
+fn bare_bincode_from_older_peers_is_still_read() {
+    let bytes = BincodeWireCodec.encode(&text("hello")).unwrap();
+
+    let opened = WireFormat::default().open(&bytes).unwrap();
+
+    assert_eq!(opened.decode::<String>().unwrap(), "hello");
+}
+
+#[test]
+fn newer_envelopes_and_unknown_codecs_are_refused() {
+    let format = WireFormat::default();
+    let mut bytes = format.seal(&text("hello")).unwrap();
+
+    bytes[2] = WIRE_VERSION + 1;
+    assert!(format.open(&bytes).is_err());
+
+    bytes[2] = WIRE_VERSION;
+    bytes[3] = 42;
+    assert!(format.open(&bytes).is_err());
+}
+
+#[test]
+fn expiring_messages_carry_their_expiry_in_a_newer_envelope() {
+    let format = WireFormat::default();
+
+    let bytes = format.seal_expiring(&text("hello"), Some(60_000)).unwrap();
+
+    assert_eq!(&bytes[..4], &[b'B', b'L', WIRE_VERSION, BINCODE_CODEC]);
+    let (opened, expires_at) = format.open_expiring(&bytes).unwrap();
+    assert_eq!(opened.decode::<String>().unwrap(), "hello");
+    assert_eq!(expires_at, Some(60_000));
+    assert!(format.open(&bytes[..8]).is_err());
+}
+
+#[test]
+fn plain_messages_never_expire() {
+    let format = WireFormat::default();
+
+    let bytes = format.seal_expiring(&text("hello"), None).unwrap();
+
+    assert_eq!(bytes[2], PLAIN_VERSION);
+    assert_eq!(format.open_expiring(&bytes).unwrap().1, None);
+}
+
+#[test]
+fn signed_envelopes_verify_for_their_author_and_topic_only() {
+    let author = keystore();
+    let mallory = keystore();
+    let (author_did, mallory_did) = (did_of(&author), did_of(&mallory));
+    let format = WireFormat::default();
+    let envelope = format.seal(&text("hello")).unwrap();
+
+    let signed = SignedEnvelope::new(&author, "ab", envelope.clone()).unwrap();
+    let forged = SignedEnvelope::new(&mallory, "ab", envelope).unwrap();
+
+    let authors = [mallory_did.as_str(), author_did.as_str()];
+    assert_eq!(signed.verify("ab", &authors).unwrap(), author_did);
+    assert!(signed.verify("ac", &authors).is_err());
+    assert!(forged.verify("ab", &[author_did.as_str()]).is_err());
+}
+
+#[test]
+fn signed_envelopes_open_with_their_signature_alone_or_batched() {
+    let author = keystore();
+    let format = WireFormat::default();
+    let envelope = format.seal_expiring(&text("hello"), Some(60_000)).unwrap();
+    let signed = SignedEnvelope::new(&author, "ab", envelope)
+        .unwrap()
+        .seal()
+        .unwrap();
+
+    assert_eq!(signed[3], SIGNED_CODEC);
+    assert!(format.open(&signed).is_err());
+    for bytes in [signed.clone(), format.batch(&[signed.clone()]).unwrap()] {
+        let opened = format.open_each(&bytes).unwrap();
+        assert_eq!(opened.len(), 1);
+        assert_eq!(opened[0].sata.decode::<String>().unwrap(), "hello");
+        assert_eq!(opened[0].expires_at, Some(60_000));
+        assert_eq!(opened[0].envelope, signed);
+        let signature = opened[0].signed.as_ref().unwrap();
+        assert!(signature.verify("ab", &[did_of(&author).as_str()]).is_ok());
+    }
+}
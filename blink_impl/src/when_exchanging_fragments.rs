@@ -0,0 +1,464 @@
+use crate::clock::SystemClock;
+use crate::conflux::{
+    new_behaviour, Conflux, ConfluxBehaviour, ConfluxError, ConfluxState, FragmentRequest,
+    FragmentResponse, FragmentUpdate,
+};
+use crate::fragments::{CidPolicy, DataFragment, Patch, RAW_CODEC, SATA_CODEC};
+use crate::peer_to_peer_service::BlinkCommand;
+use libp2p::{futures::StreamExt, request_response::RequestId, PeerId};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Receiver};
+use warp::sync::RwLock;
+
+fn conflux() -> (Conflux, Receiver<BlinkCommand>) {
+    let (commands, receiver) = channel(8);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    (
+        Conflux::new(state, commands, Arc::new(SystemClock)),
+        receiver,
+    )
+}
+
+// Request ids only come out of a behaviour, the requests themselves go nowhere
+fn ask(conflux: &mut ConfluxState, behaviour: &mut ConfluxBehaviour, cid: &str) -> RequestId {
+    let peer = PeerId::random();
+    let request_id = behaviour.send_request(&peer, FragmentRequest::Want(cid.to_string()));
+    conflux.asked(request_id, cid.to_string(), peer);
+    request_id
+}
+
+#[test]
+fn fragment_held_locally_is_served_without_asking() {
+    let mut conflux = ConfluxState::default();
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+    conflux.add_fragment(fragment.clone()).unwrap();
+
+    let (mut received, first) = conflux.want(fragment.cid().to_string());
+
+    assert!(!first);
+    assert_eq!(received.try_recv().unwrap(), Some(fragment));
+}
+
+#[test]
+fn first_peer_having_the_fragment_answers_every_waiter() {
+    let mut conflux = ConfluxState::default();
+    let mut behaviour = new_behaviour();
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+    let cid = fragment.cid().to_string();
+    let (mut first_waiter, first) = conflux.want(cid.clone());
+    let (mut second_waiter, again) = conflux.want(cid.clone());
+    assert!(first);
+    assert!(!again);
+    let missing = ask(&mut conflux, &mut behaviour, &cid);
+    let having = ask(&mut conflux, &mut behaviour, &cid);
+
+    conflux.missed(&missing);
+    assert!(first_waiter.try_recv().is_err());
+    conflux.received(&having, fragment.clone());
+
+    assert_eq!(first_waiter.try_recv().unwrap(), Some(fragment.clone()));
+    assert_eq!(second_waiter.try_recv().unwrap(), Some(fragment.clone()));
+    assert_eq!(conflux.fragment(&cid), Some(fragment));
+}
+
+#[test]
+fn fragment_not_matching_its_cid_is_dropped() {
+    let mut conflux = ConfluxState::default();
+    let mut behaviour = new_behaviour();
+    let cid = DataFragment::new(b"hello".to_vec(), 1).cid().to_string();
+    let (mut waiter, _) = conflux.want(cid.clone());
+    let request_id = ask(&mut conflux, &mut behaviour, &cid);
+
+    conflux.received(&request_id, DataFragment::new(b"forged".to_vec(), 1));
+
+    assert_eq!(waiter.try_recv().unwrap(), None);
+    assert!(conflux.fragment(&cid).is_none());
+}
+
+#[test]
+fn wants_are_answered_from_the_local_store() {
+    let mut conflux = ConfluxState::default();
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+    conflux.add_fragment(fragment.clone()).unwrap();
+
+    let response = conflux.respond(FragmentRequest::Want(fragment.cid().to_string()));
+    let missing = conflux.respond(FragmentRequest::Want("unknown".into()));
+
+    assert!(matches!(response, FragmentResponse::Have(x) if x == fragment));
+    assert!(matches!(missing, FragmentResponse::DontHave));
+}
+
+#[test]
+fn providers_already_asked_are_skipped() {
+    let mut conflux = ConfluxState::default();
+    let local = PeerId::random();
+    let asked = PeerId::random();
+    let provider = PeerId::random();
+    let mut behaviour = new_behaviour();
+    let cid = DataFragment::new(b"hello".to_vec(), 1).cid().to_string();
+    let _waiter = conflux.want(cid.clone());
+    conflux.searching(&cid);
+    let request_id = behaviour.send_request(&asked, FragmentRequest::Want(cid.clone()));
+    conflux.asked(request_id, cid.clone(), asked);
+
+    let new = conflux.providers_found(&cid, vec![local, asked, provider], &local);
+
+    assert_eq!(new, vec![provider]);
+}
+
+#[test]
+fn fetch_waits_for_the_dht_before_giving_up() {
+    let mut conflux = ConfluxState::default();
+    let mut behaviour = new_behaviour();
+    let cid = DataFragment::new(b"hello".to_vec(), 1).cid().to_string();
+    let (mut waiter, _) = conflux.want(cid.clone());
+    conflux.searching(&cid);
+    let request_id = ask(&mut conflux, &mut behaviour, &cid);
+
+    conflux.missed(&request_id);
+    assert!(waiter.try_recv().is_err());
+    conflux.providers_found(&cid, Vec::new(), &PeerId::random());
+
+    assert_eq!(waiter.try_recv().unwrap(), None);
+}
+
+#[tokio::test]
+async fn added_fragment_is_announced_and_listed() {
+    let (conflux, mut commands) = conflux();
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+
+    conflux.add(fragment.clone()).await.unwrap();
+
+    assert!(
+        matches!(commands.recv().await, Some(BlinkCommand::Provide(cid)) if cid == fragment.cid())
+    );
+    assert_eq!(conflux.list().await, Ok(vec![fragment.cid().to_string()]));
+    assert_eq!(conflux.get_by_cid(fragment.cid()).await, Ok(fragment));
+}
+
+#[tokio::test]
+async fn removed_fragment_is_no_longer_announced() {
+    let (conflux, mut commands) = conflux();
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+    conflux.add(fragment.clone()).await.unwrap();
+    commands.recv().await;
+
+    assert_eq!(conflux.remove(fragment.cid()).await, Ok(fragment.clone()));
+
+    assert!(
+        matches!(commands.recv().await, Some(BlinkCommand::StopProviding(cid)) if cid == fragment.cid())
+    );
+    assert_eq!(conflux.list().await, Ok(Vec::new()));
+    assert_eq!(
+        conflux.remove(fragment.cid()).await,
+        Err(ConfluxError::NotFound(fragment.cid().to_string()))
+    );
+}
+
+#[tokio::test]
+async fn fragment_claiming_another_cid_is_refused() {
+    let (conflux, _commands) = conflux();
+    let other = DataFragment::new(b"other".to_vec(), 1);
+    let forged: DataFragment = serde_json::from_value(serde_json::json!({
+        "cid": other.cid(),
+        "content_hash": other.cid(),
+        "data": [1, 2, 3],
+        "codec": RAW_CODEC,
+        "policy": "ContentAddressed",
+        "history": [],
+        "timestamp": 1,
+    }))
+    .unwrap();
+
+    assert_eq!(
+        conflux.add(forged).await,
+        Err(ConfluxError::CidMismatch(other.cid().to_string()))
+    );
+}
+
+#[test]
+fn codec_is_part_of_the_cid() {
+    let raw = DataFragment::new(vec![0, 159, 146, 150], 1);
+    let tagged = DataFragment::with_codec(vec![0, 159, 146, 150], SATA_CODEC, 1);
+
+    assert_eq!(raw.codec(), RAW_CODEC);
+    assert_ne!(raw.cid(), tagged.cid());
+    assert!(raw.verify());
+    assert!(tagged.verify());
+}
+
+#[test]
+fn sata_survives_a_round_trip_through_a_fragment() {
+    let sata = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &"hello".to_string())
+        .unwrap();
+
+    let fragment = DataFragment::try_from(sata).unwrap();
+    let decoded = Sata::try_from(&fragment).unwrap();
+
+    assert_eq!(fragment.codec(), SATA_CODEC);
+    assert_eq!(decoded.decode::<String>().unwrap(), "hello");
+}
+
+#[test]
+fn raw_fragment_is_not_a_sata() {
+    let fragment = DataFragment::new(b"hello".to_vec(), 1);
+
+    assert!(Sata::try_from(&fragment).is_err());
+}
+
+#[test]
+fn content_addressed_fragment_moves_to_a_new_cid_when_changed() {
+    let mut fragment = DataFragment::new(b"first".to_vec(), 1);
+    let first = fragment.cid().to_string();
+
+    fragment.set(b"second".to_vec(), 2);
+
+    assert_ne!(fragment.cid(), first);
+    assert_eq!(fragment.cid(), fragment.content_hash());
+    assert_eq!(fragment.timestamp(), 2);
+    assert!(fragment.verify());
+}
+
+#[test]
+fn identity_fragment_keeps_its_cid_when_changed() {
+    let mut fragment = DataFragment::new(b"first".to_vec(), 1).with_policy(CidPolicy::Identity);
+    let first = fragment.cid().to_string();
+
+    fragment.set(b"second".to_vec(), 2);
+
+    assert_eq!(fragment.cid(), first);
+    assert_eq!(
+        fragment.content_hash(),
+        DataFragment::new(b"second".to_vec(), 2).cid()
+    );
+    assert!(fragment.verify());
+}
+
+#[test]
+fn tampered_identity_fragment_fails_verification() {
+    let fragment = DataFragment::new(b"first".to_vec(), 1).with_policy(CidPolicy::Identity);
+    let mut tampered = serde_json::to_value(&fragment).unwrap();
+    tampered["data"] = serde_json::json!([1, 2, 3]);
+    let tampered: DataFragment = serde_json::from_value(tampered).unwrap();
+
+    assert!(!tampered.verify());
+}
+
+#[test]
+fn every_version_stays_readable() {
+    let mut fragment = DataFragment::new(b"hello world".to_vec(), 1);
+    fragment.set(b"hello there world".to_vec(), 2);
+    fragment.set(b"goodbye".to_vec(), 3);
+
+    assert_eq!(fragment.version(), 2);
+    assert_eq!(fragment.get_version(0), Some(b"hello world".to_vec()));
+    assert_eq!(fragment.get_version(1), Some(b"hello there world".to_vec()));
+    assert_eq!(fragment.get_version(2), Some(b"goodbye".to_vec()));
+    assert_eq!(fragment.get_version(3), None);
+}
+
+#[test]
+fn diff_between_versions_patches_one_into_the_other() {
+    let mut fragment = DataFragment::new(b"hello world".to_vec(), 1);
+    fragment.set(b"hello there world".to_vec(), 2);
+
+    let patch = fragment.diff(0, 1).unwrap();
+
+    assert_eq!(
+        patch.apply(b"hello world").unwrap(),
+        b"hello there world".to_vec()
+    );
+    assert!(fragment.diff(1, 1).unwrap().is_empty());
+    assert!(fragment.diff(0, 5).is_none());
+}
+
+#[test]
+fn patch_out_of_range_is_refused() {
+    let patch = Patch::between(b"a long piece of data", b"a long piece");
+
+    assert!(patch.apply(b"short").is_err());
+}
+
+#[test]
+fn revert_adds_a_version_with_the_old_data() {
+    let mut fragment = DataFragment::new(b"first".to_vec(), 1);
+    let first = fragment.cid().to_string();
+    fragment.set(b"second".to_vec(), 2);
+
+    fragment.revert(0, 3).unwrap();
+
+    assert_eq!(fragment.version(), 2);
+    assert_eq!(fragment.data(), b"first");
+    assert_eq!(fragment.cid(), first);
+    assert_eq!(fragment.get_version(1), Some(b"second".to_vec()));
+    assert!(fragment.revert(7, 4).is_err());
+}
+
+#[test]
+fn later_write_wins_on_every_replica() {
+    let base = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    let mut ours = base.clone();
+    ours.set(b"ours".to_vec(), 2);
+    let mut theirs = base.clone();
+    theirs.set(b"theirs".to_vec(), 3);
+
+    let mut left = ours.clone();
+    let mut right = theirs.clone();
+
+    assert!(left.merge(&theirs).unwrap());
+    assert!(!right.merge(&ours).unwrap());
+    assert_eq!(left.data(), right.data());
+    assert_eq!(left.data(), b"theirs");
+}
+
+#[test]
+fn concurrent_writes_with_the_same_timestamp_settle_on_one() {
+    let base = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    let mut ours = base.clone();
+    ours.set(b"ours".to_vec(), 2);
+    let mut theirs = base.clone();
+    theirs.set(b"theirs".to_vec(), 2);
+
+    let mut left = ours.clone();
+    let mut right = theirs.clone();
+    left.merge(&theirs).unwrap();
+    right.merge(&ours).unwrap();
+
+    assert_eq!(left.data(), right.data());
+}
+
+#[test]
+fn unrelated_fragment_is_not_merged() {
+    let mut fragment = DataFragment::new(b"one".to_vec(), 1);
+
+    assert!(fragment
+        .merge(&DataFragment::new(b"two".to_vec(), 2))
+        .is_err());
+}
+
+#[test]
+fn update_is_merged_into_a_replica_we_hold() {
+    let mut state = ConfluxState::default();
+    let base = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    state.add_fragment(base.clone()).unwrap();
+    let mut remote = base.clone();
+    remote.set(b"edited".to_vec(), 2);
+
+    assert!(state.merge(remote.clone()).unwrap());
+    assert!(!state.merge(remote).unwrap());
+    assert!(matches!(
+        state.respond(FragmentRequest::Update(base.clone())),
+        FragmentResponse::Have(x) if x.data() == b"edited"
+    ));
+}
+
+#[test]
+fn update_for_a_fragment_we_dont_hold_is_ignored() {
+    let mut state = ConfluxState::default();
+    let fragment = DataFragment::new(b"elsewhere".to_vec(), 1);
+
+    assert!(!state.merge(fragment.clone()).unwrap());
+    assert!(matches!(
+        state.respond(FragmentRequest::Update(fragment)),
+        FragmentResponse::DontHave
+    ));
+}
+
+#[test]
+fn answer_to_a_pushed_update_is_merged_back() {
+    let mut state = ConfluxState::default();
+    let mut behaviour = new_behaviour();
+    let base = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    state.add_fragment(base.clone()).unwrap();
+    let request_id =
+        behaviour.send_request(&PeerId::random(), FragmentRequest::Update(base.clone()));
+    state.pushed(request_id);
+    let mut newer = base;
+    newer.set(b"newer".to_vec(), 5);
+
+    assert!(state.received(&request_id, newer.clone()));
+    assert_eq!(state.fragment(newer.cid()), Some(newer));
+}
+
+#[tokio::test]
+async fn update_is_stored_and_pushed() {
+    let (conflux, mut commands) = conflux();
+    let fragment = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+
+    conflux.update(fragment.clone()).await.unwrap();
+
+    assert!(matches!(commands.recv().await, Some(BlinkCommand::PushFragment(x)) if x == fragment));
+    assert_eq!(conflux.list().await, Ok(vec![fragment.cid().to_string()]));
+}
+
+#[tokio::test]
+async fn watcher_sees_local_and_remote_changes() {
+    let (commands, _receiver) = channel(8);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state.clone(), commands, Arc::new(SystemClock));
+    let base = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    let mut watch = conflux.watch(base.cid());
+    let mut remote = base.clone();
+    remote.set(b"edited".to_vec(), 2);
+
+    conflux.add(base.clone()).await.unwrap();
+    state.write().merge(remote.clone()).unwrap();
+    conflux.remove(base.cid()).await.unwrap();
+
+    assert_eq!(watch.next().await, Some(FragmentUpdate::Local(base)));
+    assert!(matches!(watch.next().await, Some(FragmentUpdate::Remote(x)) if x.data() == b"edited"));
+    assert_eq!(watch.next().await, Some(FragmentUpdate::Removed));
+}
+
+#[tokio::test]
+async fn fetched_fragment_reaches_its_watchers() {
+    let mut state = ConfluxState::default();
+    let mut behaviour = new_behaviour();
+    let fragment = DataFragment::new(b"far away".to_vec(), 1);
+    let mut watch = state.watch(fragment.cid().to_string());
+    let _want = state.want(fragment.cid().to_string());
+    let request_id = ask(&mut state, &mut behaviour, fragment.cid());
+
+    state.received(&request_id, fragment.clone());
+
+    assert_eq!(watch.recv().await, Some(FragmentUpdate::Remote(fragment)));
+}
+
+#[test]
+fn fragment_survives_dag_cbor_and_dag_json() {
+    let mut fragment = DataFragment::new(b"first".to_vec(), 1).with_policy(CidPolicy::Identity);
+    fragment.set(b"second".to_vec(), 2);
+
+    for codec in [IpldCodec::DagCbor, IpldCodec::DagJson] {
+        let encoded = fragment.encode(codec).unwrap();
+
+        assert_eq!(DataFragment::decode(codec, &encoded).unwrap(), fragment);
+    }
+}
+
+#[test]
+fn fragment_is_not_encoded_as_raw() {
+    let fragment = DataFragment::new(b"raw".to_vec(), 1);
+
+    assert!(fragment.encode(IpldCodec::Raw).is_err());
+}
+
+#[test]
+fn updates_go_to_the_peers_interested_in_a_fragment_we_hold() {
+    let mut state = ConfluxState::default();
+    let fragment = DataFragment::new(b"shared".to_vec(), 1);
+    let elsewhere = DataFragment::new(b"elsewhere".to_vec(), 1);
+    state.add_fragment(fragment.clone()).unwrap();
+    let peer = PeerId::random();
+
+    state.interest(fragment.cid(), peer);
+    state.interest(elsewhere.cid(), PeerId::random());
+
+    assert_eq!(state.interested(fragment.cid()), vec![peer]);
+    assert!(state.interested(elsewhere.cid()).is_empty());
+
+    state.remove_fragment(fragment.cid()).unwrap();
+    assert!(state.interested(fragment.cid()).is_empty());
+}
@@ -0,0 +1,135 @@
+use crate::conversations::{message_id, ConversationStore, StoredMessage};
+use crate::expiry::Expiry;
+use crate::mailbox::Mailbox;
+use crate::publishing::Unpublished;
+use crate::test_support::{temp_path, text};
+use crate::wire::WireFormat;
+use libp2p::PeerId;
+
+#[test]
+fn messages_expire_only_once_their_time_is_up() {
+    let mut expiry = Expiry::default();
+    let message = text("gone soon");
+    let id = message_id(&message);
+
+    assert!(expiry.schedule(id.clone(), 1_000).unwrap());
+    assert_eq!(expiry.expires_at(&message), Some(1_000));
+    assert_eq!(expiry.expires_at(&text("here to stay")), None);
+
+    assert!(expiry.due(999).unwrap().is_empty());
+    assert_eq!(expiry.due(1_000).unwrap(), vec![id]);
+    assert!(expiry.due(1_000).unwrap().is_empty());
+    assert_eq!(expiry.expires_at(&message), None);
+}
+
+#[test]
+fn the_earlier_expiry_wins() {
+    let mut expiry = Expiry::default();
+    let message = text("gone soon");
+    let id = message_id(&message);
+
+    assert!(expiry.schedule(id.clone(), 2_000).unwrap());
+    assert!(!expiry.schedule(id.clone(), 3_000).unwrap());
+    assert!(expiry.schedule(id.clone(), 1_000).unwrap());
+
+    assert_eq!(expiry.expires_at(&message), Some(1_000));
+    assert_eq!(expiry.due(1_000).unwrap(), vec![id]);
+    assert!(expiry.due(2_000).unwrap().is_empty());
+}
+
+#[test]
+fn messages_expire_in_the_order_of_their_expiry() {
+    let mut expiry = Expiry::default();
+    expiry.schedule("late".into(), 3_000).unwrap();
+    expiry.schedule("early".into(), 1_000).unwrap();
+    expiry.schedule("middle".into(), 2_000).unwrap();
+
+    assert_eq!(expiry.due(2_500).unwrap(), vec!["early", "middle"]);
+    assert_eq!(expiry.due(u64::MAX).unwrap(), vec!["late"]);
+}
+
+#[test]
+fn the_schedule_survives_a_restart() {
+    let path = temp_path("restart", "expiry");
+    let message = text("gone soon");
+    {
+        let mut expiry = Expiry::default();
+        expiry.open(&path).unwrap();
+        expiry.schedule(message_id(&message), 1_000).unwrap();
+    }
+
+    let mut expiry = Expiry::default();
+    expiry.open(&path).unwrap();
+
+    assert_eq!(expiry.expires_at(&message), Some(1_000));
+    assert_eq!(expiry.due(1_000).unwrap(), vec![message_id(&message)]);
+    let mut reopened = Expiry::default();
+    reopened.open(&path).unwrap();
+    assert_eq!(reopened.expires_at(&message), None);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn mailboxes_replay_messages_with_their_expiry() {
+    let mut mailbox = Mailbox::default();
+    mailbox.set_enabled(true);
+    mailbox.watch(PeerId::random(), "did:key:bob".into(), "ab".into(), true);
+    let format = WireFormat::default();
+    for (received_at, body, expires_at) in [(10, "gone soon", Some(5_000)), (20, "kept", None)] {
+        let message = text(body);
+        let envelope = format.seal_expiring(&message, expires_at).unwrap();
+        mailbox.store(
+            "ab".into(),
+            received_at,
+            message_id(&message),
+            envelope,
+            expires_at,
+        );
+    }
+    let replay = |now| {
+        let (replayed, _) =
+            mailbox.missed_since("did:key:bob", &["ab".to_string()], 0, 0, now, u64::MAX);
+        replayed
+            .iter()
+            .map(|(_, x)| format.open_expiring(x).unwrap().1)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(replay(20), vec![Some(5_000), None]);
+    assert_eq!(replay(5_000), vec![None]);
+}
+
+#[test]
+fn expired_messages_leave_history_on_every_topic() {
+    let mut store = ConversationStore::default();
+    let message = text("gone soon");
+    for topic in ["ab", "ac"] {
+        store.record(StoredMessage::new(
+            "a".into(),
+            topic.into(),
+            1,
+            message.clone(),
+        ));
+    }
+    store.record(StoredMessage::new("a".into(), "ab".into(), 2, text("kept")));
+
+    assert!(store.remove(&message_id(&message)));
+
+    assert_eq!(store.history("ab", .., 10).len(), 1);
+    assert!(store.history("ac", .., 10).is_empty());
+    assert!(store.record(StoredMessage::new("a".into(), "ab".into(), 3, message)));
+}
+
+#[test]
+fn expired_messages_are_never_published() {
+    let mut unpublished = Unpublished::default();
+    let message = text("gone soon");
+    unpublished.defer("ab".into(), message.clone());
+    unpublished.defer("ab".into(), text("kept"));
+
+    unpublished.forget(&message_id(&message));
+
+    let left = unpublished.take("ab");
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].decode::<String>().unwrap(), "kept");
+}
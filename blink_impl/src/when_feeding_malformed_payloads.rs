@@ -0,0 +1,110 @@
+use crate::fragment_tree::{reassemble, FragmentTree, TREE_CODEC};
+use crate::fragments::DataFragment;
+use crate::wire::{
+    open_envelope, open_envelopes, parse_envelope, seal_envelope, BincodeWireCodec,
+    DagCborWireCodec, WireCodec, WIRE_VERSION,
+};
+use anyhow::anyhow;
+use proptest::prelude::*;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::collections::HashMap;
+
+// Valid headers with whatever follows, so the codecs get exercised and not just the header check
+fn envelope() -> impl Strategy<Value = Vec<u8>> {
+    (
+        0..=WIRE_VERSION + 1,
+        any::<u8>(),
+        prop::collection::vec(any::<u8>(), 0..512),
+    )
+        .prop_map(|(version, codec, payload)| {
+            let mut bytes = vec![b'B', b'L', version, codec];
+            bytes.extend(payload);
+            bytes
+        })
+}
+
+fn codec() -> impl Strategy<Value = Box<dyn WireCodec>> {
+    prop_oneof![
+        Just(Box::new(BincodeWireCodec) as Box<dyn WireCodec>),
+        Just(Box::new(DagCborWireCodec) as Box<dyn WireCodec>),
+    ]
+}
+
+fn message() -> impl Strategy<Value = String> {
+    ".{0,256}"
+}
+
+fn store(tree: &FragmentTree) -> HashMap<String, DataFragment> {
+    tree.fragments()
+        .iter()
+        .map(|x| (x.cid().to_string(), x.clone()))
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn random_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..1024)) {
+        let _ = open_envelope(&bytes);
+    }
+
+    #[test]
+    fn random_payloads_in_a_valid_envelope_never_panic(bytes in envelope()) {
+        let _ = parse_envelope(&bytes);
+        let _ = open_envelope(&bytes);
+        let _ = open_envelopes(&bytes);
+    }
+
+    #[test]
+    fn sealed_messages_open_unchanged(codec in codec(), body in message()) {
+        let sata = Sata::default()
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, &body)
+            .unwrap();
+
+        let bytes = seal_envelope(&*codec, &sata).unwrap();
+
+        let opened = open_envelope(&bytes).unwrap();
+        prop_assert_eq!(opened.decode::<String>().unwrap(), body);
+    }
+
+    #[test]
+    fn truncated_envelopes_are_refused_without_panicking(body in message(), cut in any::<prop::sample::Index>()) {
+        let sata = Sata::default()
+            .encode(IpldCodec::DagCbor, Kind::Dynamic, &body)
+            .unwrap();
+        let bytes = seal_envelope(&BincodeWireCodec, &sata).unwrap();
+
+        let _ = open_envelope(&bytes[..cut.index(bytes.len())]);
+    }
+
+    #[test]
+    fn split_blobs_reassemble_unchanged(data in prop::collection::vec(any::<u8>(), 0..4096), chunk_size in 1usize..512) {
+        let tree = FragmentTree::split_with(&data, chunk_size, 0).unwrap();
+        let fragments = store(&tree);
+
+        let blob = reassemble(tree.root(), |cid| {
+            fragments.get(cid).cloned().ok_or_else(|| anyhow!("Missing {}", cid))
+        });
+
+        prop_assert_eq!(blob.unwrap(), data);
+    }
+
+    #[test]
+    fn random_tree_nodes_never_panic(node in prop::collection::vec(any::<u8>(), 0..512)) {
+        let root = DataFragment::with_codec(node, TREE_CODEC, 0);
+
+        let _ = reassemble(&root, |cid| Err(anyhow!("Missing {}", cid)));
+    }
+}
+
+#[test]
+fn a_tree_linking_one_subtree_over_and_over_is_refused() {
+    let chunk = DataFragment::new(vec![1], 0);
+    let links = vec![chunk.cid().to_string(); 1000];
+    // Claims a single byte, but would take a thousand
+    let node = bincode::serialize(&(1u64, links)).unwrap();
+    let root = DataFragment::with_codec(node, TREE_CODEC, 0);
+
+    let blob = reassemble(&root, |_| Ok(chunk.clone()));
+
+    assert!(blob.is_err());
+}
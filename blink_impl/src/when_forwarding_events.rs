@@ -0,0 +1,54 @@
+use crate::event_category::EventCategory;
+use crate::event_forwarder::{sign, EventForwarder, ForwardTarget};
+use blink_contract::{Event, EventBus};
+use std::time::Duration;
+use tokio::{io::AsyncReadExt, net::UnixListener};
+
+#[tokio::test]
+async fn selected_events_arrive_signed_in_one_batch() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("blink_events_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let key = b"secret".to_vec();
+    let mut forwarder = EventForwarder::new(
+        ForwardTarget::Unix(path.clone()),
+        key.clone(),
+        vec![EventCategory::Streams],
+    );
+    forwarder.event_occurred(Event::StreamClosed(7));
+    forwarder.event_occurred(Event::PeerIdentified);
+    forwarder.event_occurred(Event::StreamRejected(8));
+
+    let (mut stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut line = String::new();
+    stream.read_to_string(&mut line).await.unwrap();
+    let (signature, body) = line.trim_end().split_once(' ').unwrap();
+
+    assert_eq!(signature, sign(&key, body.as_bytes()));
+    let batch: serde_json::Value = serde_json::from_str(body).unwrap();
+    let names: Vec<&str> = batch["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["StreamClosed", "StreamRejected"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn every_stream_event_is_in_the_streams_category() {
+    assert_eq!(
+        EventCategory::of(&Event::GroupStreamAdded(1, String::new(), 2)),
+        EventCategory::Streams
+    );
+    assert_eq!(
+        EventCategory::of(&Event::TransactionFailed(1, String::new())),
+        EventCategory::Transactions
+    );
+}
@@ -0,0 +1,72 @@
+use crate::group_calls::{GroupRegistry, Uplink};
+use blink_contract::StreamKind;
+use libp2p::PeerId;
+
+fn audio_from(peer: PeerId) -> Uplink {
+    Uplink::new(peer, StreamKind::Audio, "opus".to_string(), None)
+}
+
+#[test]
+fn joining_member_receives_everyone_already_in_the_call() {
+    let local = PeerId::random();
+    let first = PeerId::random();
+    let second = PeerId::random();
+    let mut groups = GroupRegistry::default();
+    groups.host(1);
+
+    assert!(groups
+        .add_uplink(1, 10, local, audio_from(first))
+        .is_empty());
+    let downlinks = groups.add_uplink(1, 20, local, audio_from(second));
+
+    assert_eq!(downlinks.len(), 2);
+    assert!(downlinks
+        .iter()
+        .any(|x| x.peer == first && x.uplink == 20 && x.origin == second));
+    assert!(downlinks
+        .iter()
+        .any(|x| x.peer == second && x.uplink == 10 && x.origin == first));
+}
+
+#[test]
+fn forwarder_media_is_relayed_but_never_sent_back_to_it() {
+    let local = PeerId::random();
+    let member = PeerId::random();
+    let mut groups = GroupRegistry::default();
+    groups.host(1);
+
+    groups.add_uplink(1, 10, local, audio_from(local));
+    let downlinks = groups.add_uplink(1, 20, local, audio_from(member));
+
+    assert_eq!(downlinks.len(), 1);
+    assert_eq!(downlinks[0].peer, member);
+    assert_eq!(groups.source_of(downlinks[0].id), Some((10, local)));
+}
+
+#[test]
+fn member_leaving_closes_what_was_relayed_from_and_to_it() {
+    let local = PeerId::random();
+    let first = PeerId::random();
+    let second = PeerId::random();
+    let mut groups = GroupRegistry::default();
+    groups.host(1);
+    groups.add_uplink(1, 10, local, audio_from(first));
+    groups.add_uplink(1, 20, local, audio_from(second));
+
+    let (group, closed) = groups.remove_uplink(20).unwrap();
+
+    assert_eq!(group, 1);
+    assert_eq!(closed.len(), 2);
+    assert!(groups.downlinks_of(10).is_empty());
+    assert_eq!(groups.members(1), vec![first]);
+}
+
+#[test]
+fn streams_for_calls_we_dont_host_are_ignored() {
+    let mut groups = GroupRegistry::default();
+
+    let downlinks = groups.add_uplink(1, 10, PeerId::random(), audio_from(PeerId::random()));
+
+    assert!(downlinks.is_empty());
+    assert_eq!(groups.group_of_uplink(10), None);
+}
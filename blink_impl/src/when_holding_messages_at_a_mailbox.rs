@@ -0,0 +1,133 @@
+use crate::conversations::message_id;
+use crate::mailbox::{Mailbox, HOLD_FOR, TOPIC_CAP};
+use crate::test_support::text;
+use crate::wire::WireFormat;
+use libp2p::PeerId;
+
+const BOB: &str = "did:key:bob";
+const TOPIC: &str = "ab";
+
+// A mailbox watching the topic for bob
+fn watching() -> Mailbox {
+    let mut mailbox = Mailbox::default();
+    mailbox.set_enabled(true);
+    mailbox.watch(PeerId::random(), BOB.into(), TOPIC.into(), true);
+    mailbox
+}
+
+fn store(mailbox: &mut Mailbox, received_at: u64, body: &str) {
+    let message = text(body);
+    let envelope = WireFormat::default().seal(&message).unwrap();
+    mailbox.store(
+        TOPIC.into(),
+        received_at,
+        message_id(&message),
+        envelope,
+        None,
+    );
+}
+
+fn body(envelope: &[u8]) -> String {
+    let message = WireFormat::default().open(envelope).unwrap();
+    message.decode::<String>().unwrap()
+}
+
+fn replayed(mailbox: &Mailbox, did: &str, now: u64) -> Vec<String> {
+    let (messages, next) = mailbox.missed_since(did, &[TOPIC.to_string()], 0, 0, now, u64::MAX);
+    assert_eq!(next, None);
+    messages.iter().map(|(_, x)| body(x)).collect()
+}
+
+#[test]
+fn a_topic_is_watched_once_the_watcher_is_seen_subscribed() {
+    let mut mailbox = Mailbox::default();
+    mailbox.set_enabled(true);
+    let peer = PeerId::random();
+
+    assert!(!mailbox.watch(peer, BOB.into(), TOPIC.into(), false));
+    assert!(!mailbox.is_watching(TOPIC));
+
+    assert!(mailbox.subscribed(&peer, TOPIC));
+    assert!(mailbox.is_watching(TOPIC));
+}
+
+#[test]
+fn other_peers_subscribing_start_no_watch() {
+    let mut mailbox = Mailbox::default();
+    mailbox.set_enabled(true);
+    mailbox.watch(PeerId::random(), BOB.into(), TOPIC.into(), false);
+
+    assert!(!mailbox.subscribed(&PeerId::random(), TOPIC));
+    assert!(!mailbox.is_watching(TOPIC));
+}
+
+#[test]
+fn watches_asked_for_by_peers_that_left_are_dropped() {
+    let mut mailbox = Mailbox::default();
+    mailbox.set_enabled(true);
+    let peer = PeerId::random();
+    mailbox.watch(peer, BOB.into(), TOPIC.into(), false);
+
+    mailbox.disconnected(&peer);
+
+    assert!(!mailbox.subscribed(&peer, TOPIC));
+}
+
+#[test]
+fn only_the_watching_did_gets_the_messages_back() {
+    let mut mailbox = watching();
+    store(&mut mailbox, 10, "hi bob");
+
+    assert_eq!(replayed(&mailbox, BOB, 20), vec!["hi bob"]);
+    assert!(replayed(&mailbox, "did:key:mallory", 20).is_empty());
+}
+
+#[test]
+fn a_topic_holds_at_most_its_cap() {
+    let mut mailbox = watching();
+    for x in 0..TOPIC_CAP + 10 {
+        store(&mut mailbox, 10, &x.to_string());
+    }
+
+    let replayed = replayed(&mailbox, BOB, 20);
+
+    assert_eq!(replayed.len(), TOPIC_CAP);
+    assert_eq!(replayed[0], "10");
+}
+
+#[test]
+fn messages_are_held_for_a_limited_time() {
+    let mut mailbox = watching();
+    store(&mut mailbox, 10, "old");
+    store(&mut mailbox, 20, "new");
+
+    assert_eq!(replayed(&mailbox, BOB, 10 + HOLD_FOR), vec!["new"]);
+
+    store(&mut mailbox, 10 + HOLD_FOR, "newest");
+    assert_eq!(replayed(&mailbox, BOB, 0), vec!["new", "newest"]);
+}
+
+#[test]
+fn the_replay_comes_in_pages() {
+    let mut mailbox = watching();
+    for x in 0..10 {
+        store(&mut mailbox, 10, &format!("message {}", x));
+    }
+    let envelope = WireFormat::default().seal(&text("message 0")).unwrap();
+    let size = bincode::serialized_size(&(TOPIC.to_string(), envelope)).unwrap();
+
+    let mut received = Vec::new();
+    let mut pages = 0;
+    let mut after = Some(0);
+    while let Some(start) = after {
+        let (messages, next) =
+            mailbox.missed_since(BOB, &[TOPIC.to_string()], 0, start, 20, size * 3);
+        received.extend(messages.iter().map(|(_, x)| body(x)));
+        pages += 1;
+        after = next;
+    }
+
+    let expected: Vec<String> = (0..10).map(|x| format!("message {}", x)).collect();
+    assert_eq!(received, expected);
+    assert_eq!(pages, 4);
+}
@@ -0,0 +1,134 @@
+use crate::clock::SystemClock;
+use crate::config::BlinkConfig;
+use crate::keystore::InMemoryKeystore;
+use crate::node::BlinkNode;
+use crate::test_support::did;
+use crate::when_using_peer_to_peer_service::{MultiPassImpl, TestCache};
+use blink_contract::{BlinkError, Event};
+use libp2p::Multiaddr;
+use std::collections::HashMap;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::UnboundedReceiver;
+use warp::crypto::DID;
+use warp::sync::RwLock;
+
+const TIMEOUT_SECS: u64 = 5;
+
+fn node() -> (BlinkNode, UnboundedReceiver<(String, Event)>) {
+    let config = BlinkConfig {
+        listen_addrs: vec!["/memory/0".parse().unwrap()],
+        mdns: false,
+        ..Default::default()
+    };
+    let (node, events, _) = BlinkNode::new(config, Arc::new(SystemClock));
+    (node, events)
+}
+
+async fn add_identity(node: &mut BlinkNode, did: Arc<DID>) -> Result<DID, BlinkError> {
+    node.add_identity(
+        Arc::new(InMemoryKeystore::new(did).unwrap()),
+        Arc::new(RwLock::new(TestCache::default())),
+        Arc::new(RwLock::new(MultiPassImpl::new(true))),
+    )
+    .await
+}
+
+// Listen address of every identity, as the node reported them
+async fn listen_addrs(
+    events: &mut UnboundedReceiver<(String, Event)>,
+    count: usize,
+) -> HashMap<String, Multiaddr> {
+    let mut addrs = HashMap::new();
+    while addrs.len() < count {
+        if let Some((did, Event::NewListenAddr(addr))) = events.recv().await {
+            addrs.insert(did, addr);
+        }
+    }
+    addrs
+}
+
+#[tokio::test]
+async fn every_identity_reports_events_under_its_own_did() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut node, mut events) = node();
+        let alice = add_identity(&mut node, Arc::new(did())).await.unwrap();
+        let bob = add_identity(&mut node, Arc::new(did())).await.unwrap();
+
+        let addrs = listen_addrs(&mut events, 2).await;
+
+        assert!(addrs.contains_key(&alice.to_string()));
+        assert!(addrs.contains_key(&bob.to_string()));
+        assert_ne!(addrs[&alice.to_string()], addrs[&bob.to_string()]);
+        node.shutdown().await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn an_identity_is_hosted_once() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut node, _events) = node();
+        let did = Arc::new(did());
+        add_identity(&mut node, did.clone()).await.unwrap();
+
+        let again = add_identity(&mut node, did).await;
+
+        assert!(matches!(again, Err(BlinkError::Invalid(_))));
+        assert_eq!(node.identities().len(), 1);
+        node.shutdown().await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn hosted_identities_pair_with_each_other() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut node, mut events) = node();
+        let alice = add_identity(&mut node, Arc::new(did())).await.unwrap();
+        let bob = add_identity(&mut node, Arc::new(did())).await.unwrap();
+        let addrs = listen_addrs(&mut events, 2).await;
+
+        node.service(&alice)
+            .unwrap()
+            .lock()
+            .await
+            .pair_to_another_peer(addrs[&bob.to_string()].clone().into())
+            .await
+            .unwrap();
+
+        let mut paired = Vec::new();
+        while paired.len() < 2 {
+            if let Some((did, Event::GeneratedTopic(with, _))) = events.recv().await {
+                paired.push((did, with.to_string()));
+            }
+        }
+        assert!(paired.contains(&(alice.to_string(), bob.to_string())));
+        assert!(paired.contains(&(bob.to_string(), alice.to_string())));
+        node.shutdown().await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn removed_identity_is_stopped() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut node, _events) = node();
+        let did = add_identity(&mut node, Arc::new(did())).await.unwrap();
+        let service = node.service(&did).unwrap();
+
+        node.remove_identity(&did).await.unwrap();
+
+        assert!(node.service(&did).is_none());
+        assert!(node.identities().is_empty());
+        service.lock().await.wait_for_shutdown().await;
+        assert!(matches!(
+            node.remove_identity(&did).await,
+            Err(BlinkError::NotFound(_))
+        ));
+    })
+    .await
+    .expect("Timeout");
+}
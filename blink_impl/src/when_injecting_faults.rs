@@ -0,0 +1,43 @@
+use crate::chaos::ChaosHandle;
+use std::time::Duration;
+
+#[test]
+fn each_fault_fires_only_as_often_as_requested() {
+    let chaos = ChaosHandle::default();
+    chaos.drop_next_publishes(2);
+
+    assert!(chaos.take_dropped_publish());
+    assert!(chaos.take_dropped_publish());
+    assert!(!chaos.take_dropped_publish());
+    assert!(!chaos.take_failed_cache_write());
+}
+
+#[test]
+fn reset_clears_every_fault() {
+    let chaos = ChaosHandle::default();
+    chaos.fail_next_cache_writes(1);
+    chaos.delay_identify(Duration::from_secs(1));
+
+    chaos.reset();
+
+    assert!(!chaos.take_failed_cache_write());
+    assert_eq!(chaos.identify_delay(), None);
+}
+
+#[test]
+fn reset_sends_publishes_without_delay_again() {
+    let chaos = ChaosHandle::default();
+    chaos.delay_publishes(Duration::from_millis(200));
+    assert_eq!(chaos.publish_delay(), Some(Duration::from_millis(200)));
+
+    chaos.reset();
+
+    assert_eq!(chaos.publish_delay(), None);
+}
+
+#[test]
+fn link_faults_need_a_running_service() {
+    let chaos = ChaosHandle::default();
+
+    assert!(chaos.cut_link(libp2p::PeerId::random()).is_err());
+}
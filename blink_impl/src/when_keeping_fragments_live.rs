@@ -0,0 +1,84 @@
+use crate::clock::SystemClock;
+use crate::conflux::{Conflux, ConfluxError, ConfluxState, FragmentUpdate};
+use crate::fragments::{CidPolicy, DataFragment};
+use libp2p::futures::{SinkExt, StreamExt};
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+use warp::sync::RwLock;
+
+async fn conflux_with(fragment: &DataFragment) -> (Conflux, Arc<RwLock<ConfluxState>>) {
+    let (commands, mut receiver) = channel(8);
+    // Pushes to peers go nowhere
+    tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state.clone(), commands, Arc::new(SystemClock));
+    conflux.add(fragment.clone()).await.unwrap();
+    (conflux, state)
+}
+
+fn shared_fragment() -> DataFragment {
+    DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity)
+}
+
+#[tokio::test]
+async fn live_fragment_streams_local_and_remote_versions() {
+    let fragment = shared_fragment();
+    let (conflux, state) = conflux_with(&fragment).await;
+    let mut live = conflux.live(fragment.cid()).await.unwrap();
+    let mut remote = fragment.clone();
+    remote.set(b"remote".to_vec(), u64::MAX);
+
+    let written = live.write(b"local".to_vec()).await.unwrap();
+    state.write().merge(remote.clone()).unwrap();
+
+    assert_eq!(live.next().await, Some(FragmentUpdate::Alive(true)));
+    assert_eq!(live.next().await, Some(FragmentUpdate::Local(written)));
+    assert!(matches!(live.next().await, Some(FragmentUpdate::Remote(x)) if x.data() == b"remote"));
+}
+
+#[tokio::test]
+async fn data_sent_into_the_sink_becomes_a_version() {
+    let fragment = shared_fragment();
+    let (conflux, _state) = conflux_with(&fragment).await;
+    let mut live = conflux.live(fragment.cid()).await.unwrap();
+
+    live.sink().unwrap().send(b"typed".to_vec()).await.unwrap();
+
+    assert_eq!(live.next().await, Some(FragmentUpdate::Alive(true)));
+    assert!(
+        matches!(live.next().await, Some(FragmentUpdate::Local(x)) if x.data() == b"typed" && x.cid() == fragment.cid())
+    );
+}
+
+#[tokio::test]
+async fn killed_fragment_ends_its_stream_until_woken_up() {
+    let fragment = shared_fragment();
+    let (conflux, _state) = conflux_with(&fragment).await;
+    let mut watch = conflux.watch(fragment.cid());
+    let mut live = conflux.live(fragment.cid()).await.unwrap();
+
+    live.kill();
+
+    assert_eq!(live.next().await, None);
+    assert!(live.sink().is_none());
+    assert_eq!(
+        live.write(b"late".to_vec()).await,
+        Err(ConfluxError::NotLive(fragment.cid().to_string()))
+    );
+    live.wake();
+    assert!(live.write(b"back".to_vec()).await.is_ok());
+    assert_eq!(watch.next().await, Some(FragmentUpdate::Alive(true)));
+    assert_eq!(watch.next().await, Some(FragmentUpdate::Alive(false)));
+    assert_eq!(watch.next().await, Some(FragmentUpdate::Alive(true)));
+}
+
+#[tokio::test]
+async fn content_addressed_fragment_cant_be_live() {
+    let fragment = DataFragment::new(b"fixed".to_vec(), 1);
+    let (conflux, _state) = conflux_with(&fragment).await;
+
+    assert!(matches!(
+        conflux.live(fragment.cid()).await,
+        Err(ConfluxError::NotLive(_))
+    ));
+}
@@ -0,0 +1,45 @@
+use crate::moderation::ModerationStore;
+use crate::test_support::temp_path;
+
+#[test]
+fn decisions_survive_a_restart() {
+    let path = temp_path("restart", "moderation");
+    {
+        let mut store = ModerationStore::default();
+        store.open(&path).unwrap();
+        store.set_blocked("did:key:spam", true, 1).unwrap();
+        store.set_banned(7, "did:key:troll", true, 1).unwrap();
+    }
+
+    let mut store = ModerationStore::default();
+    store.open(&path).unwrap();
+
+    assert!(store.is_blocked("did:key:spam"));
+    assert!(store.is_banned(7, "did:key:troll"));
+    assert!(!store.is_banned(8, "did:key:troll"));
+}
+
+#[test]
+fn newest_decision_wins_when_devices_sync() {
+    let mut phone = ModerationStore::default();
+    let mut laptop = ModerationStore::default();
+    phone.set_blocked("did:key:friend", true, 1).unwrap();
+    laptop.merge(phone.records()).unwrap();
+    laptop.set_blocked("did:key:friend", false, 2).unwrap();
+    phone.set_quarantined("did:key:stranger", true, 3).unwrap();
+
+    phone.merge(laptop.records()).unwrap();
+    laptop.merge(phone.records()).unwrap();
+
+    assert!(!phone.is_blocked("did:key:friend"));
+    assert!(laptop.is_quarantined("did:key:stranger"));
+}
+
+#[test]
+fn repeating_a_decision_changes_nothing() {
+    let mut store = ModerationStore::default();
+
+    assert!(store.set_quarantined("did:key:spam", true, 1).unwrap());
+    assert!(!store.set_quarantined("did:key:spam", true, 2).unwrap());
+    assert_eq!(store.quarantined(), vec!["did:key:spam".to_string()]);
+}
@@ -0,0 +1,37 @@
+use crate::mutes::{Conversation, Mutes};
+use crate::test_support::did;
+
+#[test]
+fn messages_from_a_muted_did_are_muted_on_any_topic() {
+    let mut mutes = Mutes::default();
+    let sender = did();
+    mutes.mute(Conversation::from(&sender));
+
+    assert!(mutes.is_muted(&["pairwise"], Some(&sender.to_string())));
+    assert!(mutes.is_muted(&["pairwise/calls", "pairwise"], Some(&sender.to_string())));
+    assert!(!mutes.is_muted(&["pairwise"], Some(&did().to_string())));
+    assert!(!mutes.is_muted(&["pairwise"], None));
+}
+
+#[test]
+fn muting_a_pairwise_topic_mutes_its_channels() {
+    let mut mutes = Mutes::default();
+    mutes.mute(Conversation::Topic("pairwise".into()));
+
+    assert!(mutes.is_muted(&["pairwise/calls", "pairwise"], None));
+    assert!(!mutes.is_muted(&["group"], None));
+}
+
+#[test]
+fn unmuting_restores_delivery() {
+    let mut mutes = Mutes::default();
+    let group = Conversation::Topic("group".into());
+
+    assert!(mutes.mute(group.clone()));
+    assert!(!mutes.mute(group.clone()));
+    assert_eq!(mutes.muted(), vec![group.clone()]);
+    assert!(mutes.unmute(&group));
+
+    assert!(!mutes.is_muted(&["group"], None));
+    assert!(mutes.muted().is_empty());
+}
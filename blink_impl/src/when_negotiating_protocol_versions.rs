@@ -0,0 +1,20 @@
+use crate::version::{is_compatible, PROTOCOL_VERSION};
+
+#[test]
+fn peers_on_the_same_major_version_are_compatible() {
+    assert!(is_compatible(PROTOCOL_VERSION));
+    assert!(is_compatible("/blink/1.4.2"));
+}
+
+#[test]
+fn another_major_version_is_incompatible() {
+    assert!(!is_compatible("/blink/2.0.0"));
+    assert!(!is_compatible("/blink/0.9.0"));
+}
+
+#[test]
+fn peers_that_dont_advertise_a_blink_version_are_incompatible() {
+    assert!(!is_compatible("/ipfs/0.1.0"));
+    assert!(!is_compatible("/blink/latest"));
+    assert!(!is_compatible(""));
+}
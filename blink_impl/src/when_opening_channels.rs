@@ -0,0 +1,42 @@
+use crate::channels::ChannelRegistry;
+
+#[test]
+fn channel_opened_everywhere_is_open_on_every_conversation() {
+    let mut channels = ChannelRegistry::default();
+
+    assert!(channels.open(None, "signals"));
+    assert!(!channels.open(None, "signals"));
+
+    assert!(channels.is_open("did:key:bob", "signals"));
+    assert!(channels.is_open("did:key:carol", "signals"));
+    assert!(!channels.is_open("did:key:bob", "fragments"));
+}
+
+#[test]
+fn channel_opened_on_a_conversation_stays_there() {
+    let mut channels = ChannelRegistry::default();
+    channels.open(None, "signals");
+    channels.open(Some("did:key:bob"), "fragments");
+
+    assert_eq!(
+        channels.channels_of("did:key:bob"),
+        ["fragments", "signals"]
+    );
+    assert_eq!(channels.channels_of("did:key:carol"), ["signals"]);
+    assert!(channels.is_known("fragments"));
+}
+
+#[test]
+fn closing_everywhere_keeps_channels_opened_by_did() {
+    let mut channels = ChannelRegistry::default();
+    channels.open(None, "signals");
+    channels.open(Some("did:key:bob"), "signals");
+
+    assert!(channels.close(None, "signals"));
+    assert!(!channels.close(None, "signals"));
+
+    assert!(channels.is_open("did:key:bob", "signals"));
+    assert!(!channels.is_open("did:key:carol", "signals"));
+    assert!(channels.close(Some("did:key:bob"), "signals"));
+    assert!(!channels.is_known("signals"));
+}
@@ -0,0 +1,40 @@
+use crate::auto_pairing::AutoPairing;
+use crate::test_support::did;
+use sata::Sata;
+
+#[test]
+fn only_the_first_message_held_starts_pairing() {
+    let mut pairing = AutoPairing::default();
+    let recipient = did();
+
+    assert!(pairing.hold(recipient.clone(), Sata::default()));
+    assert!(!pairing.hold(recipient.clone(), Sata::default()));
+    assert!(pairing.hold(did(), Sata::default()));
+
+    assert_eq!(pairing.paired(&recipient.to_string()).len(), 2);
+    assert!(pairing.paired(&recipient.to_string()).is_empty());
+}
+
+#[test]
+fn expiring_gives_the_recipient_back_once() {
+    let mut pairing = AutoPairing::default();
+    let recipient = did();
+    pairing.hold(recipient.clone(), Sata::default());
+
+    let expired = pairing.expired(&recipient.to_string());
+
+    assert_eq!(expired.map(|x| x.to_string()), Some(recipient.to_string()));
+    assert!(pairing.expired(&recipient.to_string()).is_none());
+    assert!(pairing.hold(recipient, Sata::default()));
+}
+
+#[test]
+fn messages_are_held_only_while_pairing() {
+    let mut pairing = AutoPairing::default();
+    let recipient = did();
+    pairing.hold(recipient.clone(), Sata::default());
+
+    pairing.paired(&recipient.to_string());
+
+    assert!(pairing.expired(&recipient.to_string()).is_none());
+}
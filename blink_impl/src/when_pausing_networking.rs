@@ -0,0 +1,42 @@
+use crate::pause::{PauseMode, PauseState};
+use crate::testkit::TestNode;
+use blink_contract::Event;
+use libp2p::PeerId;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn resuming_brings_back_what_pausing_took() {
+    let mut pause = PauseState::default();
+    let peer = PeerId::random();
+    assert!(pause.resume().is_none());
+
+    assert!(pause.pause(true, vec![peer]));
+    assert!(!pause.pause(false, Vec::new()));
+
+    assert!(pause.is_paused());
+    assert_eq!(pause.resume(), Some((true, vec![peer])));
+    assert!(!pause.is_paused());
+}
+
+#[tokio::test]
+async fn a_paused_node_refuses_to_dial_until_resumed() {
+    let mut node = TestNode::start().await.unwrap();
+    let other = TestNode::start().await.unwrap();
+
+    node.service.pause(PauseMode::Disconnect).await.unwrap();
+    node.wait_for(|x| matches!(x, Event::NetworkingPaused), TIMEOUT)
+        .await
+        .unwrap();
+
+    assert!(node.service.is_paused());
+    let dial = other.address().clone().into();
+    assert!(node.service.pair_to_another_peer(dial).await.is_err());
+
+    node.service.resume().await.unwrap();
+    node.wait_for(|x| matches!(x, Event::NetworkingResumed), TIMEOUT)
+        .await
+        .unwrap();
+    assert!(!node.service.is_paused());
+}
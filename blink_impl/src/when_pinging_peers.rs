@@ -0,0 +1,65 @@
+use crate::peer_info::{PingTracker, UNRESPONSIVE_AFTER};
+use libp2p::PeerId;
+use std::time::Duration;
+
+#[test]
+fn the_first_ping_sets_the_round_trip_time() {
+    let mut pings = PingTracker::default();
+    let peer = PeerId::random();
+
+    pings.succeeded(&peer, Duration::from_millis(80));
+
+    let info = pings.info(&peer).unwrap();
+    assert_eq!(info.rtt_ms, Some(80.0));
+    assert_eq!(info.last_rtt_ms, Some(80));
+}
+
+#[test]
+fn later_pings_are_smoothed() {
+    let mut pings = PingTracker::default();
+    let peer = PeerId::random();
+    pings.succeeded(&peer, Duration::from_millis(80));
+
+    pings.succeeded(&peer, Duration::from_millis(160));
+
+    let info = pings.info(&peer).unwrap();
+    assert_eq!(info.rtt_ms, Some(90.0));
+    assert_eq!(info.last_rtt_ms, Some(160));
+}
+
+#[test]
+fn a_peer_is_reported_unresponsive_once_per_streak_of_failures() {
+    let mut pings = PingTracker::default();
+    let peer = PeerId::random();
+
+    let reports: Vec<bool> = (0..UNRESPONSIVE_AFTER + 1)
+        .map(|_| pings.failed(&peer))
+        .collect();
+
+    assert_eq!(reports.iter().filter(|x| **x).count(), 1);
+    assert!(reports[UNRESPONSIVE_AFTER as usize - 1]);
+}
+
+#[test]
+fn an_answered_ping_ends_the_streak() {
+    let mut pings = PingTracker::default();
+    let peer = PeerId::random();
+    pings.failed(&peer);
+
+    pings.succeeded(&peer, Duration::from_millis(80));
+
+    let info = pings.info(&peer).unwrap();
+    assert_eq!(info.consecutive_failures, 0);
+    assert_eq!(info.failures, 1);
+}
+
+#[test]
+fn disconnected_peers_are_forgotten() {
+    let mut pings = PingTracker::default();
+    let peer = PeerId::random();
+    pings.succeeded(&peer, Duration::from_millis(80));
+
+    pings.disconnected(&peer);
+
+    assert_eq!(pings.info(&peer), None);
+}
@@ -0,0 +1,52 @@
+use crate::publishing::{
+    check_sendable, PublishFailure, SendError, Unpublished, MAX_TRANSMIT_SIZE,
+};
+use crate::test_support::text;
+use libp2p::gossipsub::error::PublishError;
+
+#[test]
+fn messages_within_the_transmit_size_can_be_sent() {
+    assert_eq!(check_sendable(&text("hi")), Ok(()));
+}
+
+#[test]
+fn oversized_messages_are_refused() {
+    let body = "x".repeat(MAX_TRANSMIT_SIZE);
+
+    let result = check_sendable(&text(&body));
+
+    assert!(matches!(
+        result,
+        Err(SendError::TooLarge(size, MAX_TRANSMIT_SIZE)) if size > MAX_TRANSMIT_SIZE
+    ));
+}
+
+#[test]
+fn only_missing_peers_is_worth_retrying() {
+    assert_eq!(
+        PublishFailure::from(PublishError::InsufficientPeers),
+        PublishFailure::InsufficientPeers
+    );
+    assert!(matches!(
+        PublishFailure::from(PublishError::MessageTooLarge),
+        PublishFailure::Permanent(_)
+    ));
+}
+
+#[test]
+fn deferred_messages_are_taken_per_topic_in_order() {
+    let mut unpublished = Unpublished::default();
+    unpublished.defer("ab".into(), text("first"));
+    unpublished.defer("ac".into(), text("other"));
+    unpublished.defer("ab".into(), text("second"));
+
+    let taken: Vec<String> = unpublished
+        .take("ab")
+        .into_iter()
+        .map(|x| x.decode::<String>().unwrap())
+        .collect();
+
+    assert_eq!(taken, vec!["first", "second"]);
+    assert!(unpublished.take("ab").is_empty());
+    assert_eq!(unpublished.take("ac").len(), 1);
+}
@@ -0,0 +1,66 @@
+use crate::identity_profile::{profile_topic, IdentityProfile, ProfileCache, SignedProfile};
+use crate::keystore::InMemoryKeystore;
+use crate::test_support::{did_of, keystore};
+
+fn profile(username: &str, published_at: u64) -> IdentityProfile {
+    IdentityProfile {
+        username: username.into(),
+        status: Some("Around".into()),
+        avatar: None,
+        published_at,
+    }
+}
+
+fn signed(keystore: &InMemoryKeystore, username: &str, published_at: u64) -> SignedProfile {
+    SignedProfile::new(keystore, did_of(keystore), profile(username, published_at)).unwrap()
+}
+
+#[test]
+fn a_did_is_followed_once() {
+    let alice = did_of(&keystore());
+    let mut cache = ProfileCache::default();
+
+    assert_eq!(cache.follow(&alice), Some(profile_topic(&alice)));
+    assert_eq!(cache.follow(&alice), None);
+    assert_eq!(cache.did_of_topic(&profile_topic(&alice)), Some(&alice));
+}
+
+#[test]
+fn a_signed_profile_survives_the_trip_through_sata() {
+    let sent = signed(&keystore(), "alice", 1);
+
+    let received: SignedProfile = sent.to_sata().unwrap().decode().unwrap();
+
+    assert_eq!(received, sent);
+    assert!(received.verify().is_ok());
+}
+
+#[test]
+fn only_newer_profiles_replace_the_cached_one() {
+    let alice = keystore();
+    let topic = profile_topic(&did_of(&alice));
+    let mut cache = ProfileCache::default();
+    cache.follow(&did_of(&alice));
+
+    assert!(cache.update(&topic, signed(&alice, "alice", 2)));
+    assert!(!cache.update(&topic, signed(&alice, "old alice", 1)));
+    assert!(cache.update(&topic, signed(&alice, "new alice", 3)));
+
+    let cached = cache.profile(&did_of(&alice)).unwrap();
+    assert_eq!(cached.username, "new alice");
+}
+
+#[test]
+fn profiles_must_be_signed_by_the_did_followed_on_the_topic() {
+    let (alice, mallory) = (keystore(), keystore());
+    let topic = profile_topic(&did_of(&alice));
+    let mut cache = ProfileCache::default();
+    cache.follow(&did_of(&alice));
+    let mut forged = signed(&mallory, "alice", 5);
+    forged.did = did_of(&alice);
+
+    assert!(!cache.update(&topic, signed(&mallory, "mallory", 5)));
+    assert!(!cache.update(&topic, forged));
+    assert!(!cache.update(&profile_topic(&did_of(&mallory)), signed(&mallory, "m", 5)));
+    assert_eq!(cache.profile(&did_of(&alice)), None);
+}
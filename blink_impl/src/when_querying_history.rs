@@ -0,0 +1,79 @@
+use crate::conversations::{ConversationStore, StoredMessage};
+use crate::test_support::text;
+
+fn message(sender: &str, topic: &str, timestamp: u64, body: &str) -> StoredMessage {
+    StoredMessage::new(sender.into(), topic.into(), timestamp, text(body))
+}
+
+fn bodies(messages: Vec<StoredMessage>) -> Vec<String> {
+    messages
+        .into_iter()
+        .map(|x| x.sata.decode::<String>().unwrap())
+        .collect()
+}
+
+fn store() -> ConversationStore {
+    let mut store = ConversationStore::default();
+    store.record(message("alice", "ab", 1, "hi bob"));
+    store.record(message("bob", "ab", 2, "hi alice"));
+    store.record(message("carol", "ac", 3, "Lunch tomorrow?"));
+    store.record(message("alice", "ab", 4, "lunch?"));
+    store.record(message("bob", "ab", 5, "sure"));
+    store
+}
+
+#[test]
+fn history_pages_back_from_the_latest_message() {
+    let store = store();
+
+    let latest = store.history("ab", .., 2);
+    let before = store.history("ab", ..latest[0].timestamp, 2);
+
+    assert_eq!(bodies(latest), vec!["lunch?", "sure"]);
+    assert_eq!(bodies(before), vec!["hi bob", "hi alice"]);
+}
+
+#[test]
+fn history_stays_within_its_conversation_and_range() {
+    let store = store();
+
+    assert_eq!(bodies(store.history("ac", .., 10)), vec!["Lunch tomorrow?"]);
+    assert_eq!(
+        bodies(store.history("ab", 2..=4, 10)),
+        vec!["hi alice", "lunch?"]
+    );
+}
+
+#[test]
+fn search_ignores_case_and_returns_the_newest_first() {
+    let store = store();
+
+    assert_eq!(
+        bodies(store.search("LUNCH")),
+        vec!["lunch?", "Lunch tomorrow?"]
+    );
+}
+
+#[test]
+fn replayed_message_is_recorded_once() {
+    let mut store = ConversationStore::default();
+    let hi = text("hi");
+
+    assert!(store.record(StoredMessage::new(
+        "alice".into(),
+        "ab".into(),
+        1,
+        hi.clone()
+    )));
+    assert!(!store.record(StoredMessage::new("alice".into(), "ab".into(), 7, hi)));
+    assert_eq!(store.history("ab", .., 10).len(), 1);
+}
+
+#[test]
+fn late_message_is_placed_by_its_timestamp() {
+    let mut store = ConversationStore::default();
+    store.record(message("alice", "ab", 5, "second"));
+    store.record(message("bob", "ab", 1, "first"));
+
+    assert_eq!(bodies(store.history("ab", .., 10)), vec!["first", "second"]);
+}
@@ -0,0 +1,79 @@
+use crate::dht::{self, DhtAnswer};
+use blink_contract::BlinkError;
+use libp2p::kad::{
+    record::Key, GetClosestPeersError, GetClosestPeersOk, GetProvidersOk, PutRecordOk, QueryResult,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashSet;
+
+fn no_addresses(_: &PeerId) -> Vec<Multiaddr> {
+    Vec::new()
+}
+
+#[test]
+fn found_peers_are_answered_with_their_addresses() {
+    let peer = PeerId::random();
+    let address: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+    let result = QueryResult::GetClosestPeers(Ok(GetClosestPeersOk {
+        key: peer.to_bytes(),
+        peers: vec![peer],
+    }));
+
+    let answer = dht::answer(result, |x| {
+        assert_eq!(*x, peer);
+        vec![address.clone()]
+    });
+    match answer {
+        Ok(DhtAnswer::Addresses(addresses)) => assert_eq!(addresses, vec![address]),
+        other => panic!("Unexpected answer {:?}", other),
+    }
+}
+
+#[test]
+fn peers_without_addresses_are_not_found() {
+    let peer = PeerId::random();
+    let result = QueryResult::GetClosestPeers(Ok(GetClosestPeersOk {
+        key: peer.to_bytes(),
+        peers: Vec::new(),
+    }));
+
+    match dht::answer(result, no_addresses) {
+        Err(BlinkError::NotFound(_)) => {}
+        other => panic!("Unexpected answer {:?}", other),
+    }
+}
+
+#[test]
+fn failed_queries_are_errors() {
+    let result = QueryResult::GetClosestPeers(Err(GetClosestPeersError::Timeout {
+        key: PeerId::random().to_bytes(),
+        peers: Vec::new(),
+    }));
+
+    match dht::answer(result, no_addresses) {
+        Err(BlinkError::Other(_)) => {}
+        other => panic!("Unexpected answer {:?}", other),
+    }
+}
+
+#[test]
+fn providers_and_stored_records_are_answered() {
+    let provider = PeerId::random();
+    let result = QueryResult::GetProviders(Ok(GetProvidersOk {
+        key: Key::new(&"cid"),
+        providers: HashSet::from([provider]),
+        closest_peers: Vec::new(),
+    }));
+    match dht::answer(result, no_addresses) {
+        Ok(DhtAnswer::Providers(providers)) => assert!(providers.contains(&provider)),
+        other => panic!("Unexpected answer {:?}", other),
+    }
+
+    let result = QueryResult::PutRecord(Ok(PutRecordOk {
+        key: Key::new(&"key"),
+    }));
+    assert!(matches!(
+        dht::answer(result, no_addresses),
+        Ok(DhtAnswer::Stored)
+    ));
+}
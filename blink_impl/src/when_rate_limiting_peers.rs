@@ -0,0 +1,86 @@
+use crate::rate_limit::{Limited, RateLimit, RateLimiter, RateLimits};
+use libp2p::PeerId;
+
+fn limiter(per_peer: Option<RateLimit>, per_topic: Option<RateLimit>) -> RateLimiter {
+    let mut limiter = RateLimiter::default();
+    limiter.set_limits(RateLimits {
+        per_peer,
+        per_topic,
+    });
+    limiter
+}
+
+fn messages(per_sec: f64) -> Option<RateLimit> {
+    Some(RateLimit {
+        messages_per_sec: per_sec,
+        bytes_per_sec: 1_000_000.0,
+    })
+}
+
+#[test]
+fn nothing_is_limited_by_default() {
+    let mut limiter = RateLimiter::default();
+    let peer = PeerId::random();
+
+    for _ in 0..1000 {
+        assert_eq!(limiter.check(&peer, "topic", 1024, 0), Ok(()));
+    }
+}
+
+#[test]
+fn a_peer_over_its_rate_is_dropped_and_reported_once() {
+    let mut limiter = limiter(messages(2.0), None);
+    let peer = PeerId::random();
+
+    assert_eq!(limiter.check(&peer, "topic", 10, 0), Ok(()));
+    assert_eq!(limiter.check(&peer, "topic", 10, 0), Ok(()));
+
+    assert_eq!(
+        limiter.check(&peer, "topic", 10, 0),
+        Err((Limited::Peer, true))
+    );
+    assert_eq!(
+        limiter.check(&peer, "topic", 10, 0),
+        Err((Limited::Peer, false))
+    );
+    assert_eq!(limiter.check(&PeerId::random(), "topic", 10, 0), Ok(()));
+}
+
+#[test]
+fn buckets_refill_over_time() {
+    let mut limiter = limiter(messages(2.0), None);
+    let peer = PeerId::random();
+    limiter.check(&peer, "topic", 10, 0).unwrap();
+    limiter.check(&peer, "topic", 10, 0).unwrap();
+
+    assert!(limiter.check(&peer, "topic", 10, 100).is_err());
+    assert_eq!(limiter.check(&peer, "topic", 10, 600), Ok(()));
+}
+
+#[test]
+fn bytes_count_against_the_limit_too() {
+    let mut limiter = limiter(
+        Some(RateLimit {
+            messages_per_sec: 100.0,
+            bytes_per_sec: 1000.0,
+        }),
+        None,
+    );
+    let peer = PeerId::random();
+
+    assert_eq!(limiter.check(&peer, "topic", 800, 0), Ok(()));
+    assert!(limiter.check(&peer, "topic", 800, 0).is_err());
+}
+
+#[test]
+fn a_busy_topic_is_limited_whoever_sends() {
+    let mut limiter = limiter(None, messages(1.0));
+
+    assert_eq!(limiter.check(&PeerId::random(), "topic", 10, 0), Ok(()));
+
+    assert_eq!(
+        limiter.check(&PeerId::random(), "topic", 10, 0),
+        Err((Limited::Topic, true))
+    );
+    assert_eq!(limiter.check(&PeerId::random(), "other", 10, 0), Ok(()));
+}
@@ -0,0 +1,71 @@
+use crate::identified_peers::{Advertisement, IdentifiedPeers};
+use libp2p::identify::IdentifyInfo;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+
+fn info(listen_addrs: &[&str], protocols: &[&str]) -> IdentifyInfo {
+    IdentifyInfo {
+        public_key: Keypair::generate_ed25519().public(),
+        protocol_version: "blink/1".into(),
+        agent_version: "test".into(),
+        listen_addrs: listen_addrs.iter().map(|x| x.parse().unwrap()).collect(),
+        protocols: protocols.iter().map(|x| x.to_string()).collect(),
+        observed_addr: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+    }
+}
+
+#[test]
+fn only_changed_advertisements_are_updates() {
+    let mut identified = IdentifiedPeers::default();
+    let peer = PeerId::random();
+    let first = info(&["/ip4/10.0.0.1/tcp/4001"], &["/ipfs/id/1.0.0"]);
+
+    assert_eq!(identified.advertised(peer, &first), Advertisement::New);
+    assert_eq!(identified.advertised(peer, &first), Advertisement::Same);
+    let relayed = info(
+        &[
+            "/ip4/10.0.0.1/tcp/4001",
+            "/ip4/10.0.0.9/tcp/4001/p2p-circuit",
+        ],
+        &["/ipfs/id/1.0.0"],
+    );
+    assert_eq!(
+        identified.advertised(peer, &relayed),
+        Advertisement::Changed
+    );
+    let more_protocols = info(
+        &[
+            "/ip4/10.0.0.1/tcp/4001",
+            "/ip4/10.0.0.9/tcp/4001/p2p-circuit",
+        ],
+        &["/ipfs/id/1.0.0", "/blink/profile/1.1.0"],
+    );
+    assert_eq!(
+        identified.advertised(peer, &more_protocols),
+        Advertisement::Changed
+    );
+}
+
+#[test]
+fn order_doesnt_matter() {
+    let mut identified = IdentifiedPeers::default();
+    let peer = PeerId::random();
+
+    identified.advertised(
+        peer,
+        &info(&["/ip4/10.0.0.1/tcp/4001", "/ip4/10.0.0.2/tcp/4001"], &[]),
+    );
+    let reordered = info(&["/ip4/10.0.0.2/tcp/4001", "/ip4/10.0.0.1/tcp/4001"], &[]);
+    assert_eq!(identified.advertised(peer, &reordered), Advertisement::Same);
+}
+
+#[test]
+fn reconnected_peers_are_new_again() {
+    let mut identified = IdentifiedPeers::default();
+    let peer = PeerId::random();
+    let advertised = info(&["/ip4/10.0.0.1/tcp/4001"], &[]);
+
+    identified.advertised(peer, &advertised);
+    identified.disconnected(&peer);
+    assert_eq!(identified.advertised(peer, &advertised), Advertisement::New);
+}
@@ -0,0 +1,98 @@
+use crate::fragments::DataFragment;
+use crate::recording::{
+    recorded_frames, Direction, RecordedFrame, RecordedPayload, RecordingManifest,
+    RecordingOptions, RecordingOutput, RecordingRegistry,
+};
+use blink_contract::StreamKind;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+const BOTH_SIDES: RecordingOptions = RecordingOptions {
+    incoming: true,
+    outgoing: true,
+};
+
+fn audio(direction: Direction, sequence: u64, size: usize) -> RecordedFrame {
+    RecordedFrame {
+        direction,
+        at: sequence,
+        payload: RecordedPayload::Audio(sequence, vec![0; size]),
+    }
+}
+
+fn outputs(receiver: &mut UnboundedReceiver<RecordingOutput>) -> Vec<RecordingOutput> {
+    let mut outputs = Vec::new();
+    while let Ok(output) = receiver.try_recv() {
+        outputs.push(output);
+    }
+    outputs
+}
+
+#[test]
+fn manifest_lists_every_chunk_in_order() {
+    let (sender, mut receiver) = unbounded_channel();
+    let mut recordings = RecordingRegistry::new(sender);
+    recordings.start(1, StreamKind::Audio, "did:key:peer".into(), BOTH_SIDES, 0);
+
+    recordings.record(1, audio(Direction::Incoming, 0, 200 * 1024));
+    recordings.record(1, audio(Direction::Outgoing, 1, 100 * 1024));
+    recordings.record(1, audio(Direction::Incoming, 2, 10));
+    recordings.finish(1, 3);
+
+    let outputs = outputs(&mut receiver);
+    let chunks: Vec<&DataFragment> = outputs
+        .iter()
+        .filter_map(|x| match x {
+            RecordingOutput::Chunk(chunk) => Some(chunk),
+            _ => None,
+        })
+        .collect();
+    let manifest = match outputs.last() {
+        Some(RecordingOutput::Finished(1, manifest)) => {
+            RecordingManifest::from_fragment(manifest).unwrap()
+        }
+        _ => panic!("Recording didn't finish"),
+    };
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(
+        manifest.chunks,
+        chunks
+            .iter()
+            .map(|x| x.cid().to_string())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(recorded_frames(chunks[0]).unwrap().len(), 2);
+    assert_eq!(recorded_frames(chunks[1]).unwrap().len(), 1);
+}
+
+#[test]
+fn only_the_selected_side_is_recorded() {
+    let (sender, mut receiver) = unbounded_channel();
+    let mut recordings = RecordingRegistry::new(sender);
+    let options = RecordingOptions {
+        incoming: true,
+        outgoing: false,
+    };
+    recordings.start(1, StreamKind::Audio, "did:key:peer".into(), options, 0);
+
+    recordings.record(1, audio(Direction::Outgoing, 0, 10));
+    recordings.record(1, audio(Direction::Incoming, 1, 10));
+    recordings.finish(1, 2);
+
+    match &outputs(&mut receiver)[0] {
+        RecordingOutput::Chunk(chunk) => {
+            let frames = recorded_frames(chunk).unwrap();
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].direction, Direction::Incoming);
+        }
+        _ => panic!("Expected a chunk"),
+    }
+}
+
+#[test]
+fn fragments_with_the_same_data_share_a_cid() {
+    let first = DataFragment::new(b"frame".to_vec(), 1);
+    let second = DataFragment::new(b"frame".to_vec(), 2);
+
+    assert_eq!(first.cid(), second.cid());
+    assert_ne!(first.cid(), DataFragment::new(b"other".to_vec(), 1).cid());
+}
@@ -0,0 +1,89 @@
+use crate::config::BlinkConfig;
+use crate::relay_server::{RelayServerSettings, RelayStats, RelayTracker};
+use libp2p::relay::v2::relay::Event;
+use libp2p::PeerId;
+use std::time::Duration;
+
+fn accepted(src_peer_id: PeerId) -> Event {
+    Event::ReservationReqAccepted {
+        src_peer_id,
+        renewed: false,
+    }
+}
+
+#[test]
+fn relaying_is_off_by_default() {
+    assert!(!BlinkConfig::default().relay_server.enabled);
+}
+
+#[test]
+fn the_settings_carry_over_to_libp2p() {
+    let settings = RelayServerSettings {
+        enabled: true,
+        max_reservations: 512,
+        max_circuits: 64,
+        max_circuit_bytes: 1 << 20,
+        max_circuit_duration_secs: 600,
+    };
+
+    let config = settings.config();
+
+    assert_eq!(config.max_reservations, 512);
+    assert_eq!(config.max_circuits, 64);
+    assert_eq!(config.max_circuit_bytes, 1 << 20);
+    assert_eq!(config.max_circuit_duration, Duration::from_secs(600));
+}
+
+#[test]
+fn renewed_reservations_are_counted_once_until_they_time_out() {
+    let mut tracker = RelayTracker::default();
+    let peer = PeerId::random();
+    let other = PeerId::random();
+
+    tracker.event(&accepted(peer));
+    tracker.event(&Event::ReservationReqAccepted {
+        src_peer_id: peer,
+        renewed: true,
+    });
+    tracker.event(&accepted(other));
+    tracker.event(&Event::ReservationTimedOut { src_peer_id: other });
+
+    let stats = tracker.stats();
+    assert_eq!(stats.reservations, 1);
+    assert_eq!(stats.reservations_accepted, 3);
+}
+
+#[test]
+fn circuits_are_open_until_closed() {
+    let mut tracker = RelayTracker::default();
+    let src_peer_id = PeerId::random();
+    let dst_peer_id = PeerId::random();
+
+    tracker.event(&Event::CircuitReqAccepted {
+        src_peer_id,
+        dst_peer_id,
+    });
+    tracker.event(&Event::CircuitReqAccepted {
+        src_peer_id,
+        dst_peer_id,
+    });
+    tracker.event(&Event::CircuitReqDenied {
+        src_peer_id,
+        dst_peer_id,
+    });
+    tracker.event(&Event::CircuitClosed {
+        src_peer_id,
+        dst_peer_id,
+        error: None,
+    });
+
+    assert_eq!(
+        tracker.stats(),
+        RelayStats {
+            circuits: 1,
+            circuits_accepted: 2,
+            circuits_denied: 1,
+            ..RelayStats::default()
+        }
+    );
+}
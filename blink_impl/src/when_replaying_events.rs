@@ -0,0 +1,110 @@
+use crate::event_history::{EventHistory, Verbosity};
+use crate::event_sink::EventSink;
+use blink_contract::{Event, EventBus};
+use std::sync::Arc;
+use warp::sync::RwLock;
+
+#[derive(Default)]
+struct Collected {
+    events: Vec<Event>,
+}
+
+impl EventBus for Collected {
+    fn event_occurred(&mut self, event: Event) {
+        self.events.push(event);
+    }
+}
+
+fn history(capacity: usize) -> (EventHistory, Arc<RwLock<Collected>>) {
+    let inner = Arc::new(RwLock::new(Collected::default()));
+    (EventHistory::new(inner.clone(), capacity), inner)
+}
+
+fn numbers(events: Vec<(u64, Event)>) -> Vec<u64> {
+    events.into_iter().map(|(x, _)| x).collect()
+}
+
+#[test]
+fn events_are_forwarded_and_kept() {
+    let (mut history, inner) = history(8);
+
+    history.event_occurred(Event::PeerIdentified);
+    history.event_occurred(Event::TaskCancelled);
+
+    assert_eq!(inner.read().events.len(), 2);
+    assert_eq!(numbers(history.recent()), vec![1, 2]);
+    assert!(matches!(history.recent()[1].1, Event::TaskCancelled));
+}
+
+#[test]
+fn only_the_latest_events_are_kept() {
+    let (mut history, _) = history(2);
+
+    for _ in 0..5 {
+        history.event_occurred(Event::PeerIdentified);
+    }
+
+    assert_eq!(numbers(history.recent()), vec![4, 5]);
+}
+
+#[test]
+fn events_since_returns_what_came_after() {
+    let (mut history, _) = history(8);
+    for _ in 0..4 {
+        history.event_occurred(Event::PeerIdentified);
+    }
+
+    assert_eq!(numbers(history.since(0)), vec![1, 2, 3, 4]);
+    assert_eq!(numbers(history.since(2)), vec![3, 4]);
+    assert!(history.since(4).is_empty());
+}
+
+#[test]
+fn subscribers_receive_events_numbered_like_the_history() {
+    let (mut history, _) = history(8);
+    history.event_occurred(Event::PeerIdentified);
+    let mut events = history.subscribe();
+
+    history.event_occurred(Event::TaskCancelled);
+
+    let (number, event) = events.try_recv().unwrap();
+    assert_eq!(number, 2);
+    assert_eq!(history.last(), 2);
+    assert!(matches!(event, Event::TaskCancelled));
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn queued_events_reach_the_bus_in_order() {
+    let inner = Arc::new(RwLock::new(Collected::default()));
+    let (sink, deliver) = EventSink::new(inner.clone());
+    tokio::spawn(deliver);
+
+    sink.event_occurred(Event::PeerIdentified);
+    sink.event_occurred(Event::TaskCancelled);
+    sink.flush().await;
+
+    let events = &inner.read().events;
+    assert!(matches!(
+        events[..],
+        [Event::PeerIdentified, Event::TaskCancelled]
+    ));
+}
+
+#[test]
+fn quiet_keeps_connection_events_from_the_bus_but_not_the_history() {
+    let (mut history, inner) = history(8);
+    history.set_verbosity(Verbosity::Quiet);
+
+    history.event_occurred(Event::PeerIdentified);
+    history.event_occurred(Event::SubscriptionError("topic".into()));
+    history.set_verbosity(Verbosity::Silent);
+    history.event_occurred(Event::SubscriptionError("topic".into()));
+
+    assert_eq!(inner.read().events.len(), 1);
+    assert!(matches!(
+        inner.read().events[0],
+        Event::SubscriptionError(_)
+    ));
+    assert_eq!(numbers(history.recent()), vec![1, 2, 3]);
+}
@@ -0,0 +1,60 @@
+use crate::did_records::{key_of, DidRecord};
+use crate::keystore::InMemoryKeystore;
+use crate::test_support::keystore;
+use blink_contract::Keystore;
+use libp2p::kad::Record;
+use libp2p::PeerId;
+
+const NOW: u64 = 1_000_000;
+
+fn record_of(keystore: &InMemoryKeystore, peer_id: &PeerId, expires_at: u64) -> DidRecord {
+    let did = keystore.public_key().unwrap().to_string();
+    let addrs = vec!["/ip4/10.0.0.1/tcp/4001".parse().unwrap()];
+    DidRecord::new(keystore, did, peer_id, addrs, expires_at).unwrap()
+}
+
+#[test]
+fn a_published_record_resolves_to_the_peer_and_addresses() {
+    let keystore = keystore();
+    let peer_id = PeerId::random();
+    let published = record_of(&keystore, &peer_id, NOW + 1000);
+
+    let record = published.to_record().unwrap();
+    let resolved = DidRecord::from_record(&record, NOW).unwrap();
+
+    assert_eq!(record.key, key_of(&published.did));
+    assert_eq!(resolved, published);
+    assert_eq!(resolved.peer_id().unwrap(), peer_id);
+}
+
+#[test]
+fn expired_records_are_rejected() {
+    let keystore = keystore();
+    let record = record_of(&keystore, &PeerId::random(), NOW)
+        .to_record()
+        .unwrap();
+
+    assert!(DidRecord::from_record(&record, NOW).is_err());
+}
+
+#[test]
+fn records_pointing_somewhere_else_are_rejected() {
+    let keystore = keystore();
+    let mut published = record_of(&keystore, &PeerId::random(), NOW + 1000);
+
+    published.addrs = vec!["/ip4/10.6.6.6/tcp/4001".parse().unwrap()];
+
+    let record = published.to_record().unwrap();
+    assert!(DidRecord::from_record(&record, NOW).is_err());
+}
+
+#[test]
+fn records_stored_under_another_dids_key_are_rejected() {
+    let published = record_of(&keystore(), &PeerId::random(), NOW + 1000);
+    let other = keystore().public_key().unwrap().to_string();
+
+    let value = published.to_record().unwrap().value;
+    let record = Record::new(key_of(&other), value);
+
+    assert!(DidRecord::from_record(&record, NOW).is_err());
+}
@@ -0,0 +1,40 @@
+use crate::rendezvous::{namespace_of, RendezvousPoints};
+use libp2p::PeerId;
+
+const DID: &str = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+#[test]
+fn a_did_always_registers_under_the_same_namespace() {
+    assert_eq!(namespace_of(DID), namespace_of(DID));
+    assert_ne!(namespace_of(DID), namespace_of("did:key:other"));
+}
+
+#[test]
+fn the_namespace_doesnt_reveal_the_did() {
+    let namespace = namespace_of(DID).to_string();
+
+    assert!(namespace.starts_with("blink/"));
+    assert!(!namespace.contains(DID));
+}
+
+#[test]
+fn registrations_found_are_traced_back_to_the_did_looked_up() {
+    let mut points = RendezvousPoints::default();
+
+    let namespace = points.want(DID.to_string());
+
+    assert_eq!(points.did_of(&namespace), Some(DID.to_string()));
+    assert_eq!(points.wanted(), vec![namespace]);
+    assert_eq!(points.did_of(&namespace_of("did:key:other")), None);
+}
+
+#[test]
+fn only_configured_nodes_are_rendezvous_points() {
+    let mut points = RendezvousPoints::default();
+    let node = PeerId::random();
+
+    points.add_node(node);
+
+    assert!(points.is_node(&node));
+    assert!(!points.is_node(&PeerId::random()));
+}
@@ -0,0 +1,197 @@
+// Acceptance suite: a bootstrap node doubling as mailbox and several clients, all in-process
+// and connected through the memory transport.
+// Peers behind NAT are simulated by nodes nobody is given an address for, they only dial out.
+// The swarm has no relay client transport yet, so relayed connections aren't covered.
+use crate::peer_to_peer_service::{MessageContent, PeerToPeerService};
+use crate::when_using_peer_to_peer_service::{
+    assert_message, create_service, create_service_with_keys, pair_to_another_peer,
+};
+use blink_contract::{Event, StreamKind};
+use libp2p::{Multiaddr, PeerId};
+use sata::Sata;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::Receiver;
+use warp::crypto::DID;
+
+const SUITE_TIMEOUT_SECS: u64 = 20;
+
+// Time for subscriptions to propagate when no event tells us they did
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+struct Node {
+    service: PeerToPeerService,
+    did: Arc<DID>,
+    addresses: Vec<Multiaddr>,
+    messages: Receiver<MessageContent>,
+}
+
+impl Node {
+    async fn start(known: Vec<Multiaddr>) -> Self {
+        let (service, _, _, _, did, addresses, messages) = create_service(known, true).await;
+        Self {
+            service,
+            did,
+            addresses,
+            messages,
+        }
+    }
+
+    async fn restart(did: Arc<DID>, known: Vec<Multiaddr>) -> Self {
+        let (service, _, _, _, did, addresses, messages) =
+            create_service_with_keys(did, known, true).await;
+        Self {
+            service,
+            did,
+            addresses,
+            messages,
+        }
+    }
+
+    fn peer_id(&self) -> PeerId {
+        self.service.local_peer_id()
+    }
+
+    // Dials the other node and returns the DID it identified as
+    async fn pair(&mut self, other: &Node) -> DID {
+        pair_to_another_peer(&mut self.service, other.addresses[0].clone().into())
+            .await
+            .0
+    }
+
+    async fn wait_for(&self, predicate: impl Fn(&Event) -> bool) {
+        let timeout = Duration::from_secs(SUITE_TIMEOUT_SECS);
+        self.service
+            .wait_for_event_since(0, predicate, timeout)
+            .await
+            .unwrap();
+    }
+
+    async fn wait_for_topic_with(&self, did: &DID) {
+        let did = did.to_string();
+        self.wait_for(|x| matches!(x, Event::GeneratedTopic(peer, _) if peer.to_string() == did))
+            .await;
+    }
+}
+
+// A bootstrap node every client knows, running in mailbox mode, and the clients.
+struct Network {
+    bootstrap: Node,
+    clients: Vec<Node>,
+}
+
+impl Network {
+    async fn start(clients: usize) -> Self {
+        let mut bootstrap = Node::start(Vec::new()).await;
+        bootstrap.service.set_mailbox_mode(true).await.unwrap();
+        let mut nodes = Vec::new();
+        for _ in 0..clients {
+            nodes.push(Node::start(bootstrap.addresses.clone()).await);
+        }
+        Self {
+            bootstrap,
+            clients: nodes,
+        }
+    }
+}
+
+fn message_to(recipients: &[&DID]) -> Sata {
+    let mut sata = Sata::default();
+    for recipient in recipients {
+        sata.add_recipient(recipient.as_ref()).unwrap();
+    }
+    sata
+}
+
+#[tokio::test]
+async fn clients_pair_by_did() {
+    tokio::time::timeout(Duration::from_secs(SUITE_TIMEOUT_SECS), async {
+        let mut network = Network::start(2).await;
+        let b = network.clients.pop().unwrap();
+        let mut a = network.clients.pop().unwrap();
+
+        let paired = a.pair(&b).await;
+
+        assert_eq!(paired.to_string(), b.did.to_string());
+        b.wait_for_topic_with(&a.did).await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn offline_recipient_gets_its_messages_from_the_mailbox() {
+    tokio::time::timeout(Duration::from_secs(SUITE_TIMEOUT_SECS), async {
+        let mut network = Network::start(2).await;
+        let mut b = network.clients.pop().unwrap();
+        let mut a = network.clients.pop().unwrap();
+        let mailbox = network.bootstrap.peer_id();
+        a.pair(&network.bootstrap).await;
+        b.pair(&network.bootstrap).await;
+        a.pair(&b).await;
+        b.wait_for_topic_with(&a.did).await;
+        b.service.register_mailbox(mailbox).await.unwrap();
+        tokio::time::sleep(SETTLE_TIME).await;
+
+        let b_did = b.did.clone();
+        drop(b);
+        a.service.send(message_to(&[&b_did])).await.unwrap();
+        tokio::time::sleep(SETTLE_TIME).await;
+
+        let mut b = Node::restart(b_did, network.bootstrap.addresses.clone()).await;
+        b.pair(&network.bootstrap).await;
+        b.pair(&a).await;
+        b.service.sync_from_mailbox(mailbox, 0).await.unwrap();
+
+        assert_message(&mut b.messages).await;
+        b.wait_for(|x| matches!(x, Event::MailboxReplayed(count) if *count > 0))
+            .await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn group_message_reaches_every_member() {
+    tokio::time::timeout(Duration::from_secs(SUITE_TIMEOUT_SECS), async {
+        let mut network = Network::start(3).await;
+        let mut c = network.clients.pop().unwrap();
+        let mut b = network.clients.pop().unwrap();
+        let mut a = network.clients.pop().unwrap();
+        let did_a = c.pair(&a).await;
+        let did_b = c.pair(&b).await;
+
+        c.service.send(message_to(&[&did_a, &did_b])).await.unwrap();
+
+        assert_message(&mut a.messages).await;
+        assert_message(&mut b.messages).await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn media_reaches_a_peer_behind_nat() {
+    tokio::time::timeout(Duration::from_secs(SUITE_TIMEOUT_SECS), async {
+        let mut network = Network::start(1).await;
+        let mut a = network.clients.pop().unwrap();
+        // Nobody learns the address of this node, the only connection is the one it opens
+        let mut behind_nat = Node::start(Vec::new()).await;
+        behind_nat.pair(&a).await;
+        a.wait_for_topic_with(&behind_nat.did).await;
+
+        let mut call = a.service.call(&behind_nat.did).await.unwrap();
+        let id = call.id();
+        behind_nat
+            .wait_for(|x| matches!(x, Event::IncomingStream(_, stream, StreamKind::Audio) if *stream == id))
+            .await;
+        let mut answer = behind_nat.service.accept_stream(id).unwrap();
+        a.wait_for(|x| matches!(x, Event::StreamOpened(stream, _) if *stream == id))
+            .await;
+
+        call.send_frame(vec![1, 2, 3]).await.unwrap();
+
+        assert_eq!(answer.next_frame().await, Some(vec![1, 2, 3]));
+    })
+    .await
+    .expect("Timeout");
+}
@@ -0,0 +1,73 @@
+use crate::delivery::DeliveryStrategy;
+use crate::scoring::{PeerScores, ScoreLevel, ScoreSettings};
+use libp2p::PeerId;
+
+#[test]
+fn crossings_are_reported_once() {
+    let mut scores = PeerScores::default();
+    let peer = PeerId::random();
+
+    assert_eq!(scores.scored(peer, 0.0), None);
+    assert_eq!(scores.scored(peer, -90.0), Some(ScoreLevel::Graylisted));
+    assert_eq!(scores.scored(peer, -95.0), None);
+    assert_eq!(scores.scored(peer, -20.0), Some(ScoreLevel::Fine));
+    assert_eq!(scores.score(&peer), Some(-20.0));
+}
+
+#[test]
+fn blacklisted_peers_stay_blacklisted() {
+    let mut scores = PeerScores::default();
+    let peer = PeerId::random();
+
+    assert_eq!(scores.scored(peer, -300.0), Some(ScoreLevel::Blacklisted));
+    assert_eq!(scores.scored(peer, 0.0), None);
+    scores.disconnected(&peer);
+    assert_eq!(scores.scored(peer, 0.0), None);
+}
+
+#[test]
+fn disconnected_peers_start_over() {
+    let mut scores = PeerScores::default();
+    let peer = PeerId::random();
+
+    scores.scored(peer, -90.0);
+    scores.disconnected(&peer);
+    assert_eq!(scores.score(&peer), None);
+    assert_eq!(scores.scored(peer, -90.0), Some(ScoreLevel::Graylisted));
+}
+
+#[test]
+fn topic_params_are_set_again_when_the_strategy_changes() {
+    let mut scores = PeerScores::default();
+
+    assert!(scores.needs_params("topic", DeliveryStrategy::Flood));
+    assert!(!scores.needs_params("topic", DeliveryStrategy::Flood));
+    assert!(scores.needs_params("topic", DeliveryStrategy::Mesh));
+}
+
+#[test]
+fn flooded_topics_dont_penalize_mesh_deliveries() {
+    let settings = ScoreSettings::default();
+
+    let flood = settings.topic_params(DeliveryStrategy::Flood);
+    assert_eq!(flood.mesh_message_deliveries_weight, 0.0);
+    assert_eq!(flood.invalid_message_deliveries_weight, -10.0);
+    let mesh = settings.topic_params(DeliveryStrategy::Mesh);
+    assert_eq!(mesh.mesh_message_deliveries_weight, -1.0);
+}
+
+#[test]
+fn thresholds_must_be_in_order() {
+    assert!(ScoreSettings::default().thresholds().is_ok());
+
+    let blacklist_above_graylist = ScoreSettings {
+        blacklist_threshold: -10.0,
+        ..Default::default()
+    };
+    assert!(blacklist_above_graylist.thresholds().is_err());
+    let graylist_above_publish = ScoreSettings {
+        graylist_threshold: -20.0,
+        ..Default::default()
+    };
+    assert!(graylist_above_publish.thresholds().is_err());
+}
@@ -0,0 +1,40 @@
+use crate::signaling::{CallRegistry, CallState};
+
+#[test]
+fn invite_during_an_active_call_gets_a_busy_answer() {
+    let mut calls = CallRegistry::default();
+    assert!(calls.incoming(1, "alice".to_string()));
+    calls.accept(1).unwrap();
+
+    assert!(!calls.incoming(2, "bob".to_string()));
+    assert_eq!(calls.peer_of(2), None);
+}
+
+#[test]
+fn only_the_invited_peer_can_accept() {
+    let mut calls = CallRegistry::default();
+    calls.invite(1, "alice".to_string());
+
+    assert!(!calls.accepted(1, "bob"));
+    assert!(calls.accepted(1, "alice"));
+    assert!(!calls.accepted(1, "alice"));
+}
+
+#[test]
+fn outgoing_invites_cannot_be_accepted_locally() {
+    let mut calls = CallRegistry::default();
+    calls.invite(1, "alice".to_string());
+
+    assert_eq!(calls.accept(1), None);
+}
+
+#[test]
+fn calls_end_only_for_their_own_peer() {
+    let mut calls = CallRegistry::default();
+    calls.incoming(1, "alice".to_string());
+
+    assert!(calls.end(1, "bob").is_none());
+    let call = calls.end(1, "alice").unwrap();
+    assert_eq!(call.state, CallState::Incoming);
+    assert_eq!(calls.peer_of(1), None);
+}
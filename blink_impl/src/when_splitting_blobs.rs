@@ -0,0 +1,141 @@
+use crate::clock::SystemClock;
+use crate::conflux::{Conflux, ConfluxError, ConfluxState};
+use crate::fragment_tree::{reassemble, FragmentTree, TREE_CODEC};
+use crate::fragments::DataFragment;
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::channel;
+use warp::sync::RwLock;
+
+fn blob(size: usize) -> Vec<u8> {
+    (0..size).map(|x| (x % 251) as u8).collect()
+}
+
+fn by_cid(tree: &FragmentTree) -> HashMap<String, DataFragment> {
+    tree.fragments()
+        .iter()
+        .map(|x| (x.cid().to_string(), x.clone()))
+        .collect()
+}
+
+fn from(fragments: &HashMap<String, DataFragment>, root: &DataFragment) -> anyhow::Result<Vec<u8>> {
+    reassemble(root, |cid| {
+        fragments
+            .get(cid)
+            .cloned()
+            .ok_or_else(|| anyhow!("Missing {}", cid))
+    })
+}
+
+#[test]
+fn blob_is_reassembled_from_its_chunks() {
+    let data = blob(1000);
+
+    let tree = FragmentTree::split_with(&data, 64, 1).unwrap();
+
+    assert_eq!(tree.root().codec(), TREE_CODEC);
+    assert_eq!(tree.fragments().len(), 16 + 1);
+    assert_eq!(from(&by_cid(&tree), tree.root()).unwrap(), data);
+}
+
+#[test]
+fn large_blob_gets_several_levels() {
+    let data = blob(400);
+
+    let tree = FragmentTree::split_with(&data, 1, 1).unwrap();
+
+    // 400 chunks, 3 nodes linking them and the root linking the nodes
+    assert_eq!(tree.fragments().len(), 400 + 3 + 1);
+    assert_eq!(from(&by_cid(&tree), tree.root()).unwrap(), data);
+}
+
+#[test]
+fn empty_blob_has_a_root_without_links() {
+    let tree = FragmentTree::split(&[], 1).unwrap();
+
+    assert_eq!(tree.fragments().len(), 1);
+    assert_eq!(from(&by_cid(&tree), tree.root()).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn chunk_swapped_for_another_is_refused() {
+    let tree = FragmentTree::split_with(&blob(128), 64, 1).unwrap();
+    let mut fragments = by_cid(&tree);
+    let first = tree.fragments()[0].cid().to_string();
+    fragments.insert(first, DataFragment::new(b"impostor".to_vec(), 1));
+
+    assert!(from(&fragments, tree.root()).is_err());
+}
+
+#[test]
+fn chunk_is_not_a_root() {
+    let chunk = DataFragment::new(b"plain".to_vec(), 1);
+
+    assert!(from(&HashMap::new(), &chunk).is_err());
+}
+
+#[tokio::test]
+async fn blob_added_to_conflux_comes_back_whole() {
+    let (commands, _receiver) = channel(1024);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state, commands, Arc::new(SystemClock));
+    let data = blob(600 * 1024);
+
+    let root = conflux.add_blob(&data).await.unwrap();
+
+    assert_eq!(conflux.get_blob(&root).await, Ok(data));
+}
+
+#[tokio::test]
+async fn chunk_is_not_a_blob() {
+    let (commands, _receiver) = channel(8);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state, commands, Arc::new(SystemClock));
+    let chunk = DataFragment::new(b"plain".to_vec(), 1);
+    conflux.add(chunk.clone()).await.unwrap();
+
+    assert!(matches!(
+        conflux.get_blob(chunk.cid()).await,
+        Err(ConfluxError::InvalidTree(_))
+    ));
+}
+
+#[tokio::test]
+async fn progress_is_reported_up_to_the_whole_blob() {
+    let (commands, _receiver) = channel(1024);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state, commands, Arc::new(SystemClock));
+    let data = blob(600 * 1024);
+    let root = conflux.add_blob(&data).await.unwrap();
+    let mut progress = Vec::new();
+
+    conflux
+        .get_blob_with_progress(&root, |bytes, size| progress.push((bytes, size)))
+        .await
+        .unwrap();
+
+    assert_eq!(progress.len(), 3);
+    assert!(progress.windows(2).all(|x| x[0].0 < x[1].0));
+    assert_eq!(
+        progress.last(),
+        Some(&(data.len() as u64, data.len() as u64))
+    );
+}
+
+#[tokio::test]
+async fn only_stored_blobs_are_pinned() {
+    let (commands, _receiver) = channel(1024);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state, commands, Arc::new(SystemClock));
+    let root = conflux.add_blob(&blob(600 * 1024)).await.unwrap();
+
+    conflux.pin_blob(&root).await.unwrap();
+
+    assert_eq!(conflux.unpin_blob(&root).await, Ok(true));
+    assert_eq!(conflux.unpin_blob(&root).await, Ok(false));
+    assert!(matches!(
+        conflux.pin_blob("missing").await,
+        Err(ConfluxError::NotFound(_))
+    ));
+}
@@ -0,0 +1,226 @@
+use crate::clock::SystemClock;
+use crate::conflux::{Conflux, ConfluxError, ConfluxState, GcLimits};
+use crate::fragment_store::{DiskFragmentStore, FragmentStore, MemoryFragmentStore, StoreKey};
+use crate::fragments::DataFragment;
+use crate::peer_to_peer_service::BlinkCommand;
+use crate::test_support::{keystore, temp_path};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver};
+use warp::sync::RwLock;
+
+fn conflux() -> (Conflux, Receiver<BlinkCommand>) {
+    let (commands, receiver) = channel(8);
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    (
+        Conflux::new(state, commands, Arc::new(SystemClock)),
+        receiver,
+    )
+}
+
+#[test]
+fn fragments_survive_reopening_the_store() {
+    let directory = temp_path("reopen", "fragments");
+    let fragment = DataFragment::new(b"hello".to_vec(), 42);
+    {
+        let mut store = DiskFragmentStore::open(&directory).unwrap();
+        store.put(fragment.clone()).unwrap();
+    }
+
+    let store = DiskFragmentStore::open(&directory).unwrap();
+
+    assert_eq!(store.cids().unwrap(), vec![fragment.cid().to_string()]);
+    assert_eq!(store.get(fragment.cid()).unwrap(), Some(fragment));
+}
+
+#[test]
+fn removed_fragment_is_gone_from_disk() {
+    let directory = temp_path("remove", "fragments");
+    let fragment = DataFragment::new(b"hello".to_vec(), 42);
+    let mut store = DiskFragmentStore::open(&directory).unwrap();
+    store.put(fragment.clone()).unwrap();
+
+    assert_eq!(
+        store.remove(fragment.cid()).unwrap(),
+        Some(fragment.clone())
+    );
+
+    assert_eq!(store.get(fragment.cid()).unwrap(), None);
+    assert!(store.cids().unwrap().is_empty());
+}
+
+#[test]
+fn cids_cannot_escape_the_store_directory() {
+    let directory = temp_path("escape", "fragments");
+    let store = DiskFragmentStore::open(&directory).unwrap();
+
+    assert!(store.get("../secret").is_err());
+}
+
+#[tokio::test]
+async fn switching_store_announces_what_it_holds() {
+    let (conflux, mut commands) = conflux();
+    let fragment = DataFragment::new(b"hello".to_vec(), 42);
+    let mut store = MemoryFragmentStore::default();
+    store.put(fragment.clone()).unwrap();
+
+    assert_eq!(conflux.use_store(store).await, Ok(1));
+
+    assert!(
+        matches!(commands.recv().await, Some(BlinkCommand::Provide(cid)) if cid == fragment.cid())
+    );
+    assert_eq!(conflux.get_by_cid(fragment.cid()).await, Ok(fragment));
+}
+
+#[tokio::test]
+async fn exported_fragments_import_into_another_node() {
+    let (source, _source_commands) = conflux();
+    let (destination, _destination_commands) = conflux();
+    let fragments = vec![
+        DataFragment::new(b"one".to_vec(), 1),
+        DataFragment::new(b"two".to_vec(), 2),
+    ];
+    source.import(fragments.clone()).await.unwrap();
+
+    let exported = source.export().await.unwrap();
+    assert_eq!(destination.import(exported).await, Ok(2));
+
+    for fragment in fragments {
+        assert_eq!(destination.get_by_cid(fragment.cid()).await, Ok(fragment));
+    }
+}
+
+fn limited_to(max_fragments: usize) -> ConfluxState {
+    let mut state = ConfluxState::default();
+    state.set_limits(GcLimits {
+        max_fragments: Some(max_fragments),
+        max_bytes: None,
+        max_age: None,
+    });
+    state
+}
+
+#[test]
+fn least_recently_used_fragments_are_evicted_first() {
+    let mut state = limited_to(2);
+    let first = DataFragment::new(b"first".to_vec(), 1);
+    let second = DataFragment::new(b"second".to_vec(), 2);
+    let third = DataFragment::new(b"third".to_vec(), 3);
+    state.add_fragment(first.clone()).unwrap();
+    state.add_fragment(second.clone()).unwrap();
+    state.add_fragment(third.clone()).unwrap();
+    let _ = state.want(first.cid().to_string());
+
+    assert_eq!(state.collect_garbage(10), vec![second.cid().to_string()]);
+    assert!(state.collect_garbage(10).is_empty());
+}
+
+#[test]
+fn pinned_fragments_are_never_evicted() {
+    let mut state = limited_to(0);
+    let pinned = DataFragment::new(b"pinned".to_vec(), 1);
+    let loose = DataFragment::new(b"loose".to_vec(), 1);
+    state.add_fragment(pinned.clone()).unwrap();
+    state.add_fragment(loose.clone()).unwrap();
+    state.pin(pinned.cid().to_string());
+
+    assert_eq!(state.collect_garbage(10), vec![loose.cid().to_string()]);
+    assert!(state.unpin(pinned.cid()));
+    assert_eq!(state.collect_garbage(10), vec![pinned.cid().to_string()]);
+}
+
+#[test]
+fn fragments_past_their_age_are_evicted() {
+    let mut state = ConfluxState::default();
+    state.set_limits(GcLimits {
+        max_fragments: None,
+        max_bytes: None,
+        max_age: Some(Duration::from_secs(1)),
+    });
+    let old = DataFragment::new(b"old".to_vec(), 1_000);
+    let recent = DataFragment::new(b"recent".to_vec(), 4_000);
+    state.add_fragment(old.clone()).unwrap();
+    state.add_fragment(recent).unwrap();
+
+    assert_eq!(state.collect_garbage(4_500), vec![old.cid().to_string()]);
+}
+
+#[test]
+fn byte_budget_evicts_until_it_fits() {
+    let mut state = ConfluxState::default();
+    state.set_limits(GcLimits {
+        max_fragments: None,
+        max_bytes: Some(8),
+        max_age: None,
+    });
+    let large = DataFragment::new(vec![1; 6], 1);
+    let small = DataFragment::new(vec![2; 4], 1);
+    state.add_fragment(large.clone()).unwrap();
+    state.add_fragment(small).unwrap();
+
+    assert_eq!(state.collect_garbage(10), vec![large.cid().to_string()]);
+}
+
+#[tokio::test]
+async fn only_stored_fragments_can_be_pinned() {
+    let (conflux, mut commands) = conflux();
+    let fragment = DataFragment::new(b"pin me".to_vec(), 1);
+
+    assert_eq!(
+        conflux.pin(fragment.cid()).await,
+        Err(ConfluxError::NotFound(fragment.cid().to_string()))
+    );
+    conflux.add(fragment.clone()).await.unwrap();
+    commands.recv().await;
+    assert_eq!(conflux.pin(fragment.cid()).await, Ok(()));
+    assert!(conflux.unpin(fragment.cid()).await);
+}
+
+#[test]
+fn encrypted_store_reads_back_what_it_wrote() {
+    let directory = temp_path("encrypted", "fragments");
+    let fragment = DataFragment::new(b"secret history".to_vec(), 1);
+    let mut store =
+        DiskFragmentStore::open_encrypted(&directory, StoreKey::from_passphrase("hunter2"))
+            .unwrap();
+
+    store.put(fragment.clone()).unwrap();
+
+    let on_disk = std::fs::read(directory.join(fragment.cid())).unwrap();
+    assert!(!on_disk
+        .windows(b"secret history".len())
+        .any(|x| x == b"secret history"));
+    assert_eq!(store.get(fragment.cid()).unwrap(), Some(fragment));
+}
+
+#[test]
+fn encrypted_store_refuses_another_key() {
+    let directory = temp_path("wrong_key", "fragments");
+    let fragment = DataFragment::new(b"secret".to_vec(), 1);
+    DiskFragmentStore::open_encrypted(&directory, StoreKey::from_passphrase("right"))
+        .unwrap()
+        .put(fragment.clone())
+        .unwrap();
+
+    let store =
+        DiskFragmentStore::open_encrypted(&directory, StoreKey::from_passphrase("wrong")).unwrap();
+
+    assert!(store.get(fragment.cid()).is_err());
+}
+
+#[test]
+fn identity_derives_the_same_store_key_every_time() {
+    let directory = temp_path("identity_key", "fragments");
+    let keystore = keystore();
+    let fragment = DataFragment::new(b"mine".to_vec(), 1);
+    DiskFragmentStore::open_encrypted(&directory, StoreKey::from_keystore(&keystore).unwrap())
+        .unwrap()
+        .put(fragment.clone())
+        .unwrap();
+
+    let reopened =
+        DiskFragmentStore::open_encrypted(&directory, StoreKey::from_keystore(&keystore).unwrap())
+            .unwrap();
+
+    assert_eq!(reopened.get(fragment.cid()).unwrap(), Some(fragment));
+}
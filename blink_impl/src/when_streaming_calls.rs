@@ -0,0 +1,169 @@
+use crate::peer_to_peer_service::BlinkCommand;
+use crate::streams::{self, StreamMessage, StreamRegistry};
+use blink_contract::StreamKind;
+use libp2p::PeerId;
+use std::time::Duration;
+
+const TIMEOUT_SECS: u64 = 1;
+
+#[test]
+fn the_callers_preferred_codec_we_support_is_picked() {
+    let offered = |codecs: &[&str]| codecs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        streams::negotiate_codec(StreamKind::Audio, &offered(&["aac", "opus"])),
+        Some("opus".to_string())
+    );
+    assert_eq!(
+        streams::negotiate_codec(StreamKind::Video, &offered(&["h264", "vp8"])),
+        Some("h264".to_string())
+    );
+    assert_eq!(
+        streams::negotiate_codec(StreamKind::Audio, &offered(&["vp8"])),
+        None
+    );
+}
+
+#[tokio::test]
+async fn frames_make_the_round_trip() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, mut sent) = tokio::sync::mpsc::channel(8);
+        let peer = PeerId::random();
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry.open(1, peer, StreamKind::Audio, commands).unwrap();
+
+        handle.send_frame(vec![1, 2]).await.unwrap();
+        assert!(registry.push_frame(1, &peer, 1, vec![3, 4], 0));
+
+        match sent.recv().await.unwrap() {
+            BlinkCommand::SendStreamMessage(to, StreamMessage::Frame(1, 1, frame)) => {
+                assert_eq!(to, peer);
+                assert_eq!(frame, vec![1, 2]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(handle.next_frame().await, Some(vec![3, 4]));
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn muted_streams_send_no_frames() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, mut sent) = tokio::sync::mpsc::channel(8);
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry
+            .open(1, PeerId::random(), StreamKind::Audio, commands)
+            .unwrap();
+
+        handle.mute(true).await.unwrap();
+        handle.send_frame(vec![1]).await.unwrap();
+        handle.mute(false).await.unwrap();
+        handle.send_frame(vec![2]).await.unwrap();
+
+        let mut messages = Vec::new();
+        while let Ok(BlinkCommand::SendStreamMessage(_, message)) = sent.try_recv() {
+            messages.push(message);
+        }
+        assert!(matches!(
+            messages[..],
+            [
+                StreamMessage::Mute(1, true),
+                StreamMessage::Mute(1, false),
+                StreamMessage::Frame(1, 1, _)
+            ]
+        ));
+        assert!(!handle.is_muted());
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn hanging_up_closes_the_stream() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, mut sent) = tokio::sync::mpsc::channel(8);
+        let mut registry = StreamRegistry::default();
+        let handle = registry
+            .open(1, PeerId::random(), StreamKind::Audio, commands)
+            .unwrap();
+
+        handle.hangup().await.unwrap();
+
+        assert!(matches!(
+            sent.recv().await,
+            Some(BlinkCommand::CloseStream(1))
+        ));
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn the_remote_hanging_up_ends_the_frames() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, _sent) = tokio::sync::mpsc::channel(8);
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry
+            .open(1, PeerId::random(), StreamKind::Audio, commands)
+            .unwrap();
+
+        assert!(registry.close(1));
+
+        assert_eq!(handle.next_frame().await, None);
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[test]
+fn a_live_stream_id_cannot_be_taken_over() {
+    let (commands, _sent) = tokio::sync::mpsc::channel(8);
+    let (caller, intruder) = (PeerId::random(), PeerId::random());
+    let mut registry = StreamRegistry::default();
+    registry
+        .open(1, caller, StreamKind::Audio, commands.clone())
+        .unwrap();
+
+    assert!(!registry.open_incoming(1, intruder, StreamKind::Video, commands.clone()));
+    assert!(registry
+        .open(1, intruder, StreamKind::Audio, commands)
+        .is_none());
+
+    assert_eq!(registry.peer_of(1), Some(caller));
+    assert_eq!(registry.kind_of(1), Some(StreamKind::Audio));
+}
+
+#[test]
+fn a_peer_can_only_leave_so_many_streams_waiting() {
+    let (commands, _sent) = tokio::sync::mpsc::channel(8);
+    let flooder = PeerId::random();
+    let mut registry = StreamRegistry::default();
+
+    let opened = (0..100)
+        .filter(|id| registry.open_incoming(*id, flooder, StreamKind::Audio, commands.clone()))
+        .count();
+
+    assert!(opened < 100);
+    assert!(registry.open_incoming(100, PeerId::random(), StreamKind::Audio, commands.clone()));
+    registry.take_incoming(0).unwrap();
+    assert!(registry.open_incoming(101, flooder, StreamKind::Audio, commands));
+}
+
+#[test]
+fn sequence_numbers_at_the_limit_wrap_around() {
+    let (commands, _sent) = tokio::sync::mpsc::channel(8);
+    let peer = PeerId::random();
+    let mut registry = StreamRegistry::default();
+    let _handle = registry.open(1, peer, StreamKind::Video, commands).unwrap();
+    let frame = streams::VideoFrame {
+        sequence: u64::MAX,
+        keyframe: true,
+        timestamp: 0,
+        data: Vec::new(),
+        screen: None,
+    };
+
+    assert!(!registry.push_video_frame(1, &peer, frame, 0));
+}
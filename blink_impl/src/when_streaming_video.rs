@@ -0,0 +1,124 @@
+use crate::streams::{Region, ScreenMetadata, StreamRegistry, VideoFrame};
+use blink_contract::{StreamKind, VideoCaps};
+use libp2p::{futures::StreamExt, PeerId};
+use std::time::Duration;
+
+const TIMEOUT_SECS: u64 = 1;
+
+fn frame(sequence: u64, keyframe: bool) -> VideoFrame {
+    VideoFrame {
+        sequence,
+        keyframe,
+        timestamp: sequence * 33,
+        data: vec![sequence as u8],
+        screen: None,
+    }
+}
+
+#[tokio::test]
+async fn deltas_are_dropped_until_a_keyframe_arrives() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, _commands_rx) = tokio::sync::mpsc::channel(8);
+        let peer = PeerId::random();
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry.open(1, peer, StreamKind::Video, commands).unwrap();
+        let mut video = handle.video_frames().unwrap();
+
+        assert!(registry.push_video_frame(1, &peer, frame(1, false), 0));
+        assert!(!registry.push_video_frame(1, &peer, frame(2, false), 0));
+        assert!(!registry.push_video_frame(1, &peer, frame(3, true), 0));
+        assert!(!registry.push_video_frame(1, &peer, frame(4, false), 0));
+
+        assert_eq!(video.next().await.unwrap().sequence, 3);
+        assert_eq!(video.next().await.unwrap().sequence, 4);
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn lost_frame_asks_for_a_keyframe_once() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, _commands_rx) = tokio::sync::mpsc::channel(8);
+        let peer = PeerId::random();
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry.open(1, peer, StreamKind::Video, commands).unwrap();
+        let mut video = handle.video_frames().unwrap();
+
+        assert!(!registry.push_video_frame(1, &peer, frame(1, true), 0));
+        assert!(registry.push_video_frame(1, &peer, frame(3, false), 0));
+        assert!(!registry.push_video_frame(1, &peer, frame(4, false), 0));
+        assert!(!registry.push_video_frame(1, &peer, frame(5, true), 0));
+
+        assert_eq!(video.next().await.unwrap().sequence, 1);
+        assert_eq!(video.next().await.unwrap().sequence, 5);
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn screen_share_frames_keep_their_region_metadata() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (commands, _commands_rx) = tokio::sync::mpsc::channel(8);
+        let peer = PeerId::random();
+        let mut registry = StreamRegistry::default();
+        let mut handle = registry
+            .open(1, peer, StreamKind::ScreenShare, commands)
+            .unwrap();
+        let mut video = handle.video_frames().unwrap();
+        let metadata = ScreenMetadata {
+            display_width: 1920,
+            display_height: 1080,
+            cursor: Some((10, 20)),
+            dirty_regions: vec![Region {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            }],
+        };
+        let mut keyframe = frame(1, true);
+        keyframe.screen = Some(metadata.clone());
+
+        registry.push_video_frame(1, &peer, keyframe, 0);
+
+        assert_eq!(video.next().await.unwrap().screen, Some(metadata));
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[test]
+fn frames_from_another_peer_are_ignored() {
+    let (commands, _commands_rx) = tokio::sync::mpsc::channel(8);
+    let mut registry = StreamRegistry::default();
+    let _handle = registry
+        .open(1, PeerId::random(), StreamKind::Video, commands)
+        .unwrap();
+
+    assert!(!registry.push_video_frame(1, &PeerId::random(), frame(1, false), 0));
+}
+
+#[test]
+fn negotiated_caps_are_the_lowest_of_both_ends() {
+    let caller = VideoCaps {
+        max_width: 1920,
+        max_height: 1080,
+        max_bitrate: 1_000_000,
+    };
+    let callee = VideoCaps {
+        max_width: 1280,
+        max_height: 720,
+        max_bitrate: 2_500_000,
+    };
+
+    assert_eq!(
+        caller.intersect(&callee),
+        VideoCaps {
+            max_width: 1280,
+            max_height: 720,
+            max_bitrate: 1_000_000,
+        }
+    );
+}
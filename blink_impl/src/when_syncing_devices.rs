@@ -0,0 +1,125 @@
+use crate::device_key::DeviceCertificate;
+use crate::device_sync::{self, DeviceSyncRequest};
+use crate::keystore::InMemoryKeystore;
+use crate::moderation::ModerationRecords;
+use crate::test_support::{keystore, text};
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use sata::Sata;
+use std::collections::HashMap;
+
+// A device of the keystore's DID with a transport key of its own
+fn device(keystore: &InMemoryKeystore) -> (PeerId, DeviceCertificate) {
+    let transport = Keypair::generate_ed25519();
+    let certificate = DeviceCertificate::new(keystore, &transport.public()).unwrap();
+    (transport.public().to_peer_id(), certificate)
+}
+
+fn messages(count: usize) -> Vec<(Sata, Option<u64>)> {
+    (0..count)
+        .map(|x| (text(&format!("message {}", x)), None))
+        .collect()
+}
+
+fn page(
+    messages: Vec<(Sata, Option<u64>)>,
+    offset: usize,
+    max_bytes: u64,
+) -> device_sync::DeviceSnapshot {
+    let (_, certificate) = device(&keystore());
+    device_sync::page(
+        certificate,
+        messages,
+        offset,
+        max_bytes,
+        HashMap::new(),
+        ModerationRecords::default(),
+    )
+}
+
+#[test]
+fn another_device_of_the_same_did_is_trusted() {
+    let keystore = keystore();
+    let (peer, certificate) = device(&keystore);
+
+    let request = DeviceSyncRequest::new(certificate, 0, true);
+
+    assert!(request.verify_sender(&keystore, &peer).is_ok());
+}
+
+#[test]
+fn a_device_of_another_did_is_rejected() {
+    let (peer, certificate) = device(&keystore());
+
+    let request = DeviceSyncRequest::new(certificate, 0, true);
+
+    assert!(request.verify_sender(&keystore(), &peer).is_err());
+}
+
+#[test]
+fn a_certificate_sent_from_another_peer_is_rejected() {
+    let keystore = keystore();
+    let (_, certificate) = device(&keystore);
+
+    let request = DeviceSyncRequest::new(certificate, 0, true);
+
+    assert!(request.verify_sender(&keystore, &PeerId::random()).is_err());
+}
+
+#[test]
+fn a_page_holds_what_fits_in_its_size() {
+    let messages = messages(10);
+    let size = bincode::serialized_size(&messages[0]).unwrap();
+
+    let page = page(messages, 0, size * 3);
+
+    assert_eq!(page.messages.len(), 3);
+    assert_eq!(page.next, Some(3));
+}
+
+#[test]
+fn every_message_is_sent_once_across_the_pages() {
+    let all = messages(10);
+    let size = bincode::serialized_size(&all[0]).unwrap();
+
+    let mut received = Vec::new();
+    let mut offset = Some(0);
+    while let Some(start) = offset {
+        let page = page(all.clone(), start, size * 4);
+        received.extend(
+            page.messages
+                .iter()
+                .map(|x| x.0.decode::<String>().unwrap()),
+        );
+        offset = page.next;
+    }
+
+    let expected: Vec<String> = (0..10).map(|x| format!("message {}", x)).collect();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn a_message_larger_than_a_page_still_goes() {
+    let page = page(messages(2), 0, 1);
+
+    assert_eq!(page.messages.len(), 1);
+    assert_eq!(page.next, Some(1));
+}
+
+#[test]
+fn a_device_without_messages_sends_a_single_page() {
+    let page = page(Vec::new(), 0, crate::protocol::PAGE_BYTES);
+
+    assert!(page.messages.is_empty());
+    assert_eq!(page.next, None);
+}
+
+#[test]
+fn a_disappearing_message_keeps_its_expiry_in_a_page() {
+    let messages = vec![(text("gone soon"), Some(1_000)), (text("kept"), None)];
+
+    let page = page(messages, 0, crate::protocol::PAGE_BYTES);
+
+    let expiries: Vec<Option<u64>> = page.messages.iter().map(|x| x.1).collect();
+    assert_eq!(expiries, vec![Some(1_000), None]);
+}
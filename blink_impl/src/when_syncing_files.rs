@@ -0,0 +1,101 @@
+use crate::constellation::{normalize, SyncIndex, SyncedFile};
+
+fn file(cid: &str) -> SyncedFile {
+    SyncedFile {
+        cid: cid.to_string(),
+        size: 4,
+    }
+}
+
+fn index(paths: &[&str]) -> SyncIndex {
+    let mut index = SyncIndex::default();
+    for path in paths {
+        index.insert(path.to_string(), file(path)).unwrap();
+    }
+    index
+}
+
+#[test]
+fn paths_are_normalized_and_never_leave_the_root() {
+    assert_eq!(normalize("/docs//./notes.txt").unwrap(), "docs/notes.txt");
+    assert_eq!(normalize("/").unwrap(), "");
+    assert!(normalize("docs/../../etc/passwd").is_err());
+}
+
+#[test]
+fn files_create_their_parent_directories() {
+    let mut index = index(&["docs/notes.txt"]);
+
+    assert!(index.is_directory("docs"));
+    assert!(index
+        .insert("docs/notes.txt/inner".into(), file("x"))
+        .is_err());
+    assert!(index.insert("docs".into(), file("x")).is_err());
+}
+
+#[test]
+fn replacing_a_file_returns_the_old_one() {
+    let mut index = index(&["notes.txt"]);
+
+    let replaced = index.insert("notes.txt".into(), file("new")).unwrap();
+
+    assert_eq!(replaced, Some(file("notes.txt")));
+    assert!(!index.references("notes.txt"));
+}
+
+#[test]
+fn directories_need_a_parent_unless_recursive() {
+    let mut index = SyncIndex::default();
+
+    assert!(index.create_directory("a/b".into(), false).is_err());
+    index.create_directory("a/b".into(), true).unwrap();
+    index.create_directory("a/b/c".into(), false).unwrap();
+
+    assert!(index.is_directory("a/b/c"));
+}
+
+#[test]
+fn only_empty_directories_are_removed_unless_recursive() {
+    let mut index = index(&["docs/a.txt", "docs/deep/b.txt", "other.txt"]);
+
+    assert!(index.remove("docs", false).is_err());
+    let removed = index.remove("docs", true).unwrap();
+
+    assert_eq!(removed.len(), 2);
+    assert!(!index.is_directory("docs"));
+    assert!(index.file("other.txt").is_some());
+    assert!(index.remove("missing", true).is_err());
+}
+
+#[test]
+fn shared_directory_is_mounted_where_asked() {
+    let shared = index(&["photos/a.jpg", "photos/trip/b.jpg", "notes.txt"]).subtree("photos");
+    let mut index = SyncIndex::default();
+
+    index.mount("from-bob", shared).unwrap();
+
+    assert_eq!(index.file("from-bob/a.jpg"), Some(&file("photos/a.jpg")));
+    assert_eq!(
+        index.file("from-bob/trip/b.jpg"),
+        Some(&file("photos/trip/b.jpg"))
+    );
+    assert!(index.file("from-bob/notes.txt").is_none());
+}
+
+#[test]
+fn shared_index_cannot_escape_its_mount_point() {
+    let mut shared = SyncIndex::default();
+    shared.insert("../escape".into(), file("x")).unwrap();
+
+    assert!(SyncIndex::default().mount("from-bob", shared).is_err());
+}
+
+#[test]
+fn directory_tree_lists_files_and_empty_directories() {
+    let mut index = index(&["docs/a.txt", "b.txt"]);
+    index.create_directory("empty".into(), false).unwrap();
+
+    let root = index.directory("root").unwrap();
+
+    assert_eq!(root.get_items().len(), 3);
+}
@@ -0,0 +1,46 @@
+use crate::snapshot::{NetworkSnapshot, SnapshotStore};
+use crate::test_support::temp_path;
+use libp2p::PeerId;
+
+#[test]
+fn peers_and_topics_survive_a_restart() {
+    let path = temp_path("restart", "snapshot");
+    let peer = PeerId::random();
+    let saved = NetworkSnapshot {
+        peers: vec![(
+            peer.to_string(),
+            vec!["/ip4/10.0.0.1/tcp/4001".parse().unwrap()],
+        )],
+        topics: vec!["pairwise".into(), "pairwise/channel/general".into()],
+    };
+    {
+        let mut store = SnapshotStore::default();
+        store.open(&path).unwrap();
+        store.save(&saved).unwrap();
+    }
+
+    let restored = SnapshotStore::default().open(&path).unwrap();
+
+    assert_eq!(restored, saved);
+    assert_eq!(restored.peers()[0].0, peer);
+}
+
+#[test]
+fn nothing_is_restored_the_first_time() {
+    let path = temp_path("first", "snapshot");
+    let mut store = SnapshotStore::default();
+
+    assert!(!store.is_enabled());
+    assert_eq!(store.open(&path).unwrap(), NetworkSnapshot::default());
+    assert!(store.is_enabled());
+}
+
+#[test]
+fn peers_that_dont_parse_are_skipped() {
+    let snapshot = NetworkSnapshot {
+        peers: vec![("not a peer".into(), Vec::new())],
+        topics: Vec::new(),
+    };
+
+    assert!(snapshot.peers().is_empty());
+}
@@ -0,0 +1,24 @@
+use crate::presence::Presence;
+use blink_contract::Status;
+
+#[test]
+fn own_status_is_only_announced_when_it_changes() {
+    let mut presence = Presence::default();
+
+    assert_eq!(presence.own(), Status::Online);
+    assert!(!presence.set_own(Status::Online));
+    assert!(presence.set_own(Status::DoNotDisturb));
+    assert_eq!(presence.own(), Status::DoNotDisturb);
+}
+
+#[test]
+fn contact_status_changes_only_when_it_differs() {
+    let mut presence = Presence::default();
+
+    assert_eq!(presence.contact("did:key:bob"), None);
+    assert!(presence.announced("did:key:bob".into(), Status::Online));
+    assert!(!presence.announced("did:key:bob".into(), Status::Online));
+    assert!(presence.announced("did:key:bob".into(), Status::Away));
+    assert_eq!(presence.contact("did:key:bob"), Some(Status::Away));
+    assert_eq!(presence.contact("did:key:alice"), None);
+}
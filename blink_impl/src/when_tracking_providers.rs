@@ -0,0 +1,88 @@
+use crate::event_sink::EventSink;
+use crate::providers::ProviderTracker;
+use crate::when_using_peer_to_peer_service::LogHandler;
+use blink_contract::Event;
+use libp2p::PeerId;
+use std::collections::HashSet;
+use std::sync::Arc;
+use warp::sync::RwLock;
+
+const CID: &str = "bafy-provided";
+
+fn logger() -> (EventSink, Arc<RwLock<LogHandler>>) {
+    let log = Arc::new(RwLock::new(LogHandler { events: Vec::new() }));
+    let (logger, deliver) = EventSink::new(log.clone());
+    tokio::spawn(deliver);
+    (logger, log)
+}
+
+fn at_risk(log: &RwLock<LogHandler>) -> Vec<String> {
+    log.read()
+        .events
+        .iter()
+        .filter_map(|x| match x {
+            Event::ContentAtRisk(cid) => Some(cid.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn content_others_provide_is_not_at_risk() {
+    let (logger, log) = logger();
+    let local = PeerId::random();
+    let mut tracker = ProviderTracker::default();
+    tracker.track(CID.into());
+
+    let providers = HashSet::from([local, PeerId::random(), PeerId::random()]);
+    tracker.providers_found(&logger, CID.into(), &providers, &local);
+    logger.flush().await;
+
+    assert_eq!(tracker.availability(CID), Some(2));
+    assert!(at_risk(&log).is_empty());
+}
+
+#[tokio::test]
+async fn content_is_at_risk_once_the_other_providers_are_gone() {
+    let (logger, log) = logger();
+    let local = PeerId::random();
+    let remote = PeerId::random();
+    let mut tracker = ProviderTracker::default();
+    tracker.track(CID.into());
+
+    tracker.providers_found(&logger, CID.into(), &HashSet::from([local, remote]), &local);
+    tracker.providers_found(&logger, CID.into(), &HashSet::from([local]), &local);
+    logger.flush().await;
+
+    assert_eq!(tracker.availability(CID), Some(0));
+    assert_eq!(at_risk(&log), vec![CID.to_string()]);
+}
+
+#[tokio::test]
+async fn content_nobody_else_was_found_for_is_at_risk() {
+    let (logger, log) = logger();
+    let local = PeerId::random();
+    let mut tracker = ProviderTracker::default();
+    tracker.track(CID.into());
+
+    tracker.providers_found(&logger, CID.into(), &HashSet::new(), &local);
+    logger.flush().await;
+
+    assert_eq!(at_risk(&log), vec![CID.to_string()]);
+}
+
+#[tokio::test]
+async fn content_we_stopped_providing_is_never_at_risk() {
+    let (logger, log) = logger();
+    let local = PeerId::random();
+    let mut tracker = ProviderTracker::default();
+    tracker.track(CID.into());
+    assert!(tracker.untrack(CID));
+
+    tracker.providers_found(&logger, CID.into(), &HashSet::new(), &local);
+    logger.flush().await;
+
+    assert_eq!(tracker.availability(CID), None);
+    assert!(tracker.provided().is_empty());
+    assert!(at_risk(&log).is_empty());
+}
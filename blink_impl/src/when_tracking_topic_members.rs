@@ -0,0 +1,45 @@
+use crate::membership::TopicMembers;
+use libp2p::PeerId;
+
+#[test]
+fn subscriptions_are_tracked_per_topic() {
+    let mut members = TopicMembers::default();
+    let alice = PeerId::random();
+    let bob = PeerId::random();
+
+    assert!(members.subscribed("ab".into(), alice));
+    assert!(!members.subscribed("ab".into(), alice));
+    assert!(members.subscribed("ac".into(), bob));
+
+    assert_eq!(members.peers("ab"), vec![alice]);
+    assert_eq!(members.peers("ac"), vec![bob]);
+    assert!(members.peers("ad").is_empty());
+}
+
+#[test]
+fn unsubscribed_peers_are_forgotten() {
+    let mut members = TopicMembers::default();
+    let alice = PeerId::random();
+    members.subscribed("ab".into(), alice);
+
+    assert!(members.unsubscribed("ab", &alice));
+    assert!(!members.unsubscribed("ab", &alice));
+    assert!(!members.has_members("ab"));
+}
+
+#[test]
+fn disconnecting_leaves_every_topic() {
+    let mut members = TopicMembers::default();
+    let alice = PeerId::random();
+    let bob = PeerId::random();
+    members.subscribed("ab".into(), alice);
+    members.subscribed("ac".into(), alice);
+    members.subscribed("ac".into(), bob);
+
+    let mut left = members.disconnected(&alice);
+    left.sort();
+
+    assert_eq!(left, vec!["ab", "ac"]);
+    assert!(!members.has_members("ab"));
+    assert_eq!(members.peers("ac"), vec![bob]);
+}
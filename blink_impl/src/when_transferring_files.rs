@@ -0,0 +1,209 @@
+use crate::file_transfer::{
+    new_behaviour, ChunkOutcome, FileOffer, FileTransferBehaviour, TransferRegistry,
+    TransferRequest, MAX_PENDING_OFFERS,
+};
+use crate::test_support::temp_path;
+use libp2p::PeerId;
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+// Two full chunks and a partial one, each with different bytes
+fn source_file(name: &str) -> (PathBuf, Vec<u8>) {
+    let path = temp_path(name, "transfer");
+    let content: Vec<u8> = (0..2 * CHUNK_SIZE + 1000).map(|x| (x / 7) as u8).collect();
+    std::fs::write(&path, &content).unwrap();
+    (path, content)
+}
+
+struct Transfer {
+    sender: TransferRegistry,
+    receiver: TransferRegistry,
+    sender_peer: PeerId,
+    receiver_peer: PeerId,
+    // Only used to hand out request ids
+    behaviour: FileTransferBehaviour,
+}
+
+impl Transfer {
+    fn offer(source: &Path) -> Self {
+        let offer = FileOffer::from_file(source).unwrap();
+        let sender_peer = PeerId::random();
+        let receiver_peer = PeerId::random();
+        let mut sender = TransferRegistry::default();
+        sender.offer(1, receiver_peer, source.to_path_buf(), offer.clone());
+        let mut receiver = TransferRegistry::default();
+        receiver.incoming_offer(1, sender_peer, offer).unwrap();
+        Self {
+            sender,
+            receiver,
+            sender_peer,
+            receiver_peer,
+            behaviour: new_behaviour(),
+        }
+    }
+
+    fn fetch(&mut self, index: usize, chunk: Vec<u8>) -> ChunkOutcome {
+        let request_id = self
+            .behaviour
+            .send_request(&self.sender_peer, TransferRequest::Fetch(1, index));
+        self.receiver.track_fetch(request_id, 1, index);
+        let (id, outcome) = self.receiver.chunk_received(&request_id, chunk).unwrap();
+        assert_eq!(id, 1);
+        outcome.unwrap()
+    }
+
+    fn pull_all(&mut self) -> Vec<ChunkOutcome> {
+        let mut outcomes = Vec::new();
+        while let Some((_, indices)) = self.receiver.next_fetches(1) {
+            if indices.is_empty() {
+                break;
+            }
+            for index in indices {
+                let (chunk, _, _) = self.sender.serve(1, &self.receiver_peer, index).unwrap();
+                outcomes.push(self.fetch(index, chunk));
+            }
+        }
+        outcomes
+    }
+}
+
+#[test]
+fn received_file_matches_the_source() {
+    let (source, content) = source_file("source");
+    let destination = temp_path("destination", "transfer");
+    let mut transfer = Transfer::offer(&source);
+
+    assert!(!transfer.receiver.accept(1, &destination).unwrap());
+    let outcomes = transfer.pull_all();
+
+    assert_eq!(outcomes.len(), 3);
+    assert!(
+        matches!(outcomes[0], ChunkOutcome::Progress(_, total) if total == content.len() as u64)
+    );
+    assert!(matches!(outcomes[2], ChunkOutcome::Completed(total) if total == content.len() as u64));
+    assert_eq!(std::fs::read(&destination).unwrap(), content);
+}
+
+#[test]
+fn chunk_not_matching_its_cid_is_refused() {
+    let (source, _) = source_file("tampered");
+    let destination = temp_path("tampered_destination", "transfer");
+    let mut transfer = Transfer::offer(&source);
+    transfer.receiver.accept(1, &destination).unwrap();
+    transfer.receiver.next_fetches(1);
+
+    let request_id = transfer
+        .behaviour
+        .send_request(&transfer.sender_peer, TransferRequest::Fetch(1, 0));
+    transfer.receiver.track_fetch(request_id, 1, 0);
+    let (_, outcome) = transfer
+        .receiver
+        .chunk_received(&request_id, vec![0; CHUNK_SIZE])
+        .unwrap();
+
+    assert!(outcome.is_err());
+}
+
+#[test]
+fn accepting_into_a_partial_file_only_fetches_what_is_missing() {
+    let (source, content) = source_file("resumed");
+    let destination = temp_path("resumed_destination", "transfer");
+    std::fs::write(&destination, &content[..2 * CHUNK_SIZE]).unwrap();
+    let mut transfer = Transfer::offer(&source);
+
+    transfer.receiver.accept(1, &destination).unwrap();
+
+    assert_eq!(transfer.receiver.next_fetches(1).unwrap().1, vec![2]);
+}
+
+#[test]
+fn accepting_a_file_already_there_completes_right_away() {
+    let (source, content) = source_file("complete");
+    let destination = temp_path("complete_destination", "transfer");
+    std::fs::write(&destination, &content).unwrap();
+    let mut transfer = Transfer::offer(&source);
+
+    assert!(transfer.receiver.accept(1, &destination).unwrap());
+}
+
+#[test]
+fn failed_fetch_is_asked_for_again() {
+    let (source, _) = source_file("retried");
+    let destination = temp_path("retried_destination", "transfer");
+    let mut transfer = Transfer::offer(&source);
+    transfer.receiver.accept(1, &destination).unwrap();
+    transfer.receiver.next_fetches(1);
+
+    let request_id = transfer
+        .behaviour
+        .send_request(&transfer.sender_peer, TransferRequest::Fetch(1, 1));
+    transfer.receiver.track_fetch(request_id, 1, 1);
+    assert_eq!(transfer.receiver.fetch_failed(&request_id), Some(1));
+
+    assert_eq!(transfer.receiver.next_fetches(1).unwrap().1, vec![1]);
+}
+
+#[test]
+fn only_the_offered_peer_gets_chunks() {
+    let (source, _) = source_file("stranger");
+    let mut transfer = Transfer::offer(&source);
+
+    assert!(transfer.sender.serve(1, &PeerId::random(), 0).is_none());
+    assert!(transfer
+        .sender
+        .serve(1, &transfer.receiver_peer, 0)
+        .is_some());
+}
+
+#[test]
+fn offers_whose_chunks_dont_add_up_to_the_size_are_refused() {
+    let (source, _) = source_file("inconsistent");
+    let offer = FileOffer::from_file(&source).unwrap();
+    let mut receiver = TransferRegistry::default();
+
+    for size in [0, CHUNK_SIZE as u64, 4 * CHUNK_SIZE as u64] {
+        let mut forged = offer.clone();
+        forged.size = size;
+        assert!(receiver
+            .incoming_offer(1, PeerId::random(), forged)
+            .is_err());
+    }
+    assert!(receiver.next_fetches(1).is_none());
+    assert!(receiver.incoming_offer(1, PeerId::random(), offer).is_ok());
+}
+
+#[test]
+fn an_id_in_use_isnt_taken_over_by_another_offer() {
+    let (source, _) = source_file("hijacked");
+    let mut transfer = Transfer::offer(&source);
+    let offer = FileOffer::from_file(&source).unwrap();
+
+    assert!(transfer
+        .receiver
+        .incoming_offer(1, PeerId::random(), offer.clone())
+        .is_err());
+    assert!(transfer
+        .sender
+        .incoming_offer(1, transfer.receiver_peer, offer)
+        .is_err());
+    assert_eq!(transfer.receiver.sender_of(1), Some(transfer.sender_peer));
+}
+
+#[test]
+fn a_peer_has_a_limited_number_of_offers_waiting() {
+    let (source, _) = source_file("flooded");
+    let offer = FileOffer::from_file(&source).unwrap();
+    let mut receiver = TransferRegistry::default();
+    let peer = PeerId::random();
+    for id in 0..MAX_PENDING_OFFERS as u64 {
+        receiver.incoming_offer(id, peer, offer.clone()).unwrap();
+    }
+
+    assert!(receiver.incoming_offer(100, peer, offer.clone()).is_err());
+    assert!(receiver
+        .incoming_offer(100, PeerId::random(), offer.clone())
+        .is_ok());
+    receiver.remove(0, None);
+    assert!(receiver.incoming_offer(101, peer, offer).is_ok());
+}
@@ -1,11 +1,17 @@
+use crate::clock::SystemClock;
+use crate::keystore::InMemoryKeystore;
 use crate::peer_to_peer_service::{MessageContent, PeerToPeerService};
-use blink_contract::{Event, EventBus};
-use did_key::Ed25519KeyPair;
+use crate::test_support::did;
+use crate::CancellationToken;
+use blink_contract::{
+    BlinkError, Event, EventBus, ExtensionHandler, MessageValidator, RecipientError, Status,
+    StreamKind, Validation,
+};
 use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::Multiaddr;
 use sata::libipld::IpldCodec;
 use sata::{Kind, Sata};
-use std::{sync::atomic::AtomicBool, sync::Arc, time::Duration};
+use std::{sync::Arc, time::Duration};
 use tokio::sync::mpsc::Receiver;
 use warp::sync::RwLock;
 use warp::{
@@ -22,17 +28,20 @@ use warp::{
 
 const TIMEOUT_SECS: u64 = 1;
 
+// In-process, services connect instantly and nothing touches the network
+const LISTEN_ADDRESS: &str = "/memory/0";
+
 #[derive(Default)]
-struct TestCache {
-    data_added: Vec<(DataType, Sata)>,
+pub(super) struct TestCache {
+    pub(super) data_added: Vec<(DataType, Sata)>,
 }
 
-struct MultiPassImpl {
+pub(super) struct MultiPassImpl {
     pass_as_valid: bool,
 }
 
 impl MultiPassImpl {
-    fn new(pass_as_valid: bool) -> Self {
+    pub(super) fn new(pass_as_valid: bool) -> Self {
         Self { pass_as_valid }
     }
 }
@@ -124,7 +133,7 @@ impl PocketDimension for TestCache {
     }
 }
 
-struct LogHandler {
+pub(super) struct LogHandler {
     pub events: Vec<Event>,
 }
 
@@ -140,10 +149,18 @@ impl EventBus for LogHandler {
     }
 }
 
-async fn create_service(
-    initial_address: Vec<Multiaddr>,
-    pass_multi_pass_validation_requests: bool,
-) -> (
+#[derive(Default)]
+struct TestExtension {
+    received: Vec<(String, Sata)>,
+}
+
+impl ExtensionHandler for TestExtension {
+    fn message_received(&mut self, sender: String, data: Sata) {
+        self.received.push((sender, data));
+    }
+}
+
+pub(super) type TestService = (
     PeerToPeerService,
     Arc<RwLock<LogHandler>>,
     Arc<RwLock<TestCache>>,
@@ -151,17 +168,52 @@ async fn create_service(
     Arc<DID>,
     Vec<Multiaddr>,
     Receiver<MessageContent>,
-) {
-    let id_keys = Arc::new(DID::from(did_key::generate::<Ed25519KeyPair>(None)));
-    let cancellation_token = Arc::new(AtomicBool::new(false));
+);
+
+pub(super) async fn create_service(
+    initial_address: Vec<Multiaddr>,
+    pass_multi_pass_validation_requests: bool,
+) -> TestService {
+    let id_keys = Arc::new(did());
+    create_service_with_keys(
+        id_keys,
+        initial_address,
+        pass_multi_pass_validation_requests,
+    )
+    .await
+}
+
+// Same identity as an earlier service, as if it restarted
+pub(super) async fn create_service_with_keys(
+    id_keys: Arc<DID>,
+    initial_address: Vec<Multiaddr>,
+    pass_multi_pass_validation_requests: bool,
+) -> TestService {
+    create_cancellable_service(
+        id_keys,
+        initial_address,
+        pass_multi_pass_validation_requests,
+        CancellationToken::new(),
+    )
+    .await
+}
+
+async fn create_cancellable_service(
+    id_keys: Arc<DID>,
+    initial_address: Vec<Multiaddr>,
+    pass_multi_pass_validation_requests: bool,
+    cancellation_token: CancellationToken,
+) -> TestService {
     let cache = Arc::new(RwLock::new(TestCache::default()));
     let log_handler = Arc::new(RwLock::new(LogHandler::new()));
     let multi_pass = Arc::new(RwLock::new(MultiPassImpl::new(
         pass_multi_pass_validation_requests,
     )));
+    let keystore = Arc::new(InMemoryKeystore::new(id_keys.clone()).unwrap());
     let (service, receiver) = PeerToPeerService::new(
-        id_keys.clone(),
-        "/ip4/0.0.0.0/tcp/0",
+        keystore,
+        Arc::new(SystemClock),
+        LISTEN_ADDRESS,
         Some(initial_address),
         cache.clone(),
         multi_pass.clone(),
@@ -171,21 +223,11 @@ async fn create_service(
     .await
     .unwrap();
 
-    let mut addr_to_send = None;
-    let mut break_loop = false;
-    while !break_loop {
-        for event in &log_handler.read().events {
-            if let Event::NewListenAddr(addr) = event {
-                break_loop = true;
-                addr_to_send = Some(addr.clone());
-                break;
-            }
-        }
-        tokio::time::sleep(Duration::from_millis(10)).await;
-    }
-
-    let mut map = Vec::new();
-    map.push(addr_to_send.unwrap());
+    service
+        .ready(false, Duration::from_secs(TIMEOUT_SECS))
+        .await
+        .unwrap();
+    let map = service.listen_addrs();
 
     (
         service,
@@ -207,6 +249,54 @@ async fn open_does_not_throw() {
     .expect("timeout");
 }
 
+#[tokio::test]
+async fn cancelling_the_token_stops_the_service() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let cancellation_token = CancellationToken::new();
+        let id_keys = Arc::new(did());
+        let (service, log_handler, _, _, _, _, _) =
+            create_cancellable_service(id_keys, Vec::new(), true, cancellation_token.clone()).await;
+
+        cancellation_token.cancel();
+        service.wait_for_shutdown().await;
+
+        let events = &log_handler.read().events;
+        assert_eq!(
+            events
+                .iter()
+                .filter(|x| matches!(x, Event::TaskCancelled))
+                .count(),
+            1
+        );
+    })
+    .await
+    .expect("timeout");
+}
+
+#[tokio::test]
+async fn failed_dials_are_reported() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+        let nobody: Multiaddr = "/ip4/127.0.0.1/tcp/1".parse().unwrap();
+
+        service
+            .pair_to_another_peer(DialOpts::unknown_peer_id().address(nobody).build())
+            .await
+            .unwrap();
+
+        service
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::OutgoingConnectionError(None, _)),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+    })
+    .await
+    .expect("timeout");
+}
+
 #[tokio::test]
 async fn connecting_to_peer_does_not_generate_errors() {
     tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
@@ -218,7 +308,6 @@ async fn connecting_to_peer_does_not_generate_errors() {
         pair_to_another_peer(
             &mut service_b.0,
             service_a.5.first().unwrap().clone().into(),
-            service_b.1,
         )
         .await;
     })
@@ -231,13 +320,12 @@ async fn message_reaches_other_client() {
     tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
         let mut second_client = create_service(Vec::new(), true).await;
 
-        let (mut first_client, first_client_log_handler, _, _, _, _, _) =
+        let (mut first_client, _, _, _, _, _, _) =
             create_service(second_client.5.clone(), true).await;
 
         let (did_from_pair, _) = pair_to_another_peer(
             &mut first_client,
             second_client.5.first().unwrap().clone().into(),
-            first_client_log_handler.clone(),
         )
         .await;
 
@@ -254,18 +342,81 @@ async fn message_reaches_other_client() {
     .expect("Timeout");
 }
 
+#[tokio::test]
+async fn services_connected_in_memory_reach_each_other() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut services = connected_services(3).await;
+        let mut sender = services.pop().unwrap();
+        let mut some_data = Sata::default();
+        for (_, _, _, _, did, _, _) in &services {
+            some_data.add_recipient(did.as_ref()).unwrap();
+        }
+
+        sender.0.send(some_data).await.unwrap();
+
+        for service in &mut services {
+            assert_message(&mut service.6).await;
+        }
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn peer_is_ready_once_it_subscribed_to_the_pairwise_topic() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+
+        let (mut first_client, _, _, _, _, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+        first_client
+            .await_peer_ready(&did_from_pair, Duration::from_secs(TIMEOUT_SECS))
+            .await
+            .unwrap();
+
+        let mut some_data = Sata::default();
+        some_data.add_recipient(did_from_pair.as_ref()).unwrap();
+        first_client.send(some_data).await.unwrap();
+
+        assert_message(&mut second_client.6).await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn waiting_for_an_unpaired_peer_times_out() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+        let stranger = did();
+
+        let result = service
+            .await_peer_ready(&stranger, Duration::from_millis(100))
+            .await;
+
+        assert!(result.is_err());
+    })
+    .await
+    .expect("Timeout");
+}
+
 #[tokio::test]
 async fn message_to_another_client_is_added_to_cache() {
     tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
         let second_client = create_service(Vec::new(), true).await;
 
-        let (mut first_client, first_client_log_handler, _, _, _, _, _) =
+        let (mut first_client, _, _, _, _, _, _) =
             create_service(second_client.5.clone(), true).await;
 
         let (did_from_pair, _) = pair_to_another_peer(
             &mut first_client,
             second_client.5.first().unwrap().clone().into(),
-            first_client_log_handler.clone(),
         )
         .await;
 
@@ -290,7 +441,7 @@ async fn failure_to_identify_peer_causes_error() {
     tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
         let first_client = create_service(Vec::new(), false).await;
 
-        let (mut second_client, log_handler_second_client, _, _, _, _, _) =
+        let (mut second_client, _, _, _, _, _, _) =
             create_service(first_client.5.clone(), false).await;
 
         second_client
@@ -298,53 +449,55 @@ async fn failure_to_identify_peer_causes_error() {
             .await
             .unwrap();
 
-        let mut found_error = false;
-        while !found_error {
-            for event in &log_handler_second_client.read().events {
-                if let Event::FailureToIdentifyPeer = event {
-                    found_error = true;
-                }
-            }
-            tokio::time::sleep(Duration::from_millis(10)).await;
-        }
+        second_client
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::FailureToIdentifyPeer),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
     })
     .await
     .expect("Timeout");
 }
 
-async fn pair_to_another_peer(
+pub(super) async fn pair_to_another_peer(
     service: &mut PeerToPeerService,
     dial_opts: DialOpts,
-    logger: Arc<RwLock<LogHandler>>,
 ) -> (DID, String) {
+    // Peers identify again now and then, so a topic for one paired earlier says nothing
+    let paired = service.paired();
     service.pair_to_another_peer(dial_opts).await.unwrap();
 
-    let mut found_event = false;
-    let mut result = None;
-    let mut to_remove = 0;
+    let generated = service
+        .wait_for_event_since(
+            0,
+            |x| matches!(x, Event::GeneratedTopic(did, _) if !paired.contains_key(&did.to_string())),
+            Duration::from_secs(TIMEOUT_SECS),
+        )
+        .await
+        .unwrap();
+    match generated {
+        Event::GeneratedTopic(did, topic) => (did, topic),
+        _ => unreachable!(),
+    }
+}
 
-    while !found_event {
-        for (i, event) in logger.read().events.iter().enumerate() {
-            if let Event::GeneratedTopic(did, topic) = event {
-                result = Some((did.clone(), topic.clone()));
-                found_event = true;
-                to_remove = i;
-                break;
-            }
-        }
-        if !found_event {
-            tokio::time::sleep(Duration::from_millis(10)).await;
+// Services paired with every other one, in the order they were started.
+pub(super) async fn connected_services(count: usize) -> Vec<TestService> {
+    let mut services: Vec<TestService> = Vec::new();
+    for _ in 0..count {
+        let mut service = create_service(Vec::new(), true).await;
+        for other in &services {
+            pair_to_another_peer(&mut service.0, other.5[0].clone().into()).await;
         }
+        services.push(service);
     }
-
-    let event_number = logger.read().events.len();
-    logger.write().events.swap_remove(to_remove);
-    assert_eq!(event_number - 1, logger.read().events.len());
-
-    result.unwrap()
+    services
 }
 
-async fn assert_message(receiver: &mut Receiver<MessageContent>) {
+pub(super) async fn assert_message(receiver: &mut Receiver<MessageContent>) {
     let mut message_received = false;
 
     while !message_received {
@@ -363,12 +516,10 @@ async fn send_message_sends_it_to_every_recipient() {
         let mut client_b = create_service(Vec::new(), true).await;
         client_a.5.extend(client_b.5.into_iter());
 
-        let (mut service_c, c_log, _, _, _, _, _) = create_service(client_a.5.clone(), true).await;
+        let (mut service_c, _, _, _, _, _, _) = create_service(client_a.5.clone(), true).await;
 
-        let (did_a, _) =
-            pair_to_another_peer(&mut service_c, client_a.5[0].clone().into(), c_log.clone()).await;
-        let (did_b, _) =
-            pair_to_another_peer(&mut service_c, client_a.5[1].clone().into(), c_log.clone()).await;
+        let (did_a, _) = pair_to_another_peer(&mut service_c, client_a.5[0].clone().into()).await;
+        let (did_b, _) = pair_to_another_peer(&mut service_c, client_a.5[1].clone().into()).await;
 
         assert_ne!(did_a, did_b);
 
@@ -381,10 +532,426 @@ async fn send_message_sends_it_to_every_recipient() {
             .unwrap();
         assert_eq!(to_send.recipients().as_ref().unwrap().len(), 2);
 
-        service_c.send(to_send).await.unwrap();
+        let report = service_c.send(to_send).await.unwrap();
+
+        assert_eq!(report.len(), 2);
+        assert!(report.values().all(|x| x.is_ok()));
+        assert_message(&mut client_a.6).await;
+        assert_message(&mut client_b.6).await;
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn transaction_completes_once_every_message_was_sent() {
+    tokio::time::timeout(Duration::from_secs(7), async {
+        let mut client_a = create_service(Vec::new(), true).await;
+        let mut client_b = create_service(Vec::new(), true).await;
+        client_a.5.extend(client_b.5.into_iter());
+
+        let (mut service_c, _, _, _, _, _, _) = create_service(client_a.5.clone(), true).await;
+
+        let (did_a, _) = pair_to_another_peer(&mut service_c, client_a.5[0].clone().into()).await;
+        let (did_b, _) = pair_to_another_peer(&mut service_c, client_a.5[1].clone().into()).await;
+
+        let mut to_a = Sata::default();
+        to_a.add_recipient(did_a.as_ref()).unwrap();
+        let mut to_b = Sata::default();
+        to_b.add_recipient(did_b.as_ref()).unwrap();
+
+        let id = service_c.send_transaction(vec![to_a, to_b]).await.unwrap();
 
         assert_message(&mut client_a.6).await;
         assert_message(&mut client_b.6).await;
+
+        service_c
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::TransactionCompleted(completed_id) if *completed_id == id),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn extension_messages_reach_the_extension_handler() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+        let second_extension = Arc::new(RwLock::new(TestExtension::default()));
+        second_client
+            .0
+            .register_extension("games", second_extension.clone())
+            .await
+            .unwrap();
+
+        let (mut first_client, _, _, _, first_did, _, _) =
+            create_service(second_client.5.clone(), true).await;
+        first_client
+            .register_extension("games", Arc::new(RwLock::new(TestExtension::default())))
+            .await
+            .unwrap();
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+
+        let mut some_data = Sata::default();
+        some_data.add_recipient(did_from_pair.as_ref()).unwrap();
+
+        first_client
+            .send_to_extension("games", some_data)
+            .await
+            .unwrap();
+
+        while second_extension.read().received.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(second_extension.read().received[0].0, first_did.to_string());
+        assert!(second_client.6.try_recv().is_err());
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn paired_peers_learn_each_other_extensions() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+        second_client
+            .0
+            .register_extension("games", Arc::new(RwLock::new(TestExtension::default())))
+            .await
+            .unwrap();
+
+        let (mut first_client, _, _, _, _, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+
+        while first_client.peer_extensions(&did_from_pair).is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            first_client.peer_extensions(&did_from_pair),
+            Some(vec!["games".to_string()])
+        );
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn paired_peers_hear_each_other_status() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+        second_client.0.set_status(Status::Away).await.unwrap();
+
+        let (mut first_client, _, _, _, _, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+
+        while first_client.contact_status(&did_from_pair).is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            first_client.contact_status(&did_from_pair),
+            Some(Status::Away)
+        );
+
+        second_client
+            .0
+            .set_status(Status::DoNotDisturb)
+            .await
+            .unwrap();
+        while first_client.contact_status(&did_from_pair) != Some(Status::DoNotDisturb) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn channel_messages_come_out_under_their_channel() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+
+        let (mut first_client, _, _, _, first_did, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+        second_client
+            .0
+            .open_channels(None, &["signals"])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            first_client.channel_topic(&did_from_pair, "call"),
+            Err(BlinkError::Invalid(_))
+        ));
+        let mut some_data = Sata::default();
+        some_data.add_recipient(did_from_pair.as_ref()).unwrap();
+        first_client
+            .send_to_channel("signals", some_data)
+            .await
+            .unwrap();
+
+        let (topic, _) = second_client.6.recv().await.unwrap();
+        let channel = second_client.0.channel_of(&topic).unwrap();
+        assert_eq!(channel.did, first_did.to_string());
+        assert_eq!(channel.channel.as_deref(), Some("signals"));
+        assert_eq!(
+            topic.to_string(),
+            first_client
+                .channel_topic(&did_from_pair, "signals")
+                .unwrap()
+        );
+    })
+    .await
+    .expect("Timeout");
+}
+
+struct RejectEverything;
+
+impl MessageValidator for RejectEverything {
+    fn validate(&mut self, _: &str, _: &str, _: &Sata) -> Validation {
+        Validation::Reject
+    }
+}
+
+#[tokio::test]
+async fn rejected_messages_are_not_delivered() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+
+        let (mut first_client, _, _, _, first_did, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, topic) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+        second_client
+            .0
+            .register_validator(&topic, Arc::new(RwLock::new(RejectEverything)))
+            .unwrap();
+
+        let mut some_data = Sata::default();
+        some_data.add_recipient(did_from_pair.as_ref()).unwrap();
+        let some_data = some_data
+            .encode(IpldCodec::DagJson, Kind::Dynamic, "Test".to_string())
+            .unwrap();
+        first_client.send(some_data).await.unwrap();
+
+        let rejected = second_client
+            .0
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::MessageRejected(..)),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+        match rejected {
+            Event::MessageRejected(rejected_topic, author) => {
+                assert_eq!(rejected_topic, topic);
+                assert_eq!(author, first_did.to_string());
+            }
+            _ => unreachable!(),
+        }
+        assert!(second_client.6.try_recv().is_err());
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn invited_peer_hears_the_call_ringing() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let second_client = create_service(Vec::new(), true).await;
+
+        let (mut first_client, _, _, _, first_did, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+
+        let id = first_client
+            .invite_call(&did_from_pair, StreamKind::Audio)
+            .await
+            .unwrap();
+
+        second_client
+            .0
+            .wait_for_event_since(
+                0,
+                |x| {
+                    matches!(x, Event::IncomingCall(did, call_id, StreamKind::Audio)
+                        if *did == first_did.to_string() && *call_id == id)
+                },
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[cfg(feature = "chaos")]
+#[tokio::test]
+async fn injected_cache_failure_is_reported_and_message_still_delivered() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut second_client = create_service(Vec::new(), true).await;
+        second_client.0.chaos().fail_next_cache_writes(1);
+
+        let (mut first_client, _, _, _, _, _, _) =
+            create_service(second_client.5.clone(), true).await;
+
+        let (did_from_pair, _) = pair_to_another_peer(
+            &mut first_client,
+            second_client.5.first().unwrap().clone().into(),
+        )
+        .await;
+
+        let mut some_data = Sata::default();
+        some_data.add_recipient(did_from_pair.as_ref()).unwrap();
+        first_client.send(some_data).await.unwrap();
+
+        assert_message(&mut second_client.6).await;
+        assert!(second_client.2.read().data_added.is_empty());
+        second_client
+            .0
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::ErrorAddingToCache(_)),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn unpaired_recipients_are_reported_without_failing_the_others() {
+    tokio::time::timeout(Duration::from_secs(7), async {
+        let client_a = create_service(Vec::new(), true).await;
+        let (mut service_c, _, _, _, _, _, _) = create_service(client_a.5.clone(), true).await;
+        let (did_a, _) = pair_to_another_peer(&mut service_c, client_a.5[0].clone().into()).await;
+        let stranger = did();
+
+        let mut sata = Sata::default();
+        sata.add_recipient(did_a.as_ref()).unwrap();
+        sata.add_recipient(stranger.as_ref()).unwrap();
+        let to_send = sata
+            .encode(IpldCodec::DagJson, Kind::Dynamic, "Test".to_string())
+            .unwrap();
+        let report = service_c.send(to_send).await.unwrap();
+
+        assert_eq!(report[&did_a.to_string()], Ok(()));
+        assert_eq!(
+            report[&stranger.to_string()],
+            Err(RecipientError::NotPaired)
+        );
+        let failed = service_c
+            .wait_for_event_since(
+                0,
+                |x| matches!(x, Event::SendFailed { .. }),
+                Duration::from_secs(TIMEOUT_SECS),
+            )
+            .await
+            .unwrap();
+        match failed {
+            Event::SendFailed { recipient, reason } => {
+                assert_eq!(recipient.to_string(), stranger.to_string());
+                assert_eq!(reason, RecipientError::NotPaired);
+            }
+            _ => unreachable!(),
+        }
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn only_friend_requests_received_can_be_answered() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+        let stranger = did();
+
+        service.send_friend_request(&stranger).await.unwrap();
+        let accepted = service.accept_friend_request(&stranger).await;
+        let declined = service.decline_friend_request(&stranger);
+
+        assert!(matches!(accepted, Err(BlinkError::NotFound(_))));
+        assert!(matches!(declined, Err(BlinkError::NotFound(_))));
+        assert_eq!(service.sent_friend_requests(), [stranger.to_string()]);
+        assert!(service.friend_requests().is_empty());
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn errors_say_what_went_wrong() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (mut service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+        let stranger = did();
+
+        let call = service.call(&stranger).await;
+        let accepted = service.accept_call(42).await;
+
+        assert!(matches!(call, Err(BlinkError::NotIdentified(did)) if did == stranger.to_string()));
+        assert!(matches!(accepted, Err(BlinkError::NotFound(_))));
+        assert!(matches!(
+            Error::from(accepted.unwrap_err()),
+            Error::DataObjectNotFound
+        ));
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn waiting_for_an_event_times_out_when_none_matches() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let (service, _, _, _, _, _, _) = create_service(Vec::new(), true).await;
+
+        let waited = service
+            .wait_for_event(
+                |x| matches!(x, Event::FailureToIdentifyPeer),
+                Duration::from_millis(100),
+            )
+            .await;
+
+        assert!(matches!(waited, Err(BlinkError::Timeout(_))));
     })
     .await
     .expect("Timeout");
@@ -0,0 +1,117 @@
+use crate::raygun::{conversation_id, fold, ChatMessage, ChatPayload};
+use uuid::Uuid;
+
+const ALICE: &str = "did:key:alice";
+const BOB: &str = "did:key:bob";
+
+fn message(id: Uuid, lines: &[&str]) -> ChatPayload {
+    ChatPayload::Message {
+        id,
+        lines: lines.iter().map(|x| x.to_string()).collect(),
+        replied: None,
+    }
+}
+
+fn replay(history: Vec<(&str, ChatPayload)>) -> Vec<ChatMessage> {
+    fold(
+        history
+            .into_iter()
+            .enumerate()
+            .map(|(i, (sender, payload))| (sender.to_string(), i as u64, payload)),
+    )
+}
+
+#[test]
+fn both_ends_agree_on_the_conversation_id() {
+    assert_eq!(conversation_id("topic"), conversation_id("topic"));
+    assert_ne!(conversation_id("topic"), conversation_id("other"));
+}
+
+#[test]
+fn messages_are_read_back_in_the_order_sent() {
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let messages = replay(vec![
+        (ALICE, message(first, &["hi"])),
+        (BOB, message(second, &["hello"])),
+        (BOB, message(second, &["hello"])),
+    ]);
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].sender, ALICE);
+    assert_eq!(messages[1].lines, ["hello"]);
+}
+
+#[test]
+fn only_the_author_edits_or_deletes_a_message() {
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let messages = replay(vec![
+        (ALICE, message(first, &["hi"])),
+        (ALICE, message(second, &["typo"])),
+        (BOB, ChatPayload::Delete { id: first }),
+        (
+            BOB,
+            ChatPayload::Edit {
+                id: second,
+                lines: vec!["hijacked".into()],
+            },
+        ),
+        (
+            ALICE,
+            ChatPayload::Edit {
+                id: second,
+                lines: vec!["fixed".into()],
+            },
+        ),
+        (ALICE, ChatPayload::Delete { id: first }),
+    ]);
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].lines, ["fixed"]);
+}
+
+#[test]
+fn reactions_and_pins_apply_to_the_message() {
+    let id = Uuid::new_v4();
+    let react = |emoji: &str, added| ChatPayload::React {
+        id,
+        emoji: emoji.into(),
+        added,
+    };
+
+    let messages = replay(vec![
+        (ALICE, message(id, &["hi"])),
+        (ALICE, react("👍", true)),
+        (BOB, react("👍", true)),
+        (BOB, react("🎉", true)),
+        (BOB, react("🎉", false)),
+        (BOB, ChatPayload::Pin { id, pinned: true }),
+    ]);
+
+    let reactions = &messages[0].reactions;
+    assert_eq!(
+        reactions,
+        &[("👍".to_string(), vec![ALICE.to_string(), BOB.to_string()])]
+    );
+    assert!(messages[0].pinned);
+}
+
+#[test]
+fn replies_point_to_the_message_answered() {
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let messages = replay(vec![
+        (ALICE, message(first, &["hi"])),
+        (
+            BOB,
+            ChatPayload::Message {
+                id: second,
+                lines: vec!["hello".into()],
+                replied: Some(first),
+            },
+        ),
+    ]);
+
+    assert_eq!(messages[1].replied, Some(first));
+}
@@ -0,0 +1,130 @@
+use crate::clock::VirtualClock;
+use crate::conflux::{CollisionPolicy, Conflux, ConfluxError, ConfluxState};
+use crate::fragments::{CidPolicy, DataFragment, SATA_CODEC};
+use crate::oracle::Oracle;
+use crate::peer_to_peer_service::BlinkCommand;
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Receiver};
+use warp::sync::RwLock;
+
+fn oracle() -> (Oracle, Receiver<BlinkCommand>) {
+    let (oracle, _clock, receiver) = oracle_with_clock();
+    (oracle, receiver)
+}
+
+fn oracle_with_clock() -> (Oracle, Arc<VirtualClock>, Receiver<BlinkCommand>) {
+    let (commands, receiver) = channel(8);
+    let clock = Arc::new(VirtualClock::new(1_000));
+    let state = Arc::new(RwLock::new(ConfluxState::default()));
+    let conflux = Conflux::new(state, commands, clock.clone());
+    (Oracle::new(conflux, clock.clone()), clock, receiver)
+}
+
+fn shared(data: &[u8], timestamp: u64) -> DataFragment {
+    let mut fragment = DataFragment::new(b"draft".to_vec(), 1).with_policy(CidPolicy::Identity);
+    fragment.set(data.to_vec(), timestamp);
+    fragment
+}
+
+#[tokio::test]
+async fn created_fragment_is_stamped_and_provided() {
+    let (oracle, mut commands) = oracle();
+
+    let fragment = oracle.create(b"hello".to_vec()).await.unwrap();
+
+    assert_eq!(fragment.timestamp(), 1_000);
+    assert!(
+        matches!(commands.recv().await, Some(BlinkCommand::Provide(cid)) if cid == fragment.cid())
+    );
+    assert_eq!(oracle.get(fragment.cid()).await, Ok(fragment));
+}
+
+#[tokio::test]
+async fn creating_a_taken_cid_is_refused() {
+    let (oracle, _commands) = oracle();
+    let fragment = oracle.create(b"hello".to_vec()).await.unwrap();
+
+    assert_eq!(
+        oracle.create(b"hello".to_vec()).await,
+        Err(ConfluxError::Collision(fragment.cid().to_string()))
+    );
+}
+
+#[tokio::test]
+async fn shared_fragment_keeps_its_cid_when_set() {
+    let (oracle, _commands) = oracle();
+    let fragment = oracle.create_shared(b"draft".to_vec()).await.unwrap();
+
+    let edited = oracle.set(fragment.cid(), b"final".to_vec()).await.unwrap();
+
+    assert_eq!(fragment.policy(), CidPolicy::Identity);
+    assert_eq!(edited.cid(), fragment.cid());
+    assert_eq!(oracle.get(fragment.cid()).await.unwrap().data(), b"final");
+}
+
+#[tokio::test]
+async fn sata_is_kept_as_a_fragment() {
+    let (oracle, _commands) = oracle();
+    let sata = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &"hello".to_string())
+        .unwrap();
+
+    let fragment = oracle.create_from_sata(sata).await.unwrap();
+
+    assert_eq!(fragment.codec(), SATA_CODEC);
+    assert_eq!(
+        Sata::try_from(&fragment)
+            .unwrap()
+            .decode::<String>()
+            .unwrap(),
+        "hello"
+    );
+}
+
+#[tokio::test]
+async fn newer_fragment_replaces_an_older_one() {
+    let (oracle, _commands) = oracle();
+    let conflux = oracle.conflux();
+    conflux.add(shared(b"old", 2)).await.unwrap();
+
+    let stored = conflux
+        .add_with(shared(b"new", 3), CollisionPolicy::ReplaceIfNewer)
+        .await
+        .unwrap();
+    let kept = conflux
+        .add_with(shared(b"older", 1), CollisionPolicy::ReplaceIfNewer)
+        .await
+        .unwrap();
+
+    assert_eq!(stored.data(), b"new");
+    assert_eq!(kept.data(), b"new");
+}
+
+#[tokio::test]
+async fn colliding_fragments_can_be_merged() {
+    let (oracle, _commands) = oracle();
+    let conflux = oracle.conflux();
+    conflux.add(shared(b"ours", 5)).await.unwrap();
+
+    let merged = conflux
+        .add_with(shared(b"theirs", 4), CollisionPolicy::Merge)
+        .await
+        .unwrap();
+
+    assert_eq!(merged.data(), b"ours");
+    assert_eq!(merged.version(), 1);
+}
+
+#[tokio::test]
+async fn oracle_applies_its_collision_policy() {
+    let (oracle, clock, _commands) = oracle_with_clock();
+    let oracle = oracle.with_collision_policy(CollisionPolicy::ReplaceIfNewer);
+    let first = oracle.create(b"same".to_vec()).await.unwrap();
+    clock.advance(std::time::Duration::from_millis(5));
+
+    let second = oracle.create(b"same".to_vec()).await.unwrap();
+
+    assert_eq!(second.cid(), first.cid());
+    assert_eq!(second.timestamp(), 1_005);
+}
@@ -0,0 +1,84 @@
+use crate::testkit::{Cluster, Topology};
+use sata::Sata;
+use std::time::Duration;
+
+const TIMEOUT_SECS: u64 = 10;
+
+async fn send(cluster: &mut Cluster, from: usize, to: usize) {
+    let mut some_data = Sata::default();
+    some_data
+        .add_recipient(cluster.node(to).did().as_ref())
+        .unwrap();
+    cluster
+        .node_mut(from)
+        .service
+        .send(some_data)
+        .await
+        .unwrap();
+}
+
+async fn received(cluster: &mut Cluster, node: usize, within: Duration) -> bool {
+    tokio::time::timeout(within, cluster.node_mut(node).messages.recv())
+        .await
+        .map_or(false, |x| x.is_some())
+}
+
+#[test]
+fn topologies_pair_the_expected_nodes() {
+    assert_eq!(Topology::FullMesh.edges(3), vec![(0, 1), (0, 2), (1, 2)]);
+    assert_eq!(Topology::Line.edges(3), vec![(0, 1), (1, 2)]);
+    assert_eq!(Topology::Ring.edges(3), vec![(0, 1), (1, 2), (2, 0)]);
+    assert_eq!(Topology::Star(1).edges(3), vec![(1, 0), (1, 2)]);
+}
+
+#[tokio::test]
+async fn a_partition_holds_messages_back_until_healed() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut cluster = Cluster::start(2, Topology::FullMesh).await.unwrap();
+        send(&mut cluster, 0, 1).await;
+        assert!(received(&mut cluster, 1, Duration::from_secs(1)).await);
+
+        cluster.partition(&[&[0], &[1]]).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send(&mut cluster, 0, 1).await;
+        assert!(!received(&mut cluster, 1, Duration::from_millis(300)).await);
+
+        cluster.heal_partition(&[&[0], &[1]]).await.unwrap();
+        send(&mut cluster, 0, 1).await;
+        assert!(received(&mut cluster, 1, Duration::from_secs(1)).await);
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn latency_delays_what_the_node_publishes() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut cluster = Cluster::start(2, Topology::Line).await.unwrap();
+        cluster.add_latency(0, Duration::from_millis(500));
+
+        send(&mut cluster, 0, 1).await;
+
+        assert!(!received(&mut cluster, 1, Duration::from_millis(200)).await);
+        assert!(received(&mut cluster, 1, Duration::from_secs(1)).await);
+    })
+    .await
+    .expect("Timeout");
+}
+
+#[tokio::test]
+async fn a_restarted_node_is_reachable_again() {
+    tokio::time::timeout(Duration::from_secs(TIMEOUT_SECS), async {
+        let mut cluster = Cluster::start(3, Topology::Star(0)).await.unwrap();
+        let did = cluster.node(2).did();
+
+        cluster.kill(2).await;
+        cluster.restart(2).await.unwrap();
+
+        assert_eq!(cluster.node(2).did(), did);
+        send(&mut cluster, 0, 2).await;
+        assert!(received(&mut cluster, 2, Duration::from_secs(1)).await);
+    })
+    .await
+    .expect("Timeout");
+}
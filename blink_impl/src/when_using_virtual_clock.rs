@@ -0,0 +1,29 @@
+use crate::clock::VirtualClock;
+use blink_contract::Clock;
+use std::time::Duration;
+
+#[test]
+fn time_only_moves_when_advanced() {
+    let clock = VirtualClock::new(1_000);
+    assert_eq!(clock.now_millis(), 1_000);
+
+    clock.advance(Duration::from_secs(60));
+
+    assert_eq!(clock.now_millis(), 61_000);
+}
+
+#[tokio::test]
+async fn sleep_completes_once_the_deadline_is_reached() {
+    let clock = VirtualClock::new(0);
+    let mut sleep = tokio::spawn(clock.sleep(Duration::from_secs(10 * 60)));
+
+    clock.advance(Duration::from_secs(5 * 60));
+    let early = tokio::time::timeout(Duration::from_millis(10), &mut sleep).await;
+    assert!(early.is_err());
+
+    clock.advance(Duration::from_secs(5 * 60));
+    tokio::time::timeout(Duration::from_secs(1), sleep)
+        .await
+        .expect("Timeout")
+        .unwrap();
+}
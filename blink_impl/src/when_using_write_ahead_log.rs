@@ -0,0 +1,82 @@
+use crate::test_support::temp_path;
+use crate::wal::{WalOperation, WriteAheadLog};
+use sata::Sata;
+
+fn publish(topic: &str) -> WalOperation {
+    WalOperation::Publish(topic.to_string(), Sata::default())
+}
+
+#[test]
+fn uncommitted_operations_are_pending_after_reopen() {
+    let path = temp_path("pending", "wal");
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let first = wal.begin(publish("first")).unwrap();
+        wal.begin(publish("second")).unwrap();
+        wal.commit(first).unwrap();
+    }
+
+    let wal = WriteAheadLog::open(&path).unwrap();
+    let pending = wal.pending();
+
+    assert_eq!(pending.len(), 1);
+    match &pending[0].1 {
+        WalOperation::Publish(topic, _) => assert_eq!(topic, "second"),
+        other => panic!("Unexpected operation {:?}", other),
+    }
+}
+
+#[test]
+fn ids_keep_increasing_across_reopen() {
+    let path = temp_path("ids", "wal");
+    let first = {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.begin(publish("first")).unwrap()
+    };
+
+    let mut wal = WriteAheadLog::open(&path).unwrap();
+    let second = wal.begin(publish("second")).unwrap();
+
+    assert!(second > first);
+}
+
+#[test]
+fn fully_committed_log_has_nothing_to_replay() {
+    let path = temp_path("committed", "wal");
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let id = wal.begin(publish("topic")).unwrap();
+        wal.commit(id).unwrap();
+    }
+
+    assert!(WriteAheadLog::open(&path).unwrap().pending().is_empty());
+}
+
+#[test]
+fn group_bans_are_pending_until_committed() {
+    let path = temp_path("ban", "wal");
+    {
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.begin(WalOperation::GroupBan(
+            7,
+            "did:key:mallory".into(),
+            true,
+            42,
+        ))
+        .unwrap();
+    }
+
+    let wal = WriteAheadLog::open(&path).unwrap();
+    let pending = wal.pending();
+
+    assert_eq!(pending.len(), 1);
+    match &pending[0].1 {
+        WalOperation::GroupBan(group, did, banned, at) => {
+            assert_eq!(
+                (*group, did.as_str(), *banned, *at),
+                (7, "did:key:mallory", true, 42)
+            )
+        }
+        other => panic!("Unexpected operation {:?}", other),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::validators::{validate, ValidatorRegistry};
+use blink_contract::{MessageValidator, Validation};
+use sata::{libipld::IpldCodec, Kind, Sata};
+use std::sync::Arc;
+use warp::sync::RwLock;
+
+// Rejects "spam", ignores "stale", accepts anything else
+#[derive(Default)]
+struct WordFilter {
+    seen: Vec<String>,
+}
+
+impl MessageValidator for WordFilter {
+    fn validate(&mut self, _: &str, _: &str, data: &Sata) -> Validation {
+        let word: String = data.decode().unwrap();
+        self.seen.push(word.clone());
+        match word.as_str() {
+            "spam" => Validation::Reject,
+            "stale" => Validation::Ignore,
+            _ => Validation::Accept,
+        }
+    }
+}
+
+fn batch(words: &[&str]) -> Vec<Sata> {
+    words
+        .iter()
+        .map(|x| {
+            Sata::default()
+                .encode(IpldCodec::DagCbor, Kind::Dynamic, x.to_string())
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn a_batch_is_only_as_good_as_its_worst_message() {
+    let mut filter = WordFilter::default();
+
+    assert_eq!(
+        validate(&mut filter, "topic", "did", &batch(&["hi", "there"])),
+        Validation::Accept
+    );
+    assert_eq!(
+        validate(&mut filter, "topic", "did", &batch(&["hi", "stale"])),
+        Validation::Ignore
+    );
+    assert_eq!(
+        validate(
+            &mut filter,
+            "topic",
+            "did",
+            &batch(&["stale", "spam", "hi"])
+        ),
+        Validation::Reject
+    );
+    // Nothing after a rejected message is looked at
+    assert_eq!(filter.seen.last().map(String::as_str), Some("spam"));
+}
+
+#[test]
+fn a_topic_has_one_validator_at_a_time() {
+    let mut validators = ValidatorRegistry::default();
+
+    assert!(validators.register("topic".into(), Arc::new(RwLock::new(WordFilter::default()))));
+    assert!(!validators.register("topic".into(), Arc::new(RwLock::new(WordFilter::default()))));
+    assert!(validators.validator("other").is_none());
+
+    assert!(validators.unregister("topic"));
+    assert!(validators.validator("topic").is_none());
+}
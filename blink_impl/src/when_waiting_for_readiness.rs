@@ -0,0 +1,36 @@
+use crate::readiness::Readiness;
+use libp2p::Multiaddr;
+
+fn addr(text: &str) -> Multiaddr {
+    text.parse().unwrap()
+}
+
+#[test]
+fn ready_once_listening() {
+    let mut readiness = Readiness::default();
+    let local = addr("/ip4/127.0.0.1/tcp/4001");
+    assert!(!readiness.is_ready(false));
+
+    readiness.listening(local.clone());
+    readiness.listening(local.clone());
+
+    assert!(readiness.is_ready(false));
+    assert_eq!(readiness.listen_addrs(), vec![local.clone()]);
+    readiness.expired(&local);
+    assert!(!readiness.is_ready(false));
+}
+
+#[test]
+fn bootstrapped_waits_for_the_bootstrap_once_started() {
+    let mut readiness = Readiness::default();
+    readiness.listening(addr("/ip4/127.0.0.1/tcp/4001"));
+    assert!(readiness.is_ready(true));
+
+    readiness.bootstrap_started();
+    assert!(!readiness.is_ready(true));
+    assert!(readiness.is_ready(false));
+
+    readiness.bootstrapped();
+    readiness.bootstrap_started();
+    assert!(readiness.is_ready(true));
+}
@@ -0,0 +1,325 @@
+use crate::did_to_libp2p_pub;
+use anyhow::{anyhow, bail, Result};
+use bincode::Options;
+use blink_contract::Keystore;
+use sata::libipld::{
+    cbor::DagCborCodec,
+    codec::Codec,
+    serde::{from_ipld, to_ipld},
+    Ipld,
+};
+use sata::Sata;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::crypto::DID;
+
+const MAGIC: [u8; 2] = *b"BL";
+// Bumped when the envelope layout changes, newer envelopes are refused rather than misread
+pub(crate) const WIRE_VERSION: u8 = 2;
+// Envelopes without an expiry keep the older layout, so peers before version 2 still read them
+pub(crate) const PLAIN_VERSION: u8 = 1;
+// Magic, version and codec id
+const HEADER_SIZE: usize = 4;
+// Milliseconds since the unix epoch, big endian, after the header of a version 2 envelope
+const EXPIRY_SIZE: usize = 8;
+
+pub const BINCODE_CODEC: u8 = 0;
+pub const DAG_CBOR_CODEC: u8 = 1;
+// Not a codec of its own, the payload lists envelopes sealed with one
+pub const BATCH_CODEC: u8 = 0xFF;
+// Not a codec of its own either, the payload is an envelope and its author's signature
+pub const SIGNED_CODEC: u8 = 0xFE;
+
+// Turns messages into gossipsub payloads and back.
+// The id goes into every envelope, so receivers need a codec registered under the same id.
+pub trait WireCodec: Send + Sync {
+    fn id(&self) -> u8;
+    fn encode(&self, sata: &Sata) -> Result<Vec<u8>>;
+    fn decode(&self, bytes: &[u8]) -> Result<Sata>;
+}
+
+#[derive(Default)]
+pub struct BincodeWireCodec;
+
+impl WireCodec for BincodeWireCodec {
+    fn id(&self) -> u8 {
+        BINCODE_CODEC
+    }
+
+    fn encode(&self, sata: &Sata) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(sata)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Sata> {
+        Ok(bounded_bincode(bytes)?)
+    }
+}
+
+#[derive(Default)]
+pub struct DagCborWireCodec;
+
+impl WireCodec for DagCborWireCodec {
+    fn id(&self) -> u8 {
+        DAG_CBOR_CODEC
+    }
+
+    fn encode(&self, sata: &Sata) -> Result<Vec<u8>> {
+        let ipld = to_ipld(sata).map_err(|e| anyhow!(e.to_string()))?;
+        DagCborCodec.encode(&ipld).map_err(|e| anyhow!(e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Sata> {
+        let ipld: Ipld = DagCborCodec.decode(bytes).map_err(|e| anyhow!(e))?;
+        from_ipld(ipld).map_err(|e| anyhow!(e.to_string()))
+    }
+}
+
+// Codec used for outgoing messages and every codec incoming ones may use.
+pub(crate) struct WireFormat {
+    codecs: HashMap<u8, Arc<dyn WireCodec>>,
+    outgoing: Arc<dyn WireCodec>,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        let mut format = Self {
+            codecs: HashMap::new(),
+            outgoing: Arc::new(BincodeWireCodec),
+        };
+        format.register(Arc::new(DagCborWireCodec));
+        format.register(Arc::new(BincodeWireCodec));
+        format
+    }
+}
+
+impl WireFormat {
+    pub(crate) fn register(&mut self, codec: Arc<dyn WireCodec>) {
+        self.codecs.insert(codec.id(), codec);
+    }
+
+    // Sends with the codec from now on, it's registered for receiving too.
+    pub(crate) fn use_codec(&mut self, codec: Arc<dyn WireCodec>) {
+        self.register(codec.clone());
+        self.outgoing = codec;
+    }
+
+    pub(crate) fn outgoing(&self) -> u8 {
+        self.outgoing.id()
+    }
+
+    pub(crate) fn seal(&self, sata: &Sata) -> Result<Vec<u8>> {
+        seal_envelope(&*self.outgoing, sata)
+    }
+
+    // Seals a message receivers delete once the time, in milliseconds since the unix epoch, passed.
+    pub(crate) fn seal_expiring(&self, sata: &Sata, expires_at: Option<u64>) -> Result<Vec<u8>> {
+        let expires_at = match expires_at {
+            Some(expires_at) => expires_at,
+            None => return self.seal(sata),
+        };
+        let payload = self.outgoing.encode(sata)?;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + EXPIRY_SIZE + payload.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(WIRE_VERSION);
+        bytes.push(self.outgoing.id());
+        bytes.extend_from_slice(&expires_at.to_be_bytes());
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    pub(crate) fn open(&self, bytes: &[u8]) -> Result<Sata> {
+        self.open_expiring(bytes).map(|(sata, _)| sata)
+    }
+
+    // The message and when it expires, if its sender gave it an expiry.
+    pub(crate) fn open_expiring(&self, bytes: &[u8]) -> Result<(Sata, Option<u64>)> {
+        let (codec, expires_at, payload) = parse_header(bytes)?;
+        match self.codecs.get(&codec) {
+            Some(decoder) => Ok((decoder.decode(payload)?, expires_at)),
+            None if codec == BATCH_CODEC => bail!("Envelope holds a batch"),
+            None if codec == SIGNED_CODEC => bail!("Envelope is signed"),
+            None => bail!("No wire codec registered with id {}", codec),
+        }
+    }
+
+    pub(crate) fn seal_batch(&self, batch: &[Sata]) -> Result<Vec<u8>> {
+        let envelopes = batch
+            .iter()
+            .map(|x| self.seal(x))
+            .collect::<Result<Vec<_>>>()?;
+        self.batch(&envelopes)
+    }
+
+    // Packs envelopes sealed already, signed ones included, into a batch.
+    pub(crate) fn batch(&self, envelopes: &[Vec<u8>]) -> Result<Vec<u8>> {
+        let payload = bincode::serialize(envelopes)?;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&MAGIC);
+        // The expiry of each message is in its own envelope, the batch keeps the plain layout
+        bytes.push(PLAIN_VERSION);
+        bytes.push(BATCH_CODEC);
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    // Every message of a batch, or the single one of any other envelope.
+    pub(crate) fn open_all(&self, bytes: &[u8]) -> Result<Vec<Sata>> {
+        let opened = self.open_all_expiring(bytes)?;
+        Ok(opened.into_iter().map(|(sata, _)| sata).collect())
+    }
+
+    // Same as open_all, with when each message expires.
+    pub(crate) fn open_all_expiring(&self, bytes: &[u8]) -> Result<Vec<(Sata, Option<u64>)>> {
+        let opened = self.open_each(bytes)?;
+        Ok(opened.into_iter().map(|x| (x.sata, x.expires_at)).collect())
+    }
+
+    // Every message of a batch, or the single one of any other envelope, with the envelope
+    // each came in. Signatures are opened but not checked, see SignedEnvelope::verify.
+    pub(crate) fn open_each(&self, bytes: &[u8]) -> Result<Vec<Opened>> {
+        let (codec, payload) = parse_envelope(bytes)?;
+        if codec != BATCH_CODEC {
+            return Ok(vec![self.open_single(bytes)?]);
+        }
+        // Batches don't nest, open refuses one inside another
+        let envelopes: Vec<Vec<u8>> = bounded_bincode(payload)?;
+        envelopes.iter().map(|x| self.open_single(x)).collect()
+    }
+
+    fn open_single(&self, bytes: &[u8]) -> Result<Opened> {
+        let (codec, payload) = parse_envelope(bytes)?;
+        if codec != SIGNED_CODEC {
+            let (sata, expires_at) = self.open_expiring(bytes)?;
+            return Ok(Opened {
+                sata,
+                expires_at,
+                signed: None,
+                envelope: bytes.to_vec(),
+            });
+        }
+        // Signatures don't nest either, the signed envelope has to hold a message
+        let signed: SignedEnvelope = bounded_bincode(payload)?;
+        let (sata, expires_at) = self.open_expiring(&signed.envelope)?;
+        Ok(Opened {
+            sata,
+            expires_at,
+            signed: Some(signed),
+            envelope: bytes.to_vec(),
+        })
+    }
+}
+
+// A message out of an envelope, with the envelope it came in so it can be passed on as sent.
+pub(crate) struct Opened {
+    pub(crate) sata: Sata,
+    pub(crate) expires_at: Option<u64>,
+    pub(crate) signed: Option<SignedEnvelope>,
+    pub(crate) envelope: Vec<u8>,
+}
+
+// An envelope signed by its author for the topic it's published on. Whoever passes it on, a
+// mailbox replaying it or a peer flooding it, can't change it or move it to another topic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SignedEnvelope {
+    envelope: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SignedEnvelope {
+    pub(crate) fn new(keystore: &dyn Keystore, topic: &str, envelope: Vec<u8>) -> Result<Self> {
+        let signature = keystore.sign(&signed_bytes(topic, &envelope)?)?;
+        Ok(Self {
+            envelope,
+            signature,
+        })
+    }
+
+    // Which of the DIDs signed it for the topic, an error when none of them did.
+    pub(crate) fn verify<'a>(&self, topic: &str, authors: &[&'a str]) -> Result<&'a str> {
+        let signed = signed_bytes(topic, &self.envelope)?;
+        for author in authors {
+            let public_key = did_to_libp2p_pub(&DID::try_from(author.to_string())?)?;
+            if public_key.verify(&signed, &self.signature) {
+                return Ok(*author);
+            }
+        }
+        bail!("Envelope on {} isn't signed by its author", topic)
+    }
+
+    pub(crate) fn seal(&self) -> Result<Vec<u8>> {
+        let payload = bincode::serialize(self)?;
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(PLAIN_VERSION);
+        bytes.push(SIGNED_CODEC);
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+}
+
+fn signed_bytes(topic: &str, envelope: &[u8]) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(topic, envelope))?)
+}
+
+pub fn seal_envelope(codec: &dyn WireCodec, sata: &Sata) -> Result<Vec<u8>> {
+    let payload = codec.encode(sata)?;
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(PLAIN_VERSION);
+    bytes.push(codec.id());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+// Codec id and payload of an envelope, bare bincode from older peers has no header.
+pub fn parse_envelope(bytes: &[u8]) -> Result<(u8, &[u8])> {
+    parse_header(bytes).map(|(codec, _, payload)| (codec, payload))
+}
+
+// Codec id, expiry and payload of an envelope
+fn parse_header(bytes: &[u8]) -> Result<(u8, Option<u64>, &[u8])> {
+    if bytes.len() < HEADER_SIZE || bytes[..2] != MAGIC {
+        return Ok((BINCODE_CODEC, None, bytes));
+    }
+    let (version, codec) = (bytes[2], bytes[3]);
+    if version > WIRE_VERSION {
+        bail!(
+            "Envelope version {} is newer than {}",
+            version,
+            WIRE_VERSION
+        );
+    }
+    let payload = &bytes[HEADER_SIZE..];
+    if version <= PLAIN_VERSION {
+        return Ok((codec, None, payload));
+    }
+    if payload.len() < EXPIRY_SIZE {
+        bail!("Envelope too short for its expiry");
+    }
+    let (expiry, payload) = payload.split_at(EXPIRY_SIZE);
+    let mut expires_at = [0; EXPIRY_SIZE];
+    expires_at.copy_from_slice(expiry);
+    Ok((codec, Some(u64::from_be_bytes(expires_at)), payload))
+}
+
+// Reads a gossip payload the way a service with the built-in codecs does.
+// Any bytes at all give an error rather than a panic, the fuzz targets hold it to that.
+pub fn open_envelope(bytes: &[u8]) -> Result<Sata> {
+    WireFormat::default().open(bytes)
+}
+
+// Same as open_envelope, unpacking batches into the messages they hold.
+pub fn open_envelopes(bytes: &[u8]) -> Result<Vec<Sata>> {
+    WireFormat::default().open_all(bytes)
+}
+
+// Bincode refusing lengths longer than the input, so a forged prefix can't make it allocate
+// far more than was received.
+pub(crate) fn bounded_bincode<T: DeserializeOwned>(bytes: &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)
+}
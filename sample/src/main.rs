@@ -2,13 +2,14 @@ use crate::{
     did_key::Ed25519KeyPair,
     trait_impl::{EventHandlerImpl, MultiPassImpl, PocketDimensionImpl},
 };
-use blink_impl::peer_to_peer_service::{MessageContent, PeerToPeerService};
+use blink_impl::{
+    BenchmarkOptions, CancellationToken, InMemoryKeystore, MessageContent, PeerToPeerService,
+    SystemClock,
+};
 use libp2p::Multiaddr;
 use log::{error, info};
 use sata::{libipld::IpldCodec, Kind, Sata};
-use std::{
-    collections::HashMap, future::Future, io::stdin, pin::Pin, sync::atomic::AtomicBool, sync::Arc,
-};
+use std::{collections::HashMap, future::Future, io::stdin, pin::Pin, sync::Arc};
 use tokio::{sync::mpsc::Receiver, task::JoinHandle};
 use warp::crypto::{did_key, DID};
 use warp::sync::RwLock;
@@ -41,13 +42,16 @@ async fn create_service() -> (PeerToPeerService, Receiver<MessageContent>) {
 
     info!("DID Key: {}", (*id_keys).to_string());
 
-    let cancellation_token = Arc::new(AtomicBool::new(false));
+    let cancellation_token = CancellationToken::new();
     let cache = Arc::new(RwLock::new(PocketDimensionImpl::default()));
     let log_handler = Arc::new(RwLock::new(EventHandlerImpl::default()));
     let multi_pass = Arc::new(RwLock::new(MultiPassImpl::default()));
 
+    let keystore = Arc::new(InMemoryKeystore::new(id_keys.clone()).unwrap());
+
     let result = PeerToPeerService::new(
-        id_keys.clone(),
+        keystore,
+        Arc::new(SystemClock),
         "/ip4/0.0.0.0/tcp/0",
         None,
         cache.clone(),
@@ -141,6 +145,66 @@ fn create_command_map_handler() -> HashMap<
         ),
     );
 
+    map_command.insert(
+        "bench".to_string(),
+        Box::new(
+            |service: Arc<RwLock<PeerToPeerService>>, args: Vec<String>| {
+                Box::pin(async move {
+                    if args.is_empty() || args.len() > 3 {
+                        error!("bench did [probes] [payload_size]");
+                        return;
+                    }
+                    let did = match DID::try_from(args[0].clone()) {
+                        Ok(did) => did,
+                        Err(e) => {
+                            error!("{}", e.enum_to_string());
+                            return;
+                        }
+                    };
+                    let mut options = BenchmarkOptions::default();
+                    if let Some(probes) = args.get(1).and_then(|x| x.parse().ok()) {
+                        options.probes = probes;
+                    }
+                    if let Some(size) = args.get(2).and_then(|x| x.parse().ok()) {
+                        options.payload_size = size;
+                    }
+                    match service.write().benchmark(&did, options).await {
+                        Ok(report) => {
+                            info!(
+                                "Received {}/{} probes, loss {:.1}%, {:.0} bytes/s, one-way delay ~{:.1} ms (rtt {}-{} ms)",
+                                report.received,
+                                report.sent,
+                                report.loss * 100.0,
+                                report.throughput,
+                                report.one_way_delay_ms,
+                                report.min_rtt_ms,
+                                report.max_rtt_ms
+                            );
+                        }
+                        Err(e) => {
+                            error!("{}", e);
+                        }
+                    }
+                })
+            },
+        ),
+    );
+
+    map_command.insert(
+        "answer_bench".to_string(),
+        Box::new(
+            |service: Arc<RwLock<PeerToPeerService>>, args: Vec<String>| {
+                Box::pin(async move {
+                    match args.first().map(|x| x.as_str()) {
+                        Some("on") => service.write().set_benchmark_answering(true),
+                        Some("off") => service.write().set_benchmark_answering(false),
+                        _ => error!("answer_bench on|off"),
+                    }
+                })
+            },
+        ),
+    );
+
     map_command
 }
 
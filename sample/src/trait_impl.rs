@@ -81,12 +81,259 @@ impl EventBus for EventHandlerImpl {
             Event::TaskCancelled => {
                 info!("Event: Task cancelled");
             }
-            Event::CouldntFindTopicForDid => {
-                info!("Event: Couldn't find topic for did")
-            }
             Event::GeneratedTopic(_, _) => {
                 info!("Event: Generated topic")
             }
+            Event::ErrorProvidingContent(x) => {
+                info!("Event: Error providing content {}", x)
+            }
+            Event::ContentAtRisk(x) => {
+                info!("Event: We are the only provider of {}", x)
+            }
+            Event::MailboxReplayed(x) => {
+                info!("Event: Replayed {} messages from mailbox", x)
+            }
+            Event::MailboxError(x) => {
+                info!("Event: Mailbox error {}", x)
+            }
+            Event::WriteAheadLogError(x) => {
+                info!("Event: Write-ahead log error {}", x)
+            }
+            Event::DeviceSynced(x) => {
+                info!("Event: Synced {} messages from another device", x)
+            }
+            Event::DeviceSyncError(x) => {
+                info!("Event: Device sync error {}", x)
+            }
+            Event::IncomingStream(did, id, kind) => {
+                info!("Event: Incoming {:?} stream {} from {}", kind, id, did)
+            }
+            Event::StreamOpened(id, codec) => {
+                info!("Event: Stream {} opened using {}", id, codec)
+            }
+            Event::StreamRejected(id) => {
+                info!("Event: Stream {} rejected", id)
+            }
+            Event::StreamMuted(id, muted) => {
+                info!("Event: Stream {} muted: {}", id, muted)
+            }
+            Event::StreamClosed(id) => {
+                info!("Event: Stream {} closed", id)
+            }
+            Event::StreamError(x) => {
+                info!("Event: Stream error {}", x)
+            }
+            Event::VideoCapsNegotiated(id, caps) => {
+                info!(
+                    "Event: Stream {} limited to {}x{} at {} bps",
+                    id, caps.max_width, caps.max_height, caps.max_bitrate
+                )
+            }
+            Event::KeyframeRequested(id) => {
+                info!("Event: Keyframe requested on stream {}", id)
+            }
+            Event::TransactionCompleted(id) => {
+                info!("Event: Transaction {} completed", id)
+            }
+            Event::TransactionFailed(id, reason) => {
+                info!("Event: Transaction {} failed: {}", id, reason)
+            }
+            Event::UnknownExtension(namespace) => {
+                info!("Event: Message for unknown extension {}", namespace)
+            }
+            Event::StreamBitrateChanged(id, bitrate) => {
+                info!("Event: Stream {} target bitrate {} bps", id, bitrate)
+            }
+            Event::PeerExtensionsChanged(did, extensions) => {
+                info!("Event: {} supports extensions {:?}", did, extensions)
+            }
+            Event::GroupParticipantsChanged(group, participants) => {
+                info!(
+                    "Event: Group call {} participants {:?}",
+                    group, participants
+                )
+            }
+            Event::GroupStreamAdded(group, did, id) => {
+                info!("Event: Stream {} from {} in group call {}", id, did, group)
+            }
+            Event::IncomingCall(did, id, kind) => {
+                info!("Event: Incoming {:?} call {} from {}", kind, id, did)
+            }
+            Event::CallAccepted(id) => {
+                info!("Event: Call {} accepted", id)
+            }
+            Event::CallEnded(id, reason) => {
+                info!("Event: Call {} ended: {:?}", id, reason)
+            }
+            Event::MessageQuarantined(did) => {
+                info!("Event: Held back a message from quarantined {}", did)
+            }
+            Event::RecordingSaved(id, manifest) => {
+                info!("Event: Recording of stream {} saved as {}", id, manifest)
+            }
+            Event::RecordingError(x) => {
+                info!("Event: Recording error {}", x)
+            }
+            Event::IncomingFile(did, id, name, size) => {
+                info!(
+                    "Event: {} offers {} ({} bytes) as transfer {}",
+                    did, name, size, id
+                )
+            }
+            Event::TransferProgress(id, bytes, total) => {
+                info!("Event: Transfer {} at {}/{} bytes", id, bytes, total)
+            }
+            Event::TransferCompleted(id) => {
+                info!("Event: Transfer {} completed", id)
+            }
+            Event::TransferFailed(id, reason) => {
+                info!("Event: Transfer {} failed: {}", id, reason)
+            }
+            Event::ContentProvided(cid) => {
+                info!("Event: Providing {}", cid)
+            }
+            Event::FragmentMerged(cid) => {
+                info!("Event: Merged a remote update into {}", cid)
+            }
+            Event::FragmentEvicted(cid) => {
+                info!("Event: Evicted {} from the fragment store", cid)
+            }
+            Event::PublishDeferred(topic) => {
+                info!("Event: Waiting for a peer on {} to publish", topic)
+            }
+            Event::PeerJoinedTopic(peer, topic) => {
+                info!("Event: {} subscribed to {}", peer, topic)
+            }
+            Event::PeerLeftTopic(peer, topic) => {
+                info!("Event: {} left {}", peer, topic)
+            }
+            Event::Dialing(x) => {
+                info!("Event: Dialing {}", x)
+            }
+            Event::IncomingConnection(x) => {
+                info!("Event: Incoming connection from {}", x)
+            }
+            Event::IncomingConnectionError(address, error) => {
+                info!(
+                    "Event: Incoming connection from {} failed: {}",
+                    address, error
+                )
+            }
+            Event::OutgoingConnectionError(peer, error) => {
+                info!(
+                    "Event: Connection to {} failed: {}",
+                    peer.unwrap_or_else(|| "unknown peer".into()),
+                    error
+                )
+            }
+            Event::BannedPeer(x) => {
+                info!("Event: Refused connection from banned {}", x)
+            }
+            Event::IncompatiblePeer(did, theirs, ours) => {
+                info!(
+                    "Event: Peer {} runs protocol {}, incompatible with {}",
+                    did, theirs, ours
+                )
+            }
+            Event::RendezvousRegistered(x) => {
+                info!("Event: Registered at rendezvous node {}", x)
+            }
+            Event::RendezvousDiscovered(did, peer) => {
+                info!("Event: Rendezvous found {} at {}", did, peer)
+            }
+            Event::RendezvousError(x) => {
+                info!("Event: Rendezvous error {}", x)
+            }
+            Event::DidRecordPublished => {
+                info!("Event: Published our DID record")
+            }
+            Event::DidResolved(did, peer) => {
+                info!("Event: DHT resolved {} to {}", did, peer)
+            }
+            Event::DidRecordError(x) => {
+                info!("Event: DID record error {}", x)
+            }
+            Event::MdnsError(x) => {
+                info!("Event: Couldn't start mDNS {}", x)
+            }
+            Event::PeerUnresponsive(x) => {
+                info!("Event: {} stopped answering pings", x)
+            }
+            Event::FileSyncProgress(path, bytes, total) => {
+                info!("Event: Syncing {} at {}/{} bytes", path, bytes, total)
+            }
+            Event::FileSynced(path, cid) => {
+                info!("Event: Synced {} as {}", path, cid)
+            }
+            Event::DirectoryOffered(did, index) => {
+                info!("Event: {} shared directory {}", did, index)
+            }
+            Event::FriendRequestReceived(x) => {
+                info!("Event: {} wants to be friends", x)
+            }
+            Event::FriendRequestAccepted(x) => {
+                info!("Event: {} accepted our friend request", x)
+            }
+            Event::FriendRequestError(x) => {
+                info!("Event: Friend request error {}", x)
+            }
+            Event::ProfileUpdated(x) => {
+                info!("Event: {} updated its profile", x)
+            }
+            Event::ProfileError(x) => {
+                info!("Event: Couldn't publish our profile {}", x)
+            }
+            Event::ContactStatusChanged(did, status) => {
+                info!("Event: {} is now {:?}", did, status)
+            }
+            Event::MessageRejected(topic, author) => {
+                info!("Event: Rejected a message from {} on {}", author, topic)
+            }
+            Event::RateLimited(peer, topic) => {
+                info!(
+                    "Event: Dropping messages from {} on {}, over the rate limit",
+                    peer, topic
+                )
+            }
+            Event::PeerGraylisted(x, score) => {
+                info!("Event: Ignoring {}, its score fell to {}", x, score)
+            }
+            Event::PeerBlacklisted(x, score) => {
+                info!("Event: Blacklisted {}, its score fell to {}", x, score)
+            }
+            Event::PeerScoreRecovered(x, score) => {
+                info!("Event: {} recovered with a score of {}", x, score)
+            }
+            Event::ExternalAddressConfirmed(x) => {
+                info!("Event: Peers reach us at {}", x)
+            }
+            Event::PeerIdentifyUpdated(x) => {
+                info!("Event: {} has new addresses or protocols", x)
+            }
+            Event::NetworkingPaused => {
+                info!("Event: Networking paused");
+            }
+            Event::NetworkingResumed => {
+                info!("Event: Networking resumed");
+            }
+            Event::SendFailed { recipient, reason } => {
+                info!("Event: Couldn't send to {}: {}", recipient, reason)
+            }
+            Event::UnpairedTopic(x) => {
+                info!("Event: Message on {}, no DID is paired through it", x)
+            }
+            Event::MessageMuted(x) => {
+                info!("Event: Message on muted {}", x)
+            }
+            Event::MessageExpired(x) => {
+                info!("Event: Message {} expired", x)
+            }
+            Event::MessageEdited(topic, id) => {
+                info!("Event: Message {} on {} was edited", id, topic)
+            }
+            Event::MessageDeleted(topic, id) => {
+                info!("Event: Message {} on {} was deleted", id, topic)
+            }
         }
     }
 }
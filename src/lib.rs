@@ -1,2 +1,7 @@
 pub use blink_contract;
 pub use blink_impl;
+
+pub mod prelude;
+
+#[cfg(test)]
+mod when_importing_the_prelude;
@@ -0,0 +1,28 @@
+// Everything an application needs to run Blink, at versions matching the ones Blink was built with.
+// Brought in with `use blink::prelude::*;`
+
+pub use blink_contract::{
+    BlinkError, CallEndReason, Clock, Event, EventBus, ExtensionHandler, Keystore,
+    MessageValidator, RecipientError, Status, StreamKind, Validation, VideoCaps,
+};
+pub use blink_impl::{
+    cid_of, did_to_libp2p_pub, libp2p_pub_to_did, BandwidthCaps, BandwidthStats, BatchSettings,
+    BenchmarkOptions, BenchmarkReport, BincodeWireCodec, BlinkConfig, BlinkMessage, BlinkNode,
+    CachePolicy, CacheScope, CallHandle, CallId, CancellationToken, ChannelTopic, CidPolicy,
+    CollisionPolicy, ConfigDelta, Conflux, ConfluxError, Conversation, DagCborWireCodec,
+    DataFragment, DeliverySettings, DeliveryStrategy, DiskFragmentStore, EventCategory,
+    EventForwarder, ForwardTarget, FragmentStore, FragmentTree, FragmentUpdate, FragmentWatch,
+    GcLimits, GroupCallId, IdentityProfile, IdlePolicy, InMemoryKeystore, LinkQuality,
+    LiveFragment, MemoryFragmentStore, MessageContent, Oracle, PauseMode, PeerInfo,
+    PeerToPeerService, RateLimit, RateLimits, RecordingOptions, RelayServerSettings, RelayStats,
+    ScoreSettings, ScreenFrame, SendError, SendReport, StoreKey, StoredMessage, StreamId,
+    SystemClock, TopicName, TransactionId, TransferId, Typed, TypedReceiver, Verbosity, VideoFrame,
+    VirtualClock, WireCodec,
+};
+
+// Message envelope
+pub use sata::{libipld::IpldCodec, Kind, Sata};
+
+// Identities and addressing
+pub use libp2p::{swarm::dial_opts::DialOpts, Multiaddr, PeerId};
+pub use warp::crypto::DID;
@@ -0,0 +1,137 @@
+use crate::prelude::{
+    cid_of, did_to_libp2p_pub, libp2p_pub_to_did, BandwidthCaps, BandwidthStats, BatchSettings,
+    BenchmarkOptions, BenchmarkReport, BincodeWireCodec, BlinkConfig, BlinkError, BlinkMessage,
+    BlinkNode, CachePolicy, CacheScope, CallEndReason, CallHandle, CallId, CancellationToken,
+    ChannelTopic, CidPolicy, Clock, CollisionPolicy, ConfigDelta, Conflux, ConfluxError,
+    Conversation, DagCborWireCodec, DataFragment, DeliverySettings, DeliveryStrategy, DialOpts,
+    DiskFragmentStore, Event, EventBus, EventCategory, EventForwarder, ExtensionHandler,
+    ForwardTarget, FragmentStore, FragmentTree, FragmentUpdate, FragmentWatch, GcLimits,
+    GroupCallId, IdentityProfile, IdlePolicy, InMemoryKeystore, IpldCodec, Keystore, Kind,
+    LinkQuality, LiveFragment, MemoryFragmentStore, MessageContent, MessageValidator, Multiaddr,
+    Oracle, PauseMode, PeerId, PeerInfo, PeerToPeerService, RateLimit, RateLimits, RecipientError,
+    RecordingOptions, RelayServerSettings, RelayStats, Sata, ScoreSettings, ScreenFrame, SendError,
+    SendReport, Status, StoreKey, StoredMessage, StreamId, StreamKind, SystemClock, TopicName,
+    TransactionId, TransferId, Typed, TypedReceiver, Validation, Verbosity, VideoCaps, VideoFrame,
+    VirtualClock, WireCodec, DID,
+};
+use libp2p::identity::Keypair;
+
+// Names every item of the prelude, the build breaks as soon as one of them stops being exported
+fn _assert_exports() {
+    let _ = (cid_of, did_to_libp2p_pub, libp2p_pub_to_did);
+    let _: Option<BlinkError> = None;
+    let _: Option<CallEndReason> = None;
+    let _: Option<Event> = None;
+    let _: Option<RecipientError> = None;
+    let _: Option<Status> = None;
+    let _: Option<StreamKind> = None;
+    let _: Option<Validation> = None;
+    let _: Option<VideoCaps> = None;
+    let _: Option<BandwidthCaps> = None;
+    let _: Option<BandwidthStats> = None;
+    let _: Option<BatchSettings> = None;
+    let _: Option<BenchmarkOptions> = None;
+    let _: Option<BenchmarkReport> = None;
+    let _: Option<BincodeWireCodec> = None;
+    let _: Option<BlinkConfig> = None;
+    let _: Option<BlinkMessage> = None;
+    let _: Option<BlinkNode> = None;
+    let _: Option<CachePolicy> = None;
+    let _: Option<CacheScope> = None;
+    let _: Option<CallHandle> = None;
+    let _: Option<CallId> = None;
+    let _: Option<CancellationToken> = None;
+    let _: Option<ChannelTopic> = None;
+    let _: Option<CidPolicy> = None;
+    let _: Option<CollisionPolicy> = None;
+    let _: Option<ConfigDelta> = None;
+    let _: Option<Conflux> = None;
+    let _: Option<ConfluxError> = None;
+    let _: Option<Conversation> = None;
+    let _: Option<DagCborWireCodec> = None;
+    let _: Option<DataFragment> = None;
+    let _: Option<DeliverySettings> = None;
+    let _: Option<DeliveryStrategy> = None;
+    let _: Option<DiskFragmentStore> = None;
+    let _: Option<EventCategory> = None;
+    let _: Option<EventForwarder> = None;
+    let _: Option<ForwardTarget> = None;
+    let _: Option<FragmentTree> = None;
+    let _: Option<FragmentUpdate> = None;
+    let _: Option<FragmentWatch> = None;
+    let _: Option<GcLimits> = None;
+    let _: Option<GroupCallId> = None;
+    let _: Option<IdentityProfile> = None;
+    let _: Option<IdlePolicy> = None;
+    let _: Option<InMemoryKeystore> = None;
+    let _: Option<LinkQuality> = None;
+    let _: Option<LiveFragment> = None;
+    let _: Option<MemoryFragmentStore> = None;
+    let _: Option<MessageContent> = None;
+    let _: Option<Oracle> = None;
+    let _: Option<PauseMode> = None;
+    let _: Option<PeerInfo> = None;
+    let _: Option<PeerToPeerService> = None;
+    let _: Option<RateLimit> = None;
+    let _: Option<RateLimits> = None;
+    let _: Option<RecordingOptions> = None;
+    let _: Option<RelayServerSettings> = None;
+    let _: Option<RelayStats> = None;
+    let _: Option<ScoreSettings> = None;
+    let _: Option<ScreenFrame> = None;
+    let _: Option<SendError> = None;
+    let _: Option<SendReport> = None;
+    let _: Option<StoreKey> = None;
+    let _: Option<StoredMessage> = None;
+    let _: Option<StreamId> = None;
+    let _: Option<SystemClock> = None;
+    let _: Option<TopicName> = None;
+    let _: Option<TransactionId> = None;
+    let _: Option<TransferId> = None;
+    let _: Option<Typed<String>> = None;
+    let _: Option<TypedReceiver> = None;
+    let _: Option<Verbosity> = None;
+    let _: Option<VideoFrame> = None;
+    let _: Option<VirtualClock> = None;
+    let _: Option<IpldCodec> = None;
+    let _: Option<Kind> = None;
+    let _: Option<Sata> = None;
+    let _: Option<DialOpts> = None;
+    let _: Option<Multiaddr> = None;
+    let _: Option<PeerId> = None;
+    let _: Option<DID> = None;
+    let _: Option<&dyn Clock> = None;
+    let _: Option<&dyn EventBus> = None;
+    let _: Option<&dyn ExtensionHandler> = None;
+    let _: Option<&dyn Keystore> = None;
+    let _: Option<&dyn MessageValidator> = None;
+    let _: Option<&dyn FragmentStore> = None;
+    let _: Option<&dyn WireCodec> = None;
+}
+
+#[test]
+fn did_helpers_convert_keys_both_ways() {
+    let public_key = Keypair::generate_ed25519().public();
+
+    let did = libp2p_pub_to_did(&public_key).unwrap();
+
+    assert_eq!(did_to_libp2p_pub(&did).unwrap(), public_key);
+}
+
+#[test]
+fn cancellation_tokens_share_their_state_with_clones() {
+    let token = CancellationToken::new();
+
+    token.clone().cancel();
+
+    assert!(token.is_cancelled());
+}
+
+#[test]
+fn messages_are_built_from_the_envelope_types() {
+    let sata = Sata::default()
+        .encode(IpldCodec::DagCbor, Kind::Dynamic, &"hello".to_string())
+        .unwrap();
+
+    assert_eq!(sata.decode::<String>().unwrap(), "hello");
+}